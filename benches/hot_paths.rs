@@ -0,0 +1,217 @@
+//! Benchmarks for the toolkit's hot paths
+//!
+//! Covered paths:
+//! - Storage store/retrieve
+//! - Borsh decode of on-chain agent accounts, and with the `zero-copy`
+//!   feature the load/record/save cycle of an execution in both layouts
+//! - Protocol message validation and hashing
+//! - Client transaction build and sign
+//!
+//! Each benchmark has a performance budget (mean time per iteration). The
+//! nightly job runs
+//!
+//! ```text
+//! SONOMA_ENFORCE_BUDGETS=1 cargo bench --bench hot_paths
+//! ```
+//!
+//! which fails if any benchmark's mean exceeds its budget. Budgets are
+//! deliberately generous (roughly 3x the numbers measured on a CI runner)
+//! so they catch regressions rather than noise. Raise them only together
+//! with a justification in the commit message.
+//!
+//! The agent layouts are compared with
+//!
+//! ```text
+//! cargo bench --bench hot_paths --features zero-copy -- program/
+//! ```
+//!
+//! Host time stands in for compute units; on-chain, an agent's
+//! `metrics.total_compute_units` records the CU its executions consumed.
+
+use std::path::PathBuf;
+use criterion::{black_box, criterion_group, Criterion};
+use solana_sdk::{
+    hash::Hash,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    transaction::Transaction,
+};
+use sonoma_labs_toolkit::{
+    network::Message,
+    program::{
+        action::AgentAction,
+        capability::CapabilityFlags,
+        instruction::{AgentConfig, AgentInstruction},
+        state::AgentAccount,
+    },
+    storage::{StorageConfig, StorageManager},
+};
+use borsh::BorshDeserialize;
+#[cfg(feature = "zero-copy")]
+use borsh::BorshSerialize;
+#[cfg(feature = "zero-copy")]
+use sonoma_labs_toolkit::program::zero_copy::AgentAccountZc;
+
+/// Environment variable enabling budget enforcement
+const ENFORCE_BUDGETS_ENV: &str = "SONOMA_ENFORCE_BUDGETS";
+
+/// Performance budgets: benchmark id and maximum mean time in nanoseconds
+const BUDGETS: &[(&str, f64)] = &[
+    ("storage/store", 250_000.0),
+    ("storage/retrieve", 100_000.0),
+    ("program/decode_agent_account", 5_000.0),
+    ("protocol/validate", 2_000.0),
+    ("protocol/hash", 10_000.0),
+    ("client/build_execute_transaction", 150_000.0),
+];
+
+fn agent_config() -> AgentConfig {
+    AgentConfig {
+        autonomous_mode: true,
+        execution_limit: 1000,
+        memory_limit: 10 * 1024 * 1024,
+        capabilities: CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE,
+        min_execution_interval: 0,
+        allowed_programs: vec![],
+    }
+}
+
+fn bench_storage(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let temp_dir = tempfile::tempdir().unwrap();
+    let manager = runtime.block_on(async {
+        StorageManager::new(StorageConfig {
+            base_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await
+        .unwrap()
+    });
+    let value = vec![7u8; 1024];
+    runtime.block_on(manager.store("bench-key", &value)).unwrap();
+
+    let mut group = c.benchmark_group("storage");
+    group.bench_function("store", |b| {
+        b.to_async(&runtime).iter(|| manager.store("bench-key", black_box(&value)))
+    });
+    group.bench_function("retrieve", |b| {
+        b.to_async(&runtime).iter(|| manager.retrieve::<Vec<u8>>(black_box("bench-key")))
+    });
+    group.finish();
+}
+
+fn bench_program(c: &mut Criterion) {
+    let account = AgentAccount::new(Pubkey::new_unique(), "bench_agent".to_string(), agent_config());
+    let data = borsh::to_vec(&account).unwrap();
+
+    let mut group = c.benchmark_group("program");
+    group.bench_function("decode_agent_account", |b| {
+        b.iter(|| AgentAccount::try_from_slice(black_box(&data)).unwrap())
+    });
+
+    #[cfg(feature = "zero-copy")]
+    {
+        // What `Execute` does to the agent account in each layout
+        let mut borsh_data = data.clone();
+        group.bench_function("execution_round_trip/borsh", |b| {
+            b.iter(|| {
+                let mut agent = AgentAccount::try_from_slice(black_box(&borsh_data)).unwrap();
+                agent.record_execution(1_700_000_000);
+                agent.metrics.record(true, 1_000);
+                agent.serialize(&mut &mut borsh_data[..]).unwrap();
+            })
+        });
+
+        let mut buffer = vec![0u64; AgentAccountZc::LEN / 8];
+        let zero_copy_data: &mut [u8] = bytemuck::cast_slice_mut(&mut buffer);
+        AgentAccountZc::store(&account, zero_copy_data).unwrap();
+        group.bench_function("execution_round_trip/zero_copy", |b| {
+            b.iter(|| {
+                let mut agent = AgentAccount::unpack(black_box(&*zero_copy_data)).unwrap();
+                agent.record_execution(1_700_000_000);
+                agent.metrics.record(true, 1_000);
+                AgentAccountZc::load_mut(&mut *zero_copy_data).unwrap().record_execution(&agent);
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_protocol(c: &mut Criterion) {
+    let message = Message::request("bench-id", "getAgentState", vec![0u8; 256]);
+
+    let mut group = c.benchmark_group("protocol");
+    group.bench_function("validate", |b| b.iter(|| black_box(&message).validate().unwrap()));
+    group.bench_function("hash", |b| b.iter(|| black_box(&message).hash()));
+    group.finish();
+}
+
+fn bench_client(c: &mut Criterion) {
+    let program_id = Pubkey::new_unique();
+    let agent = Pubkey::new_unique();
+    let result_account = Pubkey::new_unique();
+    let payer = Keypair::new();
+    let blockhash = Hash::new_unique();
+
+    let mut group = c.benchmark_group("client");
+    group.bench_function("build_execute_transaction", |b| {
+        b.iter(|| {
+            let instruction = AgentInstruction::execute(
+                &program_id,
+                &agent,
+                &payer.pubkey(),
+                &result_account,
+                1,
+                black_box(AgentAction::Memo { text: "m".repeat(128) }),
+            );
+            Transaction::new_signed_with_payer(
+                &[instruction],
+                Some(&payer.pubkey()),
+                &[&payer],
+                blockhash,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_storage, bench_program, bench_protocol, bench_client);
+
+/// Compare criterion's recorded means against the budgets
+fn enforce_budgets() {
+    let criterion_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("target"))
+        .join("criterion");
+
+    let mut violations = Vec::new();
+    for (id, budget) in BUDGETS {
+        let path = criterion_dir.join(id).join("new").join("estimates.json");
+        let estimates: serde_json::Value = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap(),
+            Err(e) => {
+                violations.push(format!("{}: no results at {} ({})", id, path.display(), e));
+                continue;
+            }
+        };
+
+        let mean = estimates["mean"]["point_estimate"].as_f64().unwrap_or(f64::INFINITY);
+        if mean > *budget {
+            violations.push(format!("{}: mean {:.0}ns exceeds budget {:.0}ns", id, mean, budget));
+        }
+    }
+
+    if !violations.is_empty() {
+        panic!("Performance budgets exceeded:\n{}", violations.join("\n"));
+    }
+    println!("All {} performance budgets met", BUDGETS.len());
+}
+
+fn main() {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+
+    if std::env::var_os(ENFORCE_BUDGETS_ENV).is_some() {
+        enforce_budgets();
+    }
+}
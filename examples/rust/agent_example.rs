@@ -3,13 +3,13 @@ use solana_sdk::{
     commitment_config::CommitmentConfig,
     pubkey::Pubkey,
     signature::{Keypair, Signer},
-    system_instruction,
     transaction::Transaction,
 };
 use sonoma_labs_toolkit::{
     agent::{Agent, AgentConfig, AgentState, Capabilities},
     error::SonomaError,
-    program::{instruction::*, state::*},
+    program::instruction::*,
+    solana::airdrop,
 };
 use std::{str::FromStr, time::Duration};
 
@@ -26,16 +26,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let payer = Keypair::new();
     println!("Using keypair: {}", payer.pubkey());
 
-    // Request airdrop for testing
-    let airdrop_signature = client.request_airdrop(
-        &payer.pubkey(),
-        1_000_000_000, // 1 SOL
-    )?;
-    client.confirm_transaction(&airdrop_signature)?;
-    println!("Airdrop successful");
+    // Fund the keypair for testing
+    let balance = airdrop::ensure_balance(&client, &payer.pubkey(), 1_000_000_000)?; // 1 SOL
+    println!("Airdrop successful, balance: {} lamports", balance);
 
-    // Initialize program ID
-    let program_id = Pubkey::from_str("YOUR_PROGRAM_ID")?;
+    // Program ID of the deployed agent program
+    let program_id = Pubkey::from_str(&std::env::var("SONOMA_PROGRAM_ID")?)?;
 
     // Create agent configuration
     let config = AgentConfig {
@@ -46,7 +42,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             compute: true,
             storage: true,
             network: false,
-            custom_capabilities: vec!["example".to_string()],
+            custom_capabilities: vec!["oracle".to_string()],
         },
         metadata: Some(serde_json::json!({
             "description": "Example Rust agent",
@@ -66,7 +62,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Agent created: {}", agent.pubkey());
 
     // Subscribe to agent state changes
-    let state_subscription = agent.subscribe_state_changes()?;
+    let mut state_subscription = agent.subscribe_state_changes()?;
     tokio::spawn(async move {
         while let Ok(state) = state_subscription.recv().await {
             println!("Agent state changed: {:?}", state);
@@ -108,6 +104,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         &program_id,
         &agent.pubkey(),
         &payer.pubkey(),
+        agent.account()?.execution_count + 1,
         action_data,
     )?;
 
@@ -134,38 +131,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-// Helper function for custom instruction
+// Helper function for custom instruction: the agent's `execution`-th
+// execution, logging `data` as a memo
 fn create_custom_instruction(
     program_id: &Pubkey,
     agent: &Pubkey,
     authority: &Pubkey,
+    execution: u64,
     data: &[u8],
 ) -> Result<solana_sdk::instruction::Instruction, Box<dyn std::error::Error>> {
-    let instruction = solana_sdk::instruction::Instruction {
-        program_id: *program_id,
-        accounts: vec![
-            solana_sdk::instruction::AccountMeta::new(*agent, false),
-            solana_sdk::instruction::AccountMeta::new(*authority, true),
-        ],
-        data: data.to_vec(),
+    let (result, _) = sonoma_labs_toolkit::program::pda::find_result_address(program_id, agent);
+    let action = sonoma_labs_toolkit::program::action::AgentAction::Memo {
+        text: String::from_utf8(data.to_vec())?,
     };
-    Ok(instruction)
-}
-
-// Example of implementing custom trait
-trait AgentExtension {
-    fn get_metrics(&self) -> Result<AgentMetrics, SonomaError>;
-    fn subscribe_state_changes(&self) -> Result<tokio::sync::broadcast::Receiver<AgentState>, SonomaError>;
+    Ok(AgentInstruction::execute(program_id, agent, authority, &result, execution, action))
 }
-
-impl AgentExtension for Agent {
-    fn get_metrics(&self) -> Result<AgentMetrics, SonomaError> {
-        // Implementation details
-        unimplemented!()
-    }
-
-    fn subscribe_state_changes(&self) -> Result<tokio::sync::broadcast::Receiver<AgentState>, SonomaError> {
-        // Implementation details
-        unimplemented!()
-    }
-}
\ No newline at end of file
@@ -0,0 +1,262 @@
+//! Cross-venue arbitrage detection
+//!
+//! This module provides:
+//! - `VenueQuote`: the best bid and ask of a market on one venue
+//! - `ArbitrageDetector`, keeping the latest quote of every venue and
+//!   finding spreads between venues wider than their fees and slippage
+//! - `ArbitrageSignal`s published on the message bus for trading agents
+//!   to act on
+//!
+//! Run in an `AgentRuntime`, the detector subscribes to the quotes topic,
+//! where price feeds publish `VenueQuote`s, and publishes a signal on the
+//! signals topic for each opportunity a new quote opens. A quote is only
+//! compared with quotes of the other venues no older than `max_quote_age`,
+//! so a venue whose feed stalled can't produce phantom spreads.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use super::bus::{Message, MessageBus};
+use super::error::{AgentError, AgentResult};
+use super::lifecycle::AgentLifecycle;
+use super::runtime::ScheduledAgent;
+
+/// Topic price feeds publish quotes on by default
+pub const DEFAULT_QUOTES_TOPIC: &str = "quotes";
+
+/// Topic signals are published on by default
+pub const DEFAULT_SIGNALS_TOPIC: &str = "arbitrage";
+
+/// Best bid and ask of a market on a venue
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VenueQuote {
+    pub venue: String,
+    pub market: String,
+    pub bid: f64,
+    /// Quantity available at the bid, in base units
+    pub bid_size: u64,
+    pub ask: f64,
+    /// Quantity available at the ask, in base units
+    pub ask_size: u64,
+    /// Unix time of the quote
+    pub timestamp: u64,
+}
+
+/// Opportunity to buy on one venue and sell on another at a profit
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArbitrageSignal {
+    pub market: String,
+    pub buy_venue: String,
+    pub buy_price: f64,
+    pub sell_venue: String,
+    pub sell_price: f64,
+    /// Quantity both sides can take, in base units
+    pub quantity: u64,
+    /// Spread between the sell and buy prices, in basis points of the buy
+    /// price
+    pub spread_bps: f64,
+    /// Spread left after both venues' fees and slippage, in basis points
+    pub net_bps: f64,
+    /// Unix time of the newer quote
+    pub timestamp: u64,
+}
+
+/// Detection settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArbitrageConfig {
+    /// Fee of each venue, in basis points; venues not listed charge
+    /// `default_fee_bps`
+    pub fee_bps: HashMap<String, f64>,
+    pub default_fee_bps: f64,
+    /// Slippage expected on each side of the trade, in basis points
+    pub slippage_bps: f64,
+    /// Smallest spread after costs worth signalling, in basis points
+    pub min_net_bps: f64,
+    /// Oldest quote compared, in seconds older than the new quote
+    pub max_quote_age: u64,
+    pub quotes_topic: String,
+    pub signals_topic: String,
+}
+
+impl Default for ArbitrageConfig {
+    fn default() -> Self {
+        Self {
+            fee_bps: HashMap::new(),
+            default_fee_bps: 10.0,
+            slippage_bps: 5.0,
+            min_net_bps: 5.0,
+            max_quote_age: 5,
+            quotes_topic: DEFAULT_QUOTES_TOPIC.to_string(),
+            signals_topic: DEFAULT_SIGNALS_TOPIC.to_string(),
+        }
+    }
+}
+
+impl ArbitrageConfig {
+    fn fee(&self, venue: &str) -> f64 {
+        self.fee_bps.get(venue).copied().unwrap_or(self.default_fee_bps)
+    }
+}
+
+/// Analysis agent finding arbitrage between venues' quotes
+pub struct ArbitrageDetector {
+    name: String,
+    config: ArbitrageConfig,
+    /// Latest quote of each market, by venue
+    quotes: HashMap<String, HashMap<String, VenueQuote>>,
+    bus: Option<MessageBus>,
+    signals: u64,
+}
+
+impl ArbitrageDetector {
+    pub fn new(name: &str, config: ArbitrageConfig) -> Self {
+        Self {
+            name: name.to_string(),
+            config,
+            quotes: HashMap::new(),
+            bus: None,
+            signals: 0,
+        }
+    }
+
+    /// Signals published so far
+    pub fn signals(&self) -> u64 {
+        self.signals
+    }
+
+    /// Record `quote` as its venue's latest, returning the opportunities it
+    /// opens against the other venues' quotes, widest first
+    pub fn on_quote(&mut self, quote: VenueQuote) -> Vec<ArbitrageSignal> {
+        let venues = self.quotes.get(&quote.market).into_iter().flat_map(HashMap::values);
+        let fresh = venues.filter(|other| {
+            other.venue != quote.venue && quote.timestamp.saturating_sub(other.timestamp) <= self.config.max_quote_age
+        });
+
+        let mut signals: Vec<ArbitrageSignal> = fresh
+            .flat_map(|other| [self.signal(&quote, other), self.signal(other, &quote)])
+            .flatten()
+            .collect();
+        signals.sort_by(|a, b| b.net_bps.total_cmp(&a.net_bps));
+
+        self.quotes.entry(quote.market.clone()).or_default().insert(quote.venue.clone(), quote);
+        signals
+    }
+
+    /// Opportunity to buy at `buy`'s ask and sell at `sell`'s bid, if its
+    /// spread covers the costs
+    fn signal(&self, buy: &VenueQuote, sell: &VenueQuote) -> Option<ArbitrageSignal> {
+        let quantity = buy.ask_size.min(sell.bid_size);
+        if buy.ask <= 0.0 || quantity == 0 {
+            return None;
+        }
+        let spread_bps = (sell.bid - buy.ask) / buy.ask * 10_000.0;
+        let costs = self.config.fee(&buy.venue) + self.config.fee(&sell.venue) + 2.0 * self.config.slippage_bps;
+        let net_bps = spread_bps - costs;
+        (net_bps >= self.config.min_net_bps).then(|| ArbitrageSignal {
+            market: buy.market.clone(),
+            buy_venue: buy.venue.clone(),
+            buy_price: buy.ask,
+            sell_venue: sell.venue.clone(),
+            sell_price: sell.bid,
+            quantity,
+            spread_bps,
+            net_bps,
+            timestamp: buy.timestamp.max(sell.timestamp),
+        })
+    }
+}
+
+impl AgentLifecycle for ArbitrageDetector {}
+
+#[async_trait::async_trait]
+impl ScheduledAgent for ArbitrageDetector {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn max_actions_per_cycle(&self) -> u32 {
+        0
+    }
+
+    /// Quotes are handled as they arrive; cycles take no action
+    async fn run_cycle(&mut self, _max_actions: u32) -> AgentResult<u32> {
+        Ok(0)
+    }
+
+    fn connect(&mut self, bus: MessageBus) {
+        bus.subscribe(&self.name, &self.config.quotes_topic);
+        self.bus = Some(bus);
+    }
+
+    async fn on_message(&mut self, message: Message) -> AgentResult<()> {
+        if message.topic() != Some(self.config.quotes_topic.as_str()) {
+            return Ok(());
+        }
+        let bus = self.bus.clone().ok_or(AgentError::InvalidStateTransition)?;
+        for signal in self.on_quote(message.decode()?) {
+            println!(
+                "Arbitrage on {}: buy on {} at {}, sell on {} at {} ({:.1} bps net)",
+                signal.market, signal.buy_venue, signal.buy_price, signal.sell_venue, signal.sell_price, signal.net_bps
+            );
+            bus.publish(&self.name, &self.config.signals_topic, &signal)?;
+            self.signals += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(venue: &str, bid: f64, ask: f64, timestamp: u64) -> VenueQuote {
+        VenueQuote {
+            venue: venue.to_string(),
+            market: "SOL/USDC".to_string(),
+            bid,
+            bid_size: 50,
+            ask,
+            ask_size: 20,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_spreads_net_of_costs() {
+        let mut detector = ArbitrageDetector::new("arb", ArbitrageConfig::default());
+        assert!(detector.on_quote(quote("orca", 99.9, 100.0, 10)).is_empty());
+
+        // 20 bps spread against 30 bps of fees and slippage
+        assert!(detector.on_quote(quote("raydium", 100.2, 100.3, 10)).is_empty());
+
+        let signals = detector.on_quote(quote("phoenix", 100.5, 100.6, 11));
+        assert_eq!(signals.len(), 1);
+        let signal = &signals[0];
+        assert_eq!((signal.buy_venue.as_str(), signal.sell_venue.as_str()), ("orca", "phoenix"));
+        assert_eq!(signal.quantity, 20);
+        assert!((signal.spread_bps - 50.0).abs() < 1e-6);
+        assert!((signal.net_bps - 20.0).abs() < 1e-6);
+
+        // Stale quotes aren't compared
+        assert!(detector.on_quote(quote("meteora", 101.0, 101.1, 30)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_signals_published_on_the_bus() {
+        let bus = MessageBus::new();
+        let mut inbox = bus.mailbox("arb").unwrap();
+        let mut trader = bus.mailbox("trader").unwrap();
+        bus.subscribe("trader", DEFAULT_SIGNALS_TOPIC);
+
+        let mut detector = ArbitrageDetector::new("arb", ArbitrageConfig::default());
+        detector.connect(bus.clone());
+        for quote in [quote("orca", 99.9, 100.0, 10), quote("phoenix", 100.5, 100.6, 10)] {
+            bus.publish("feed", DEFAULT_QUOTES_TOPIC, &quote).unwrap();
+            detector.on_message(inbox.try_recv().unwrap()).await.unwrap();
+        }
+
+        let signal: ArbitrageSignal = trader.try_recv().unwrap().decode().unwrap();
+        assert_eq!(signal.sell_venue, "phoenix");
+        assert_eq!(detector.signals(), 1);
+        assert!(trader.try_recv().is_none());
+    }
+}
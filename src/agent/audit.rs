@@ -0,0 +1,414 @@
+//! Audit trail of autonomous agents' decisions
+//!
+//! This module provides:
+//! - `DecisionRecord`: what a decision considered (inputs and candidate
+//!   actions), what it chose, with which confidence if known, and how it
+//!   turned out
+//! - `AuditLog`, an append-only log of decisions persisted through a
+//!   `StorageManager`
+//! - Queries by time, action, outcome and confidence, and lookup of the
+//!   decision behind a transaction
+//!
+//! Records are keyed by agent and sequence number, so scans return an
+//! agent's decisions in the order they were made. Records are never
+//! updated: a decision is appended once its outcome is known, and an
+//! append never overwrites a record already stored under its sequence
+//! number, e.g. by another log writing for the same agent.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use crate::storage::{Page, PageRequest, StorageManager, MAX_PAGE_SIZE};
+use super::error::{AgentError, AgentResult};
+
+/// Default number of memory entries recorded as a decision's inputs
+pub const DEFAULT_MAX_INPUTS: usize = 10;
+
+/// Audit log configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditConfig {
+    /// Prefix of the storage keys, followed by the agent's name
+    pub key_prefix: String,
+    /// Most memory entries recorded as a decision's inputs, latest first
+    pub max_inputs: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            key_prefix: "agents/audit/".to_string(),
+            max_inputs: DEFAULT_MAX_INPUTS,
+        }
+    }
+}
+
+/// How a decision turned out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DecisionOutcome {
+    /// The chosen action was sent in the transaction `signature`
+    Executed { signature: String },
+    /// Sending the chosen action failed
+    Failed { error: String },
+    /// The agent lacked the capabilities for the chosen action
+    Denied { error: String },
+}
+
+/// Decision made by an agent while planning
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecisionRecord {
+    pub agent: String,
+    /// Increasing with each decision of the agent, from 0
+    pub sequence: u64,
+    /// Unix time the decision was recorded
+    pub timestamp: u64,
+    /// What the agent considered, as JSON
+    pub inputs: String,
+    /// Actions the agent could have taken, the chosen one included
+    pub candidates: Vec<String>,
+    pub chosen: String,
+    /// In [0, 1]; `None` if the agent didn't score its decision
+    pub confidence: Option<f32>,
+    pub outcome: DecisionOutcome,
+}
+
+impl DecisionRecord {
+    /// Record of a decision to be appended, sequenced by the log
+    pub fn new(
+        agent: &str,
+        inputs: &Value,
+        candidates: Vec<String>,
+        chosen: String,
+        confidence: Option<f32>,
+        outcome: DecisionOutcome,
+    ) -> Self {
+        Self {
+            agent: agent.to_string(),
+            sequence: 0,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            inputs: inputs.to_string(),
+            candidates,
+            chosen,
+            confidence: confidence.map(|confidence| if confidence.is_nan() { 0.0 } else { confidence.clamp(0.0, 1.0) }),
+            outcome,
+        }
+    }
+
+    pub fn inputs(&self) -> AgentResult<Value> {
+        serde_json::from_str(&self.inputs).map_err(|_| AgentError::ValidationError)
+    }
+
+    /// Signature of the transaction the decision sent, if it was executed
+    pub fn signature(&self) -> Option<&str> {
+        match &self.outcome {
+            DecisionOutcome::Executed { signature } => Some(signature),
+            _ => None,
+        }
+    }
+}
+
+/// Decisions to find; every decision matches the default query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditQuery {
+    /// Earliest Unix time, inclusive
+    pub since: Option<u64>,
+    /// Latest Unix time, inclusive
+    pub until: Option<u64>,
+    /// Text the chosen action contains, e.g. `Transfer`
+    pub action: Option<String>,
+    pub executed: Option<bool>,
+    /// Decisions of unknown confidence don't match any minimum
+    pub min_confidence: Option<f32>,
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: u64) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn action(mut self, action: &str) -> Self {
+        self.action = Some(action.to_string());
+        self
+    }
+
+    /// Only decisions that were executed, or only those that weren't
+    pub fn executed(mut self, executed: bool) -> Self {
+        self.executed = Some(executed);
+        self
+    }
+
+    pub fn min_confidence(mut self, min_confidence: f32) -> Self {
+        self.min_confidence = Some(min_confidence);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, record: &DecisionRecord) -> bool {
+        self.since.map_or(true, |since| record.timestamp >= since)
+            && self.until.map_or(true, |until| record.timestamp <= until)
+            && self.action.as_ref().map_or(true, |action| record.chosen.contains(action.as_str()))
+            && self.executed.map_or(true, |executed| record.signature().is_some() == executed)
+            && self
+                .min_confidence
+                .map_or(true, |min| record.confidence.map_or(false, |confidence| confidence >= min))
+    }
+}
+
+/// Append-only log of agents' decisions
+#[derive(Clone)]
+pub struct AuditLog {
+    storage: Arc<StorageManager>,
+    config: AuditConfig,
+    /// Next sequence number of each agent that appended through this log
+    sequences: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog").field("config", &self.config).finish()
+    }
+}
+
+impl AuditLog {
+    pub fn new(storage: Arc<StorageManager>, config: AuditConfig) -> Self {
+        Self {
+            storage,
+            config,
+            sequences: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn max_inputs(&self) -> usize {
+        self.config.max_inputs
+    }
+
+    /// Append `record` as the latest decision of its agent, returning its
+    /// sequence number
+    pub async fn append(&self, mut record: DecisionRecord) -> AgentResult<u64> {
+        let mut sequences = self.sequences.lock().await;
+        let mut next = match sequences.get(&record.agent) {
+            Some(next) => *next,
+            // Resume after the decisions appended before a restart
+            None => self.next_sequence(&record.agent).await?,
+        };
+
+        // Skip sequence numbers taken by records appended elsewhere
+        loop {
+            record.sequence = next;
+            let stored = self
+                .storage
+                .store_if_absent(&self.key(&record.agent, next), &record)
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+            if stored {
+                break;
+            }
+            next += 1;
+        }
+        sequences.insert(record.agent.clone(), next + 1);
+        Ok(next)
+    }
+
+    /// Decisions of the agent named `agent`, oldest first, one page at a
+    /// time
+    pub async fn history(&self, agent: &str, page: &PageRequest) -> AgentResult<Page<DecisionRecord>> {
+        let page = self
+            .storage
+            .scan::<DecisionRecord>(&self.prefix(agent), page)
+            .await
+            .map_err(|e| AgentError::Custom(e.to_string()))?;
+        Ok(Page {
+            items: page.items.into_iter().map(|(_, record)| record).collect(),
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    /// Decisions of the agent named `agent` matching `query`, oldest first
+    pub async fn query(&self, agent: &str, query: &AuditQuery) -> AgentResult<Vec<DecisionRecord>> {
+        let mut matching = Vec::new();
+        let mut page = PageRequest::first(MAX_PAGE_SIZE);
+        loop {
+            let records = self.history(agent, &page).await?;
+            for record in records.items {
+                if query.matches(&record) {
+                    matching.push(record);
+                    if query.limit.map_or(false, |limit| matching.len() >= limit) {
+                        return Ok(matching);
+                    }
+                }
+            }
+            match records.next_cursor {
+                Some(cursor) => page = PageRequest::after(cursor, MAX_PAGE_SIZE),
+                None => return Ok(matching),
+            }
+        }
+    }
+
+    /// Decision of the agent named `agent` that sent the transaction
+    /// `signature`
+    pub async fn explain(&self, agent: &str, signature: &str) -> AgentResult<Option<DecisionRecord>> {
+        let executed = self.query(agent, &AuditQuery::default().executed(true)).await?;
+        Ok(executed.into_iter().find(|record| record.signature() == Some(signature)))
+    }
+
+    /// Sequence number following the latest decision stored for the agent
+    /// named `agent`, 0 if there is none
+    async fn next_sequence(&self, agent: &str) -> AgentResult<u64> {
+        let prefix = self.prefix(agent);
+        let mut last = None;
+        let mut page = PageRequest::first(MAX_PAGE_SIZE);
+        loop {
+            let keys = self
+                .storage
+                .scan::<DecisionRecord>(&prefix, &page)
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+            if let Some((key, _)) = keys.items.last() {
+                last = Some(key.clone());
+            }
+            match keys.next_cursor {
+                Some(cursor) => page = PageRequest::after(cursor, MAX_PAGE_SIZE),
+                None => break,
+            }
+        }
+
+        match last {
+            Some(key) => key
+                .strip_prefix(&prefix)
+                .and_then(|sequence| sequence.parse::<u64>().ok())
+                .map(|sequence| sequence + 1)
+                .ok_or(AgentError::ValidationError),
+            None => Ok(0),
+        }
+    }
+
+    fn prefix(&self, agent: &str) -> String {
+        format!("{}{}/", self.config.key_prefix, agent)
+    }
+
+    /// Key of a decision; zero-padded so keys sort by sequence
+    fn key(&self, agent: &str, sequence: u64) -> String {
+        format!("{}{:020}", self.prefix(agent), sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::storage::StorageConfig;
+
+    async fn storage(dir: &tempfile::TempDir) -> Arc<StorageManager> {
+        let config = StorageConfig {
+            base_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        Arc::new(StorageManager::new(config).await.unwrap())
+    }
+
+    fn executed(signature: &str) -> DecisionOutcome {
+        DecisionOutcome::Executed { signature: signature.to_string() }
+    }
+
+    fn record(chosen: &str, confidence: Option<f32>, outcome: DecisionOutcome) -> DecisionRecord {
+        let candidates = vec![chosen.to_string(), "Memo".to_string()];
+        let inputs = json!([{ "price": 19.5 }]);
+        DecisionRecord::new("trader", &inputs, candidates, chosen.to_string(), confidence, outcome)
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let executed = record("Transfer", Some(0.9), DecisionOutcome::Executed { signature: "sig".to_string() });
+        let denied = record("Swap", Some(0.4), DecisionOutcome::Denied { error: "denied".to_string() });
+        let unscored = record("Swap", None, DecisionOutcome::Denied { error: "denied".to_string() });
+
+        assert!(AuditQuery::default().matches(&denied));
+        assert!(AuditQuery::default().action("Transfer").matches(&executed));
+        assert!(!AuditQuery::default().action("Transfer").matches(&denied));
+        assert!(!AuditQuery::default().executed(true).matches(&denied));
+        assert!(!AuditQuery::default().min_confidence(0.5).matches(&denied));
+        assert!(AuditQuery::default().matches(&unscored));
+        assert!(!AuditQuery::default().min_confidence(0.0).matches(&unscored));
+        assert!(!AuditQuery::default().since(executed.timestamp + 1).matches(&executed));
+        assert!(AuditQuery::default().until(executed.timestamp).matches(&executed));
+    }
+
+    #[test]
+    fn test_record() {
+        let record = record("Transfer", Some(1.5), DecisionOutcome::Executed { signature: "sig".to_string() });
+        assert_eq!(record.confidence, Some(1.0));
+        assert_eq!(record.signature(), Some("sig"));
+        assert_eq!(record.inputs().unwrap()[0]["price"], json!(19.5));
+    }
+
+    #[tokio::test]
+    async fn test_append_and_resume() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let log = AuditLog::new(storage.clone(), AuditConfig::default());
+
+        assert_eq!(log.append(record("Transfer", None, executed("a"))).await, Ok(0));
+        assert_eq!(log.append(record("Swap", None, executed("b"))).await, Ok(1));
+
+        // A restarted log resumes after the latest record, past any gap
+        let gap = DecisionRecord { sequence: 5, ..record("Memo", None, executed("c")) };
+        storage.store(&log.key("trader", 5), &gap).await.unwrap();
+        let restarted = AuditLog::new(storage.clone(), AuditConfig::default());
+        assert_eq!(restarted.append(record("Transfer", None, executed("d"))).await, Ok(6));
+
+        // A log that fell behind skips the records appended elsewhere
+        assert_eq!(log.append(record("Swap", None, executed("e"))).await, Ok(2));
+        let taken = DecisionRecord { sequence: 3, ..record("Memo", None, executed("f")) };
+        storage.store(&log.key("trader", 3), &taken).await.unwrap();
+        assert_eq!(log.append(record("Swap", None, executed("g"))).await, Ok(4));
+        let kept: DecisionRecord = storage.retrieve(&log.key("trader", 3)).await.unwrap();
+        assert_eq!(kept.signature(), Some("f"));
+    }
+
+    #[tokio::test]
+    async fn test_history_and_explain() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(storage(&dir).await, AuditConfig::default());
+        for i in 0..5 {
+            let outcome = match i % 2 {
+                0 => executed(&format!("sig-{}", i)),
+                _ => DecisionOutcome::Failed { error: "failed".to_string() },
+            };
+            log.append(record("Transfer", Some(i as f32 / 4.0), outcome)).await.unwrap();
+        }
+
+        let first = log.history("trader", &PageRequest::first(2)).await.unwrap();
+        assert_eq!(first.items.iter().map(|r| r.sequence).collect::<Vec<_>>(), vec![0, 1]);
+        let cursor = first.next_cursor.unwrap();
+        let second = log.history("trader", &PageRequest::after(cursor, 2)).await.unwrap();
+        assert_eq!(second.items.iter().map(|r| r.sequence).collect::<Vec<_>>(), vec![2, 3]);
+        let cursor = second.next_cursor.unwrap();
+        let last = log.history("trader", &PageRequest::after(cursor, 2)).await.unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert!(last.next_cursor.is_none());
+        assert!(log.history("analyst", &PageRequest::first(2)).await.unwrap().items.is_empty());
+
+        let confident = log.query("trader", &AuditQuery::default().min_confidence(0.5)).await.unwrap();
+        assert_eq!(confident.len(), 3);
+
+        assert_eq!(log.explain("trader", "sig-2").await.unwrap().unwrap().sequence, 2);
+        assert_eq!(log.explain("trader", "sig-3").await.unwrap(), None);
+    }
+}
@@ -1,17 +1,32 @@
-use solana_program::{
-    account_info::AccountInfo,
-    pubkey::Pubkey,
-    program_error::ProgramError,
-};
-use crate::SonomaConfig;
-use super::{AgentBehavior, AgentState, capabilities::AgentCapabilities, base::Agent};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_program::program_error::ProgramError;
+use crate::solana::program::action::AgentAction;
+use crate::validation::{Validate, Violations};
+use super::{AgentBehavior, base::Agent};
+use super::audit::{AuditLog, DecisionOutcome, DecisionRecord};
+use super::capabilities::AgentCapabilities;
+use super::error::{AgentError, AgentResult};
+use super::lifecycle::AgentLifecycle;
+use super::memory::AgentMemory;
+use super::runtime::ScheduledAgent;
 
 #[derive(Debug)]
 pub struct AutonomousAgent {
-    base: Agent,
+    base: Arc<Agent>,
     autonomous_config: AutonomousConfig,
     execution_state: ExecutionState,
     last_action: Option<String>,
+    /// Actions planned for the next cycles, oldest first
+    planned: VecDeque<AgentAction>,
+    memory: AgentMemory,
+    /// Checked before each action is sent; the agent's on-chain grants,
+    /// read before the first action, unless set
+    capabilities: Option<AgentCapabilities>,
+    /// Log each decision is appended to; decisions are unaudited if `None`
+    audit: Option<AuditLog>,
 }
 
 #[derive(Debug, Clone)]
@@ -19,10 +34,11 @@ pub struct AutonomousConfig {
     pub decision_threshold: f32,
     pub max_actions_per_cycle: u32,
     pub learning_rate: f32,
+    /// Most entries the agent's memory holds
     pub memory_capacity: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExecutionState {
     Planning,
     Executing,
@@ -30,6 +46,25 @@ pub enum ExecutionState {
     Idle,
 }
 
+/// Decision of the planning phase, before its outcome is known
+#[derive(Debug)]
+struct Decision {
+    inputs: Value,
+    candidates: Vec<String>,
+    chosen: String,
+}
+
+/// State of an autonomous agent kept across restarts
+///
+/// Planned actions aren't kept: plans are made against the market of the
+/// moment, and replaying stale ones after a restart could be costly.
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    execution_state: ExecutionState,
+    last_action: Option<String>,
+    memory: AgentMemory,
+}
+
 impl Default for AutonomousConfig {
     fn default() -> Self {
         Self {
@@ -41,25 +76,229 @@ impl Default for AutonomousConfig {
     }
 }
 
+impl Validate for AutonomousConfig {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            (0.0..=1.0).contains(&self.decision_threshold),
+            "decision_threshold",
+            "must be in [0, 1]",
+            "use a confidence threshold such as 0.7",
+        );
+        violations.check(
+            self.learning_rate > 0.0 && self.learning_rate <= 1.0,
+            "learning_rate",
+            "must be in (0, 1]",
+            "use a small rate such as 0.01",
+        );
+        violations.check(
+            self.max_actions_per_cycle > 0,
+            "max_actions_per_cycle",
+            "must be greater than 0",
+            "allow at least 1 action per cycle, e.g. 100",
+        );
+        violations.check(
+            self.memory_capacity > 0,
+            "memory_capacity",
+            "must be greater than 0",
+            "keep at least 1 memory entry, e.g. 1000",
+        );
+    }
+}
+
 impl AutonomousAgent {
-    pub fn new(name: &str, config: &SonomaConfig) -> Self {
+    /// Autonomous agent acting through the on-chain agent `base`
+    pub fn new(base: Agent) -> Self {
+        let autonomous_config = AutonomousConfig::default();
         Self {
-            base: Agent::new(name, config),
-            autonomous_config: AutonomousConfig::default(),
+            base: Arc::new(base),
+            memory: AgentMemory::new(autonomous_config.memory_capacity),
+            autonomous_config,
             execution_state: ExecutionState::Idle,
             last_action: None,
+            planned: VecDeque::new(),
+            capabilities: None,
+            audit: None,
         }
     }
 
-    pub async fn execute_cycle(&mut self) -> Result<(), ProgramError> {
+    /// Reject planned actions needing capabilities beyond `capabilities`
+    /// before sending them, rather than beyond the agent's on-chain grants
+    pub fn with_capabilities(mut self, capabilities: AgentCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    pub fn capabilities(&self) -> Option<&AgentCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Append each decision to `audit`, with the latest memory entries as
+    /// its inputs and the actions planned for the cycle as its candidates
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.base.name
+    }
+
+    /// Queue `action` for a coming cycle
+    pub fn plan(&mut self, action: AgentAction) {
+        self.planned.push_back(action);
+    }
+
+    pub fn planned_actions(&self) -> usize {
+        self.planned.len()
+    }
+
+    /// Observations and decisions of the agent, for planning to recall
+    ///
+    /// Executed actions are recorded as decisions tagged `executed`, and
+    /// failed ones as observations tagged `failure`.
+    pub fn memory(&self) -> &AgentMemory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut AgentMemory {
+        &mut self.memory
+    }
+
+    /// Execute up to `max_actions_per_cycle` planned actions on-chain,
+    /// oldest first, returning how many were executed
+    ///
+    /// A failed action stays queued and ends the cycle; an action the
+    /// agent lacks the capabilities for is dropped, as it can't succeed.
+    pub async fn execute_cycle(&mut self) -> AgentResult<u32> {
+        self.execute_actions(self.autonomous_config.max_actions_per_cycle).await
+    }
+
+    async fn execute_actions(&mut self, max_actions: u32) -> AgentResult<u32> {
         println!("Executing autonomous cycle for agent: {}", self.base.name);
         self.execution_state = ExecutionState::Planning;
-        // Implement autonomous decision-making cycle
-        self.last_action = Some("Completed planning phase".to_string());
-        Ok(())
+        let budget = max_actions.min(self.autonomous_config.max_actions_per_cycle);
+
+        if self.capabilities.is_none() && !self.planned.is_empty() {
+            self.load_capabilities().await;
+        }
+
+        let mut executed = 0;
+        self.execution_state = ExecutionState::Executing;
+        while executed < budget {
+            let Some(action) = self.planned.front().cloned() else {
+                break;
+            };
+            let description = format!("{:?}", action);
+            let decision = self.decision(&description, (budget - executed) as usize);
+            if let Some(capabilities) = &self.capabilities {
+                if let Err(e) = capabilities.allows_action(&action) {
+                    let missing = capabilities.flags().missing(action.required_capabilities());
+                    println!("Agent {} lacks {} to run {}", self.base.name, missing, description);
+                    self.audit(decision, DecisionOutcome::Denied { error: e.to_string() }).await;
+                    self.planned.pop_front();
+                    self.memory.observe(
+                        json!({ "action": description, "error": e.to_string() }),
+                        1.0,
+                        &["failure", "denied"],
+                    );
+                    self.execution_state = ExecutionState::Idle;
+                    return Err(e);
+                }
+            }
+
+            let base = self.base.clone();
+            let result = tokio::task::spawn_blocking(move || base.execute_action(action))
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+
+            match result {
+                Ok(signature) => {
+                    self.audit(decision, DecisionOutcome::Executed { signature: signature.to_string() }).await;
+                    self.planned.pop_front();
+                    self.memory.record_decision(
+                        json!({ "action": description, "signature": signature.to_string() }),
+                        self.autonomous_config.decision_threshold,
+                        &["executed"],
+                    );
+                    self.last_action = Some(signature.to_string());
+                    executed += 1;
+                }
+                Err(e) => {
+                    self.audit(decision, DecisionOutcome::Failed { error: e.to_string() }).await;
+                    self.memory.observe(
+                        json!({ "action": description, "error": e.to_string() }),
+                        1.0,
+                        &["failure"],
+                    );
+                    self.execution_state = ExecutionState::Idle;
+                    return Err(AgentError::Custom(e.to_string()));
+                }
+            }
+        }
+
+        self.execution_state = ExecutionState::Idle;
+        Ok(executed)
+    }
+
+    /// Default the capabilities to the agent's on-chain grants. Actions go
+    /// unchecked client-side until they can be read; the program still
+    /// refuses those the agent wasn't granted.
+    async fn load_capabilities(&mut self) {
+        let base = self.base.clone();
+        let account = tokio::task::spawn_blocking(move || base.account())
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|account| account.map_err(|e| e.to_string()));
+        match account {
+            Ok(account) => self.capabilities = Some(AgentCapabilities::from_flags(account.config.capabilities)),
+            Err(e) => println!("Failed to read the capabilities of agent {}: {}", self.base.name, e),
+        }
+    }
+
+    /// Decision to run `chosen`, the first of the `candidates` next planned
+    /// actions, if decisions are audited
+    fn decision(&self, chosen: &str, candidates: usize) -> Option<Decision> {
+        let audit = self.audit.as_ref()?;
+        Some(Decision {
+            inputs: serde_json::to_value(self.memory.recent(audit.max_inputs())).unwrap_or_default(),
+            candidates: self.planned.iter().take(candidates).map(|action| format!("{:?}", action)).collect(),
+            chosen: chosen.to_string(),
+        })
+    }
+
+    /// Append `decision` with its `outcome` to the audit log
+    ///
+    /// Planning runs actions in the order they were planned without scoring
+    /// them, so the decision's confidence is unknown. A failed append is
+    /// logged rather than failing the cycle, whose action already ran.
+    async fn audit(&self, decision: Option<Decision>, outcome: DecisionOutcome) {
+        let (Some(audit), Some(decision)) = (&self.audit, decision) else {
+            return;
+        };
+        let record = DecisionRecord::new(
+            &self.base.name,
+            &decision.inputs,
+            decision.candidates,
+            decision.chosen,
+            None,
+            outcome,
+        );
+        if let Err(e) = audit.append(record).await {
+            println!("Failed to audit decision of agent {}: {}", self.base.name, e);
+        }
     }
 
     pub async fn update_config(&mut self, config: AutonomousConfig) -> Result<(), ProgramError> {
+        if let Err(errors) = config.validate() {
+            println!("Rejected autonomous configuration for {}: {}", self.base.name, errors);
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        self.memory.set_capacity(config.memory_capacity);
         self.autonomous_config = config;
         println!("Updated autonomous configuration for: {}", self.base.name);
         Ok(())
@@ -70,6 +309,52 @@ impl AutonomousAgent {
     }
 }
 
+#[async_trait::async_trait]
+impl AgentLifecycle for AutonomousAgent {
+    async fn on_shutdown(&mut self) -> AgentResult<()> {
+        if !self.planned.is_empty() {
+            println!("Agent {} stopped with {} planned actions left", self.base.name, self.planned.len());
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl ScheduledAgent for AutonomousAgent {
+    fn name(&self) -> &str {
+        &self.base.name
+    }
+
+    fn max_actions_per_cycle(&self) -> u32 {
+        self.autonomous_config.max_actions_per_cycle
+    }
+
+    async fn run_cycle(&mut self, max_actions: u32) -> AgentResult<u32> {
+        self.execute_actions(max_actions).await
+    }
+
+    fn checkpoint(&self) -> AgentResult<Option<Value>> {
+        let checkpoint = Checkpoint {
+            execution_state: self.execution_state.clone(),
+            last_action: self.last_action.clone(),
+            memory: self.memory.clone(),
+        };
+        serde_json::to_value(checkpoint)
+            .map(Some)
+            .map_err(|e| AgentError::Custom(e.to_string()))
+    }
+
+    fn restore(&mut self, state: Value) -> AgentResult<()> {
+        let checkpoint: Checkpoint = serde_json::from_value(state).map_err(|_| AgentError::ValidationError)?;
+        self.execution_state = checkpoint.execution_state;
+        self.last_action = checkpoint.last_action;
+        self.memory = checkpoint.memory;
+        // The configured capacity wins over the checkpointed one
+        self.memory.set_capacity(self.autonomous_config.memory_capacity);
+        Ok(())
+    }
+}
+
 impl AgentBehavior for AutonomousAgent {
     fn process_data(&self) -> Result<(), Box<dyn std::error::Error>> {
         println!("Processing data in autonomous agent: {}", self.base.name);
@@ -87,27 +372,76 @@ impl AgentBehavior for AutonomousAgent {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::capabilities::CapabilityKind;
+    use solana_client::rpc_client::RpcClient;
+    use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+
+    fn agent() -> AutonomousAgent {
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        AutonomousAgent::new(Agent::open(&rpc, &Pubkey::new_unique(), &Keypair::new(), "test_autonomous_agent"))
+    }
 
     #[test]
     fn test_autonomous_agent_creation() {
-        let config = SonomaConfig::default();
-        let agent = AutonomousAgent::new("test_autonomous_agent", &config);
-        assert_eq!(agent.base.name, "test_autonomous_agent");
-        matches!(agent.execution_state, ExecutionState::Idle);
+        let agent = agent();
+        assert_eq!(agent.name(), "test_autonomous_agent");
+        assert!(matches!(agent.execution_state, ExecutionState::Idle));
+        assert_eq!(agent.planned_actions(), 0);
     }
 
-    #[test]
-    fn test_autonomous_config_update() {
-        let config = SonomaConfig::default();
-        let mut agent = AutonomousAgent::new("test_autonomous_agent", &config);
+    #[tokio::test]
+    async fn test_autonomous_config_update() {
+        let mut agent = agent();
         let new_config = AutonomousConfig {
             decision_threshold: 0.8,
             max_actions_per_cycle: 200,
             learning_rate: 0.02,
             memory_capacity: 2000,
         };
-        agent.update_config(new_config.clone()).unwrap();
+        agent.update_config(new_config.clone()).await.unwrap();
         assert_eq!(agent.autonomous_config.decision_threshold, 0.8);
         assert_eq!(agent.autonomous_config.max_actions_per_cycle, 200);
+        assert_eq!(agent.memory().capacity(), 2000);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let mut agent = agent();
+        agent.memory_mut().observe(json!({ "price": 19.5 }), 0.7, &["SOL/USDC"]);
+        agent.last_action = Some("signature".to_string());
+        let state = agent.checkpoint().unwrap().unwrap();
+
+        let mut restarted = self::agent();
+        restarted.restore(state).unwrap();
+        assert_eq!(restarted.last_action.as_deref(), Some("signature"));
+        assert_eq!(restarted.memory().recent(1)[0].content, json!({ "price": 19.5 }));
+        assert!(matches!(restarted.restore(json!("garbage")), Err(AgentError::ValidationError)));
+    }
+
+    #[tokio::test]
+    async fn test_actions_need_granted_capabilities() {
+        let mut agent = agent().with_capabilities(AgentCapabilities::new([CapabilityKind::Compute]));
+        agent.plan(AgentAction::Transfer {
+            destination: Pubkey::new_unique(),
+            lamports: 1_000,
+        });
+
+        assert_eq!(agent.execute_cycle().await, Err(AgentError::InsufficientPermissions));
+        assert_eq!(agent.planned_actions(), 0);
+        assert_eq!(agent.memory().recent(1)[0].tags, vec!["failure", "denied"]);
+    }
+
+    #[test]
+    fn test_autonomous_config_validation() {
+        assert!(AutonomousConfig::default().validate().is_ok());
+
+        let config = AutonomousConfig {
+            decision_threshold: 1.2,
+            learning_rate: 0.0,
+            ..Default::default()
+        };
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.0.len(), 2);
+        assert_eq!(errors.0[0].field, "decision_threshold");
     }
 }
\ No newline at end of file
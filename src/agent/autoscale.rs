@@ -0,0 +1,240 @@
+//! Load-adaptive cycle intervals
+//!
+//! This module provides:
+//! - `AutoscaleConfig`, bounding an agent's cycle interval and the load it
+//!   tolerates before slowing down
+//! - `Autoscaler`, stretching the interval while the agent is overloaded
+//!   and shrinking it back once the load subsides
+//! - The `LatencySource` trait the runtime reads RPC latency from,
+//!   implemented for `RpcEndpoints`
+//!
+//! An agent is overloaded if the RPC latency exceeds the target, more
+//! messages wait in its mailbox than allowed, or too many of its recent
+//! cycles failed. Its interval shrinks only once every signal is below half
+//! its limit, so it doesn't flap around a limit.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::solana::failover::RpcEndpoints;
+use super::error::{AgentError, AgentResult};
+
+/// Recent cycles the error rate is measured over by default
+pub const DEFAULT_ERROR_WINDOW: usize = 20;
+
+/// Bounds and load limits of an adaptive schedule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoscaleConfig {
+    /// Shortest interval between cycles, and the one the agent starts at
+    pub min_interval: Duration,
+    /// Longest interval between cycles
+    pub max_interval: Duration,
+    /// RPC latency above which the agent slows down
+    pub target_latency: Duration,
+    /// Messages waiting in the mailbox above which the agent slows down
+    pub max_queue_depth: usize,
+    /// Share of recent cycles failing above which the agent slows down
+    pub max_error_rate: f64,
+    /// Recent cycles the error rate is measured over
+    pub error_window: usize,
+    /// Factor the interval is multiplied by while overloaded, above 1
+    pub stretch: f64,
+    /// Factor the interval is multiplied by once the load subsided, below 1
+    pub shrink: f64,
+}
+
+impl Default for AutoscaleConfig {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(60),
+            target_latency: Duration::from_millis(500),
+            max_queue_depth: 100,
+            max_error_rate: 0.2,
+            error_window: DEFAULT_ERROR_WINDOW,
+            stretch: 2.0,
+            shrink: 0.75,
+        }
+    }
+}
+
+impl AutoscaleConfig {
+    pub fn validate(&self) -> AgentResult<()> {
+        let valid = !self.min_interval.is_zero()
+            && self.min_interval <= self.max_interval
+            && (0.0..=1.0).contains(&self.max_error_rate)
+            && self.error_window > 0
+            && self.stretch > 1.0
+            && self.shrink > 0.0
+            && self.shrink < 1.0;
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidConfiguration)
+        }
+    }
+}
+
+/// Load an agent is under, as of its latest cycle
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LoadSignals {
+    /// Latency of the RPC endpoint; `None` if unknown
+    pub rpc_latency: Option<Duration>,
+    /// Messages waiting in the agent's mailbox
+    pub queue_depth: usize,
+    /// Share of the agent's recent cycles that failed
+    pub error_rate: f64,
+}
+
+/// Source of the RPC latency agents are scheduled by
+pub trait LatencySource: Send + Sync {
+    /// Current latency; `None` before any request was measured
+    fn latency(&self) -> Option<Duration>;
+}
+
+impl LatencySource for RpcEndpoints {
+    /// Average latency of the endpoint requests currently go to
+    fn latency(&self) -> Option<Duration> {
+        let url = self.current_url();
+        self.status()
+            .into_iter()
+            .find(|status| status.url == url)
+            .filter(|status| status.metrics.total_responses > 0)
+            .map(|status| status.metrics.latency_ewma)
+    }
+}
+
+/// Cycle interval of one agent, adjusted to its load after every cycle
+#[derive(Debug, Clone)]
+pub struct Autoscaler {
+    config: AutoscaleConfig,
+    interval: Duration,
+    /// Whether each recent cycle failed, oldest first
+    failures: VecDeque<bool>,
+}
+
+impl Autoscaler {
+    pub fn new(config: AutoscaleConfig) -> Self {
+        Self {
+            interval: config.min_interval,
+            failures: VecDeque::with_capacity(config.error_window),
+            config,
+        }
+    }
+
+    /// Interval until the next cycle
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Record whether a cycle failed, for the error rate
+    pub fn record_cycle(&mut self, failed: bool) {
+        if self.failures.len() == self.config.error_window {
+            self.failures.pop_front();
+        }
+        self.failures.push_back(failed);
+    }
+
+    /// Share of the recent cycles that failed, 0 before the first
+    pub fn error_rate(&self) -> f64 {
+        if self.failures.is_empty() {
+            return 0.0;
+        }
+        self.failures.iter().filter(|&&failed| failed).count() as f64 / self.failures.len() as f64
+    }
+
+    /// Stretch or shrink the interval for `signals`, returning the new one
+    pub fn adjust(&mut self, signals: &LoadSignals) -> Duration {
+        let config = &self.config;
+        let latency = signals.rpc_latency.unwrap_or_default();
+        let overloaded = latency > config.target_latency
+            || signals.queue_depth > config.max_queue_depth
+            || signals.error_rate > config.max_error_rate;
+        let idle = latency <= config.target_latency / 2
+            && signals.queue_depth <= config.max_queue_depth / 2
+            && signals.error_rate <= config.max_error_rate / 2.0;
+
+        let factor = if overloaded {
+            config.stretch
+        } else if idle {
+            config.shrink
+        } else {
+            return self.interval;
+        };
+        self.interval = self.interval.mul_f64(factor).clamp(config.min_interval, config.max_interval);
+        self.interval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AutoscaleConfig {
+        AutoscaleConfig {
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(8),
+            error_window: 4,
+            ..AutoscaleConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(config().validate().is_ok());
+
+        let invalid = [
+            AutoscaleConfig { min_interval: Duration::ZERO, ..config() },
+            AutoscaleConfig { max_interval: Duration::from_millis(500), ..config() },
+            AutoscaleConfig { stretch: 1.0, ..config() },
+            AutoscaleConfig { shrink: 1.0, ..config() },
+            AutoscaleConfig { error_window: 0, ..config() },
+        ];
+        for config in invalid {
+            assert_eq!(config.validate(), Err(AgentError::InvalidConfiguration));
+        }
+    }
+
+    #[test]
+    fn test_stretch_and_shrink() {
+        let mut autoscaler = Autoscaler::new(config());
+        assert_eq!(autoscaler.interval(), Duration::from_secs(1));
+
+        let congested = LoadSignals {
+            rpc_latency: Some(Duration::from_secs(2)),
+            ..LoadSignals::default()
+        };
+        assert_eq!(autoscaler.adjust(&congested), Duration::from_secs(2));
+        assert_eq!(autoscaler.adjust(&congested), Duration::from_secs(4));
+        assert_eq!(autoscaler.adjust(&congested), Duration::from_secs(8));
+        assert_eq!(autoscaler.adjust(&congested), Duration::from_secs(8));
+
+        // Between half the limit and the limit the interval holds
+        let recovering = LoadSignals {
+            queue_depth: 60,
+            ..LoadSignals::default()
+        };
+        assert_eq!(autoscaler.adjust(&recovering), Duration::from_secs(8));
+
+        assert_eq!(autoscaler.adjust(&LoadSignals::default()), Duration::from_secs(6));
+        for _ in 0..10 {
+            autoscaler.adjust(&LoadSignals::default());
+        }
+        assert_eq!(autoscaler.interval(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_error_rate_window() {
+        let mut autoscaler = Autoscaler::new(config());
+        assert_eq!(autoscaler.error_rate(), 0.0);
+
+        autoscaler.record_cycle(true);
+        autoscaler.record_cycle(false);
+        assert_eq!(autoscaler.error_rate(), 0.5);
+
+        for _ in 0..4 {
+            autoscaler.record_cycle(false);
+        }
+        assert_eq!(autoscaler.error_rate(), 0.0);
+    }
+}
@@ -1,16 +1,878 @@
-pub mod base;
-pub mod trading;
-pub mod analysis;
-pub mod state;
-pub mod capabilities;
-
-pub use base::Agent;
-pub use trading::TradingAgent;
-pub use analysis::AnalysisAgent;
-pub use state::AgentState;
-pub use capabilities::AgentCapabilities;
-
-pub trait AgentBehavior {
-    fn process_data(&self) -> Result<(), Box<dyn std::error::Error>>;
-    fn update_state(&mut self) -> Result<(), Box<dyn std::error::Error>>;
-}
\ No newline at end of file
+//! RPC-backed handle to an on-chain agent
+//!
+//! This module provides:
+//! - Client-side agent configuration and its on-chain counterpart
+//! - Creating, staking and starting an agent in one transaction, from a
+//!   config or cloned from an existing agent
+//! - Executing actions, pausing, resuming and closing an agent
+//! - Subscribing to the agent's state transitions, over WebSocket or any
+//!   other account stream
+//! - Reading the agent's events, past and live, and its execution history
+//! - Reading the agent's execution metrics, consistently with its own
+//!   latest writes, optionally through a shared account cache
+//! - Simulating executions before sending them
+//! - Tracking the agent's execution budget, warning as it runs low
+//! - Exporting unsigned transactions for offline signing
+//! - Failing over between several RPC endpoints
+//! - Priority fees estimated from recent prioritization fees
+//! - Per-transaction and daily fee budgets, checked before sending
+//! - Memos attributing every transaction to the agent and its strategy
+//!
+//! An `Agent` keeps its own connection, using the URL and commitment of the
+//! client it was created with or an ordered list of endpoints, and a shared
+//! handle to the payer's signer, which signs every transaction as the
+//! agent's authority.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account,
+    address_lookup_table::AddressLookupTableAccount,
+    clock::Slot,
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    system_instruction,
+    transaction::VersionedTransaction,
+};
+use tokio::sync::broadcast;
+use crate::error::{SonomaError, SonomaResult};
+use crate::solana::cache::AccountCache;
+use crate::solana::client::{self, ReadOptions};
+use crate::solana::events::{self, EventRecord};
+use crate::solana::failover::{EndpointStatus, RpcEndpoints};
+use crate::solana::fees::PriorityFeeOracle;
+use crate::solana::history::{self, ExecutionRecord};
+use crate::solana::memo::TransactionMemo;
+use crate::solana::offline::OfflineTransaction;
+use crate::solana::send::{self, SendStrategy};
+use crate::solana::signer::SonomaSigner;
+use crate::solana::simulation::{self, SimulationResult};
+use crate::solana::stream::{AccountStream, WebSocketStream};
+use crate::solana::transaction::{ComputeBudget, TransactionBuilder};
+use crate::solana::program::{
+    action::AgentAction,
+    capability::CapabilityFlags,
+    error::AgentError,
+    instruction::{self, AgentInstruction},
+    pda,
+    state::{AgentAccount, AgentState, PauseReason, MIN_AGENT_STAKE},
+};
+use crate::validation::{ConfigErrors, ConfigViolation, Validate, Violations};
+use super::budget::{BudgetTracker, BudgetWarning, ExecutionBudget};
+use super::fee_guard::FeeGuard;
+
+/// Number of state transitions a lagging subscriber may fall behind by
+pub const STATE_CHANNEL_CAPACITY: usize = 16;
+
+/// Capabilities granted to an agent
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub compute: bool,
+    pub storage: bool,
+    pub network: bool,
+    /// Names of further capabilities: on-chain ones such as "oracle" or
+    /// "trading", or client-side ones (see `CapabilityKind::Custom`)
+    pub custom_capabilities: Vec<String>,
+}
+
+impl Capabilities {
+    /// Capability flags granting these capabilities; client-side custom
+    /// names have no flag and are skipped
+    pub fn to_flags(&self) -> CapabilityFlags {
+        [
+            (self.compute, CapabilityFlags::COMPUTE),
+            (self.storage, CapabilityFlags::STORAGE),
+            (self.network, CapabilityFlags::NETWORK),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, flag)| enabled.then_some(flag))
+        .chain(self.custom_capabilities.iter().filter_map(|name| CapabilityFlags::from_name(name)))
+        .fold(CapabilityFlags::NONE, |flags, flag| flags | flag)
+    }
+}
+
+impl Validate for Capabilities {
+    fn collect_violations(&self, violations: &mut Violations) {
+        for name in &self.custom_capabilities {
+            violations.check(
+                !name.trim().is_empty(),
+                "custom_capabilities",
+                "names must not be blank",
+                "name the capability, e.g. \"trading\"",
+            );
+        }
+    }
+}
+
+/// Client-side agent configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentConfig {
+    pub autonomous_mode: bool,
+    pub execution_limit: u64,
+    pub memory_limit: u64,
+    pub capabilities: Capabilities,
+    /// Free-form description of the agent; not stored on chain
+    pub metadata: Option<serde_json::Value>,
+}
+
+impl Validate for AgentConfig {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            self.execution_limit > 0,
+            "execution_limit",
+            "must be greater than 0",
+            "allow at least 1 execution, e.g. 1000",
+        );
+        violations.check(
+            self.memory_limit > 0,
+            "memory_limit",
+            "must be greater than 0",
+            "set memory_limit in bytes, e.g. 5000",
+        );
+        violations.nested("capabilities", &self.capabilities);
+    }
+}
+
+impl AgentConfig {
+    /// On-chain config with these settings, keeping the rate limit and
+    /// allowed programs of `current`
+    pub fn to_program_config(&self, current: Option<&instruction::AgentConfig>) -> SonomaResult<instruction::AgentConfig> {
+        self.validate().map_err(SonomaError::InvalidConfiguration)?;
+
+        Ok(instruction::AgentConfig {
+            autonomous_mode: self.autonomous_mode,
+            execution_limit: self.execution_limit,
+            memory_limit: self.memory_limit,
+            capabilities: self.capabilities.to_flags(),
+            min_execution_interval: current.map_or(0, |config| config.min_execution_interval),
+            allowed_programs: current.map_or_else(Vec::new, |config| config.allowed_programs.clone()),
+        })
+    }
+}
+
+/// Execution statistics of an agent, decoded from its account
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentMetrics {
+    pub total_executions: u64,
+    pub successful_executions: u64,
+    pub failed_executions: u64,
+    /// Fraction of executions that succeeded, 0 before the first one
+    pub success_rate: f64,
+    pub average_compute_units: u64,
+    pub total_compute_units: u64,
+    /// Unix timestamp of the last execution, 0 if it never executed
+    pub last_execution: i64,
+}
+
+impl From<&AgentAccount> for AgentMetrics {
+    fn from(agent: &AgentAccount) -> Self {
+        let metrics = &agent.metrics;
+        let success_rate = match metrics.total_executions {
+            0 => 0.0,
+            total => metrics.successful_executions as f64 / total as f64,
+        };
+
+        Self {
+            total_executions: metrics.total_executions,
+            successful_executions: metrics.successful_executions,
+            failed_executions: metrics.failed_executions,
+            success_rate,
+            average_compute_units: metrics.average_compute_units,
+            total_compute_units: metrics.total_compute_units,
+            last_execution: agent.last_execution,
+        }
+    }
+}
+
+/// Agent owned by a payer
+pub struct Agent {
+    pub name: String,
+    rpc: RpcClient,
+    endpoints: Arc<RpcEndpoints>,
+    program_id: Pubkey,
+    payer: Arc<dyn SonomaSigner>,
+    pubkey: Pubkey,
+    compute_budget: ComputeBudget,
+    /// Prices transactions whose compute budget sets no unit price
+    priority_fees: Option<PriorityFeeOracle>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    send_strategy: SendStrategy,
+    /// Memo attached to every transaction, naming the agent
+    memo: Option<TransactionMemo>,
+    /// Budgets every transaction is checked against before it is sent
+    fee_guard: Option<FeeGuard>,
+    /// Cache serving `account` reads, shared with other handles
+    cache: Option<Arc<AccountCache>>,
+    /// Source of account updates for subscriptions; the RPC endpoint's
+    /// WebSocket if `None`
+    account_stream: Option<Arc<dyn AccountStream>>,
+    /// Commitment of the agent's reads; the RPC client's if `None`
+    read_commitment: Option<CommitmentConfig>,
+    /// Slot of the last transaction the agent sent, 0 before the first;
+    /// reads are served at or after it
+    last_write_slot: AtomicU64,
+    /// Budget as of the agent's latest execution through this handle
+    budget: BudgetTracker,
+}
+
+impl fmt::Debug for Agent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Agent")
+            .field("name", &self.name)
+            .field("rpc_url", &self.endpoints.current_url())
+            .field("program_id", &self.program_id)
+            .field("payer", &self.payer.pubkey())
+            .field("signer", &self.payer.source())
+            .field("pubkey", &self.pubkey)
+            .field("compute_budget", &self.compute_budget)
+            .field("priority_fees", &self.priority_fees)
+            .field("lookup_tables", &self.lookup_tables.iter().map(|table| table.key).collect::<Vec<_>>())
+            .field("send_strategy", &self.send_strategy)
+            .field("memo", &self.memo)
+            .field("fee_guard", &self.fee_guard.as_ref().map(FeeGuard::limits))
+            .field("cache", &self.cache.as_ref().map(|cache| cache.stats()))
+            .field("account_stream", &self.account_stream.is_some())
+            .field("read_commitment", &self.read_commitment)
+            .field("last_write_slot", &self.last_write_slot())
+            .field("budget", &self.budget())
+            .finish()
+    }
+}
+
+impl Agent {
+    /// Create an agent named `name` for `payer`, stake `MIN_AGENT_STAKE`
+    /// for it and start it
+    pub fn new(
+        client: &RpcClient,
+        program_id: &Pubkey,
+        payer: &Keypair,
+        name: &str,
+        config: AgentConfig,
+    ) -> SonomaResult<Self> {
+        Self::new_with_signer(client, program_id, Arc::new(payer.insecure_clone()), name, config)
+    }
+
+    /// `new`, signing with `payer` wherever it holds its key
+    pub fn new_with_signer(
+        client: &RpcClient,
+        program_id: &Pubkey,
+        payer: Arc<dyn SonomaSigner>,
+        name: &str,
+        config: AgentConfig,
+    ) -> SonomaResult<Self> {
+        let endpoints = RpcEndpoints::new(&client.url(), client.commitment());
+        Self::new_with_endpoints(Arc::new(endpoints), program_id, payer, name, config)
+    }
+
+    /// `new_with_signer`, connecting through `endpoints`
+    pub fn new_with_endpoints(
+        endpoints: Arc<RpcEndpoints>,
+        program_id: &Pubkey,
+        payer: Arc<dyn SonomaSigner>,
+        name: &str,
+        config: AgentConfig,
+    ) -> SonomaResult<Self> {
+        let config = config.to_program_config(None)?;
+        let agent = Self::open_with_endpoints(endpoints, program_id, payer, name);
+        let authority = agent.payer.pubkey();
+
+        agent.send(&[
+            AgentInstruction::initialize(program_id, &agent.pubkey, &authority, name.to_string(), config),
+            AgentInstruction::stake(program_id, &agent.pubkey, &authority, MIN_AGENT_STAKE),
+            AgentInstruction::resume(program_id, &agent.pubkey, &authority),
+        ])?;
+        Ok(agent)
+    }
+
+    /// Create an agent named `name` for `payer` with the config of the
+    /// agent at `source`, stake `MIN_AGENT_STAKE` for it and start it, as
+    /// `new` does; the source is read first so a wrong address fails before
+    /// anything is sent
+    pub fn clone_from(
+        client: &RpcClient,
+        program_id: &Pubkey,
+        payer: &Keypair,
+        source: &Pubkey,
+        name: &str,
+    ) -> SonomaResult<Self> {
+        Self::clone_from_with_signer(client, program_id, Arc::new(payer.insecure_clone()), source, name)
+    }
+
+    /// `clone_from`, signing with `payer` wherever it holds its key
+    pub fn clone_from_with_signer(
+        client: &RpcClient,
+        program_id: &Pubkey,
+        payer: Arc<dyn SonomaSigner>,
+        source: &Pubkey,
+        name: &str,
+    ) -> SonomaResult<Self> {
+        let agent = Self::open_with_signer(client, program_id, payer, name);
+        let authority = agent.payer.pubkey();
+        let options = agent.read_options();
+        let (_, clone) =
+            client::clone_agents_with_options(&agent.rpc, program_id, source, &authority, &[name], &options)?.remove(0);
+
+        agent.send(&[
+            clone,
+            AgentInstruction::stake(program_id, &agent.pubkey, &authority, MIN_AGENT_STAKE),
+            AgentInstruction::resume(program_id, &agent.pubkey, &authority),
+        ])?;
+        Ok(agent)
+    }
+
+    /// Handle to the agent `payer` created as `name`, without checking
+    /// that it exists
+    pub fn open(client: &RpcClient, program_id: &Pubkey, payer: &Keypair, name: &str) -> Self {
+        Self::open_with_signer(client, program_id, Arc::new(payer.insecure_clone()), name)
+    }
+
+    /// `open`, signing with `payer` wherever it holds its key
+    pub fn open_with_signer(client: &RpcClient, program_id: &Pubkey, payer: Arc<dyn SonomaSigner>, name: &str) -> Self {
+        let endpoints = RpcEndpoints::new(&client.url(), client.commitment());
+        Self::open_with_endpoints(Arc::new(endpoints), program_id, payer, name)
+    }
+
+    /// `open_with_signer`, connecting through `endpoints`
+    pub fn open_with_endpoints(
+        endpoints: Arc<RpcEndpoints>,
+        program_id: &Pubkey,
+        payer: Arc<dyn SonomaSigner>,
+        name: &str,
+    ) -> Self {
+        let (pubkey, _) = pda::find_agent_address(program_id, &payer.pubkey(), name);
+        Self {
+            name: name.to_string(),
+            rpc: endpoints.client(),
+            endpoints,
+            program_id: *program_id,
+            payer,
+            pubkey,
+            compute_budget: ComputeBudget::default(),
+            priority_fees: None,
+            lookup_tables: Vec::new(),
+            send_strategy: SendStrategy::default(),
+            memo: None,
+            fee_guard: None,
+            cache: None,
+            account_stream: None,
+            read_commitment: None,
+            last_write_slot: AtomicU64::new(0),
+            budget: BudgetTracker::default(),
+        }
+    }
+
+    /// Warn once the agent has used `threshold` of its execution or
+    /// memory limit, rather than `DEFAULT_BUDGET_WARNING_THRESHOLD`
+    pub fn with_budget_warning_threshold(mut self, threshold: f64) -> Self {
+        self.budget = BudgetTracker::new(threshold);
+        self
+    }
+
+    /// Request `compute_budget` in every transaction sent from now on
+    pub fn with_compute_budget(mut self, compute_budget: ComputeBudget) -> Self {
+        self.compute_budget = compute_budget;
+        self
+    }
+
+    /// Pay the priority fee `oracle` suggests from recent fees in every
+    /// transaction sent from now on, unless the compute budget sets a price
+    pub fn with_priority_fees(mut self, oracle: PriorityFeeOracle) -> Self {
+        self.priority_fees = Some(oracle);
+        self
+    }
+
+    /// Send and confirm transactions following `send_strategy` from now on
+    pub fn with_send_strategy(mut self, send_strategy: SendStrategy) -> Self {
+        self.send_strategy = send_strategy;
+        self
+    }
+
+    /// Send v0 transactions looking accounts up in `table` from now on
+    pub fn with_lookup_table(mut self, table: AddressLookupTableAccount) -> Self {
+        self.lookup_tables.push(table);
+        self
+    }
+
+    /// Attach `memo` to every transaction of the agent, with the agent's
+    /// address as its `agent`
+    pub fn with_memo(mut self, memo: TransactionMemo) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    /// Handle to the agent `creator` created as `name`, whose authority
+    /// has since been transferred to the payer, e.g. by a key rotation
+    pub fn with_creator(mut self, creator: &Pubkey) -> Self {
+        self.pubkey = pda::find_agent_address(&self.program_id, creator, &self.name).0;
+        self
+    }
+
+    /// Estimate the cost of every transaction before sending it, refusing
+    /// those beyond the budgets of `guard`
+    pub fn with_fee_guard(mut self, guard: FeeGuard) -> Self {
+        self.fee_guard = Some(guard);
+        self
+    }
+
+    /// Serve `account` reads from `cache` from now on, while cached reads
+    /// are younger than its TTL and not older than the agent's last write
+    pub fn with_account_cache(mut self, cache: Arc<AccountCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Read account updates for subscriptions from `stream` from now on,
+    /// e.g. a `GeyserStream`
+    pub fn with_account_stream(mut self, stream: Arc<dyn AccountStream>) -> Self {
+        self.account_stream = Some(stream);
+        self
+    }
+
+    /// Read the agent's accounts at `commitment` from now on, e.g.
+    /// `processed` to see executions before they are confirmed
+    pub fn with_read_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.read_commitment = Some(commitment);
+        self
+    }
+
+    /// Address of the agent account
+    pub fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    /// Connection the agent sends its transactions through
+    pub fn rpc(&self) -> &RpcClient {
+        &self.rpc
+    }
+
+    /// Health and metrics of the agent's RPC endpoints
+    pub fn endpoint_status(&self) -> Vec<EndpointStatus> {
+        self.endpoints.status()
+    }
+
+    /// Fetch the agent account
+    pub fn account(&self) -> SonomaResult<AgentAccount> {
+        let options = self.read_options();
+        Ok(match &self.cache {
+            Some(cache) => cache.get_agent(&self.rpc, &self.program_id, &self.pubkey, &options)?,
+            None => client::get_agent_with_options(&self.rpc, &self.program_id, &self.pubkey, &options)?,
+        })
+    }
+
+    /// Keep the agent's cached account current from its account stream
+    /// rather than refetching it once the TTL expires; fails with
+    /// `InvalidConfiguration` if no cache is attached
+    pub fn watch_account(&self) -> SonomaResult<()> {
+        match &self.cache {
+            Some(cache) => cache.watch(self.account_stream().as_ref(), &self.program_id, &self.pubkey),
+            None => Err(SonomaError::InvalidConfiguration(ConfigErrors(vec![ConfigViolation {
+                field: "cache".to_string(),
+                message: "no account cache is attached to watch".to_string(),
+                suggestion: "attach one with `with_account_cache`".to_string(),
+            }]))),
+        }
+    }
+
+    /// Slot of the last transaction the agent sent and saw land
+    pub fn last_write_slot(&self) -> Option<Slot> {
+        match self.last_write_slot.load(Ordering::Relaxed) {
+            0 => None,
+            slot => Some(slot),
+        }
+    }
+
+    /// Options of the agent's reads, at its read commitment, which a node
+    /// must serve at or after `last_write_slot` so they reflect the agent's
+    /// own writes
+    pub fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            commitment: self.read_commitment,
+            min_context_slot: self.last_write_slot(),
+        }
+    }
+
+    pub fn get_state(&self) -> SonomaResult<AgentState> {
+        Ok(self.account()?.state)
+    }
+
+    /// Fetch the agent's execution metrics
+    pub fn get_metrics(&self) -> SonomaResult<AgentMetrics> {
+        Ok(AgentMetrics::from(&self.account()?))
+    }
+
+    /// Broadcast the agent's state each time it changes
+    ///
+    /// Notifications are read on a background thread, which stops once
+    /// every receiver is dropped or after reporting the agent's closure as
+    /// `Terminated`.
+    pub fn subscribe_state_changes(&self) -> SonomaResult<broadcast::Receiver<AgentState>> {
+        let mut updates = self.account_stream().subscribe_account(&self.pubkey)?;
+        let mut state = Some(self.get_state()?);
+        let (sender, receiver) = broadcast::channel(STATE_CHANNEL_CAPACITY);
+
+        std::thread::spawn(move || loop {
+            let account = match updates.blocking_recv() {
+                Ok(update) => update.account,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let Some(next) = state_transition(&state, &account) else {
+                continue;
+            };
+            let closed = account.lamports == 0;
+            state = Some(next.clone());
+            if sender.send(next).is_err() || closed {
+                break;
+            }
+        });
+        Ok(receiver)
+    }
+
+    /// Stream the agent's subscriptions read account updates from
+    fn account_stream(&self) -> Arc<dyn AccountStream> {
+        match &self.account_stream {
+            Some(stream) => stream.clone(),
+            None => Arc::new(WebSocketStream::from_rpc(&self.rpc)),
+        }
+    }
+
+    /// Fetch the events the agent emitted in its last `limit` transactions,
+    /// oldest first
+    pub fn get_events(&self, limit: usize) -> SonomaResult<Vec<EventRecord>> {
+        events::get_events(&self.rpc, &self.program_id, Some(&self.pubkey), None, limit)
+    }
+
+    /// Fetch the agent's execution attempts from its last `limit`
+    /// transactions, oldest first, failed ones included
+    pub fn get_execution_history(&self, limit: usize) -> SonomaResult<Vec<ExecutionRecord>> {
+        history::get_execution_history(&self.rpc, &self.program_id, &self.pubkey, None, limit)
+    }
+
+    /// Broadcast the agent's events as their transactions land
+    pub fn subscribe_events(&self) -> SonomaResult<broadcast::Receiver<EventRecord>> {
+        events::subscribe_events(&self.rpc, &self.program_id, Some(&self.pubkey))
+    }
+
+    /// Execute `data` as a memo logged by the agent; it must be non-empty
+    /// UTF-8 of at most `MAX_MEMO_LEN` bytes
+    pub fn execute(&self, data: &[u8]) -> SonomaResult<Signature> {
+        let text = std::str::from_utf8(data).map_err(|_| SonomaError::InvalidAction)?;
+        self.execute_action(AgentAction::Memo { text: text.to_string() })
+    }
+
+    /// Execute `action` as the agent's next execution, then update its
+    /// budget
+    ///
+    /// The program enforces the agent's `execution_limit`; once a budget
+    /// was read, executions it leaves no room for are also refused before
+    /// they are sent
+    pub fn execute_action(&self, action: AgentAction) -> SonomaResult<Signature> {
+        if self.budget().map_or(false, |budget| budget.remaining_executions() == 0) {
+            return Err(SonomaError::Program(AgentError::ExecutionLimitExceeded));
+        }
+        let size = borsh::to_vec(&action).map_or(0, |data| data.len() as u64);
+        let signature = self.send(&[self.execute_instruction(action)?])?;
+
+        // The execution landed even if its budget can't be read back
+        match self.account() {
+            Ok(account) => {
+                self.budget.record(&account, Some(size));
+            }
+            Err(e) => println!("Failed to update budget of agent {}: {}", self.name, e),
+        }
+        Ok(signature)
+    }
+
+    /// Budget of the agent as of its latest execution or refresh, `None`
+    /// before either
+    pub fn budget(&self) -> Option<ExecutionBudget> {
+        self.budget.budget()
+    }
+
+    /// Read the agent's budget from its account, e.g. after its limits
+    /// were raised, returning the warnings it newly raised
+    pub fn refresh_budget(&self) -> SonomaResult<Vec<BudgetWarning>> {
+        Ok(self.budget.record(&self.account()?, None))
+    }
+
+    /// Broadcast a warning each time the agent's budget crosses the
+    /// warning threshold
+    pub fn subscribe_budget_warnings(&self) -> broadcast::Receiver<BudgetWarning> {
+        self.budget.subscribe()
+    }
+
+    /// Unsigned transaction executing `action`, to sign offline and send
+    /// with `OfflineTransaction::submit`
+    ///
+    /// `blockhash` is fixed in the exported message; pass a durable nonce
+    /// if signing may outlast a recent blockhash.
+    pub fn export_execute(&self, action: AgentAction, blockhash: Hash) -> SonomaResult<OfflineTransaction> {
+        self.export(&[self.execute_instruction(action)?], blockhash)
+    }
+
+    /// Unsigned transaction of `instructions`, paid for by the payer, to
+    /// sign offline
+    pub fn export(&self, instructions: &[Instruction], blockhash: Hash) -> SonomaResult<OfflineTransaction> {
+        Ok(OfflineTransaction::new(&self.builder(instructions)?, &self.payer.pubkey(), blockhash)?)
+    }
+
+    /// Simulate executing `action`, without sending it
+    pub fn simulate_execute(&self, action: AgentAction) -> SonomaResult<SimulationResult> {
+        let transaction = self.transaction(&[self.execute_instruction(action)?], Hash::default())?;
+        simulation::simulate(&self.rpc, &transaction)
+    }
+
+    /// `Execute` instruction running `action` as the agent's next execution
+    fn execute_instruction(&self, action: AgentAction) -> SonomaResult<Instruction> {
+        action.validate()?;
+        let execution = self.account()?.execution_count + 1;
+        let (result, _) = pda::find_result_address(&self.program_id, &self.pubkey);
+
+        Ok(AgentInstruction::execute(
+            &self.program_id,
+            &self.pubkey,
+            &self.payer.pubkey(),
+            &result,
+            execution,
+            action,
+        ))
+    }
+
+    /// Replace the agent's config; its rate limit and allowed programs are
+    /// kept. Agents with an update delay only stage the update.
+    pub fn update_config(&self, config: &AgentConfig) -> SonomaResult<Signature> {
+        let current = self.account()?.config;
+        let config = config.to_program_config(Some(&current))?;
+        self.send(&[AgentInstruction::update(&self.program_id, &self.pubkey, &self.payer.pubkey(), config)])
+    }
+
+    pub fn pause(&self) -> SonomaResult<Signature> {
+        self.pause_for(PauseReason::Manual)
+    }
+
+    /// Pause the agent, recording `reason` on-chain
+    pub fn pause_for(&self, reason: PauseReason) -> SonomaResult<Signature> {
+        self.send(&[AgentInstruction::pause(
+            &self.program_id,
+            &self.pubkey,
+            &self.payer.pubkey(),
+            reason,
+            None,
+        )])
+    }
+
+    pub fn resume(&self) -> SonomaResult<Signature> {
+        self.send(&[AgentInstruction::resume(&self.program_id, &self.pubkey, &self.payer.pubkey())])
+    }
+
+    /// Close the agent and withdraw its stake, sending the agent's rent and
+    /// the stake to `recipient`
+    pub fn close(&self, recipient: &Pubkey) -> SonomaResult<Signature> {
+        // Fail before sending if the agent is already closed
+        self.account()?;
+        let (stake, _) = pda::find_stake_address(&self.program_id, &self.pubkey);
+        let config = self.read_options().account_config(&self.rpc);
+        let accounts = self.rpc.get_multiple_accounts_with_config(&[self.pubkey, stake], config)?.value;
+        let lamports: u64 = accounts.iter().flatten().map(|account| account.lamports).sum();
+
+        let payer = self.payer.pubkey();
+        let mut instructions = vec![AgentInstruction::close(&self.program_id, &self.pubkey, &payer)];
+        if accounts[1].is_some() {
+            instructions.push(AgentInstruction::unstake(&self.program_id, &self.pubkey, &payer));
+        }
+        if *recipient != payer {
+            instructions.push(system_instruction::transfer(&payer, recipient, lamports));
+        }
+        self.send(&instructions)
+    }
+
+    /// Sign `instructions` with the payer and send them in one transaction,
+    /// following the agent's send strategy, once its fee guard admits them
+    fn send(&self, instructions: &[Instruction]) -> SonomaResult<Signature> {
+        if let Some(guard) = &self.fee_guard {
+            let estimate = guard.estimate(&self.rpc, &self.builder(instructions)?, &self.payer.pubkey())?;
+            guard.admit(&self.name, &estimate)?;
+        }
+        let signature = send::send_with_strategy(&self.rpc, &self.send_strategy, |blockhash| {
+            self.transaction(instructions, blockhash)
+        })?;
+        self.record_write(&signature);
+        Ok(signature)
+    }
+
+    /// Pin later reads to the slot `signature` landed in. Best effort: if
+    /// the status can't be fetched, reads keep the previous pin.
+    fn record_write(&self, signature: &Signature) {
+        if let Ok(statuses) = self.rpc.get_signature_statuses(&[*signature]) {
+            if let Some(Some(status)) = statuses.value.first() {
+                self.last_write_slot.fetch_max(status.slot, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Transaction of `instructions` with the agent's compute budget and
+    /// lookup tables, signed by the payer
+    fn transaction(&self, instructions: &[Instruction], blockhash: Hash) -> SonomaResult<VersionedTransaction> {
+        Ok(self
+            .builder(instructions)?
+            .build_versioned(&self.payer.pubkey(), &[self.payer.as_signer()], blockhash)?)
+    }
+
+    /// `instructions` with the agent's compute budget and lookup tables
+    ///
+    /// With priority fees enabled, the unit price is estimated anew for
+    /// every transaction, so a resend after expiry pays the current rate.
+    fn builder(&self, instructions: &[Instruction]) -> SonomaResult<TransactionBuilder> {
+        let mut compute_budget = self.compute_budget;
+        if let (Some(oracle), None) = (&self.priority_fees, compute_budget.unit_price) {
+            compute_budget.unit_price = Some(oracle.estimate_for(&self.rpc, instructions)?);
+        }
+
+        let builder = TransactionBuilder::new()
+            .with_compute_budget(compute_budget)
+            .with_lookup_tables(self.lookup_tables.iter().cloned())
+            .add_instructions(instructions.iter().cloned());
+        Ok(match &self.memo {
+            Some(memo) => builder.with_attribution(&TransactionMemo {
+                agent: Some(self.pubkey),
+                ..memo.clone()
+            }),
+            None => builder,
+        })
+    }
+}
+
+/// State of an agent account if it differs from `last`; closed accounts
+/// are `Terminated`
+fn state_transition(last: &Option<AgentState>, account: &Account) -> Option<AgentState> {
+    let state = if account.lamports == 0 {
+        AgentState::Terminated
+    } else {
+        AgentAccount::unpack(&account.data).ok()?.state
+    };
+    (last.as_ref() != Some(&state)).then_some(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+
+    fn config() -> AgentConfig {
+        AgentConfig {
+            autonomous_mode: true,
+            execution_limit: 1000,
+            memory_limit: 10 * 1024 * 1024,
+            capabilities: Capabilities {
+                compute: true,
+                storage: true,
+                network: false,
+                custom_capabilities: vec!["oracle".to_string()],
+            },
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_program_config() {
+        let program_config = config().to_program_config(None).unwrap();
+        assert_eq!(
+            program_config.capabilities,
+            CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE | CapabilityFlags::ORACLE
+        );
+        assert_eq!(program_config.min_execution_interval, 0);
+
+        let current = instruction::AgentConfig {
+            min_execution_interval: 60,
+            allowed_programs: vec![Pubkey::new_unique()],
+            ..program_config.clone()
+        };
+        let updated = config().to_program_config(Some(&current)).unwrap();
+        assert_eq!(updated.min_execution_interval, 60);
+        assert_eq!(updated.allowed_programs, current.allowed_programs);
+
+        // Client-side capabilities have no on-chain flag
+        let mut custom = config();
+        custom.capabilities.custom_capabilities.push("teleport".to_string());
+        assert_eq!(custom.to_program_config(None).unwrap().capabilities, program_config.capabilities);
+
+        let mut blank = config();
+        blank.capabilities.custom_capabilities.push(" ".to_string());
+        match blank.to_program_config(None) {
+            Err(SonomaError::InvalidConfiguration(errors)) => {
+                assert_eq!(errors.0[0].field, "capabilities.custom_capabilities")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut invalid = config();
+        invalid.execution_limit = 0;
+        assert!(matches!(invalid.to_program_config(None), Err(SonomaError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let program_config = config().to_program_config(None).unwrap();
+        let mut agent = AgentAccount::new(Pubkey::new_unique(), "agent".to_string(), program_config);
+        assert_eq!(AgentMetrics::from(&agent).success_rate, 0.0);
+
+        agent.metrics.record(true, 3_000);
+        agent.metrics.record(true, 5_000);
+        agent.metrics.record(false, 1_000);
+        agent.metrics.record(true, 3_000);
+        agent.last_execution = 1_700_000_000;
+
+        let metrics = AgentMetrics::from(&agent);
+        assert_eq!((metrics.total_executions, metrics.failed_executions), (4, 1));
+        assert_eq!(metrics.success_rate, 0.75);
+        assert_eq!(metrics.average_compute_units, 3_000);
+        assert_eq!(metrics.last_execution, 1_700_000_000);
+    }
+
+    #[test]
+    fn test_state_transition() {
+        let program_config = config().to_program_config(None).unwrap();
+        let mut agent = AgentAccount::new(Pubkey::new_unique(), "agent".to_string(), program_config);
+        agent.state = AgentState::Running;
+        let mut account = Account::new(1_000_000, 0, &Pubkey::new_unique());
+        account.data = borsh::to_vec(&agent).unwrap();
+
+        assert_eq!(state_transition(&None, &account), Some(AgentState::Running));
+        assert_eq!(state_transition(&Some(AgentState::Running), &account), None);
+        assert_eq!(state_transition(&Some(AgentState::Paused), &account), Some(AgentState::Running));
+
+        account.data[0] = u8::MAX;
+        assert_eq!(state_transition(&Some(AgentState::Paused), &account), None);
+
+        let closed = Account::default();
+        assert_eq!(state_transition(&Some(AgentState::Running), &closed), Some(AgentState::Terminated));
+    }
+
+    #[test]
+    fn test_open() {
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        let (program_id, payer) = (Pubkey::new_unique(), Keypair::new());
+        let agent = Agent::open(&rpc, &program_id, &payer, "example-rust-agent");
+
+        assert_eq!(agent.pubkey(), pda::find_agent_address(&program_id, &payer.pubkey(), "example-rust-agent").0);
+        assert_eq!(agent.rpc().url(), rpc.url());
+        assert_eq!(agent.endpoint_status().len(), 1);
+        assert!(matches!(agent.execute(&[0xff]), Err(SonomaError::InvalidAction)));
+        assert!(matches!(agent.execute(b""), Err(SonomaError::InvalidAction)));
+        assert!(matches!(agent.watch_account(), Err(SonomaError::InvalidConfiguration(_))));
+
+        assert_eq!(agent.read_options(), ReadOptions::default());
+        let agent = agent.with_read_commitment(CommitmentConfig::processed());
+        assert_eq!(agent.read_options().commitment, Some(CommitmentConfig::processed()));
+    }
+}
@@ -0,0 +1,243 @@
+//! Client-side view of an agent's execution budget
+//!
+//! This module provides:
+//! - `ExecutionBudget`: executions left under the agent's on-chain
+//!   `execution_limit`, and the estimated memory its actions use against
+//!   its `memory_limit`
+//! - `BudgetTracker`, updating the budget after each execution and warning
+//!   when the agent approaches its limits
+//!
+//! The program refuses executions past `execution_limit` and staged
+//! actions larger than `memory_limit` bytes; the tracked budget only lets
+//! `Agent::execute_action` refuse a doomed execution before paying for it.
+//! Warnings are emitted once per crossing of the threshold, so operators
+//! can raise the limits before executions start failing.
+
+use std::fmt;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use crate::solana::program::state::AgentAccount;
+
+/// Fraction of a limit used from which warnings are emitted by default
+pub const DEFAULT_BUDGET_WARNING_THRESHOLD: f64 = 0.8;
+
+/// Number of warnings a lagging subscriber may fall behind by
+pub const BUDGET_CHANNEL_CAPACITY: usize = 16;
+
+/// Budget of an agent as of its latest execution
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionBudget {
+    pub execution_limit: u64,
+    pub executions: u64,
+    /// Largest action data the program accepts, in bytes
+    pub memory_limit: u64,
+    /// Serialized size of the largest action executed through this handle,
+    /// in bytes
+    pub memory_used: u64,
+}
+
+impl ExecutionBudget {
+    /// Budget of `account`, whose actions used up to `memory_used` bytes
+    pub fn from_account(account: &AgentAccount, memory_used: u64) -> Self {
+        Self {
+            execution_limit: account.config.execution_limit,
+            executions: account.execution_count,
+            memory_limit: account.config.memory_limit,
+            memory_used,
+        }
+    }
+
+    pub fn remaining_executions(&self) -> u64 {
+        self.execution_limit.saturating_sub(self.executions)
+    }
+
+    /// Fraction of `execution_limit` used
+    pub fn execution_usage(&self) -> f64 {
+        usage(self.executions, self.execution_limit)
+    }
+
+    /// Fraction of `memory_limit` used by the largest action
+    pub fn memory_usage(&self) -> f64 {
+        usage(self.memory_used, self.memory_limit)
+    }
+}
+
+fn usage(used: u64, limit: u64) -> f64 {
+    match limit {
+        0 => 1.0,
+        limit => used as f64 / limit as f64,
+    }
+}
+
+/// Limit an agent is approaching
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BudgetWarning {
+    ExecutionsLow { remaining: u64, limit: u64 },
+    ExecutionsExhausted { limit: u64 },
+    MemoryHigh { used: u64, limit: u64 },
+}
+
+impl fmt::Display for BudgetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetWarning::ExecutionsLow { remaining, limit } => {
+                write!(f, "{} of {} executions left", remaining, limit)
+            }
+            BudgetWarning::ExecutionsExhausted { limit } => write!(f, "all {} executions used", limit),
+            BudgetWarning::MemoryHigh { used, limit } => {
+                write!(f, "actions use {} of {} bytes of memory", used, limit)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    budget: Option<ExecutionBudget>,
+    /// Warnings emitted since usage last fell below the threshold
+    executions_warned: bool,
+    exhausted_warned: bool,
+    memory_warned: bool,
+}
+
+/// Tracks an agent's budget, broadcasting warnings as it runs low
+#[derive(Debug)]
+pub struct BudgetTracker {
+    threshold: f64,
+    state: Mutex<TrackerState>,
+    warnings: broadcast::Sender<BudgetWarning>,
+}
+
+impl Default for BudgetTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUDGET_WARNING_THRESHOLD)
+    }
+}
+
+impl BudgetTracker {
+    /// Tracker warning once usage of a limit reaches `threshold`, a
+    /// fraction clamped to [0, 1]
+    pub fn new(threshold: f64) -> Self {
+        let (warnings, _) = broadcast::channel(BUDGET_CHANNEL_CAPACITY);
+        Self {
+            threshold: if threshold.is_nan() { 1.0 } else { threshold.clamp(0.0, 1.0) },
+            state: Mutex::new(TrackerState::default()),
+            warnings,
+        }
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Budget as of the latest update, `None` before the first
+    pub fn budget(&self) -> Option<ExecutionBudget> {
+        self.lock().budget
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BudgetWarning> {
+        self.warnings.subscribe()
+    }
+
+    /// Update the budget from `account`, read after an execution of
+    /// `action_size` bytes, if any
+    pub fn record(&self, account: &AgentAccount, action_size: Option<u64>) -> Vec<BudgetWarning> {
+        let memory_used = self
+            .budget()
+            .map_or(0, |budget| budget.memory_used)
+            .max(action_size.unwrap_or(0));
+        self.update(ExecutionBudget::from_account(account, memory_used))
+    }
+
+    /// Replace the budget, returning the warnings it newly raised
+    pub fn update(&self, budget: ExecutionBudget) -> Vec<BudgetWarning> {
+        let mut state = self.lock();
+        state.budget = Some(budget);
+
+        let mut raised = Vec::new();
+        let mut warn = |condition: bool, warned: &mut bool, warning: BudgetWarning| {
+            if condition && !*warned {
+                raised.push(warning);
+            }
+            *warned = condition;
+        };
+        warn(
+            budget.remaining_executions() == 0,
+            &mut state.exhausted_warned,
+            BudgetWarning::ExecutionsExhausted { limit: budget.execution_limit },
+        );
+        warn(
+            budget.remaining_executions() > 0 && budget.execution_usage() >= self.threshold,
+            &mut state.executions_warned,
+            BudgetWarning::ExecutionsLow {
+                remaining: budget.remaining_executions(),
+                limit: budget.execution_limit,
+            },
+        );
+        warn(
+            budget.memory_used > 0 && budget.memory_usage() >= self.threshold,
+            &mut state.memory_warned,
+            BudgetWarning::MemoryHigh {
+                used: budget.memory_used,
+                limit: budget.memory_limit,
+            },
+        );
+        drop(state);
+
+        for warning in &raised {
+            println!("Agent budget warning: {}", warning);
+            // No subscribers is fine; warnings are logged regardless
+            let _ = self.warnings.send(warning.clone());
+        }
+        raised
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, TrackerState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(executions: u64, memory_used: u64) -> ExecutionBudget {
+        ExecutionBudget {
+            execution_limit: 10,
+            executions,
+            memory_limit: 1_000,
+            memory_used,
+        }
+    }
+
+    #[test]
+    fn test_warnings_once_per_crossing() {
+        let tracker = BudgetTracker::new(0.8);
+        let mut warnings = tracker.subscribe();
+
+        assert!(tracker.update(budget(7, 100)).is_empty());
+        assert_eq!(
+            tracker.update(budget(8, 100)),
+            vec![BudgetWarning::ExecutionsLow { remaining: 2, limit: 10 }]
+        );
+        assert!(tracker.update(budget(9, 100)).is_empty());
+        assert_eq!(tracker.update(budget(10, 900)).len(), 2);
+        assert_eq!(tracker.budget().unwrap().remaining_executions(), 0);
+
+        // Raising the limit clears the warnings, so they fire again later
+        let raised = ExecutionBudget {
+            execution_limit: 100,
+            memory_limit: 10_000,
+            ..budget(10, 900)
+        };
+        assert!(tracker.update(raised).is_empty());
+        assert_eq!(
+            tracker.update(ExecutionBudget { executions: 80, ..raised }),
+            vec![BudgetWarning::ExecutionsLow { remaining: 20, limit: 100 }]
+        );
+
+        assert_eq!(warnings.try_recv().unwrap(), BudgetWarning::ExecutionsLow { remaining: 2, limit: 10 });
+        assert_eq!(warnings.try_recv().unwrap(), BudgetWarning::ExecutionsExhausted { limit: 10 });
+    }
+}
@@ -0,0 +1,327 @@
+//! In-process message bus between agents
+//!
+//! This module provides:
+//! - A bounded mailbox per agent, addressed by the agent's name
+//! - Direct messages, topic publishing and broadcast to every agent
+//! - Backpressure without waiting: a full mailbox rejects direct messages
+//!   with `MailboxFull`, and published messages it can't take are dropped
+//!   and counted in the `Delivery`
+//! - Typed payloads, carried as JSON and decoded by the recipient
+//!
+//! `AgentRuntime` gives each registered agent a mailbox and delivers its
+//! messages between cycles (see `ScheduledAgent::on_message`). Senders
+//! never wait on a mailbox: an agent sending from its cycle would otherwise
+//! wait on a recipient whose mailbox is only drained once that cycle ends.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use super::error::{AgentError, AgentResult};
+
+/// Messages a mailbox holds before it turns new ones away
+pub const DEFAULT_MAILBOX_CAPACITY: usize = 64;
+
+/// Who a message was sent to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Recipient {
+    /// The agent with this name
+    Agent(String),
+    /// The agents subscribed to this topic
+    Topic(String),
+    /// Every agent but the sender
+    All,
+}
+
+/// Message between agents
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    /// Name of the sending agent
+    pub from: String,
+    pub recipient: Recipient,
+    pub payload: Value,
+}
+
+impl Message {
+    /// Payload decoded as `T`
+    pub fn decode<T: DeserializeOwned>(&self) -> AgentResult<T> {
+        T::deserialize(&self.payload).map_err(|_| AgentError::ValidationError)
+    }
+
+    /// Topic the message was published on, if any
+    pub fn topic(&self) -> Option<&str> {
+        match &self.recipient {
+            Recipient::Topic(topic) => Some(topic),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of publishing or broadcasting a message
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Delivery {
+    pub delivered: usize,
+    /// Recipients whose mailbox was full
+    pub dropped: usize,
+}
+
+#[derive(Default)]
+struct Routes {
+    mailboxes: HashMap<String, mpsc::Sender<Message>>,
+    /// Subscribed agents by topic
+    topics: HashMap<String, HashSet<String>>,
+}
+
+/// Bus connecting the agents of a process; cloning shares it
+#[derive(Clone)]
+pub struct MessageBus {
+    routes: Arc<RwLock<Routes>>,
+    capacity: usize,
+}
+
+impl Default for MessageBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageBus {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_MAILBOX_CAPACITY)
+    }
+
+    /// Bus whose mailboxes hold `capacity` messages
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            routes: Arc::new(RwLock::new(Routes::default())),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Open the mailbox of the agent named `name`; it stays addressable
+    /// until the mailbox is dropped
+    pub fn mailbox(&self, name: &str) -> AgentResult<Mailbox> {
+        let mut routes = self.write();
+        if routes.mailboxes.contains_key(name) {
+            return Err(AgentError::InvalidInput);
+        }
+
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        routes.mailboxes.insert(name.to_string(), sender);
+        Ok(Mailbox {
+            name: name.to_string(),
+            receiver,
+            bus: self.clone(),
+        })
+    }
+
+    /// Deliver messages published on `topic` to `agent`
+    pub fn subscribe(&self, agent: &str, topic: &str) {
+        self.write()
+            .topics
+            .entry(topic.to_string())
+            .or_default()
+            .insert(agent.to_string());
+    }
+
+    pub fn unsubscribe(&self, agent: &str, topic: &str) {
+        let mut routes = self.write();
+        if let Some(subscribers) = routes.topics.get_mut(topic) {
+            subscribers.remove(agent);
+            if subscribers.is_empty() {
+                routes.topics.remove(topic);
+            }
+        }
+    }
+
+    /// Names of the agents with an open mailbox, sorted
+    pub fn agents(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.read().mailboxes.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Send `payload` to the agent named `to`, failing with `MailboxFull`
+    /// if its mailbox is full
+    pub fn send<T: Serialize>(&self, from: &str, to: &str, payload: &T) -> AgentResult<()> {
+        let sender = self.sender(to)?;
+        let message = message(from, Recipient::Agent(to.to_string()), payload)?;
+        sender.try_send(message).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => AgentError::MailboxFull,
+            mpsc::error::TrySendError::Closed(_) => AgentError::InvalidInput,
+        })
+    }
+
+    /// Publish `payload` to the agents subscribed to `topic`, other than
+    /// the sender
+    pub fn publish<T: Serialize>(&self, from: &str, topic: &str, payload: &T) -> AgentResult<Delivery> {
+        let message = message(from, Recipient::Topic(topic.to_string()), payload)?;
+        let senders: Vec<_> = {
+            let routes = self.read();
+            routes
+                .topics
+                .get(topic)
+                .into_iter()
+                .flatten()
+                .filter(|agent| agent.as_str() != from)
+                .filter_map(|agent| routes.mailboxes.get(agent).cloned())
+                .collect()
+        };
+        Ok(deliver(senders, message))
+    }
+
+    /// Send `payload` to every agent other than the sender
+    pub fn broadcast<T: Serialize>(&self, from: &str, payload: &T) -> AgentResult<Delivery> {
+        let message = message(from, Recipient::All, payload)?;
+        let senders: Vec<_> = self
+            .read()
+            .mailboxes
+            .iter()
+            .filter(|(agent, _)| agent.as_str() != from)
+            .map(|(_, sender)| sender.clone())
+            .collect();
+        Ok(deliver(senders, message))
+    }
+
+    fn sender(&self, agent: &str) -> AgentResult<mpsc::Sender<Message>> {
+        self.read().mailboxes.get(agent).cloned().ok_or(AgentError::InvalidInput)
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Routes> {
+        self.routes.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Routes> {
+        self.routes.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn message<T: Serialize>(from: &str, recipient: Recipient, payload: &T) -> AgentResult<Message> {
+    Ok(Message {
+        from: from.to_string(),
+        recipient,
+        payload: serde_json::to_value(payload).map_err(|_| AgentError::InvalidInput)?,
+    })
+}
+
+/// Offer `message` to each mailbox, dropping it for the full ones
+fn deliver(senders: Vec<mpsc::Sender<Message>>, message: Message) -> Delivery {
+    let mut delivery = Delivery::default();
+    for sender in senders {
+        match sender.try_send(message.clone()) {
+            Ok(()) => delivery.delivered += 1,
+            Err(mpsc::error::TrySendError::Full(_)) => delivery.dropped += 1,
+            // Closed mailboxes are on their way out of the routes
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+    if delivery.dropped > 0 {
+        println!("Message from {} dropped by {} full mailboxes", message.from, delivery.dropped);
+    }
+    delivery
+}
+
+/// Messages addressed to one agent
+pub struct Mailbox {
+    name: String,
+    receiver: mpsc::Receiver<Message>,
+    bus: MessageBus,
+}
+
+impl Mailbox {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Next message, waiting for one to arrive
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+
+    /// Next message, if one is waiting
+    pub fn try_recv(&mut self) -> Option<Message> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Messages waiting to be received
+    pub fn len(&self) -> usize {
+        self.receiver.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.receiver.is_empty()
+    }
+}
+
+impl Drop for Mailbox {
+    fn drop(&mut self) {
+        let mut routes = self.bus.write();
+        routes.mailboxes.remove(&self.name);
+        for subscribers in routes.topics.values_mut() {
+            subscribers.remove(&self.name);
+        }
+        routes.topics.retain(|_, subscribers| !subscribers.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Signal {
+        market: String,
+        score: f64,
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let bus = MessageBus::new();
+        let mut analyst = bus.mailbox("analyst").unwrap();
+        let mut trader = bus.mailbox("trader").unwrap();
+        let mut auditor = bus.mailbox("auditor").unwrap();
+        assert!(matches!(bus.mailbox("trader"), Err(AgentError::InvalidInput)));
+        bus.subscribe("trader", "signals");
+        bus.subscribe("analyst", "signals");
+
+        let signal = Signal { market: "SOL/USDC".to_string(), score: 0.8 };
+        assert_eq!(bus.publish("analyst", "signals", &signal).unwrap().delivered, 1);
+        let received = trader.try_recv().unwrap();
+        assert_eq!(received.topic(), Some("signals"));
+        assert_eq!(received.decode::<Signal>(), Ok(signal));
+        assert!(analyst.try_recv().is_none());
+        assert!(auditor.try_recv().is_none());
+
+        bus.send("trader", "analyst", &"ack").unwrap();
+        assert_eq!(analyst.recv().await.unwrap().decode::<String>(), Ok("ack".to_string()));
+        assert!(matches!(bus.send("trader", "nobody", &()), Err(AgentError::InvalidInput)));
+
+        assert_eq!(bus.broadcast("auditor", &"halt").unwrap().delivered, 2);
+        assert_eq!(trader.try_recv().unwrap().recipient, Recipient::All);
+
+        drop(trader);
+        assert_eq!(bus.agents(), vec!["analyst", "auditor"]);
+        assert_eq!(bus.publish("analyst", "signals", &()), Ok(Delivery::default()));
+    }
+
+    #[tokio::test]
+    async fn test_backpressure() {
+        let bus = MessageBus::with_capacity(1);
+        let mut slow = bus.mailbox("slow").unwrap();
+        let mut fast = bus.mailbox("fast").unwrap();
+        bus.subscribe("slow", "ticks");
+        bus.subscribe("fast", "ticks");
+
+        // Sending never waits on a full mailbox
+        bus.send("fast", "slow", &1).unwrap();
+        assert_eq!(bus.send("fast", "slow", &2), Err(AgentError::MailboxFull));
+        assert_eq!(bus.publish("other", "ticks", &3), Ok(Delivery { delivered: 1, dropped: 1 }));
+        assert_eq!(fast.try_recv().unwrap().decode::<u32>(), Ok(3));
+
+        // Room frees up as the mailbox drains
+        assert_eq!(slow.recv().await.unwrap().decode::<u32>(), Ok(1));
+        bus.send("fast", "slow", &4).unwrap();
+        assert_eq!(slow.recv().await.unwrap().decode::<u32>(), Ok(4));
+    }
+}
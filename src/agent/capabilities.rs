@@ -1,116 +1,407 @@
-# Rust/Cargo
-/target/
-**/*.rs.bk
-Cargo.lock
-
-# Node.js
-node_modules/
-js/node_modules/
-npm-debug.log*
-yarn-debug.log*
-yarn-error.log*
-.npm
-.yarn-integrity
-.env
-.env.local
-.env.development.local
-.env.test.local
-.env.production.local
-
-# Build outputs
-/dist/
-/build/
-js/dist/
-js/build/
-
-# TypeScript
-*.tsbuildinfo
-
-# IDEs and editors
-.idea/
-.vscode/
-*.swp
-*.swo
-*.swn
-*.bak
-*.sublime-workspace
-*.sublime-project
-
-# OS generated files
-.DS_Store
-.DS_Store?
-._*
-.Spotlight-V100
-.Trashes
-ehthumbs.db
-Thumbs.db
-
-# Test coverage
-coverage/
-.nyc_output/
-
-# Documentation
-/docs/
-
-# Solana
-test-ledger/
-.anchor/
-
-# Logs
-logs
-*.log
-
-# Runtime data
-pids
-*.pid
-*.seed
-*.pid.lock
-
-# Optional npm cache directory
-.npm
-
-# Optional eslint cache
-.eslintcache
-
-# Optional REPL history
-.node_repl_history
-
-# Output of 'npm pack'
-*.tgz
-
-# Yarn Integrity file
-.yarn-integrity
-
-# dotenv environment variable files
-.env
-.env.test
-.env.production
-
-# parcel-bundler cache
-.cache
-.parcel-cache
-
-# Next.js build output
-.next
-out
-
-# Nuxt.js build / generate output
-.nuxt
-dist
-
-# Gatsby files
-.cache/
-public
-
-# Mac files
-.DS_Store
-
-# Windows files
-Thumbs.db
-Desktop.ini
-
-# Linux files
-*~
-
-# Rust IDE specific files
-*.iml
\ No newline at end of file
+//! Pluggable agent capabilities
+//!
+//! This module provides:
+//! - `CapabilityKind`, the typed capabilities an agent can be granted
+//! - The `Capability` trait: a named, async handler requiring a set of
+//!   capabilities
+//! - `CapabilityRegistry`, where downstream crates register capabilities
+//!   at runtime
+//! - `AgentCapabilities`, what an agent was granted and which registered
+//!   capabilities it may use
+//! - Action plans invoking capabilities by name, in order
+//!
+//! Built-in kinds mirror the on-chain `CapabilityFlags`, so a capability
+//! placing trades requires `Trading` like the program's own trading
+//! actions do, and an agent's grants can be read from its account. Custom
+//! kinds only exist client-side.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::solana::program::{action::AgentAction, capability::CapabilityFlags};
+use super::base::Capabilities;
+use super::error::{AgentError, AgentResult};
+
+/// Capability an agent can be granted
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum CapabilityKind {
+    Compute,
+    Storage,
+    Network,
+    Oracle,
+    Trading,
+    /// Client-side capability without an on-chain flag
+    Custom(String),
+}
+
+impl CapabilityKind {
+    /// Kind named `name`, custom unless it names an on-chain flag
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "compute" => CapabilityKind::Compute,
+            "storage" => CapabilityKind::Storage,
+            "network" => CapabilityKind::Network,
+            "oracle" => CapabilityKind::Oracle,
+            "trading" => CapabilityKind::Trading,
+            custom => CapabilityKind::Custom(custom.to_string()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            CapabilityKind::Compute => "compute",
+            CapabilityKind::Storage => "storage",
+            CapabilityKind::Network => "network",
+            CapabilityKind::Oracle => "oracle",
+            CapabilityKind::Trading => "trading",
+            CapabilityKind::Custom(name) => name,
+        }
+    }
+
+    /// On-chain flag of the kind; `None` for custom kinds
+    pub fn flag(&self) -> Option<CapabilityFlags> {
+        match self {
+            CapabilityKind::Custom(_) => None,
+            kind => CapabilityFlags::from_name(kind.name()),
+        }
+    }
+
+    /// Kinds of the known capabilities set in `flags`
+    pub fn from_flags(flags: CapabilityFlags) -> Vec<Self> {
+        flags.names().into_iter().map(Self::from_name).collect()
+    }
+}
+
+impl fmt::Display for CapabilityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<String> for CapabilityKind {
+    fn from(name: String) -> Self {
+        Self::from_name(&name)
+    }
+}
+
+impl From<CapabilityKind> for String {
+    fn from(kind: CapabilityKind) -> Self {
+        kind.name().to_string()
+    }
+}
+
+/// Capability an agent invokes by name
+#[async_trait::async_trait]
+pub trait Capability: Send + Sync {
+    /// Name the capability is registered and invoked under
+    fn name(&self) -> &str;
+
+    /// Capabilities an agent must be granted to invoke the capability
+    fn required_capabilities(&self) -> Vec<CapabilityKind>;
+
+    async fn invoke(&self, input: Value) -> AgentResult<Value>;
+}
+
+/// Capabilities granted to an agent and the registered ones it may invoke
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentCapabilities {
+    pub granted: BTreeSet<CapabilityKind>,
+    /// Names of the capabilities the agent may invoke; every registered
+    /// capability its grants cover if empty
+    pub enabled: Vec<String>,
+}
+
+impl AgentCapabilities {
+    pub fn new(granted: impl IntoIterator<Item = CapabilityKind>) -> Self {
+        Self {
+            granted: granted.into_iter().collect(),
+            enabled: Vec::new(),
+        }
+    }
+
+    /// Capabilities matching the on-chain `flags` of an agent
+    pub fn from_flags(flags: CapabilityFlags) -> Self {
+        Self::new(CapabilityKind::from_flags(flags))
+    }
+
+    pub fn grant(&mut self, kind: CapabilityKind) {
+        self.granted.insert(kind);
+    }
+
+    pub fn revoke(&mut self, kind: &CapabilityKind) {
+        self.granted.remove(kind);
+    }
+
+    pub fn has(&self, kind: &CapabilityKind) -> bool {
+        self.granted.contains(kind)
+    }
+
+    /// On-chain flags of the granted kinds; custom kinds have none
+    pub fn flags(&self) -> CapabilityFlags {
+        self.granted
+            .iter()
+            .filter_map(CapabilityKind::flag)
+            .fold(CapabilityFlags::NONE, |flags, flag| flags | flag)
+    }
+
+    /// Kinds of `required` the agent wasn't granted
+    pub fn missing<'a>(&self, required: &'a [CapabilityKind]) -> Vec<&'a CapabilityKind> {
+        required.iter().filter(|kind| !self.has(kind)).collect()
+    }
+
+    /// Restrict the agent to the capabilities named in `enabled`
+    pub fn with_enabled(mut self, enabled: &[&str]) -> Self {
+        self.enabled = enabled.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    /// Whether the agent may invoke `capability`
+    pub fn allows(&self, capability: &dyn Capability) -> AgentResult<()> {
+        if !self.enabled.is_empty() && !self.enabled.iter().any(|name| name == capability.name()) {
+            return Err(AgentError::Unauthorized);
+        }
+        let required = capability.required_capabilities();
+        let missing = self.missing(&required);
+        if !missing.is_empty() {
+            println!("Capability {} requires ungranted {:?}", capability.name(), missing);
+            return Err(AgentError::InsufficientPermissions);
+        }
+        Ok(())
+    }
+
+    /// Whether the agent may run `action`, as the program would check on-chain
+    pub fn allows_action(&self, action: &AgentAction) -> AgentResult<()> {
+        if self.flags().contains(action.required_capabilities()) {
+            Ok(())
+        } else {
+            Err(AgentError::InsufficientPermissions)
+        }
+    }
+}
+
+impl From<&Capabilities> for AgentCapabilities {
+    fn from(capabilities: &Capabilities) -> Self {
+        let built_in = [
+            (capabilities.compute, CapabilityKind::Compute),
+            (capabilities.storage, CapabilityKind::Storage),
+            (capabilities.network, CapabilityKind::Network),
+        ];
+        Self::new(
+            built_in
+                .into_iter()
+                .filter_map(|(granted, kind)| granted.then_some(kind))
+                .chain(capabilities.custom_capabilities.iter().map(|name| CapabilityKind::from_name(name))),
+        )
+    }
+}
+
+/// Step of an action plan: a capability to invoke and its input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedStep {
+    pub capability: String,
+    #[serde(default)]
+    pub input: Value,
+}
+
+/// Capabilities to invoke in order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ActionPlan {
+    pub steps: Vec<PlannedStep>,
+}
+
+/// Capabilities available to agents, by name
+#[derive(Default)]
+pub struct CapabilityRegistry {
+    capabilities: RwLock<HashMap<String, Arc<dyn Capability>>>,
+}
+
+impl CapabilityRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `capability`; its name must not be registered yet
+    pub fn register(&self, capability: Arc<dyn Capability>) -> AgentResult<()> {
+        let name = capability.name().to_string();
+        if name.is_empty() {
+            return Err(AgentError::InvalidInput);
+        }
+
+        let mut capabilities = self.capabilities.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if capabilities.contains_key(&name) {
+            return Err(AgentError::InvalidConfiguration);
+        }
+        capabilities.insert(name, capability);
+        Ok(())
+    }
+
+    /// Remove the capability named `name`, returning it
+    pub fn unregister(&self, name: &str) -> Option<Arc<dyn Capability>> {
+        self.capabilities
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Capability>> {
+        self.capabilities
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name)
+            .cloned()
+    }
+
+    /// Names of the registered capabilities, sorted
+    pub fn names(&self) -> Vec<String> {
+        let capabilities = self.capabilities.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut names: Vec<String> = capabilities.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Invoke the capability named `name` for an agent holding `agent`
+    pub async fn invoke(&self, agent: &AgentCapabilities, name: &str, input: Value) -> AgentResult<Value> {
+        let capability = self.get(name).ok_or(AgentError::CapabilityNotFound)?;
+        agent.allows(capability.as_ref())?;
+        capability.invoke(input).await
+    }
+
+    /// Run the steps of `plan` in order, returning their outputs
+    ///
+    /// Every step is checked before the first runs, so a plan the agent
+    /// isn't allowed to complete doesn't start; a failing step ends it.
+    pub async fn execute_plan(&self, agent: &AgentCapabilities, plan: &ActionPlan) -> AgentResult<Vec<Value>> {
+        let capabilities = plan
+            .steps
+            .iter()
+            .map(|step| {
+                let capability = self.get(&step.capability).ok_or(AgentError::CapabilityNotFound)?;
+                agent.allows(capability.as_ref())?;
+                Ok(capability)
+            })
+            .collect::<AgentResult<Vec<_>>>()?;
+
+        let mut outputs = Vec::with_capacity(capabilities.len());
+        for (capability, step) in capabilities.iter().zip(&plan.steps) {
+            outputs.push(capability.invoke(step.input.clone()).await?);
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_sdk::pubkey::Pubkey;
+
+    struct Echo;
+
+    #[async_trait::async_trait]
+    impl Capability for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn required_capabilities(&self) -> Vec<CapabilityKind> {
+            vec![CapabilityKind::Network]
+        }
+
+        async fn invoke(&self, input: Value) -> AgentResult<Value> {
+            Ok(input)
+        }
+    }
+
+    struct Trade;
+
+    #[async_trait::async_trait]
+    impl Capability for Trade {
+        fn name(&self) -> &str {
+            "trade"
+        }
+
+        fn required_capabilities(&self) -> Vec<CapabilityKind> {
+            vec![CapabilityKind::Trading, CapabilityKind::Custom("exchange".to_string())]
+        }
+
+        async fn invoke(&self, input: Value) -> AgentResult<Value> {
+            Ok(json!({ "filled": input["quantity"] }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registry() {
+        let registry = CapabilityRegistry::new();
+        registry.register(Arc::new(Echo)).unwrap();
+        registry.register(Arc::new(Trade)).unwrap();
+        assert_eq!(registry.register(Arc::new(Echo)), Err(AgentError::InvalidConfiguration));
+        assert_eq!(registry.names(), vec!["echo", "trade"]);
+
+        let agent = AgentCapabilities::new([CapabilityKind::Network]);
+        assert_eq!(registry.invoke(&agent, "echo", json!("hi")).await, Ok(json!("hi")));
+        assert_eq!(registry.invoke(&agent, "trade", json!({})).await, Err(AgentError::InsufficientPermissions));
+        assert_eq!(registry.invoke(&agent, "swap", json!({})).await, Err(AgentError::CapabilityNotFound));
+
+        let restricted = AgentCapabilities::from_flags(CapabilityFlags::ALL).with_enabled(&["trade"]);
+        assert_eq!(registry.invoke(&restricted, "echo", json!("hi")).await, Err(AgentError::Unauthorized));
+
+        assert!(registry.unregister("echo").is_some());
+        assert_eq!(registry.invoke(&agent, "echo", json!("hi")).await, Err(AgentError::CapabilityNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_execute_plan() {
+        let registry = CapabilityRegistry::new();
+        registry.register(Arc::new(Echo)).unwrap();
+        registry.register(Arc::new(Trade)).unwrap();
+        let plan: ActionPlan = serde_json::from_value(json!({
+            "steps": [
+                { "capability": "echo", "input": "price check" },
+                { "capability": "trade", "input": { "quantity": 5 } }
+            ]
+        }))
+        .unwrap();
+
+        let mut trader = AgentCapabilities::from_flags(CapabilityFlags::ALL);
+        // Custom kinds aren't covered by on-chain flags
+        assert_eq!(registry.execute_plan(&trader, &plan).await, Err(AgentError::InsufficientPermissions));
+        trader.grant(CapabilityKind::from_name("exchange"));
+        assert_eq!(
+            registry.execute_plan(&trader, &plan).await,
+            Ok(vec![json!("price check"), json!({ "filled": 5 })])
+        );
+
+        let observer = AgentCapabilities::new([CapabilityKind::Network]);
+        assert_eq!(registry.execute_plan(&observer, &plan).await, Err(AgentError::InsufficientPermissions));
+    }
+
+    #[test]
+    fn test_kinds_mirror_flags() {
+        let capabilities = Capabilities {
+            compute: true,
+            storage: false,
+            network: true,
+            custom_capabilities: vec!["trading".to_string(), "exchange".to_string()],
+        };
+        let agent = AgentCapabilities::from(&capabilities);
+        assert!(agent.has(&CapabilityKind::Trading));
+        assert!(agent.has(&CapabilityKind::Custom("exchange".to_string())));
+        assert_eq!(agent.flags(), CapabilityFlags::COMPUTE | CapabilityFlags::NETWORK | CapabilityFlags::TRADING);
+        assert_eq!(AgentCapabilities::from_flags(agent.flags()).granted.len(), 3);
+
+        let transfer = AgentAction::Transfer {
+            destination: Pubkey::new_unique(),
+            lamports: 1,
+        };
+        assert_eq!(agent.allows_action(&transfer), Ok(()));
+        let compute_only = AgentCapabilities::new([CapabilityKind::Compute]);
+        assert_eq!(compute_only.allows_action(&transfer), Err(AgentError::InsufficientPermissions));
+        assert_eq!(compute_only.allows_action(&AgentAction::Noop), Ok(()));
+
+        assert_eq!(serde_json::to_value(&CapabilityKind::Oracle).unwrap(), json!("oracle"));
+        assert_eq!(serde_json::from_value::<CapabilityKind>(json!("x")).unwrap().flag(), None);
+    }
+}
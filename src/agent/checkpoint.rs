@@ -0,0 +1,115 @@
+//! Checkpointing agent state across restarts
+//!
+//! This module provides:
+//! - `AgentCheckpoint`, an agent's saved state and cycle statistics
+//! - `Checkpointer`, saving and loading checkpoints through a
+//!   `StorageManager`
+//! - Checkpoint interval and key configuration
+//!
+//! `AgentRuntime::with_checkpoints` restores each agent from its checkpoint
+//! when it is registered, saves it on the configured interval while it
+//! runs, and once more on shutdown. What is saved is up to the agent (see
+//! `ScheduledAgent::checkpoint`); it is kept as JSON, so agents can evolve
+//! their state without breaking older checkpoints.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::storage::{StorageError, StorageManager};
+use super::error::{AgentError, AgentResult};
+use super::runtime::RunStats;
+
+/// Default time between checkpoints of a running agent
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Checkpoint configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckpointConfig {
+    /// Time between checkpoints of a running agent
+    pub interval: Duration,
+    /// Prefix of the storage keys, followed by the agent's name
+    pub key_prefix: String,
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_CHECKPOINT_INTERVAL,
+            key_prefix: "agents/checkpoints/".to_string(),
+        }
+    }
+}
+
+/// Saved state of an agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentCheckpoint {
+    pub agent: String,
+    /// Unix time the checkpoint was saved
+    pub saved_at: u64,
+    /// The agent's state, as JSON
+    pub state: String,
+    pub stats: RunStats,
+}
+
+impl AgentCheckpoint {
+    pub fn state(&self) -> AgentResult<Value> {
+        serde_json::from_str(&self.state).map_err(|_| AgentError::ValidationError)
+    }
+}
+
+/// Saves and loads agent checkpoints
+#[derive(Clone)]
+pub struct Checkpointer {
+    storage: Arc<StorageManager>,
+    config: CheckpointConfig,
+}
+
+impl Checkpointer {
+    pub fn new(storage: Arc<StorageManager>, config: CheckpointConfig) -> Self {
+        Self { storage, config }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.config.interval
+    }
+
+    /// Save `state` and `stats` as the checkpoint of the agent named `agent`
+    pub async fn save(&self, agent: &str, state: &Value, stats: &RunStats) -> AgentResult<()> {
+        let checkpoint = AgentCheckpoint {
+            agent: agent.to_string(),
+            saved_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            state: state.to_string(),
+            stats: stats.clone(),
+        };
+        self.storage
+            .store(&self.key(agent), &checkpoint)
+            .await
+            .map_err(|e| AgentError::Custom(e.to_string()))
+    }
+
+    /// Latest checkpoint of the agent named `agent`, if one was saved
+    pub async fn load(&self, agent: &str) -> AgentResult<Option<AgentCheckpoint>> {
+        match self.storage.retrieve::<AgentCheckpoint>(&self.key(agent)).await {
+            Ok(checkpoint) => Ok(Some(checkpoint)),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(AgentError::Custom(e.to_string())),
+        }
+    }
+
+    /// Delete the checkpoint of the agent named `agent`, e.g. to start it
+    /// afresh
+    pub async fn delete(&self, agent: &str) -> AgentResult<()> {
+        self.storage
+            .delete(&self.key(agent))
+            .await
+            .map_err(|e| AgentError::Custom(e.to_string()))
+    }
+
+    fn key(&self, agent: &str) -> String {
+        format!("{}{}", self.config.key_prefix, agent)
+    }
+}
@@ -0,0 +1,443 @@
+//! Historical market data sync
+//!
+//! This module provides:
+//! - The `CandleProvider` trait external market data sources implement
+//! - `CandleStore`, candles persisted through a `StorageManager` by market
+//!   and interval, read back by time range for backtests
+//! - `DataSync`, backfilling the candles of configured markets from a
+//!   provider, resuming where the last sync stopped
+//! - Gap detection over the stored history, and repair of the gaps the
+//!   provider can fill
+//! - Requests spaced to the provider's rate limit, retried with backoff
+//!   when it still rate limits them
+//!
+//! Progress is saved after every request, so an interrupted sync resumes
+//! from the last window stored. Only complete periods are synced: the sync
+//! stops at the start of the period `until` falls in. A gap the provider
+//! has no candles for, e.g. a period without trades, stays in the report
+//! and is asked for again on the next sync.
+
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time::Instant;
+use crate::storage::{PageRequest, StorageError, StorageManager, MAX_PAGE_SIZE};
+use super::backtest::Candle;
+use super::error::{AgentError, AgentResult};
+
+/// Prefix of the storage keys of candles, followed by market, interval and
+/// start time
+pub const CANDLE_KEY_PREFIX: &str = "market-data/candles/";
+
+/// Prefix of the storage keys of sync progress, followed by provider,
+/// market and interval
+pub const SYNC_KEY_PREFIX: &str = "market-data/sync/";
+
+/// Why a provider request failed
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ProviderError {
+    #[error("Rate limited by the provider")]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("Provider request failed: {0}")]
+    Request(String),
+}
+
+/// External source of historical candles
+#[async_trait::async_trait]
+pub trait CandleProvider: Send + Sync {
+    /// Name progress is saved under
+    fn name(&self) -> &str;
+
+    /// Most candles a single request returns
+    fn max_candles(&self) -> usize;
+
+    /// Candles of `market` every `interval` seconds starting in
+    /// `start..end`, in any order
+    async fn fetch(&self, market: &str, interval: u64, start: u64, end: u64) -> Result<Vec<Candle>, ProviderError>;
+}
+
+/// Candles persisted by market and interval
+#[derive(Clone)]
+pub struct CandleStore {
+    storage: Arc<StorageManager>,
+}
+
+impl CandleStore {
+    pub fn new(storage: Arc<StorageManager>) -> Self {
+        Self { storage }
+    }
+
+    /// Store `candles` of `interval` seconds, replacing those stored for
+    /// the same periods
+    pub async fn store(&self, interval: u64, candles: &[Candle]) -> AgentResult<()> {
+        for candle in candles {
+            self.storage
+                .store(&Self::key(&candle.market, interval, candle.timestamp), candle)
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Stored candles of `market` starting in `start..end`, oldest first
+    pub async fn range(&self, market: &str, interval: u64, start: u64, end: u64) -> AgentResult<Vec<Candle>> {
+        let prefix = Self::prefix(market, interval);
+        let mut page = match start.checked_sub(1) {
+            Some(before) => PageRequest::after(Self::key(market, interval, before), MAX_PAGE_SIZE),
+            None => PageRequest::first(MAX_PAGE_SIZE),
+        };
+        let mut candles = Vec::new();
+        loop {
+            let scanned = self
+                .storage
+                .scan::<Candle>(&prefix, &page)
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+            for (_, candle) in scanned.items {
+                if candle.timestamp >= end {
+                    return Ok(candles);
+                }
+                candles.push(candle);
+            }
+            match scanned.next_cursor {
+                Some(cursor) => page = PageRequest::after(cursor, MAX_PAGE_SIZE),
+                None => return Ok(candles),
+            }
+        }
+    }
+
+    /// Periods of `market` in `start..end` without a stored candle, as
+    /// ranges of their start times
+    pub async fn gaps(&self, market: &str, interval: u64, start: u64, end: u64) -> AgentResult<Vec<(u64, u64)>> {
+        let mut gaps = Vec::new();
+        let mut expected = period_after(start, interval);
+        for candle in self.range(market, interval, expected, end).await? {
+            if candle.timestamp > expected {
+                gaps.push((expected, candle.timestamp));
+            }
+            expected = candle.timestamp.saturating_add(interval);
+        }
+        if expected < end {
+            gaps.push((expected, end));
+        }
+        Ok(gaps)
+    }
+
+    fn prefix(market: &str, interval: u64) -> String {
+        format!("{}{}/{}/", CANDLE_KEY_PREFIX, market, interval)
+    }
+
+    fn key(market: &str, interval: u64, timestamp: u64) -> String {
+        format!("{}{:020}", Self::prefix(market, interval), timestamp)
+    }
+}
+
+/// Markets to sync and how fast
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncConfig {
+    pub markets: Vec<String>,
+    /// Seconds per candle
+    pub interval: u64,
+    /// Unix time the history starts at
+    pub start: u64,
+    /// Shortest time between two requests to the provider
+    pub min_request_interval: Duration,
+    /// Retries of a rate-limited request before the market's sync fails
+    pub max_retries: u32,
+    /// Wait before the first retry of a rate-limited request that didn't
+    /// say how long to wait, doubled on each retry
+    pub backoff: Duration,
+}
+
+impl SyncConfig {
+    pub fn validate(&self) -> AgentResult<()> {
+        let valid =
+            !self.markets.is_empty() && self.markets.iter().all(|market| !market.is_empty()) && self.interval > 0;
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidConfiguration)
+        }
+    }
+}
+
+/// How far the sync of a market got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncProgress {
+    /// Unix time every period before which was requested
+    pub synced_until: u64,
+}
+
+/// Outcome of the sync of one market
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketSync {
+    pub market: String,
+    /// Candles stored, repaired gaps included
+    pub fetched: usize,
+    pub synced_until: u64,
+    /// Gaps the provider had no candles for
+    pub gaps: Vec<(u64, u64)>,
+    /// Why the sync stopped early, if it did
+    pub error: Option<String>,
+}
+
+/// Outcome of a sync
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    pub markets: Vec<MarketSync>,
+}
+
+impl SyncReport {
+    /// Whether every market synced without error or gap
+    pub fn is_complete(&self) -> bool {
+        self.markets.iter().all(|market| market.error.is_none() && market.gaps.is_empty())
+    }
+}
+
+/// Backfills the candles of configured markets from a provider
+pub struct DataSync {
+    provider: Arc<dyn CandleProvider>,
+    store: CandleStore,
+    storage: Arc<StorageManager>,
+    config: SyncConfig,
+    last_request: Option<Instant>,
+}
+
+impl DataSync {
+    pub fn new(
+        provider: Arc<dyn CandleProvider>,
+        storage: Arc<StorageManager>,
+        config: SyncConfig,
+    ) -> AgentResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            provider,
+            store: CandleStore::new(storage.clone()),
+            storage,
+            config,
+            last_request: None,
+        })
+    }
+
+    pub fn store(&self) -> &CandleStore {
+        &self.store
+    }
+
+    /// Sync every market up to the period `until` falls in, then repair the
+    /// gaps of its history
+    ///
+    /// A market whose sync fails is reported with the error, keeping the
+    /// progress it made; the other markets still sync.
+    pub async fn sync(&mut self, until: u64) -> SyncReport {
+        let until = until - until % self.config.interval;
+        let mut report = SyncReport::default();
+        for market in self.config.markets.clone() {
+            let mut sync = MarketSync {
+                market: market.clone(),
+                ..MarketSync::default()
+            };
+            if let Err(e) = self.sync_market(&mut sync, until).await {
+                println!("Sync of {} from {} failed: {}", market, self.provider.name(), e);
+                sync.error = Some(e.to_string());
+            }
+            report.markets.push(sync);
+        }
+        report
+    }
+
+    /// Saved progress of `market`
+    pub async fn progress(&self, market: &str) -> AgentResult<Option<SyncProgress>> {
+        match self.storage.retrieve(&self.progress_key(market)).await {
+            Ok(progress) => Ok(Some(progress)),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(AgentError::Custom(e.to_string())),
+        }
+    }
+
+    async fn sync_market(&mut self, sync: &mut MarketSync, until: u64) -> AgentResult<()> {
+        let interval = self.config.interval;
+        let start = period_after(self.config.start, interval);
+        let mut progress = self.progress(&sync.market).await?.unwrap_or(SyncProgress { synced_until: start });
+        sync.synced_until = progress.synced_until;
+
+        while progress.synced_until < until {
+            let end = until.min(progress.synced_until.saturating_add(self.window()));
+            sync.fetched += self.backfill(&sync.market, progress.synced_until, end).await?;
+            progress.synced_until = end;
+            self.storage
+                .store(&self.progress_key(&sync.market), &progress)
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+            sync.synced_until = end;
+        }
+
+        let gaps = self.store.gaps(&sync.market, interval, start, progress.synced_until).await?;
+        if !gaps.is_empty() {
+            println!("Repairing {} gaps in the history of {}", gaps.len(), sync.market);
+        }
+        for (gap_start, gap_end) in gaps {
+            let mut from = gap_start;
+            while from < gap_end {
+                let end = gap_end.min(from.saturating_add(self.window()));
+                sync.fetched += self.backfill(&sync.market, from, end).await?;
+                from = end;
+            }
+        }
+        sync.gaps = self.store.gaps(&sync.market, interval, start, progress.synced_until).await?;
+        Ok(())
+    }
+
+    /// Fetch and store the candles of `market` starting in `start..end`,
+    /// returning how many were stored
+    async fn backfill(&mut self, market: &str, start: u64, end: u64) -> AgentResult<usize> {
+        let candles: Vec<Candle> = self
+            .fetch(market, start, end)
+            .await?
+            .into_iter()
+            .filter(|candle| candle.market == market && (start..end).contains(&candle.timestamp))
+            .collect();
+        self.store.store(self.config.interval, &candles).await?;
+        Ok(candles.len())
+    }
+
+    /// Fetch from the provider, spacing requests and retrying rate-limited
+    /// ones
+    async fn fetch(&mut self, market: &str, start: u64, end: u64) -> AgentResult<Vec<Candle>> {
+        let mut retries = 0;
+        loop {
+            if let Some(last) = self.last_request {
+                tokio::time::sleep_until(last + self.config.min_request_interval).await;
+            }
+            self.last_request = Some(Instant::now());
+
+            match self.provider.fetch(market, self.config.interval, start, end).await {
+                Ok(candles) => return Ok(candles),
+                Err(ProviderError::RateLimited { retry_after }) if retries < self.config.max_retries => {
+                    let wait = retry_after.unwrap_or_else(|| self.config.backoff.saturating_mul(1 << retries.min(16)));
+                    println!("Rate limited by {}, retrying in {:?}", self.provider.name(), wait);
+                    tokio::time::sleep(wait).await;
+                    retries += 1;
+                }
+                Err(e) => return Err(AgentError::Custom(e.to_string())),
+            }
+        }
+    }
+
+    /// Seconds of candles a single request covers
+    fn window(&self) -> u64 {
+        self.config.interval.saturating_mul(self.provider.max_candles().max(1) as u64)
+    }
+
+    fn progress_key(&self, market: &str) -> String {
+        format!("{}{}/{}/{}", SYNC_KEY_PREFIX, self.provider.name(), market, self.config.interval)
+    }
+}
+
+/// Start of the first period of `interval` seconds starting at or after
+/// unix time `timestamp`
+fn period_after(timestamp: u64, interval: u64) -> u64 {
+    match timestamp % interval {
+        0 => timestamp,
+        offset => timestamp + (interval - offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    use crate::storage::StorageConfig;
+
+    const HOUR: u64 = 3600;
+
+    /// Hourly candles, rate limiting its first request; hour 5 is missing
+    /// from the first response covering it, and hour 7 always is
+    struct Provider {
+        requests: Mutex<Vec<(u64, u64)>>,
+        served: Mutex<HashSet<u64>>,
+    }
+
+    #[async_trait::async_trait]
+    impl CandleProvider for Provider {
+        fn name(&self) -> &str {
+            "test"
+        }
+
+        fn max_candles(&self) -> usize {
+            4
+        }
+
+        async fn fetch(
+            &self,
+            market: &str,
+            _interval: u64,
+            start: u64,
+            end: u64,
+        ) -> Result<Vec<Candle>, ProviderError> {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push((start, end));
+            if requests.len() == 1 {
+                return Err(ProviderError::RateLimited { retry_after: Some(Duration::from_millis(1)) });
+            }
+            let mut served = self.served.lock().unwrap();
+            Ok((start / HOUR..end / HOUR)
+                .filter(|&hour| hour != 7 && (hour != 5 || !served.insert(hour)))
+                .map(|hour| Candle::from_trade(market, hour * HOUR, 100.0 + hour as f64, 1.0))
+                .collect())
+        }
+    }
+
+    async fn storage(dir: &tempfile::TempDir) -> Arc<StorageManager> {
+        let config = StorageConfig {
+            base_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        Arc::new(StorageManager::new(config).await.unwrap())
+    }
+
+    fn config() -> SyncConfig {
+        SyncConfig {
+            markets: vec!["SOL/USDC".to_string()],
+            interval: HOUR,
+            start: 0,
+            min_request_interval: Duration::from_millis(1),
+            max_retries: 2,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_repairs_gaps_and_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let provider = Arc::new(Provider {
+            requests: Mutex::new(Vec::new()),
+            served: Mutex::new(HashSet::new()),
+        });
+        assert!(DataSync::new(provider.clone(), storage.clone(), SyncConfig { interval: 0, ..config() }).is_err());
+
+        let mut sync = DataSync::new(provider.clone(), storage.clone(), config()).unwrap();
+        let report = sync.sync(10 * HOUR + 60).await;
+        let market = &report.markets[0];
+        assert_eq!(market.error, None);
+        assert_eq!((market.fetched, market.synced_until), (9, 10 * HOUR));
+        assert_eq!(market.gaps, vec![(7 * HOUR, 8 * HOUR)]);
+        assert!(!report.is_complete());
+        // The rate-limited request, three windows, then both gaps
+        assert_eq!(provider.requests.lock().unwrap().len(), 6);
+
+        let candles = sync.store().range("SOL/USDC", HOUR, 2 * HOUR, 6 * HOUR).await.unwrap();
+        assert_eq!(candles.iter().map(|c| c.timestamp / HOUR).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+
+        // A new sync resumes from the saved progress
+        let mut restarted = DataSync::new(provider.clone(), storage, config()).unwrap();
+        let progress = restarted.progress("SOL/USDC").await.unwrap();
+        assert_eq!(progress, Some(SyncProgress { synced_until: 10 * HOUR }));
+        let report = restarted.sync(12 * HOUR).await;
+        assert_eq!(report.markets[0].fetched, 2);
+        assert_eq!(provider.requests.lock().unwrap()[6], (10 * HOUR, 12 * HOUR));
+    }
+}
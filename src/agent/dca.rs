@@ -0,0 +1,290 @@
+//! Dollar-cost averaging strategy
+//!
+//! This module provides:
+//! - `DcaConfig`: the market, side and fixed size of the periodic orders,
+//!   their interval and the slippage they may take
+//! - `DcaStrategy`, the `Strategy` placing one order per interval
+//! - `DcaProgress`, the schedule's progress, saved to and loaded from
+//!   storage so a restarted agent carries on where it stopped
+//!
+//! The schedule follows the market's clock, the timestamps of its ticks,
+//! so a backtest replays it as it would have run live. An interval missed
+//! entirely, e.g. while the agent was down, is skipped rather than caught
+//! up with a burst of orders. With a slippage limit the orders are limit
+//! orders that far from the last price, which the `OrderRouter` turns into
+//! the swap's minimum output; without one they are market orders.
+//!
+//! Orders are swaps from the agent's own funds when the strategy runs in a
+//! `TradingAgent` with an `OnChainExecutor`.
+
+use serde::{Deserialize, Serialize};
+use crate::storage::{StorageError, StorageManager};
+use super::error::{AgentError, AgentResult};
+use super::orders::{Order, OrderRequest, OrderSide};
+use super::trading::{Fill, MarketTick, Strategy};
+
+/// Prefix of the storage keys of saved progress, followed by the
+/// strategy's name
+pub const DCA_KEY_PREFIX: &str = "agents/dca/";
+
+/// Periodic orders of a DCA strategy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DcaConfig {
+    pub name: String,
+    pub market: String,
+    pub side: OrderSide,
+    /// Quantity of every order, in base units
+    pub quantity: u64,
+    /// Seconds between orders
+    pub interval: u64,
+    /// Largest slippage from the last price, in basis points; market
+    /// orders if `None`
+    pub max_slippage_bps: Option<u32>,
+    /// Orders to place before the schedule completes; endless if `None`
+    pub max_orders: Option<u64>,
+}
+
+impl DcaConfig {
+    pub fn validate(&self) -> AgentResult<()> {
+        let valid = !self.name.is_empty()
+            && !self.market.is_empty()
+            && self.quantity > 0
+            && self.interval > 0
+            && self.max_slippage_bps.map_or(true, |bps| bps < 10_000);
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidConfiguration)
+        }
+    }
+}
+
+/// Where a DCA schedule stands
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DcaProgress {
+    /// Unix time the next order is due; due on the first tick if `None`
+    pub next_at: Option<u64>,
+    pub orders_placed: u64,
+    pub filled_quantity: u64,
+    /// Quote amount exchanged by the fills
+    pub notional: f64,
+}
+
+impl DcaProgress {
+    /// Average price of the fills, `None` before the first
+    pub fn average_price(&self) -> Option<f64> {
+        (self.filled_quantity > 0).then(|| self.notional / self.filled_quantity as f64)
+    }
+
+    /// Save the progress of the strategy named `name` to `storage`
+    pub async fn save(&self, storage: &StorageManager, name: &str) -> AgentResult<()> {
+        storage
+            .store(&format!("{}{}", DCA_KEY_PREFIX, name), self)
+            .await
+            .map_err(|e| AgentError::Custom(e.to_string()))
+    }
+
+    /// Progress of the strategy named `name` last saved to `storage`
+    pub async fn load(storage: &StorageManager, name: &str) -> AgentResult<Option<Self>> {
+        match storage.retrieve(&format!("{}{}", DCA_KEY_PREFIX, name)).await {
+            Ok(progress) => Ok(Some(progress)),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(AgentError::Custom(e.to_string())),
+        }
+    }
+}
+
+/// Strategy placing a fixed-size order on its market every interval
+pub struct DcaStrategy {
+    config: DcaConfig,
+    progress: DcaProgress,
+    /// Last tick of the market, if the strategy hasn't acted on it yet
+    tick: Option<MarketTick>,
+}
+
+impl DcaStrategy {
+    pub fn new(config: DcaConfig) -> AgentResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            progress: DcaProgress::default(),
+            tick: None,
+        })
+    }
+
+    /// Carry on from `progress`, e.g. loaded after a restart
+    pub fn with_progress(mut self, progress: DcaProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn config(&self) -> &DcaConfig {
+        &self.config
+    }
+
+    pub fn progress(&self) -> &DcaProgress {
+        &self.progress
+    }
+
+    /// Whether every order of a bounded schedule was placed
+    pub fn is_complete(&self) -> bool {
+        self.config.max_orders.map_or(false, |max| self.progress.orders_placed >= max)
+    }
+
+    /// Limit price `max_slippage_bps` away from `price`, against the order
+    fn limit_price(&self, price: f64) -> Option<f64> {
+        let slippage = f64::from(self.config.max_slippage_bps?) / 10_000.0;
+        Some(match self.config.side {
+            OrderSide::Buy => price * (1.0 + slippage),
+            OrderSide::Sell => price * (1.0 - slippage),
+        })
+    }
+}
+
+impl Strategy for DcaStrategy {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn on_tick(&mut self, tick: &MarketTick) {
+        if tick.market == self.config.market {
+            self.tick = Some(tick.clone());
+        }
+    }
+
+    fn on_fill(&mut self, order: &Order, fill: &Fill) {
+        if order.request.market == self.config.market {
+            self.progress.filled_quantity = self.progress.filled_quantity.saturating_add(fill.quantity);
+            self.progress.notional += fill.quantity as f64 * fill.price;
+        }
+    }
+
+    fn generate_orders(&mut self) -> Vec<OrderRequest> {
+        let Some(tick) = self.tick.take() else {
+            return vec![];
+        };
+        if self.is_complete() || self.progress.next_at.map_or(false, |next_at| tick.timestamp < next_at) {
+            return vec![];
+        }
+
+        // The next order is due an interval after this one was, or after
+        // now if whole intervals were missed
+        let due = self.progress.next_at.unwrap_or(tick.timestamp);
+        let next_at = due.saturating_add(self.config.interval);
+        self.progress.next_at = Some(if next_at > tick.timestamp {
+            next_at
+        } else {
+            tick.timestamp.saturating_add(self.config.interval)
+        });
+        self.progress.orders_placed += 1;
+
+        vec![OrderRequest {
+            client_order_id: String::new(),
+            agent: String::new(),
+            strategy: String::new(),
+            market: self.config.market.clone(),
+            side: self.config.side,
+            quantity: self.config.quantity,
+            limit_price: self.limit_price(tick.price),
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::backtest::{BacktestConfig, Backtester, Candle};
+    use crate::storage::StorageConfig;
+
+    const HOUR: u64 = 3600;
+
+    fn config() -> DcaConfig {
+        DcaConfig {
+            name: "dca".to_string(),
+            market: "SOL/USDC".to_string(),
+            side: OrderSide::Buy,
+            quantity: 10,
+            interval: 3 * HOUR,
+            max_slippage_bps: Some(100),
+            max_orders: Some(3),
+        }
+    }
+
+    fn tick(timestamp: u64, price: f64) -> MarketTick {
+        MarketTick {
+            market: "SOL/USDC".to_string(),
+            price,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        assert!(DcaStrategy::new(DcaConfig { interval: 0, ..config() }).is_err());
+        assert!(DcaStrategy::new(DcaConfig { max_slippage_bps: Some(10_000), ..config() }).is_err());
+
+        let mut dca = DcaStrategy::new(config()).unwrap();
+        assert!(dca.generate_orders().is_empty());
+
+        dca.on_tick(&tick(0, 100.0));
+        let orders = dca.generate_orders();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].quantity, 10);
+        assert_eq!(orders[0].limit_price, Some(101.0));
+        // Acted on once per tick
+        assert!(dca.generate_orders().is_empty());
+
+        dca.on_tick(&tick(2 * HOUR, 100.0));
+        assert!(dca.generate_orders().is_empty());
+        dca.on_tick(&tick(3 * HOUR, 100.0));
+        assert_eq!(dca.generate_orders().len(), 1);
+        assert_eq!(dca.progress().next_at, Some(6 * HOUR));
+
+        // Intervals missed entirely are skipped
+        dca.on_tick(&tick(20 * HOUR, 100.0));
+        assert_eq!(dca.generate_orders().len(), 1);
+        assert_eq!(dca.progress().next_at, Some(23 * HOUR));
+        assert!(dca.is_complete());
+
+        dca.on_tick(&tick(30 * HOUR, 100.0));
+        assert!(dca.generate_orders().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_progress_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageManager::new(StorageConfig {
+            base_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(DcaProgress::load(&storage, "dca").await.unwrap(), None);
+
+        let mut dca = DcaStrategy::new(config()).unwrap();
+        dca.on_tick(&tick(0, 100.0));
+        dca.generate_orders();
+        dca.progress().save(&storage, "dca").await.unwrap();
+
+        let progress = DcaProgress::load(&storage, "dca").await.unwrap().unwrap();
+        let mut restarted = DcaStrategy::new(config()).unwrap().with_progress(progress);
+        restarted.on_tick(&tick(HOUR, 100.0));
+        assert!(restarted.generate_orders().is_empty());
+        restarted.on_tick(&tick(3 * HOUR, 100.0));
+        assert_eq!(restarted.generate_orders().len(), 1);
+        assert_eq!(restarted.progress().orders_placed, 2);
+    }
+
+    #[tokio::test]
+    async fn test_backtest() {
+        let candles: Vec<Candle> = (0..12)
+            .map(|hour| Candle::from_trade("SOL/USDC", hour * HOUR, 100.0 + hour as f64, 1.0))
+            .collect();
+        let dca = DcaStrategy::new(DcaConfig { max_orders: None, ..config() }).unwrap();
+
+        let report = Backtester::new(BacktestConfig::default()).run(Box::new(dca), &candles).await.unwrap();
+        // Orders at hours 0, 3, 6 and 9, filled on the following candles
+        assert_eq!(report.fills, 4);
+        assert_eq!(report.rejected, 0);
+    }
+}
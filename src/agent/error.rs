@@ -1,9 +1,8 @@
 use solana_program::{program_error::ProgramError, decode_error::DecodeError};
 use thiserror::Error;
-use num_derive::FromPrimitive;
-use num_traits::FromPrimitive;
 
-#[derive(Error, Debug, Clone, PartialEq, FromPrimitive)]
+#[derive(Error, Debug, Clone, PartialEq)]
+#[repr(u32)]
 pub enum AgentError {
     #[error("Invalid agent configuration")]
     InvalidConfiguration = 0,
@@ -49,11 +48,38 @@ pub enum AgentError {
 
     #[error("Custom error: {0}")]
     Custom(String) = 14,
+
+    #[error("Recipient's mailbox is full")]
+    MailboxFull = 15,
+}
+
+impl AgentError {
+    /// Code of the error, as returned in `ProgramError::Custom`
+    pub fn code(&self) -> u32 {
+        match self {
+            AgentError::InvalidConfiguration => 0,
+            AgentError::NotInitialized => 1,
+            AgentError::InvalidStateTransition => 2,
+            AgentError::CapabilityNotFound => 3,
+            AgentError::InsufficientPermissions => 4,
+            AgentError::ProcessingError => 5,
+            AgentError::MemoryError => 6,
+            AgentError::NetworkError => 7,
+            AgentError::ValidationError => 8,
+            AgentError::ResourceLimitExceeded => 9,
+            AgentError::Timeout => 10,
+            AgentError::InvalidInput => 11,
+            AgentError::SystemOverload => 12,
+            AgentError::Unauthorized => 13,
+            AgentError::Custom(_) => 14,
+            AgentError::MailboxFull => 15,
+        }
+    }
 }
 
 impl From<AgentError> for ProgramError {
     fn from(e: AgentError) -> Self {
-        ProgramError::Custom(e as u32)
+        ProgramError::Custom(e.code())
     }
 }
 
@@ -129,7 +155,7 @@ impl ErrorHandler for DefaultErrorHandler {
     fn can_recover(&self, error: &AgentError) -> bool {
         matches!(
             error,
-            AgentError::NetworkError | AgentError::Timeout | AgentError::SystemOverload
+            AgentError::NetworkError | AgentError::Timeout | AgentError::SystemOverload | AgentError::MailboxFull
         )
     }
 }
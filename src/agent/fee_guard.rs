@@ -0,0 +1,314 @@
+//! Client-side fee budgets of an agent's transactions
+//!
+//! This module provides:
+//! - `FeeEstimate`: the base fee, priority fee and rent of the accounts a
+//!   transaction creates, estimated before it is sent
+//! - `FeeGuard`, refusing transactions above a per-transaction budget or
+//!   beyond a rolling daily budget
+//! - Refusals appended to an `AuditLog` as denied decisions
+//!
+//! Rent is estimated by simulating the transaction: writable accounts that
+//! don't exist yet are read back from the simulation, and the rent-exempt
+//! balance of their size is counted. An admitted transaction counts
+//! against the daily budget at its estimate, whether or not it lands, as
+//! a failed transaction still pays its fees.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig},
+};
+use solana_sdk::{
+    account::Account,
+    compute_budget,
+    hash::Hash,
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Signature,
+    transaction::VersionedTransaction,
+};
+use thiserror::Error;
+use crate::error::SonomaResult;
+use crate::solana::fees;
+use crate::solana::transaction::{BuildError, TransactionBuilder};
+use super::audit::{AuditLog, DecisionOutcome, DecisionRecord};
+
+/// Fee of each signature of a transaction, in lamports
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+/// Compute units the runtime allows each instruction without a limit set
+const DEFAULT_INSTRUCTION_COMPUTE_UNITS: u64 = 200_000;
+
+/// Most compute units a transaction may request
+const MAX_COMPUTE_UNITS: u64 = 1_400_000;
+
+/// Window of the daily budget
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Fee budgets of an agent, in lamports; unlimited if `None`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeLimits {
+    /// Most a single transaction may cost
+    pub max_per_transaction: Option<u64>,
+    /// Most the transactions of the last 24 hours may cost together
+    pub max_daily: Option<u64>,
+}
+
+/// Estimated cost of a transaction, in lamports
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub base_fee: u64,
+    pub priority_fee: u64,
+    /// Rent-exempt balance of the accounts the transaction creates
+    pub rent: u64,
+}
+
+impl FeeEstimate {
+    pub fn total(&self) -> u64 {
+        self.base_fee.saturating_add(self.priority_fee).saturating_add(self.rent)
+    }
+}
+
+/// Why a transaction was refused
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum FeeRefusal {
+    #[error("Estimated cost of {estimate} lamports exceeds the per-transaction budget of {limit}")]
+    PerTransaction { estimate: u64, limit: u64 },
+
+    #[error("Estimated cost of {estimate} lamports after {spent} spent today exceeds the daily budget of {limit}")]
+    Daily { estimate: u64, spent: u64, limit: u64 },
+}
+
+/// Enforces an agent's fee budgets before its transactions are sent
+#[derive(Debug)]
+pub struct FeeGuard {
+    limits: FeeLimits,
+    /// Cost of each admitted transaction of the last 24 hours, oldest first
+    spent: Mutex<VecDeque<(Instant, u64)>>,
+    audit: Option<AuditLog>,
+}
+
+impl FeeGuard {
+    pub fn new(limits: FeeLimits) -> Self {
+        Self {
+            limits,
+            spent: Mutex::new(VecDeque::new()),
+            audit: None,
+        }
+    }
+
+    /// Append each refusal to `audit`, as a denied decision of the agent
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn limits(&self) -> FeeLimits {
+        self.limits
+    }
+
+    /// Cost of the transactions admitted in the last 24 hours
+    pub fn spent_today(&self) -> u64 {
+        self.spent_at(Instant::now())
+    }
+
+    /// Estimate the cost of the transaction `builder` makes, paid for by
+    /// `payer`
+    pub fn estimate(
+        &self,
+        rpc: &RpcClient,
+        builder: &TransactionBuilder,
+        payer: &Pubkey,
+    ) -> SonomaResult<FeeEstimate> {
+        let instructions = builder.instructions();
+        let message = v0::Message::try_compile(payer, &instructions, builder.lookup_tables(), Hash::default())
+            .map_err(BuildError::from)?;
+        let signatures = u64::from(message.header.num_required_signatures);
+
+        let budget = builder.compute_budget();
+        let units = budget.unit_limit.map(u64::from).unwrap_or_else(|| {
+            let programs = instructions.iter().filter(|instruction| instruction.program_id != compute_budget::id());
+            (programs.count() as u64 * DEFAULT_INSTRUCTION_COMPUTE_UNITS).min(MAX_COMPUTE_UNITS)
+        });
+        let priority_fee = (u128::from(budget.unit_price.unwrap_or(0)) * u128::from(units) + 999_999) / 1_000_000;
+
+        let transaction = VersionedTransaction {
+            signatures: vec![Signature::default(); signatures as usize],
+            message: VersionedMessage::V0(message),
+        };
+        Ok(FeeEstimate {
+            base_fee: signatures * LAMPORTS_PER_SIGNATURE,
+            priority_fee: u64::try_from(priority_fee).unwrap_or(u64::MAX),
+            rent: created_rent(rpc, &transaction, &fees::writable_accounts(&instructions))?,
+        })
+    }
+
+    /// Admit a transaction of `agent` costing `estimate`, counting it
+    /// against the daily budget, or refuse it if it exceeds a budget
+    pub fn admit(&self, agent: &str, estimate: &FeeEstimate) -> Result<(), FeeRefusal> {
+        let result = self.admit_at(estimate, Instant::now());
+        if let Err(refusal) = &result {
+            println!("Refused transaction of agent {}: {}", agent, refusal);
+            self.audit_refusal(agent, estimate, refusal);
+        }
+        result
+    }
+
+    fn admit_at(&self, estimate: &FeeEstimate, now: Instant) -> Result<(), FeeRefusal> {
+        let cost = estimate.total();
+        if let Some(limit) = self.limits.max_per_transaction.filter(|&limit| cost > limit) {
+            return Err(FeeRefusal::PerTransaction { estimate: cost, limit });
+        }
+
+        let mut spent = self.spent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        expire(&mut spent, now);
+        let today = spent.iter().map(|(_, cost)| cost).sum::<u64>();
+        if let Some(limit) = self.limits.max_daily.filter(|&limit| today.saturating_add(cost) > limit) {
+            return Err(FeeRefusal::Daily { estimate: cost, spent: today, limit });
+        }
+        spent.push_back((now, cost));
+        Ok(())
+    }
+
+    fn spent_at(&self, now: Instant) -> u64 {
+        let mut spent = self.spent.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        expire(&mut spent, now);
+        spent.iter().map(|(_, cost)| cost).sum()
+    }
+
+    /// Append the refusal to the audit log in the background. Guards are
+    /// checked from blocking code, so without a Tokio runtime to append on
+    /// the refusal is only logged.
+    fn audit_refusal(&self, agent: &str, estimate: &FeeEstimate, refusal: &FeeRefusal) {
+        let Some(audit) = self.audit.clone() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            println!("No runtime to audit the refused transaction of agent {} on", agent);
+            return;
+        };
+        let record = DecisionRecord::new(
+            agent,
+            &json!({ "estimate": estimate, "limits": self.limits, "spent_today": self.spent_today() }),
+            Vec::new(),
+            "send transaction".to_string(),
+            None,
+            DecisionOutcome::Denied { error: refusal.to_string() },
+        );
+        let agent = agent.to_string();
+        runtime.spawn(async move {
+            if let Err(e) = audit.append(record).await {
+                println!("Failed to audit refused transaction of agent {}: {}", agent, e);
+            }
+        });
+    }
+}
+
+/// Drop the costs older than a day
+fn expire(spent: &mut VecDeque<(Instant, u64)>, now: Instant) {
+    while spent.front().map_or(false, |(at, _)| now.saturating_duration_since(*at) >= DAY) {
+        spent.pop_front();
+    }
+}
+
+/// Rent-exempt balance of the accounts among `writable` that don't exist
+/// yet and that simulating `transaction` creates
+fn created_rent(rpc: &RpcClient, transaction: &VersionedTransaction, writable: &[Pubkey]) -> SonomaResult<u64> {
+    if writable.is_empty() {
+        return Ok(0);
+    }
+    let existing = rpc.get_multiple_accounts(writable)?;
+    let missing: Vec<String> = writable
+        .iter()
+        .zip(existing)
+        .filter(|(_, account)| account.is_none())
+        .map(|(address, _)| address.to_string())
+        .collect();
+    if missing.is_empty() {
+        return Ok(0);
+    }
+
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        commitment: Some(rpc.commitment()),
+        accounts: Some(RpcSimulateTransactionAccountsConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            addresses: missing,
+        }),
+        ..Default::default()
+    };
+    let created = rpc.simulate_transaction_with_config(transaction, config)?.value.accounts.unwrap_or_default();
+    let rent = Rent::default();
+    Ok(created
+        .into_iter()
+        .flatten()
+        .filter_map(|account| account.decode::<Account>())
+        .map(|account| rent.minimum_balance(account.data.len()))
+        .sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::Instruction;
+
+    fn estimate(total: u64) -> FeeEstimate {
+        FeeEstimate {
+            base_fee: LAMPORTS_PER_SIGNATURE,
+            priority_fee: total - LAMPORTS_PER_SIGNATURE,
+            rent: 0,
+        }
+    }
+
+    #[test]
+    fn test_budgets() {
+        let guard = FeeGuard::new(FeeLimits {
+            max_per_transaction: Some(50_000),
+            max_daily: Some(100_000),
+        });
+        let start = Instant::now();
+
+        assert_eq!(
+            guard.admit_at(&estimate(60_000), start),
+            Err(FeeRefusal::PerTransaction { estimate: 60_000, limit: 50_000 })
+        );
+        guard.admit_at(&estimate(40_000), start).unwrap();
+        guard.admit_at(&estimate(40_000), start + Duration::from_secs(60)).unwrap();
+        assert_eq!(
+            guard.admit_at(&estimate(40_000), start + Duration::from_secs(120)),
+            Err(FeeRefusal::Daily { estimate: 40_000, spent: 80_000, limit: 100_000 })
+        );
+        assert_eq!(guard.spent_at(start + Duration::from_secs(120)), 80_000);
+
+        // The first transaction leaves the window a day after it was admitted
+        guard.admit_at(&estimate(40_000), start + DAY).unwrap();
+        assert_eq!(guard.spent_at(start + DAY), 80_000);
+
+        let unlimited = FeeGuard::new(FeeLimits::default());
+        unlimited.admit_at(&estimate(u64::MAX / 2), start).unwrap();
+        unlimited.admit_at(&estimate(u64::MAX / 2), start).unwrap();
+    }
+
+    #[test]
+    fn test_estimate_without_new_accounts() {
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let builder = TransactionBuilder::new()
+            .add_instruction(instruction)
+            .with_compute_unit_limit(100_000)
+            .with_compute_unit_price(1_500);
+
+        let estimate = FeeGuard::new(FeeLimits::default()).estimate(&rpc, &builder, &payer).unwrap();
+        assert_eq!(estimate.base_fee, LAMPORTS_PER_SIGNATURE);
+        assert_eq!(estimate.priority_fee, 150);
+        assert_eq!(estimate.rent, 0);
+    }
+}
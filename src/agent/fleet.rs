@@ -0,0 +1,326 @@
+//! Bulk operations on a payer's fleet of on-chain agents
+//!
+//! This module provides:
+//! - `FleetManager`, creating, configuring, pausing, resuming and closing
+//!   many agents with several agents' instructions per transaction
+//! - Address lookup tables and a send strategy shared by every batch
+//! - A `FleetReport` telling which agents succeeded, were skipped or failed
+//!
+//! Every batch is planned from the agents' on-chain state, read right
+//! before it is sent: agents already in the requested state are skipped.
+//! An operation interrupted part-way is resumed by running it again with
+//! the same names. If a batch fails, its agents are planned and sent one by
+//! one, so a single failing agent doesn't fail the others and each error
+//! is reported for the agent that caused it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    address_lookup_table::AddressLookupTableAccount,
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    transaction::VersionedTransaction,
+};
+use crate::error::{SonomaError, SonomaResult};
+use crate::solana::client::{self, ClientError, ReadOptions};
+use crate::solana::send::{self, SendStrategy};
+use crate::solana::signer::SonomaSigner;
+use crate::solana::transaction::{ComputeBudget, TransactionBuilder};
+use crate::solana::program::{
+    instruction::AgentInstruction,
+    pda,
+    state::{AgentAccount, AgentState, PauseReason, MIN_AGENT_STAKE},
+};
+use super::base::AgentConfig;
+
+/// Agents per transaction by default; small enough for creating agents
+/// without lookup tables
+pub const DEFAULT_BATCH_SIZE: usize = 4;
+
+/// Instructions for one agent, `None` if it is already in the requested state
+type Plan = SonomaResult<Option<Vec<Instruction>>>;
+
+/// Outcome of a bulk operation, per agent
+#[derive(Debug, Default)]
+pub struct FleetReport {
+    /// Agents changed, with the transaction that changed them
+    pub succeeded: Vec<(String, Signature)>,
+    /// Agents already in the requested state
+    pub skipped: Vec<String>,
+    /// Agents that could not be changed, with the reason
+    pub failed: Vec<(String, SonomaError)>,
+}
+
+impl FleetReport {
+    /// Whether every agent is now in the requested state
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Bulk operations on the agents created by one payer
+pub struct FleetManager {
+    rpc: RpcClient,
+    program_id: Pubkey,
+    payer: Arc<dyn SonomaSigner>,
+    batch_size: usize,
+    compute_budget: ComputeBudget,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    send_strategy: SendStrategy,
+}
+
+impl FleetManager {
+    /// Manager of the agents `payer` created, connecting with the URL and
+    /// commitment of `client`
+    pub fn new(client: &RpcClient, program_id: &Pubkey, payer: &Keypair) -> Self {
+        Self::new_with_signer(client, program_id, Arc::new(payer.insecure_clone()))
+    }
+
+    /// `new`, signing with `payer` wherever it holds its key
+    pub fn new_with_signer(client: &RpcClient, program_id: &Pubkey, payer: Arc<dyn SonomaSigner>) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(client.url(), client.commitment()),
+            program_id: *program_id,
+            payer,
+            batch_size: DEFAULT_BATCH_SIZE,
+            compute_budget: ComputeBudget::default(),
+            lookup_tables: Vec::new(),
+            send_strategy: SendStrategy::default(),
+        }
+    }
+
+    /// Send up to `batch_size` agents per transaction, at least one
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_compute_budget(mut self, compute_budget: ComputeBudget) -> Self {
+        self.compute_budget = compute_budget;
+        self
+    }
+
+    /// Compress the accounts of every batch through `table`, so larger
+    /// batches fit a transaction
+    pub fn with_lookup_table(mut self, table: AddressLookupTableAccount) -> Self {
+        self.lookup_tables.push(table);
+        self
+    }
+
+    pub fn with_send_strategy(mut self, send_strategy: SendStrategy) -> Self {
+        self.send_strategy = send_strategy;
+        self
+    }
+
+    /// Address of the agent the payer created as `name`
+    pub fn address(&self, name: &str) -> Pubkey {
+        pda::find_agent_address(&self.program_id, &self.payer.pubkey(), name).0
+    }
+
+    /// Create the agents named `names` with `config`, stake
+    /// `MIN_AGENT_STAKE` for each and start them, as `Agent::new` does;
+    /// agents that already exist are skipped
+    pub fn create(&self, names: &[&str], config: &AgentConfig) -> SonomaResult<FleetReport> {
+        let config = config.to_program_config(None)?;
+        let authority = self.payer.pubkey();
+
+        Ok(self.run(names, |name, agent| {
+            Ok(agent.is_none().then(|| {
+                let address = self.address(name);
+                let initialize = name.to_string();
+                vec![
+                    AgentInstruction::initialize(&self.program_id, &address, &authority, initialize, config.clone()),
+                    AgentInstruction::stake(&self.program_id, &address, &authority, MIN_AGENT_STAKE),
+                    AgentInstruction::resume(&self.program_id, &address, &authority),
+                ]
+            }))
+        }))
+    }
+
+    /// Replace the agents' config, keeping each one's rate limit and
+    /// allowed programs, as `Agent::update_config` does; agents whose
+    /// config already matches are skipped
+    ///
+    /// Agents with an update delay only stage the update, and are staged
+    /// again if the operation is resumed before the update is committed.
+    pub fn configure(&self, names: &[&str], config: &AgentConfig) -> SonomaResult<FleetReport> {
+        config.to_program_config(None)?;
+        let authority = self.payer.pubkey();
+
+        Ok(self.run(names, |name, agent| {
+            let current = &self.existing(name, agent)?.config;
+            let updated = config.to_program_config(Some(current))?;
+            Ok((updated != *current).then(|| {
+                vec![AgentInstruction::update(&self.program_id, &self.address(name), &authority, updated)]
+            }))
+        }))
+    }
+
+    /// Pause the agents; agents already paused are skipped
+    pub fn pause(&self, names: &[&str]) -> FleetReport {
+        let authority = self.payer.pubkey();
+
+        self.run(names, |name, agent| {
+            let paused = self.existing(name, agent)?.state == AgentState::Paused;
+            Ok((!paused).then(|| {
+                let address = self.address(name);
+                vec![AgentInstruction::pause(&self.program_id, &address, &authority, PauseReason::Manual, None)]
+            }))
+        })
+    }
+
+    /// Resume the agents; agents already running are skipped
+    pub fn resume(&self, names: &[&str]) -> FleetReport {
+        let authority = self.payer.pubkey();
+
+        self.run(names, |name, agent| {
+            let running = self.existing(name, agent)?.state == AgentState::Running;
+            Ok((!running).then(|| vec![AgentInstruction::resume(&self.program_id, &self.address(name), &authority)]))
+        })
+    }
+
+    /// Close the agents and withdraw their stake to the payer; agents
+    /// already closed are skipped
+    pub fn close(&self, names: &[&str]) -> FleetReport {
+        let authority = self.payer.pubkey();
+
+        self.run(names, |name, agent| {
+            if agent.is_none() {
+                return Ok(None);
+            }
+            let address = self.address(name);
+            let (stake, _) = pda::find_stake_address(&self.program_id, &address);
+            let options = ReadOptions::default();
+            let staked = self.rpc.get_account_with_config(&stake, options.account_config(&self.rpc))?.value.is_some();
+
+            let close = AgentInstruction::close(&self.program_id, &address, &authority);
+            let mut instructions = vec![close];
+            if staked {
+                instructions.push(AgentInstruction::unstake(&self.program_id, &address, &authority));
+            }
+            Ok(Some(instructions))
+        })
+    }
+
+    /// `agent`, or an error naming the missing account
+    fn existing<'a>(&self, name: &str, agent: Option<&'a AgentAccount>) -> SonomaResult<&'a AgentAccount> {
+        agent.ok_or_else(|| ClientError::AccountNotFound(self.address(name)).into())
+    }
+
+    /// Plan and send `names` in batches, falling back to one agent per
+    /// transaction for the agents of a failed batch
+    fn run<F>(&self, names: &[&str], plan: F) -> FleetReport
+    where
+        F: Fn(&str, Option<&AgentAccount>) -> Plan,
+    {
+        let mut report = FleetReport::default();
+        for batch in names.chunks(self.batch_size) {
+            match self.send_batch(batch, &plan) {
+                Ok(outcome) => merge(&mut report, outcome),
+                Err(error) if batch.len() == 1 => report.failed.push((batch[0].to_string(), error)),
+                Err(error) => {
+                    println!("Fleet batch of {} agents failed, retrying one by one: {}", batch.len(), error);
+                    for name in batch {
+                        match self.send_batch(&[name], &plan) {
+                            Ok(outcome) => merge(&mut report, outcome),
+                            Err(error) => report.failed.push((name.to_string(), error)),
+                        }
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    /// Plan `batch` from the agents' current state and send the changes in
+    /// one transaction; agents whose plan failed are reported without
+    /// failing the rest
+    fn send_batch<F>(&self, batch: &[&str], plan: &F) -> SonomaResult<FleetReport>
+    where
+        F: Fn(&str, Option<&AgentAccount>) -> Plan,
+    {
+        let addresses: Vec<Pubkey> = batch.iter().map(|name| self.address(name)).collect();
+        let agents: HashMap<Pubkey, AgentAccount> =
+            client::fetch_agents_with_options(&self.rpc, &self.program_id, &addresses, &ReadOptions::default())?;
+
+        let mut report = FleetReport::default();
+        let mut changed = Vec::new();
+        let mut instructions = Vec::new();
+        for (name, address) in batch.iter().zip(&addresses) {
+            match plan(name, agents.get(address)) {
+                Ok(Some(planned)) => {
+                    changed.push(name.to_string());
+                    instructions.extend(planned);
+                }
+                Ok(None) => report.skipped.push(name.to_string()),
+                Err(error) => report.failed.push((name.to_string(), error)),
+            }
+        }
+
+        if !instructions.is_empty() {
+            let signature = self.send(&instructions)?;
+            report.succeeded.extend(changed.into_iter().map(|name| (name, signature)));
+        }
+        Ok(report)
+    }
+
+    /// Sign `instructions` with the payer and send them in one transaction
+    fn send(&self, instructions: &[Instruction]) -> SonomaResult<Signature> {
+        send::send_with_strategy(&self.rpc, &self.send_strategy, |blockhash| self.transaction(instructions, blockhash))
+    }
+
+    fn transaction(&self, instructions: &[Instruction], blockhash: Hash) -> SonomaResult<VersionedTransaction> {
+        Ok(TransactionBuilder::new()
+            .with_compute_budget(self.compute_budget)
+            .with_lookup_tables(self.lookup_tables.iter().cloned())
+            .add_instructions(instructions.iter().cloned())
+            .build_versioned(&self.payer.pubkey(), &[self.payer.as_signer()], blockhash)?)
+    }
+}
+
+fn merge(report: &mut FleetReport, outcome: FleetReport) {
+    report.succeeded.extend(outcome.succeeded);
+    report.skipped.extend(outcome.skipped);
+    report.failed.extend(outcome.failed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Signer;
+    use crate::test_utils::client_config;
+
+    fn manager() -> FleetManager {
+        let client = RpcClient::new_mock("succeeds".to_string());
+        FleetManager::new(&client, &Pubkey::new_unique(), &Keypair::new())
+    }
+
+    #[test]
+    fn test_addresses_match_agents() {
+        let payer = Keypair::new();
+        let program_id = Pubkey::new_unique();
+        let client = RpcClient::new_mock("succeeds".to_string());
+        let fleet = FleetManager::new(&client, &program_id, &payer).with_batch_size(0);
+
+        assert_eq!(fleet.batch_size, 1);
+        assert_eq!(fleet.address("alpha"), pda::find_agent_address(&program_id, &payer.pubkey(), "alpha").0);
+        assert_ne!(fleet.address("alpha"), fleet.address("beta"));
+    }
+
+    #[test]
+    fn test_invalid_config_fails_before_sending() {
+        let mut config = client_config();
+        config.execution_limit = 0;
+
+        let fleet = manager();
+        assert!(matches!(fleet.create(&["alpha"], &config), Err(SonomaError::InvalidConfiguration(_))));
+        assert!(matches!(fleet.configure(&["alpha"], &config), Err(SonomaError::InvalidConfiguration(_))));
+
+        let report = fleet.pause(&[]);
+        assert!(report.is_complete());
+        assert!(report.succeeded.is_empty() && report.skipped.is_empty());
+    }
+}
@@ -0,0 +1,39 @@
+//! Agent lifecycle hooks
+//!
+//! This module provides the `AgentLifecycle` trait, whose hooks
+//! `AgentRuntime` invokes around an agent's state transitions:
+//! - `on_start` before the first cycle; an agent failing to start never runs
+//! - `on_pause` and `on_resume` when the agent is paused and resumed
+//! - `on_error` after a failed cycle, message or hook
+//! - `on_shutdown` after the last cycle, e.g. to flush positions and release
+//!   resources
+//!
+//! Every hook does nothing by default.
+
+use super::error::{AgentError, AgentResult};
+
+#[async_trait::async_trait]
+pub trait AgentLifecycle: Send {
+    /// Called once before the first cycle
+    async fn on_start(&mut self) -> AgentResult<()> {
+        Ok(())
+    }
+
+    /// Called when the agent is paused, after any running cycle finished
+    async fn on_pause(&mut self) -> AgentResult<()> {
+        Ok(())
+    }
+
+    /// Called when a paused agent is resumed, before its next cycle
+    async fn on_resume(&mut self) -> AgentResult<()> {
+        Ok(())
+    }
+
+    /// Called with the error of a failed cycle, message or hook
+    async fn on_error(&mut self, _error: &AgentError) {}
+
+    /// Called once the agent stops, after its last cycle
+    async fn on_shutdown(&mut self) -> AgentResult<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Bounded agent memory
+//!
+//! This module provides:
+//! - `AgentMemory`, a fixed-capacity store of an agent's observations and
+//!   decisions, oldest first
+//! - Importance scores deciding what is pruned once the store is full
+//! - Recall by recency, importance, kind and tag, for planning
+//!
+//! A full store evicts its least important entry, the oldest among equally
+//! important ones, so routine entries cycle through like a ring buffer while
+//! important ones are kept.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What a memory entry records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryKind {
+    Observation,
+    Decision,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    /// Increasing with each entry remembered
+    pub id: u64,
+    pub kind: MemoryKind,
+    pub content: Value,
+    /// In [0, 1]; less important entries are pruned first
+    pub importance: f32,
+    pub tags: Vec<String>,
+    /// Unix time the entry was remembered
+    pub timestamp: u64,
+}
+
+/// Entries to recall; every entry matches the default query
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryQuery {
+    pub kind: Option<MemoryKind>,
+    /// Tag the entries must carry
+    pub tag: Option<String>,
+    pub min_importance: f32,
+    /// Most entries to recall; all matching if `None`
+    pub limit: Option<usize>,
+}
+
+impl MemoryQuery {
+    pub fn kind(mut self, kind: MemoryKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    pub fn min_importance(mut self, min_importance: f32) -> Self {
+        self.min_importance = min_importance;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn matches(&self, entry: &MemoryEntry) -> bool {
+        self.kind.map_or(true, |kind| entry.kind == kind)
+            && self.tag.as_ref().map_or(true, |tag| entry.tags.contains(tag))
+            && entry.importance >= self.min_importance
+    }
+}
+
+/// Fixed-capacity memory of an agent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentMemory {
+    capacity: usize,
+    entries: VecDeque<MemoryEntry>,
+    next_id: u64,
+}
+
+impl AgentMemory {
+    /// Memory holding at most `capacity` entries, at least 1
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity.min(1024)),
+            next_id: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Change the capacity, pruning entries that no longer fit
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.entries.len() > self.capacity {
+            self.evict();
+        }
+    }
+
+    /// Remember `content`, returning the entry's id
+    ///
+    /// `importance` is clamped to [0, 1].
+    pub fn remember(&mut self, kind: MemoryKind, content: Value, importance: f32, tags: &[&str]) -> u64 {
+        if self.entries.len() >= self.capacity {
+            self.evict();
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push_back(MemoryEntry {
+            id,
+            kind,
+            content,
+            importance: if importance.is_nan() { 0.0 } else { importance.clamp(0.0, 1.0) },
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        });
+        id
+    }
+
+    pub fn observe(&mut self, content: Value, importance: f32, tags: &[&str]) -> u64 {
+        self.remember(MemoryKind::Observation, content, importance, tags)
+    }
+
+    pub fn record_decision(&mut self, content: Value, importance: f32, tags: &[&str]) -> u64 {
+        self.remember(MemoryKind::Decision, content, importance, tags)
+    }
+
+    pub fn get(&self, id: u64) -> Option<&MemoryEntry> {
+        self.entries.iter().find(|entry| entry.id == id)
+    }
+
+    /// The `n` latest entries, latest first
+    pub fn recent(&self, n: usize) -> Vec<&MemoryEntry> {
+        self.entries.iter().rev().take(n).collect()
+    }
+
+    /// Entries matching `query`, most important first and latest first
+    /// among equally important ones
+    pub fn recall(&self, query: &MemoryQuery) -> Vec<&MemoryEntry> {
+        let mut matching: Vec<&MemoryEntry> = self.entries.iter().filter(|entry| query.matches(entry)).collect();
+        matching.sort_by(|a, b| b.importance.total_cmp(&a.importance).then(b.id.cmp(&a.id)));
+        if let Some(limit) = query.limit {
+            matching.truncate(limit);
+        }
+        matching
+    }
+
+    /// Scale every entry's importance by `1 - rate`, so entries lose
+    /// importance as they age; `rate` is clamped to [0, 1]
+    pub fn decay(&mut self, rate: f32) {
+        let factor = 1.0 - rate.clamp(0.0, 1.0);
+        for entry in &mut self.entries {
+            entry.importance *= factor;
+        }
+    }
+
+    /// Forget entries less important than `min_importance`, returning how
+    /// many were forgotten
+    pub fn prune(&mut self, min_importance: f32) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| entry.importance >= min_importance);
+        before - self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Drop the least important entry, the oldest among equally important
+    /// ones
+    fn evict(&mut self) {
+        let least = self
+            .entries
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.importance.total_cmp(&b.importance).then(a.id.cmp(&b.id)))
+            .map(|(index, _)| index);
+        if let Some(index) = least {
+            self.entries.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pruning_keeps_important_entries() {
+        let mut memory = AgentMemory::new(3);
+        let alert = memory.observe(json!("price crashed"), 0.9, &["alert"]);
+        memory.observe(json!(1), 0.1, &[]);
+        memory.observe(json!(2), 0.1, &[]);
+        memory.observe(json!(3), 0.1, &[]);
+
+        // The oldest routine entry was evicted, not the alert
+        assert_eq!(memory.len(), 3);
+        assert!(memory.get(alert).is_some());
+        let recent: Vec<&Value> = memory.recent(2).iter().map(|entry| &entry.content).collect();
+        assert_eq!(recent, vec![&json!(3), &json!(2)]);
+
+        memory.set_capacity(1);
+        assert_eq!(memory.recent(5).len(), 1);
+        assert!(memory.get(alert).is_some());
+
+        memory.decay(0.5);
+        assert_eq!(memory.get(alert).unwrap().importance, 0.45);
+        assert_eq!(memory.prune(0.5), 1);
+        assert!(memory.is_empty());
+    }
+
+    #[test]
+    fn test_recall() {
+        let mut memory = AgentMemory::new(10);
+        memory.observe(json!({ "price": 20.0 }), 0.3, &["SOL/USDC"]);
+        memory.record_decision(json!("buy"), 0.8, &["SOL/USDC"]);
+        memory.observe(json!({ "price": 19.0 }), 0.6, &["SOL/USDC"]);
+        memory.observe(json!({ "price": 1.0 }), 0.6, &["BONK/USDC"]);
+
+        let observations = memory.recall(&MemoryQuery::default().kind(MemoryKind::Observation).tag("SOL/USDC"));
+        let prices: Vec<&Value> = observations.iter().map(|entry| &entry.content["price"]).collect();
+        assert_eq!(prices, vec![&json!(19.0), &json!(20.0)]);
+
+        let important = memory.recall(&MemoryQuery::default().min_importance(0.5).limit(2));
+        assert_eq!(important[0].kind, MemoryKind::Decision);
+        // Latest first among equally important entries
+        assert_eq!(important[1].content, json!({ "price": 1.0 }));
+    }
+}
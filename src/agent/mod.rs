@@ -1,14 +1,65 @@
 pub mod base;
 pub mod trading;
-pub mod analysis;
 pub mod state;
 pub mod capabilities;
+pub mod rollout;
+pub mod fleet;
+pub mod autoscale;
+pub mod fee_guard;
+pub mod rotation;
+pub mod dca;
+pub mod arbitrage;
+pub mod orders;
+pub mod slippage;
+pub mod rebalance;
+pub mod data_sync;
+pub mod error;
+pub mod autonomous_agent;
+pub mod runtime;
+pub mod bus;
+pub mod lifecycle;
+pub mod memory;
+pub mod checkpoint;
+pub mod supervisor;
+pub mod orchestrator;
+pub mod backtest;
+pub mod risk;
+pub mod portfolio;
+pub mod rules;
+pub mod template;
+pub mod budget;
+pub mod audit;
 
-pub use base::Agent;
-pub use trading::TradingAgent;
-pub use analysis::AnalysisAgent;
+pub use base::{Agent, AgentConfig, AgentMetrics, Capabilities};
+pub use trading::{ExecutionMode, OnChainExecutor, OrderRouter, SimulatedFill, Strategy, TradingAgent};
 pub use state::AgentState;
-pub use capabilities::AgentCapabilities;
+pub use capabilities::{ActionPlan, AgentCapabilities, Capability, CapabilityKind, CapabilityRegistry, PlannedStep};
+pub use rollout::{GroupMetrics, RolloutConfig, RolloutController, RolloutState, RolloutTarget};
+pub use fleet::{FleetManager, FleetReport};
+pub use autoscale::{AutoscaleConfig, Autoscaler, LatencySource, LoadSignals};
+pub use fee_guard::{FeeEstimate, FeeGuard, FeeLimits, FeeRefusal};
+pub use rotation::{AuthorityRotation, RotationReport};
+pub use dca::{DcaConfig, DcaProgress, DcaStrategy};
+pub use arbitrage::{ArbitrageConfig, ArbitrageDetector, ArbitrageSignal, VenueQuote};
+pub use orders::{Order, OrderManager, OrderRequest, OrderStatus};
+pub use slippage::{SlippageGuard, SlippageLimits, SwapQuote, SwapRouter, SwapVenue};
+pub use rebalance::{RebalanceConfig, RebalanceStrategy};
+pub use data_sync::{CandleProvider, CandleStore, DataSync, SyncConfig, SyncReport};
+pub use autonomous_agent::{AutonomousAgent, AutonomousConfig};
+pub use runtime::{AgentRuntime, RunState, RunStats, Schedule, ScheduledAgent};
+pub use bus::{Delivery, Mailbox, Message, MessageBus, Recipient};
+pub use lifecycle::AgentLifecycle;
+pub use memory::{AgentMemory, MemoryEntry, MemoryKind, MemoryQuery};
+pub use checkpoint::{AgentCheckpoint, CheckpointConfig, Checkpointer};
+pub use supervisor::{Escalation, SupervisionPolicy};
+pub use orchestrator::{Orchestrator, PipelineReport, StepOutcome};
+pub use backtest::{BacktestConfig, BacktestReport, Backtester, Candle, FillModel};
+pub use risk::{RiskLimits, RiskManager, RiskViolation};
+pub use portfolio::{AssetSource, Holding, Portfolio};
+pub use rules::{RuleStrategy, StrategyConfig, StrategyConfigError};
+pub use template::{AgentTemplate, SpawnedAgent};
+pub use budget::{BudgetTracker, BudgetWarning, ExecutionBudget};
+pub use audit::{AuditConfig, AuditLog, AuditQuery, DecisionOutcome, DecisionRecord};
 
 pub trait AgentBehavior {
     fn process_data(&self) -> Result<(), Box<dyn std::error::Error>>;
@@ -0,0 +1,537 @@
+//! Pipelines of agents with dependencies between them
+//!
+//! This module provides:
+//! - `Orchestrator`, holding agents and the dependencies between them
+//! - Cycle detection when dependencies are added
+//! - Pipeline runs: one cycle of every agent, in topological order
+//! - Failure propagation: agents depending on a failed agent, directly or
+//!   not, are skipped for the run
+//!
+//! An orchestrator is itself a `ScheduledAgent`, so the pipeline is run on
+//! a schedule by registering it with an `AgentRuntime`. Its lifecycle hooks
+//! are passed on to every agent: `on_start` and `on_resume` in topological
+//! order, `on_pause` and `on_shutdown` in reverse.
+//!
+//! The agents keep their own names on the runtime's bus: each gets a
+//! mailbox under its name, drained before its cycle, so messages sent to
+//! it or published on the topics it subscribed to reach it. Messages sent
+//! to the orchestrator itself are passed on to every agent. Its checkpoint
+//! holds the agents' checkpoints keyed by their names.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use super::bus::{Mailbox, Message, MessageBus, Recipient};
+use super::error::{AgentError, AgentResult};
+use super::lifecycle::AgentLifecycle;
+use super::runtime::ScheduledAgent;
+
+/// How an agent fared in a pipeline run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StepOutcome {
+    Completed { actions: u32 },
+    Failed { error: String },
+    /// Not run because this dependency failed or was skipped
+    Skipped { dependency: String },
+}
+
+/// Outcome of one agent in a pipeline run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineStep {
+    pub agent: String,
+    pub outcome: StepOutcome,
+}
+
+/// Outcomes of a pipeline run, in the order the agents were run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PipelineReport {
+    pub steps: Vec<PipelineStep>,
+}
+
+impl PipelineReport {
+    pub fn outcome(&self, agent: &str) -> Option<&StepOutcome> {
+        self.steps.iter().find(|step| step.agent == agent).map(|step| &step.outcome)
+    }
+
+    /// Actions taken by the agents that completed
+    pub fn actions(&self) -> u32 {
+        self.steps
+            .iter()
+            .map(|step| match step.outcome {
+                StepOutcome::Completed { actions } => actions,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Names of the agents that failed
+    pub fn failed(&self) -> Vec<&str> {
+        self.steps
+            .iter()
+            .filter(|step| matches!(step.outcome, StepOutcome::Failed { .. }))
+            .map(|step| step.agent.as_str())
+            .collect()
+    }
+}
+
+/// Agents run as a pipeline, each after the agents it depends on
+pub struct Orchestrator {
+    name: String,
+    agents: HashMap<String, Box<dyn ScheduledAgent>>,
+    /// Dependencies of each agent
+    dependencies: BTreeMap<String, BTreeSet<String>>,
+    /// Mailbox of each agent once connected to a bus
+    mailboxes: HashMap<String, Mailbox>,
+    last_report: Option<PipelineReport>,
+}
+
+impl Orchestrator {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            agents: HashMap::new(),
+            dependencies: BTreeMap::new(),
+            mailboxes: HashMap::new(),
+            last_report: None,
+        }
+    }
+
+    /// Add `agent` to the pipeline; its name must be unique within it
+    pub fn add_agent<A: ScheduledAgent + 'static>(&mut self, agent: A) -> AgentResult<()> {
+        let name = agent.name().to_string();
+        if self.agents.contains_key(&name) {
+            return Err(AgentError::InvalidInput);
+        }
+        self.dependencies.insert(name.clone(), BTreeSet::new());
+        self.agents.insert(name, Box::new(agent));
+        Ok(())
+    }
+
+    /// Run `agent`'s cycles only after `dependency` completed its own
+    ///
+    /// Fails with `InvalidConfiguration` if this would make the agents
+    /// depend on each other in a cycle.
+    pub fn add_dependency(&mut self, agent: &str, dependency: &str) -> AgentResult<()> {
+        if !self.agents.contains_key(agent) || !self.agents.contains_key(dependency) {
+            return Err(AgentError::InvalidInput);
+        }
+        if agent == dependency || self.depends_on(dependency, agent) {
+            return Err(AgentError::InvalidConfiguration);
+        }
+        self.dependencies
+            .entry(agent.to_string())
+            .or_default()
+            .insert(dependency.to_string());
+        Ok(())
+    }
+
+    pub fn dependencies(&self, agent: &str) -> Vec<String> {
+        self.dependencies
+            .get(agent)
+            .map(|dependencies| dependencies.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Agent names, each after its dependencies; alphabetical where
+    /// the dependencies leave a choice
+    pub fn order(&self) -> Vec<String> {
+        let mut remaining: BTreeMap<&str, usize> = self
+            .dependencies
+            .iter()
+            .map(|(agent, dependencies)| (agent.as_str(), dependencies.len()))
+            .collect();
+        let mut ready: BTreeSet<&str> = remaining
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(agent, _)| *agent)
+            .collect();
+
+        let mut order = Vec::with_capacity(remaining.len());
+        while let Some(agent) = ready.pop_first() {
+            remaining.remove(agent);
+            for (dependent, dependencies) in &self.dependencies {
+                if dependencies.contains(agent) {
+                    let count = remaining.get_mut(dependent.as_str()).expect("dependencies are acyclic");
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.insert(dependent);
+                    }
+                }
+            }
+            order.push(agent.to_string());
+        }
+        order
+    }
+
+    /// Outcomes of the latest pipeline run
+    pub fn last_report(&self) -> Option<&PipelineReport> {
+        self.last_report.as_ref()
+    }
+
+    /// Run one cycle of every agent in topological order, taking at most
+    /// `max_actions` actions in total
+    ///
+    /// An agent is skipped if one of its dependencies failed or was
+    /// skipped.
+    pub async fn run_pipeline(&mut self, max_actions: u32) -> PipelineReport {
+        let mut report = PipelineReport::default();
+        let mut budget = max_actions;
+
+        for name in self.order() {
+            let blocked = self.dependencies[&name].iter().find(|dependency| {
+                !matches!(report.outcome(dependency), Some(StepOutcome::Completed { .. }))
+            });
+            let outcome = match blocked {
+                Some(dependency) => StepOutcome::Skipped {
+                    dependency: dependency.clone(),
+                },
+                None => {
+                    let agent = self.agents.get_mut(&name).expect("every ordered agent is held");
+                    if let Some(mailbox) = self.mailboxes.get_mut(&name) {
+                        // A message the agent fails to handle doesn't fail its cycle
+                        while let Some(message) = mailbox.try_recv() {
+                            let _ = deliver(&self.name, &name, agent.as_mut(), message).await;
+                        }
+                    }
+                    let agent_budget = agent.max_actions_per_cycle().min(budget);
+                    match agent.run_cycle(agent_budget).await {
+                        Ok(actions) => {
+                            let actions = actions.min(agent_budget);
+                            budget -= actions;
+                            StepOutcome::Completed { actions }
+                        }
+                        Err(e) => {
+                            println!("Agent {} failed in pipeline {}: {}", name, self.name, e);
+                            agent.on_error(&e).await;
+                            StepOutcome::Failed { error: e.to_string() }
+                        }
+                    }
+                }
+            };
+            report.steps.push(PipelineStep { agent: name, outcome });
+        }
+
+        self.last_report = Some(report.clone());
+        report
+    }
+
+    /// Whether `agent` depends on `other`, directly or not
+    fn depends_on(&self, agent: &str, other: &str) -> bool {
+        let mut pending = vec![agent];
+        let mut seen = BTreeSet::new();
+        while let Some(current) = pending.pop() {
+            if !seen.insert(current) {
+                continue;
+            }
+            if let Some(dependencies) = self.dependencies.get(current) {
+                if dependencies.contains(other) {
+                    return true;
+                }
+                pending.extend(dependencies.iter().map(String::as_str));
+            }
+        }
+        false
+    }
+
+    /// Call `hook` on every agent, returning the first error
+    async fn for_each_agent(&mut self, hook: Hook) -> AgentResult<()> {
+        let mut order = self.order();
+        if matches!(hook, Hook::Pause | Hook::Shutdown) {
+            order.reverse();
+        }
+
+        let mut result = Ok(());
+        for name in order {
+            let Some(agent) = self.agents.get_mut(&name) else {
+                continue;
+            };
+            let hook_result = match hook {
+                Hook::Start => agent.on_start().await,
+                Hook::Pause => agent.on_pause().await,
+                Hook::Resume => agent.on_resume().await,
+                Hook::Shutdown => agent.on_shutdown().await,
+            };
+            if let Err(e) = hook_result {
+                println!("Agent {} failed its {:?} hook in pipeline {}: {}", name, hook, self.name, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+/// Pass `message` to `agent` of pipeline `pipeline`, logging and passing
+/// on its failure to handle it
+async fn deliver(pipeline: &str, name: &str, agent: &mut dyn ScheduledAgent, message: Message) -> AgentResult<()> {
+    let from = message.from.clone();
+    let result = agent.on_message(message).await;
+    if let Err(e) = &result {
+        println!("Agent {} failed handling a message from {} in pipeline {}: {}", name, from, pipeline, e);
+        agent.on_error(e).await;
+    }
+    result
+}
+
+/// Lifecycle hook passed on to the agents of a pipeline
+#[derive(Debug, Clone, Copy)]
+enum Hook {
+    Start,
+    Pause,
+    Resume,
+    Shutdown,
+}
+
+#[async_trait::async_trait]
+impl AgentLifecycle for Orchestrator {
+    async fn on_start(&mut self) -> AgentResult<()> {
+        self.for_each_agent(Hook::Start).await
+    }
+
+    async fn on_pause(&mut self) -> AgentResult<()> {
+        self.for_each_agent(Hook::Pause).await
+    }
+
+    async fn on_resume(&mut self) -> AgentResult<()> {
+        self.for_each_agent(Hook::Resume).await
+    }
+
+    async fn on_shutdown(&mut self) -> AgentResult<()> {
+        self.for_each_agent(Hook::Shutdown).await
+    }
+}
+
+#[async_trait::async_trait]
+impl ScheduledAgent for Orchestrator {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Actions of every agent's full cycle
+    fn max_actions_per_cycle(&self) -> u32 {
+        self.agents
+            .values()
+            .map(|agent| agent.max_actions_per_cycle())
+            .fold(0, u32::saturating_add)
+    }
+
+    /// Run the pipeline, failing if any agent failed
+    async fn run_cycle(&mut self, max_actions: u32) -> AgentResult<u32> {
+        let report = self.run_pipeline(max_actions).await;
+        let failed = report.failed();
+        if !failed.is_empty() {
+            return Err(AgentError::Custom(format!("agents failed: {}", failed.join(", "))));
+        }
+        Ok(report.actions())
+    }
+
+    /// Open a mailbox for every agent under its name and connect it to the
+    /// bus; an agent whose name is taken on the bus only receives the
+    /// messages sent to the orchestrator
+    fn connect(&mut self, bus: MessageBus) {
+        for (name, agent) in &mut self.agents {
+            match bus.mailbox(name) {
+                Ok(mailbox) => {
+                    self.mailboxes.insert(name.clone(), mailbox);
+                }
+                Err(e) => println!("Agent {} of pipeline {} has no mailbox: {}", name, self.name, e),
+            }
+            agent.connect(bus.clone());
+        }
+    }
+
+    /// Pass the message on to every agent, returning the first error;
+    /// broadcasts already reached the agents with a mailbox
+    async fn on_message(&mut self, message: Message) -> AgentResult<()> {
+        let mut result = Ok(());
+        for name in self.order() {
+            if message.recipient == Recipient::All && self.mailboxes.contains_key(&name) {
+                continue;
+            }
+            let Some(agent) = self.agents.get_mut(&name) else {
+                continue;
+            };
+            result = result.and(deliver(&self.name, &name, agent.as_mut(), message.clone()).await);
+        }
+        result
+    }
+
+    /// Checkpoints of the agents keeping state, keyed by their names
+    fn checkpoint(&self) -> AgentResult<Option<Value>> {
+        let mut states = Map::new();
+        for (name, agent) in &self.agents {
+            if let Some(state) = agent.checkpoint()? {
+                states.insert(name.clone(), state);
+            }
+        }
+        Ok((!states.is_empty()).then_some(Value::Object(states)))
+    }
+
+    /// Restore every agent from its checkpoint under its name, returning
+    /// the first error
+    fn restore(&mut self, state: Value) -> AgentResult<()> {
+        let Value::Object(states) = state else {
+            return Err(AgentError::ValidationError);
+        };
+        let mut result = Ok(());
+        for (name, state) in states {
+            let Some(agent) = self.agents.get_mut(&name) else {
+                println!("Checkpoint of pipeline {} has state for unknown agent {}", self.name, name);
+                continue;
+            };
+            if let Err(e) = agent.restore(state) {
+                println!("Agent {} failed restoring its checkpoint in pipeline {}: {}", name, self.name, e);
+                result = result.and(Err(e));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records when it ran and what it received in a shared log
+    struct Stage {
+        name: String,
+        fail: bool,
+        cycles: u64,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AgentLifecycle for Stage {
+        async fn on_shutdown(&mut self) -> AgentResult<()> {
+            self.log.lock().unwrap().push(format!("shutdown {}", self.name));
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ScheduledAgent for Stage {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn max_actions_per_cycle(&self) -> u32 {
+            2
+        }
+
+        async fn run_cycle(&mut self, max_actions: u32) -> AgentResult<u32> {
+            self.log.lock().unwrap().push(self.name.clone());
+            if self.fail {
+                return Err(AgentError::ProcessingError);
+            }
+            self.cycles += 1;
+            Ok(max_actions)
+        }
+
+        fn connect(&mut self, bus: MessageBus) {
+            if self.name == "trading" {
+                bus.subscribe("trading", "signals");
+            }
+        }
+
+        async fn on_message(&mut self, message: Message) -> AgentResult<()> {
+            let payload = message.decode::<String>()?;
+            self.log.lock().unwrap().push(format!("{} got {}", self.name, payload));
+            Ok(())
+        }
+
+        fn checkpoint(&self) -> AgentResult<Option<Value>> {
+            Ok((self.cycles > 0).then(|| Value::from(self.cycles)))
+        }
+
+        fn restore(&mut self, state: Value) -> AgentResult<()> {
+            self.cycles = state.as_u64().ok_or(AgentError::ValidationError)?;
+            Ok(())
+        }
+    }
+
+    /// analysis -> signals -> trading, and analysis -> reporting
+    fn pipeline(fail: &str) -> (Orchestrator, Arc<Mutex<Vec<String>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut orchestrator = Orchestrator::new("pipeline");
+        for name in ["trading", "signals", "analysis", "reporting"] {
+            let stage = Stage { name: name.to_string(), fail: name == fail, cycles: 0, log: log.clone() };
+            orchestrator.add_agent(stage).unwrap();
+        }
+        orchestrator.add_dependency("signals", "analysis").unwrap();
+        orchestrator.add_dependency("trading", "signals").unwrap();
+        orchestrator.add_dependency("reporting", "analysis").unwrap();
+        (orchestrator, log)
+    }
+
+    #[test]
+    fn test_dependency_graph() {
+        let (mut orchestrator, _) = pipeline("");
+        assert_eq!(orchestrator.order(), vec!["analysis", "reporting", "signals", "trading"]);
+        assert_eq!(orchestrator.dependencies("trading"), vec!["signals"]);
+
+        assert_eq!(orchestrator.add_dependency("analysis", "trading"), Err(AgentError::InvalidConfiguration));
+        assert_eq!(orchestrator.add_dependency("trading", "trading"), Err(AgentError::InvalidConfiguration));
+        assert_eq!(orchestrator.add_dependency("trading", "unknown"), Err(AgentError::InvalidInput));
+    }
+
+    #[tokio::test]
+    async fn test_failures_propagate_downstream() {
+        let (mut orchestrator, log) = pipeline("signals");
+        let report = orchestrator.run_pipeline(5).await;
+
+        assert_eq!(*log.lock().unwrap(), vec!["analysis", "reporting", "signals"]);
+        assert_eq!(report.outcome("analysis"), Some(&StepOutcome::Completed { actions: 2 }));
+        assert_eq!(report.outcome("reporting"), Some(&StepOutcome::Completed { actions: 2 }));
+        assert_eq!(
+            report.outcome("trading"),
+            Some(&StepOutcome::Skipped { dependency: "signals".to_string() })
+        );
+        assert_eq!(report.failed(), vec!["signals"]);
+        assert_eq!(orchestrator.run_cycle(5).await, Err(AgentError::Custom("agents failed: signals".to_string())));
+
+        log.lock().unwrap().clear();
+        orchestrator.on_shutdown().await.unwrap();
+        let shutdowns = log.lock().unwrap().clone();
+        assert_eq!(shutdowns, vec!["shutdown trading", "shutdown signals", "shutdown reporting", "shutdown analysis"]);
+    }
+
+    #[tokio::test]
+    async fn test_messages_reach_agents_by_name() {
+        let bus = MessageBus::new();
+        let (mut orchestrator, log) = pipeline("");
+        orchestrator.connect(bus.clone());
+
+        bus.publish("analysis", "signals", &"buy").unwrap();
+        bus.send("monitor", "reporting", &"flush").unwrap();
+        orchestrator.on_message(Message {
+            from: "monitor".to_string(),
+            recipient: Recipient::Agent("pipeline".to_string()),
+            payload: Value::from("halt"),
+        })
+        .await
+        .unwrap();
+        assert_eq!(log.lock().unwrap().len(), 4);
+
+        log.lock().unwrap().clear();
+        orchestrator.run_pipeline(10).await;
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["analysis", "reporting got flush", "reporting", "signals", "trading got buy", "trading"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_keyed_by_agent() {
+        let (mut orchestrator, _) = pipeline("");
+        assert_eq!(orchestrator.checkpoint(), Ok(None));
+        orchestrator.run_pipeline(10).await;
+        orchestrator.run_pipeline(10).await;
+        let state = orchestrator.checkpoint().unwrap().unwrap();
+        assert_eq!(state["trading"], Value::from(2));
+
+        let (mut restored, _) = pipeline("");
+        restored.restore(state.clone()).unwrap();
+        assert_eq!(restored.checkpoint(), Ok(Some(state)));
+        assert_eq!(restored.restore(Value::from(1)), Err(AgentError::ValidationError));
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use super::error::{AgentError, AgentResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderSide {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Created,
+    Submitted,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    Rejected,
+}
+
+impl OrderStatus {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, OrderStatus::Filled | OrderStatus::Cancelled | OrderStatus::Rejected)
+    }
+
+    pub fn can_transition_to(&self, next: OrderStatus) -> bool {
+        matches!(
+            (self, next),
+            (OrderStatus::Created, OrderStatus::Submitted | OrderStatus::Cancelled | OrderStatus::Rejected)
+                | (OrderStatus::Submitted | OrderStatus::PartiallyFilled, OrderStatus::PartiallyFilled)
+                | (OrderStatus::Submitted | OrderStatus::PartiallyFilled, OrderStatus::Filled)
+                | (OrderStatus::Submitted | OrderStatus::PartiallyFilled, OrderStatus::Cancelled)
+                | (OrderStatus::Submitted, OrderStatus::Rejected)
+        )
+    }
+}
+
+/// Order as requested by a strategy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderRequest {
+    /// Caller-chosen id; submitting the same id twice is a no-op
+    pub client_order_id: String,
+    pub agent: String,
+    pub strategy: String,
+    pub market: String,
+    pub side: OrderSide,
+    /// Quantity in base units
+    pub quantity: u64,
+    pub limit_price: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Order {
+    pub request: OrderRequest,
+    pub status: OrderStatus,
+    pub filled_quantity: u64,
+    pub average_fill_price: Option<f64>,
+    /// Reference returned by the executor, e.g. a transaction signature
+    pub submission_id: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Order {
+    fn new(request: OrderRequest) -> Self {
+        let now = now();
+        Self {
+            request,
+            status: OrderStatus::Created,
+            filled_quantity: 0,
+            average_fill_price: None,
+            submission_id: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn remaining_quantity(&self) -> u64 {
+        self.request.quantity.saturating_sub(self.filled_quantity)
+    }
+
+    fn transition(&mut self, next: OrderStatus) -> AgentResult<()> {
+        if !self.status.can_transition_to(next) {
+            return Err(AgentError::InvalidStateTransition);
+        }
+        self.status = next;
+        self.updated_at = now();
+        Ok(())
+    }
+}
+
+/// Outcome of an order as observed on-chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ExecutionOutcome {
+    Fill { quantity: u64, price: f64 },
+    Cancelled,
+    Failed(String),
+}
+
+/// Execution result used to reconcile an order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionReport {
+    pub client_order_id: String,
+    pub submission_id: Option<String>,
+    pub outcome: ExecutionOutcome,
+}
+
+/// Execution venue orders are submitted to
+#[async_trait::async_trait]
+pub trait OrderExecutor: Send + Sync {
+    /// Submit an order, returning a submission reference
+    async fn submit(&self, order: &Order) -> AgentResult<String>;
+
+    /// Cancel a previously submitted order
+    async fn cancel(&self, order: &Order) -> AgentResult<()>;
+}
+
+/// Order management system sitting between strategies and execution
+#[derive(Debug, Default)]
+pub struct OrderManager {
+    orders: HashMap<String, Order>,
+}
+
+impl OrderManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an order, returning the existing one if the id is already known
+    pub fn create(&mut self, request: OrderRequest) -> AgentResult<&Order> {
+        if request.quantity == 0 || request.client_order_id.is_empty() {
+            return Err(AgentError::InvalidInput);
+        }
+
+        Ok(self
+            .orders
+            .entry(request.client_order_id.clone())
+            .or_insert_with(|| Order::new(request)))
+    }
+
+    /// Submit a created order; orders already submitted are returned unchanged
+    pub async fn submit(
+        &mut self,
+        client_order_id: &str,
+        executor: &dyn OrderExecutor,
+    ) -> AgentResult<Order> {
+        let order = self
+            .orders
+            .get_mut(client_order_id)
+            .ok_or(AgentError::InvalidInput)?;
+
+        if order.status != OrderStatus::Created {
+            return Ok(order.clone());
+        }
+
+        match executor.submit(order).await {
+            Ok(submission_id) => {
+                order.submission_id = Some(submission_id);
+                order.transition(OrderStatus::Submitted)?;
+            }
+            Err(e) => {
+                println!("Order {} rejected by executor: {:?}", client_order_id, e);
+                order.transition(OrderStatus::Rejected)?;
+            }
+        }
+
+        Ok(order.clone())
+    }
+
+    /// Reject a created order before it is submitted, e.g. for breaching a
+    /// risk limit
+    pub fn reject(&mut self, client_order_id: &str) -> AgentResult<Order> {
+        let order = self
+            .orders
+            .get_mut(client_order_id)
+            .ok_or(AgentError::InvalidInput)?;
+        order.transition(OrderStatus::Rejected)?;
+        Ok(order.clone())
+    }
+
+    /// Cancel an open order
+    pub async fn cancel(
+        &mut self,
+        client_order_id: &str,
+        executor: &dyn OrderExecutor,
+    ) -> AgentResult<Order> {
+        let order = self
+            .orders
+            .get_mut(client_order_id)
+            .ok_or(AgentError::InvalidInput)?;
+
+        if order.status.is_terminal() {
+            return Ok(order.clone());
+        }
+
+        if order.status != OrderStatus::Created {
+            executor.cancel(order).await?;
+        }
+        order.transition(OrderStatus::Cancelled)?;
+
+        Ok(order.clone())
+    }
+
+    /// Apply an execution report to the matching order
+    pub fn reconcile(&mut self, report: ExecutionReport) -> AgentResult<&Order> {
+        let order = self
+            .orders
+            .get_mut(&report.client_order_id)
+            .ok_or(AgentError::InvalidInput)?;
+
+        if let (Some(expected), Some(actual)) = (&order.submission_id, &report.submission_id) {
+            if expected != actual {
+                return Err(AgentError::ValidationError);
+            }
+        }
+
+        match report.outcome {
+            ExecutionOutcome::Fill { quantity, price } => {
+                let quantity = quantity.min(order.remaining_quantity());
+                let next = if quantity == order.remaining_quantity() {
+                    OrderStatus::Filled
+                } else {
+                    OrderStatus::PartiallyFilled
+                };
+                // Fills of orders that can't take them, e.g. cancelled ones,
+                // leave the order untouched
+                order.transition(next)?;
+
+                let previous = order.filled_quantity as f64 * order.average_fill_price.unwrap_or(0.0);
+                order.filled_quantity += quantity;
+                order.average_fill_price = Some(
+                    (previous + quantity as f64 * price) / order.filled_quantity.max(1) as f64,
+                );
+            }
+            ExecutionOutcome::Cancelled => order.transition(OrderStatus::Cancelled)?,
+            ExecutionOutcome::Failed(reason) => {
+                println!("Order {} failed on-chain: {}", report.client_order_id, reason);
+                order.transition(OrderStatus::Rejected)?;
+            }
+        }
+
+        Ok(order)
+    }
+
+    pub fn get(&self, client_order_id: &str) -> Option<&Order> {
+        self.orders.get(client_order_id)
+    }
+
+    pub fn by_strategy(&self, strategy: &str) -> Vec<&Order> {
+        self.query(|order| order.request.strategy == strategy)
+    }
+
+    pub fn by_agent(&self, agent: &str) -> Vec<&Order> {
+        self.query(|order| order.request.agent == agent)
+    }
+
+    pub fn open_orders(&self) -> Vec<&Order> {
+        self.query(|order| !order.status.is_terminal())
+    }
+
+    fn query(&self, predicate: impl Fn(&Order) -> bool) -> Vec<&Order> {
+        let mut orders: Vec<&Order> = self.orders.values().filter(|order| predicate(order)).collect();
+        orders.sort_by(|a, b| {
+            a.created_at
+                .cmp(&b.created_at)
+                .then_with(|| a.request.client_order_id.cmp(&b.request.client_order_id))
+        });
+        orders
+    }
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Orders shared by the tests of the modules trading on them
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// Request for `quantity` on SOL/USDC by strategy `test` of agent
+    /// `trader`
+    pub fn request(side: OrderSide, quantity: u64, limit_price: Option<f64>) -> OrderRequest {
+        OrderRequest {
+            client_order_id: format!("{:?}-{}", side, quantity),
+            agent: "trader".to_string(),
+            strategy: "test".to_string(),
+            market: "SOL/USDC".to_string(),
+            side,
+            quantity,
+            limit_price,
+        }
+    }
+
+    /// Order for `request` as `OrderManager::create` registers it
+    pub fn order(request: OrderRequest) -> Order {
+        Order::new(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct MockExecutor {
+        submissions: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl OrderExecutor for MockExecutor {
+        async fn submit(&self, _order: &Order) -> AgentResult<String> {
+            let n = self.submissions.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("sig-{}", n))
+        }
+
+        async fn cancel(&self, _order: &Order) -> AgentResult<()> {
+            Ok(())
+        }
+    }
+
+    fn request(id: &str, strategy: &str) -> OrderRequest {
+        OrderRequest {
+            client_order_id: id.to_string(),
+            strategy: strategy.to_string(),
+            ..fixtures::request(OrderSide::Buy, 100, Some(20.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_submission() {
+        let executor = MockExecutor { submissions: AtomicU32::new(0) };
+        let mut oms = OrderManager::new();
+
+        oms.create(request("order-1", "dca")).unwrap();
+        oms.create(request("order-1", "dca")).unwrap();
+
+        let first = oms.submit("order-1", &executor).await.unwrap();
+        let second = oms.submit("order-1", &executor).await.unwrap();
+
+        assert_eq!(first.status, OrderStatus::Submitted);
+        assert_eq!(first.submission_id, second.submission_id);
+        assert_eq!(executor.submissions.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_fills() {
+        let executor = MockExecutor { submissions: AtomicU32::new(0) };
+        let mut oms = OrderManager::new();
+        oms.create(request("order-1", "dca")).unwrap();
+        oms.submit("order-1", &executor).await.unwrap();
+
+        let partial = oms.reconcile(ExecutionReport {
+            client_order_id: "order-1".to_string(),
+            submission_id: Some("sig-0".to_string()),
+            outcome: ExecutionOutcome::Fill { quantity: 40, price: 19.5 },
+        }).unwrap();
+        assert_eq!(partial.status, OrderStatus::PartiallyFilled);
+        assert_eq!(partial.remaining_quantity(), 60);
+
+        let filled = oms.reconcile(ExecutionReport {
+            client_order_id: "order-1".to_string(),
+            submission_id: Some("sig-0".to_string()),
+            outcome: ExecutionOutcome::Fill { quantity: 60, price: 20.0 },
+        }).unwrap();
+        assert_eq!(filled.status, OrderStatus::Filled);
+        assert!(oms.open_orders().is_empty());
+
+        let mismatched = oms.reconcile(ExecutionReport {
+            client_order_id: "order-1".to_string(),
+            submission_id: Some("sig-9".to_string()),
+            outcome: ExecutionOutcome::Cancelled,
+        });
+        assert_eq!(mismatched.unwrap_err(), AgentError::ValidationError);
+
+        // A fill reported after a cancel is refused without touching the order
+        oms.create(request("order-2", "dca")).unwrap();
+        oms.submit("order-2", &executor).await.unwrap();
+        oms.cancel("order-2", &executor).await.unwrap();
+        let late = oms.reconcile(ExecutionReport {
+            client_order_id: "order-2".to_string(),
+            submission_id: Some("sig-1".to_string()),
+            outcome: ExecutionOutcome::Fill { quantity: 40, price: 19.5 },
+        });
+        assert_eq!(late.unwrap_err(), AgentError::InvalidStateTransition);
+        let cancelled = oms.get("order-2").unwrap();
+        assert_eq!(cancelled.filled_quantity, 0);
+        assert_eq!(cancelled.average_fill_price, None);
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut oms = OrderManager::new();
+        oms.create(request("order-1", "dca")).unwrap();
+        oms.create(request("order-2", "rebalance")).unwrap();
+
+        assert_eq!(oms.by_strategy("dca").len(), 1);
+        assert_eq!(oms.by_agent("trader").len(), 2);
+        assert_eq!(oms.open_orders().len(), 2);
+    }
+}
@@ -0,0 +1,296 @@
+//! Portfolio tracking for trading agents
+//!
+//! This module provides:
+//! - `Portfolio`, an agent's holdings of SOL and SPL tokens with their
+//!   average cost, realized and unrealized PnL
+//! - Updates from fills, marks from market prices, and reconciliation with
+//!   balances read on-chain
+//!
+//! Markets are named `BASE/QUOTE`, e.g. `SOL/USDC`. A fill moves the base
+//! asset at its price, realizing PnL at average cost when it reduces the
+//! holding, and moves the quote asset by the fill's notional. Quantities
+//! are in base units (lamports for SOL), prices in quote base units per
+//! base unit, and PnL in quote base units.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use solana_account_decoder::UiAccountData;
+use solana_client::{rpc_client::RpcClient, rpc_request::TokenAccountsFilter};
+use solana_sdk::pubkey::Pubkey;
+use crate::solana::client::{ClientError, ClientResult};
+use super::orders::{Order, OrderSide};
+use super::trading::Fill;
+
+/// Symbol of native SOL
+pub const SOL: &str = "SOL";
+
+/// Where the balance of an asset is read on-chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssetSource {
+    /// Lamports of the owner
+    Native,
+    /// Token accounts of the owner for the mint
+    Token(Pubkey),
+}
+
+/// Holding of one asset
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Holding {
+    pub asset: String,
+    /// Signed: negative when short
+    pub quantity: i64,
+    /// Average price paid for the holding, zero when flat
+    pub average_cost: f64,
+    pub realized_pnl: f64,
+    /// Latest market price, if marked
+    pub mark_price: Option<f64>,
+}
+
+impl Holding {
+    /// Total paid for the holding at its average cost
+    pub fn cost_basis(&self) -> f64 {
+        self.quantity as f64 * self.average_cost
+    }
+
+    pub fn market_value(&self) -> Option<f64> {
+        self.mark_price.map(|price| self.quantity as f64 * price)
+    }
+
+    /// PnL of the holding if closed at its mark price
+    pub fn unrealized_pnl(&self) -> Option<f64> {
+        self.mark_price.map(|price| self.quantity as f64 * (price - self.average_cost))
+    }
+
+    /// Add `quantity` at `price`, returning the PnL realized by the part
+    /// that reduces the holding
+    fn apply(&mut self, quantity: i64, price: f64) -> f64 {
+        if self.quantity == 0 || self.quantity.signum() == quantity.signum() {
+            let total = self.quantity.saturating_add(quantity);
+            if total != 0 {
+                self.average_cost = (self.cost_basis() + quantity as f64 * price) / total as f64;
+            }
+            self.quantity = total;
+            return 0.0;
+        }
+
+        let closed = quantity.abs().min(self.quantity.abs());
+        let realized = closed as f64 * (price - self.average_cost) * self.quantity.signum() as f64;
+        self.quantity += quantity;
+        if self.quantity == 0 {
+            self.average_cost = 0.0;
+        } else if self.quantity.signum() == quantity.signum() {
+            // Flipped: the remainder opened a holding at the fill price
+            self.average_cost = price;
+        }
+        self.realized_pnl += realized;
+        realized
+    }
+}
+
+/// Holdings of an agent
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Portfolio {
+    holdings: HashMap<String, Holding>,
+    sources: HashMap<String, AssetSource>,
+}
+
+impl Default for Portfolio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Portfolio {
+    /// Portfolio reading SOL balances on-chain
+    pub fn new() -> Self {
+        Self {
+            holdings: HashMap::new(),
+            sources: HashMap::from([(SOL.to_string(), AssetSource::Native)]),
+        }
+    }
+
+    /// Read the balance of `symbol` from the owner's token accounts for
+    /// `mint`
+    pub fn with_token(mut self, symbol: &str, mint: Pubkey) -> Self {
+        self.sources.insert(symbol.to_string(), AssetSource::Token(mint));
+        self
+    }
+
+    pub fn holding(&self, asset: &str) -> Option<&Holding> {
+        self.holdings.get(asset)
+    }
+
+    /// Holdings by asset symbol
+    pub fn holdings(&self) -> Vec<&Holding> {
+        let mut holdings: Vec<&Holding> = self.holdings.values().collect();
+        holdings.sort_by(|a, b| a.asset.cmp(&b.asset));
+        holdings
+    }
+
+    /// Signed quantity held of `asset`
+    pub fn quantity(&self, asset: &str) -> i64 {
+        self.holdings.get(asset).map_or(0, |holding| holding.quantity)
+    }
+
+    /// Signed quantity held of the base asset of `market`
+    pub fn position(&self, market: &str) -> i64 {
+        self.quantity(base_asset(market))
+    }
+
+    pub fn realized_pnl(&self) -> f64 {
+        self.holdings.values().map(|holding| holding.realized_pnl).sum()
+    }
+
+    /// Unrealized PnL of the marked holdings
+    pub fn unrealized_pnl(&self) -> f64 {
+        self.holdings.values().filter_map(Holding::unrealized_pnl).sum()
+    }
+
+    /// Value of the marked holdings
+    pub fn market_value(&self) -> f64 {
+        self.holdings.values().filter_map(Holding::market_value).sum()
+    }
+
+    /// Apply `fill` of `order`, returning the PnL it realized
+    pub fn apply_fill(&mut self, order: &Order, fill: &Fill) -> f64 {
+        let quantity = signed(order.request.side, fill.quantity);
+        let (base, quote) = split_market(&order.request.market);
+        let realized = self.holding_mut(base).apply(quantity, fill.price);
+        if let Some(quote) = quote {
+            let notional = (quantity as f64 * fill.price).round() as i64;
+            let holding = self.holding_mut(quote);
+            holding.quantity = holding.quantity.saturating_sub(notional);
+        }
+        realized
+    }
+
+    /// Mark the base asset of `market` at `price`
+    pub fn mark(&mut self, market: &str, price: f64) {
+        self.holding_mut(base_asset(market)).mark_price = Some(price);
+    }
+
+    /// Set the quantity held of `asset` to `amount` as read on-chain
+    ///
+    /// The average cost is kept, so deposits and withdrawals don't
+    /// realize PnL; a holding opened by a deposit costs its mark price.
+    pub fn set_balance(&mut self, asset: &str, amount: u64) {
+        let holding = self.holding_mut(asset);
+        if holding.quantity == 0 {
+            holding.average_cost = holding.mark_price.unwrap_or(0.0);
+        }
+        holding.quantity = i64::try_from(amount).unwrap_or(i64::MAX);
+        if holding.quantity == 0 {
+            holding.average_cost = 0.0;
+        }
+    }
+
+    /// Read the balances of the tracked assets held by `owner`
+    pub fn read_balances(&self, rpc: &RpcClient, owner: &Pubkey) -> ClientResult<HashMap<String, u64>> {
+        let mut balances = HashMap::new();
+        for (asset, source) in &self.sources {
+            let amount = match source {
+                AssetSource::Native => rpc.get_balance(owner)?,
+                AssetSource::Token(mint) => token_balance(rpc, owner, mint)?,
+            };
+            balances.insert(asset.clone(), amount);
+        }
+        Ok(balances)
+    }
+
+    /// Reconcile the holdings with the balances of `owner` on-chain
+    pub fn sync(&mut self, rpc: &RpcClient, owner: &Pubkey) -> ClientResult<()> {
+        for (asset, amount) in self.read_balances(rpc, owner)? {
+            self.set_balance(&asset, amount);
+        }
+        Ok(())
+    }
+
+    fn holding_mut(&mut self, asset: &str) -> &mut Holding {
+        self.holdings.entry(asset.to_string()).or_insert_with(|| Holding {
+            asset: asset.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
+/// `quantity` signed by `side`: negative when selling
+pub(crate) fn signed(side: OrderSide, quantity: u64) -> i64 {
+    let quantity = i64::try_from(quantity).unwrap_or(i64::MAX);
+    match side {
+        OrderSide::Buy => quantity,
+        OrderSide::Sell => -quantity,
+    }
+}
+
+/// Base and quote asset of `market`
+fn split_market(market: &str) -> (&str, Option<&str>) {
+    match market.split_once('/') {
+        Some((base, quote)) => (base, Some(quote)),
+        None => (market, None),
+    }
+}
+
+fn base_asset(market: &str) -> &str {
+    split_market(market).0
+}
+
+/// Amount of `mint` held across the token accounts of `owner`
+fn token_balance(rpc: &RpcClient, owner: &Pubkey, mint: &Pubkey) -> ClientResult<u64> {
+    let mut total = 0u64;
+    for keyed in rpc.get_token_accounts_by_owner(owner, TokenAccountsFilter::Mint(*mint))? {
+        let amount = match &keyed.account.data {
+            UiAccountData::Json(account) => account.parsed["info"]["tokenAmount"]["amount"]
+                .as_str()
+                .and_then(|amount| amount.parse::<u64>().ok()),
+            _ => None,
+        };
+        let address = keyed.pubkey.parse().unwrap_or_default();
+        total = total.saturating_add(amount.ok_or(ClientError::InvalidAccountData(address))?);
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::orders::fixtures::{order, request};
+
+    #[test]
+    fn test_fills_and_pnl() {
+        let mut portfolio = Portfolio::new();
+        portfolio.set_balance("USDC", 10_000);
+
+        portfolio.apply_fill(&order(request(OrderSide::Buy, 10, None)), &Fill { quantity: 10, price: 20.0 });
+        portfolio.apply_fill(&order(request(OrderSide::Buy, 10, None)), &Fill { quantity: 10, price: 30.0 });
+        let sell = order(request(OrderSide::Sell, 5, None));
+        let realized = portfolio.apply_fill(&sell, &Fill { quantity: 5, price: 35.0 });
+        assert_eq!(realized, 50.0);
+
+        let sol = portfolio.holding(SOL).unwrap();
+        assert_eq!((sol.quantity, sol.average_cost, sol.cost_basis()), (15, 25.0, 375.0));
+        assert_eq!(portfolio.quantity("USDC"), 10_000 - 200 - 300 + 175);
+        assert_eq!(portfolio.position("SOL/USDC"), 15);
+
+        portfolio.mark("SOL/USDC", 27.0);
+        assert_eq!(portfolio.unrealized_pnl(), 30.0);
+        assert_eq!(portfolio.realized_pnl(), 50.0);
+        assert_eq!(portfolio.market_value(), 405.0);
+    }
+
+    #[test]
+    fn test_balances_keep_cost_basis() {
+        let mut portfolio = Portfolio::new();
+        portfolio.apply_fill(&order(request(OrderSide::Buy, 10, None)), &Fill { quantity: 10, price: 20.0 });
+
+        // A withdrawal read on-chain doesn't realize PnL
+        portfolio.set_balance(SOL, 4);
+        let sol = portfolio.holding(SOL).unwrap();
+        assert_eq!((sol.quantity, sol.average_cost, sol.realized_pnl), (4, 20.0, 0.0));
+
+        // A deposit opening a holding costs its mark price
+        portfolio.mark("BONK/USDC", 0.5);
+        portfolio.set_balance("BONK", 1_000);
+        assert_eq!(portfolio.holding("BONK").unwrap().cost_basis(), 500.0);
+        assert_eq!(portfolio.holdings().len(), 3);
+    }
+}
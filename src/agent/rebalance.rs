@@ -0,0 +1,435 @@
+//! Portfolio rebalancing strategy
+//!
+//! This module provides:
+//! - `RebalanceConfig`: target weights of a basket of tokens valued in a
+//!   quote token, and the drift tolerated before rebalancing
+//! - `RebalanceStrategy`, the `Strategy` trading the basket back to its
+//!   targets once any weight drifts past the threshold
+//! - Each rebalance appended to an `AuditLog` once its orders closed
+//!
+//! Each token is traded on its `TOKEN/QUOTE` market; the quote token may
+//! have a target weight of its own, kept as cash. A rebalance places one
+//! market order per token off target, sells first so they fund the buys,
+//! and no other rebalance starts until all of them closed. Run in a
+//! `TradingAgent`, the orders pass its risk checks, and through an
+//! `OnChainExecutor` the fee guard of its agent. An order either of them
+//! refuses is rejected, closing the rebalance as failed; the next tick with
+//! the basket still off target starts a new one.
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use super::audit::{AuditLog, DecisionOutcome, DecisionRecord};
+use super::error::{AgentError, AgentResult};
+use super::orders::{Order, OrderRequest, OrderSide};
+use super::portfolio::{signed, Portfolio};
+use super::trading::{Fill, MarketTick, Strategy};
+
+/// Basket a rebalancing strategy keeps on target
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RebalanceConfig {
+    pub name: String,
+    /// Token the basket is valued and traded in, e.g. `USDC`
+    pub quote: String,
+    /// Target share of the basket's value of each token, summing to 1
+    pub targets: HashMap<String, f64>,
+    /// Largest drift of any weight from its target left alone, in basis
+    /// points of the basket's value
+    pub drift_threshold_bps: f64,
+    /// Smallest order worth placing, in quote units
+    pub min_order_notional: f64,
+}
+
+impl RebalanceConfig {
+    pub fn validate(&self) -> AgentResult<()> {
+        let total: f64 = self.targets.values().sum();
+        let valid = !self.name.is_empty()
+            && !self.quote.is_empty()
+            && !self.targets.is_empty()
+            && self.targets.values().all(|weight| (0.0..=1.0).contains(weight))
+            && (total - 1.0).abs() < 1e-6
+            && self.drift_threshold_bps > 0.0
+            && self.min_order_notional >= 0.0;
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidConfiguration)
+        }
+    }
+
+    fn market(&self, token: &str) -> String {
+        format!("{}/{}", token, self.quote)
+    }
+}
+
+/// Rebalance whose orders haven't all closed
+#[derive(Debug, Clone)]
+struct Rebalance {
+    /// Weights, targets and value the rebalance was decided on
+    inputs: Value,
+    /// Markets with an open order
+    pending: HashSet<String>,
+    signatures: Vec<String>,
+    unfilled: usize,
+}
+
+/// Strategy keeping a basket of tokens at its target weights
+pub struct RebalanceStrategy {
+    config: RebalanceConfig,
+    /// Signed quantity held of each token, quote included
+    holdings: HashMap<String, i64>,
+    /// Latest price of each basket token, in quote units
+    prices: HashMap<String, f64>,
+    /// Whether a tick arrived since the last orders were generated
+    ticked: bool,
+    rebalance: Option<Rebalance>,
+    audit: Option<AuditLog>,
+    rebalances: u64,
+}
+
+impl RebalanceStrategy {
+    pub fn new(config: RebalanceConfig) -> AgentResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            config,
+            holdings: HashMap::new(),
+            prices: HashMap::new(),
+            ticked: false,
+            rebalance: None,
+            audit: None,
+            rebalances: 0,
+        })
+    }
+
+    /// Start from the basket's holdings in `portfolio`, e.g. the agent's
+    /// live portfolio synced on-chain
+    pub fn with_portfolio(mut self, portfolio: &Portfolio) -> Self {
+        let tokens = self.config.targets.keys().chain([&self.config.quote]);
+        self.holdings = tokens.map(|token| (token.clone(), portfolio.quantity(token))).collect();
+        self
+    }
+
+    /// Append each rebalance to `audit` once its orders closed
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn config(&self) -> &RebalanceConfig {
+        &self.config
+    }
+
+    pub fn quantity(&self, token: &str) -> i64 {
+        self.holdings.get(token).copied().unwrap_or(0)
+    }
+
+    /// Rebalances started so far
+    pub fn rebalances(&self) -> u64 {
+        self.rebalances
+    }
+
+    /// Whether a rebalance's orders are still open
+    pub fn is_rebalancing(&self) -> bool {
+        self.rebalance.is_some()
+    }
+
+    /// Value of the basket in quote units, `None` until every token with a
+    /// holding or target was priced
+    pub fn value(&self) -> Option<f64> {
+        self.values().map(|values| values.values().sum())
+    }
+
+    /// Current share of the basket's value of each token
+    pub fn weights(&self) -> Option<HashMap<String, f64>> {
+        let values = self.values()?;
+        let total: f64 = values.values().sum();
+        (total > 0.0).then(|| values.into_iter().map(|(token, value)| (token, value / total)).collect())
+    }
+
+    /// Largest drift of any weight from its target, in basis points
+    pub fn drift_bps(&self) -> Option<f64> {
+        let weights = self.weights()?;
+        let drift = |token: &String| {
+            let target = self.config.targets.get(token).copied().unwrap_or(0.0);
+            (weights.get(token).copied().unwrap_or(0.0) - target).abs()
+        };
+        Some(weights.keys().chain(self.config.targets.keys()).map(drift).fold(0.0, f64::max) * 10_000.0)
+    }
+
+    fn values(&self) -> Option<HashMap<String, f64>> {
+        let tokens: HashSet<&String> = self.config.targets.keys().chain(self.holdings.keys()).collect();
+        tokens
+            .into_iter()
+            .map(|token| {
+                let price = if *token == self.config.quote { Some(1.0) } else { self.prices.get(token).copied() };
+                Some((token.clone(), self.quantity(token) as f64 * price?))
+            })
+            .collect()
+    }
+
+    /// Close the rebalance of `market`'s order, appending the rebalance to
+    /// the audit log once it was the last open one
+    fn close(&mut self, order: &Order, filled: bool) {
+        let Some(rebalance) = &mut self.rebalance else {
+            return;
+        };
+        if !rebalance.pending.remove(&order.request.market) {
+            return;
+        }
+        match (filled, &order.submission_id) {
+            (true, Some(signature)) => rebalance.signatures.push(signature.clone()),
+            (true, None) => {}
+            (false, _) => rebalance.unfilled += 1,
+        }
+        if rebalance.pending.is_empty() {
+            let rebalance = self.rebalance.take().unwrap();
+            self.audit(&order.request.agent, rebalance);
+        }
+    }
+
+    /// Append the closed `rebalance` to the audit log in the background.
+    /// Strategies run in blocking code, so without a Tokio runtime to append
+    /// on the rebalance is only logged.
+    fn audit(&self, agent: &str, rebalance: Rebalance) {
+        let outcome = match rebalance.unfilled {
+            0 => DecisionOutcome::Executed { signature: rebalance.signatures.join(",") },
+            unfilled => DecisionOutcome::Failed { error: format!("{} orders closed unfilled", unfilled) },
+        };
+        println!("Rebalance of {} by {} closed: {:?}", self.config.name, agent, outcome);
+
+        let Some(audit) = self.audit.clone() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            println!("No runtime to audit the rebalance of {} on", self.config.name);
+            return;
+        };
+        let candidates = self.config.targets.keys().map(|token| self.config.market(token)).collect();
+        let record = DecisionRecord::new(agent, &rebalance.inputs, candidates, "rebalance".to_string(), None, outcome);
+        let name = self.config.name.clone();
+        runtime.spawn(async move {
+            if let Err(e) = audit.append(record).await {
+                println!("Failed to audit rebalance of {}: {}", name, e);
+            }
+        });
+    }
+}
+
+impl Strategy for RebalanceStrategy {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn on_tick(&mut self, tick: &MarketTick) {
+        let token = self.config.targets.keys().find(|token| self.config.market(token) == tick.market);
+        if let Some(token) = token.cloned() {
+            self.prices.insert(token, tick.price);
+            self.ticked = true;
+        }
+    }
+
+    fn on_fill(&mut self, order: &Order, fill: &Fill) {
+        let Some((token, _)) = order.request.market.split_once('/') else {
+            return;
+        };
+        let quantity = signed(order.request.side, fill.quantity);
+        let notional = (quantity as f64 * fill.price).round() as i64;
+        let holding = self.holdings.entry(token.to_string()).or_default();
+        *holding = holding.saturating_add(quantity);
+        let cash = self.holdings.entry(self.config.quote.clone()).or_default();
+        *cash = cash.saturating_sub(notional);
+
+        if order.remaining_quantity() == 0 {
+            self.close(order, true);
+        }
+    }
+
+    fn on_order_closed(&mut self, order: &Order) {
+        self.close(order, false);
+    }
+
+    fn generate_orders(&mut self) -> Vec<OrderRequest> {
+        if !std::mem::take(&mut self.ticked) || self.rebalance.is_some() {
+            return vec![];
+        }
+        let (Some(value), Some(weights), Some(drift)) = (self.value(), self.weights(), self.drift_bps()) else {
+            return vec![];
+        };
+        if drift <= self.config.drift_threshold_bps {
+            return vec![];
+        }
+
+        let mut orders: Vec<OrderRequest> = self
+            .config
+            .targets
+            .iter()
+            .filter(|(token, _)| **token != self.config.quote)
+            .filter_map(|(token, target)| {
+                let price = self.prices[token];
+                let delta = target * value - self.quantity(token) as f64 * price;
+                let quantity = (delta.abs() / price).floor() as u64;
+                if quantity == 0 || delta.abs() < self.config.min_order_notional {
+                    return None;
+                }
+                Some(OrderRequest {
+                    client_order_id: String::new(),
+                    agent: String::new(),
+                    strategy: String::new(),
+                    market: self.config.market(token),
+                    side: if delta > 0.0 { OrderSide::Buy } else { OrderSide::Sell },
+                    quantity,
+                    limit_price: None,
+                })
+            })
+            .collect();
+        orders.sort_by_key(|order| (order.side == OrderSide::Buy, order.market.clone()));
+        if orders.is_empty() {
+            return orders;
+        }
+
+        println!(
+            "Rebalancing {}: drift of {:.0} bps beyond {:.0}, placing {} orders",
+            self.config.name,
+            drift,
+            self.config.drift_threshold_bps,
+            orders.len()
+        );
+        self.rebalances += 1;
+        self.rebalance = Some(Rebalance {
+            inputs: json!({ "weights": weights, "targets": self.config.targets, "value": value, "drift_bps": drift }),
+            pending: orders.iter().map(|order| order.market.clone()).collect(),
+            signatures: Vec::new(),
+            unfilled: 0,
+        });
+        orders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use crate::agent::audit::{AuditConfig, AuditQuery};
+    use crate::agent::orders::{ExecutionOutcome, ExecutionReport, OrderManager};
+    use crate::agent::trading::SimulatedExecutor;
+    use crate::storage::{StorageConfig, StorageManager};
+
+    fn config() -> RebalanceConfig {
+        RebalanceConfig {
+            name: "rebalance".to_string(),
+            quote: "USDC".to_string(),
+            targets: HashMap::from([("SOL".to_string(), 0.5), ("USDC".to_string(), 0.5)]),
+            drift_threshold_bps: 500.0,
+            min_order_notional: 100.0,
+        }
+    }
+
+    fn strategy() -> RebalanceStrategy {
+        let mut portfolio = Portfolio::new();
+        portfolio.set_balance("SOL", 1_000);
+        portfolio.set_balance("USDC", 100_000);
+        RebalanceStrategy::new(config()).unwrap().with_portfolio(&portfolio)
+    }
+
+    fn tick(price: f64) -> MarketTick {
+        MarketTick {
+            market: "SOL/USDC".to_string(),
+            price,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    /// Submit `request` as `trader`'s order `id`, then fill it at `fill` or
+    /// reject it
+    async fn close(orders: &mut OrderManager, id: &str, request: OrderRequest, fill: Option<f64>) -> Order {
+        let quantity = request.quantity;
+        orders
+            .create(OrderRequest {
+                client_order_id: id.to_string(),
+                agent: "trader".to_string(),
+                ..request
+            })
+            .unwrap();
+        let Some(price) = fill else {
+            return orders.reject(id).unwrap();
+        };
+        let order = orders.submit(id, &SimulatedExecutor::new("sig")).await.unwrap();
+        let report = ExecutionReport {
+            client_order_id: id.to_string(),
+            submission_id: order.submission_id,
+            outcome: ExecutionOutcome::Fill { quantity, price },
+        };
+        orders.reconcile(report).unwrap().clone()
+    }
+
+    #[test]
+    fn test_drift_threshold() {
+        let invalid = RebalanceConfig {
+            targets: HashMap::from([("SOL".to_string(), 0.6), ("USDC".to_string(), 0.5)]),
+            ..config()
+        };
+        assert!(RebalanceStrategy::new(invalid).is_err());
+
+        let mut rebalance = strategy();
+        assert!(rebalance.generate_orders().is_empty());
+
+        // 98 bps of drift
+        rebalance.on_tick(&tick(104.0));
+        assert!(rebalance.generate_orders().is_empty());
+
+        rebalance.on_tick(&tick(150.0));
+        assert!((rebalance.drift_bps().unwrap() - 1_000.0).abs() < 1e-6);
+        let orders = rebalance.generate_orders();
+        assert_eq!(orders.len(), 1);
+        assert_eq!((orders[0].side, orders[0].quantity), (OrderSide::Sell, 166));
+        assert!(rebalance.is_rebalancing());
+
+        // No new rebalance while one is open
+        rebalance.on_tick(&tick(160.0));
+        assert!(rebalance.generate_orders().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rebalances_audited() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = StorageManager::new(StorageConfig {
+            base_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let audit = AuditLog::new(Arc::new(storage), AuditConfig::default());
+        let mut rebalance = strategy().with_audit_log(audit.clone());
+        let mut orders = OrderManager::new();
+
+        // A rejected order, e.g. refused by the risk checks, fails the
+        // rebalance and the next tick retries it
+        rebalance.on_tick(&tick(150.0));
+        let request = rebalance.generate_orders().remove(0);
+        rebalance.on_order_closed(&close(&mut orders, "order-1", request, None).await);
+        assert!(!rebalance.is_rebalancing());
+
+        rebalance.on_tick(&tick(150.0));
+        let request = rebalance.generate_orders().remove(0);
+        let order = close(&mut orders, "order-2", request, Some(150.0)).await;
+        rebalance.on_fill(&order, &Fill { quantity: order.request.quantity, price: 150.0 });
+        assert!(!rebalance.is_rebalancing());
+        assert_eq!((rebalance.quantity("SOL"), rebalance.quantity("USDC")), (834, 124_900));
+        assert_eq!(rebalance.rebalances(), 2);
+
+        rebalance.on_tick(&tick(150.0));
+        assert!(rebalance.generate_orders().is_empty());
+
+        let mut records = Vec::new();
+        for _ in 0..100 {
+            records = audit.query("trader", &AuditQuery::default()).await.unwrap();
+            if records.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let executed: Vec<bool> = records.iter().map(|record| record.signature().is_some()).collect();
+        assert_eq!(executed, vec![false, true]);
+    }
+}
@@ -0,0 +1,268 @@
+//! Risk limits enforced on trading agents
+//!
+//! This module provides:
+//! - Per-agent limits: position size per market, order notional and daily
+//!   loss
+//! - `RiskManager`, checking every order before it is submitted against
+//!   the agent's `Portfolio`, and tracking the PnL realized each day
+//! - A kill switch halting all further orders once the daily loss limit is
+//!   hit, and pausing the on-chain agent if one is attached
+//!
+//! The daily loss counts the PnL realized since midnight UTC, as reported
+//! by the portfolio; a halted manager stays halted until reset.
+
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use crate::solana::program::state::PauseReason;
+use super::base::Agent;
+use super::orders::{OrderRequest, OrderSide};
+use super::portfolio::{signed, Portfolio};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Limits of an agent; unset limits aren't enforced
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RiskLimits {
+    /// Largest position in any market, long or short, in base units
+    pub max_position: Option<u64>,
+    /// Largest notional of a single order, in quote units
+    pub max_order_notional: Option<f64>,
+    /// Largest loss realized in a UTC day before trading halts, in quote
+    /// units
+    pub max_daily_loss: Option<f64>,
+}
+
+/// Why an order was refused
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RiskViolation {
+    #[error("Trading halted: {0}")]
+    Halted(String),
+
+    #[error("Position in {market} would reach {position}, beyond the limit of {limit}")]
+    MaxPosition {
+        market: String,
+        position: i64,
+        limit: u64,
+    },
+
+    #[error("Order notional {notional} exceeds the limit of {limit}")]
+    MaxOrderNotional { notional: f64, limit: f64 },
+
+    #[error("No price known for {0} to check the order notional against")]
+    UnknownPrice(String),
+
+    #[error("Daily loss {loss} reached the limit of {limit}")]
+    DailyLoss { loss: f64, limit: f64 },
+}
+
+/// Unfilled quantity of an agent's open orders in a market, per side
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OpenOrders {
+    pub buys: u64,
+    pub sells: u64,
+}
+
+/// Checks orders against an agent's limits
+pub struct RiskManager {
+    limits: RiskLimits,
+    /// Day `daily_pnl` was realized in, as days since the Unix epoch
+    day: u64,
+    daily_pnl: f64,
+    halted: Option<String>,
+    kill_switch: Option<Arc<Agent>>,
+}
+
+impl RiskManager {
+    pub fn new(limits: RiskLimits) -> Self {
+        Self {
+            limits,
+            day: 0,
+            daily_pnl: 0.0,
+            halted: None,
+            kill_switch: None,
+        }
+    }
+
+    /// Pause `agent` on-chain when the kill switch trips
+    pub fn with_kill_switch(mut self, agent: Arc<Agent>) -> Self {
+        self.kill_switch = Some(agent);
+        self
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    /// PnL realized on the day of the latest fill
+    pub fn daily_pnl(&self) -> f64 {
+        self.daily_pnl
+    }
+
+    /// Why trading is halted, if it is
+    pub fn halted(&self) -> Option<&str> {
+        self.halted.as_deref()
+    }
+
+    /// Check `order` before it is submitted
+    ///
+    /// `price` is the market's latest price, used for orders without a
+    /// limit; `open` the agent's open orders in the market. The position
+    /// limit is checked against the worst case on the order's side: its
+    /// position in `portfolio` once the order and every open order on the
+    /// same side fill, while the other side's orders don't.
+    pub fn check(
+        &self,
+        order: &OrderRequest,
+        portfolio: &Portfolio,
+        price: Option<f64>,
+        open: OpenOrders,
+    ) -> Result<(), RiskViolation> {
+        if let Some(reason) = &self.halted {
+            return Err(RiskViolation::Halted(reason.clone()));
+        }
+
+        if let Some(limit) = self.limits.max_order_notional {
+            let price = order
+                .limit_price
+                .or(price)
+                .ok_or_else(|| RiskViolation::UnknownPrice(order.market.clone()))?;
+            let notional = order.quantity as f64 * price;
+            if notional > limit {
+                return Err(RiskViolation::MaxOrderNotional { notional, limit });
+            }
+        }
+
+        if let Some(limit) = self.limits.max_position {
+            let open = match order.side {
+                OrderSide::Buy => open.buys,
+                OrderSide::Sell => open.sells,
+            };
+            let position = portfolio
+                .position(&order.market)
+                .saturating_add(signed(order.side, open))
+                .saturating_add(signed(order.side, order.quantity));
+            // Orders reducing a position on the other side never breach it
+            let exposure = match order.side {
+                OrderSide::Buy => position,
+                OrderSide::Sell => position.saturating_neg(),
+            };
+            if exposure > 0 && exposure.unsigned_abs() > limit {
+                return Err(RiskViolation::MaxPosition {
+                    market: order.market.clone(),
+                    position,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Record `realized` PnL of a fill made at Unix time `timestamp`,
+    /// tripping the kill switch if it takes the day's loss to the limit
+    pub async fn record_pnl(&mut self, realized: f64, timestamp: u64) {
+        let day = timestamp / SECONDS_PER_DAY;
+        if day != self.day {
+            self.day = day;
+            self.daily_pnl = 0.0;
+        }
+        self.daily_pnl += realized;
+
+        if let Some(limit) = self.limits.max_daily_loss {
+            let loss = -self.daily_pnl;
+            if loss >= limit && self.halted.is_none() {
+                self.trip(RiskViolation::DailyLoss { loss, limit }.to_string()).await;
+            }
+        }
+    }
+
+    /// Halt all further orders, pausing the on-chain agent if attached
+    pub async fn trip(&mut self, reason: String) {
+        println!("Risk kill switch tripped: {}", reason);
+        self.halted = Some(reason);
+
+        if let Some(agent) = self.kill_switch.clone() {
+            match tokio::task::spawn_blocking(move || agent.pause_for(PauseReason::RiskLimit)).await {
+                Ok(Ok(signature)) => println!("Paused on-chain agent: {}", signature),
+                Ok(Err(e)) => println!("Failed to pause on-chain agent: {}", e),
+                Err(e) => println!("Failed to pause on-chain agent: {}", e),
+            }
+        }
+    }
+
+    /// Resume trading after a trip; the on-chain agent stays paused until
+    /// resumed itself
+    pub fn reset(&mut self) {
+        self.halted = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::orders::fixtures::request;
+
+    #[test]
+    fn test_order_limits() {
+        let risk = RiskManager::new(RiskLimits {
+            max_position: Some(100),
+            max_order_notional: Some(1_000.0),
+            max_daily_loss: None,
+        });
+        let mut portfolio = Portfolio::new();
+        portfolio.set_balance("SOL", 20);
+
+        let none = OpenOrders::default();
+        assert_eq!(risk.check(&request(OrderSide::Buy, 50, None), &portfolio, Some(20.0), none), Ok(()));
+        assert_eq!(
+            risk.check(&request(OrderSide::Buy, 60, None), &portfolio, Some(20.0), none),
+            Err(RiskViolation::MaxOrderNotional { notional: 1_200.0, limit: 1_000.0 })
+        );
+        assert_eq!(
+            risk.check(&request(OrderSide::Buy, 10, None), &portfolio, None, none),
+            Err(RiskViolation::UnknownPrice("SOL/USDC".to_string()))
+        );
+        // Holdings and open orders on the order's side count towards the
+        // position; open orders on the other side don't offset them
+        let open = OpenOrders { buys: 70, sells: 90 };
+        assert_eq!(
+            risk.check(&request(OrderSide::Sell, 40, Some(5.0)), &portfolio, None, open),
+            Err(RiskViolation::MaxPosition { market: "SOL/USDC".to_string(), position: -110, limit: 100 })
+        );
+        assert_eq!(
+            risk.check(&request(OrderSide::Buy, 20, Some(5.0)), &portfolio, None, open),
+            Err(RiskViolation::MaxPosition { market: "SOL/USDC".to_string(), position: 110, limit: 100 })
+        );
+
+        // Orders reducing a position beyond the limit are allowed
+        portfolio.set_balance("SOL", 150);
+        assert_eq!(risk.check(&request(OrderSide::Sell, 20, Some(5.0)), &portfolio, None, none), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_daily_loss_kill_switch() {
+        let mut risk = RiskManager::new(RiskLimits {
+            max_daily_loss: Some(100.0),
+            ..Default::default()
+        });
+        let portfolio = Portfolio::new();
+        let day = 19_700 * SECONDS_PER_DAY;
+
+        risk.record_pnl(-50.0, day).await;
+        risk.record_pnl(-20.0, day + 60).await;
+        assert_eq!(risk.daily_pnl(), -70.0);
+        assert!(risk.halted().is_none());
+
+        // A new day starts from zero
+        risk.record_pnl(-100.0, day + SECONDS_PER_DAY).await;
+        assert_eq!(risk.daily_pnl(), -100.0);
+        assert!(matches!(
+            risk.check(&request(OrderSide::Buy, 1, Some(30.0)), &portfolio, None, OpenOrders::default()),
+            Err(RiskViolation::Halted(_))
+        ));
+
+        risk.reset();
+        let open = OpenOrders::default();
+        assert_eq!(risk.check(&request(OrderSide::Buy, 1, Some(30.0)), &portfolio, None, open), Ok(()));
+    }
+}
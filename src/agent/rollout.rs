@@ -0,0 +1,402 @@
+//! Staged (canary) rollout of a new config or strategy version to a fleet
+//!
+//! This module provides:
+//! - `RolloutController`, applying a candidate version to a canary share of
+//!   a fleet while the rest keeps the current one as the control group
+//! - Per-group error rates, read from the orchestrator's pipeline reports,
+//!   and PnL recorded by the trading agents
+//! - Automatic promotion of the candidate to the whole fleet, or rollback
+//!   of the canaries, once both groups were observed long enough
+//! - The `RolloutTarget` trait applying a version to an agent, implemented
+//!   for on-chain agents updated with `Agent::update_config`
+//!
+//! Canaries are the first agents of the fleet in name order, so a rollout
+//! restarted with the same fleet picks the same agents. A candidate is
+//! rolled back if its error rate exceeds the control group's by more than
+//! `max_error_rate_increase`, or its average PnL per agent falls short of
+//! the control group's by more than `max_pnl_drop`; otherwise it is
+//! promoted.
+
+use std::collections::{BTreeSet, HashMap};
+use serde::{Deserialize, Serialize};
+use super::base::{Agent, AgentConfig};
+use super::error::{AgentError, AgentResult};
+use super::orchestrator::{PipelineReport, StepOutcome};
+
+/// Share of the fleet given the candidate first, in percent
+pub const DEFAULT_CANARY_PERCENT: u8 = 10;
+
+/// Pipeline runs of each group observed before deciding by default
+pub const DEFAULT_MIN_OBSERVATIONS: u64 = 20;
+
+/// Rollout configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RolloutConfig {
+    /// Share of the fleet given the candidate, in percent; at least one
+    /// agent, and at least one left in the control group
+    pub canary_percent: u8,
+    /// Cycles each group must have completed or failed before deciding
+    pub min_observations: u64,
+    /// Largest increase of the error rate over the control group's, as a
+    /// fraction of cycles
+    pub max_error_rate_increase: f64,
+    /// Largest shortfall of the average PnL per agent against the control
+    /// group's
+    pub max_pnl_drop: f64,
+}
+
+impl Default for RolloutConfig {
+    fn default() -> Self {
+        Self {
+            canary_percent: DEFAULT_CANARY_PERCENT,
+            min_observations: DEFAULT_MIN_OBSERVATIONS,
+            max_error_rate_increase: 0.05,
+            max_pnl_drop: 0.0,
+        }
+    }
+}
+
+/// Where a rollout stands
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RolloutState {
+    /// Created; no agent runs the candidate yet
+    Pending,
+    /// The canaries run the candidate
+    Canary,
+    /// Every agent runs the candidate
+    Promoted,
+    /// The canaries were reverted to the current version
+    RolledBack { reason: String },
+}
+
+/// Observations of one group of agents
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupMetrics {
+    pub agents: usize,
+    pub cycles: u64,
+    pub failures: u64,
+    pub pnl: f64,
+}
+
+impl GroupMetrics {
+    /// Fraction of cycles that failed
+    pub fn error_rate(&self) -> f64 {
+        match self.cycles {
+            0 => 0.0,
+            cycles => self.failures as f64 / cycles as f64,
+        }
+    }
+
+    pub fn pnl_per_agent(&self) -> f64 {
+        self.pnl / self.agents.max(1) as f64
+    }
+}
+
+/// Applies versions to the agents of a fleet
+pub trait RolloutTarget<V> {
+    /// Make the agent named `agent` run `version`
+    fn apply(&mut self, agent: &str, version: &V) -> AgentResult<()>;
+}
+
+/// On-chain agents by name, updated with their new config; blocks on RPC
+impl RolloutTarget<AgentConfig> for HashMap<String, Agent> {
+    fn apply(&mut self, agent: &str, version: &AgentConfig) -> AgentResult<()> {
+        let handle = self.get(agent).ok_or(AgentError::InvalidInput)?;
+        handle.update_config(version).map_err(|e| AgentError::Custom(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Rolls a candidate version out to a fleet through a canary group
+#[derive(Debug, Clone)]
+pub struct RolloutController<V> {
+    config: RolloutConfig,
+    current: V,
+    candidate: V,
+    canary: BTreeSet<String>,
+    control: BTreeSet<String>,
+    canary_metrics: GroupMetrics,
+    control_metrics: GroupMetrics,
+    state: RolloutState,
+}
+
+impl<V> RolloutController<V> {
+    /// Rollout of `candidate` to the agents named `fleet`, which run
+    /// `current`
+    ///
+    /// Fails with `InvalidConfiguration` if the split would leave either
+    /// group empty.
+    pub fn new(fleet: &[&str], current: V, candidate: V, config: RolloutConfig) -> AgentResult<Self> {
+        let fleet: BTreeSet<String> = fleet.iter().map(|name| name.to_string()).collect();
+        let canaries = (fleet.len() * usize::from(config.canary_percent) / 100).max(1);
+        if config.canary_percent == 0 || canaries >= fleet.len() {
+            return Err(AgentError::InvalidConfiguration);
+        }
+
+        let canary: BTreeSet<String> = fleet.iter().take(canaries).cloned().collect();
+        let control: BTreeSet<String> = fleet.difference(&canary).cloned().collect();
+        Ok(Self {
+            canary_metrics: GroupMetrics { agents: canary.len(), ..Default::default() },
+            control_metrics: GroupMetrics { agents: control.len(), ..Default::default() },
+            config,
+            current,
+            candidate,
+            canary,
+            control,
+            state: RolloutState::Pending,
+        })
+    }
+
+    pub fn state(&self) -> &RolloutState {
+        &self.state
+    }
+
+    /// Agents given the candidate first, in name order
+    pub fn canary(&self) -> Vec<&str> {
+        self.canary.iter().map(String::as_str).collect()
+    }
+
+    pub fn control(&self) -> Vec<&str> {
+        self.control.iter().map(String::as_str).collect()
+    }
+
+    pub fn canary_metrics(&self) -> GroupMetrics {
+        self.canary_metrics
+    }
+
+    pub fn control_metrics(&self) -> GroupMetrics {
+        self.control_metrics
+    }
+
+    /// Apply the candidate to the canaries
+    ///
+    /// Canaries already updated when one fails are reverted, and the
+    /// rollout is rolled back.
+    pub fn start<T: RolloutTarget<V>>(&mut self, target: &mut T) -> AgentResult<()> {
+        if self.state != RolloutState::Pending {
+            return Err(AgentError::InvalidStateTransition);
+        }
+
+        let mut updated = Vec::with_capacity(self.canary.len());
+        for agent in &self.canary {
+            if let Err(e) = target.apply(agent, &self.candidate) {
+                println!("Failed to apply the candidate to canary {}: {}", agent, e);
+                for agent in updated {
+                    self.revert(target, agent);
+                }
+                self.state = RolloutState::RolledBack { reason: format!("canary {} failed to update: {}", agent, e) };
+                return Err(e);
+            }
+            updated.push(agent);
+        }
+        self.state = RolloutState::Canary;
+        Ok(())
+    }
+
+    /// Count the cycles the fleet's agents completed or failed in a
+    /// pipeline run; skipped agents and agents outside the fleet are
+    /// ignored
+    pub fn record_report(&mut self, report: &PipelineReport) {
+        if self.state != RolloutState::Canary {
+            return;
+        }
+        for step in &report.steps {
+            let failed = match step.outcome {
+                StepOutcome::Completed { .. } => false,
+                StepOutcome::Failed { .. } => true,
+                StepOutcome::Skipped { .. } => continue,
+            };
+            if let Some(metrics) = self.metrics_mut(&step.agent) {
+                metrics.cycles += 1;
+                metrics.failures += u64::from(failed);
+            }
+        }
+    }
+
+    /// Add `pnl`, realized by `agent` since its last record, to its group
+    pub fn record_pnl(&mut self, agent: &str, pnl: f64) {
+        if self.state != RolloutState::Canary {
+            return;
+        }
+        if let Some(metrics) = self.metrics_mut(agent) {
+            metrics.pnl += pnl;
+        }
+    }
+
+    /// Promote or roll back the candidate once each group completed or
+    /// failed `min_observations` cycles, returning the resulting state
+    ///
+    /// Promotion applies the candidate to the control group, carrying on
+    /// past agents that fail to update, which are logged. Rollback reverts
+    /// the canaries to the current version.
+    pub fn evaluate<T: RolloutTarget<V>>(&mut self, target: &mut T) -> &RolloutState {
+        let observed = self.canary_metrics.cycles.min(self.control_metrics.cycles);
+        if self.state != RolloutState::Canary || observed < self.config.min_observations {
+            return &self.state;
+        }
+
+        let (canary, control) = (self.canary_metrics, self.control_metrics);
+        let error_increase = canary.error_rate() - control.error_rate();
+        let pnl_drop = control.pnl_per_agent() - canary.pnl_per_agent();
+        let reason = if error_increase > self.config.max_error_rate_increase {
+            Some(format!(
+                "error rate {:.3} against {:.3} for the control group",
+                canary.error_rate(),
+                control.error_rate()
+            ))
+        } else if pnl_drop > self.config.max_pnl_drop {
+            Some(format!(
+                "PnL per agent {:.2} against {:.2} for the control group",
+                canary.pnl_per_agent(),
+                control.pnl_per_agent()
+            ))
+        } else {
+            None
+        };
+
+        match reason {
+            Some(reason) => {
+                println!("Rolling back the candidate: {}", reason);
+                for agent in &self.canary {
+                    self.revert(target, agent);
+                }
+                self.state = RolloutState::RolledBack { reason };
+            }
+            None => {
+                for agent in &self.control {
+                    if let Err(e) = target.apply(agent, &self.candidate) {
+                        println!("Failed to promote the candidate to {}: {}", agent, e);
+                    }
+                }
+                self.state = RolloutState::Promoted;
+            }
+        }
+        &self.state
+    }
+
+    fn revert<T: RolloutTarget<V>>(&self, target: &mut T, agent: &str) {
+        if let Err(e) = target.apply(agent, &self.current) {
+            println!("Failed to revert {} to the current version: {}", agent, e);
+        }
+    }
+
+    fn metrics_mut(&mut self, agent: &str) -> Option<&mut GroupMetrics> {
+        if self.canary.contains(agent) {
+            Some(&mut self.canary_metrics)
+        } else if self.control.contains(agent) {
+            Some(&mut self.control_metrics)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::orchestrator::PipelineStep;
+
+    /// Versions of each agent, failing to update the agents in `broken`
+    #[derive(Default)]
+    struct Fleet {
+        versions: HashMap<String, u32>,
+        broken: Vec<String>,
+    }
+
+    impl RolloutTarget<u32> for Fleet {
+        fn apply(&mut self, agent: &str, version: &u32) -> AgentResult<()> {
+            if self.broken.iter().any(|broken| broken == agent) {
+                return Err(AgentError::NetworkError);
+            }
+            self.versions.insert(agent.to_string(), *version);
+            Ok(())
+        }
+    }
+
+    const FLEET: [&str; 4] = ["d", "c", "b", "a"];
+
+    fn config() -> RolloutConfig {
+        RolloutConfig {
+            canary_percent: 25,
+            min_observations: 2,
+            ..Default::default()
+        }
+    }
+
+    /// Run in which the agents in `failed` failed and the others completed
+    fn report(failed: &[&str]) -> PipelineReport {
+        let steps = FLEET
+            .iter()
+            .map(|agent| PipelineStep {
+                agent: agent.to_string(),
+                outcome: match failed.contains(agent) {
+                    true => StepOutcome::Failed { error: "boom".to_string() },
+                    false => StepOutcome::Completed { actions: 1 },
+                },
+            })
+            .collect();
+        PipelineReport { steps }
+    }
+
+    #[test]
+    fn test_split() {
+        let rollout = RolloutController::new(&FLEET, 1, 2, config()).unwrap();
+        assert_eq!((rollout.canary(), rollout.control()), (vec!["a"], vec!["b", "c", "d"]));
+
+        let all = RolloutConfig { canary_percent: 100, ..config() };
+        assert_eq!(RolloutController::new(&FLEET, 1, 2, all).unwrap_err(), AgentError::InvalidConfiguration);
+        assert!(RolloutController::new(&["a"], 1, 2, config()).is_err());
+    }
+
+    #[test]
+    fn test_promotes_healthy_candidate() {
+        let mut fleet = Fleet::default();
+        let mut rollout = RolloutController::new(&FLEET, 1, 2, config()).unwrap();
+        rollout.start(&mut fleet).unwrap();
+        assert_eq!(fleet.versions, HashMap::from([("a".to_string(), 2)]));
+        assert_eq!(rollout.start(&mut fleet), Err(AgentError::InvalidStateTransition));
+
+        rollout.record_report(&report(&["c"]));
+        rollout.record_pnl("a", 5.0);
+        rollout.record_pnl("b", 3.0);
+        // Not observed long enough yet
+        assert_eq!(rollout.evaluate(&mut fleet), &RolloutState::Canary);
+
+        rollout.record_report(&report(&[]));
+        assert_eq!(rollout.canary_metrics().cycles, 2);
+        assert_eq!(rollout.control_metrics().failures, 1);
+        assert_eq!(rollout.evaluate(&mut fleet), &RolloutState::Promoted);
+        assert!(FLEET.iter().all(|agent| fleet.versions[*agent] == 2));
+    }
+
+    #[test]
+    fn test_rolls_back_failing_candidate() {
+        let mut fleet = Fleet::default();
+        let mut rollout = RolloutController::new(&FLEET, 1, 2, config()).unwrap();
+        rollout.start(&mut fleet).unwrap();
+        rollout.record_report(&report(&["a"]));
+        rollout.record_report(&report(&[]));
+
+        assert!(matches!(rollout.evaluate(&mut fleet), RolloutState::RolledBack { .. }));
+        assert_eq!(fleet.versions, HashMap::from([("a".to_string(), 1)]));
+
+        // A canary losing money against the control group is rolled back too
+        let mut rollout = RolloutController::new(&FLEET, 1, 2, config()).unwrap();
+        rollout.start(&mut fleet).unwrap();
+        rollout.record_report(&report(&[]));
+        rollout.record_report(&report(&[]));
+        rollout.record_pnl("a", -1.0);
+        assert!(matches!(rollout.evaluate(&mut fleet), RolloutState::RolledBack { reason } if reason.contains("PnL")));
+    }
+
+    #[test]
+    fn test_failed_canary_update_rolls_back() {
+        let rollout_config = RolloutConfig { canary_percent: 50, ..config() };
+        let mut fleet = Fleet { broken: vec!["b".to_string()], ..Default::default() };
+        let mut rollout = RolloutController::new(&FLEET, 1, 2, rollout_config).unwrap();
+
+        assert_eq!(rollout.start(&mut fleet), Err(AgentError::NetworkError));
+        assert!(matches!(rollout.state(), RolloutState::RolledBack { .. }));
+        assert_eq!(fleet.versions, HashMap::from([("a".to_string(), 1)]));
+    }
+}
@@ -0,0 +1,238 @@
+//! Rotating the authority key of a fleet of on-chain agents
+//!
+//! This module provides:
+//! - `AuthorityRotation`, transferring the authority of many agents from
+//!   the current key to a new one in batched `TransferAuthority`
+//!   transactions signed by both keys
+//! - Verification of every agent's authority on-chain once all batches
+//!   were sent
+//! - An atomic update of the local signer configuration once every agent
+//!   is verified, or a rollback of the transferred agents otherwise
+//! - A `RotationReport` telling which agents are under which key
+//!
+//! The new key signs its transfers, so they take effect at once without
+//! `AcceptAuthority`; it must be loadable before the rotation starts, e.g.
+//! generated with `FileSigner::generate`. The current key pays for every
+//! transaction, rollback included. Agent addresses are derived from the
+//! key that created them, so after a rotation agents are opened with
+//! `Agent::with_creator`.
+
+use std::path::Path;
+use std::sync::Arc;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::Signature,
+};
+use crate::error::{SonomaError, SonomaResult};
+use crate::solana::client::{self, ClientError, ReadOptions};
+use crate::solana::program::{error::AgentError, instruction::AgentInstruction};
+use crate::solana::send::{self, SendStrategy};
+use crate::solana::signer::{SignerSource, SonomaSigner};
+use crate::solana::transaction::TransactionBuilder;
+
+/// Agents transferred per transaction by default
+pub const DEFAULT_ROTATION_BATCH_SIZE: usize = 8;
+
+/// Outcome of a rotation
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RotationReport {
+    /// Agents under the new key
+    pub rotated: Vec<Pubkey>,
+    /// Agents left under the current key, with why their transfer failed;
+    /// after a rollback, every agent of the fleet
+    pub failed: Vec<(Pubkey, String)>,
+    /// Whether the signer configuration now names the new key
+    pub committed: bool,
+}
+
+impl RotationReport {
+    /// Whether every agent is under the key the signer configuration names:
+    /// all rotated and committed, or all rolled back
+    pub fn is_consistent(&self) -> bool {
+        self.committed || self.rotated.is_empty()
+    }
+}
+
+/// Moves a fleet from its current authority key to a new one
+pub struct AuthorityRotation {
+    rpc: RpcClient,
+    program_id: Pubkey,
+    current: Arc<dyn SonomaSigner>,
+    batch_size: usize,
+    send_strategy: SendStrategy,
+}
+
+impl AuthorityRotation {
+    /// Rotation away from `current`, connecting with the URL and commitment
+    /// of `client`
+    pub fn new(client: &RpcClient, program_id: &Pubkey, current: Arc<dyn SonomaSigner>) -> Self {
+        Self {
+            rpc: RpcClient::new_with_commitment(client.url(), client.commitment()),
+            program_id: *program_id,
+            current,
+            batch_size: DEFAULT_ROTATION_BATCH_SIZE,
+            send_strategy: SendStrategy::default(),
+        }
+    }
+
+    /// Transfer up to `batch_size` agents per transaction, at least one
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn with_send_strategy(mut self, send_strategy: SendStrategy) -> Self {
+        self.send_strategy = send_strategy;
+        self
+    }
+
+    /// Transfer the authority of `agents` to the key `next` loads, then
+    /// save `next` to the signer configuration at `config` once every
+    /// agent is verified on-chain
+    ///
+    /// If any agent wasn't transferred, the transferred ones are moved back
+    /// and the configuration is left as it was; agents that couldn't be
+    /// moved back stay in the report's `rotated`, and the new key must be
+    /// kept to recover them. Fails before sending anything if an agent
+    /// doesn't exist or isn't under the current key.
+    pub fn rotate(
+        &self,
+        agents: &[Pubkey],
+        next: &SignerSource,
+        config: impl AsRef<Path>,
+    ) -> SonomaResult<RotationReport> {
+        let next_signer = next.load()?;
+        let (current, new) = (self.current.pubkey(), next_signer.pubkey());
+        for (agent, authority) in agents.iter().zip(self.authorities(agents)?) {
+            if authority != Some(current) {
+                println!("Agent {} is not under the current key {}, not rotating", agent, current);
+                return Err(match authority {
+                    Some(_) => SonomaError::Program(AgentError::InvalidAuthority),
+                    None => ClientError::AccountNotFound(*agent).into(),
+                });
+            }
+        }
+
+        let mut errors = self.transfer(agents, self.current.as_ref(), next_signer.as_ref());
+        let (rotated, pending) = self.verify(agents, &new)?;
+        if pending.is_empty() {
+            next.save(config)?;
+            println!("Rotated {} agents from {} to {}", rotated.len(), current, new);
+            return Ok(RotationReport {
+                rotated,
+                failed: Vec::new(),
+                committed: true,
+            });
+        }
+
+        println!("Rotation of {} agents failed, moving {} back to {}", pending.len(), rotated.len(), current);
+        errors.extend(self.transfer(&rotated, next_signer.as_ref(), self.current.as_ref()));
+        let (stranded, _) = self.verify(agents, &new)?;
+        let failed = agents
+            .iter()
+            .filter(|agent| !stranded.contains(agent))
+            .map(|agent| {
+                let error = errors.iter().find(|(failed, _)| failed == agent).map(|(_, error)| error.clone());
+                (*agent, error.unwrap_or_else(|| "rolled back".to_string()))
+            })
+            .collect();
+        Ok(RotationReport {
+            rotated: stranded,
+            failed,
+            committed: false,
+        })
+    }
+
+    /// Authority of each of `agents`, `None` for those without an account
+    fn authorities(&self, agents: &[Pubkey]) -> SonomaResult<Vec<Option<Pubkey>>> {
+        let accounts = client::fetch_agents_with_options(&self.rpc, &self.program_id, agents, &ReadOptions::default())?;
+        Ok(agents.iter().map(|agent| accounts.get(agent).map(|account| account.authority)).collect())
+    }
+
+    /// `agents` split by whether their authority is `authority`
+    fn verify(&self, agents: &[Pubkey], authority: &Pubkey) -> SonomaResult<(Vec<Pubkey>, Vec<Pubkey>)> {
+        let (verified, pending): (Vec<_>, Vec<_>) = agents
+            .iter()
+            .zip(self.authorities(agents)?)
+            .partition(|(_, current)| current.as_ref() == Some(authority));
+        let addresses = |agents: Vec<(&Pubkey, Option<Pubkey>)>| -> Vec<Pubkey> {
+            agents.into_iter().map(|(agent, _)| *agent).collect()
+        };
+        Ok((addresses(verified), addresses(pending)))
+    }
+
+    /// Transfer `agents` from `from` to `to` in batches, returning the
+    /// agents of the batches that failed with the error
+    fn transfer(&self, agents: &[Pubkey], from: &dyn SonomaSigner, to: &dyn SonomaSigner) -> Vec<(Pubkey, String)> {
+        let mut errors = Vec::new();
+        for batch in agents.chunks(self.batch_size) {
+            let instructions: Vec<Instruction> = batch
+                .iter()
+                .map(|agent| {
+                    AgentInstruction::transfer_authority(&self.program_id, agent, &from.pubkey(), &to.pubkey(), true)
+                })
+                .collect();
+            if let Err(e) = self.send(&instructions, from, to) {
+                println!("Transferring {} agents to {} failed: {}", batch.len(), to.pubkey(), e);
+                errors.extend(batch.iter().map(|agent| (*agent, e.to_string())));
+            }
+        }
+        errors
+    }
+
+    /// Send `instructions` signed by both keys, paid for by the current
+    /// one, which is either of them
+    fn send(
+        &self,
+        instructions: &[Instruction],
+        from: &dyn SonomaSigner,
+        to: &dyn SonomaSigner,
+    ) -> SonomaResult<Signature> {
+        let payer = self.current.pubkey();
+        let signers = [from.as_signer(), to.as_signer()];
+        send::send_with_strategy(&self.rpc, &self.send_strategy, |blockhash| {
+            Ok(TransactionBuilder::new()
+                .add_instructions(instructions.iter().cloned())
+                .build_versioned(&payer, &signers, blockhash)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn test_report_consistency() {
+        let agent = Pubkey::new_unique();
+        let rolled_back = RotationReport {
+            failed: vec![(agent, "rolled back".to_string())],
+            ..RotationReport::default()
+        };
+        assert!(rolled_back.is_consistent());
+
+        let stranded = RotationReport {
+            rotated: vec![agent],
+            ..RotationReport::default()
+        };
+        assert!(!stranded.is_consistent());
+        assert!(RotationReport { committed: true, ..stranded }.is_consistent());
+    }
+
+    #[test]
+    fn test_unloadable_key_fails_before_sending() {
+        let client = RpcClient::new_mock("succeeds".to_string());
+        let rotation =
+            AuthorityRotation::new(&client, &Pubkey::new_unique(), Arc::new(Keypair::new())).with_batch_size(0);
+        assert_eq!(rotation.batch_size, 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let next = SignerSource::File(dir.path().join("missing.json"));
+        let result = rotation.rotate(&[Pubkey::new_unique()], &next, dir.path().join("signer.json"));
+        assert!(matches!(result, Err(SonomaError::Signer(_))));
+        assert!(!dir.path().join("signer.json").exists());
+    }
+}
@@ -0,0 +1,568 @@
+//! Rule-based strategies configured in TOML
+//!
+//! This module provides:
+//! - `StrategyConfig`, a strategy declared as rules: conditions over
+//!   indicators of a market's price, and the order to place when they hold
+//! - Indicators: price, simple and exponential moving averages, RSI, and
+//!   the strategy's position
+//! - `RuleStrategy`, the `Strategy` interpreting a config
+//!
+//! A config reads like:
+//!
+//! ```toml
+//! name = "rsi-dip"
+//! market = "SOL/USDC"
+//! max_position = 100
+//!
+//! [[rules]]
+//! when = [{ indicator = "rsi", period = 14, op = "<", value = 30.0 }]
+//! action = { side = "buy", quantity = 10 }
+//!
+//! [[rules]]
+//! when = [{ indicator = "rsi", period = 14, op = ">", value = 70.0 }]
+//! action = { side = "sell", quantity = 10, max_position = 0 }
+//! ```
+//!
+//! Rules are evaluated in order on each tick of the market, and the first
+//! whose conditions all hold places a market order. Conditions over an
+//! indicator still warming up don't hold. An action's `max_position`, or
+//! else the strategy's, caps the position the order may take long or
+//! short, counting the strategy's orders not yet filled or closed as if
+//! they filled; the order is shrunk to fit and skipped if nothing fits.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use super::orders::{Order, OrderRequest, OrderSide};
+use super::portfolio::signed;
+use super::trading::{Fill, MarketTick, Strategy};
+
+#[derive(Error, Debug)]
+pub enum StrategyConfigError {
+    #[error("Failed to read strategy config: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid strategy config: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Invalid rule {rule}: {reason}")]
+    InvalidRule { rule: usize, reason: String },
+}
+
+/// Value a condition is evaluated on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Indicator {
+    /// Latest price
+    Price,
+    /// Simple moving average of the last `period` prices
+    Sma,
+    /// Exponential moving average over `period` prices
+    Ema,
+    /// Relative strength index over `period` price changes, from 0 to 100
+    Rsi,
+    /// Signed position of the strategy, from its fills
+    Position,
+}
+
+impl Indicator {
+    fn needs_period(self) -> bool {
+        matches!(self, Indicator::Sma | Indicator::Ema | Indicator::Rsi)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = "<")]
+    Below,
+    #[serde(rename = "<=")]
+    AtMost,
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = ">=")]
+    AtLeast,
+}
+
+impl Comparison {
+    fn holds(self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            Comparison::Below => lhs < rhs,
+            Comparison::AtMost => lhs <= rhs,
+            Comparison::Above => lhs > rhs,
+            Comparison::AtLeast => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Condition {
+    pub indicator: Indicator,
+    /// Required by moving averages and RSI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<usize>,
+    pub op: Comparison,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleAction {
+    #[serde(with = "side")]
+    pub side: OrderSide,
+    pub quantity: u64,
+    /// Largest position the order may take, overriding the strategy's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_position: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Conditions that must all hold
+    pub when: Vec<Condition>,
+    pub action: RuleAction,
+}
+
+/// Strategy declared as rules over a market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StrategyConfig {
+    pub name: String,
+    pub market: String,
+    /// Largest position of any order, long or short, in base units
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_position: Option<u64>,
+    pub rules: Vec<Rule>,
+}
+
+impl StrategyConfig {
+    pub fn from_toml(source: &str) -> Result<Self, StrategyConfigError> {
+        let config: Self = toml::from_str(source)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StrategyConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn validate(&self) -> Result<(), StrategyConfigError> {
+        for (index, rule) in self.rules.iter().enumerate() {
+            let invalid = |reason: &str| StrategyConfigError::InvalidRule {
+                rule: index,
+                reason: reason.to_string(),
+            };
+            if rule.action.quantity == 0 {
+                return Err(invalid("quantity must be positive"));
+            }
+            for condition in &rule.when {
+                match (condition.indicator.needs_period(), condition.period) {
+                    (true, None | Some(0)) => {
+                        return Err(invalid(&format!("{:?} needs a positive period", condition.indicator)));
+                    }
+                    (false, Some(_)) => {
+                        return Err(invalid(&format!("{:?} takes no period", condition.indicator)));
+                    }
+                    _ => {}
+                }
+                if !condition.value.is_finite() {
+                    return Err(invalid("condition value must be finite"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Strategy interpreting the config
+    pub fn compile(self) -> RuleStrategy {
+        RuleStrategy::new(self)
+    }
+}
+
+/// Running value of an indicator with a period
+#[derive(Debug, Clone)]
+enum IndicatorState {
+    Sma {
+        period: usize,
+        window: VecDeque<f64>,
+        sum: f64,
+    },
+    Ema {
+        period: usize,
+        seen: usize,
+        value: f64,
+    },
+    /// Wilder's smoothing of gains and losses
+    Rsi {
+        period: usize,
+        changes: usize,
+        previous: Option<f64>,
+        average_gain: f64,
+        average_loss: f64,
+    },
+}
+
+impl IndicatorState {
+    fn new(indicator: Indicator, period: usize) -> Option<Self> {
+        match indicator {
+            Indicator::Sma => Some(IndicatorState::Sma {
+                period,
+                window: VecDeque::with_capacity(period.min(1024)),
+                sum: 0.0,
+            }),
+            Indicator::Ema => Some(IndicatorState::Ema { period, seen: 0, value: 0.0 }),
+            Indicator::Rsi => Some(IndicatorState::Rsi {
+                period,
+                changes: 0,
+                previous: None,
+                average_gain: 0.0,
+                average_loss: 0.0,
+            }),
+            Indicator::Price | Indicator::Position => None,
+        }
+    }
+
+    fn update(&mut self, price: f64) {
+        match self {
+            IndicatorState::Sma { period, window, sum } => {
+                window.push_back(price);
+                *sum += price;
+                if window.len() > *period {
+                    *sum -= window.pop_front().unwrap_or_default();
+                }
+            }
+            IndicatorState::Ema { period, seen, value } => {
+                *seen += 1;
+                // Seeded with the average of the first `period` prices
+                if *seen <= *period {
+                    *value += (price - *value) / *seen as f64;
+                } else {
+                    *value += (price - *value) * 2.0 / (*period as f64 + 1.0);
+                }
+            }
+            IndicatorState::Rsi {
+                period,
+                changes,
+                previous,
+                average_gain,
+                average_loss,
+            } => {
+                if let Some(previous) = previous.replace(price) {
+                    let change = price - previous;
+                    *changes += 1;
+                    let weight = (*changes).min(*period) as f64;
+                    *average_gain += (change.max(0.0) - *average_gain) / weight;
+                    *average_loss += ((-change).max(0.0) - *average_loss) / weight;
+                }
+            }
+        }
+    }
+
+    /// `None` until the indicator has seen a full period
+    fn value(&self) -> Option<f64> {
+        match self {
+            IndicatorState::Sma { period, window, sum } => (window.len() == *period).then(|| sum / *period as f64),
+            IndicatorState::Ema { period, seen, value } => (*seen >= *period).then_some(*value),
+            IndicatorState::Rsi {
+                period,
+                changes,
+                average_gain,
+                average_loss,
+                ..
+            } => (*changes >= *period).then(|| {
+                if *average_loss > 0.0 {
+                    100.0 - 100.0 / (1.0 + average_gain / average_loss)
+                } else if *average_gain > 0.0 {
+                    100.0
+                } else {
+                    50.0
+                }
+            }),
+        }
+    }
+}
+
+/// Strategy placing the orders of the first rule of its config that holds
+pub struct RuleStrategy {
+    config: StrategyConfig,
+    indicators: HashMap<(Indicator, usize), IndicatorState>,
+    last_price: Option<f64>,
+    position: i64,
+    /// Quantity of the strategy's buy and sell orders that may still fill
+    pending_buys: u64,
+    pending_sells: u64,
+    /// Whether a tick arrived since the rules were last evaluated
+    ticked: bool,
+}
+
+impl RuleStrategy {
+    pub fn new(config: StrategyConfig) -> Self {
+        let indicators = config
+            .rules
+            .iter()
+            .flat_map(|rule| &rule.when)
+            .filter_map(|condition| {
+                let period = condition.period?;
+                let state = IndicatorState::new(condition.indicator, period)?;
+                Some(((condition.indicator, period), state))
+            })
+            .collect();
+        Self {
+            config,
+            indicators,
+            last_price: None,
+            position: 0,
+            pending_buys: 0,
+            pending_sells: 0,
+            ticked: false,
+        }
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, StrategyConfigError> {
+        StrategyConfig::from_toml(source).map(Self::new)
+    }
+
+    pub fn config(&self) -> &StrategyConfig {
+        &self.config
+    }
+
+    /// Signed position from the strategy's fills
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+
+    /// Current value of `indicator`, `None` while warming up
+    pub fn indicator(&self, indicator: Indicator, period: Option<usize>) -> Option<f64> {
+        match indicator {
+            Indicator::Price => self.last_price,
+            Indicator::Position => Some(self.position as f64),
+            _ => self.indicators.get(&(indicator, period?))?.value(),
+        }
+    }
+
+    fn holds(&self, condition: &Condition) -> bool {
+        self.indicator(condition.indicator, condition.period)
+            .map_or(false, |value| condition.op.holds(value, condition.value))
+    }
+
+    /// Quantity of the strategy's orders on `side` that may still fill
+    pub fn pending(&self, side: OrderSide) -> u64 {
+        match side {
+            OrderSide::Buy => self.pending_buys,
+            OrderSide::Sell => self.pending_sells,
+        }
+    }
+
+    fn pending_mut(&mut self, side: OrderSide) -> &mut u64 {
+        match side {
+            OrderSide::Buy => &mut self.pending_buys,
+            OrderSide::Sell => &mut self.pending_sells,
+        }
+    }
+
+    /// Quantity of `action` fitting its position limit once the pending
+    /// orders on its side fill
+    fn fitting_quantity(&self, action: &RuleAction) -> u64 {
+        let Some(limit) = action.max_position.or(self.config.max_position) else {
+            return action.quantity;
+        };
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let pending = i64::try_from(self.pending(action.side)).unwrap_or(i64::MAX);
+        let room = match action.side {
+            OrderSide::Buy => limit.saturating_sub(self.position).saturating_sub(pending),
+            OrderSide::Sell => limit.saturating_add(self.position).saturating_sub(pending),
+        };
+        action.quantity.min(u64::try_from(room).unwrap_or(0))
+    }
+}
+
+impl Strategy for RuleStrategy {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn on_tick(&mut self, tick: &MarketTick) {
+        if tick.market != self.config.market {
+            return;
+        }
+        self.last_price = Some(tick.price);
+        for state in self.indicators.values_mut() {
+            state.update(tick.price);
+        }
+        self.ticked = true;
+    }
+
+    fn on_fill(&mut self, order: &Order, fill: &Fill) {
+        if order.request.market == self.config.market {
+            self.position = self.position.saturating_add(signed(order.request.side, fill.quantity));
+            let pending = self.pending_mut(order.request.side);
+            *pending = pending.saturating_sub(fill.quantity);
+        }
+    }
+
+    fn on_order_closed(&mut self, order: &Order) {
+        if order.request.market == self.config.market {
+            let pending = self.pending_mut(order.request.side);
+            *pending = pending.saturating_sub(order.remaining_quantity());
+        }
+    }
+
+    fn generate_orders(&mut self) -> Vec<OrderRequest> {
+        if !std::mem::take(&mut self.ticked) {
+            return vec![];
+        }
+        let Some(rule) = self.config.rules.iter().find(|rule| rule.when.iter().all(|c| self.holds(c))) else {
+            return vec![];
+        };
+
+        let (side, quantity) = (rule.action.side, self.fitting_quantity(&rule.action));
+        if quantity == 0 {
+            return vec![];
+        }
+        *self.pending_mut(side) += quantity;
+        vec![OrderRequest {
+            client_order_id: String::new(),
+            agent: String::new(),
+            strategy: String::new(),
+            market: self.config.market.clone(),
+            side,
+            quantity,
+            limit_price: None,
+        }]
+    }
+}
+
+/// Order sides as "buy" and "sell"
+mod side {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use crate::agent::orders::OrderSide;
+
+    pub fn serialize<S: Serializer>(side: &OrderSide, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match side {
+            OrderSide::Buy => "buy",
+            OrderSide::Sell => "sell",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<OrderSide, D::Error> {
+        match String::deserialize(deserializer)?.to_lowercase().as_str() {
+            "buy" => Ok(OrderSide::Buy),
+            "sell" => Ok(OrderSide::Sell),
+            other => Err(serde::de::Error::custom(format!("unknown side {:?}, expected buy or sell", other))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::orders::{fixtures::order, OrderStatus};
+
+    const RSI_DIP: &str = r#"
+        name = "rsi-dip"
+        market = "SOL/USDC"
+        max_position = 15
+
+        [[rules]]
+        when = [{ indicator = "rsi", period = 3, op = "<", value = 30.0 }]
+        action = { side = "buy", quantity = 10 }
+
+        [[rules]]
+        when = [
+            { indicator = "rsi", period = 3, op = ">", value = 70.0 },
+            { indicator = "position", op = ">", value = 0.0 },
+        ]
+        action = { side = "sell", quantity = 10, max_position = 0 }
+    "#;
+
+    fn tick(price: f64) -> MarketTick {
+        MarketTick {
+            market: "SOL/USDC".to_string(),
+            price,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    fn fill(strategy: &mut RuleStrategy, request: OrderRequest) {
+        let quantity = request.quantity;
+        strategy.on_fill(&order(request), &Fill { quantity, price: 0.0 });
+    }
+
+    #[test]
+    fn test_rules_place_orders_within_limits() {
+        let mut strategy = RuleStrategy::from_toml(RSI_DIP).unwrap();
+
+        // Warming up: RSI needs 3 price changes
+        for price in [20.0, 19.0, 18.0] {
+            strategy.on_tick(&tick(price));
+            assert!(strategy.generate_orders().is_empty());
+        }
+        strategy.on_tick(&tick(17.0));
+        assert_eq!(strategy.indicator(Indicator::Rsi, Some(3)), Some(0.0));
+        let orders = strategy.generate_orders();
+        assert_eq!((orders[0].side, orders[0].quantity), (OrderSide::Buy, 10));
+        fill(&mut strategy, orders[0].clone());
+
+        // Shrunk to the room left under max_position
+        strategy.on_tick(&tick(16.0));
+        let orders = strategy.generate_orders();
+        assert_eq!(orders[0].quantity, 5);
+        fill(&mut strategy, orders[0].clone());
+        strategy.on_tick(&tick(15.0));
+        assert!(strategy.generate_orders().is_empty());
+
+        // A rally sells the position down to flat
+        for price in [18.0, 21.0, 24.0] {
+            strategy.on_tick(&tick(price));
+        }
+        let orders = strategy.generate_orders();
+        assert_eq!((orders[0].side, orders[0].quantity), (OrderSide::Sell, 10));
+        fill(&mut strategy, orders[0].clone());
+        strategy.on_tick(&tick(27.0));
+        assert_eq!(strategy.generate_orders()[0].quantity, 5);
+    }
+
+    #[test]
+    fn test_pending_orders_count_towards_limits() {
+        let mut strategy = RuleStrategy::from_toml(RSI_DIP).unwrap();
+        for price in [20.0, 19.0, 18.0, 17.0] {
+            strategy.on_tick(&tick(price));
+        }
+        let first = strategy.generate_orders().remove(0);
+        assert_eq!(first.quantity, 10);
+
+        // The unfilled buy leaves room for 5 more under max_position
+        strategy.on_tick(&tick(16.0));
+        let second = strategy.generate_orders().remove(0);
+        assert_eq!(second.quantity, 5);
+        assert_eq!(strategy.pending(OrderSide::Buy), 15);
+        strategy.on_tick(&tick(15.0));
+        assert!(strategy.generate_orders().is_empty());
+
+        // Closing an order releases its quantity
+        strategy.on_order_closed(&Order { status: OrderStatus::Rejected, ..order(first) });
+        assert_eq!(strategy.pending(OrderSide::Buy), 5);
+        fill(&mut strategy, second);
+        assert_eq!((strategy.position(), strategy.pending(OrderSide::Buy)), (5, 0));
+        strategy.on_tick(&tick(14.0));
+        assert_eq!(strategy.generate_orders()[0].quantity, 10);
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        let missing_period = RSI_DIP.replacen("period = 3, ", "", 1);
+        assert!(matches!(
+            StrategyConfig::from_toml(&missing_period),
+            Err(StrategyConfigError::InvalidRule { rule: 0, .. })
+        ));
+        let unknown_side = RSI_DIP.replacen("\"buy\"", "\"hold\"", 1);
+        assert!(matches!(StrategyConfig::from_toml(&unknown_side), Err(StrategyConfigError::Parse(_))));
+        let zero_quantity = RSI_DIP.replacen("quantity = 10 }", "quantity = 0 }", 1);
+        assert!(matches!(
+            StrategyConfig::from_toml(&zero_quantity),
+            Err(StrategyConfigError::InvalidRule { rule: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,958 @@
+//! Runtime driving agents' cycles on a schedule
+//!
+//! This module provides:
+//! - The `ScheduledAgent` trait: one cycle of work, bounded by a number of
+//!   actions
+//! - Interval and cron schedules, and intervals adapting to the load (see
+//!   `autoscale`)
+//! - `AgentRuntime`, running each registered agent on its own tokio task
+//! - A message bus shared by the registered agents, with messages
+//!   delivered between cycles
+//! - Pausing and resuming agents, with their lifecycle hooks invoked around
+//!   each transition (see `AgentLifecycle`)
+//! - Per-agent cycle statistics
+//! - Supervision, restarting agents that panic (see `supervisor`)
+//! - Checkpointing agents' state to storage, restored when they are
+//!   registered again after a restart (see `checkpoint`)
+//! - Graceful shutdown, letting running cycles finish
+//!
+//! A cycle that fails is counted and logged; the agent runs again at its
+//! next scheduled time. A paused agent runs no cycles and receives no
+//! messages, which wait in its mailbox.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use super::autoscale::{AutoscaleConfig, Autoscaler, LatencySource, LoadSignals};
+use super::bus::{Mailbox, Message, MessageBus};
+use super::checkpoint::{CheckpointConfig, Checkpointer, DEFAULT_CHECKPOINT_INTERVAL};
+use super::error::{AgentError, AgentResult};
+use super::lifecycle::AgentLifecycle;
+use super::supervisor::{RestartBudget, SupervisionPolicy};
+use crate::storage::StorageManager;
+
+/// Agent the runtime can drive
+#[async_trait::async_trait]
+pub trait ScheduledAgent: AgentLifecycle {
+    /// Name the agent is registered under; unique within a runtime
+    fn name(&self) -> &str;
+
+    /// Most actions a single cycle may take
+    fn max_actions_per_cycle(&self) -> u32;
+
+    /// Run one cycle taking at most `max_actions` actions, returning how
+    /// many it took
+    async fn run_cycle(&mut self, max_actions: u32) -> AgentResult<u32>;
+
+    /// Called on registration with the bus the runtime's agents share, e.g.
+    /// to keep it for sending or to subscribe to topics
+    fn connect(&mut self, _bus: MessageBus) {}
+
+    /// Handle a message from another agent, delivered between cycles
+    async fn on_message(&mut self, _message: Message) -> AgentResult<()> {
+        Ok(())
+    }
+
+    /// State to save in the agent's checkpoint; `None` if it keeps none
+    fn checkpoint(&self) -> AgentResult<Option<Value>> {
+        Ok(None)
+    }
+
+    /// Resume from the state of the agent's last checkpoint, before it
+    /// starts
+    fn restore(&mut self, _state: Value) -> AgentResult<()> {
+        Ok(())
+    }
+}
+
+/// When an agent's cycles run
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Every interval, the first right away
+    Interval(Duration),
+    /// At each time the cron expression matches, in UTC
+    Cron(cron::Schedule),
+    /// Every interval, the first right away, stretched while the agent is
+    /// overloaded and shrunk back once the load subsides
+    Adaptive(AutoscaleConfig),
+}
+
+impl Schedule {
+    /// Schedule from a cron expression with seconds, e.g. `0 */5 * * * *`
+    pub fn cron(expression: &str) -> AgentResult<Self> {
+        cron::Schedule::from_str(expression)
+            .map(Self::Cron)
+            .map_err(|_| AgentError::InvalidConfiguration)
+    }
+
+    /// Adaptive schedule within `config`'s bounds
+    pub fn adaptive(config: AutoscaleConfig) -> AgentResult<Self> {
+        config.validate()?;
+        Ok(Self::Adaptive(config))
+    }
+
+    /// Time to wait before the next cycle; `None` if no cycle is left
+    fn next_delay(&self, first: bool) -> Option<Duration> {
+        match self {
+            Schedule::Interval(_) | Schedule::Adaptive(_) if first => Some(Duration::ZERO),
+            Schedule::Interval(interval) => Some(*interval),
+            Schedule::Adaptive(config) => Some(config.min_interval),
+            Schedule::Cron(schedule) => {
+                let next = schedule.upcoming(Utc).next()?;
+                Some((next - Utc::now()).to_std().unwrap_or_default())
+            }
+        }
+    }
+}
+
+/// Where a registered agent is in its lifecycle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunState {
+    #[default]
+    Starting,
+    Running,
+    Paused,
+    Stopped,
+    /// Stopped after failing more often than its supervision policy allows
+    Failed,
+}
+
+/// Cycle statistics of a registered agent
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunStats {
+    pub state: RunState,
+    pub cycles: u64,
+    pub actions: u64,
+    pub failures: u64,
+    /// Messages handled, failed ones included
+    pub messages: u64,
+    /// Times the agent was restarted after failing
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// Current interval of an adaptive schedule
+    #[serde(default)]
+    pub interval: Option<Duration>,
+}
+
+struct RunningAgent {
+    handle: JoinHandle<()>,
+    paused: watch::Sender<bool>,
+    stats: Arc<Mutex<RunStats>>,
+}
+
+/// Runs registered agents on their schedules until shut down
+///
+/// Must be used within a tokio runtime.
+pub struct AgentRuntime {
+    agents: HashMap<String, RunningAgent>,
+    bus: MessageBus,
+    checkpoints: Option<Checkpointer>,
+    /// RPC latency adaptive schedules slow down for
+    latency: Option<Arc<dyn LatencySource>>,
+    shutdown: watch::Sender<bool>,
+}
+
+impl Default for AgentRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AgentRuntime {
+    pub fn new() -> Self {
+        Self::with_bus(MessageBus::new())
+    }
+
+    /// Runtime whose agents are connected to `bus`
+    pub fn with_bus(bus: MessageBus) -> Self {
+        let (shutdown, _) = watch::channel(false);
+        Self {
+            agents: HashMap::new(),
+            bus,
+            checkpoints: None,
+            latency: None,
+            shutdown,
+        }
+    }
+
+    /// Checkpoint agents registered from now on to `storage`, restoring
+    /// them from their last checkpoint first
+    pub fn with_checkpoints(mut self, storage: Arc<StorageManager>, config: CheckpointConfig) -> Self {
+        self.checkpoints = Some(Checkpointer::new(storage, config));
+        self
+    }
+
+    /// Slow agents on adaptive schedules down while the RPC latency `source`
+    /// reports exceeds their target, e.g. an `RpcEndpoints` the agents share
+    pub fn with_latency_source(mut self, source: Arc<dyn LatencySource>) -> Self {
+        self.latency = Some(source);
+        self
+    }
+
+    /// Bus connecting the registered agents, e.g. to message them from
+    /// outside the runtime
+    pub fn bus(&self) -> &MessageBus {
+        &self.bus
+    }
+
+    /// Start running `agent` on `schedule`
+    ///
+    /// The agent isn't restarted if it fails; see `register_supervised`.
+    pub fn register<A: ScheduledAgent + 'static>(&mut self, agent: A, schedule: Schedule) -> AgentResult<()> {
+        let mut agent = Some(agent);
+        self.spawn(move || agent.take(), schedule, SupervisionPolicy::never_restart())
+    }
+
+    /// Start running an agent made by `factory` on `schedule`, restarting
+    /// it with a new one from `factory` whenever it fails, as `policy`
+    /// allows
+    pub fn register_supervised<A, F>(
+        &mut self,
+        mut factory: F,
+        schedule: Schedule,
+        policy: SupervisionPolicy,
+    ) -> AgentResult<()>
+    where
+        A: ScheduledAgent + 'static,
+        F: FnMut() -> A + Send + 'static,
+    {
+        self.spawn(move || Some(factory()), schedule, policy)
+    }
+
+    fn spawn<A, F>(&mut self, mut factory: F, schedule: Schedule, policy: SupervisionPolicy) -> AgentResult<()>
+    where
+        A: ScheduledAgent + 'static,
+        F: FnMut() -> Option<A> + Send + 'static,
+    {
+        let mut agent = factory().ok_or(AgentError::InvalidInput)?;
+        let name = agent.name().to_string();
+        if self.agents.contains_key(&name) {
+            return Err(AgentError::InvalidInput);
+        }
+
+        let mailbox = self.bus.mailbox(&name)?;
+        agent.connect(self.bus.clone());
+        let (paused, paused_rx) = watch::channel(false);
+        let stats = Arc::new(Mutex::new(RunStats::default()));
+        let task = AgentTask {
+            name: name.clone(),
+            schedule,
+            bus: self.bus.clone(),
+            paused: paused_rx,
+            shutdown: self.shutdown.subscribe(),
+            checkpoints: self.checkpoints.clone(),
+            latency: self.latency.clone(),
+            stats: stats.clone(),
+        };
+        let handle = tokio::spawn(supervise(agent, mailbox, factory, task, RestartBudget::new(policy)));
+        self.agents.insert(name, RunningAgent { handle, paused, stats });
+        Ok(())
+    }
+
+    /// Stop running cycles of the agent named `name` until resumed
+    pub fn pause(&self, name: &str) -> AgentResult<()> {
+        self.set_paused(name, true)
+    }
+
+    pub fn resume(&self, name: &str) -> AgentResult<()> {
+        self.set_paused(name, false)
+    }
+
+    fn set_paused(&self, name: &str, paused: bool) -> AgentResult<()> {
+        let agent = self.agents.get(name).ok_or(AgentError::InvalidInput)?;
+        agent.paused.send_replace(paused);
+        Ok(())
+    }
+
+    /// Names of the registered agents, sorted
+    pub fn agents(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.agents.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn stats(&self, name: &str) -> Option<RunStats> {
+        self.agents
+            .get(name)
+            .map(|agent| agent.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone())
+    }
+
+    /// Stop scheduling cycles and wait for the running ones to finish
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(true);
+        for (name, agent) in self.agents {
+            if let Err(e) = agent.handle.await {
+                println!("Agent {} task ended abnormally: {}", name, e);
+            }
+        }
+    }
+}
+
+/// What an agent's task runs with, shared by the instances of a
+/// supervised agent
+#[derive(Clone)]
+struct AgentTask {
+    name: String,
+    schedule: Schedule,
+    bus: MessageBus,
+    paused: watch::Receiver<bool>,
+    shutdown: watch::Receiver<bool>,
+    checkpoints: Option<Checkpointer>,
+    latency: Option<Arc<dyn LatencySource>>,
+    stats: Arc<Mutex<RunStats>>,
+}
+
+/// Run `agent`, then new instances from `factory` each time one fails,
+/// until the agent stops or its restart budget is exhausted
+async fn supervise<A, F>(agent: A, mailbox: Mailbox, mut factory: F, task: AgentTask, mut budget: RestartBudget)
+where
+    A: ScheduledAgent + 'static,
+    F: FnMut() -> Option<A> + Send + 'static,
+{
+    let mut next = Some((agent, mailbox));
+    while let Some((agent, mailbox)) = next.take() {
+        let error = match tokio::spawn(run_agent(agent, mailbox, task.clone())).await {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => e.to_string(),
+            Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+            Err(e) => e.to_string(),
+        };
+        println!("Agent {} failed: {}", task.name, error);
+        update(&task.stats, |run| {
+            run.state = RunState::Stopped;
+            run.last_error = Some(error);
+        });
+        if *task.shutdown.borrow() {
+            return;
+        }
+
+        let Some(backoff) = budget.restart(std::time::Instant::now()) else {
+            update(&task.stats, |run| run.state = RunState::Failed);
+            budget.escalation().escalate(&task.name).await;
+            return;
+        };
+        let mut shutdown = task.shutdown.clone();
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = shutdown.changed() => return,
+        }
+
+        let Some(mut agent) = factory() else {
+            return;
+        };
+        let mailbox = match task.bus.mailbox(&task.name) {
+            Ok(mailbox) => mailbox,
+            Err(e) => {
+                println!("Agent {} could not be restarted: {}", task.name, e);
+                update(&task.stats, |run| run.state = RunState::Failed);
+                return;
+            }
+        };
+        agent.connect(task.bus.clone());
+        update(&task.stats, |run| {
+            run.state = RunState::Starting;
+            run.restarts += 1;
+        });
+        println!("Restarting agent {} after {:?}", task.name, backoff);
+        next = Some((agent, mailbox));
+    }
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "unknown panic".to_string()),
+    }
+}
+
+/// Run `agent` until shutdown; fails if the agent fails to start
+async fn run_agent<A: ScheduledAgent>(mut agent: A, mut mailbox: Mailbox, task: AgentTask) -> AgentResult<()> {
+    let AgentTask {
+        schedule,
+        mut paused,
+        mut shutdown,
+        checkpoints,
+        latency,
+        stats,
+        ..
+    } = task;
+
+    if let Some(checkpoints) = &checkpoints {
+        restore_checkpoint(&mut agent, checkpoints, &stats).await;
+    }
+    if let Err(e) = agent.on_start().await {
+        report_error(&mut agent, "starting", e.clone(), &stats).await;
+        return Err(e);
+    }
+    update(&stats, |run| run.state = RunState::Running);
+    if *paused.borrow_and_update() {
+        transition(&mut agent, true, &stats).await;
+    }
+
+    let checkpoint_interval = checkpoints.as_ref().map_or(DEFAULT_CHECKPOINT_INTERVAL, Checkpointer::interval);
+    let mut next_checkpoint = Instant::now() + checkpoint_interval;
+    let mut autoscaler = match &schedule {
+        Schedule::Adaptive(config) => Some(Autoscaler::new(config.clone())),
+        _ => None,
+    };
+    let mut first = true;
+    'run: loop {
+        let delay = match &autoscaler {
+            Some(autoscaler) if !first => Some(autoscaler.interval()),
+            _ => schedule.next_delay(first),
+        };
+        let Some(delay) = delay else {
+            break;
+        };
+        first = false;
+
+        // Handle messages and pauses until the cycle is due; a cycle falling
+        // due while paused runs on resume
+        let deadline = Instant::now() + delay;
+        loop {
+            let is_paused = *paused.borrow();
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline), if !is_paused => break,
+                Some(message) = mailbox.recv(), if !is_paused => handle_message(&mut agent, message, &stats).await,
+                Ok(()) = paused.changed() => {
+                    let pause = *paused.borrow_and_update();
+                    if pause != is_paused {
+                        transition(&mut agent, pause, &stats).await;
+                    }
+                }
+                _ = tokio::time::sleep_until(next_checkpoint), if checkpoints.is_some() => {
+                    if let Some(checkpoints) = &checkpoints {
+                        save_checkpoint(&mut agent, checkpoints, &stats).await;
+                    }
+                    next_checkpoint = Instant::now() + checkpoint_interval;
+                }
+                _ = shutdown.changed() => break 'run,
+            }
+        }
+        if *shutdown.borrow() {
+            break;
+        }
+
+        let max_actions = agent.max_actions_per_cycle();
+        let result = agent.run_cycle(max_actions).await;
+        update(&stats, |run| {
+            run.cycles += 1;
+            match &result {
+                Ok(actions) => run.actions += u64::from((*actions).min(max_actions)),
+                Err(_) => run.failures += 1,
+            }
+        });
+        if let Some(autoscaler) = &mut autoscaler {
+            autoscaler.record_cycle(result.is_err());
+            let signals = LoadSignals {
+                rpc_latency: latency.as_ref().and_then(|source| source.latency()),
+                queue_depth: mailbox.len(),
+                error_rate: autoscaler.error_rate(),
+            };
+            let previous = autoscaler.interval();
+            let interval = autoscaler.adjust(&signals);
+            if interval != previous {
+                println!("Agent {} cycle interval {:?} -> {:?} under {:?}", agent.name(), previous, interval, signals);
+            }
+            update(&stats, |run| run.interval = Some(interval));
+        }
+        if let Err(e) = result {
+            report_error(&mut agent, "its cycle", e, &stats).await;
+        }
+    }
+
+    if let Err(e) = agent.on_shutdown().await {
+        report_error(&mut agent, "shutting down", e, &stats).await;
+    }
+    update(&stats, |run| run.state = RunState::Stopped);
+    if let Some(checkpoints) = &checkpoints {
+        save_checkpoint(&mut agent, checkpoints, &stats).await;
+    }
+    Ok(())
+}
+
+/// Restore the agent and its statistics from its last checkpoint; it
+/// starts afresh if that fails
+async fn restore_checkpoint<A: ScheduledAgent>(agent: &mut A, checkpoints: &Checkpointer, stats: &Mutex<RunStats>) {
+    let result = match checkpoints.load(agent.name()).await {
+        Ok(Some(checkpoint)) => checkpoint.state().and_then(|state| agent.restore(state)).map(|_| {
+            update(stats, |run| {
+                *run = RunStats {
+                    state: run.state,
+                    ..checkpoint.stats
+                }
+            });
+        }),
+        Ok(None) => Ok(()),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        report_error(agent, "restoring its checkpoint", e, stats).await;
+    }
+}
+
+async fn save_checkpoint<A: ScheduledAgent>(agent: &mut A, checkpoints: &Checkpointer, stats: &Mutex<RunStats>) {
+    let result = match agent.checkpoint() {
+        Ok(Some(state)) => {
+            let run = stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+            checkpoints.save(agent.name(), &state, &run).await
+        }
+        Ok(None) => return,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        report_error(agent, "checkpointing", e, stats).await;
+    }
+}
+
+async fn transition<A: ScheduledAgent>(agent: &mut A, pause: bool, stats: &Mutex<RunStats>) {
+    let (result, context, state) = match pause {
+        true => (agent.on_pause().await, "pausing", RunState::Paused),
+        false => (agent.on_resume().await, "resuming", RunState::Running),
+    };
+    update(stats, |run| run.state = state);
+    if let Err(e) = result {
+        report_error(agent, context, e, stats).await;
+    }
+}
+
+async fn handle_message<A: ScheduledAgent>(agent: &mut A, message: Message, stats: &Mutex<RunStats>) {
+    let from = message.from.clone();
+    let result = agent.on_message(message).await;
+    update(stats, |run| run.messages += 1);
+    if let Err(e) = result {
+        report_error(agent, &format!("handling a message from {}", from), e, stats).await;
+    }
+}
+
+/// Log and record `error`, then pass it to the agent's `on_error` hook
+async fn report_error<A: ScheduledAgent>(agent: &mut A, context: &str, error: AgentError, stats: &Mutex<RunStats>) {
+    println!("Agent {} failed {}: {}", agent.name(), context, error);
+    update(stats, |run| run.last_error = Some(error.to_string()));
+    agent.on_error(&error).await;
+}
+
+fn update(stats: &Mutex<RunStats>, f: impl FnOnce(&mut RunStats)) {
+    f(&mut stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    struct CountingAgent {
+        name: String,
+        max_actions: u32,
+        /// Largest action budget a cycle was given
+        max_budget: Arc<AtomicU32>,
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl ScheduledAgent for CountingAgent {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn max_actions_per_cycle(&self) -> u32 {
+            self.max_actions
+        }
+
+        async fn run_cycle(&mut self, max_actions: u32) -> AgentResult<u32> {
+            self.max_budget.fetch_max(max_actions, Ordering::SeqCst);
+            if self.fail {
+                return Err(AgentError::ProcessingError);
+            }
+            Ok(max_actions)
+        }
+    }
+
+    impl AgentLifecycle for CountingAgent {}
+
+    /// Advance the paused clock by `millis` milliseconds, one at a time so
+    /// the agents run every timer at the time it falls due
+    async fn advance(millis: u64) {
+        for _ in 0..millis {
+            tokio::time::advance(Duration::from_millis(1)).await;
+        }
+    }
+
+    fn agent(name: &str, fail: bool) -> (CountingAgent, Arc<AtomicU32>) {
+        let max_budget = Arc::new(AtomicU32::new(0));
+        let agent = CountingAgent {
+            name: name.to_string(),
+            max_actions: 3,
+            max_budget: max_budget.clone(),
+            fail,
+        };
+        (agent, max_budget)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval_schedule() {
+        let mut runtime = AgentRuntime::new();
+        let (counter, budget) = agent("counter", false);
+        let (failing, _) = agent("failing", true);
+        runtime.register(counter, Schedule::Interval(Duration::from_millis(10))).unwrap();
+        runtime.register(failing, Schedule::Interval(Duration::from_millis(10))).unwrap();
+
+        let (duplicate, _) = agent("counter", false);
+        assert_eq!(
+            runtime.register(duplicate, Schedule::Interval(Duration::from_secs(1))),
+            Err(AgentError::InvalidInput)
+        );
+        assert_eq!(runtime.agents(), vec!["counter", "failing"]);
+
+        advance(55).await;
+        let stats = runtime.stats("counter").unwrap();
+        assert!(stats.cycles >= 3);
+        assert_eq!(stats.actions, stats.cycles * 3);
+        assert_eq!(budget.load(Ordering::SeqCst), 3);
+
+        let failing = runtime.stats("failing").unwrap();
+        assert_eq!(failing.failures, failing.cycles);
+        assert_eq!(failing.last_error.as_deref(), Some("Processing error"));
+
+        // Shutdown returns once the tasks stop
+        tokio::time::timeout(Duration::from_secs(1), runtime.shutdown()).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_adaptive_schedule() {
+        let config = AutoscaleConfig {
+            min_interval: Duration::from_millis(10),
+            max_interval: Duration::from_millis(80),
+            ..AutoscaleConfig::default()
+        };
+        assert!(Schedule::adaptive(AutoscaleConfig { stretch: 0.5, ..config.clone() }).is_err());
+
+        let mut runtime = AgentRuntime::new();
+        let (counter, _) = agent("counter", false);
+        let (failing, _) = agent("failing", true);
+        runtime.register(counter, Schedule::adaptive(config.clone()).unwrap()).unwrap();
+        runtime.register(failing, Schedule::adaptive(config).unwrap()).unwrap();
+
+        // Failing cycles stretch the interval to 20, 40 then 80ms, so the
+        // failing agent runs at 0, 20, 60 and 140ms
+        advance(150).await;
+        let failing = runtime.stats("failing").unwrap();
+        assert_eq!(failing.cycles, 4);
+        assert_eq!(failing.interval, Some(Duration::from_millis(80)));
+
+        let counter = runtime.stats("counter").unwrap();
+        assert!(counter.cycles >= 14);
+        assert_eq!(counter.interval, Some(Duration::from_millis(10)));
+
+        runtime.shutdown().await;
+    }
+
+    /// Publishes its cycle number on `ticks`, or counts the ticks it
+    /// receives
+    struct Relay {
+        name: String,
+        bus: Option<MessageBus>,
+        publisher: bool,
+        cycles: u32,
+        received: Arc<AtomicU32>,
+    }
+
+    #[async_trait::async_trait]
+    impl ScheduledAgent for Relay {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn max_actions_per_cycle(&self) -> u32 {
+            1
+        }
+
+        async fn run_cycle(&mut self, _max_actions: u32) -> AgentResult<u32> {
+            self.cycles += 1;
+            if let (true, Some(bus)) = (self.publisher, &self.bus) {
+                bus.publish(&self.name, "ticks", &self.cycles)?;
+            }
+            Ok(0)
+        }
+
+        fn connect(&mut self, bus: MessageBus) {
+            if !self.publisher {
+                bus.subscribe(&self.name, "ticks");
+            }
+            self.bus = Some(bus);
+        }
+
+        async fn on_message(&mut self, message: Message) -> AgentResult<()> {
+            message.decode::<u32>()?;
+            self.received.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    impl AgentLifecycle for Relay {}
+
+    #[tokio::test(start_paused = true)]
+    async fn test_messages_between_cycles() {
+        let received = Arc::new(AtomicU32::new(0));
+        let relay = |name: &str, publisher| Relay {
+            name: name.to_string(),
+            bus: None,
+            publisher,
+            cycles: 0,
+            received: received.clone(),
+        };
+
+        let mut runtime = AgentRuntime::new();
+        runtime.register(relay("publisher", true), Schedule::Interval(Duration::from_millis(10))).unwrap();
+        // The subscriber's cycles are too far apart to see the ticks any
+        // other way
+        runtime.register(relay("subscriber", false), Schedule::Interval(Duration::from_secs(60))).unwrap();
+        assert_eq!(runtime.bus().agents(), vec!["publisher", "subscriber"]);
+
+        advance(55).await;
+        let subscriber = runtime.stats("subscriber").unwrap();
+        assert_eq!(subscriber.cycles, 1);
+        assert!(subscriber.messages >= 3);
+        assert_eq!(u64::from(received.load(Ordering::SeqCst)), subscriber.messages);
+
+        let bus = runtime.bus().clone();
+        tokio::time::timeout(Duration::from_secs(1), runtime.shutdown()).await.unwrap();
+        assert!(bus.agents().is_empty());
+    }
+
+    /// Records the hooks invoked on it; its cycles fail
+    struct Recorder {
+        hooks: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Recorder {
+        fn record(&self, hook: &str) {
+            self.hooks.lock().unwrap().push(hook.to_string());
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AgentLifecycle for Recorder {
+        async fn on_start(&mut self) -> AgentResult<()> {
+            self.record("start");
+            Ok(())
+        }
+
+        async fn on_pause(&mut self) -> AgentResult<()> {
+            self.record("pause");
+            Ok(())
+        }
+
+        async fn on_resume(&mut self) -> AgentResult<()> {
+            self.record("resume");
+            Ok(())
+        }
+
+        async fn on_error(&mut self, error: &AgentError) {
+            self.record(&format!("error: {}", error));
+        }
+
+        async fn on_shutdown(&mut self) -> AgentResult<()> {
+            self.record("shutdown");
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ScheduledAgent for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn max_actions_per_cycle(&self) -> u32 {
+            1
+        }
+
+        async fn run_cycle(&mut self, _max_actions: u32) -> AgentResult<u32> {
+            Err(AgentError::Timeout)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_lifecycle_hooks() {
+        let hooks = Arc::new(Mutex::new(Vec::new()));
+        let mut runtime = AgentRuntime::new();
+        runtime
+            .register(Recorder { hooks: hooks.clone() }, Schedule::Interval(Duration::from_millis(100)))
+            .unwrap();
+
+        advance(20).await;
+        runtime.pause("recorder").unwrap();
+        advance(150).await;
+        let paused = runtime.stats("recorder").unwrap();
+        assert_eq!((paused.state, paused.cycles), (RunState::Paused, 1));
+
+        runtime.resume("recorder").unwrap();
+        advance(20).await;
+        // The cycle that fell due while paused ran on resume
+        let resumed = runtime.stats("recorder").unwrap();
+        assert_eq!((resumed.state, resumed.cycles), (RunState::Running, 2));
+        assert_eq!(runtime.pause("nobody"), Err(AgentError::InvalidInput));
+
+        tokio::time::timeout(Duration::from_secs(1), runtime.shutdown()).await.unwrap();
+        assert_eq!(
+            *hooks.lock().unwrap(),
+            vec!["start", "error: Operation timeout", "pause", "resume", "error: Operation timeout", "shutdown"]
+        );
+    }
+
+    /// Counts its cycles, keeping the count in its checkpoint
+    struct Tally {
+        cycles: u64,
+    }
+
+    impl AgentLifecycle for Tally {}
+
+    #[async_trait::async_trait]
+    impl ScheduledAgent for Tally {
+        fn name(&self) -> &str {
+            "tally"
+        }
+
+        fn max_actions_per_cycle(&self) -> u32 {
+            1
+        }
+
+        async fn run_cycle(&mut self, _max_actions: u32) -> AgentResult<u32> {
+            self.cycles += 1;
+            Ok(1)
+        }
+
+        fn checkpoint(&self) -> AgentResult<Option<Value>> {
+            Ok(Some(Value::from(self.cycles)))
+        }
+
+        fn restore(&mut self, state: Value) -> AgentResult<()> {
+            self.cycles = state.as_u64().ok_or(AgentError::ValidationError)?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_checkpoint_restore() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(
+            StorageManager::new(crate::storage::StorageConfig {
+                base_dir: temp_dir.path().to_path_buf(),
+                ..Default::default()
+            })
+            .await
+            .unwrap(),
+        );
+        let config = CheckpointConfig::default();
+
+        let mut runtime = AgentRuntime::new().with_checkpoints(storage.clone(), config.clone());
+        runtime.register(Tally { cycles: 0 }, Schedule::Interval(Duration::from_millis(10))).unwrap();
+        // Looking for a checkpoint reads storage in real time
+        while runtime.stats("tally").unwrap().state != RunState::Running {
+            tokio::task::yield_now().await;
+        }
+        advance(35).await;
+        runtime.shutdown().await;
+
+        let saved = Checkpointer::new(storage.clone(), config.clone()).load("tally").await.unwrap().unwrap();
+        let cycles = saved.state().unwrap().as_u64().unwrap();
+        assert!(cycles >= 3);
+        assert_eq!(saved.stats.cycles, cycles);
+
+        // After a restart the agent and its statistics carry on
+        let mut runtime = AgentRuntime::new().with_checkpoints(storage, config);
+        runtime.register(Tally { cycles: 0 }, Schedule::Interval(Duration::from_secs(60))).unwrap();
+        // Restoring reads storage in real time, so wait for the first cycle
+        // without moving the clock towards the second
+        while runtime.stats("tally").unwrap().cycles <= cycles {
+            tokio::task::yield_now().await;
+        }
+        let stats = runtime.stats("tally").unwrap();
+        assert_eq!((stats.state, stats.cycles), (RunState::Running, cycles + 1));
+        runtime.shutdown().await;
+    }
+
+    /// Panics in its first cycle unless it is instance `healthy_from` or later
+    struct Flaky {
+        name: String,
+        instance: u32,
+        healthy_from: u32,
+    }
+
+    impl AgentLifecycle for Flaky {}
+
+    #[async_trait::async_trait]
+    impl ScheduledAgent for Flaky {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        fn max_actions_per_cycle(&self) -> u32 {
+            1
+        }
+
+        async fn run_cycle(&mut self, _max_actions: u32) -> AgentResult<u32> {
+            if self.instance < self.healthy_from {
+                panic!("strategy bug in instance {}", self.instance);
+            }
+            Ok(1)
+        }
+    }
+
+    fn flaky(name: &str, healthy_from: u32) -> impl FnMut() -> Flaky + Send + 'static {
+        let name = name.to_string();
+        let instances = Arc::new(AtomicU32::new(0));
+        move || Flaky {
+            name: name.clone(),
+            instance: instances.fetch_add(1, Ordering::SeqCst),
+            healthy_from,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervised_restarts() {
+        let policy = SupervisionPolicy {
+            max_restarts: 2,
+            initial_backoff: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let mut runtime = AgentRuntime::new();
+        let schedule = Schedule::Interval(Duration::from_secs(60));
+        runtime.register_supervised(flaky("recovers", 2), schedule.clone(), policy.clone()).unwrap();
+        runtime.register_supervised(flaky("hopeless", 10), schedule.clone(), policy).unwrap();
+        runtime.register(flaky("unsupervised", 1)(), schedule).unwrap();
+
+        advance(100).await;
+        let recovers = runtime.stats("recovers").unwrap();
+        assert_eq!((recovers.state, recovers.restarts, recovers.cycles), (RunState::Running, 2, 1));
+        assert_eq!(recovers.last_error.as_deref(), Some("panicked: strategy bug in instance 1"));
+
+        let hopeless = runtime.stats("hopeless").unwrap();
+        assert_eq!((hopeless.state, hopeless.restarts), (RunState::Failed, 2));
+        let unsupervised = runtime.stats("unsupervised").unwrap();
+        assert_eq!((unsupervised.state, unsupervised.restarts), (RunState::Failed, 0));
+        // The failed agents' mailboxes were closed
+        assert_eq!(runtime.bus().agents(), vec!["recovers"]);
+
+        tokio::time::timeout(Duration::from_secs(1), runtime.shutdown()).await.unwrap();
+    }
+
+    #[test]
+    fn test_cron_schedule() {
+        let schedule = Schedule::cron("* * * * * *").unwrap();
+        assert!(schedule.next_delay(true).unwrap() <= Duration::from_secs(1));
+        assert!(matches!(Schedule::cron("not cron"), Err(AgentError::InvalidConfiguration)));
+        assert_eq!(Schedule::Interval(Duration::from_secs(5)).next_delay(true), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,344 @@
+//! Slippage and price-impact protection of swaps
+//!
+//! This module provides:
+//! - `SwapQuote`: the output a route quotes for an input amount, and the
+//!   price impact of trading that size along it
+//! - `SlippageGuard`, refusing swaps on missing or stale quotes, or whose
+//!   price impact or limit price the quote breaches, and deriving the
+//!   minimum output the swap may accept
+//! - `SwapRouter`, the `OrderRouter` placing orders as swaps through a
+//!   `SwapVenue`, with the minimum output written into the swap
+//!   instruction
+//!
+//! Quotes are read from a `QuoteCache` the caller keeps fresh. The minimum
+//! output is the quoted output less the allowed slippage, raised to what
+//! the order's limit price requires, if it has one; the DEX then fails the
+//! swap rather than fill it for less.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::Instruction;
+use thiserror::Error;
+use crate::solana::program::action::{AgentAction, CpiAction};
+use crate::storage::{QuoteCache, QuoteKey};
+use super::error::{AgentError, AgentResult};
+use super::orders::{Order, OrderSide};
+use super::trading::OrderRouter;
+
+/// Output a route quotes for an input amount
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub amount_in: u64,
+    /// Output expected for `amount_in`
+    pub amount_out: u64,
+    /// Output per unit of input for an infinitesimal trade, e.g. the pool's
+    /// spot price
+    pub mid_price: f64,
+}
+
+impl SwapQuote {
+    /// Shortfall of the quoted output from the output at the mid price, in
+    /// basis points
+    pub fn price_impact_bps(&self) -> f64 {
+        let ideal = self.amount_in as f64 * self.mid_price;
+        if ideal <= 0.0 {
+            return 0.0;
+        }
+        ((ideal - self.amount_out as f64) / ideal * 10_000.0).max(0.0)
+    }
+
+    /// Quote for `amount_in` at the same rate, e.g. for a size sharing the
+    /// quote's cache bucket
+    pub fn scaled(&self, amount_in: u64) -> Self {
+        if amount_in == self.amount_in || self.amount_in == 0 {
+            return Self { amount_in, ..*self };
+        }
+        let amount_out = u128::from(self.amount_out) * u128::from(amount_in) / u128::from(self.amount_in);
+        Self {
+            amount_in,
+            amount_out: u64::try_from(amount_out).unwrap_or(u64::MAX),
+            mid_price: self.mid_price,
+        }
+    }
+}
+
+/// Limits swaps are checked against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlippageLimits {
+    /// Largest shortfall from the quoted output the swap accepts, in basis
+    /// points
+    pub max_slippage_bps: u32,
+    /// Largest price impact of the quote, in basis points; unchecked if
+    /// `None`
+    pub max_price_impact_bps: Option<u32>,
+    /// Oldest quote a swap is placed on
+    pub max_quote_age: Duration,
+}
+
+impl Default for SlippageLimits {
+    fn default() -> Self {
+        Self {
+            max_slippage_bps: 50,
+            max_price_impact_bps: Some(100),
+            max_quote_age: Duration::from_secs(2),
+        }
+    }
+}
+
+impl SlippageLimits {
+    pub fn validate(&self) -> AgentResult<()> {
+        let valid = self.max_slippage_bps < 10_000 && !self.max_quote_age.is_zero();
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidConfiguration)
+        }
+    }
+}
+
+/// Amounts a swap is placed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SwapTerms {
+    pub amount_in: u64,
+    /// Least output the swap accepts
+    pub minimum_amount_out: u64,
+}
+
+/// Why a swap was refused
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum SlippageViolation {
+    #[error("No quote for {input} to {output}")]
+    MissingQuote { input: String, output: String },
+
+    #[error("Quote is {age:?} old, older than the limit of {limit:?}")]
+    StaleQuote { age: Duration, limit: Duration },
+
+    #[error("Price impact of {impact_bps:.1} bps exceeds the limit of {limit_bps} bps")]
+    PriceImpact { impact_bps: f64, limit_bps: u32 },
+
+    #[error("Quoted output {quoted} is below the {required} the limit price requires")]
+    LimitPrice { quoted: u64, required: u64 },
+}
+
+/// Checks swaps against slippage limits before they are placed
+#[derive(Debug, Clone)]
+pub struct SlippageGuard {
+    limits: SlippageLimits,
+}
+
+impl SlippageGuard {
+    pub fn new(limits: SlippageLimits) -> AgentResult<Self> {
+        limits.validate()?;
+        Ok(Self { limits })
+    }
+
+    pub fn limits(&self) -> &SlippageLimits {
+        &self.limits
+    }
+
+    /// Terms of a swap on `quote`, fetched `age` ago, accepting no less
+    /// than `required` out
+    pub fn check(
+        &self,
+        quote: &SwapQuote,
+        age: Duration,
+        required: Option<u64>,
+    ) -> Result<SwapTerms, SlippageViolation> {
+        if age > self.limits.max_quote_age {
+            return Err(SlippageViolation::StaleQuote { age, limit: self.limits.max_quote_age });
+        }
+        let impact_bps = quote.price_impact_bps();
+        if let Some(limit_bps) = self.limits.max_price_impact_bps.filter(|&limit| impact_bps > f64::from(limit)) {
+            return Err(SlippageViolation::PriceImpact { impact_bps, limit_bps });
+        }
+        if let Some(required) = required.filter(|&required| quote.amount_out < required) {
+            return Err(SlippageViolation::LimitPrice { quoted: quote.amount_out, required });
+        }
+
+        let slipped = u128::from(quote.amount_out) * u128::from(10_000 - self.limits.max_slippage_bps) / 10_000;
+        Ok(SwapTerms {
+            amount_in: quote.amount_in,
+            minimum_amount_out: (slipped as u64).max(required.unwrap_or(0)),
+        })
+    }
+
+    /// Terms of a swap of `amount_in` on the quote cached under `key`,
+    /// scaled to that amount
+    pub fn check_cached(
+        &self,
+        cache: &QuoteCache<SwapQuote>,
+        key: &QuoteKey,
+        amount_in: u64,
+        required: Option<u64>,
+    ) -> Result<SwapTerms, SlippageViolation> {
+        let (quote, age) = cache.get_with_age(key).ok_or_else(|| SlippageViolation::MissingQuote {
+            input: key.input.clone(),
+            output: key.output.clone(),
+        })?;
+        self.check(&quote.scaled(amount_in), age, required)
+    }
+}
+
+/// Swap filling an order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapInput {
+    /// Token sold, as named in quote keys
+    pub input: String,
+    /// Token bought, as named in quote keys
+    pub output: String,
+    pub amount_in: u64,
+}
+
+/// DEX orders are swapped through
+pub trait SwapVenue: Send + Sync {
+    /// Tokens and input amount of the swap filling `order`
+    fn swap_input(&self, order: &Order) -> AgentResult<SwapInput>;
+
+    /// Instruction swapping `terms.amount_in`, failing if it would return
+    /// less than `terms.minimum_amount_out`
+    fn swap_instruction(&self, order: &Order, terms: &SwapTerms) -> AgentResult<Instruction>;
+}
+
+/// Routes orders as swaps through a venue, guarded against slippage
+pub struct SwapRouter {
+    venue: Arc<dyn SwapVenue>,
+    quotes: Arc<Mutex<QuoteCache<SwapQuote>>>,
+    guard: SlippageGuard,
+}
+
+impl SwapRouter {
+    pub fn new(venue: Arc<dyn SwapVenue>, quotes: Arc<Mutex<QuoteCache<SwapQuote>>>, guard: SlippageGuard) -> Self {
+        Self { venue, quotes, guard }
+    }
+}
+
+impl OrderRouter for SwapRouter {
+    fn route(&self, order: &Order) -> AgentResult<AgentAction> {
+        let swap = self.venue.swap_input(order)?;
+        let terms = {
+            let quotes = self.quotes.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let key = quotes.key(&swap.input, &swap.output, swap.amount_in);
+            let required = required_output(order, swap.amount_in);
+            self.guard.check_cached(&quotes, &key, swap.amount_in, required)
+        };
+        let terms = terms.map_err(|violation| {
+            println!("Swap of order {} refused: {}", order.request.client_order_id, violation);
+            AgentError::Custom(violation.to_string())
+        })?;
+
+        let instruction = self.venue.swap_instruction(order, &terms)?;
+        Ok(AgentAction::Swap {
+            cpi: CpiAction::from_instruction(&instruction),
+            amount_in: terms.amount_in,
+            minimum_amount_out: terms.minimum_amount_out,
+        })
+    }
+}
+
+/// Output a swap of `amount_in` must return to fill `order` at its limit
+/// price: quote units for a sell, base units for a buy
+fn required_output(order: &Order, amount_in: u64) -> Option<u64> {
+    let limit = order.request.limit_price.filter(|&limit| limit > 0.0)?;
+    let required = match order.request.side {
+        OrderSide::Sell => amount_in as f64 * limit,
+        OrderSide::Buy => amount_in as f64 / limit,
+    };
+    Some(required.ceil() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::agent::orders::{OrderManager, OrderRequest};
+    use crate::storage::QuoteCacheConfig;
+
+    fn quote(amount_out: u64) -> SwapQuote {
+        SwapQuote {
+            amount_in: 1_000,
+            amount_out,
+            mid_price: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_guard() {
+        assert!(SlippageGuard::new(SlippageLimits { max_slippage_bps: 10_000, ..SlippageLimits::default() }).is_err());
+        let guard = SlippageGuard::new(SlippageLimits::default()).unwrap();
+        let fresh = Duration::from_millis(100);
+
+        assert_eq!(
+            guard.check(&quote(99_500), fresh, None),
+            Ok(SwapTerms { amount_in: 1_000, minimum_amount_out: 99_002 })
+        );
+        assert_eq!(
+            guard.check(&quote(99_500), Duration::from_secs(3), None),
+            Err(SlippageViolation::StaleQuote { age: Duration::from_secs(3), limit: Duration::from_secs(2) })
+        );
+        assert!(matches!(guard.check(&quote(98_000), fresh, None), Err(SlippageViolation::PriceImpact { .. })));
+        assert_eq!(quote(99_500).scaled(1_100).amount_out, 109_450);
+
+        // A limit price tighter than the slippage raises the minimum
+        assert_eq!(guard.check(&quote(99_500), fresh, Some(99_400)).unwrap().minimum_amount_out, 99_400);
+        assert_eq!(
+            guard.check(&quote(99_500), fresh, Some(99_600)),
+            Err(SlippageViolation::LimitPrice { quoted: 99_500, required: 99_600 })
+        );
+    }
+
+    struct Dex {
+        program_id: Pubkey,
+    }
+
+    impl SwapVenue for Dex {
+        fn swap_input(&self, order: &Order) -> AgentResult<SwapInput> {
+            Ok(SwapInput {
+                input: "SOL".to_string(),
+                output: "USDC".to_string(),
+                amount_in: order.request.quantity,
+            })
+        }
+
+        fn swap_instruction(&self, _order: &Order, terms: &SwapTerms) -> AgentResult<Instruction> {
+            let data = [terms.amount_in.to_le_bytes(), terms.minimum_amount_out.to_le_bytes()].concat();
+            Ok(Instruction::new_with_bytes(self.program_id, &data, vec![]))
+        }
+    }
+
+    #[test]
+    fn test_router_threads_minimum_output() {
+        let quotes = Arc::new(Mutex::new(QuoteCache::new(QuoteCacheConfig::default())));
+        let dex = Dex { program_id: Pubkey::new_unique() };
+        let guard = SlippageGuard::new(SlippageLimits::default()).unwrap();
+        let router = SwapRouter::new(Arc::new(dex), quotes.clone(), guard);
+
+        let mut orders = OrderManager::new();
+        let order = orders
+            .create(OrderRequest {
+                client_order_id: "sell-1".to_string(),
+                agent: "trader".to_string(),
+                strategy: "test".to_string(),
+                market: "SOL/USDC".to_string(),
+                side: OrderSide::Sell,
+                quantity: 1_000,
+                limit_price: None,
+            })
+            .unwrap()
+            .clone();
+        assert!(router.route(&order).is_err());
+
+        {
+            let mut quotes = quotes.lock().unwrap();
+            let key = quotes.key("SOL", "USDC", 1_000);
+            quotes.insert(key, quote(99_500));
+        }
+        match router.route(&order).unwrap() {
+            AgentAction::Swap { cpi, amount_in, minimum_amount_out } => {
+                assert_eq!((amount_in, minimum_amount_out), (1_000, 99_002));
+                assert_eq!(cpi.data[8..], 99_002u64.to_le_bytes());
+            }
+            action => panic!("Routed to {:?}", action),
+        }
+    }
+}
@@ -1,116 +1,3 @@
-# Rust/Cargo
-/target/
-**/*.rs.bk
-Cargo.lock
+//! Agent lifecycle state, as stored on chain
 
-# Node.js
-node_modules/
-js/node_modules/
-npm-debug.log*
-yarn-debug.log*
-yarn-error.log*
-.npm
-.yarn-integrity
-.env
-.env.local
-.env.development.local
-.env.test.local
-.env.production.local
-
-# Build outputs
-/dist/
-/build/
-js/dist/
-js/build/
-
-# TypeScript
-*.tsbuildinfo
-
-# IDEs and editors
-.idea/
-.vscode/
-*.swp
-*.swo
-*.swn
-*.bak
-*.sublime-workspace
-*.sublime-project
-
-# OS generated files
-.DS_Store
-.DS_Store?
-._*
-.Spotlight-V100
-.Trashes
-ehthumbs.db
-Thumbs.db
-
-# Test coverage
-coverage/
-.nyc_output/
-
-# Documentation
-/docs/
-
-# Solana
-test-ledger/
-.anchor/
-
-# Logs
-logs
-*.log
-
-# Runtime data
-pids
-*.pid
-*.seed
-*.pid.lock
-
-# Optional npm cache directory
-.npm
-
-# Optional eslint cache
-.eslintcache
-
-# Optional REPL history
-.node_repl_history
-
-# Output of 'npm pack'
-*.tgz
-
-# Yarn Integrity file
-.yarn-integrity
-
-# dotenv environment variable files
-.env
-.env.test
-.env.production
-
-# parcel-bundler cache
-.cache
-.parcel-cache
-
-# Next.js build output
-.next
-out
-
-# Nuxt.js build / generate output
-.nuxt
-dist
-
-# Gatsby files
-.cache/
-public
-
-# Mac files
-.DS_Store
-
-# Windows files
-Thumbs.db
-Desktop.ini
-
-# Linux files
-*~
-
-# Rust IDE specific files
-*.iml
\ No newline at end of file
+pub use crate::solana::program::state::{AgentState, PauseReason};
@@ -0,0 +1,156 @@
+//! Supervision of runtime agents
+//!
+//! This module provides:
+//! - `SupervisionPolicy`: how often a failed agent is restarted, and how
+//!   long the runtime waits before each restart
+//! - Escalation once an agent fails more often than its budget allows,
+//!   e.g. pausing its on-chain agent so nothing acts for it meanwhile
+//!
+//! An agent fails when its task panics or it fails to start; a failed
+//! cycle is not a failure of the agent. The runtime restarts a failed
+//! agent with a fresh instance from its factory (see
+//! `AgentRuntime::register_supervised`), restored from its checkpoint if
+//! the runtime keeps them.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::solana::program::state::PauseReason;
+use super::base::Agent;
+
+/// What the runtime does once an agent exhausted its restart budget
+#[derive(Debug, Clone)]
+pub enum Escalation {
+    /// Leave the agent stopped
+    Stop,
+    /// Leave the agent stopped and pause its on-chain agent
+    PauseOnChain(Arc<Agent>),
+}
+
+impl Escalation {
+    pub(crate) async fn escalate(&self, name: &str) {
+        println!("Agent {} exhausted its restart budget, leaving it stopped", name);
+        if let Escalation::PauseOnChain(agent) = self {
+            let agent = agent.clone();
+            match tokio::task::spawn_blocking(move || agent.pause_for(PauseReason::Emergency)).await {
+                Ok(Ok(signature)) => println!("Paused on-chain agent of {}: {}", name, signature),
+                Ok(Err(e)) => println!("Failed to pause on-chain agent of {}: {}", name, e),
+                Err(e) => println!("Failed to pause on-chain agent of {}: {}", name, e),
+            }
+        }
+    }
+}
+
+/// How failed agents are restarted
+#[derive(Debug, Clone)]
+pub struct SupervisionPolicy {
+    /// Restarts allowed within `window`; once exceeded the agent is
+    /// escalated
+    pub max_restarts: u32,
+    pub window: Duration,
+    /// Wait before the first restart, doubled for each further one within
+    /// the window
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub escalation: Escalation,
+}
+
+impl Default for SupervisionPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            window: Duration::from_secs(600),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            escalation: Escalation::Stop,
+        }
+    }
+}
+
+impl SupervisionPolicy {
+    /// Policy escalating the first failure
+    pub fn never_restart() -> Self {
+        Self {
+            max_restarts: 0,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_escalation(mut self, escalation: Escalation) -> Self {
+        self.escalation = escalation;
+        self
+    }
+}
+
+/// Restarts of an agent within its policy's window
+pub(crate) struct RestartBudget {
+    policy: SupervisionPolicy,
+    restarts: VecDeque<Instant>,
+    backoff: Duration,
+}
+
+impl RestartBudget {
+    pub(crate) fn new(policy: SupervisionPolicy) -> Self {
+        let backoff = policy.initial_backoff;
+        Self {
+            policy,
+            restarts: VecDeque::new(),
+            backoff,
+        }
+    }
+
+    pub(crate) fn escalation(&self) -> &Escalation {
+        &self.policy.escalation
+    }
+
+    /// Wait before restarting after a failure at `now`; `None` once the
+    /// budget is exhausted
+    pub(crate) fn restart(&mut self, now: Instant) -> Option<Duration> {
+        while self
+            .restarts
+            .front()
+            .map_or(false, |restart| now.duration_since(*restart) >= self.policy.window)
+        {
+            self.restarts.pop_front();
+        }
+        if self.restarts.is_empty() {
+            self.backoff = self.policy.initial_backoff;
+        }
+        if self.restarts.len() >= self.policy.max_restarts as usize {
+            return None;
+        }
+
+        self.restarts.push_back(now);
+        let backoff = self.backoff;
+        self.backoff = self.backoff.saturating_mul(2).min(self.policy.max_backoff);
+        Some(backoff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_budget() {
+        let policy = SupervisionPolicy {
+            max_restarts: 3,
+            window: Duration::from_secs(60),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+            escalation: Escalation::Stop,
+        };
+        let mut budget = RestartBudget::new(policy);
+        let start = Instant::now();
+
+        let backoffs: Vec<_> = (0..4).map(|i| budget.restart(start + Duration::from_secs(i))).collect();
+        let secs = Duration::from_secs;
+        assert_eq!(backoffs, vec![Some(secs(1)), Some(secs(2)), Some(secs(3)), None]);
+
+        // Restarts leave the window, and the backoff resets once none is left
+        assert_eq!(budget.restart(start + secs(61)), Some(secs(3)));
+        assert_eq!(budget.restart(start + secs(200)), Some(secs(1)));
+
+        assert_eq!(RestartBudget::new(SupervisionPolicy::never_restart()).restart(start), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Templates for fleets of similar agents
+//!
+//! This module provides:
+//! - `AgentTemplate`: a name pattern, config (with its capabilities) and
+//!   strategy shared by every agent spawned from it
+//! - Sequential agent names, e.g. `trader-0`, `trader-1`, ...
+//!
+//! Agents are spawned from a template with `Sonoma::spawn_from_template`,
+//! or as a whole fleet with `Sonoma::spawn_fleet`.
+//! Each one gets a strategy of its own from the template's factory, as
+//! strategies keep state.
+
+use std::fmt;
+use std::sync::Arc;
+use crate::solana::program::state::MAX_NAME_LEN;
+use crate::validation::{ConfigErrors, Validate, Violations};
+use super::base::{Agent, AgentConfig, Capabilities};
+use super::capabilities::AgentCapabilities;
+use super::rules::StrategyConfig;
+use super::trading::Strategy;
+
+/// Placeholder replaced by an agent's index in name patterns
+pub const INDEX_PLACEHOLDER: &str = "{index}";
+
+/// Makes the strategy of the agent spawned at an index
+pub type StrategyFactory = Arc<dyn Fn(usize) -> Box<dyn Strategy> + Send + Sync>;
+
+/// Shared settings of agents spawned as a fleet
+#[derive(Clone)]
+pub struct AgentTemplate {
+    /// Name of the agents, with `{index}` standing for each one's index;
+    /// `-{index}` is appended if the pattern doesn't contain it
+    pub name_pattern: String,
+    /// Config of the agents, capabilities included
+    pub config: AgentConfig,
+    pub strategy: Option<StrategyFactory>,
+}
+
+impl fmt::Debug for AgentTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AgentTemplate")
+            .field("name_pattern", &self.name_pattern)
+            .field("config", &self.config)
+            .field("strategy", &self.strategy.is_some())
+            .finish()
+    }
+}
+
+impl AgentTemplate {
+    pub fn new(name_pattern: &str, config: AgentConfig) -> Self {
+        Self {
+            name_pattern: name_pattern.to_string(),
+            config,
+            strategy: None,
+        }
+    }
+
+    /// Replace the capabilities of the template's config
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.config.capabilities = capabilities;
+        self
+    }
+
+    /// Give each agent the strategy `factory` makes for its index
+    pub fn with_strategy<F>(mut self, factory: F) -> Self
+    where
+        F: Fn(usize) -> Box<dyn Strategy> + Send + Sync + 'static,
+    {
+        self.strategy = Some(Arc::new(factory));
+        self
+    }
+
+    /// Give each agent a rule-based strategy compiled from `config`
+    pub fn with_rules(self, config: StrategyConfig) -> Self {
+        self.with_strategy(move |_| Box::new(config.clone().compile()))
+    }
+
+    /// Name of the agent at `index`
+    pub fn name(&self, index: usize) -> String {
+        if self.name_pattern.contains(INDEX_PLACEHOLDER) {
+            self.name_pattern.replace(INDEX_PLACEHOLDER, &index.to_string())
+        } else {
+            format!("{}-{}", self.name_pattern, index)
+        }
+    }
+
+    /// Config of the agents
+    pub fn agent_config(&self) -> AgentConfig {
+        self.config.clone()
+    }
+
+    /// Strategy of the agent at `index`, if the template has one
+    pub fn strategy(&self, index: usize) -> Option<Box<dyn Strategy>> {
+        self.strategy.as_ref().map(|factory| factory(index))
+    }
+
+    /// Validate the template for the agent at `index`, whose name must fit
+    /// on-chain
+    pub fn validate_instance(&self, index: usize) -> Result<(), ConfigErrors> {
+        Instance { template: self, index }.validate()
+    }
+}
+
+impl Validate for AgentTemplate {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            !self.name_pattern.is_empty(),
+            "name_pattern",
+            "must not be empty",
+            "use a pattern such as \"trader-{index}\"",
+        );
+        violations.nested("config", &self.config);
+    }
+}
+
+/// Template applied at an index
+struct Instance<'a> {
+    template: &'a AgentTemplate,
+    index: usize,
+}
+
+impl Validate for Instance<'_> {
+    fn collect_violations(&self, violations: &mut Violations) {
+        self.template.collect_violations(violations);
+        let name = self.template.name(self.index);
+        violations.check(
+            name.len() <= MAX_NAME_LEN,
+            "name_pattern",
+            &format!("name \"{}\" is longer than {} bytes", name, MAX_NAME_LEN),
+            "shorten the pattern",
+        );
+    }
+}
+
+/// Agent spawned from a template
+pub struct SpawnedAgent {
+    pub index: usize,
+    pub agent: Agent,
+    pub capabilities: AgentCapabilities,
+    pub strategy: Option<Box<dyn Strategy>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::client_config;
+
+    #[test]
+    fn test_names_and_config() {
+        let template = AgentTemplate::new("trader-{index}-sol", client_config()).with_capabilities(Capabilities {
+            compute: true,
+            custom_capabilities: vec!["trading".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(template.name(7), "trader-7-sol");
+        assert_eq!(AgentTemplate::new("scout", client_config()).name(0), "scout-0");
+        assert!(template.agent_config().capabilities.compute);
+        assert!(template.strategy(0).is_none());
+        assert!(template.validate_instance(12).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let errors = AgentTemplate::new("", client_config()).validate().unwrap_err();
+        assert_eq!(errors.0[0].field, "name_pattern");
+
+        let long = AgentTemplate::new(&"x".repeat(MAX_NAME_LEN - 2), client_config());
+        assert!(long.validate_instance(9).is_ok());
+        assert_eq!(long.validate_instance(10).unwrap_err().0[0].field, "name_pattern");
+
+        let mut unlimited = AgentTemplate::new("trader", client_config());
+        unlimited.config.execution_limit = 0;
+        assert_eq!(unlimited.validate().unwrap_err().0[0].field, "config.execution_limit");
+    }
+}
@@ -0,0 +1,679 @@
+//! Trading agent driven by a pluggable strategy
+//!
+//! This module provides:
+//! - The `Strategy` trait: reacting to market ticks and fills, and
+//!   generating orders
+//! - `TradingAgent`, passing strategy orders through the `OrderManager`
+//!   lifecycle to an `OrderExecutor`
+//! - `OnChainExecutor`, submitting orders as the agent's on-chain
+//!   executions through an `OrderRouter`
+//! - Execution modes: live, paper trading against live prices, and dry runs
+//! - Risk checks on every order before it is submitted (see `risk`)
+//! - The agent's `Portfolio`, updated from its fills and marked at each
+//!   tick, kept apart for each execution mode
+//!
+//! An execution landing only means the order was submitted; fills are
+//! applied from execution reports (see `TradingAgent::on_execution_report`),
+//! which also pass them on to the strategy.
+//!
+//! In paper mode orders never reach the executor. They are filled locally
+//! at the prices of the ticks the agent receives, with the slippage and
+//! fees of its `FillModel`: market orders on the tick they are placed, and
+//! limit orders once a tick reaches their limit. A dry run only records the
+//! orders the strategy would place.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::solana::program::action::AgentAction;
+use super::backtest::FillModel;
+use super::base::Agent;
+use super::error::{AgentError, AgentResult};
+use super::orders::{
+    ExecutionOutcome, ExecutionReport, Order, OrderExecutor, OrderManager, OrderRequest, OrderSide, OrderStatus,
+};
+use super::portfolio::Portfolio;
+use super::risk::{OpenOrders, RiskManager, RiskViolation};
+
+/// Price observed on a market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketTick {
+    pub market: String,
+    pub price: f64,
+    /// Unix time of the observation
+    pub timestamp: u64,
+}
+
+/// Part of an order that was filled
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    pub quantity: u64,
+    pub price: f64,
+}
+
+/// Where a `TradingAgent`'s orders go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExecutionMode {
+    /// Submitted to the agent's executor
+    #[default]
+    Live,
+    /// Filled locally at live prices, never submitted
+    Paper,
+    /// Recorded as submitted, never submitted nor filled
+    DryRun,
+}
+
+impl ExecutionMode {
+    pub const ALL: [ExecutionMode; 3] = [ExecutionMode::Live, ExecutionMode::Paper, ExecutionMode::DryRun];
+}
+
+/// Fill simulated in paper mode
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulatedFill {
+    pub client_order_id: String,
+    pub market: String,
+    pub side: OrderSide,
+    pub quantity: u64,
+    pub price: f64,
+    pub fee: f64,
+    /// Unix time of the tick the order was filled on
+    pub timestamp: u64,
+}
+
+/// Outcome of cancelling every open order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CancelReport {
+    pub cancelled: Vec<Order>,
+    /// Orders the executor failed to cancel, still open, e.g. on-chain
+    /// orders whose execution already landed
+    pub failed: Vec<(Order, AgentError)>,
+}
+
+/// Trading logic run by a `TradingAgent`
+pub trait Strategy: Send {
+    /// Name recorded on the strategy's orders
+    fn name(&self) -> &str;
+
+    /// Observe a new market price
+    fn on_tick(&mut self, tick: &MarketTick);
+
+    /// Observe a fill of one of the strategy's orders; `order` already
+    /// includes it
+    fn on_fill(&mut self, order: &Order, fill: &Fill);
+
+    /// Observe one of the strategy's orders closing before it filled
+    /// completely, cancelled or rejected; its remaining quantity won't fill
+    fn on_order_closed(&mut self, _order: &Order) {}
+
+    /// Orders to place now, after the last tick or fill
+    ///
+    /// Requests with an empty `client_order_id` get one assigned; the
+    /// agent and strategy names are filled in by the agent.
+    fn generate_orders(&mut self) -> Vec<OrderRequest>;
+}
+
+/// Agent running a strategy and managing the orders it places
+pub struct TradingAgent {
+    name: String,
+    strategy: Box<dyn Strategy>,
+    executor: Arc<dyn OrderExecutor>,
+    /// Executor standing in for `executor` outside live mode
+    simulated: Arc<SimulatedExecutor>,
+    mode: ExecutionMode,
+    /// Mode each order was placed in
+    order_modes: HashMap<String, ExecutionMode>,
+    fill_model: FillModel,
+    simulated_fills: Vec<SimulatedFill>,
+    /// Holdings of each mode, so simulated fills never reach the live
+    /// portfolio synced with on-chain balances
+    portfolios: HashMap<ExecutionMode, Portfolio>,
+    risk: Option<RiskManager>,
+    /// Latest price of each market ticked
+    last_prices: HashMap<String, f64>,
+    /// Unix time of the latest tick, which fills are dated with
+    clock: Option<u64>,
+    orders: OrderManager,
+    next_order: u64,
+}
+
+impl TradingAgent {
+    pub fn new(name: &str, strategy: Box<dyn Strategy>, executor: Arc<dyn OrderExecutor>) -> Self {
+        Self {
+            name: name.to_string(),
+            strategy,
+            executor,
+            simulated: Arc::new(SimulatedExecutor::new("simulated")),
+            mode: ExecutionMode::Live,
+            order_modes: HashMap::new(),
+            fill_model: FillModel::default(),
+            simulated_fills: Vec::new(),
+            portfolios: ExecutionMode::ALL.into_iter().map(|mode| (mode, Portfolio::new())).collect(),
+            risk: None,
+            last_prices: HashMap::new(),
+            clock: None,
+            orders: OrderManager::new(),
+            next_order: 0,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: ExecutionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Slippage and fees of fills simulated in paper mode
+    pub fn with_fill_model(mut self, fill_model: FillModel) -> Self {
+        self.fill_model = fill_model;
+        self
+    }
+
+    /// Track live holdings in `portfolio`, e.g. one reading token
+    /// balances; paper trading and dry runs start from a copy of it
+    pub fn with_portfolio(mut self, portfolio: Portfolio) -> Self {
+        for mode in [ExecutionMode::Paper, ExecutionMode::DryRun] {
+            self.portfolios.insert(mode, portfolio.clone());
+        }
+        self.portfolios.insert(ExecutionMode::Live, portfolio);
+        self
+    }
+
+    /// Check every order against `risk` before submitting it; orders
+    /// breaching a limit are rejected
+    pub fn with_risk(mut self, risk: RiskManager) -> Self {
+        self.risk = Some(risk);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Portfolio of the current mode
+    pub fn portfolio(&self) -> &Portfolio {
+        self.portfolio_in(self.mode)
+    }
+
+    pub fn portfolio_in(&self, mode: ExecutionMode) -> &Portfolio {
+        &self.portfolios[&mode]
+    }
+
+    /// Mutable live portfolio, e.g. to sync it with balances read on-chain;
+    /// paper and dry-run holdings are left alone
+    pub fn portfolio_mut(&mut self) -> &mut Portfolio {
+        self.portfolios.entry(ExecutionMode::Live).or_default()
+    }
+
+    pub fn risk(&self) -> Option<&RiskManager> {
+        self.risk.as_ref()
+    }
+
+    pub fn risk_mut(&mut self) -> Option<&mut RiskManager> {
+        self.risk.as_mut()
+    }
+
+    pub fn mode(&self) -> ExecutionMode {
+        self.mode
+    }
+
+    /// Switch modes; orders already placed stay in the mode they were
+    /// placed in
+    pub fn set_mode(&mut self, mode: ExecutionMode) {
+        self.mode = mode;
+    }
+
+    /// Fills simulated in paper mode, oldest first
+    pub fn simulated_fills(&self) -> &[SimulatedFill] {
+        &self.simulated_fills
+    }
+
+    pub fn orders(&self) -> &OrderManager {
+        &self.orders
+    }
+
+    /// Pass `tick` to the strategy and submit the orders it generates,
+    /// returning them as submitted or rejected
+    pub async fn on_tick(&mut self, tick: &MarketTick) -> AgentResult<Vec<Order>> {
+        self.last_prices.insert(tick.market.clone(), tick.price);
+        self.clock = self.clock.max(Some(tick.timestamp));
+        for portfolio in self.portfolios.values_mut() {
+            portfolio.mark(&tick.market, tick.price);
+        }
+        self.strategy.on_tick(tick);
+        let placed = self.place_orders().await?;
+        if self.mode == ExecutionMode::Paper {
+            self.fill_paper_orders(tick).await?;
+        }
+        Ok(placed)
+    }
+
+    /// Apply an execution report to its order, passing fills on to the
+    /// strategy, then submit any orders the strategy generates in response
+    ///
+    /// Fills are dated with the latest tick for the daily loss limit, or
+    /// the current time before the first tick.
+    pub async fn on_execution_report(&mut self, report: ExecutionReport) -> AgentResult<Vec<Order>> {
+        let fill = match report.outcome {
+            ExecutionOutcome::Fill { quantity, price } => Some(Fill { quantity, price }),
+            _ => None,
+        };
+        let order = self.orders.reconcile(report)?.clone();
+
+        match fill {
+            Some(fill) => {
+                let mode = self.order_modes.get(&order.request.client_order_id).copied().unwrap_or(self.mode);
+                let realized = self.portfolios.entry(mode).or_default().apply_fill(&order, &fill);
+                if let Some(risk) = &mut self.risk {
+                    // Dated by the market's clock, so backtests and paper
+                    // fills count losses on the day they were simulated
+                    let timestamp = self.clock.unwrap_or_else(|| {
+                        std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs()
+                    });
+                    risk.record_pnl(realized, timestamp).await;
+                }
+                self.strategy.on_fill(&order, &fill);
+                self.place_orders().await
+            }
+            None => {
+                self.closed(&order);
+                Ok(Vec::new())
+            }
+        }
+    }
+
+    /// Cancel every open order, carrying on past the orders the executor
+    /// fails to cancel, which stay open and are reported with the error
+    pub async fn cancel_all(&mut self) -> CancelReport {
+        let open: Vec<Order> = self.orders.open_orders().into_iter().cloned().collect();
+
+        let mut report = CancelReport::default();
+        for order in open {
+            let client_order_id = &order.request.client_order_id;
+            let executor = self.executor_of(client_order_id);
+            match self.orders.cancel(client_order_id, executor.as_ref()).await {
+                Ok(cancelled) => {
+                    self.closed(&cancelled);
+                    report.cancelled.push(cancelled);
+                }
+                Err(e) => {
+                    println!("Order {} of agent {} could not be cancelled: {}", client_order_id, self.name, e);
+                    report.failed.push((order, e));
+                }
+            }
+        }
+        report
+    }
+
+    /// Tell the strategy about `order` if it closed unfilled
+    fn closed(&mut self, order: &Order) {
+        if matches!(order.status, OrderStatus::Cancelled | OrderStatus::Rejected) {
+            self.strategy.on_order_closed(order);
+        }
+    }
+
+    /// Check `request` against the risk limits, counting the agent's
+    /// holdings and open orders in its market and the current mode towards
+    /// the position
+    fn check_risk(&self, request: &OrderRequest) -> Result<(), RiskViolation> {
+        let Some(risk) = &self.risk else {
+            return Ok(());
+        };
+        let mut open = OpenOrders::default();
+        for order in self.orders.open_orders() {
+            let mode = self.order_modes.get(&order.request.client_order_id).copied().unwrap_or(self.mode);
+            if order.request.market == request.market && mode == self.mode {
+                let side = match order.request.side {
+                    OrderSide::Buy => &mut open.buys,
+                    OrderSide::Sell => &mut open.sells,
+                };
+                *side = side.saturating_add(order.remaining_quantity());
+            }
+        }
+        let price = self.last_prices.get(&request.market).copied();
+        risk.check(request, self.portfolio(), price, open)
+    }
+
+    /// Executor of the mode an order was placed in
+    fn executor_of(&self, client_order_id: &str) -> Arc<dyn OrderExecutor> {
+        match self.order_modes.get(client_order_id) {
+            Some(ExecutionMode::Paper | ExecutionMode::DryRun) => self.simulated.clone(),
+            _ => self.executor.clone(),
+        }
+    }
+
+    /// Fill the open paper orders on `tick`'s market that its price reaches
+    async fn fill_paper_orders(&mut self, tick: &MarketTick) -> AgentResult<()> {
+        let open: Vec<Order> = self
+            .orders
+            .open_orders()
+            .into_iter()
+            .filter(|order| order.request.market == tick.market)
+            .filter(|order| self.order_modes.get(&order.request.client_order_id) == Some(&ExecutionMode::Paper))
+            .cloned()
+            .collect();
+
+        for order in open {
+            let side = order.request.side;
+            let price = match (order.request.limit_price, side) {
+                (None, _) => self.fill_model.market_price(side, tick.price),
+                (Some(limit), OrderSide::Buy) if tick.price <= limit => tick.price,
+                (Some(limit), OrderSide::Sell) if tick.price >= limit => tick.price,
+                _ => continue,
+            };
+            let quantity = order.remaining_quantity();
+            self.simulated_fills.push(SimulatedFill {
+                client_order_id: order.request.client_order_id.clone(),
+                market: order.request.market.clone(),
+                side,
+                quantity,
+                price,
+                fee: self.fill_model.fee(quantity as f64 * price),
+                timestamp: tick.timestamp,
+            });
+            self.on_execution_report(ExecutionReport {
+                client_order_id: order.request.client_order_id,
+                submission_id: order.submission_id,
+                outcome: ExecutionOutcome::Fill { quantity, price },
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn place_orders(&mut self) -> AgentResult<Vec<Order>> {
+        let mut placed = Vec::new();
+        for mut request in self.strategy.generate_orders() {
+            if request.client_order_id.is_empty() {
+                self.next_order += 1;
+                request.client_order_id = format!("{}-{}-{}", self.name, self.strategy.name(), self.next_order);
+            }
+            request.agent = self.name.clone();
+            request.strategy = self.strategy.name().to_string();
+
+            let client_order_id = request.client_order_id.clone();
+            let checked = self.check_risk(&request);
+            self.orders.create(request)?;
+            if let Err(violation) = checked {
+                println!("Order {} of {} refused: {}", client_order_id, self.name, violation);
+                let order = self.orders.reject(&client_order_id)?;
+                self.closed(&order);
+                placed.push(order);
+                continue;
+            }
+            self.order_modes.entry(client_order_id.clone()).or_insert(self.mode);
+            if self.mode == ExecutionMode::DryRun {
+                println!("Dry run of {} would place order {}", self.name, client_order_id);
+            }
+            let executor = self.executor_of(&client_order_id);
+            let order = self.orders.submit(&client_order_id, executor.as_ref()).await?;
+            self.closed(&order);
+            placed.push(order);
+        }
+        Ok(placed)
+    }
+}
+
+/// Executor accepting every order without submitting it anywhere
+pub(crate) struct SimulatedExecutor {
+    prefix: &'static str,
+    submissions: AtomicU64,
+}
+
+impl SimulatedExecutor {
+    pub(crate) fn new(prefix: &'static str) -> Self {
+        Self {
+            prefix,
+            submissions: AtomicU64::new(0),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderExecutor for SimulatedExecutor {
+    async fn submit(&self, _order: &Order) -> AgentResult<String> {
+        Ok(format!("{}-{}", self.prefix, self.submissions.fetch_add(1, Ordering::SeqCst)))
+    }
+
+    async fn cancel(&self, _order: &Order) -> AgentResult<()> {
+        Ok(())
+    }
+}
+
+/// Turns orders into the agent actions that place them on-chain, e.g. a
+/// `Swap` through a DEX
+pub trait OrderRouter: Send + Sync {
+    fn route(&self, order: &Order) -> AgentResult<AgentAction>;
+}
+
+/// Submits each order as the next execution of an on-chain agent
+pub struct OnChainExecutor {
+    agent: Arc<Agent>,
+    router: Arc<dyn OrderRouter>,
+}
+
+impl OnChainExecutor {
+    pub fn new(agent: Arc<Agent>, router: Arc<dyn OrderRouter>) -> Self {
+        Self { agent, router }
+    }
+}
+
+#[async_trait::async_trait]
+impl OrderExecutor for OnChainExecutor {
+    /// Execute the routed action, returning the transaction signature
+    async fn submit(&self, order: &Order) -> AgentResult<String> {
+        let action = self.router.route(order)?;
+        action.validate().map_err(|_| AgentError::InvalidInput)?;
+
+        let agent = self.agent.clone();
+        let signature = tokio::task::spawn_blocking(move || agent.execute_action(action))
+            .await
+            .map_err(|e| AgentError::Custom(e.to_string()))?
+            .map_err(|e| AgentError::Custom(e.to_string()))?;
+        Ok(signature.to_string())
+    }
+
+    /// Executions land atomically, so there is nothing left to cancel once
+    /// submitted
+    async fn cancel(&self, _order: &Order) -> AgentResult<()> {
+        Err(AgentError::InvalidStateTransition)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use crate::agent::orders::{OrderSide, OrderStatus};
+    use crate::agent::risk::RiskLimits;
+
+    struct MockExecutor {
+        submissions: AtomicU32,
+    }
+
+    #[async_trait::async_trait]
+    impl OrderExecutor for MockExecutor {
+        async fn submit(&self, _order: &Order) -> AgentResult<String> {
+            let n = self.submissions.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("sig-{}", n))
+        }
+
+        async fn cancel(&self, _order: &Order) -> AgentResult<()> {
+            Ok(())
+        }
+    }
+
+    /// Buys 10 whenever the price drops below `threshold`, until it holds
+    /// `target`
+    struct DipBuyer {
+        threshold: f64,
+        target: u64,
+        position: u64,
+        last_price: Option<f64>,
+    }
+
+    impl Strategy for DipBuyer {
+        fn name(&self) -> &str {
+            "dip"
+        }
+
+        fn on_tick(&mut self, tick: &MarketTick) {
+            self.last_price = Some(tick.price);
+        }
+
+        fn on_fill(&mut self, _order: &Order, fill: &Fill) {
+            self.position += fill.quantity;
+        }
+
+        fn generate_orders(&mut self) -> Vec<OrderRequest> {
+            match self.last_price.take() {
+                Some(price) if price < self.threshold && self.position < self.target => vec![OrderRequest {
+                    client_order_id: String::new(),
+                    agent: String::new(),
+                    strategy: String::new(),
+                    market: "SOL/USDC".to_string(),
+                    side: OrderSide::Buy,
+                    quantity: 10,
+                    limit_price: Some(price),
+                }],
+                _ => vec![],
+            }
+        }
+    }
+
+    fn tick(price: f64) -> MarketTick {
+        MarketTick {
+            market: "SOL/USDC".to_string(),
+            price,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strategy_lifecycle() {
+        let executor = Arc::new(MockExecutor { submissions: AtomicU32::new(0) });
+        let strategy = DipBuyer { threshold: 20.0, target: 10, position: 0, last_price: None };
+        let mut agent = TradingAgent::new("trader", Box::new(strategy), executor.clone());
+
+        assert!(agent.on_tick(&tick(21.0)).await.unwrap().is_empty());
+        let placed = agent.on_tick(&tick(19.0)).await.unwrap();
+        assert_eq!(placed.len(), 1);
+        assert_eq!(placed[0].status, OrderStatus::Submitted);
+        assert_eq!(placed[0].request.client_order_id, "trader-dip-1");
+        assert_eq!((placed[0].request.agent.as_str(), placed[0].request.strategy.as_str()), ("trader", "dip"));
+
+        let after_fill = agent
+            .on_execution_report(ExecutionReport {
+                client_order_id: "trader-dip-1".to_string(),
+                submission_id: Some("sig-0".to_string()),
+                outcome: ExecutionOutcome::Fill { quantity: 10, price: 19.0 },
+            })
+            .await
+            .unwrap();
+        assert!(after_fill.is_empty());
+        assert_eq!(agent.orders().get("trader-dip-1").unwrap().status, OrderStatus::Filled);
+
+        // The target position is reached
+        assert!(agent.on_tick(&tick(18.0)).await.unwrap().is_empty());
+        assert_eq!(executor.submissions.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_paper_and_dry_run_modes() {
+        let executor = Arc::new(MockExecutor { submissions: AtomicU32::new(0) });
+        let strategy = DipBuyer { threshold: 20.0, target: 10, position: 0, last_price: None };
+        let mut paper = TradingAgent::new("trader", Box::new(strategy), executor.clone())
+            .with_mode(ExecutionMode::Paper)
+            .with_fill_model(FillModel { slippage_bps: 0.0, fee_bps: 10.0 });
+
+        let placed = paper.on_tick(&tick(19.0)).await.unwrap();
+        assert_eq!(placed[0].submission_id.as_deref(), Some("simulated-0"));
+        // Filled at the tick's price, reaching the target
+        assert_eq!(paper.orders().get("trader-dip-1").unwrap().status, OrderStatus::Filled);
+        let fill = &paper.simulated_fills()[0];
+        assert_eq!((fill.quantity, fill.price, fill.fee), (10, 19.0, 0.19));
+        assert!(paper.on_tick(&tick(18.0)).await.unwrap().is_empty());
+
+        let strategy = DipBuyer { threshold: 20.0, target: 10, position: 0, last_price: None };
+        let mut dry_run =
+            TradingAgent::new("trader", Box::new(strategy), executor.clone()).with_mode(ExecutionMode::DryRun);
+        dry_run.on_tick(&tick(19.0)).await.unwrap();
+        dry_run.on_tick(&tick(18.0)).await.unwrap();
+        assert_eq!(dry_run.orders().open_orders().len(), 2);
+        assert!(dry_run.simulated_fills().is_empty());
+
+        // Neither mode reached the executor, nor the live portfolio
+        assert_eq!(executor.submissions.load(Ordering::SeqCst), 0);
+        assert_eq!(paper.portfolio().position("SOL/USDC"), 10);
+        assert_eq!(paper.portfolio_in(ExecutionMode::Live).position("SOL/USDC"), 0);
+        dry_run.set_mode(ExecutionMode::Live);
+        assert_eq!(dry_run.cancel_all().await.cancelled.len(), 2);
+        dry_run.on_tick(&tick(17.0)).await.unwrap();
+        assert_eq!(executor.submissions.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_risk_limits_reject_orders() {
+        let executor = Arc::new(MockExecutor { submissions: AtomicU32::new(0) });
+        let strategy = DipBuyer { threshold: 20.0, target: 100, position: 0, last_price: None };
+        let risk = RiskManager::new(RiskLimits {
+            max_position: Some(15),
+            ..Default::default()
+        });
+        let mut agent = TradingAgent::new("trader", Box::new(strategy), executor.clone()).with_risk(risk);
+
+        assert_eq!(agent.on_tick(&tick(19.0)).await.unwrap()[0].status, OrderStatus::Submitted);
+        // The open order counts towards the position
+        let placed = agent.on_tick(&tick(18.0)).await.unwrap();
+        assert_eq!(placed[0].status, OrderStatus::Rejected);
+        assert_eq!(executor.submissions.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_all() {
+        let executor = Arc::new(MockExecutor { submissions: AtomicU32::new(0) });
+        let strategy = DipBuyer { threshold: 20.0, target: 100, position: 0, last_price: None };
+        let mut agent = TradingAgent::new("trader", Box::new(strategy), executor);
+
+        agent.on_tick(&tick(19.0)).await.unwrap();
+        agent.on_tick(&tick(18.0)).await.unwrap();
+        let report = agent.cancel_all().await;
+        assert_eq!(report.cancelled.len(), 2);
+        assert!(report.failed.is_empty());
+        assert!(report.cancelled.iter().all(|order| order.status == OrderStatus::Cancelled));
+        assert!(agent.orders().open_orders().is_empty());
+    }
+
+    /// Submits orders it can never cancel, like `OnChainExecutor`
+    struct Uncancellable;
+
+    #[async_trait::async_trait]
+    impl OrderExecutor for Uncancellable {
+        async fn submit(&self, _order: &Order) -> AgentResult<String> {
+            Ok("sig".to_string())
+        }
+
+        async fn cancel(&self, _order: &Order) -> AgentResult<()> {
+            Err(AgentError::InvalidStateTransition)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cancel_all_reports_failures() {
+        let strategy = DipBuyer { threshold: 20.0, target: 100, position: 0, last_price: None };
+        let mut agent =
+            TradingAgent::new("trader", Box::new(strategy), Arc::new(Uncancellable)).with_mode(ExecutionMode::DryRun);
+        agent.on_tick(&tick(19.0)).await.unwrap();
+        agent.set_mode(ExecutionMode::Live);
+        agent.on_tick(&tick(18.0)).await.unwrap();
+
+        // The live order fails to cancel without stopping the simulated one
+        let report = agent.cancel_all().await;
+        assert_eq!(report.cancelled.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].1, AgentError::InvalidStateTransition);
+        assert_eq!(agent.orders().open_orders().len(), 1);
+    }
+}
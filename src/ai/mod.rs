@@ -0,0 +1,40 @@
+//! AI model integration
+//!
+//! This module provides:
+//! - The `AiProvider` trait model backends implement, answering prompts
+//!   with JSON output that follows a schema
+//! - `sentiment`: texts scored by a provider into time-bucketed sentiment
+//!   indices, published to strategies as signals
+
+use async_trait::async_trait;
+use serde_json::Value;
+use thiserror::Error;
+
+pub mod sentiment;
+
+pub use sentiment::{
+    HttpTextSource, SentimentAgent, SentimentConfig, SentimentIndex, SentimentScore, SentimentStore, TextItem,
+    TextSource,
+};
+
+/// Why a provider couldn't answer a prompt
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum AiError {
+    #[error("Rate limited by the provider")]
+    RateLimited,
+
+    #[error("Provider request failed: {0}")]
+    Request(String),
+
+    #[error("Output doesn't follow the schema: {0}")]
+    InvalidOutput(String),
+}
+
+/// Model backend answering prompts with structured output
+#[async_trait]
+pub trait AiProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Answer `prompt` with a JSON value following the JSON schema `schema`
+    async fn complete(&self, prompt: &str, schema: &Value) -> Result<Value, AiError>;
+}
@@ -0,0 +1,562 @@
+//! Sentiment signals
+//!
+//! This module provides:
+//! - The `TextSource` trait text feeds implement, and `HttpTextSource`
+//!   fetching a feed through a `NetworkClient`
+//! - `SentimentScore`, the structured output an `AiProvider` scores each
+//!   text with
+//! - `SentimentStore`, indices persisted through a `StorageManager` by
+//!   market and interval, read back by time range like `CandleStore`
+//!   candles
+//! - `SentimentAgent`, scoring new texts every cycle, aggregating them into
+//!   an index per market and period, and publishing each complete period
+//!   on the message bus for strategies to act on
+//!
+//! An index is the confidence-weighted mean score of the texts about a
+//! market published in its period. A period is only published once it has
+//! ended and every text fetched for it is scored, so an index never
+//! changes after strategies see it; texts fetched later for a published
+//! period are dropped. Texts the provider fails to score are counted and
+//! skipped rather than failing the cycle, and a rate-limited provider
+//! leaves the remaining texts for the next cycle.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::agent::bus::MessageBus;
+use crate::agent::data_sync::ProviderError;
+use crate::agent::error::{AgentError, AgentResult};
+use crate::agent::lifecycle::AgentLifecycle;
+use crate::agent::runtime::ScheduledAgent;
+use crate::network::{NetworkClient, NetworkError};
+use crate::storage::{PageRequest, StorageManager, MAX_PAGE_SIZE};
+use super::{AiError, AiProvider};
+
+/// Prefix of the storage keys of indices, followed by market, interval and
+/// start time
+pub const SENTIMENT_KEY_PREFIX: &str = "sentiment/indices/";
+
+/// Topic indices are published on by default
+pub const DEFAULT_SENTIMENT_TOPIC: &str = "sentiment";
+
+/// Text about a market
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextItem {
+    pub market: String,
+    pub text: String,
+    /// Unix time the text was published
+    pub timestamp: u64,
+}
+
+/// Feed of texts to score
+#[async_trait]
+pub trait TextSource: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Texts published after unix time `since`, in any order
+    async fn fetch(&self, since: u64) -> Result<Vec<TextItem>, ProviderError>;
+}
+
+/// Text feed served over HTTP, answering `{"since": <unix time>}` with a
+/// JSON array of `TextItem`s
+pub struct HttpTextSource {
+    name: String,
+    client: NetworkClient,
+    endpoint: String,
+}
+
+impl HttpTextSource {
+    pub fn new(name: &str, client: NetworkClient, endpoint: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            client,
+            endpoint: endpoint.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl TextSource for HttpTextSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn fetch(&self, since: u64) -> Result<Vec<TextItem>, ProviderError> {
+        let body = json!({ "since": since });
+        let response = self
+            .client
+            .send_request(&self.endpoint, body.to_string().as_bytes())
+            .await
+            .map_err(|e| match e {
+                NetworkError::RateLimitExceeded(wait) => ProviderError::RateLimited { retry_after: Some(wait) },
+                e => ProviderError::Request(e.to_string()),
+            })?;
+        serde_json::from_slice(&response).map_err(|e| ProviderError::Request(e.to_string()))
+    }
+}
+
+/// Sentiment of a text, as scored by the provider
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SentimentScore {
+    /// From -1, bearish, to 1, bullish
+    pub score: f64,
+    /// From 0 to 1
+    pub confidence: f64,
+}
+
+impl SentimentScore {
+    /// JSON schema the provider's output follows
+    pub fn schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "score": { "type": "number", "minimum": -1, "maximum": 1 },
+                "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
+            },
+            "required": ["score", "confidence"],
+        })
+    }
+
+    /// Parse the provider's output, rejecting values out of range
+    pub fn from_output(output: Value) -> Result<Self, AiError> {
+        let score: Self = serde_json::from_value(output).map_err(|e| AiError::InvalidOutput(e.to_string()))?;
+        if !(-1.0..=1.0).contains(&score.score) || !(0.0..=1.0).contains(&score.confidence) {
+            return Err(AiError::InvalidOutput(format!(
+                "score {} or confidence {} out of range",
+                score.score, score.confidence
+            )));
+        }
+        Ok(score)
+    }
+
+    fn prompt(item: &TextItem) -> String {
+        format!(
+            "Rate the sentiment of this text towards {} for traders, from -1 (bearish) to 1 (bullish), \
+             with your confidence from 0 to 1.\n\n{}",
+            item.market, item.text
+        )
+    }
+}
+
+/// Sentiment of a market over one period
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentimentIndex {
+    pub market: String,
+    /// Unix time the period started
+    pub timestamp: u64,
+    /// Seconds per period
+    pub interval: u64,
+    /// Confidence-weighted mean score of the period's texts, from -1 to 1
+    pub score: f64,
+    /// Mean confidence of the period's texts
+    pub confidence: f64,
+    /// Texts scored in the period
+    pub samples: u32,
+}
+
+/// Sentiment indices persisted by market and interval
+#[derive(Clone)]
+pub struct SentimentStore {
+    storage: Arc<StorageManager>,
+}
+
+impl SentimentStore {
+    pub fn new(storage: Arc<StorageManager>) -> Self {
+        Self { storage }
+    }
+
+    /// Store `index`, replacing the one stored for the same period
+    pub async fn store(&self, index: &SentimentIndex) -> AgentResult<()> {
+        self.storage
+            .store(&Self::key(&index.market, index.interval, index.timestamp), index)
+            .await
+            .map_err(|e| AgentError::Custom(e.to_string()))
+    }
+
+    /// Stored indices of `market` starting in `start..end`, oldest first
+    pub async fn range(&self, market: &str, interval: u64, start: u64, end: u64) -> AgentResult<Vec<SentimentIndex>> {
+        let prefix = Self::prefix(market, interval);
+        let mut page = match start.checked_sub(1) {
+            Some(before) => PageRequest::after(Self::key(market, interval, before), MAX_PAGE_SIZE),
+            None => PageRequest::first(MAX_PAGE_SIZE),
+        };
+        let mut indices = Vec::new();
+        loop {
+            let scanned = self
+                .storage
+                .scan::<SentimentIndex>(&prefix, &page)
+                .await
+                .map_err(|e| AgentError::Custom(e.to_string()))?;
+            for (_, index) in scanned.items {
+                if index.timestamp >= end {
+                    return Ok(indices);
+                }
+                indices.push(index);
+            }
+            match scanned.next_cursor {
+                Some(cursor) => page = PageRequest::after(cursor, MAX_PAGE_SIZE),
+                None => return Ok(indices),
+            }
+        }
+    }
+
+    fn prefix(market: &str, interval: u64) -> String {
+        format!("{}{}/{}/", SENTIMENT_KEY_PREFIX, market, interval)
+    }
+
+    fn key(market: &str, interval: u64, timestamp: u64) -> String {
+        format!("{}{:020}", Self::prefix(market, interval), timestamp)
+    }
+}
+
+/// Markets to track and how
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SentimentConfig {
+    /// Markets whose texts are scored; texts about others are ignored
+    pub markets: Vec<String>,
+    /// Seconds per index period
+    pub interval: u64,
+    /// Most texts scored per cycle, bounding the calls to the provider
+    pub max_texts_per_cycle: u32,
+    pub topic: String,
+}
+
+impl SentimentConfig {
+    pub fn validate(&self) -> AgentResult<()> {
+        let valid =
+            !self.markets.is_empty() && self.markets.iter().all(|market| !market.is_empty()) && self.interval > 0;
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidConfiguration)
+        }
+    }
+}
+
+/// Running sums of a period's scores
+#[derive(Debug, Clone, Copy, Default)]
+struct Period {
+    weighted_score: f64,
+    confidence: f64,
+    samples: u32,
+}
+
+/// Analysis agent turning texts into sentiment indices
+pub struct SentimentAgent {
+    name: String,
+    config: SentimentConfig,
+    sources: Vec<Arc<dyn TextSource>>,
+    provider: Arc<dyn AiProvider>,
+    store: SentimentStore,
+    bus: Option<MessageBus>,
+    /// Newest publication time fetched from each source
+    since: HashMap<String, u64>,
+    /// Texts fetched and not scored yet
+    pending: VecDeque<TextItem>,
+    /// Periods not published yet, by market and start time
+    open: BTreeMap<(String, u64), Period>,
+    /// Start of the first period not published yet
+    published_until: u64,
+    latest: HashMap<String, SentimentIndex>,
+    scored: u64,
+    failed: u64,
+}
+
+impl SentimentAgent {
+    pub fn new(
+        name: &str,
+        config: SentimentConfig,
+        sources: Vec<Arc<dyn TextSource>>,
+        provider: Arc<dyn AiProvider>,
+        storage: Arc<StorageManager>,
+    ) -> AgentResult<Self> {
+        config.validate()?;
+        Ok(Self {
+            name: name.to_string(),
+            config,
+            sources,
+            provider,
+            store: SentimentStore::new(storage),
+            bus: None,
+            since: HashMap::new(),
+            pending: VecDeque::new(),
+            open: BTreeMap::new(),
+            published_until: 0,
+            latest: HashMap::new(),
+            scored: 0,
+            failed: 0,
+        })
+    }
+
+    pub fn store(&self) -> &SentimentStore {
+        &self.store
+    }
+
+    /// Latest index published for `market`
+    pub fn latest(&self, market: &str) -> Option<&SentimentIndex> {
+        self.latest.get(market)
+    }
+
+    /// Texts scored so far
+    pub fn scored(&self) -> u64 {
+        self.scored
+    }
+
+    /// Texts the provider failed to score so far
+    pub fn failed(&self) -> u64 {
+        self.failed
+    }
+
+    /// Fetch new texts, score at most `max_texts` of them and publish the
+    /// periods complete at unix time `now`, returning their indices
+    pub async fn poll(&mut self, now: u64, max_texts: u32) -> AgentResult<Vec<SentimentIndex>> {
+        self.fetch().await;
+        self.score(max_texts).await;
+
+        // A period still waiting for texts to be scored stays open
+        let current = now - now % self.config.interval;
+        let oldest_pending = self.pending.iter().map(|item| self.period_start(item.timestamp)).min();
+        let complete_before = oldest_pending.map_or(current, |start| start.min(current));
+
+        let mut indices = Vec::new();
+        let complete: Vec<(String, u64)> =
+            self.open.keys().filter(|(_, start)| *start < complete_before).cloned().collect();
+        for key in complete {
+            let period = self.open.remove(&key).unwrap_or_default();
+            let (market, timestamp) = key;
+            let index = SentimentIndex {
+                market,
+                timestamp,
+                interval: self.config.interval,
+                score: if period.confidence > 0.0 { period.weighted_score / period.confidence } else { 0.0 },
+                confidence: period.confidence / period.samples.max(1) as f64,
+                samples: period.samples,
+            };
+            self.publish(&index).await?;
+            indices.push(index);
+        }
+        self.published_until = self.published_until.max(complete_before);
+        Ok(indices)
+    }
+
+    /// Queue the texts each source published since its last fetch
+    async fn fetch(&mut self) {
+        for source in &self.sources {
+            let since = self.since.get(source.name()).copied().unwrap_or_default();
+            let items = match source.fetch(since).await {
+                Ok(items) => items,
+                Err(e) => {
+                    println!("Fetching texts from {} failed: {}", source.name(), e);
+                    continue;
+                }
+            };
+            if let Some(newest) = items.iter().map(|item| item.timestamp).max() {
+                self.since.insert(source.name().to_string(), newest.max(since));
+            }
+            let published_until = self.published_until;
+            self.pending.extend(items.into_iter().filter(|item| {
+                item.timestamp > since
+                    && item.timestamp >= published_until
+                    && self.config.markets.contains(&item.market)
+            }));
+        }
+    }
+
+    /// Score at most `max_texts` queued texts into their periods
+    async fn score(&mut self, max_texts: u32) {
+        let schema = SentimentScore::schema();
+        for _ in 0..max_texts {
+            let Some(item) = self.pending.pop_front() else {
+                return;
+            };
+            let output = self.provider.complete(&SentimentScore::prompt(&item), &schema).await;
+            match output.and_then(SentimentScore::from_output) {
+                Ok(score) => {
+                    let start = self.period_start(item.timestamp);
+                    let period = self.open.entry((item.market.clone(), start)).or_default();
+                    period.weighted_score += score.score * score.confidence;
+                    period.confidence += score.confidence;
+                    period.samples += 1;
+                    self.scored += 1;
+                }
+                Err(AiError::RateLimited) => {
+                    println!("Rate limited by {}, scoring the rest next cycle", self.provider.name());
+                    self.pending.push_front(item);
+                    return;
+                }
+                Err(e) => {
+                    println!("Scoring a text about {} failed: {}", item.market, e);
+                    self.failed += 1;
+                }
+            }
+        }
+    }
+
+    async fn publish(&mut self, index: &SentimentIndex) -> AgentResult<()> {
+        self.store.store(index).await?;
+        if let Some(bus) = &self.bus {
+            bus.publish(&self.name, &self.config.topic, index)?;
+        }
+        self.latest.insert(index.market.clone(), index.clone());
+        Ok(())
+    }
+
+    fn period_start(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.config.interval
+    }
+}
+
+impl AgentLifecycle for SentimentAgent {}
+
+#[async_trait]
+impl ScheduledAgent for SentimentAgent {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn max_actions_per_cycle(&self) -> u32 {
+        self.config.max_texts_per_cycle
+    }
+
+    /// Each text scored counts as an action
+    async fn run_cycle(&mut self, max_actions: u32) -> AgentResult<u32> {
+        let scored = self.scored;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.poll(now, max_actions).await?;
+        Ok((self.scored - scored) as u32)
+    }
+
+    fn connect(&mut self, bus: MessageBus) {
+        self.bus = Some(bus);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use crate::storage::StorageConfig;
+
+    /// Texts published so far, served by publication time
+    struct Feed {
+        items: Mutex<Vec<TextItem>>,
+    }
+
+    impl Feed {
+        fn publish(&self, market: &str, text: &str, timestamp: u64) {
+            self.items.lock().unwrap().push(TextItem {
+                market: market.to_string(),
+                text: text.to_string(),
+                timestamp,
+            });
+        }
+    }
+
+    #[async_trait]
+    impl TextSource for Feed {
+        fn name(&self) -> &str {
+            "feed"
+        }
+
+        async fn fetch(&self, since: u64) -> Result<Vec<TextItem>, ProviderError> {
+            Ok(self.items.lock().unwrap().iter().filter(|item| item.timestamp > since).cloned().collect())
+        }
+    }
+
+    /// Bullish on "moon", bearish on "dump", out of range otherwise
+    struct Model;
+
+    #[async_trait]
+    impl AiProvider for Model {
+        fn name(&self) -> &str {
+            "model"
+        }
+
+        async fn complete(&self, prompt: &str, _schema: &Value) -> Result<Value, AiError> {
+            if prompt.ends_with("moon") {
+                Ok(json!({ "score": 0.8, "confidence": 1.0 }))
+            } else if prompt.ends_with("dump") {
+                Ok(json!({ "score": -0.6, "confidence": 0.5 }))
+            } else {
+                Ok(json!({ "score": 3.0, "confidence": 1.0 }))
+            }
+        }
+    }
+
+    async fn storage(dir: &tempfile::TempDir) -> Arc<StorageManager> {
+        let config = StorageConfig {
+            base_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        Arc::new(StorageManager::new(config).await.unwrap())
+    }
+
+    fn config() -> SentimentConfig {
+        SentimentConfig {
+            markets: vec!["SOL".to_string()],
+            interval: 60,
+            max_texts_per_cycle: 10,
+            topic: DEFAULT_SENTIMENT_TOPIC.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_score_output() {
+        let score = SentimentScore::from_output(json!({ "score": -0.5, "confidence": 0.9 })).unwrap();
+        assert_eq!(score, SentimentScore { score: -0.5, confidence: 0.9 });
+        assert!(SentimentScore::from_output(json!({ "score": 1.5, "confidence": 0.9 })).is_err());
+        assert!(SentimentScore::from_output(json!({ "score": 0.5, "confidence": -0.1 })).is_err());
+        assert!(SentimentScore::from_output(json!({ "score": 0.5 })).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_indices_published_per_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = storage(&dir).await;
+        let feed = Arc::new(Feed { items: Mutex::new(Vec::new()) });
+        let sources = vec![feed.clone() as Arc<dyn TextSource>];
+        let invalid = SentimentConfig { interval: 0, ..config() };
+        assert!(SentimentAgent::new("sentiment", invalid, sources.clone(), Arc::new(Model), storage.clone()).is_err());
+
+        let mut agent = SentimentAgent::new("sentiment", config(), sources, Arc::new(Model), storage).unwrap();
+        let bus = MessageBus::new();
+        let mut trader = bus.mailbox("trader").unwrap();
+        bus.subscribe("trader", DEFAULT_SENTIMENT_TOPIC);
+        agent.connect(bus);
+
+        feed.publish("SOL", "moon", 10);
+        feed.publish("SOL", "dump", 20);
+        feed.publish("SOL", "noise", 30);
+        feed.publish("ETH", "moon", 40);
+        feed.publish("SOL", "moon", 70);
+
+        // The first period waits for its texts to be scored
+        assert!(agent.poll(100, 1).await.unwrap().is_empty());
+        let indices = agent.poll(100, 10).await.unwrap();
+        assert_eq!(indices.len(), 1);
+        let index = &indices[0];
+        assert_eq!((index.market.as_str(), index.timestamp, index.samples), ("SOL", 0, 2));
+        assert!((index.score - 0.5 / 1.5).abs() < 1e-9);
+        assert!((index.confidence - 0.75).abs() < 1e-9);
+        assert_eq!((agent.scored(), agent.failed()), (3, 1));
+
+        let signal: SentimentIndex = trader.try_recv().unwrap().decode().unwrap();
+        assert_eq!(&signal, index);
+        assert_eq!(agent.latest("SOL"), Some(index));
+
+        // The second period is published once it ends
+        feed.publish("SOL", "dump", 110);
+        let indices = agent.poll(130, 10).await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!((indices[0].timestamp, indices[0].samples), (60, 2));
+
+        let stored = agent.store().range("SOL", 60, 0, 120).await.unwrap();
+        assert_eq!(stored.iter().map(|index| index.timestamp).collect::<Vec<_>>(), vec![0, 60]);
+        assert!(agent.store().range("ETH", 60, 0, 120).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//! Errors returned by the client-side agent API
+//!
+//! Failed transactions are decoded: a custom program error becomes the
+//! matching `AgentError`, anything else is kept as the RPC error.
+
+use num_traits::FromPrimitive;
+use solana_client::{client_error::ClientError as RpcError, pubsub_client::PubsubClientError};
+use solana_sdk::{
+    instruction::InstructionError, signature::Signature, signer::SignerError, transaction::TransactionError,
+};
+use thiserror::Error;
+use crate::agent::fee_guard::FeeRefusal;
+use crate::solana::{client::ClientError, offline::OfflineError, program::error::AgentError, transaction::BuildError};
+use crate::storage::StorageError;
+use crate::validation::ConfigErrors;
+
+#[derive(Error, Debug)]
+pub enum SonomaError {
+    /// The action was rejected, client-side or by the program
+    #[error("Invalid action")]
+    InvalidAction,
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(ConfigErrors),
+
+    #[error("Program error: {0}")]
+    Program(AgentError),
+
+    #[error(transparent)]
+    Client(#[from] ClientError),
+
+    #[error("Subscription error: {0}")]
+    Subscription(Box<PubsubClientError>),
+
+    #[error(transparent)]
+    Build(#[from] BuildError),
+
+    #[error(transparent)]
+    Offline(#[from] OfflineError),
+
+    /// Not confirmed in time; the transaction may still land
+    #[error("Transaction {0} was not confirmed in time")]
+    Timeout(Signature),
+
+    #[error("Transaction blockhash expired on all {0} attempts")]
+    Expired(u32),
+
+    /// A signer failed to load or be saved
+    #[error("Signer error: {0}")]
+    Signer(#[from] SignerError),
+
+    /// Refused client-side for exceeding the agent's fee budget
+    #[error(transparent)]
+    FeeBudget(#[from] FeeRefusal),
+
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+impl From<PubsubClientError> for SonomaError {
+    fn from(error: PubsubClientError) -> Self {
+        Self::Subscription(Box::new(error))
+    }
+}
+
+impl From<AgentError> for SonomaError {
+    fn from(error: AgentError) -> Self {
+        match error {
+            AgentError::InvalidAction => Self::InvalidAction,
+            error => Self::Program(error),
+        }
+    }
+}
+
+impl From<RpcError> for SonomaError {
+    fn from(error: RpcError) -> Self {
+        match error.get_transaction_error().as_ref().and_then(agent_error) {
+            Some(agent_error) => agent_error.into(),
+            None => Self::Client(error.into()),
+        }
+    }
+}
+
+/// Agent program error a transaction failed with, if any
+pub fn agent_error(error: &TransactionError) -> Option<AgentError> {
+    match error {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => AgentError::from_u32(*code),
+        _ => None,
+    }
+}
+
+pub type SonomaResult<T> = Result<T, SonomaError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::client_error::ClientErrorKind;
+
+    #[test]
+    fn test_transaction_error_decoding() {
+        let failed = |code| {
+            let error = TransactionError::InstructionError(0, InstructionError::Custom(code));
+            SonomaError::from(RpcError::from(ClientErrorKind::TransactionError(error)))
+        };
+
+        assert!(matches!(failed(AgentError::InvalidAction as u32), SonomaError::InvalidAction));
+        assert!(matches!(
+            failed(AgentError::InsufficientStake as u32),
+            SonomaError::Program(AgentError::InsufficientStake)
+        ));
+        assert!(matches!(failed(u32::MAX), SonomaError::Client(ClientError::Rpc(_))));
+    }
+}
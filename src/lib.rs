@@ -1,20 +1,24 @@
-use solana_program::{
-    account_info::AccountInfo,
-    entrypoint,
-    entrypoint::ProgramResult,
-    pubkey::Pubkey,
-    program_error::ProgramError,
-};
-
 pub mod agent;
-pub mod models;
-pub mod state;
 pub mod error;
-pub mod instructions;
+pub mod network;
+pub mod solana;
+pub mod storage;
+pub mod validation;
+
+pub use solana::program;
+pub use solana::program::process_instruction;
 
 #[cfg(feature = "ai-integration")]
 pub mod ai;
 
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+
+use std::sync::Arc;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use solana::signer::SonomaSigner;
+
 pub struct SonomaConfig {
     pub network: String,
     pub api_key: Option<String>,
@@ -36,6 +40,19 @@ impl Default for SonomaConfig {
     }
 }
 
+impl SonomaConfig {
+    /// RPC URL of `network`, which is either a cluster name or a URL
+    pub fn rpc_url(&self) -> String {
+        match self.network.as_str() {
+            "mainnet-beta" | "mainnet" => "https://api.mainnet-beta.solana.com".to_string(),
+            "devnet" => "https://api.devnet.solana.com".to_string(),
+            "testnet" => "https://api.testnet.solana.com".to_string(),
+            "localnet" | "localhost" => "http://localhost:8899".to_string(),
+            url => url.to_string(),
+        }
+    }
+}
+
 pub struct Sonoma {
     config: SonomaConfig,
 }
@@ -45,26 +62,80 @@ impl Sonoma {
         Self { config }
     }
 
-    pub fn create_agent(&self, name: &str) -> agent::Agent {
-        agent::Agent::new(name, &self.config)
+    /// Create and start an agent on the configured network
+    pub fn create_agent(
+        &self,
+        program_id: &Pubkey,
+        payer: &Keypair,
+        name: &str,
+        config: agent::AgentConfig,
+    ) -> error::SonomaResult<agent::Agent> {
+        let client = RpcClient::new(self.config.rpc_url());
+        agent::Agent::new(&client, program_id, payer, name, config)
     }
-}
 
-// Solana program entrypoint
-entrypoint!(process_instruction);
+    /// `create_agent`, signing with `payer` wherever it holds its key
+    pub fn create_agent_with_signer(
+        &self,
+        program_id: &Pubkey,
+        payer: Arc<dyn SonomaSigner>,
+        name: &str,
+        config: agent::AgentConfig,
+    ) -> error::SonomaResult<agent::Agent> {
+        let client = RpcClient::new(self.config.rpc_url());
+        agent::Agent::new_with_signer(&client, program_id, payer, name, config)
+    }
+
+    /// Create and start the agent at `index` of `template`'s fleet, named
+    /// after the index
+    pub fn spawn_from_template(
+        &self,
+        program_id: &Pubkey,
+        payer: &Keypair,
+        template: &agent::AgentTemplate,
+        index: usize,
+    ) -> error::SonomaResult<agent::SpawnedAgent> {
+        template
+            .validate_instance(index)
+            .map_err(error::SonomaError::InvalidConfiguration)?;
+        let config = template.agent_config();
+        let capabilities = agent::AgentCapabilities::from(&config.capabilities);
+        let agent = self.create_agent(program_id, payer, &template.name(index), config)?;
+        Ok(agent::SpawnedAgent {
+            index,
+            agent,
+            capabilities,
+            strategy: template.strategy(index),
+        })
+    }
 
-pub fn process_instruction(
-    program_id: &Pubkey,
-    accounts: &[AccountInfo],
-    instruction_data: &[u8],
-) -> ProgramResult {
-    instructions::process_instruction(program_id, accounts, instruction_data)
-        .map_err(|e| e.into())
+    /// Spawn the agents at indices `0..count` of `template`'s fleet
+    ///
+    /// Every index is validated before any agent is created. The result of
+    /// each spawn is returned at its index: an agent failing to spawn
+    /// doesn't stop the others, nor undo those already running.
+    pub fn spawn_fleet(
+        &self,
+        program_id: &Pubkey,
+        payer: &Keypair,
+        template: &agent::AgentTemplate,
+        count: usize,
+    ) -> error::SonomaResult<Vec<error::SonomaResult<agent::SpawnedAgent>>> {
+        for index in 0..count {
+            template
+                .validate_instance(index)
+                .map_err(error::SonomaError::InvalidConfiguration)?;
+        }
+        Ok((0..count)
+            .map(|index| self.spawn_from_template(program_id, payer, template, index))
+            .collect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::client_config;
 
     #[test]
     fn test_default_config() {
@@ -77,7 +148,38 @@ mod tests {
     fn test_create_agent() {
         let config = SonomaConfig::default();
         let sonoma = Sonoma::new(config);
-        let agent = sonoma.create_agent("test_agent");
-        // Add more specific tests as agent functionality is implemented
+        let agent_config = agent::AgentConfig {
+            execution_limit: 0,
+            ..client_config()
+        };
+
+        // Invalid configs are rejected before anything is sent
+        let result = sonoma.create_agent(&Pubkey::new_unique(), &Keypair::new(), "test_agent", agent_config);
+        assert!(matches!(result, Err(error::SonomaError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn test_spawn_from_template() {
+        let sonoma = Sonoma::new(SonomaConfig::default());
+        let agent_config = client_config();
+
+        // Names too long for the agent's address are rejected before anything is sent
+        let template = agent::AgentTemplate::new(&"x".repeat(40), agent_config.clone());
+        let result = sonoma.spawn_fleet(&Pubkey::new_unique(), &Keypair::new(), &template, 3);
+        assert!(matches!(result, Err(error::SonomaError::InvalidConfiguration(_))));
+
+        // Including when only the last index is too long
+        let name_len = program::state::MAX_NAME_LEN - 2;
+        let template = agent::AgentTemplate::new(&"x".repeat(name_len), agent_config);
+        let result = sonoma.spawn_fleet(&Pubkey::new_unique(), &Keypair::new(), &template, 11);
+        assert!(matches!(result, Err(error::SonomaError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn test_rpc_url() {
+        let mut config = SonomaConfig::default();
+        assert_eq!(config.rpc_url(), "https://api.devnet.solana.com");
+        config.network = "http://127.0.0.1:8899".to_string();
+        assert_eq!(config.rpc_url(), "http://127.0.0.1:8899");
     }
 }
@@ -12,24 +12,29 @@ use std::time::Duration;
 use tokio::sync::{RwLock, Semaphore};
 use reqwest::{Client as HttpClient, Response};
 use async_tungstenite::WebSocketStream;
+use async_tungstenite::tokio::{connect_async, ConnectStream};
+use async_tungstenite::tungstenite::Message as WsMessage;
 use futures::{StreamExt, SinkExt};
-use super::{NetworkConfig, NetworkError, NetworkResult, NetworkStatus, NetworkMetrics, Message};
+use super::{ClusterConfig, NetworkConfig, NetworkError, NetworkResult, NetworkStatus, NetworkMetrics, Message};
+use super::metrics::MetricsRecorder;
+use super::protocol::{self, Codec};
 
 /// Network client for handling communication
-#[derive(Clone)]
 pub struct NetworkClient {
     /// HTTP client
     http_client: HttpClient,
     /// WebSocket client
-    ws_client: Option<WebSocketStream<async_tungstenite::stream::Stream<tokio::net::TcpStream>>>,
+    ws_client: Option<WebSocketStream<ConnectStream>>,
     /// Network configuration
     config: NetworkConfig,
     /// Connection semaphore for limiting concurrent connections
     connection_semaphore: Arc<Semaphore>,
     /// Network metrics
-    metrics: Arc<RwLock<NetworkMetrics>>,
+    metrics: Arc<RwLock<MetricsRecorder>>,
     /// Network status
     status: Arc<RwLock<NetworkStatus>>,
+    /// Encoding negotiated with the WebSocket peer
+    codec: Codec,
 }
 
 impl NetworkClient {
@@ -46,13 +51,14 @@ impl NetworkClient {
             ws_client: None,
             config,
             connection_semaphore: Arc::new(Semaphore::new(100)), // Default max connections
-            metrics: Arc::new(RwLock::new(NetworkMetrics::default())),
+            metrics: Arc::new(RwLock::new(MetricsRecorder::new())),
             status: Arc::new(RwLock::new(NetworkStatus {
                 connected: false,
                 latency: Duration::from_secs(0),
                 active_connections: 0,
                 pending_requests: 0,
             })),
+            codec: Codec::default(),
         })
     }
 
@@ -65,16 +71,21 @@ impl NetworkClient {
         let mut retries = 0;
 
         loop {
-            match self.http_client.post(&format!("{}{}", self.config.url, endpoint))
+            match self.http_client.post(format!("{}{}", self.config.url, endpoint))
                 .body(body.to_vec())
                 .send()
                 .await {
                     Ok(response) => {
-                        self.update_metrics(start_time.elapsed()).await;
-                        return self.handle_response(response).await;
+                        let result = self.handle_response(response).await;
+                        match &result {
+                            Ok(_) => self.update_metrics(start_time.elapsed()).await,
+                            Err(_) => self.record_error().await,
+                        }
+                        return result;
                     }
                     Err(e) => {
                         if retries >= self.config.max_retries {
+                            self.record_error().await;
                             return Err(NetworkError::ConnectionFailed(e.to_string()));
                         }
                         retries += 1;
@@ -84,22 +95,77 @@ impl NetworkClient {
         }
     }
 
+    /// Fetch the genesis hash of the connected cluster
+    pub async fn get_genesis_hash(&self) -> NetworkResult<String> {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "getGenesisHash",
+        });
+        let response = self.send_request("", body.to_string().as_bytes()).await?;
+
+        let value: serde_json::Value = serde_json::from_slice(&response)
+            .map_err(|e| NetworkError::InvalidResponse(e.to_string()))?;
+        value["result"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| NetworkError::InvalidResponse("Missing genesis hash".to_string()))
+    }
+
+    /// Verify that the endpoint belongs to the configured cluster
+    pub async fn verify_cluster(&self) -> NetworkResult<()> {
+        if self.config.cluster.genesis_hash.is_none() {
+            return Ok(());
+        }
+
+        let genesis_hash = self.get_genesis_hash().await?;
+        self.config.cluster.verify_genesis_hash(&genesis_hash)
+    }
+
+    /// Get the cluster this client is configured for
+    pub fn cluster(&self) -> &ClusterConfig {
+        &self.config.cluster
+    }
+
     /// Connect to WebSocket endpoint
     pub async fn connect_ws(&mut self, endpoint: &str) -> NetworkResult<()> {
+        self.verify_cluster().await?;
+
         let url = format!("ws://{}{}", self.config.url.trim_start_matches("http://"), endpoint);
-        let (ws_stream, _) = async_tungstenite::connect_async(&url)
+        let (ws_stream, _) = connect_async(&url)
             .await
             .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?;
 
         self.ws_client = Some(ws_stream);
+        self.codec = Codec::default();
+        self.handshake().await?;
         self.update_status(true).await;
         Ok(())
     }
 
+    /// Exchange handshakes and adopt the capabilities both sides support
+    async fn handshake(&mut self) -> NetworkResult<()> {
+        let capabilities = self.config.capabilities.clone();
+        self.send_ws_message(Message::handshake(&capabilities)).await?;
+
+        let reply = self
+            .receive_ws_message()
+            .await?
+            .ok_or_else(|| NetworkError::ConnectionFailed("Connection closed during handshake".to_string()))?;
+        self.codec = protocol::negotiate(&capabilities, &reply)?;
+        Ok(())
+    }
+
+    /// Get the encoding negotiated with the WebSocket peer
+    pub fn codec(&self) -> &Codec {
+        &self.codec
+    }
+
     /// Send WebSocket message
     pub async fn send_ws_message(&mut self, message: Message) -> NetworkResult<()> {
+        let frame = self.codec.encode(&message)?;
         if let Some(ws) = &mut self.ws_client {
-            ws.send(message.into())
+            ws.send(WsMessage::Binary(frame))
                 .await
                 .map_err(|e| NetworkError::ProtocolError(e.to_string()))?;
             Ok(())
@@ -112,7 +178,9 @@ impl NetworkClient {
     pub async fn receive_ws_message(&mut self) -> NetworkResult<Option<Message>> {
         if let Some(ws) = &mut self.ws_client {
             match ws.next().await {
-                Some(Ok(msg)) => Ok(Some(msg.into())),
+                Some(Ok(WsMessage::Binary(frame))) => self.codec.decode(&frame).map(Some),
+                Some(Ok(WsMessage::Close(_))) => Ok(None),
+                Some(Ok(_)) => Err(NetworkError::ProtocolError("Unexpected non-binary frame".to_string())),
                 Some(Err(e)) => Err(NetworkError::ProtocolError(e.to_string())),
                 None => Ok(None),
             }
@@ -142,13 +210,12 @@ impl NetworkClient {
 
     /// Update network metrics
     async fn update_metrics(&self, latency: Duration) {
-        let mut metrics = self.metrics.write().await;
-        metrics.total_requests += 1;
-        metrics.total_responses += 1;
-        metrics.average_latency = (metrics.average_latency + latency) / 2;
-        if latency > metrics.max_latency {
-            metrics.max_latency = latency;
-        }
+        self.metrics.write().await.record_response(latency);
+    }
+
+    /// Record a failed request in the network metrics
+    async fn record_error(&self) {
+        self.metrics.write().await.record_error();
     }
 
     /// Update network status
@@ -158,8 +225,11 @@ impl NetworkClient {
     }
 
     /// Get current network metrics
+    ///
+    /// Rates and latency are windowed, so they reflect current conditions
+    /// rather than lifetime averages.
     pub async fn get_metrics(&self) -> NetworkMetrics {
-        self.metrics.read().await.clone()
+        self.metrics.write().await.snapshot()
     }
 
     /// Get current network status
@@ -189,6 +259,6 @@ mod tests {
         
         assert_eq!(metrics.total_requests, 1);
         assert_eq!(metrics.total_responses, 1);
-        assert!(metrics.average_latency <= Duration::from_millis(100));
+        assert_eq!(metrics.latency_ewma, Duration::from_millis(100));
     }
 }
\ No newline at end of file
@@ -0,0 +1,213 @@
+//! Cluster definitions for SVM-compatible networks
+//!
+//! This module provides:
+//! - Well-known Solana cluster presets
+//! - Custom SVM cluster definitions (L2s, rollups, app chains)
+//! - Genesis hash verification
+//! - Native token and explorer metadata
+
+use serde::{Serialize, Deserialize};
+use super::{NetworkError, NetworkResult};
+use crate::validation::{Validate, Violations};
+
+/// Genesis hash of Solana mainnet-beta
+pub const MAINNET_GENESIS_HASH: &str = "5eykt4UsFv8P8NJdTREpY1vzqKqZKvdpKuc147dw2N9d";
+
+/// Genesis hash of Solana devnet
+pub const DEVNET_GENESIS_HASH: &str = "EtWTRABZaYq6iMfeYKouRu166VU2xqa1wcaWoxPkrZBG";
+
+/// Genesis hash of Solana testnet
+pub const TESTNET_GENESIS_HASH: &str = "4uhcVJyU9pJkvQyS88uRDiswHXSCkY3zQawwpjk2NsNY";
+
+/// Decimals of native SOL
+pub const SOL_DECIMALS: u8 = 9;
+
+/// Description of an SVM cluster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterConfig {
+    /// Human-readable cluster name
+    pub name: String,
+    /// Expected genesis hash, verified on connect when set
+    pub genesis_hash: Option<String>,
+    /// Symbol of the native token
+    pub native_symbol: String,
+    /// Decimals of the native token
+    pub native_decimals: u8,
+    /// Explorer base URL, with `{}` as placeholder for the transaction signature
+    pub explorer_tx_url: Option<String>,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self::localnet()
+    }
+}
+
+impl ClusterConfig {
+    /// Solana mainnet-beta
+    pub fn mainnet() -> Self {
+        Self::solana("mainnet-beta", Some(MAINNET_GENESIS_HASH), "")
+    }
+
+    /// Solana devnet
+    pub fn devnet() -> Self {
+        Self::solana("devnet", Some(DEVNET_GENESIS_HASH), "?cluster=devnet")
+    }
+
+    /// Solana testnet
+    pub fn testnet() -> Self {
+        Self::solana("testnet", Some(TESTNET_GENESIS_HASH), "?cluster=testnet")
+    }
+
+    /// Local test validator (genesis differs per run, so it is not verified)
+    pub fn localnet() -> Self {
+        Self {
+            name: "localnet".to_string(),
+            genesis_hash: None,
+            native_symbol: "SOL".to_string(),
+            native_decimals: SOL_DECIMALS,
+            explorer_tx_url: None,
+        }
+    }
+
+    /// Custom SVM cluster
+    pub fn custom(
+        name: impl Into<String>,
+        genesis_hash: impl Into<String>,
+        native_symbol: impl Into<String>,
+        native_decimals: u8,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            genesis_hash: Some(genesis_hash.into()),
+            native_symbol: native_symbol.into(),
+            native_decimals,
+            explorer_tx_url: None,
+        }
+    }
+
+    /// Set the explorer transaction URL template
+    pub fn with_explorer(mut self, explorer_tx_url: impl Into<String>) -> Self {
+        self.explorer_tx_url = Some(explorer_tx_url.into());
+        self
+    }
+
+    /// Look up a well-known cluster by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "mainnet" | "mainnet-beta" => Some(Self::mainnet()),
+            "devnet" => Some(Self::devnet()),
+            "testnet" => Some(Self::testnet()),
+            "localnet" | "localhost" => Some(Self::localnet()),
+            _ => None,
+        }
+    }
+
+    /// Verify the genesis hash reported by a node against the expected one
+    pub fn verify_genesis_hash(&self, actual: &str) -> NetworkResult<()> {
+        match &self.genesis_hash {
+            Some(expected) if expected != actual => Err(NetworkError::GenesisMismatch {
+                expected: expected.clone(),
+                actual: actual.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Explorer link for a transaction, if the cluster has an explorer
+    pub fn explorer_link(&self, signature: &str) -> Option<String> {
+        self.explorer_tx_url
+            .as_ref()
+            .map(|template| template.replacen("{}", signature, 1))
+    }
+
+    /// Convert base units of the native token to a UI amount
+    pub fn to_ui_amount(&self, amount: u64) -> f64 {
+        amount as f64 / 10f64.powi(self.native_decimals as i32)
+    }
+
+    /// Convert a UI amount of the native token to base units
+    pub fn from_ui_amount(&self, amount: f64) -> u64 {
+        (amount * 10f64.powi(self.native_decimals as i32)).round() as u64
+    }
+
+    fn solana(name: &str, genesis_hash: Option<&str>, explorer_query: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            genesis_hash: genesis_hash.map(str::to_string),
+            native_symbol: "SOL".to_string(),
+            native_decimals: SOL_DECIMALS,
+            explorer_tx_url: Some(format!("https://explorer.solana.com/tx/{{}}{}", explorer_query)),
+        }
+    }
+}
+
+impl Validate for ClusterConfig {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            !self.name.is_empty(),
+            "name",
+            "must not be empty",
+            "name the cluster, e.g. devnet",
+        );
+        violations.check(
+            self.genesis_hash.as_ref().map_or(true, |hash| !hash.is_empty()),
+            "genesis_hash",
+            "must not be empty when set",
+            "remove genesis_hash to skip verification, or set it to the cluster's genesis hash",
+        );
+        violations.check(
+            self.native_decimals <= 19,
+            "native_decimals",
+            "must be at most 19 to fit base units in a u64",
+            "use the native token's decimals, e.g. 9 for SOL",
+        );
+        violations.check(
+            self.explorer_tx_url.as_ref().map_or(true, |url| url.contains("{}")),
+            "explorer_tx_url",
+            "must contain a {} placeholder for the signature",
+            "use e.g. https://explorer.solana.com/tx/{}",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_verification() {
+        let devnet = ClusterConfig::devnet();
+        assert!(devnet.verify_genesis_hash(DEVNET_GENESIS_HASH).is_ok());
+        assert!(matches!(
+            devnet.verify_genesis_hash(MAINNET_GENESIS_HASH),
+            Err(NetworkError::GenesisMismatch { .. })
+        ));
+
+        // Localnet has no fixed genesis, so anything is accepted
+        assert!(ClusterConfig::localnet().verify_genesis_hash("anything").is_ok());
+    }
+
+    #[test]
+    fn test_custom_cluster() {
+        let cluster = ClusterConfig::custom("eclipse", "genesis", "ETH", 18)
+            .with_explorer("https://explorer.example.com/tx/{}");
+
+        assert_eq!(cluster.to_ui_amount(1_500_000_000_000_000_000), 1.5);
+        assert_eq!(cluster.from_ui_amount(0.25), 250_000_000_000_000_000);
+        assert_eq!(
+            cluster.explorer_link("abc").unwrap(),
+            "https://explorer.example.com/tx/abc"
+        );
+    }
+
+    #[test]
+    fn test_presets() {
+        assert_eq!(ClusterConfig::from_name("mainnet").unwrap(), ClusterConfig::mainnet());
+        assert_eq!(
+            ClusterConfig::devnet().explorer_link("sig").unwrap(),
+            "https://explorer.solana.com/tx/sig?cluster=devnet"
+        );
+        assert!(ClusterConfig::from_name("unknown").is_none());
+    }
+}
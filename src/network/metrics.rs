@@ -0,0 +1,218 @@
+//! Windowed network metrics
+//!
+//! This module provides:
+//! - Exponentially decaying request/error rates over 1m/5m/15m windows
+//! - Exponentially weighted moving average latency
+//!
+//! Rates decay like Unix load averages: counts are folded into the
+//! averages once per tick, so old traffic fades out instead of diluting
+//! lifetime averages forever.
+
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use super::NetworkMetrics;
+
+/// Interval at which event counts are folded into the rate averages
+pub const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Weight of the newest sample in the latency EWMA
+pub const LATENCY_ALPHA: f64 = 0.2;
+
+/// Averaging windows, in seconds
+const WINDOWS: [f64; 3] = [60.0, 300.0, 900.0];
+
+/// Per-second rates averaged over 1, 5 and 15 minutes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowedRates {
+    pub one_minute: f64,
+    pub five_minutes: f64,
+    pub fifteen_minutes: f64,
+}
+
+/// Exponentially decaying event rate
+#[derive(Debug, Clone)]
+struct Meter {
+    uncounted: u64,
+    rates: [f64; 3],
+    last_tick: Instant,
+}
+
+impl Meter {
+    fn new(now: Instant) -> Self {
+        Self {
+            uncounted: 0,
+            rates: [0.0; 3],
+            last_tick: now,
+        }
+    }
+
+    fn mark(&mut self, now: Instant) {
+        self.tick(now);
+        self.uncounted += 1;
+    }
+
+    /// Fold pending counts into the averages for every elapsed tick
+    fn tick(&mut self, now: Instant) {
+        let interval = TICK_INTERVAL.as_secs_f64();
+        while now.duration_since(self.last_tick) >= TICK_INTERVAL {
+            let instant_rate = self.uncounted as f64 / interval;
+            self.uncounted = 0;
+
+            for (rate, window) in self.rates.iter_mut().zip(WINDOWS) {
+                let alpha = 1.0 - (-interval / window).exp();
+                *rate += alpha * (instant_rate - *rate);
+            }
+
+            self.last_tick += TICK_INTERVAL;
+        }
+    }
+
+    fn rates(&mut self, now: Instant) -> WindowedRates {
+        self.tick(now);
+        WindowedRates {
+            one_minute: self.rates[0],
+            five_minutes: self.rates[1],
+            fifteen_minutes: self.rates[2],
+        }
+    }
+}
+
+/// Live metrics state behind `NetworkClient::get_metrics`
+#[derive(Debug, Clone)]
+pub(crate) struct MetricsRecorder {
+    requests: Meter,
+    errors: Meter,
+    total_requests: u64,
+    total_responses: u64,
+    total_errors: u64,
+    latency_ewma: Option<f64>,
+    max_latency: Duration,
+}
+
+impl MetricsRecorder {
+    pub(crate) fn new() -> Self {
+        Self::new_at(Instant::now())
+    }
+
+    fn new_at(now: Instant) -> Self {
+        Self {
+            requests: Meter::new(now),
+            errors: Meter::new(now),
+            total_requests: 0,
+            total_responses: 0,
+            total_errors: 0,
+            latency_ewma: None,
+            max_latency: Duration::ZERO,
+        }
+    }
+
+    /// Record a completed request and its latency
+    pub(crate) fn record_response(&mut self, latency: Duration) {
+        self.record_response_at(latency, Instant::now());
+    }
+
+    /// Record a failed request
+    pub(crate) fn record_error(&mut self) {
+        self.record_error_at(Instant::now());
+    }
+
+    /// Snapshot of the current metrics
+    pub(crate) fn snapshot(&mut self) -> NetworkMetrics {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn record_response_at(&mut self, latency: Duration, now: Instant) {
+        self.requests.mark(now);
+        self.errors.tick(now);
+        self.total_requests += 1;
+        self.total_responses += 1;
+
+        let sample = latency.as_secs_f64();
+        self.latency_ewma = Some(match self.latency_ewma {
+            Some(average) => average + LATENCY_ALPHA * (sample - average),
+            None => sample,
+        });
+        self.max_latency = self.max_latency.max(latency);
+    }
+
+    fn record_error_at(&mut self, now: Instant) {
+        self.requests.mark(now);
+        self.errors.mark(now);
+        self.total_requests += 1;
+        self.total_errors += 1;
+    }
+
+    fn snapshot_at(&mut self, now: Instant) -> NetworkMetrics {
+        NetworkMetrics {
+            total_requests: self.total_requests,
+            total_responses: self.total_responses,
+            total_errors: self.total_errors,
+            requests_per_second: self.requests.rates(now),
+            errors_per_second: self.errors.rates(now),
+            latency_ewma: Duration::from_secs_f64(self.latency_ewma.unwrap_or(0.0)),
+            max_latency: self.max_latency,
+        }
+    }
+}
+
+impl Default for MetricsRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates_track_recent_traffic() {
+        let start = Instant::now();
+        let mut recorder = MetricsRecorder::new_at(start);
+
+        // 10 requests per second for one minute
+        for i in 0..600 {
+            recorder.record_response_at(Duration::from_millis(50), start + Duration::from_millis(i * 100));
+        }
+        let busy = recorder.snapshot_at(start + Duration::from_secs(60));
+        assert!(busy.requests_per_second.one_minute > 5.0);
+        assert!(busy.requests_per_second.one_minute > busy.requests_per_second.fifteen_minutes);
+
+        // Five idle minutes later the short window has decayed much faster
+        let idle = recorder.snapshot_at(start + Duration::from_secs(360));
+        assert!(idle.requests_per_second.one_minute < 0.1);
+        assert!(idle.requests_per_second.fifteen_minutes > idle.requests_per_second.one_minute);
+        assert_eq!(idle.total_requests, 600);
+    }
+
+    #[test]
+    fn test_latency_ewma() {
+        let start = Instant::now();
+        let mut recorder = MetricsRecorder::new_at(start);
+
+        recorder.record_response_at(Duration::from_millis(100), start);
+        for _ in 0..50 {
+            recorder.record_response_at(Duration::from_millis(10), start);
+        }
+
+        let metrics = recorder.snapshot_at(start);
+        assert!(metrics.latency_ewma < Duration::from_millis(11));
+        assert_eq!(metrics.max_latency, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_error_rate() {
+        let start = Instant::now();
+        let mut recorder = MetricsRecorder::new_at(start);
+
+        recorder.record_error_at(start);
+        recorder.record_response_at(Duration::from_millis(10), start);
+
+        let metrics = recorder.snapshot_at(start + TICK_INTERVAL);
+        assert!(metrics.errors_per_second.one_minute > 0.0);
+        assert!(
+            (metrics.requests_per_second.one_minute - 2.0 * metrics.errors_per_second.one_minute).abs() < 1e-9
+        );
+        assert_eq!(metrics.total_errors, 1);
+    }
+}
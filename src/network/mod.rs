@@ -9,14 +9,19 @@
 
 use std::time::Duration;
 use thiserror::Error;
-use tokio::time::timeout;
 use serde::{Serialize, Deserialize};
+use crate::validation::{ConfigErrors, Validate, Violations};
 
 mod client;
+mod cluster;
+mod metrics;
 mod protocol;
 
 pub use client::NetworkClient;
-pub use protocol::{Protocol, Message, MessageType};
+pub use cluster::ClusterConfig;
+pub use metrics::WindowedRates;
+pub(crate) use metrics::MetricsRecorder;
+pub use protocol::{Protocol, Message, MessageType, Capability, Codec};
 
 /// Default timeout for network requests
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
@@ -37,6 +42,12 @@ pub struct NetworkConfig {
     pub keep_alive: Duration,
     /// Maximum connections in pool
     pub max_connections: u32,
+    /// Cluster the endpoint is expected to belong to
+    #[serde(default)]
+    pub cluster: ClusterConfig,
+    /// Protocol capabilities advertised in the WebSocket handshake
+    #[serde(default = "Capability::supported")]
+    pub capabilities: Vec<Capability>,
 }
 
 impl Default for NetworkConfig {
@@ -47,10 +58,48 @@ impl Default for NetworkConfig {
             max_retries: MAX_RETRIES,
             keep_alive: Duration::from_secs(60),
             max_connections: 100,
+            cluster: ClusterConfig::default(),
+            capabilities: Capability::supported(),
         }
     }
 }
 
+impl Validate for NetworkConfig {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            self.url.starts_with("http://") || self.url.starts_with("https://"),
+            "url",
+            "must be an http(s) URL",
+            "use e.g. https://api.devnet.solana.com",
+        );
+        violations.check(
+            !self.timeout.is_zero(),
+            "timeout",
+            "must be greater than 0",
+            "use a timeout such as 30s",
+        );
+        violations.check(
+            !self.keep_alive.is_zero(),
+            "keep_alive",
+            "must be greater than 0",
+            "use a keep-alive such as 60s",
+        );
+        violations.check(
+            self.max_connections > 0,
+            "max_connections",
+            "must be greater than 0",
+            "allow at least 1 connection, e.g. 100",
+        );
+        violations.check(
+            self.max_retries <= 10,
+            "max_retries",
+            "must be at most 10 (retries back off exponentially)",
+            "use 3 retries or fewer",
+        );
+        violations.nested("cluster", &self.cluster);
+    }
+}
+
 /// Network errors that can occur during operations
 #[derive(Error, Debug)]
 pub enum NetworkError {
@@ -77,6 +126,17 @@ pub enum NetworkError {
     /// Authentication failed
     #[error("Authentication failed: {0}")]
     AuthenticationFailed(String),
+
+    /// Invalid configuration
+    #[error("Invalid network configuration: {0}")]
+    InvalidConfig(#[from] ConfigErrors),
+
+    /// Connected node belongs to a different cluster
+    #[error("Genesis hash mismatch: expected {expected}, got {actual}")]
+    GenesisMismatch {
+        expected: String,
+        actual: String,
+    },
 }
 
 /// Result type for network operations
@@ -104,8 +164,12 @@ pub struct NetworkMetrics {
     pub total_responses: u64,
     /// Total errors encountered
     pub total_errors: u64,
-    /// Average latency
-    pub average_latency: Duration,
+    /// Requests per second over recent windows
+    pub requests_per_second: WindowedRates,
+    /// Errors per second over recent windows
+    pub errors_per_second: WindowedRates,
+    /// Exponentially weighted moving average latency
+    pub latency_ewma: Duration,
     /// Maximum latency observed
     pub max_latency: Duration,
 }
@@ -124,8 +188,14 @@ pub trait NetworkHandler: Send + Sync {
 }
 
 /// Initialize the network module with given configuration
+///
+/// Fails if the configuration is invalid or the endpoint does not belong
+/// to the configured cluster.
 pub async fn init(config: NetworkConfig) -> NetworkResult<NetworkClient> {
-    NetworkClient::new(config).await
+    config.validate()?;
+    let client = NetworkClient::new(config).await?;
+    client.verify_cluster().await?;
+    Ok(client)
 }
 
 #[cfg(test)]
@@ -140,6 +210,25 @@ mod tests {
         assert_eq!(config.max_retries, MAX_RETRIES);
     }
 
+    #[test]
+    fn test_network_config_validation() {
+        assert!(NetworkConfig::default().validate().is_ok());
+
+        let config = NetworkConfig {
+            url: "localhost:8899".to_string(),
+            timeout: Duration::from_secs(0),
+            ..Default::default()
+        };
+        let fields: Vec<String> = config
+            .validate()
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|v| v.field)
+            .collect();
+        assert_eq!(fields, vec!["url", "timeout"]);
+    }
+
     #[tokio::test]
     async fn test_network_init() {
         let config = NetworkConfig::default();
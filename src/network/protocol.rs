@@ -4,10 +4,15 @@
 //! - Message types and serialization
 //! - Protocol versioning
 //! - Message validation
-//! - Protocol handshaking
+//! - Protocol handshaking and capability negotiation
+//! - Per-peer message encoding
 //! - Message routing
 
 use serde::{Serialize, Deserialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
 use std::time::SystemTime;
 use sha2::{Sha256, Digest};
 use super::NetworkError;
@@ -15,6 +20,65 @@ use super::NetworkError;
 /// Protocol version
 pub const PROTOCOL_VERSION: u32 = 1;
 
+/// Largest message payload accepted from a peer, in bytes, after
+/// decompression
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Payloads smaller than this are sent uncompressed even if zstd was negotiated
+pub const COMPRESSION_THRESHOLD: usize = 256;
+
+/// zstd compression level used for outgoing messages
+const ZSTD_LEVEL: i32 = 3;
+
+/// Frame flag: payload is zstd compressed
+const FLAG_ZSTD: u8 = 0b01;
+
+/// Frame flag: payload is MessagePack encoded (bincode otherwise)
+const FLAG_MSGPACK: u8 = 0b10;
+
+/// Optional protocol features advertised in the handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Capability {
+    /// zstd payload compression
+    Zstd,
+    /// MessagePack message encoding
+    MsgPack,
+}
+
+impl Capability {
+    /// Capabilities this implementation can encode and decode
+    pub fn supported() -> Vec<Capability> {
+        vec![Capability::Zstd, Capability::MsgPack]
+    }
+
+    /// Name used on the wire
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::Zstd => "zstd",
+            Capability::MsgPack => "msgpack",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Capability {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zstd" => Ok(Capability::Zstd),
+            "msgpack" => Ok(Capability::MsgPack),
+            _ => Err(NetworkError::ProtocolError(format!("Unknown capability: {}", s))),
+        }
+    }
+}
+
 /// Message types for network communication
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum MessageType {
@@ -123,10 +187,23 @@ impl Message {
         })
     }
 
+    /// Create a handshake message advertising `capabilities`
+    pub fn handshake(capabilities: &[Capability]) -> Self {
+        let mut message = Self::new(MessageType::Handshake {
+            version: PROTOCOL_VERSION,
+            timestamp: 0,
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        });
+        if let MessageType::Handshake { timestamp, .. } = &mut message.message_type {
+            *timestamp = message.timestamp;
+        }
+        message
+    }
+
     /// Calculate message hash
     pub fn hash(&self) -> [u8; 32] {
         let mut hasher = Sha256::new();
-        hasher.update(&bincode::serialize(self).unwrap_or_default());
+        hasher.update(bincode::serialize(self).unwrap_or_default());
         hasher.finalize().into()
     }
 
@@ -153,27 +230,21 @@ impl Message {
 
         // Validate message-specific fields
         match &self.message_type {
-            MessageType::Request { id, method, .. } => {
-                if id.is_empty() || method.is_empty() {
-                    return Err(NetworkError::ProtocolError(
-                        "Invalid request message format".to_string()
-                    ));
-                }
+            MessageType::Request { id, method, .. } if id.is_empty() || method.is_empty() => {
+                return Err(NetworkError::ProtocolError(
+                    "Invalid request message format".to_string()
+                ));
             }
             MessageType::Response { id, .. } |
-            MessageType::Error { id, .. } => {
-                if id.is_empty() {
-                    return Err(NetworkError::ProtocolError(
-                        "Invalid response/error message format".to_string()
-                    ));
-                }
+            MessageType::Error { id, .. } if id.is_empty() => {
+                return Err(NetworkError::ProtocolError(
+                    "Invalid response/error message format".to_string()
+                ));
             }
-            MessageType::Notification { topic, .. } => {
-                if topic.is_empty() {
-                    return Err(NetworkError::ProtocolError(
-                        "Invalid notification message format".to_string()
-                    ));
-                }
+            MessageType::Notification { topic, .. } if topic.is_empty() => {
+                return Err(NetworkError::ProtocolError(
+                    "Invalid notification message format".to_string()
+                ));
             }
             _ => {}
         }
@@ -182,6 +253,130 @@ impl Message {
     }
 }
 
+/// Select the capabilities both sides advertised
+///
+/// Capabilities the peer advertises but this implementation does not know
+/// are ignored, so newer peers can add extensions without breaking older ones.
+pub fn negotiate(local: &[Capability], handshake: &Message) -> Result<Codec, NetworkError> {
+    let (version, remote) = match &handshake.message_type {
+        MessageType::Handshake { version, capabilities, .. } => (*version, capabilities),
+        _ => {
+            return Err(NetworkError::ProtocolError(
+                "Expected handshake message".to_string()
+            ))
+        }
+    };
+
+    if version != PROTOCOL_VERSION {
+        return Err(NetworkError::ProtocolError(
+            format!("Unsupported peer protocol version: {}", version)
+        ));
+    }
+
+    let remote: BTreeSet<Capability> = remote
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+
+    Ok(Codec::new(
+        local.iter().copied().filter(|c| remote.contains(c)).collect(),
+    ))
+}
+
+/// Message encoding agreed with a single peer
+///
+/// Every frame starts with a flags byte recording how the payload was
+/// encoded, so the receiver can decode it without extra state while still
+/// rejecting encodings that were never negotiated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Codec {
+    capabilities: BTreeSet<Capability>,
+}
+
+impl Codec {
+    /// Create a codec for a negotiated capability set
+    pub fn new(capabilities: BTreeSet<Capability>) -> Self {
+        Self { capabilities }
+    }
+
+    /// Whether `capability` was agreed with the peer
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    /// Negotiated capabilities
+    pub fn capabilities(&self) -> impl Iterator<Item = &Capability> {
+        self.capabilities.iter()
+    }
+
+    /// Encode a message for this peer
+    pub fn encode(&self, message: &Message) -> Result<Vec<u8>, NetworkError> {
+        let mut flags = 0;
+
+        let mut payload = if self.has(Capability::MsgPack) {
+            flags |= FLAG_MSGPACK;
+            rmp_serde::to_vec(message).map_err(|e| NetworkError::ProtocolError(e.to_string()))?
+        } else {
+            bincode::serialize(message).map_err(|e| NetworkError::ProtocolError(e.to_string()))?
+        };
+
+        if self.has(Capability::Zstd) && payload.len() >= COMPRESSION_THRESHOLD {
+            flags |= FLAG_ZSTD;
+            payload = zstd::encode_all(&payload[..], ZSTD_LEVEL)
+                .map_err(|e| NetworkError::ProtocolError(e.to_string()))?;
+        }
+
+        let mut frame = Vec::with_capacity(payload.len() + 1);
+        frame.push(flags);
+        frame.extend_from_slice(&payload);
+        Ok(frame)
+    }
+
+    /// Decode a message received from this peer
+    ///
+    /// Payloads over `MAX_MESSAGE_SIZE` are rejected; compressed ones are
+    /// only decompressed up to that size.
+    pub fn decode(&self, frame: &[u8]) -> Result<Message, NetworkError> {
+        let (&flags, payload) = frame
+            .split_first()
+            .ok_or_else(|| NetworkError::ProtocolError("Empty frame".to_string()))?;
+        let too_large = || NetworkError::ProtocolError(format!("Message exceeds {} bytes", MAX_MESSAGE_SIZE));
+        if payload.len() > MAX_MESSAGE_SIZE {
+            return Err(too_large());
+        }
+
+        if flags & !(FLAG_ZSTD | FLAG_MSGPACK) != 0
+            || (flags & FLAG_ZSTD != 0 && !self.has(Capability::Zstd))
+            || (flags & FLAG_MSGPACK != 0 && !self.has(Capability::MsgPack))
+        {
+            return Err(NetworkError::ProtocolError(
+                format!("Frame uses unnegotiated encoding: {:#04b}", flags)
+            ));
+        }
+
+        let mut decompressed = Vec::new();
+        let payload = if flags & FLAG_ZSTD != 0 {
+            zstd::stream::read::Decoder::new(payload)
+                .map_err(|e| NetworkError::ProtocolError(e.to_string()))?
+                .take(MAX_MESSAGE_SIZE as u64 + 1)
+                .read_to_end(&mut decompressed)
+                .map_err(|e| NetworkError::ProtocolError(e.to_string()))?;
+            if decompressed.len() > MAX_MESSAGE_SIZE {
+                return Err(too_large());
+            }
+            &decompressed[..]
+        } else {
+            payload
+        };
+
+        if flags & FLAG_MSGPACK != 0 {
+            rmp_serde::from_slice(payload).map_err(|e| NetworkError::ProtocolError(e.to_string()))
+        } else {
+            bincode::deserialize(payload).map_err(|e| NetworkError::ProtocolError(e.to_string()))
+        }
+    }
+}
+
 /// Protocol handler trait
 #[async_trait::async_trait]
 pub trait Protocol: Send + Sync {
@@ -225,4 +420,53 @@ mod tests {
         invalid_msg.version = 999;
         assert!(invalid_msg.validate().is_err());
     }
+
+    #[test]
+    fn test_capability_negotiation() {
+        let mut peer = Message::handshake(&[Capability::Zstd]);
+        if let MessageType::Handshake { capabilities, .. } = &mut peer.message_type {
+            capabilities.push("brotli".to_string());
+            capabilities.push("encryption".to_string());
+        }
+
+        let codec = negotiate(&Capability::supported(), &peer).unwrap();
+        assert_eq!(codec.capabilities().copied().collect::<Vec<_>>(), vec![Capability::Zstd]);
+
+        let request = Message::request("id", "method", vec![]);
+        assert!(negotiate(&Capability::supported(), &request).is_err());
+    }
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let message = Message::notification("prices", vec![7; 1024]);
+
+        for capabilities in [
+            vec![],
+            vec![Capability::Zstd],
+            vec![Capability::MsgPack],
+            vec![Capability::Zstd, Capability::MsgPack],
+        ] {
+            let codec = Codec::new(capabilities.into_iter().collect());
+            let frame = codec.encode(&message).unwrap();
+            let decoded = codec.decode(&frame).unwrap();
+            assert_eq!(decoded.message_type, message.message_type);
+        }
+
+        let compressed = Codec::new([Capability::Zstd].into_iter().collect());
+        let frame = compressed.encode(&message).unwrap();
+        assert!(frame.len() < 1024);
+        assert!(Codec::default().decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let codec = Codec::new([Capability::Zstd].into_iter().collect());
+        let small = codec.encode(&Message::notification("prices", vec![0; MAX_MESSAGE_SIZE / 2])).unwrap();
+        assert!(codec.decode(&small).is_ok());
+
+        // Compresses to a few kilobytes but expands past the limit
+        let bomb = codec.encode(&Message::notification("prices", vec![0; MAX_MESSAGE_SIZE])).unwrap();
+        assert!(bomb.len() < MAX_MESSAGE_SIZE / 100);
+        assert!(matches!(codec.decode(&bomb), Err(NetworkError::ProtocolError(_))));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,126 @@
+//! Funding test wallets from a cluster's faucet
+//!
+//! Faucets rate-limit and cap each airdrop, and a requested airdrop can be
+//! dropped before it lands. `ensure_balance` requests airdrops until the
+//! balance is reached, backing off after each failure, and checks the
+//! balance itself rather than trusting any single airdrop.
+//!
+//! Only an `RpcClient` is needed, so examples and scripts funding devnet
+//! wallets don't depend on the `test-utils` fixtures.
+
+use std::thread;
+use std::time::Duration;
+use solana_client::{client_error::ClientError as RpcError, rpc_client::RpcClient};
+use solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey};
+use thiserror::Error;
+
+/// Largest airdrop requested at once; the devnet faucet refuses more
+pub const MAX_AIRDROP_LAMPORTS: u64 = 2 * LAMPORTS_PER_SOL;
+
+#[derive(Error, Debug)]
+pub enum AirdropError {
+    #[error("RPC error: {0}")]
+    Rpc(Box<RpcError>),
+
+    #[error("{address} holds {balance} lamports after {attempts} airdrop attempts, short of {target}")]
+    Exhausted {
+        address: Pubkey,
+        balance: u64,
+        target: u64,
+        attempts: u32,
+    },
+}
+
+impl From<RpcError> for AirdropError {
+    fn from(error: RpcError) -> Self {
+        Self::Rpc(Box::new(error))
+    }
+}
+
+/// Airdrop retry configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct AirdropConfig {
+    /// Airdrops requested before giving up, failed ones included
+    pub attempts: u32,
+    /// Wait after the first failure, doubled after each further one
+    pub initial_backoff: Duration,
+    /// Largest airdrop requested at once
+    pub max_airdrop: u64,
+}
+
+impl Default for AirdropConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 8,
+            initial_backoff: Duration::from_millis(500),
+            max_airdrop: MAX_AIRDROP_LAMPORTS,
+        }
+    }
+}
+
+/// Airdrop to `address` until it holds at least `lamports`, returning its
+/// balance
+pub fn ensure_balance(rpc: &RpcClient, address: &Pubkey, lamports: u64) -> Result<u64, AirdropError> {
+    ensure_balance_with(rpc, address, lamports, &AirdropConfig::default())
+}
+
+/// `ensure_balance`, retrying as `config` sets
+pub fn ensure_balance_with(
+    rpc: &RpcClient,
+    address: &Pubkey,
+    lamports: u64,
+    config: &AirdropConfig,
+) -> Result<u64, AirdropError> {
+    let mut balance = rpc.get_balance(address)?;
+    let mut backoff = config.initial_backoff;
+
+    for _ in 0..config.attempts {
+        if balance >= lamports {
+            return Ok(balance);
+        }
+
+        let amount = (lamports - balance).min(config.max_airdrop);
+        let landed = match rpc.request_airdrop(address, amount) {
+            Ok(signature) => rpc.poll_for_signature_with_commitment(&signature, rpc.commitment()).is_ok(),
+            Err(_) => false,
+        };
+        if !landed {
+            thread::sleep(backoff);
+            backoff = backoff.saturating_mul(2);
+        }
+        balance = rpc.get_balance(address)?;
+    }
+
+    if balance >= lamports {
+        return Ok(balance);
+    }
+    Err(AirdropError::Exhausted {
+        address: *address,
+        balance,
+        target: lamports,
+        attempts: config.attempts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ensure_balance() {
+        // The mock reports a balance of 50 lamports, whatever was airdropped
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        let address = Pubkey::new_unique();
+        assert_eq!(ensure_balance(&rpc, &address, 40).unwrap(), 50);
+
+        let config = AirdropConfig {
+            attempts: 2,
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            ensure_balance_with(&rpc, &address, 1_000, &config),
+            Err(AirdropError::Exhausted { balance: 50, target: 1_000, attempts: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,269 @@
+//! Client-side cache of decoded agent accounts
+//!
+//! This module provides:
+//! - Agent accounts keyed by address, with the slot they were read at
+//! - Reads served from the cache while younger than the TTL and at or
+//!   after the slot the caller needs
+//! - Entries kept current by an account stream
+//! - Hit/miss accounting
+//!
+//! An older read never replaces a newer one, so a lagging node can't roll
+//! an entry back. Callers that wrote to an agent pass the slot of their
+//! write as `min_context_slot`, which skips entries read before it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+use tokio::sync::broadcast;
+use crate::error::SonomaResult;
+use crate::solana::client::{self, ClientResult, ReadOptions};
+use crate::solana::program::state::AgentAccount;
+use crate::solana::stream::AccountStream;
+
+/// Default time-to-live for cached agents
+pub const DEFAULT_ACCOUNT_TTL: Duration = Duration::from_secs(2);
+
+/// Account cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountCacheConfig {
+    /// Time after which an agent is read again
+    pub ttl: Duration,
+    /// Maximum number of cached agents
+    pub max_entries: usize,
+}
+
+impl Default for AccountCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_ACCOUNT_TTL,
+            max_entries: 10_000,
+        }
+    }
+}
+
+/// Cache hit/miss statistics
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct AccountCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+struct CachedAgent {
+    agent: AgentAccount,
+    slot: Slot,
+    fetched_at: Instant,
+}
+
+#[derive(Default)]
+struct Entries {
+    agents: HashMap<Pubkey, CachedAgent>,
+    stats: AccountCacheStats,
+}
+
+/// Cache of agent accounts, shared between the handles and threads that
+/// read them
+pub struct AccountCache {
+    config: AccountCacheConfig,
+    entries: Mutex<Entries>,
+}
+
+impl AccountCache {
+    pub fn new(config: AccountCacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Cached agent at `address`, if younger than the TTL and read at or
+    /// after `min_slot`
+    pub fn get(&self, address: &Pubkey, min_slot: Option<Slot>) -> Option<AgentAccount> {
+        let mut entries = self.entries();
+        let agent = entries
+            .agents
+            .get(address)
+            .filter(|entry| entry.fetched_at.elapsed() <= self.config.ttl)
+            .filter(|entry| min_slot.map_or(true, |slot| entry.slot >= slot))
+            .map(|entry| entry.agent.clone());
+
+        match agent {
+            Some(_) => entries.stats.hits += 1,
+            None => entries.stats.misses += 1,
+        }
+        agent
+    }
+
+    /// Cached agent at `address` and the slot it was read at, regardless of
+    /// TTL
+    pub fn get_with_slot(&self, address: &Pubkey) -> Option<(AgentAccount, Slot)> {
+        self.entries()
+            .agents
+            .get(address)
+            .map(|entry| (entry.agent.clone(), entry.slot))
+    }
+
+    /// Store `agent` as read at `slot`, unless a later read is cached
+    pub fn insert(&self, address: Pubkey, agent: AgentAccount, slot: Slot) {
+        let mut entries = self.entries();
+        if entries.agents.get(&address).map_or(false, |entry| entry.slot > slot) {
+            return;
+        }
+        if entries.agents.len() >= self.config.max_entries && !entries.agents.contains_key(&address) {
+            let ttl = self.config.ttl;
+            entries.agents.retain(|_, entry| entry.fetched_at.elapsed() <= ttl);
+            if entries.agents.len() >= self.config.max_entries {
+                evict_oldest(&mut entries.agents);
+            }
+        }
+
+        entries.agents.insert(address, CachedAgent {
+            agent,
+            slot,
+            fetched_at: Instant::now(),
+        });
+    }
+
+    /// Remove the agent at `address`, e.g. once it is closed
+    pub fn invalidate(&self, address: &Pubkey) {
+        self.entries().agents.remove(address);
+    }
+
+    pub fn clear(&self) {
+        self.entries().agents.clear();
+    }
+
+    pub fn stats(&self) -> AccountCacheStats {
+        let entries = self.entries();
+        AccountCacheStats {
+            entries: entries.agents.len(),
+            ..entries.stats.clone()
+        }
+    }
+
+    /// Agent at `address` from the cache, or fetched and cached on a miss
+    pub fn get_agent(
+        &self,
+        rpc: &RpcClient,
+        program_id: &Pubkey,
+        address: &Pubkey,
+        options: &ReadOptions,
+    ) -> ClientResult<AgentAccount> {
+        if let Some(agent) = self.get(address, options.min_context_slot) {
+            return Ok(agent);
+        }
+
+        let (agent, slot) = client::get_agent_with_slot(rpc, program_id, address, options)?;
+        self.insert(*address, agent.clone(), slot);
+        Ok(agent)
+    }
+
+    /// Keep the agent at `address` current from `stream`'s updates, and
+    /// drop it once it is closed
+    ///
+    /// Updates are applied on a background thread, which stops once the
+    /// cache is dropped or the stream ends.
+    pub fn watch(
+        self: &Arc<Self>,
+        stream: &dyn AccountStream,
+        program_id: &Pubkey,
+        address: &Pubkey,
+    ) -> SonomaResult<()> {
+        let mut updates = stream.subscribe_account(address)?;
+        let cache: Weak<Self> = Arc::downgrade(self);
+        let program_id = *program_id;
+
+        std::thread::spawn(move || loop {
+            let update = match updates.blocking_recv() {
+                Ok(update) => update,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let Some(cache) = cache.upgrade() else {
+                break;
+            };
+            if update.account.lamports == 0 || update.account.owner != program_id {
+                cache.invalidate(&update.address);
+                continue;
+            }
+            match AgentAccount::unpack(&update.account.data) {
+                Ok(agent) => cache.insert(update.address, agent, update.slot),
+                Err(_) => cache.invalidate(&update.address),
+            }
+        });
+        Ok(())
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn evict_oldest(agents: &mut HashMap<Pubkey, CachedAgent>) {
+    if let Some(oldest) = agents
+        .iter()
+        .min_by_key(|(_, entry)| entry.fetched_at)
+        .map(|(address, _)| *address)
+    {
+        agents.remove(&oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::program::state::AgentState;
+    use crate::test_utils::agent_account;
+
+    fn agent(state: AgentState) -> AgentAccount {
+        AgentAccount { state, ..agent_account("agent") }
+    }
+
+    #[test]
+    fn test_slot_invalidation() {
+        let cache = AccountCache::new(AccountCacheConfig::default());
+        let address = Pubkey::new_unique();
+
+        assert_eq!(cache.get(&address, None), None);
+        cache.insert(address, agent(AgentState::Running), 100);
+        assert_eq!(cache.get(&address, Some(100)).map(|agent| agent.state), Some(AgentState::Running));
+        // A write landed after the cached read
+        assert_eq!(cache.get(&address, Some(101)), None);
+
+        // Older reads don't roll the entry back
+        cache.insert(address, agent(AgentState::Paused), 90);
+        let cached = cache.get_with_slot(&address).map(|(agent, slot)| (agent.state, slot));
+        assert_eq!(cached, Some((AgentState::Running, 100)));
+        cache.insert(address, agent(AgentState::Paused), 120);
+        assert_eq!(cache.get(&address, Some(101)).map(|agent| agent.state), Some(AgentState::Paused));
+
+        cache.invalidate(&address);
+        assert_eq!(cache.get(&address, None), None);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (2, 3, 0));
+    }
+
+    #[test]
+    fn test_ttl_and_capacity() {
+        let cache = AccountCache::new(AccountCacheConfig {
+            ttl: Duration::from_millis(20),
+            max_entries: 2,
+        });
+        let address = Pubkey::new_unique();
+        cache.insert(address, agent(AgentState::Running), 1);
+        assert!(cache.get(&address, None).is_some());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(cache.get(&address, None).is_none());
+        assert!(cache.get_with_slot(&address).is_some());
+
+        for slot in 2..5 {
+            cache.insert(Pubkey::new_unique(), agent(AgentState::Running), slot);
+        }
+        assert_eq!(cache.stats().entries, 2);
+    }
+}
@@ -0,0 +1,912 @@
+//! RPC helpers for reading agent program accounts
+//!
+//! This module provides:
+//! - Agent account lookup, individually, in batches or by creating
+//!   authority
+//! - Listing the program's agents, filtered by authority and state, in
+//!   full or one page at a time
+//! - Live updates of every agent of the program, from an account stream
+//! - Agent metadata (creation and update times)
+//! - Execution receipt lookup by execution number or range
+//! - Recent execution results with their output hashes
+//! - Program config lookup (admin and freeze state)
+//! - Reads at a chosen commitment, pinned at or after a slot
+//! - Cloning an existing agent's config into new agents
+//! - Deriving the PubSub URL of an RPC URL
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::thread;
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
+use solana_client::{
+    client_error::ClientError as RpcError,
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{
+    account::Account,
+    clock::Slot,
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    pubkey::Pubkey,
+};
+use thiserror::Error;
+use tokio::sync::broadcast;
+use crate::error::SonomaResult;
+use crate::solana::stream::AccountStream;
+use crate::solana::program::{
+    instruction::AgentInstruction,
+    pda,
+    state::{
+        AgentAccount, AgentMetadata, AgentRegistry, AgentState, ExecutionReceipt, ExecutionResult, ProgramConfig,
+        ResultAccount,
+    },
+};
+#[cfg(not(feature = "zero-copy"))]
+use crate::solana::program::state::AGENT_ACCOUNT_VERSION;
+#[cfg(feature = "zero-copy")]
+use crate::solana::program::zero_copy::{AgentAccountZc, AUTHORITY_OFFSET, STATE_OFFSET, ZERO_COPY_VERSION};
+
+/// Maximum number of accounts per `getMultipleAccounts` request
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Number of agent updates a lagging subscriber may fall behind by
+pub const AGENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("RPC error: {0}")]
+    Rpc(Box<RpcError>),
+
+    #[error("Account not found: {0}")]
+    AccountNotFound(Pubkey),
+
+    #[error("Invalid account data: {0}")]
+    InvalidAccountData(Pubkey),
+
+    #[error("Account stream error: {0}")]
+    Stream(String),
+}
+
+impl From<RpcError> for ClientError {
+    fn from(error: RpcError) -> Self {
+        Self::Rpc(Box::new(error))
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// Commitment and minimum slot of the reads
+///
+/// A lagging RPC node can serve account data from before a transaction
+/// that already landed. Reads with the slot of that transaction as
+/// `min_context_slot` fail on such a node instead of returning stale data.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReadOptions {
+    /// Commitment to read at; `None` uses the client's
+    pub commitment: Option<CommitmentConfig>,
+    /// Slot the node must have reached to serve the read
+    pub min_context_slot: Option<Slot>,
+}
+
+impl ReadOptions {
+    /// Reads at or after `slot`, at the client's commitment
+    pub fn at_slot(slot: Slot) -> Self {
+        Self {
+            commitment: None,
+            min_context_slot: Some(slot),
+        }
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = Some(commitment);
+        self
+    }
+
+    /// Account config of the RPC requests making these reads
+    pub fn account_config(&self, rpc: &RpcClient) -> RpcAccountInfoConfig {
+        RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment.unwrap_or_else(|| rpc.commitment())),
+            min_context_slot: self.min_context_slot,
+            ..Default::default()
+        }
+    }
+}
+
+pub(crate) fn get_account(rpc: &RpcClient, address: &Pubkey, options: &ReadOptions) -> ClientResult<Option<Account>> {
+    Ok(rpc.get_account_with_config(address, options.account_config(rpc))?.value)
+}
+
+fn get_multiple_accounts(
+    rpc: &RpcClient,
+    addresses: &[Pubkey],
+    options: &ReadOptions,
+) -> ClientResult<Vec<Option<Account>>> {
+    Ok(rpc.get_multiple_accounts_with_config(addresses, options.account_config(rpc))?.value)
+}
+
+/// PubSub URL of a validator's RPC URL: `ws(s)` instead of `http(s)`, and
+/// the PubSub port next to a local validator's default RPC port
+pub fn websocket_url(rpc_url: &str) -> String {
+    let url = if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    };
+    url.replace(":8899", ":8900")
+}
+
+/// Fetch and decode an agent account
+pub fn get_agent(rpc: &RpcClient, program_id: &Pubkey, agent: &Pubkey) -> ClientResult<AgentAccount> {
+    get_agent_with_options(rpc, program_id, agent, &ReadOptions::default())
+}
+
+/// `get_agent`, reading with `options`
+pub fn get_agent_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<AgentAccount> {
+    let account = get_account(rpc, agent, options)?.ok_or(ClientError::AccountNotFound(*agent))?;
+    parse_agent(program_id, agent, &account)
+}
+
+/// `get_agent`, along with the slot the node read the account at
+pub fn get_agent_with_slot(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<(AgentAccount, Slot)> {
+    let response = rpc.get_account_with_config(agent, options.account_config(rpc))?;
+    let account = response.value.ok_or(ClientError::AccountNotFound(*agent))?;
+    Ok((parse_agent(program_id, agent, &account)?, response.context.slot))
+}
+
+/// Fetch many agent accounts, `MAX_MULTIPLE_ACCOUNTS` per request
+///
+/// Batches are fetched and decoded on parallel threads. Addresses without
+/// an account are left out of the map.
+pub fn fetch_agents(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    addresses: &[Pubkey],
+) -> ClientResult<HashMap<Pubkey, AgentAccount>> {
+    fetch_agents_with_options(rpc, program_id, addresses, &ReadOptions::default())
+}
+
+/// `fetch_agents`, reading with `options`
+pub fn fetch_agents_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    addresses: &[Pubkey],
+    options: &ReadOptions,
+) -> ClientResult<HashMap<Pubkey, AgentAccount>> {
+    thread::scope(|scope| {
+        let batches: Vec<_> = addresses
+            .chunks(MAX_MULTIPLE_ACCOUNTS)
+            .map(|batch| {
+                scope.spawn(move || {
+                    decode_agents(program_id, batch, get_multiple_accounts(rpc, batch, options)?)
+                })
+            })
+            .collect();
+
+        let mut agents = HashMap::with_capacity(addresses.len());
+        for batch in batches {
+            agents.extend(batch.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))?);
+        }
+        Ok(agents)
+    })
+}
+
+/// Fetch an agent's metadata, `None` for agents created before metadata
+/// existed and never updated since
+pub fn get_metadata(rpc: &RpcClient, program_id: &Pubkey, agent: &Pubkey) -> ClientResult<Option<AgentMetadata>> {
+    get_metadata_with_options(rpc, program_id, agent, &ReadOptions::default())
+}
+
+/// `get_metadata`, reading with `options`
+pub fn get_metadata_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<Option<AgentMetadata>> {
+    let (address, _) = pda::find_metadata_address(program_id, agent);
+    let account = match get_account(rpc, &address, options)? {
+        Some(account) => account,
+        None => return Ok(None),
+    };
+    if account.owner != *program_id {
+        return Err(ClientError::InvalidAccountData(address));
+    }
+
+    match AgentMetadata::unpack(&account.data) {
+        Ok(metadata) if metadata.agent == *agent => Ok(Some(metadata)),
+        _ => Err(ClientError::InvalidAccountData(address)),
+    }
+}
+
+/// Conditions on the agents returned by `list_agents`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentFilter {
+    pub authority: Option<Pubkey>,
+    pub state: Option<AgentState>,
+    /// Also return Borsh-encoded agents not yet migrated to the current
+    /// layout, which the RPC node can't tell apart from the program's other
+    /// accounts
+    pub include_legacy: bool,
+}
+
+impl AgentFilter {
+    pub fn matches(&self, agent: &AgentAccount) -> bool {
+        self.authority.map_or(true, |authority| agent.authority == authority)
+            && self.state.as_ref().map_or(true, |state| agent.state == *state)
+    }
+
+    /// `getProgramAccounts` filters applied by the RPC node
+    ///
+    /// Borsh-encoded agents vary in size, so they are told apart from the
+    /// program's other accounts by their leading layout version, unless
+    /// `include_legacy` is set. They only have the authority at a fixed
+    /// offset, right after the layout version; agents created before layout
+    /// versions existed don't match it. Zero-copy accounts are filtered by
+    /// size, layout, authority and state.
+    pub fn rpc_filters(&self) -> Vec<RpcFilterType> {
+        let mut filters = Vec::new();
+
+        #[cfg(not(feature = "zero-copy"))]
+        {
+            if !self.include_legacy {
+                filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, vec![AGENT_ACCOUNT_VERSION])));
+            }
+            if let Some(authority) = self.authority {
+                filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(1, authority.to_bytes().to_vec())));
+            }
+        }
+
+        #[cfg(feature = "zero-copy")]
+        {
+            filters.push(RpcFilterType::DataSize(AgentAccountZc::LEN as u64));
+            filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, vec![ZERO_COPY_VERSION])));
+            if let Some(state) = &self.state {
+                filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(STATE_OFFSET, vec![state.clone() as u8])));
+            }
+            if let Some(authority) = self.authority {
+                filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                    AUTHORITY_OFFSET,
+                    authority.to_bytes().to_vec(),
+                )));
+            }
+        }
+
+        filters
+    }
+}
+
+/// Agents of one `list_agents_page` call
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentPage {
+    /// Agents in ascending address order
+    pub agents: Vec<(Pubkey, AgentAccount)>,
+    /// Address to continue after, `None` when this is the last page
+    pub next_cursor: Option<Pubkey>,
+}
+
+impl AgentPage {
+    /// Whether more pages may follow this one
+    pub fn has_more(&self) -> bool {
+        self.next_cursor.is_some()
+    }
+}
+
+/// Fetch every agent of the program matching `filter`
+///
+/// Accounts of the program that aren't agents are skipped. Programs with
+/// many agents are better read with `list_agents_page`.
+pub fn list_agents(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    filter: &AgentFilter,
+) -> ClientResult<Vec<(Pubkey, AgentAccount)>> {
+    list_agents_with_options(rpc, program_id, filter, &ReadOptions::default())
+}
+
+/// `list_agents`, reading with `options`
+pub fn list_agents_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    filter: &AgentFilter,
+    options: &ReadOptions,
+) -> ClientResult<Vec<(Pubkey, AgentAccount)>> {
+    let addresses = agent_addresses(rpc, program_id, filter, options)?;
+    let mut agents = Vec::new();
+    for batch in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        agents.extend(matching_agents(filter, batch, get_multiple_accounts(rpc, batch, options)?));
+    }
+    Ok(agents)
+}
+
+/// Fetch up to `limit` agents matching `filter` whose address comes after
+/// `after`, in ascending address order
+///
+/// Only the addresses of the matching accounts are listed in full; agents
+/// are fetched `MAX_MULTIPLE_ACCOUNTS` at a time until the page is full.
+/// The order is stable across calls, so agents created between two pages
+/// are returned by a later page if their address sorts after the cursor.
+pub fn list_agents_page(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    filter: &AgentFilter,
+    after: Option<&Pubkey>,
+    limit: usize,
+) -> ClientResult<AgentPage> {
+    list_agents_page_with_options(rpc, program_id, filter, after, limit, &ReadOptions::default())
+}
+
+/// `list_agents_page`, reading with `options`
+pub fn list_agents_page_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    filter: &AgentFilter,
+    after: Option<&Pubkey>,
+    limit: usize,
+    options: &ReadOptions,
+) -> ClientResult<AgentPage> {
+    let limit = limit.max(1);
+    let addresses = agent_addresses(rpc, program_id, filter, options)?;
+    let start = after.map_or(0, |after| addresses.partition_point(|address| address <= after));
+    let remaining = &addresses[start..];
+
+    let mut page = AgentPage::default();
+    for batch in remaining.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = get_multiple_accounts(rpc, batch, options)?;
+        for (address, agent) in matching_agents(filter, batch, accounts) {
+            if page.agents.len() == limit {
+                page.next_cursor = page.agents.last().map(|(address, _)| *address);
+                return Ok(page);
+            }
+            page.agents.push((address, agent));
+        }
+    }
+    Ok(page)
+}
+
+/// Addresses of the program accounts passing `filter`'s RPC filters, in
+/// ascending order, listed without their data
+fn agent_addresses(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    filter: &AgentFilter,
+    options: &ReadOptions,
+) -> ClientResult<Vec<Pubkey>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filter.rpc_filters()),
+        account_config: RpcAccountInfoConfig {
+            data_slice: Some(UiDataSliceConfig { offset: 0, length: 0 }),
+            ..options.account_config(rpc)
+        },
+        ..Default::default()
+    };
+
+    let mut addresses: Vec<Pubkey> = rpc
+        .get_program_accounts_with_config(program_id, config)?
+        .into_iter()
+        .map(|(address, _)| address)
+        .collect();
+    addresses.sort_unstable();
+    Ok(addresses)
+}
+
+/// Decode the agents among `accounts` that match `filter`, skipping
+/// accounts that are gone or aren't agents
+fn matching_agents(
+    filter: &AgentFilter,
+    addresses: &[Pubkey],
+    accounts: Vec<Option<Account>>,
+) -> Vec<(Pubkey, AgentAccount)> {
+    addresses
+        .iter()
+        .zip(accounts)
+        .filter_map(|(address, account)| Some((*address, AgentAccount::unpack(&account?.data).ok()?)))
+        .filter(|(_, agent)| filter.matches(agent))
+        .collect()
+}
+
+/// Broadcast every agent of the program matching `filter`, with its
+/// address, each time its account changes
+///
+/// Agents are told apart from the program's other accounts by the stream,
+/// by their layout (see `AgentFilter::rpc_filters`); with `include_legacy`,
+/// the accounts that aren't agents are skipped as they fail to decode.
+/// Updates are decoded on a background thread, which stops once every
+/// receiver is dropped.
+pub fn subscribe_program_agents(
+    stream: &dyn AccountStream,
+    program_id: &Pubkey,
+    filter: &AgentFilter,
+) -> SonomaResult<broadcast::Receiver<(Pubkey, AgentAccount)>> {
+    let mut updates = stream.subscribe_program(program_id, filter.rpc_filters())?;
+    let (sender, receiver) = broadcast::channel(AGENT_CHANNEL_CAPACITY);
+    let filter = filter.clone();
+
+    thread::spawn(move || loop {
+        let update = match updates.blocking_recv() {
+            Ok(update) => update,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let Ok(agent) = AgentAccount::unpack(&update.account.data) else {
+            continue;
+        };
+        if filter.matches(&agent) && sender.send((update.address, agent)).is_err() {
+            break;
+        }
+    });
+    Ok(receiver)
+}
+
+/// `CloneAgent` instructions creating one agent per name with the config of
+/// `source`, and the addresses of the new agents
+///
+/// The source is fetched first so a wrong address fails before anything is
+/// sent. Send one instruction per transaction; each creates an account.
+pub fn clone_agents(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    source: &Pubkey,
+    authority: &Pubkey,
+    names: &[&str],
+) -> ClientResult<Vec<(Pubkey, Instruction)>> {
+    clone_agents_with_options(rpc, program_id, source, authority, names, &ReadOptions::default())
+}
+
+/// `clone_agents`, reading with `options`
+pub fn clone_agents_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    source: &Pubkey,
+    authority: &Pubkey,
+    names: &[&str],
+    options: &ReadOptions,
+) -> ClientResult<Vec<(Pubkey, Instruction)>> {
+    get_agent_with_options(rpc, program_id, source, options)?;
+
+    Ok(names
+        .iter()
+        .map(|name| {
+            let (agent, _) = pda::find_agent_address(program_id, authority, name);
+            let instruction =
+                AgentInstruction::clone_agent(program_id, &agent, authority, source, name.to_string());
+            (agent, instruction)
+        })
+        .collect())
+}
+
+/// Fetch the program config, `None` until an admin has been appointed
+pub fn get_program_config(rpc: &RpcClient, program_id: &Pubkey) -> ClientResult<Option<ProgramConfig>> {
+    get_program_config_with_options(rpc, program_id, &ReadOptions::default())
+}
+
+/// `get_program_config`, reading with `options`
+pub fn get_program_config_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<Option<ProgramConfig>> {
+    let (address, _) = pda::find_config_address(program_id);
+    let account = match get_account(rpc, &address, options)? {
+        Some(account) => account,
+        None => return Ok(None),
+    };
+    if account.owner != *program_id {
+        return Err(ClientError::InvalidAccountData(address));
+    }
+
+    ProgramConfig::unpack(&account.data)
+        .map(Some)
+        .map_err(|_| ClientError::InvalidAccountData(address))
+}
+
+/// Addresses of the agents held by `authority`, read from its registry
+pub fn get_registry(rpc: &RpcClient, program_id: &Pubkey, authority: &Pubkey) -> ClientResult<Vec<Pubkey>> {
+    get_registry_with_options(rpc, program_id, authority, &ReadOptions::default())
+}
+
+/// `get_registry`, reading with `options`
+pub fn get_registry_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<Vec<Pubkey>> {
+    let (address, _) = pda::find_registry_address(program_id, authority);
+    let account = match get_account(rpc, &address, options)? {
+        Some(account) => account,
+        None => return Ok(Vec::new()),
+    };
+    if account.owner != *program_id {
+        return Err(ClientError::InvalidAccountData(address));
+    }
+
+    match AgentRegistry::unpack(&account.data) {
+        Ok(registry) if registry.authority == *authority => Ok(registry.agents),
+        _ => Err(ClientError::InvalidAccountData(address)),
+    }
+}
+
+/// Fetch every agent held by `authority`
+pub fn get_agents_by_authority(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    authority: &Pubkey,
+) -> ClientResult<Vec<(Pubkey, AgentAccount)>> {
+    get_agents_by_authority_with_options(rpc, program_id, authority, &ReadOptions::default())
+}
+
+/// `get_agents_by_authority`, reading with `options`
+pub fn get_agents_by_authority_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<Vec<(Pubkey, AgentAccount)>> {
+    let addresses = get_registry_with_options(rpc, program_id, authority, options)?;
+    let mut agents = Vec::with_capacity(addresses.len());
+
+    for batch in addresses.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = get_multiple_accounts(rpc, batch, options)?;
+        for (address, account) in batch.iter().zip(accounts) {
+            let account = account.ok_or(ClientError::AccountNotFound(*address))?;
+            agents.push((*address, parse_agent(program_id, address, &account)?));
+        }
+    }
+
+    Ok(agents)
+}
+
+/// Fetch the receipt of an agent's `execution`-th execution (1-based)
+pub fn get_receipt(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    execution: u64,
+) -> ClientResult<Option<ExecutionReceipt>> {
+    get_receipt_with_options(rpc, program_id, agent, execution, &ReadOptions::default())
+}
+
+/// `get_receipt`, reading with `options`
+pub fn get_receipt_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    execution: u64,
+    options: &ReadOptions,
+) -> ClientResult<Option<ExecutionReceipt>> {
+    Ok(get_receipts_with_options(rpc, program_id, agent, execution..execution + 1, options)?.pop())
+}
+
+/// Fetch the receipts of a range of executions, oldest first
+///
+/// Executions without a receipt (e.g. from before receipts existed) are
+/// skipped.
+pub fn get_receipts(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    executions: Range<u64>,
+) -> ClientResult<Vec<ExecutionReceipt>> {
+    get_receipts_with_options(rpc, program_id, agent, executions, &ReadOptions::default())
+}
+
+/// `get_receipts`, reading with `options`
+pub fn get_receipts_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    executions: Range<u64>,
+    options: &ReadOptions,
+) -> ClientResult<Vec<ExecutionReceipt>> {
+    let executions: Vec<u64> = executions.filter(|execution| *execution > 0).collect();
+    let mut receipts = Vec::with_capacity(executions.len());
+
+    for batch in executions.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let addresses: Vec<Pubkey> = batch
+            .iter()
+            .map(|execution| pda::find_receipt_address(program_id, agent, *execution).0)
+            .collect();
+        let accounts = get_multiple_accounts(rpc, &addresses, options)?;
+
+        for ((execution, address), account) in batch.iter().zip(&addresses).zip(accounts) {
+            if let Some(account) = account {
+                receipts.push(parse_receipt(program_id, agent, *execution, address, &account)?);
+            }
+        }
+    }
+
+    Ok(receipts)
+}
+
+/// Fetch the receipts of an agent's last `limit` executions, oldest first
+pub fn get_recent_receipts(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    limit: u64,
+) -> ClientResult<Vec<ExecutionReceipt>> {
+    get_recent_receipts_with_options(rpc, program_id, agent, limit, &ReadOptions::default())
+}
+
+/// `get_recent_receipts`, reading with `options`
+pub fn get_recent_receipts_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    limit: u64,
+    options: &ReadOptions,
+) -> ClientResult<Vec<ExecutionReceipt>> {
+    let count = get_agent_with_options(rpc, program_id, agent, options)?.execution_count;
+    get_receipts_with_options(rpc, program_id, agent, count.saturating_sub(limit) + 1..count + 1, options)
+}
+
+/// Fetch an agent's recent execution results, oldest first
+///
+/// Up to `RESULT_HISTORY_LEN` results are kept; agents that never executed
+/// have none.
+pub fn get_results(rpc: &RpcClient, program_id: &Pubkey, agent: &Pubkey) -> ClientResult<Vec<ExecutionResult>> {
+    get_results_with_options(rpc, program_id, agent, &ReadOptions::default())
+}
+
+/// `get_results`, reading with `options`
+pub fn get_results_with_options(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<Vec<ExecutionResult>> {
+    let (address, _) = pda::find_result_address(program_id, agent);
+    let account = match get_account(rpc, &address, options)? {
+        Some(account) => account,
+        None => return Ok(Vec::new()),
+    };
+
+    let results = parse_results(program_id, agent, &address, &account)?;
+    Ok(results.recent().cloned().collect())
+}
+
+/// Decode an agent account, checking the program owns it
+fn parse_agent(program_id: &Pubkey, address: &Pubkey, account: &Account) -> ClientResult<AgentAccount> {
+    if account.owner != *program_id {
+        return Err(ClientError::InvalidAccountData(*address));
+    }
+
+    AgentAccount::unpack(&account.data).map_err(|_| ClientError::InvalidAccountData(*address))
+}
+
+/// Decode the agents of one `getMultipleAccounts` batch, skipping missing
+/// accounts
+fn decode_agents(
+    program_id: &Pubkey,
+    addresses: &[Pubkey],
+    accounts: Vec<Option<Account>>,
+) -> ClientResult<Vec<(Pubkey, AgentAccount)>> {
+    addresses
+        .iter()
+        .zip(accounts)
+        .filter_map(|(address, account)| Some((address, account?)))
+        .map(|(address, account)| Ok((*address, parse_agent(program_id, address, &account)?)))
+        .collect()
+}
+
+/// Decode a result account, checking it belongs to `agent`
+fn parse_results(
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    address: &Pubkey,
+    account: &Account,
+) -> ClientResult<ResultAccount> {
+    if account.owner != *program_id {
+        return Err(ClientError::InvalidAccountData(*address));
+    }
+
+    match ResultAccount::unpack(&account.data) {
+        Ok(results) if results.agent == *agent => Ok(results),
+        _ => Err(ClientError::InvalidAccountData(*address)),
+    }
+}
+
+/// Decode a receipt account, checking it belongs to `agent`
+fn parse_receipt(
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    execution: u64,
+    address: &Pubkey,
+    account: &Account,
+) -> ClientResult<ExecutionReceipt> {
+    if account.owner != *program_id {
+        return Err(ClientError::InvalidAccountData(*address));
+    }
+
+    match ExecutionReceipt::unpack(&account.data) {
+        Ok(receipt) if receipt.agent == *agent && receipt.execution == execution => Ok(receipt),
+        _ => Err(ClientError::InvalidAccountData(*address)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::program::state::ExecutionStatus;
+    use crate::test_utils::{agent_account, encode_agent};
+
+    #[test]
+    fn test_agent_filter() {
+        let authority = Pubkey::new_unique();
+        let filter = AgentFilter {
+            authority: Some(authority),
+            state: Some(AgentState::Running),
+            include_legacy: false,
+        };
+        let mut agent = AgentAccount { authority, ..agent_account("agent") };
+        assert!(!filter.matches(&agent));
+        agent.state = AgentState::Running;
+        assert!(filter.matches(&agent));
+        assert!(AgentFilter::default().matches(&agent));
+
+        let filters = filter.rpc_filters();
+        let matches = |data: &[u8]| {
+            filters.iter().all(|filter| match filter {
+                RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+                RpcFilterType::DataSize(size) => data.len() as u64 == *size,
+                _ => false,
+            })
+        };
+
+        assert_eq!(filters.len(), if cfg!(feature = "zero-copy") { 4 } else { 2 });
+        assert!(matches(&encode_agent(&agent)));
+        // Other accounts of the program are filtered out by the node
+        assert!(!matches(&borsh::to_vec(&ProgramConfig::default()).unwrap()));
+        agent.authority = Pubkey::new_unique();
+        assert!(!matches(&encode_agent(&agent)));
+
+        let legacy = AgentFilter { include_legacy: true, ..filter }.rpc_filters();
+        assert_eq!(legacy.len(), if cfg!(feature = "zero-copy") { 4 } else { 1 });
+    }
+
+    #[test]
+    fn test_decode_agents() {
+        let program_id = Pubkey::new_unique();
+        let addresses = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let account = Account {
+            lamports: 1,
+            data: encode_agent(&agent_account("agent")),
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+
+        let agents = decode_agents(&program_id, &addresses, vec![Some(account.clone()), None, Some(account.clone())])
+            .unwrap();
+        assert_eq!(agents.iter().map(|(address, _)| *address).collect::<Vec<_>>(), vec![addresses[0], addresses[2]]);
+        assert_eq!(agents[0].1.name, "agent");
+
+        let foreign = Account { owner: Pubkey::new_unique(), ..account };
+        assert!(matches!(
+            decode_agents(&program_id, &addresses[..1], vec![Some(foreign)]),
+            Err(ClientError::InvalidAccountData(address)) if address == addresses[0]
+        ));
+    }
+
+    #[test]
+    fn test_matching_agents() {
+        let addresses = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let account = |data: Vec<u8>| Account {
+            lamports: 1,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        let running = AgentAccount { state: AgentState::Running, ..agent_account("running") };
+        let accounts = vec![
+            Some(account(encode_agent(&running))),
+            Some(account(encode_agent(&agent_account("idle")))),
+            None,
+            Some(account(borsh::to_vec(&ProgramConfig::default()).unwrap())),
+        ];
+
+        // Agents not matching, closed since being listed or not agents at all are skipped
+        let filter = AgentFilter { state: Some(AgentState::Running), ..Default::default() };
+        let agents = matching_agents(&filter, &addresses, accounts);
+        assert_eq!(agents, vec![(addresses[0], running)]);
+    }
+
+    #[test]
+    fn test_read_options() {
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        let config = ReadOptions::default().account_config(&rpc);
+        assert_eq!((config.commitment, config.min_context_slot), (Some(rpc.commitment()), None));
+
+        let config = ReadOptions::at_slot(42).with_commitment(CommitmentConfig::processed()).account_config(&rpc);
+        assert_eq!((config.commitment, config.min_context_slot), (Some(CommitmentConfig::processed()), Some(42)));
+        assert_eq!(config.encoding, Some(UiAccountEncoding::Base64));
+    }
+
+    #[test]
+    fn test_websocket_url() {
+        assert_eq!(websocket_url("https://api.devnet.solana.com"), "wss://api.devnet.solana.com");
+        assert_eq!(websocket_url("http://localhost:8899"), "ws://localhost:8900");
+        assert_eq!(websocket_url("wss://rpc.example.com/ws"), "wss://rpc.example.com/ws");
+    }
+
+    #[test]
+    fn test_parse_receipt() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (address, bump) = pda::find_receipt_address(&program_id, &agent, 3);
+        let receipt = ExecutionReceipt {
+            agent,
+            execution: 3,
+            signer: Pubkey::new_unique(),
+            slot: 10,
+            timestamp: 1_700_000_000,
+            action_hash: [0; 32],
+            status: ExecutionStatus::Succeeded,
+            bump,
+            memo_hash: None,
+        };
+        let mut data = borsh::to_vec(&receipt).unwrap();
+        data.resize(ExecutionReceipt::LEN, 0);
+        let mut account = Account {
+            lamports: 1,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+
+        assert_eq!(parse_receipt(&program_id, &agent, 3, &address, &account).unwrap(), receipt);
+        assert!(matches!(
+            parse_receipt(&program_id, &agent, 4, &address, &account),
+            Err(ClientError::InvalidAccountData(_))
+        ));
+
+        account.owner = Pubkey::new_unique();
+        assert!(parse_receipt(&program_id, &agent, 3, &address, &account).is_err());
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (address, bump) = pda::find_result_address(&program_id, &agent);
+        let mut results = ResultAccount::new(agent, bump);
+        results.push(ExecutionResult {
+            execution: 1,
+            status: ExecutionStatus::Succeeded,
+            output_hash: [7; 32],
+            timestamp: 1_700_000_000,
+        });
+        let mut data = borsh::to_vec(&results).unwrap();
+        data.resize(ResultAccount::LEN, 0);
+        let account = Account {
+            lamports: 1,
+            data,
+            owner: program_id,
+            executable: false,
+            rent_epoch: 0,
+        };
+
+        assert_eq!(parse_results(&program_id, &agent, &address, &account).unwrap(), results);
+        assert!(matches!(
+            parse_results(&program_id, &Pubkey::new_unique(), &address, &account),
+            Err(ClientError::InvalidAccountData(_))
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+//! Agent events read back from transaction logs
+//!
+//! This module provides:
+//! - Decoding the events a transaction's agent program invocations logged
+//! - Event history of one agent or the whole program, from recent
+//!   transactions
+//! - Live events of one agent or the whole program, from a PubSub logs
+//!   subscription
+//!
+//! Only data logged by the agent program itself is decoded, so another
+//! program can't pass off its own logs as agent events. Logs the runtime
+//! truncated lose the events past the cut.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_client::{
+    pubsub_client::PubsubClient,
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::UiTransactionEncoding;
+use tokio::sync::broadcast;
+use crate::error::SonomaResult;
+use crate::solana::client;
+use crate::solana::program::event::AgentEvent;
+use crate::solana::simulation::parse_invocations;
+
+/// Number of events a lagging subscriber may fall behind by
+pub const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// An event and the transaction that emitted it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRecord {
+    pub signature: Signature,
+    pub slot: u64,
+    pub event: AgentEvent,
+}
+
+/// Decode the events logged by `program_id` in a transaction's logs, in
+/// the order they were emitted
+pub fn parse_events<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<AgentEvent> {
+    parse_invocations(logs)
+        .iter()
+        .filter(|invocation| invocation.program_id == *program_id)
+        .flat_map(|invocation| &invocation.data)
+        .filter_map(|data| AgentEvent::decode(&STANDARD.decode(data.trim()).ok()?))
+        .collect()
+}
+
+/// Whether `event` concerns `agent`; every event does if `None`
+fn is_about(event: &AgentEvent, agent: Option<&Pubkey>) -> bool {
+    agent.map_or(true, |agent| event.agent() == agent)
+}
+
+/// Fetch the events of `agent`, or of the whole program if `None`, from
+/// its last `limit` transactions, oldest first
+///
+/// Pass the oldest signature returned so far as `before` to page further
+/// back. Failed transactions are skipped, as their events never happened.
+pub fn get_events(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: Option<&Pubkey>,
+    before: Option<Signature>,
+    limit: usize,
+) -> SonomaResult<Vec<EventRecord>> {
+    let address = agent.unwrap_or(program_id);
+    let config = GetConfirmedSignaturesForAddress2Config {
+        before,
+        until: None,
+        limit: Some(limit),
+        commitment: Some(rpc.commitment()),
+    };
+    let statuses = rpc.get_signatures_for_address_with_config(address, config)?;
+
+    let transaction_config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        commitment: Some(rpc.commitment()),
+        max_supported_transaction_version: Some(0),
+    };
+    let mut records = Vec::new();
+    // Signatures come newest first
+    for status in statuses.iter().rev().filter(|status| status.err.is_none()) {
+        let Ok(signature) = status.signature.parse::<Signature>() else {
+            continue;
+        };
+        let transaction = rpc.get_transaction_with_config(&signature, transaction_config)?;
+        let logs: Option<Vec<String>> = transaction.transaction.meta.and_then(|meta| meta.log_messages.into());
+
+        records.extend(
+            parse_events(program_id, &logs.unwrap_or_default())
+                .into_iter()
+                .filter(|event| is_about(event, agent))
+                .map(|event| EventRecord {
+                    signature,
+                    slot: transaction.slot,
+                    event,
+                }),
+        );
+    }
+    Ok(records)
+}
+
+/// Broadcast the events of `agent`, or of the program if `None`, as their
+/// transactions land
+///
+/// Notifications are read on a background thread, which stops once every
+/// receiver is dropped.
+pub fn subscribe_events(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: Option<&Pubkey>,
+) -> SonomaResult<broadcast::Receiver<EventRecord>> {
+    let address = agent.unwrap_or(program_id);
+    let filter = RpcTransactionLogsFilter::Mentions(vec![address.to_string()]);
+    let config = RpcTransactionLogsConfig {
+        commitment: Some(rpc.commitment()),
+    };
+    let (mut subscription, notifications) =
+        PubsubClient::logs_subscribe(&client::websocket_url(&rpc.url()), filter, config)?;
+    let (sender, receiver) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let (program_id, agent) = (*program_id, agent.copied());
+
+    std::thread::spawn(move || {
+        'notifications: for notification in notifications {
+            let logs = notification.value;
+            if logs.err.is_some() {
+                continue;
+            }
+            let Ok(signature) = logs.signature.parse::<Signature>() else {
+                continue;
+            };
+
+            let events = parse_events(&program_id, &logs.logs);
+            for event in events.into_iter().filter(|event| is_about(event, agent.as_ref())) {
+                let record = EventRecord {
+                    signature,
+                    slot: notification.context.slot,
+                    event,
+                };
+                if sender.send(record).is_err() {
+                    break 'notifications;
+                }
+            }
+        }
+        let _ = subscription.shutdown();
+    });
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::program::{
+        event::{AgentPaused, AgentResumed, Event},
+        state::PauseReason,
+    };
+
+    fn data_log(event: &impl Event) -> String {
+        format!("Program data: {}", STANDARD.encode(event.data()))
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let (program_id, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let paused = AgentPaused {
+            agent: Pubkey::new_unique(),
+            signer: Pubkey::new_unique(),
+            reason: PauseReason::Emergency,
+            resume_at: None,
+        };
+        let resumed = AgentResumed {
+            agent: paused.agent,
+            signer: paused.signer,
+        };
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            "Program log: Instruction: Pause Agent".to_string(),
+            data_log(&paused),
+            format!("Program {} success", program_id),
+            // Another program logging look-alike events is ignored
+            format!("Program {} invoke [1]", other),
+            data_log(&resumed),
+            format!("Program {} success", other),
+            format!("Program {} invoke [1]", program_id),
+            data_log(&resumed),
+            format!("Program {} success", program_id),
+        ];
+
+        assert_eq!(
+            parse_events(&program_id, &logs),
+            vec![AgentEvent::Paused(paused), AgentEvent::Resumed(resumed.clone())]
+        );
+        assert!(parse_events(&Pubkey::new_unique(), &logs).is_empty());
+
+        let event = AgentEvent::Resumed(resumed.clone());
+        assert!(is_about(&event, Some(&resumed.agent)));
+        assert!(is_about(&event, None));
+        assert!(!is_about(&event, Some(&other)));
+    }
+}
@@ -0,0 +1,275 @@
+//! RPC endpoint failover for client connections
+//!
+//! This module provides:
+//! - An ordered list of RPC endpoints behind a single `RpcClient`
+//! - Failing over to the next endpoint on connection and 429 errors
+//! - Health checks taking endpoints out of rotation until they recover
+//! - Per-endpoint request and latency metrics
+//!
+//! Each request goes to the first healthy endpoint in the configured
+//! order, so traffic returns to the primary once it recovers. A request is
+//! only retried elsewhere when the endpoint refused it, never after a
+//! timeout, so a transaction can't be sent twice through two endpoints.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use solana_client::{
+    client_error::{ClientError as RpcError, ClientErrorKind, Result as RpcResult},
+    rpc_client::{RpcClient, RpcClientConfig},
+    rpc_request::RpcRequest,
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::commitment_config::CommitmentConfig;
+use crate::network::{MetricsRecorder, NetworkMetrics};
+
+/// How long a failed endpoint is skipped before it is tried again
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Health and metrics of one endpoint
+#[derive(Debug, Clone)]
+pub struct EndpointStatus {
+    pub url: String,
+    pub healthy: bool,
+    pub metrics: NetworkMetrics,
+}
+
+struct Endpoint {
+    url: String,
+    sender: HttpSender,
+    metrics: Mutex<MetricsRecorder>,
+    /// Skipped until then after failing
+    unhealthy_until: Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn new(url: String) -> Self {
+        Self {
+            sender: HttpSender::new(url.clone()),
+            url,
+            metrics: Mutex::new(MetricsRecorder::new()),
+            unhealthy_until: Mutex::new(None),
+        }
+    }
+
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.lock().unwrap().map_or(true, |until| now >= until)
+    }
+
+    fn record(&self, result: &RpcResult<serde_json::Value>, latency: Duration, cooldown: Duration) {
+        let mut metrics = self.metrics.lock().unwrap();
+        match result {
+            Ok(_) => {
+                metrics.record_response(latency);
+                *self.unhealthy_until.lock().unwrap() = None;
+            }
+            Err(error) => {
+                metrics.record_error();
+                if fails_over(error) {
+                    *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + cooldown);
+                }
+            }
+        }
+    }
+}
+
+/// Ordered RPC endpoints, the first one being the primary
+pub struct RpcEndpoints {
+    endpoints: Vec<Endpoint>,
+    commitment: CommitmentConfig,
+    cooldown: Duration,
+}
+
+impl RpcEndpoints {
+    pub fn new(primary: &str, commitment: CommitmentConfig) -> Self {
+        Self {
+            endpoints: vec![Endpoint::new(primary.to_string())],
+            commitment,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+
+    /// Fall back to `url` after the endpoints added so far
+    pub fn with_fallback(mut self, url: &str) -> Self {
+        self.endpoints.push(Endpoint::new(url.to_string()));
+        self
+    }
+
+    /// Skip failed endpoints for `cooldown` before trying them again
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+
+    /// Client sending every request through these endpoints
+    pub fn client(self: &Arc<Self>) -> RpcClient {
+        RpcClient::new_sender(FailoverSender(self.clone()), RpcClientConfig::with_commitment(self.commitment))
+    }
+
+    /// URL of the endpoint requests currently go to
+    pub fn current_url(&self) -> &str {
+        &self.endpoints[self.attempt_order(Instant::now())[0]].url
+    }
+
+    /// Check every endpoint with `getHealth`, taking those that fail out
+    /// of rotation and putting those that pass back in
+    pub async fn check_health(&self) -> Vec<EndpointStatus> {
+        for endpoint in &self.endpoints {
+            let start = Instant::now();
+            let result = endpoint.sender.send(RpcRequest::GetHealth, serde_json::Value::Null).await;
+            if result.is_err() {
+                *endpoint.unhealthy_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+            }
+            endpoint.record(&result, start.elapsed(), self.cooldown);
+        }
+        self.status()
+    }
+
+    /// Check the endpoints' health every `interval` on the current Tokio
+    /// runtime, until the returned task is aborted
+    pub fn spawn_health_checks(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let endpoints = self.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            loop {
+                ticks.tick().await;
+                endpoints.check_health().await;
+            }
+        })
+    }
+
+    /// Health and metrics of every endpoint, in the configured order
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|endpoint| EndpointStatus {
+                url: endpoint.url.clone(),
+                healthy: endpoint.is_healthy(now),
+                metrics: endpoint.metrics.lock().unwrap().snapshot(),
+            })
+            .collect()
+    }
+
+    /// Indexes of the endpoints to try: healthy ones in the configured
+    /// order, then unhealthy ones as a last resort
+    fn attempt_order(&self, now: Instant) -> Vec<usize> {
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) =
+            (0..self.endpoints.len()).partition(|&index| self.endpoints[index].is_healthy(now));
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    async fn send(&self, request: RpcRequest, params: serde_json::Value) -> RpcResult<serde_json::Value> {
+        let mut result = None;
+        for index in self.attempt_order(Instant::now()) {
+            let endpoint = &self.endpoints[index];
+            let start = Instant::now();
+            let attempt = endpoint.sender.send(request, params.clone()).await;
+            endpoint.record(&attempt, start.elapsed(), self.cooldown);
+
+            match attempt {
+                Err(error) if fails_over(&error) => result = Some(Err(error)),
+                attempt => return attempt,
+            }
+        }
+        result.expect("at least one endpoint")
+    }
+}
+
+/// Whether `error` means the endpoint refused the request, which is then
+/// safe to retry on another endpoint
+pub fn fails_over(error: &RpcError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(error) => {
+            error.is_connect() || error.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS)
+        }
+        _ => false,
+    }
+}
+
+struct FailoverSender(Arc<RpcEndpoints>);
+
+#[async_trait]
+impl RpcSender for FailoverSender {
+    async fn send(&self, request: RpcRequest, params: serde_json::Value) -> RpcResult<serde_json::Value> {
+        self.0.send(request, params).await
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.0.endpoints.iter().fold(RpcTransportStats::default(), |mut total, endpoint| {
+            let stats = endpoint.sender.get_transport_stats();
+            total.request_count += stats.request_count;
+            total.elapsed_time += stats.elapsed_time;
+            total.rate_limited_time += stats.rate_limited_time;
+            total
+        })
+    }
+
+    fn url(&self) -> String {
+        self.0.current_url().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    fn endpoints() -> RpcEndpoints {
+        RpcEndpoints::new("http://primary:8899", CommitmentConfig::confirmed())
+            .with_fallback("http://secondary:8899")
+            .with_fallback("http://tertiary:8899")
+    }
+
+    #[test]
+    fn test_attempt_order() {
+        let endpoints = endpoints();
+        let now = Instant::now();
+        assert_eq!(endpoints.attempt_order(now), vec![0, 1, 2]);
+        assert_eq!(endpoints.current_url(), "http://primary:8899");
+
+        let refused = Err(RpcError::from(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        endpoints.endpoints[0].record(&refused, Duration::ZERO, DEFAULT_COOLDOWN);
+        assert_eq!(endpoints.attempt_order(now), vec![1, 2, 0]);
+        assert_eq!(endpoints.current_url(), "http://secondary:8899");
+
+        // The primary is preferred again once its cooldown ran out
+        assert_eq!(endpoints.attempt_order(now + DEFAULT_COOLDOWN * 2), vec![0, 1, 2]);
+
+        let status = endpoints.status();
+        assert_eq!(status.iter().map(|status| status.healthy).collect::<Vec<_>>(), vec![false, true, true]);
+        assert_eq!(status[0].metrics.total_errors, 1);
+    }
+
+    #[test]
+    fn test_recovery_and_metrics() {
+        let endpoints = endpoints();
+        let refused = Err(RpcError::from(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        endpoints.endpoints[0].record(&refused, Duration::ZERO, DEFAULT_COOLDOWN);
+        endpoints.endpoints[0].record(&Ok(serde_json::Value::Null), Duration::from_millis(40), DEFAULT_COOLDOWN);
+
+        let status = &endpoints.status()[0];
+        assert!(status.healthy);
+        assert_eq!((status.metrics.total_requests, status.metrics.total_responses), (2, 1));
+        assert_eq!(status.metrics.latency_ewma, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_fails_over() {
+        assert!(fails_over(&io::Error::from(io::ErrorKind::ConnectionRefused).into()));
+        assert!(!fails_over(&RpcError::from(ClientErrorKind::Custom("node is behind".to_string()))));
+
+        // Errors the endpoint answered with keep it in rotation
+        let endpoints = endpoints();
+        let answered = Err(RpcError::from(ClientErrorKind::Custom("node is behind".to_string())));
+        endpoints.endpoints[0].record(&answered, Duration::ZERO, DEFAULT_COOLDOWN);
+        assert!(endpoints.status()[0].healthy);
+    }
+}
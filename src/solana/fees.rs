@@ -0,0 +1,134 @@
+//! Priority fee estimation for client transactions
+//!
+//! This module provides:
+//! - Recent prioritization fees paid for the accounts a transaction writes
+//! - A percentile of those fees as the suggested compute unit price
+//!
+//! Fees are read with `getRecentPrioritizationFees`, which reports the
+//! lowest fee that landed a transaction locking the given accounts in each
+//! of the last slots. Slots where any fee landed report zero, so the
+//! suggestion drops back once congestion clears.
+
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+use crate::solana::client::ClientResult;
+
+/// Maximum number of accounts per `getRecentPrioritizationFees` request
+pub const MAX_FEE_ACCOUNTS: usize = 128;
+
+/// Percentile of recent fees suggested by default
+pub const DEFAULT_FEE_PERCENTILE: u8 = 75;
+
+/// Highest suggested price by default, in micro-lamports per compute unit
+pub const DEFAULT_MAX_UNIT_PRICE: u64 = 1_000_000;
+
+/// Suggests compute unit prices from recent prioritization fees
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriorityFeeOracle {
+    /// Percentile of recent fees to pay, from 0 to 100
+    pub percentile: u8,
+    /// Lowest price suggested, in micro-lamports per compute unit
+    pub min_unit_price: u64,
+    /// Highest price suggested, in micro-lamports per compute unit
+    pub max_unit_price: u64,
+}
+
+impl Default for PriorityFeeOracle {
+    fn default() -> Self {
+        Self {
+            percentile: DEFAULT_FEE_PERCENTILE,
+            min_unit_price: 0,
+            max_unit_price: DEFAULT_MAX_UNIT_PRICE,
+        }
+    }
+}
+
+impl PriorityFeeOracle {
+    /// Suggested compute unit price for a transaction writing `accounts`
+    pub fn estimate(&self, rpc: &RpcClient, accounts: &[Pubkey]) -> ClientResult<u64> {
+        let accounts = &accounts[..accounts.len().min(MAX_FEE_ACCOUNTS)];
+        let fees: Vec<u64> = rpc
+            .get_recent_prioritization_fees(accounts)?
+            .into_iter()
+            .map(|fee| fee.prioritization_fee)
+            .collect();
+        Ok(self.suggest(&fees))
+    }
+
+    /// Suggested compute unit price for a transaction of `instructions`
+    pub fn estimate_for(&self, rpc: &RpcClient, instructions: &[Instruction]) -> ClientResult<u64> {
+        self.estimate(rpc, &writable_accounts(instructions))
+    }
+
+    /// The configured percentile of `fees`, within the price bounds
+    pub fn suggest(&self, fees: &[u64]) -> u64 {
+        percentile(fees, self.percentile).clamp(self.min_unit_price, self.max_unit_price.max(self.min_unit_price))
+    }
+}
+
+/// Nearest-rank `percentile` of `fees`, 0 if there are none
+pub fn percentile(fees: &[u64], percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    let mut sorted = fees.to_vec();
+    sorted.sort_unstable();
+
+    let rank = (usize::from(percentile.min(100)) * sorted.len() + 99) / 100;
+    sorted[rank.saturating_sub(1)]
+}
+
+/// Accounts `instructions` write, whose locks the priority fee competes for
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = Vec::new();
+    for meta in instructions.iter().flat_map(|instruction| &instruction.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+
+    #[test]
+    fn test_percentile() {
+        let fees = [0, 0, 10, 500, 20, 0, 1_000, 30];
+        assert_eq!(percentile(&fees, 0), 0);
+        assert_eq!(percentile(&fees, 50), 10);
+        assert_eq!(percentile(&fees, 75), 30);
+        assert_eq!(percentile(&fees, 100), 1_000);
+        assert_eq!(percentile(&[], 75), 0);
+    }
+
+    #[test]
+    fn test_suggest_bounds() {
+        let oracle = PriorityFeeOracle {
+            min_unit_price: 100,
+            max_unit_price: 400,
+            ..Default::default()
+        };
+        assert_eq!(oracle.suggest(&[]), 100);
+        assert_eq!(oracle.suggest(&[200, 250, 300, 350]), 300);
+        assert_eq!(oracle.suggest(&[5_000; 4]), 400);
+    }
+
+    #[test]
+    fn test_writable_accounts() {
+        let (agent, authority, program) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let instruction = Instruction::new_with_bytes(
+            program,
+            &[],
+            vec![
+                AccountMeta::new(agent, false),
+                AccountMeta::new(authority, true),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ],
+        );
+        assert_eq!(writable_accounts(&[instruction.clone(), instruction]), vec![agent, authority]);
+    }
+}
@@ -0,0 +1,239 @@
+//! Execution history of an agent, read back from its transactions
+//!
+//! This module provides:
+//! - Execution records of an agent from its recent transactions: when, by
+//!   whom, which action, and whether it went through
+//! - The executions a failed transaction attempted, decoded from its
+//!   instructions
+//!
+//! Executions that went through are read from the `AgentExecuted` events
+//! the program logged, including those it ran through CPI. A failed
+//! transaction logs no events, so only the execute instructions it sent to
+//! the agent program directly are reported for it.
+
+use solana_client::{
+    rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient},
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    hash::hash,
+    instruction::CompiledInstruction,
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::TransactionError,
+};
+use solana_transaction_status::{UiLoadedAddresses, UiTransactionEncoding};
+use crate::error::{self, SonomaResult};
+use crate::solana::events::parse_events;
+use crate::solana::program::{error::AgentError, event::AgentEvent, instruction::AgentInstruction};
+
+/// How an execution attempt ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionOutcome {
+    /// Ran as the agent's `execution`th execution
+    Succeeded { execution: u64 },
+    /// The transaction failed with `error`, leaving the agent untouched
+    Failed { error: TransactionError },
+}
+
+impl ExecutionOutcome {
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Succeeded { .. })
+    }
+
+    /// Agent program error the execution failed with, if any
+    pub fn agent_error(&self) -> Option<AgentError> {
+        match self {
+            Self::Failed { error } => error::agent_error(error),
+            Self::Succeeded { .. } => None,
+        }
+    }
+}
+
+/// One execution attempt of an agent and the transaction that made it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionRecord {
+    pub signature: Signature,
+    pub slot: u64,
+    /// Unix time of the execution, or of its block for failed ones if the
+    /// node knows it
+    pub timestamp: Option<i64>,
+    /// Authority or executor that signed the execution
+    pub caller: Pubkey,
+    /// SHA-256 of the action data
+    pub action_hash: [u8; 32],
+    pub status: ExecutionOutcome,
+}
+
+/// Caller and action hash of each execute instruction a transaction sent
+/// `program_id` for `agent`, in order
+///
+/// `account_keys` are the transaction's accounts as its instructions index
+/// them, including those loaded from lookup tables.
+pub fn attempted_executions(
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    account_keys: &[Pubkey],
+    instructions: &[CompiledInstruction],
+) -> Vec<(Pubkey, [u8; 32])> {
+    let key = |instruction: &CompiledInstruction, position: usize| {
+        let index = *instruction.accounts.get(position)?;
+        account_keys.get(usize::from(index)).copied()
+    };
+
+    instructions
+        .iter()
+        .filter(|instruction| account_keys.get(usize::from(instruction.program_id_index)) == Some(program_id))
+        .filter(|instruction| key(instruction, 0).as_ref() == Some(agent))
+        .filter_map(|instruction| {
+            let action_hash = match AgentInstruction::unpack(&instruction.data).ok()? {
+                AgentInstruction::Execute { action, .. }
+                | AgentInstruction::ExecuteAfter { action, .. }
+                | AgentInstruction::ExecuteConditional { action, .. }
+                | AgentInstruction::CrankExecute { action } => hash(&borsh::to_vec(&action).ok()?).to_bytes(),
+                AgentInstruction::FinalizeExecute { hash, .. } => hash,
+                _ => return None,
+            };
+            Some((key(instruction, 1)?, action_hash))
+        })
+        .collect()
+}
+
+/// Fetch the execution attempts of `agent` from its last `limit`
+/// transactions, oldest first
+///
+/// Pass the oldest signature returned so far as `before` to page further
+/// back.
+pub fn get_execution_history(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    before: Option<Signature>,
+    limit: usize,
+) -> SonomaResult<Vec<ExecutionRecord>> {
+    let config = GetConfirmedSignaturesForAddress2Config {
+        before,
+        until: None,
+        limit: Some(limit),
+        commitment: Some(rpc.commitment()),
+    };
+    let statuses = rpc.get_signatures_for_address_with_config(agent, config)?;
+
+    // Binary encoding, to decode the instructions of failed transactions
+    let transaction_config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(rpc.commitment()),
+        max_supported_transaction_version: Some(0),
+    };
+    let mut records = Vec::new();
+    // Signatures come newest first
+    for status in statuses.iter().rev() {
+        let Ok(signature) = status.signature.parse::<Signature>() else {
+            continue;
+        };
+        let confirmed = rpc.get_transaction_with_config(&signature, transaction_config)?;
+        let Some(meta) = confirmed.transaction.meta else {
+            continue;
+        };
+
+        if let Some(error) = meta.err {
+            let Some(transaction) = confirmed.transaction.transaction.decode() else {
+                continue;
+            };
+            let mut account_keys = transaction.message.static_account_keys().to_vec();
+            let loaded: Option<UiLoadedAddresses> = meta.loaded_addresses.into();
+            if let Some(loaded) = loaded {
+                account_keys.extend(
+                    loaded
+                        .writable
+                        .iter()
+                        .chain(&loaded.readonly)
+                        .filter_map(|address| address.parse::<Pubkey>().ok()),
+                );
+            }
+
+            let attempts =
+                attempted_executions(program_id, agent, &account_keys, transaction.message.instructions());
+            records.extend(attempts.into_iter().map(|(caller, action_hash)| ExecutionRecord {
+                signature,
+                slot: confirmed.slot,
+                timestamp: confirmed.block_time,
+                caller,
+                action_hash,
+                status: ExecutionOutcome::Failed { error: error.clone() },
+            }));
+            continue;
+        }
+
+        let logs: Option<Vec<String>> = meta.log_messages.into();
+        records.extend(
+            parse_events(program_id, &logs.unwrap_or_default())
+                .into_iter()
+                .filter_map(|event| match event {
+                    AgentEvent::Executed(executed) if executed.agent == *agent => Some(executed),
+                    _ => None,
+                })
+                .map(|executed| ExecutionRecord {
+                    signature,
+                    slot: confirmed.slot,
+                    timestamp: Some(executed.timestamp),
+                    caller: executed.signer,
+                    action_hash: executed.action_hash,
+                    status: ExecutionOutcome::Succeeded {
+                        execution: executed.execution_count,
+                    },
+                }),
+        );
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{instruction::InstructionError, message::Message};
+    use crate::solana::program::{action::AgentAction, pda};
+
+    #[test]
+    fn test_attempted_executions() {
+        let (program_id, caller, other_agent) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let agent = Pubkey::new_unique();
+        let (result, _) = pda::find_result_address(&program_id, &agent);
+        let action = AgentAction::Memo {
+            text: "rebalance".to_string(),
+        };
+        let staged_hash = [7; 32];
+
+        let instructions = vec![
+            AgentInstruction::execute(&program_id, &agent, &caller, &result, 1, action.clone()),
+            // Other agents and other instructions are left out
+            AgentInstruction::execute(&program_id, &other_agent, &caller, &result, 1, action.clone()),
+            AgentInstruction::resume(&program_id, &agent, &caller),
+            AgentInstruction::finalize_execute(
+                &program_id,
+                &agent,
+                &caller,
+                &result,
+                2,
+                64,
+                staged_hash,
+            ),
+        ];
+        let message = Message::new(&instructions, Some(&caller));
+
+        let action_hash = hash(&borsh::to_vec(&action).unwrap()).to_bytes();
+        assert_eq!(
+            attempted_executions(&program_id, &agent, &message.account_keys, &message.instructions),
+            vec![(caller, action_hash), (caller, staged_hash)]
+        );
+        assert!(attempted_executions(&Pubkey::new_unique(), &agent, &message.account_keys, &message.instructions)
+            .is_empty());
+
+        let error = InstructionError::Custom(AgentError::InvalidAgentState as u32);
+        let failed = ExecutionOutcome::Failed {
+            error: TransactionError::InstructionError(0, error),
+        };
+        assert_eq!(failed.agent_error(), Some(AgentError::InvalidAgentState));
+        assert!(!failed.is_success());
+    }
+}
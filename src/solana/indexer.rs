@@ -0,0 +1,446 @@
+//! Off-chain index of the program's agents and events
+//!
+//! This module provides:
+//! - `AgentIndex`, the agents of the program and the events they emitted,
+//!   persisted through a `StorageManager`
+//! - Queries of agents by authority and state, and of an agent's events
+//!   and executions, paginated
+//! - `Indexer`, backfilling the index from a listing of the program's
+//!   agents and its recent transactions, then keeping it current from an
+//!   account stream and a logs subscription
+//!
+//! Dashboards read the index instead of scanning the program's accounts
+//! over RPC. Agent updates older than the one indexed, by slot, are
+//! ignored, so a backfill running alongside the live updates never rolls
+//! an agent back. Events are keyed by slot, transaction and position in
+//! it, so indexing an event twice stores it once; agents and transactions
+//! are abbreviated to 8 bytes in event keys. A subscriber that falls
+//! behind drops updates; run a backfill again to pick them up.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use tokio::sync::broadcast::error::RecvError;
+use crate::error::SonomaResult;
+use crate::storage::{Page, PageRequest, StorageError, StorageManager, MAX_PAGE_SIZE};
+use crate::solana::client::{self, AgentFilter, ClientError, ReadOptions};
+use crate::solana::events::{self, EventRecord};
+use crate::solana::program::{
+    event::AgentEvent,
+    state::{AgentAccount, AgentState},
+};
+use crate::solana::stream::{AccountStream, AccountUpdate};
+
+/// Prefix of the storage keys of an index, followed by the program id
+pub const INDEX_KEY_PREFIX: &str = "indexer/";
+
+/// Agent as last indexed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedAgent {
+    pub address: Pubkey,
+    pub name: String,
+    pub authority: Pubkey,
+    pub creator: Pubkey,
+    /// State, e.g. `Running`
+    pub state: String,
+    pub execution_count: u64,
+    /// Unix time of the last execution
+    pub last_execution: i64,
+    /// Slot the account was read at
+    pub slot: u64,
+    /// Whether the agent emitted `AgentClosed`
+    pub closed: bool,
+    /// Unix time the agent was last indexed
+    pub indexed_at: u64,
+}
+
+impl IndexedAgent {
+    pub fn new(address: Pubkey, agent: &AgentAccount, slot: u64) -> Self {
+        Self {
+            address,
+            name: agent.name.clone(),
+            authority: agent.authority,
+            creator: agent.creator,
+            state: format!("{:?}", agent.state),
+            execution_count: agent.execution_count,
+            last_execution: agent.last_execution,
+            slot,
+            closed: false,
+            indexed_at: now(),
+        }
+    }
+}
+
+/// Event as indexed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedEvent {
+    pub agent: Pubkey,
+    pub signature: String,
+    pub slot: u64,
+    /// Position among the program's events in the transaction
+    pub index: u32,
+    /// Event name, e.g. `AgentExecuted`
+    pub name: String,
+    /// Discriminator and Borsh-encoded event, as logged
+    pub data: Vec<u8>,
+}
+
+impl IndexedEvent {
+    pub fn new(record: &EventRecord, index: u32) -> Self {
+        Self {
+            agent: *record.event.agent(),
+            signature: record.signature.to_string(),
+            slot: record.slot,
+            index,
+            name: record.event.name().to_string(),
+            data: record.event.data(),
+        }
+    }
+
+    /// The event decoded
+    pub fn event(&self) -> Option<AgentEvent> {
+        AgentEvent::decode(&self.data)
+    }
+}
+
+/// Agents to list; every indexed agent still open matches the default
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AgentQuery {
+    pub authority: Option<Pubkey>,
+    pub state: Option<AgentState>,
+    pub include_closed: bool,
+}
+
+impl AgentQuery {
+    pub fn matches(&self, agent: &IndexedAgent) -> bool {
+        self.authority.map_or(true, |authority| agent.authority == authority)
+            && self.state.as_ref().map_or(true, |state| agent.state == format!("{:?}", state))
+            && (self.include_closed || !agent.closed)
+    }
+}
+
+/// Indexed agents and events of one program
+#[derive(Clone)]
+pub struct AgentIndex {
+    storage: Arc<StorageManager>,
+    prefix: String,
+}
+
+impl AgentIndex {
+    pub fn new(storage: Arc<StorageManager>, program_id: &Pubkey) -> Self {
+        Self {
+            storage,
+            prefix: format!("{}{}/", INDEX_KEY_PREFIX, program_id),
+        }
+    }
+
+    pub async fn agent(&self, address: &Pubkey) -> SonomaResult<Option<IndexedAgent>> {
+        match self.storage.retrieve(&self.agent_key(address)).await {
+            Ok(agent) => Ok(Some(agent)),
+            Err(StorageError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Index `agent` unless a later version of it is indexed, returning
+    /// whether it was
+    pub async fn put_agent(&self, mut agent: IndexedAgent) -> SonomaResult<bool> {
+        if let Some(indexed) = self.agent(&agent.address).await? {
+            if indexed.slot > agent.slot {
+                return Ok(false);
+            }
+            agent.closed |= indexed.closed;
+        }
+        self.storage.store(&self.agent_key(&agent.address), &agent).await?;
+        Ok(true)
+    }
+
+    /// Index `event`, marking its agent closed if it closed it
+    pub async fn put_event(&self, event: &IndexedEvent) -> SonomaResult<()> {
+        let key = format!(
+            "{}{:020}/{}/{:04}",
+            self.events_prefix(&event.agent),
+            event.slot,
+            key_tag(event.signature.as_ref()),
+            event.index
+        );
+        self.storage.store(&key, event).await?;
+
+        if event.name == "AgentClosed" {
+            if let Some(mut agent) = self.agent(&event.agent).await? {
+                agent.closed = true;
+                self.storage.store(&self.agent_key(&agent.address), &agent).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Page of the indexed agents matching `query`, by address
+    ///
+    /// Agents are filtered a page at a time, so a page may hold fewer than
+    /// its limit even though more follow.
+    pub async fn agents(&self, query: &AgentQuery, page: &PageRequest) -> SonomaResult<Page<IndexedAgent>> {
+        let scanned = self.storage.scan::<IndexedAgent>(&format!("{}agents/", self.prefix), page).await?;
+        Ok(Page {
+            items: scanned.items.into_iter().map(|(_, agent)| agent).filter(|agent| query.matches(agent)).collect(),
+            next_cursor: scanned.next_cursor,
+        })
+    }
+
+    /// Page of the events of `agent`, oldest first
+    pub async fn events(&self, agent: &Pubkey, page: &PageRequest) -> SonomaResult<Page<IndexedEvent>> {
+        let scanned = self.storage.scan::<IndexedEvent>(&self.events_prefix(agent), page).await?;
+        Ok(Page {
+            items: scanned.items.into_iter().map(|(_, event)| event).collect(),
+            next_cursor: scanned.next_cursor,
+        })
+    }
+
+    /// Every execution of `agent` indexed, oldest first
+    pub async fn executions(&self, agent: &Pubkey) -> SonomaResult<Vec<IndexedEvent>> {
+        let mut executions = Vec::new();
+        let mut page = PageRequest::first(MAX_PAGE_SIZE);
+        loop {
+            let events = self.events(agent, &page).await?;
+            executions.extend(events.items.into_iter().filter(|event| event.name == "AgentExecuted"));
+            match events.next_cursor {
+                Some(cursor) => page = PageRequest::after(cursor, MAX_PAGE_SIZE),
+                None => return Ok(executions),
+            }
+        }
+    }
+
+    fn agent_key(&self, address: &Pubkey) -> String {
+        format!("{}agents/{}", self.prefix, address)
+    }
+
+    fn events_prefix(&self, agent: &Pubkey) -> String {
+        format!("{}events/{}/", self.prefix, key_tag(agent.as_ref()))
+    }
+}
+
+/// First 8 bytes of an address or signature in hex, short enough for event
+/// keys to stay within the database's key length limit
+fn key_tag(bytes: &[u8]) -> String {
+    bytes[..8].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Outcome of a backfill
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackfillStats {
+    pub agents: usize,
+    pub events: usize,
+}
+
+/// Follows the program, keeping its index current
+pub struct Indexer {
+    rpc: Arc<RpcClient>,
+    program_id: Pubkey,
+    index: AgentIndex,
+}
+
+impl Indexer {
+    pub fn new(rpc: Arc<RpcClient>, program_id: &Pubkey, storage: Arc<StorageManager>) -> Self {
+        Self {
+            rpc,
+            program_id: *program_id,
+            index: AgentIndex::new(storage, program_id),
+        }
+    }
+
+    pub fn index(&self) -> &AgentIndex {
+        &self.index
+    }
+
+    /// Index every agent of the program, and the events of its last
+    /// `transactions` transactions
+    pub async fn backfill(&self, transactions: usize) -> SonomaResult<BackfillStats> {
+        let (rpc, program_id) = (self.rpc.clone(), self.program_id);
+        let (slot, agents, records) = tokio::task::spawn_blocking(move || -> SonomaResult<_> {
+            let slot = rpc.get_slot()?;
+            let options = ReadOptions::at_slot(slot);
+            let agents = client::list_agents_with_options(&rpc, &program_id, &AgentFilter::default(), &options)?;
+            let records = events::get_events(&rpc, &program_id, None, None, transactions)?;
+            Ok((slot, agents, records))
+        })
+        .await
+        .map_err(|e| ClientError::Stream(e.to_string()))??;
+
+        let mut stats = BackfillStats::default();
+        for (address, agent) in &agents {
+            if self.index.put_agent(IndexedAgent::new(*address, agent, slot)).await? {
+                stats.agents += 1;
+            }
+        }
+        let mut positions = EventPositions::default();
+        for record in &records {
+            self.index.put_event(&IndexedEvent::new(record, positions.next(&record.signature))).await?;
+            stats.events += 1;
+        }
+        println!("Indexed {} agents and {} events of program {}", stats.agents, stats.events, self.program_id);
+        Ok(stats)
+    }
+
+    /// Index agent updates from `stream` and events from a logs
+    /// subscription until either closes
+    pub async fn run(&self, stream: &dyn AccountStream) -> SonomaResult<()> {
+        let mut updates = stream.subscribe_program(&self.program_id, AgentFilter::default().rpc_filters())?;
+        let mut events = events::subscribe_events(&self.rpc, &self.program_id, None)?;
+        let mut positions = EventPositions::default();
+        println!("Following program {}", self.program_id);
+
+        loop {
+            tokio::select! {
+                update = updates.recv() => match received(update, "agent updates")? {
+                    Some(update) => self.index_update(update).await?,
+                    None => continue,
+                },
+                record = events.recv() => match received(record, "events")? {
+                    Some(record) => {
+                        let event = IndexedEvent::new(&record, positions.next(&record.signature));
+                        self.index.put_event(&event).await?;
+                    }
+                    None => continue,
+                },
+            }
+        }
+    }
+
+    async fn index_update(&self, update: AccountUpdate) -> SonomaResult<()> {
+        // Closed accounts and the program's other accounts don't decode
+        if let Ok(agent) = AgentAccount::unpack(&update.account.data) {
+            self.index.put_agent(IndexedAgent::new(update.address, &agent, update.slot)).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Item received from a subscription, `None` if the receiver lagged;
+/// fails once the subscription closed
+fn received<T>(item: Result<T, RecvError>, subscription: &str) -> SonomaResult<Option<T>> {
+    match item {
+        Ok(item) => Ok(Some(item)),
+        Err(RecvError::Lagged(skipped)) => {
+            println!("Indexer skipped {} {}; backfill to pick them up", skipped, subscription);
+            Ok(None)
+        }
+        Err(RecvError::Closed) => Err(ClientError::Stream(format!("subscription to {} closed", subscription)).into()),
+    }
+}
+
+/// Position of each event among its transaction's, for events received in
+/// log order
+#[derive(Default)]
+struct EventPositions {
+    last: Option<(Signature, u32)>,
+}
+
+impl EventPositions {
+    fn next(&mut self, signature: &Signature) -> u32 {
+        let index = match self.last {
+            Some((last, index)) if last == *signature => index + 1,
+            _ => 0,
+        };
+        self.last = Some((*signature, index));
+        index
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::program::event::{AgentClosed, AgentExecuted};
+    use crate::storage::StorageConfig;
+    use crate::test_utils::agent_account;
+
+    async fn index(dir: &tempfile::TempDir) -> AgentIndex {
+        let config = StorageConfig {
+            base_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        AgentIndex::new(Arc::new(StorageManager::new(config).await.unwrap()), &Pubkey::new_unique())
+    }
+
+    fn record(signature: Signature, slot: u64, event: AgentEvent) -> EventRecord {
+        EventRecord { signature, slot, event }
+    }
+
+    #[tokio::test]
+    async fn test_agents_keep_latest_slot() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = index(&dir).await;
+        let (address, mut account) = (Pubkey::new_unique(), agent_account("indexed"));
+
+        assert!(index.put_agent(IndexedAgent::new(address, &account, 10)).await.unwrap());
+        account.execution_count = 3;
+        assert!(!index.put_agent(IndexedAgent::new(address, &account, 9)).await.unwrap());
+        assert_eq!(index.agent(&address).await.unwrap().unwrap().execution_count, 0);
+        assert!(index.put_agent(IndexedAgent::new(address, &account, 11)).await.unwrap());
+        assert_eq!(index.agent(&address).await.unwrap().unwrap().execution_count, 3);
+
+        let other = agent_account("other");
+        index.put_agent(IndexedAgent::new(Pubkey::new_unique(), &other, 10)).await.unwrap();
+        let query = AgentQuery {
+            authority: Some(account.authority),
+            ..AgentQuery::default()
+        };
+        let page = index.agents(&query, &PageRequest::first(10)).await.unwrap();
+        assert_eq!(page.items.iter().map(|agent| agent.address).collect::<Vec<_>>(), vec![address]);
+    }
+
+    #[tokio::test]
+    async fn test_events_and_executions() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = index(&dir).await;
+        let (address, account) = (Pubkey::new_unique(), agent_account("indexed"));
+        index.put_agent(IndexedAgent::new(address, &account, 10)).await.unwrap();
+
+        let executed = |count| {
+            AgentEvent::Executed(AgentExecuted {
+                agent: address,
+                signer: account.authority,
+                execution_count: count,
+                timestamp: 1_700_000_000,
+                action_hash: [0; 32],
+            })
+        };
+        let closed = AgentEvent::Closed(AgentClosed {
+            agent: address,
+            authority: account.authority,
+            lamports: 1_000,
+        });
+        let (first, second) = (Signature::new_unique(), Signature::new_unique());
+        let records = [
+            record(first, 11, executed(1)),
+            record(first, 11, executed(2)),
+            record(second, 12, closed),
+        ];
+
+        let mut positions = EventPositions::default();
+        for record in &records {
+            let event = IndexedEvent::new(record, positions.next(&record.signature));
+            index.put_event(&event).await.unwrap();
+            // Indexing an event again stores it once
+            index.put_event(&event).await.unwrap();
+        }
+
+        let executions = index.executions(&address).await.unwrap();
+        assert_eq!(executions.iter().map(|event| event.index).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(executions[1].event(), Some(executed(2)));
+        assert_eq!(index.events(&address, &PageRequest::first(10)).await.unwrap().items.len(), 3);
+
+        assert!(index.agent(&address).await.unwrap().unwrap().closed);
+        assert!(index.agents(&AgentQuery::default(), &PageRequest::first(10)).await.unwrap().items.is_empty());
+        let all = AgentQuery {
+            include_closed: true,
+            ..AgentQuery::default()
+        };
+        assert_eq!(index.agents(&all, &PageRequest::first(10)).await.unwrap().items.len(), 1);
+    }
+}
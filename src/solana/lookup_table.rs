@@ -0,0 +1,124 @@
+//! Address lookup tables for agent transactions
+//!
+//! This module provides:
+//! - Creating a table holding the accounts an agent's instructions share
+//! - Extending a table in batches that fit a transaction
+//! - Fetching a table for use by `TransactionBuilder`
+//!
+//! A table's addresses can only be looked up from the slot after they were
+//! added, so send the instructions before the transactions using them.
+
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    address_lookup_table::{instruction, state::AddressLookupTable, AddressLookupTableAccount},
+    instruction::Instruction,
+    pubkey::Pubkey,
+    system_program,
+};
+use crate::solana::client::{self, ClientError, ClientResult, ReadOptions};
+use crate::solana::program::pda;
+
+/// Maximum number of addresses added by one extend instruction, keeping it
+/// within a transaction
+pub const MAX_EXTEND_ADDRESSES: usize = 30;
+
+/// Accounts shared by the instructions of `agent`: the program, the agent
+/// and its PDAs, and the programs its actions call
+pub fn agent_addresses(program_id: &Pubkey, agent: &Pubkey) -> Vec<Pubkey> {
+    vec![
+        *program_id,
+        *agent,
+        pda::find_result_address(program_id, agent).0,
+        pda::find_metadata_address(program_id, agent).0,
+        pda::find_stake_address(program_id, agent).0,
+        pda::find_vault_address(program_id, agent).0,
+        pda::find_config_address(program_id).0,
+        system_program::id(),
+        spl_memo::id(),
+    ]
+}
+
+/// Instructions adding `addresses` to `table`, one per batch of
+/// `MAX_EXTEND_ADDRESSES`; `payer` funds the table's growth
+pub fn extend_lookup_table(table: &Pubkey, authority: &Pubkey, payer: &Pubkey, addresses: &[Pubkey]) -> Vec<Instruction> {
+    addresses
+        .chunks(MAX_EXTEND_ADDRESSES)
+        .map(|batch| instruction::extend_lookup_table(*table, *authority, Some(*payer), batch.to_vec()))
+        .collect()
+}
+
+/// Instructions creating a table owned by `authority` and filling it with
+/// `addresses`, and the table's address. `recent_slot` must be a recent
+/// finalized slot; the first two instructions fit one transaction.
+pub fn create_lookup_table(
+    authority: &Pubkey,
+    payer: &Pubkey,
+    recent_slot: u64,
+    addresses: &[Pubkey],
+) -> (Pubkey, Vec<Instruction>) {
+    let (create, table) = instruction::create_lookup_table(*authority, *payer, recent_slot);
+    let mut instructions = vec![create];
+    instructions.extend(extend_lookup_table(&table, authority, payer, addresses));
+    (table, instructions)
+}
+
+/// `create_lookup_table` for the accounts of `agent`, at the current slot
+pub fn create_agent_lookup_table(
+    rpc: &RpcClient,
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    authority: &Pubkey,
+) -> ClientResult<(Pubkey, Vec<Instruction>)> {
+    let recent_slot = rpc.get_slot()?;
+    Ok(create_lookup_table(authority, authority, recent_slot, &agent_addresses(program_id, agent)))
+}
+
+/// Fetch a lookup table
+pub fn get_lookup_table(rpc: &RpcClient, address: &Pubkey) -> ClientResult<AddressLookupTableAccount> {
+    get_lookup_table_with_options(rpc, address, &ReadOptions::default())
+}
+
+/// `get_lookup_table`, reading with `options`
+pub fn get_lookup_table_with_options(
+    rpc: &RpcClient,
+    address: &Pubkey,
+    options: &ReadOptions,
+) -> ClientResult<AddressLookupTableAccount> {
+    let account = client::get_account(rpc, address, options)?.ok_or(ClientError::AccountNotFound(*address))?;
+    if account.owner != solana_sdk::address_lookup_table::program::id() {
+        return Err(ClientError::InvalidAccountData(*address));
+    }
+
+    let table = AddressLookupTable::deserialize(&account.data).map_err(|_| ClientError::InvalidAccountData(*address))?;
+    Ok(AddressLookupTableAccount {
+        key: *address,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_lookup_table() {
+        let (authority, payer) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let addresses: Vec<Pubkey> = (0..MAX_EXTEND_ADDRESSES + 5).map(|_| Pubkey::new_unique()).collect();
+
+        let (table, instructions) = create_lookup_table(&authority, &payer, 42, &addresses);
+        assert_eq!(table, instruction::derive_lookup_table_address(&authority, 42).0);
+        assert_eq!(instructions.len(), 3);
+        assert_eq!(
+            instructions[2],
+            instruction::extend_lookup_table(table, authority, Some(payer), addresses[MAX_EXTEND_ADDRESSES..].to_vec())
+        );
+    }
+
+    #[test]
+    fn test_agent_addresses() {
+        let (program_id, agent) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let addresses = agent_addresses(&program_id, &agent);
+        assert!(addresses.contains(&pda::find_result_address(&program_id, &agent).0));
+        assert!(addresses.contains(&spl_memo::id()));
+    }
+}
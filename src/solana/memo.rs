@@ -0,0 +1,68 @@
+//! Memos attributing transactions to agents
+//!
+//! This module provides:
+//! - `TransactionMemo`, naming the agent, strategy and a free-form tag a
+//!   transaction was sent for
+//! - Its text, a JSON object of the fields that are set
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+/// Memo attributing a transaction to an agent, its strategy and a tag
+///
+/// Written as a JSON object of the fields that are set, e.g.
+/// `{"agent":"<address>","strategy":"dca"}`, so explorers and accounting
+/// tools can group on-chain activity by agent and strategy.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransactionMemo {
+    pub agent: Option<Pubkey>,
+    pub strategy: Option<String>,
+    /// Free-form label, e.g. a run or account id
+    pub tag: Option<String>,
+}
+
+impl TransactionMemo {
+    pub fn with_strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = Some(strategy.into());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tag = Some(tag.into());
+        self
+    }
+}
+
+impl fmt::Display for TransactionMemo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields = serde_json::Map::new();
+        if let Some(agent) = &self.agent {
+            fields.insert("agent".to_string(), agent.to_string().into());
+        }
+        if let Some(strategy) = &self.strategy {
+            fields.insert("strategy".to_string(), strategy.as_str().into());
+        }
+        if let Some(tag) = &self.tag {
+            fields.insert("tag".to_string(), tag.as_str().into());
+        }
+        write!(f, "{}", serde_json::Value::Object(fields))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attribution_memo() {
+        let agent = Pubkey::new_unique();
+        let memo = TransactionMemo {
+            agent: Some(agent),
+            ..TransactionMemo::default()
+        }
+        .with_strategy("dca");
+        assert_eq!(memo.to_string(), format!(r#"{{"agent":"{}","strategy":"dca"}}"#, agent));
+        assert_eq!(TransactionMemo::default().with_tag("run \"7\"").to_string(), r#"{"tag":"run \"7\""}"#);
+    }
+}
@@ -0,0 +1,17 @@
+pub mod program;
+pub mod memo;
+pub mod indexer;
+pub mod airdrop;
+pub mod cache;
+pub mod client;
+pub mod events;
+pub mod failover;
+pub mod fees;
+pub mod history;
+pub mod lookup_table;
+pub mod offline;
+pub mod send;
+pub mod signer;
+pub mod simulation;
+pub mod stream;
+pub mod transaction;
@@ -0,0 +1,234 @@
+//! Offline signing of client transactions
+//!
+//! This module provides:
+//! - Unsigned transactions exported as base64 for signing elsewhere
+//! - The exact message bytes each signer signs
+//! - Importing signatures, checked against the message and its signers
+//! - Submitting the transaction once every signer has signed
+//!
+//! The message is compiled once, with the blockhash given when exporting,
+//! and never rebuilt, so every party signs the same bytes. Use a durable
+//! nonce as the blockhash when signing may take longer than a blockhash
+//! stays valid.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::Hash,
+    message::{v0, VersionedMessage},
+    pubkey::Pubkey,
+    signature::{Signature, Signer},
+    transaction::VersionedTransaction,
+};
+use thiserror::Error;
+use crate::error::SonomaResult;
+use crate::solana::transaction::{BuildError, TransactionBuilder};
+
+#[derive(Error, Debug)]
+pub enum OfflineError {
+    #[error("Failed to build transaction: {0}")]
+    Build(#[from] BuildError),
+
+    #[error("Failed to encode transaction: {0}")]
+    Encode(String),
+
+    #[error("Invalid encoded transaction: {0}")]
+    Decode(String),
+
+    #[error("{0} is not a signer of the transaction")]
+    UnknownSigner(Pubkey),
+
+    #[error("Signature of {0} does not match the message")]
+    InvalidSignature(Pubkey),
+
+    #[error("Missing signatures of {0:?}")]
+    MissingSignatures(Vec<Pubkey>),
+}
+
+/// Transaction compiled for offline signing, collecting signatures as they
+/// come back
+#[derive(Debug, Clone, PartialEq)]
+pub struct OfflineTransaction {
+    transaction: VersionedTransaction,
+}
+
+impl OfflineTransaction {
+    /// Compile `builder`'s transaction paid for by `payer`, without signing
+    /// it: a v0 message if lookup tables are configured, legacy otherwise
+    pub fn new(builder: &TransactionBuilder, payer: &Pubkey, blockhash: Hash) -> Result<Self, OfflineError> {
+        let message = if builder.lookup_tables().is_empty() {
+            let mut transaction = builder.build(payer);
+            transaction.message.recent_blockhash = blockhash;
+            VersionedMessage::Legacy(transaction.message)
+        } else {
+            let message = v0::Message::try_compile(payer, &builder.instructions(), builder.lookup_tables(), blockhash)
+                .map_err(BuildError::from)?;
+            VersionedMessage::V0(message)
+        };
+
+        let signatures = vec![Signature::default(); usize::from(message.header().num_required_signatures)];
+        Ok(Self {
+            transaction: VersionedTransaction { signatures, message },
+        })
+    }
+
+    /// Decode a transaction exported with `to_base64`, keeping the
+    /// signatures it already carries
+    pub fn from_base64(encoded: &str) -> Result<Self, OfflineError> {
+        let bytes = STANDARD.decode(encoded.trim()).map_err(|error| OfflineError::Decode(error.to_string()))?;
+        let transaction: VersionedTransaction =
+            bincode::deserialize(&bytes).map_err(|error| OfflineError::Decode(error.to_string()))?;
+        if transaction.signatures.len() != usize::from(transaction.message.header().num_required_signatures) {
+            return Err(OfflineError::Decode("signature count does not match the message".to_string()));
+        }
+        Ok(Self { transaction })
+    }
+
+    /// Wire encoding of the transaction and the signatures collected so
+    /// far, as base64
+    pub fn to_base64(&self) -> Result<String, OfflineError> {
+        let bytes = bincode::serialize(&self.transaction).map_err(|error| OfflineError::Encode(error.to_string()))?;
+        Ok(STANDARD.encode(bytes))
+    }
+
+    /// Bytes every signer signs
+    pub fn message_data(&self) -> Vec<u8> {
+        self.transaction.message.serialize()
+    }
+
+    /// Keys that must sign, in the order of their signatures
+    pub fn signers(&self) -> &[Pubkey] {
+        let signers = usize::from(self.transaction.message.header().num_required_signatures);
+        &self.transaction.message.static_account_keys()[..signers]
+    }
+
+    /// Signers whose signature is still missing
+    pub fn missing_signers(&self) -> Vec<Pubkey> {
+        self.signers()
+            .iter()
+            .zip(&self.transaction.signatures)
+            .filter(|(_, signature)| **signature == Signature::default())
+            .map(|(signer, _)| *signer)
+            .collect()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.missing_signers().is_empty()
+    }
+
+    /// Add `signature`, made by `signer` over `message_data` elsewhere
+    pub fn add_signature(&mut self, signer: &Pubkey, signature: Signature) -> Result<(), OfflineError> {
+        let index = self
+            .signers()
+            .iter()
+            .position(|key| key == signer)
+            .ok_or(OfflineError::UnknownSigner(*signer))?;
+        if !signature.verify(signer.as_ref(), &self.message_data()) {
+            return Err(OfflineError::InvalidSignature(*signer));
+        }
+        self.transaction.signatures[index] = signature;
+        Ok(())
+    }
+
+    /// Sign with `signer`, where its key is at hand
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<(), OfflineError> {
+        let signature = signer
+            .try_sign_message(&self.message_data())
+            .map_err(BuildError::from)?;
+        self.add_signature(&signer.pubkey(), signature)
+    }
+
+    /// The fully signed transaction
+    pub fn into_transaction(self) -> Result<VersionedTransaction, OfflineError> {
+        match self.missing_signers() {
+            missing if missing.is_empty() => Ok(self.transaction),
+            missing => Err(OfflineError::MissingSignatures(missing)),
+        }
+    }
+
+    /// Send the fully signed transaction and wait for its confirmation
+    ///
+    /// It is sent as signed: once its blockhash expires it can't land, and
+    /// must be exported and signed again.
+    pub fn submit(self, rpc: &RpcClient) -> SonomaResult<Signature> {
+        Ok(rpc.send_and_confirm_transaction(&self.into_transaction()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        address_lookup_table::AddressLookupTableAccount,
+        instruction::{AccountMeta, Instruction},
+        signature::Keypair,
+    };
+
+    fn builder(cosigner: &Pubkey) -> TransactionBuilder {
+        TransactionBuilder::new()
+            .with_compute_unit_price(1_000)
+            .add_instruction(Instruction::new_with_bytes(
+                Pubkey::new_unique(),
+                &[1, 2, 3],
+                vec![AccountMeta::new(Pubkey::new_unique(), false), AccountMeta::new_readonly(*cosigner, true)],
+            ))
+    }
+
+    #[test]
+    fn test_export_sign_import() {
+        let (payer, cosigner) = (Keypair::new(), Keypair::new());
+        let blockhash = Hash::new_unique();
+        let unsigned = OfflineTransaction::new(&builder(&cosigner.pubkey()), &payer.pubkey(), blockhash).unwrap();
+        assert_eq!(unsigned.missing_signers(), vec![payer.pubkey(), cosigner.pubkey()]);
+
+        // Each party signs its own copy of the export
+        let exported = unsigned.to_base64().unwrap();
+        let mut payer_copy = OfflineTransaction::from_base64(&exported).unwrap();
+        payer_copy.sign(&payer).unwrap();
+        let mut cosigner_copy = OfflineTransaction::from_base64(&payer_copy.to_base64().unwrap()).unwrap();
+        assert_eq!(cosigner_copy.missing_signers(), vec![cosigner.pubkey()]);
+        assert!(matches!(
+            cosigner_copy.clone().into_transaction(),
+            Err(OfflineError::MissingSignatures(missing)) if missing == vec![cosigner.pubkey()]
+        ));
+
+        let signature = cosigner.sign_message(&unsigned.message_data());
+        cosigner_copy.add_signature(&cosigner.pubkey(), signature).unwrap();
+        let transaction = cosigner_copy.into_transaction().unwrap();
+        assert!(transaction.verify_with_results().iter().all(|valid| *valid));
+        assert_eq!(*transaction.message.recent_blockhash(), blockhash);
+    }
+
+    #[test]
+    fn test_rejected_signatures() {
+        let (payer, stranger) = (Keypair::new(), Keypair::new());
+        let mut transaction =
+            OfflineTransaction::new(&builder(&payer.pubkey()), &payer.pubkey(), Hash::new_unique()).unwrap();
+
+        assert!(matches!(transaction.sign(&stranger), Err(OfflineError::UnknownSigner(_))));
+        let wrong_message = payer.sign_message(b"something else");
+        assert!(matches!(
+            transaction.add_signature(&payer.pubkey(), wrong_message),
+            Err(OfflineError::InvalidSignature(_))
+        ));
+        assert!(matches!(OfflineTransaction::from_base64("not base64!"), Err(OfflineError::Decode(_))));
+    }
+
+    #[test]
+    fn test_deterministic_v0_message() {
+        let payer = Pubkey::new_unique();
+        let builder = builder(&payer);
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: builder.instructions()[1].accounts.iter().map(|meta| meta.pubkey).take(1).collect(),
+        };
+        let builder = builder.with_lookup_table(table);
+        let blockhash = Hash::new_unique();
+
+        let first = OfflineTransaction::new(&builder, &payer, blockhash).unwrap();
+        let second = OfflineTransaction::new(&builder, &payer, blockhash).unwrap();
+        assert!(matches!(first.transaction.message, VersionedMessage::V0(_)));
+        assert_eq!(first.message_data(), second.message_data());
+        assert_eq!(first.to_base64().unwrap(), second.to_base64().unwrap());
+    }
+}
@@ -0,0 +1,195 @@
+//! Actions performed by agents
+//!
+//! `Execute` and its variants carry a Borsh-encoded `AgentAction`. Each
+//! action requires a set of capabilities on top of the instruction's own,
+//! and is validated before the processor dispatches on it. Actions that
+//! invoke another program are signed by the agent PDA, provided the target
+//! program is in the agent's `AgentConfig::allowed_programs`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+use crate::solana::program::{capability::CapabilityFlags, error::AgentError};
+
+/// Maximum length of a memo in bytes
+pub const MAX_MEMO_LEN: usize = 256;
+
+/// Action run by `Execute` and its variants
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum AgentAction {
+    /// Record the execution without doing anything else
+    Noop,
+
+    /// Send lamports from the agent's vault, PDA of `[VAULT_SEED, agent]`.
+    /// Expects the vault and `destination` among the trailing accounts.
+    Transfer {
+        destination: Pubkey,
+        lamports: u64,
+    },
+
+    /// Swap through an allowed program, e.g. a DEX. `amount_in` and
+    /// `minimum_amount_out` are informational; the swap instruction
+    /// itself enforces them.
+    Swap {
+        cpi: CpiAction,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    },
+
+    /// Log a memo through the SPL memo program, signed by the agent PDA.
+    /// Expects the memo program among the trailing accounts.
+    Memo {
+        text: String,
+    },
+
+    /// Invoke any allowed program
+    CustomCpi(CpiAction),
+}
+
+impl AgentAction {
+    /// Capabilities the agent needs to run this action
+    pub fn required_capabilities(&self) -> CapabilityFlags {
+        match self {
+            AgentAction::Transfer { .. } | AgentAction::Swap { .. } => {
+                CapabilityFlags::COMPUTE | CapabilityFlags::TRADING
+            }
+            AgentAction::Noop | AgentAction::Memo { .. } | AgentAction::CustomCpi(_) => {
+                CapabilityFlags::COMPUTE
+            }
+        }
+    }
+
+    /// Check the action is well-formed
+    pub fn validate(&self) -> Result<(), AgentError> {
+        let valid = match self {
+            AgentAction::Noop | AgentAction::CustomCpi(_) => true,
+            AgentAction::Transfer { lamports, .. } => *lamports > 0,
+            AgentAction::Swap { amount_in, minimum_amount_out, .. } => {
+                *amount_in > 0 && *minimum_amount_out > 0
+            }
+            AgentAction::Memo { text } => return validate_memo(text),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(AgentError::InvalidAction)
+        }
+    }
+
+    /// Whether the action can move the agent's funds: transfers, swaps and
+    /// arbitrary CPIs. Executors can't run these.
+    pub fn moves_funds(&self) -> bool {
+        matches!(self, AgentAction::Transfer { .. } | AgentAction::Swap { .. } | AgentAction::CustomCpi(_))
+    }
+
+    /// The CPI the action invokes, if any
+    pub fn cpi(&self) -> Option<&CpiAction> {
+        match self {
+            AgentAction::Swap { cpi, .. } | AgentAction::CustomCpi(cpi) => Some(cpi),
+            _ => None,
+        }
+    }
+}
+
+/// Check that a memo is non-empty and at most `MAX_MEMO_LEN` bytes
+pub fn validate_memo(text: &str) -> Result<(), AgentError> {
+    if text.is_empty() || text.len() > MAX_MEMO_LEN {
+        return Err(AgentError::InvalidAction);
+    }
+    Ok(())
+}
+
+/// Account of a CPI action, mirroring `AccountMeta`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CpiAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Instruction an agent invokes on another program
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct CpiAction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<CpiAccount>,
+    pub data: Vec<u8>,
+}
+
+impl CpiAction {
+    pub fn from_instruction(instruction: &Instruction) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|meta| CpiAccount {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: instruction.data.clone(),
+        }
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        Instruction {
+            program_id: self.program_id,
+            accounts: self
+                .accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.pubkey,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: self.data.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpi_round_trip() {
+        let instruction = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![
+                AccountMeta::new(Pubkey::new_unique(), true),
+                AccountMeta::new_readonly(Pubkey::new_unique(), false),
+            ],
+            data: vec![1, 2, 3],
+        };
+
+        let action = AgentAction::CustomCpi(CpiAction::from_instruction(&instruction));
+        let data = borsh::to_vec(&action).unwrap();
+        let decoded = AgentAction::try_from_slice(&data).unwrap();
+        assert_eq!(decoded, action);
+        assert_eq!(decoded.cpi().unwrap().instruction(), instruction);
+        assert!(AgentAction::try_from_slice(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_validate_and_capabilities() {
+        let transfer = AgentAction::Transfer { destination: Pubkey::new_unique(), lamports: 0 };
+        assert_eq!(transfer.validate(), Err(AgentError::InvalidAction));
+        assert!(transfer.required_capabilities().contains(CapabilityFlags::TRADING));
+
+        let memo = |len: usize| AgentAction::Memo { text: "m".repeat(len) };
+        assert!(memo(MAX_MEMO_LEN).validate().is_ok());
+        assert_eq!(memo(0).validate(), Err(AgentError::InvalidAction));
+        assert_eq!(memo(MAX_MEMO_LEN + 1).validate(), Err(AgentError::InvalidAction));
+        assert_eq!(memo(1).required_capabilities(), CapabilityFlags::COMPUTE);
+
+        assert!(AgentAction::Noop.validate().is_ok());
+        assert!(AgentAction::Noop.cpi().is_none());
+        assert!(transfer.moves_funds());
+        assert!(!memo(1).moves_funds() && !AgentAction::Noop.moves_funds());
+    }
+}
@@ -0,0 +1,263 @@
+//! Scheduling agents on Clockwork automation threads
+//!
+//! A thread created by the agent's authority holds a single `Crank`
+//! instruction, which the thread PDA signs as an executor of the agent.
+//! Every execution has its own receipt address, so `Crank` answers with the
+//! `CrankExecute` for the agent's next execution as the thread's dynamic
+//! instruction. That execution is paid for by the Clockwork worker, which
+//! the thread reimburses from its balance.
+//!
+//! `schedule_agent` and `unschedule_agent` build the client side.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::solana::program::{action::AgentAction, instruction::AgentInstruction};
+
+/// Clockwork thread program
+pub const THREAD_PROGRAM_ID: Pubkey = pubkey!("CLoCKyJ6DXBJqqu2VWx9RLbgnwwR6BMHHuyasVmfMzBh");
+
+/// Placeholder Clockwork replaces with the worker paying for an instruction
+pub const PAYER_PUBKEY: Pubkey = pubkey!("C1ockworkPayer11111111111111111111111111111");
+
+/// Seed prefix for Clockwork threads
+pub const THREAD_SEED: &[u8] = b"thread";
+
+// Anchor discriminators of the thread program's instructions
+const THREAD_CREATE_DISCRIMINATOR: [u8; 8] = [54, 1, 238, 224, 71, 244, 252, 173];
+const THREAD_DELETE_DISCRIMINATOR: [u8; 8] = [146, 6, 95, 17, 35, 98, 44, 140];
+
+/// When a thread runs, mirroring Clockwork's `Trigger` (Pyth triggers are
+/// not supported)
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum Trigger {
+    /// Whenever `size` bytes of `address` from `offset` on change
+    Account {
+        address: Pubkey,
+        offset: u64,
+        size: u64,
+    },
+    /// On a cron schedule; `skippable` drops runs missed while busy
+    Cron {
+        schedule: String,
+        skippable: bool,
+    },
+    /// As soon as possible
+    Now,
+    /// Once `slot` is reached
+    Slot {
+        slot: u64,
+    },
+    /// Once `epoch` is reached
+    Epoch {
+        epoch: u64,
+    },
+    /// Once the unix time `unix_ts` is reached
+    Timestamp {
+        unix_ts: i64,
+    },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SerializableAccount {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// Instruction in the form threads store and return it
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct SerializableInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<SerializableAccount>,
+    pub data: Vec<u8>,
+}
+
+impl From<Instruction> for SerializableInstruction {
+    fn from(instruction: Instruction) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .into_iter()
+                .map(|meta| SerializableAccount {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: instruction.data,
+        }
+    }
+}
+
+/// Return data through which a thread instruction steers its thread
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ThreadResponse {
+    /// Close the thread, sending its lamports here
+    pub close_to: Option<Pubkey>,
+    /// Instruction to run next, in the same run of the thread
+    pub dynamic_instruction: Option<SerializableInstruction>,
+    /// Replacement trigger
+    pub trigger: Option<Trigger>,
+}
+
+/// Derive the thread PDA of an authority and thread id
+pub fn find_thread_address(authority: &Pubkey, id: &[u8]) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[THREAD_SEED, authority.as_ref(), id], &THREAD_PROGRAM_ID)
+}
+
+/// Response of `Crank`: run `action` as the agent's `execution`-th
+/// execution, signed by `thread` and paid for by the worker
+pub fn crank_response(
+    program_id: &Pubkey,
+    agent_account: &Pubkey,
+    thread: &Pubkey,
+    execution: u64,
+    action: AgentAction,
+) -> ThreadResponse {
+    let instruction =
+        AgentInstruction::crank_execute(program_id, agent_account, &PAYER_PUBKEY, thread, execution, action);
+    ThreadResponse {
+        close_to: None,
+        dynamic_instruction: Some(instruction.into()),
+        trigger: None,
+    }
+}
+
+/// Create a thread running `instructions` on `trigger`, funded with
+/// `amount` lamports on top of its rent
+pub fn thread_create(
+    authority: &Pubkey,
+    payer: &Pubkey,
+    id: Vec<u8>,
+    instructions: Vec<Instruction>,
+    trigger: Trigger,
+    amount: u64,
+) -> Instruction {
+    let (thread, _) = find_thread_address(authority, &id);
+    let instructions: Vec<SerializableInstruction> = instructions.into_iter().map(Into::into).collect();
+
+    let mut data = THREAD_CREATE_DISCRIMINATOR.to_vec();
+    data.extend(borsh::to_vec(&(amount, id, instructions, trigger)).unwrap_or_default());
+
+    Instruction {
+        program_id: THREAD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(thread, false),
+        ],
+        data,
+    }
+}
+
+/// Delete a thread, sending its lamports to `close_to`
+pub fn thread_delete(authority: &Pubkey, close_to: &Pubkey, thread: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: THREAD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*close_to, false),
+            AccountMeta::new(*thread, false),
+        ],
+        data: THREAD_DELETE_DISCRIMINATOR.to_vec(),
+    }
+}
+
+/// Register the thread `id` of the agent's authority as an executor and
+/// create it, cranking `action` on `trigger`. `amount` lamports fund the
+/// thread's executions.
+pub fn schedule_agent(
+    program_id: &Pubkey,
+    agent_account: &Pubkey,
+    authority: &Pubkey,
+    id: &[u8],
+    trigger: Trigger,
+    action: AgentAction,
+    amount: u64,
+) -> [Instruction; 2] {
+    let (thread, _) = find_thread_address(authority, id);
+    let crank = AgentInstruction::crank(program_id, agent_account, &thread, action);
+    [
+        AgentInstruction::add_executor(program_id, agent_account, authority, &thread),
+        thread_create(authority, authority, id.to_vec(), vec![crank], trigger, amount),
+    ]
+}
+
+/// Delete the thread `id` of the agent's authority, refunding it, and
+/// revoke its executor rights
+pub fn unschedule_agent(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey, id: &[u8]) -> [Instruction; 2] {
+    let (thread, _) = find_thread_address(authority, id);
+    [
+        thread_delete(authority, authority, &thread),
+        AgentInstruction::remove_executor(program_id, agent_account, authority, &thread),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::program::pda;
+
+    #[test]
+    fn test_crank_response() {
+        let program_id = Pubkey::new_unique();
+        let (agent, thread) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (receipt, _) = pda::find_receipt_address(&program_id, &agent, 3);
+
+        let response = crank_response(&program_id, &agent, &thread, 3, AgentAction::Noop);
+        let decoded = ThreadResponse::try_from_slice(&borsh::to_vec(&response).unwrap()).unwrap();
+        let next = decoded.dynamic_instruction.unwrap();
+        assert_eq!(next.program_id, program_id);
+        assert_eq!(
+            next.accounts[1],
+            SerializableAccount { pubkey: PAYER_PUBKEY, is_signer: true, is_writable: true }
+        );
+        assert_eq!(next.accounts[3].pubkey, receipt);
+        assert_eq!(next.accounts[6].pubkey, thread);
+        assert_eq!(
+            AgentInstruction::unpack(&next.data).unwrap(),
+            AgentInstruction::CrankExecute { action: AgentAction::Noop }
+        );
+    }
+
+    #[test]
+    fn test_schedule_agent() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (thread, _) = find_thread_address(&authority, b"agent");
+        let trigger = Trigger::Cron { schedule: "*/10 * * * * * *".to_string(), skippable: true };
+
+        let [add_executor, create] =
+            schedule_agent(&program_id, &agent, &authority, b"agent", trigger.clone(), AgentAction::Noop, 1_000_000);
+        assert_eq!(
+            AgentInstruction::unpack(&add_executor.data).unwrap(),
+            AgentInstruction::AddExecutor { executor: thread }
+        );
+        assert_eq!(create.program_id, THREAD_PROGRAM_ID);
+        assert_eq!(create.accounts[3], AccountMeta::new(thread, false));
+        assert_eq!(create.data[..8], THREAD_CREATE_DISCRIMINATOR);
+
+        let (amount, id, instructions, decoded_trigger) =
+            <(u64, Vec<u8>, Vec<SerializableInstruction>, Trigger)>::try_from_slice(&create.data[8..]).unwrap();
+        assert_eq!((amount, id, decoded_trigger), (1_000_000, b"agent".to_vec(), trigger));
+        assert_eq!(
+            instructions,
+            vec![AgentInstruction::crank(&program_id, &agent, &thread, AgentAction::Noop).into()]
+        );
+
+        let [delete, remove_executor] = unschedule_agent(&program_id, &agent, &authority, b"agent");
+        assert_eq!(delete.accounts[2].pubkey, thread);
+        assert_eq!(
+            AgentInstruction::unpack(&remove_executor.data).unwrap(),
+            AgentInstruction::RemoveExecutor { executor: thread }
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Agent capabilities as a compact bitfield
+//!
+//! Capabilities used to be stored as a list of names. On-chain they are now
+//! a single `u64`; the names remain the client-facing representation and
+//! map onto the flags below.
+
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitOrAssign};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Set of agent capabilities, one bit per capability
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CapabilityFlags(u64);
+
+impl CapabilityFlags {
+    pub const NONE: Self = Self(0);
+
+    /// Run actions (`Execute` and its variants)
+    pub const COMPUTE: Self = Self(1 << 0);
+
+    /// Stage large action data in staging accounts
+    pub const STORAGE: Self = Self(1 << 1);
+
+    /// Talk to off-chain services
+    pub const NETWORK: Self = Self(1 << 2);
+
+    /// Gate actions on oracle prices (`ExecuteConditional`)
+    pub const ORACLE: Self = Self(1 << 3);
+
+    /// Place trades
+    pub const TRADING: Self = Self(1 << 4);
+
+    /// Every capability known to this version of the program
+    pub const ALL: Self = Self(
+        Self::COMPUTE.0 | Self::STORAGE.0 | Self::NETWORK.0 | Self::ORACLE.0 | Self::TRADING.0,
+    );
+
+    /// Capability names and their flags
+    pub const NAMES: [(&'static str, Self); 5] = [
+        ("compute", Self::COMPUTE),
+        ("storage", Self::STORAGE),
+        ("network", Self::NETWORK),
+        ("oracle", Self::ORACLE),
+        ("trading", Self::TRADING),
+    ];
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether every capability in `other` is set
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Capabilities of `other` missing from `self`
+    pub const fn missing(self, other: Self) -> Self {
+        Self(other.0 & !self.0)
+    }
+
+    /// Bits that don't correspond to a known capability
+    pub const fn unknown(self) -> Self {
+        Self(self.0 & !Self::ALL.0)
+    }
+
+    /// Look up a capability by name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, flag)| *flag)
+    }
+
+    /// Convert capability names, failing on the first unknown name
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        names.iter().try_fold(Self::NONE, |flags, name| {
+            Self::from_name(name.as_ref())
+                .map(|flag| flags | flag)
+                .ok_or_else(|| name.as_ref().to_string())
+        })
+    }
+
+    /// Names of the set capabilities, in bit order
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+impl BitOr for CapabilityFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for CapabilityFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for CapabilityFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl fmt::Display for CapabilityFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.names().join("|"))?;
+        if !self.unknown().is_empty() {
+            write!(f, "|{:#x}", self.unknown().0)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_round_trip() {
+        let flags = CapabilityFlags::from_names(&["compute", "oracle"]).unwrap();
+        assert_eq!(flags, CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE);
+        assert_eq!(flags.names(), vec!["compute", "oracle"]);
+        assert_eq!(flags.to_string(), "compute|oracle");
+
+        assert_eq!(
+            CapabilityFlags::from_names(&["compute", "teleport"]),
+            Err("teleport".to_string())
+        );
+    }
+
+    #[test]
+    fn test_contains_and_missing() {
+        let agent = CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE;
+        let required = CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE;
+
+        assert!(agent.contains(CapabilityFlags::COMPUTE));
+        assert!(!agent.contains(required));
+        assert_eq!(agent.missing(required), CapabilityFlags::ORACLE);
+        assert!(CapabilityFlags::from_bits(1 << 63).unknown().bits() != 0);
+    }
+
+    #[test]
+    fn test_serialized_as_u64() {
+        let data = borsh::to_vec(&CapabilityFlags::ALL).unwrap();
+        assert_eq!(data, CapabilityFlags::ALL.bits().to_le_bytes());
+    }
+}
@@ -0,0 +1,169 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction,
+    system_program,
+    sysvar::Sysvar,
+};
+
+/// Create a PDA account owned by `owner`, funded by `payer`
+///
+/// Works even if someone already sent lamports to the address, which would
+/// make a plain `create_account` fail.
+pub fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    lamports: u64,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if system_program.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(payer.key, account.key, lamports, space as u64, owner),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[signer_seeds],
+        );
+    }
+
+    let shortfall = lamports.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, shortfall),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    invoke_signed(
+        &system_instruction::allocate(account.key, space as u64),
+        &[account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, owner),
+        &[account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Resize a program-owned account, topping up rent from `payer`
+///
+/// Shrinking leaves excess lamports in the account.
+pub fn resize_account<'a>(
+    payer: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    new_len: usize,
+) -> ProgramResult {
+    if system_program.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let required = Rent::get()?.minimum_balance(new_len);
+    let shortfall = required.saturating_sub(account.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, shortfall),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+
+    account.realloc(new_len, false)
+}
+
+/// Transfer lamports from a signer of the instruction
+pub fn transfer_lamports<'a>(
+    from: &AccountInfo<'a>,
+    to: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    lamports: u64,
+) -> ProgramResult {
+    if system_program.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke(
+        &system_instruction::transfer(from.key, to.key, lamports),
+        &[from.clone(), to.clone(), system_program.clone()],
+    )
+}
+
+/// Transfer lamports out of a PDA-owned system account (e.g. an agent vault)
+pub fn transfer_lamports_signed<'a>(
+    from: &AccountInfo<'a>,
+    to: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    lamports: u64,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if system_program.key != &system_program::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke_signed(
+        &system_instruction::transfer(from.key, to.key, lamports),
+        &[from.clone(), to.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Transfer SPL tokens from a token account whose owner is a PDA
+pub fn transfer_tokens_signed<'a>(
+    token_program: &AccountInfo<'a>,
+    source: &AccountInfo<'a>,
+    destination: &AccountInfo<'a>,
+    authority: &AccountInfo<'a>,
+    amount: u64,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if token_program.key != &spl_token::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    let instruction = spl_token::instruction::transfer(
+        token_program.key,
+        source.key,
+        destination.key,
+        authority.key,
+        &[],
+        amount,
+    )?;
+
+    invoke_signed(
+        &instruction,
+        &[
+            source.clone(),
+            destination.clone(),
+            authority.clone(),
+            token_program.clone(),
+        ],
+        &[signer_seeds],
+    )
+}
+
+/// Write an SPL memo signed by a PDA
+pub fn memo_signed<'a>(
+    memo_program: &AccountInfo<'a>,
+    signer: &AccountInfo<'a>,
+    memo: &str,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    if memo_program.key != &spl_memo::id() {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke_signed(
+        &spl_memo::build_memo(memo.as_bytes(), &[signer.key]),
+        &[signer.clone(), memo_program.clone()],
+        &[signer_seeds],
+    )
+}
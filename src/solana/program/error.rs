@@ -52,6 +52,75 @@ pub enum AgentError {
 
     #[error("Invalid system program")]
     InvalidSystemProgram = 14,
+
+    #[error("Action is time-locked")]
+    ExecutionLocked = 15,
+
+    #[error("Invalid oracle account")]
+    InvalidOracleAccount = 16,
+
+    #[error("Oracle price is stale")]
+    StaleOraclePrice = 17,
+
+    #[error("Execution condition not met")]
+    ConditionNotMet = 18,
+
+    #[error("Invalid chunk for staging account")]
+    InvalidChunk = 19,
+
+    #[error("Staged upload is incomplete")]
+    UploadIncomplete = 20,
+
+    #[error("Staged data hash mismatch")]
+    HashMismatch = 21,
+
+    #[error("Account is not rent exempt")]
+    NotRentExempt = 22,
+
+    #[error("Account too small for agent data")]
+    AccountTooSmall = 23,
+
+    #[error("Delegate session has expired")]
+    DelegateExpired = 24,
+
+    #[error("Agent executed too recently")]
+    RateLimited = 25,
+
+    #[error("Agent lacks a capability required by the instruction")]
+    MissingCapability = 26,
+
+    #[error("Account must be writable")]
+    AccountNotWritable = 27,
+
+    #[error("Program is not allowed for agent CPI")]
+    ProgramNotAllowed = 28,
+
+    #[error("Program is frozen by its admin")]
+    ProgramFrozen = 29,
+
+    #[error("Agent already lists the maximum number of executors")]
+    TooManyExecutors = 30,
+
+    #[error("Parent agent is paused")]
+    ParentPaused = 31,
+
+    #[error("Action is malformed")]
+    InvalidAction = 32,
+
+    #[error("Agent has not staked enough lamports to run")]
+    InsufficientStake = 33,
+
+    #[error("Stake is locked until the agent is closed")]
+    StakeLocked = 34,
+
+    #[error("Config update is still time-locked")]
+    UpdateLocked = 35,
+
+    #[error("Authority already has an agent with this name")]
+    NameAlreadyTaken = 36,
+
+    #[error("Listing price differs from the price offered")]
+    PriceMismatch = 37,
 }
 
 impl From<AgentError> for ProgramError {
@@ -0,0 +1,262 @@
+//! Structured events for agent lifecycle changes
+//!
+//! Events are logged with `sol_log_data` as an 8-byte discriminator
+//! followed by the Borsh-encoded event, the same encoding as Anchor's
+//! `emit!`. They appear in transaction logs as `Program data: <base64>`,
+//! so indexers can follow agents without polling account data.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{hash::hash, log::sol_log_data, pubkey::Pubkey};
+use crate::solana::program::{instruction::AgentConfig, state::PauseReason};
+
+/// Prefix of log lines carrying event data
+pub const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// An event emitted by the agent program
+pub trait Event: BorshSerialize {
+    /// Event name, hashed into the discriminator
+    const NAME: &'static str;
+
+    /// Anchor event discriminator, `sha256("event:<NAME>")[..8]`
+    fn discriminator() -> [u8; 8] {
+        let mut discriminator = [0u8; 8];
+        discriminator.copy_from_slice(&hash(format!("event:{}", Self::NAME).as_bytes()).to_bytes()[..8]);
+        discriminator
+    }
+
+    /// Discriminator followed by the Borsh-encoded event
+    fn data(&self) -> Vec<u8> {
+        let mut data = Self::discriminator().to_vec();
+        data.extend(borsh::to_vec(self).unwrap_or_default());
+        data
+    }
+
+    /// Log the event to the transaction logs
+    fn emit(&self) {
+        sol_log_data(&[&self.data()]);
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentInitialized {
+    pub agent: Pubkey,
+    pub authority: Pubkey,
+    pub name: String,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentUpdated {
+    pub agent: Pubkey,
+    pub signer: Pubkey,
+    pub config: AgentConfig,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentExecuted {
+    pub agent: Pubkey,
+    pub signer: Pubkey,
+    pub execution_count: u64,
+    pub timestamp: i64,
+    /// SHA-256 of the action data
+    pub action_hash: [u8; 32],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentPaused {
+    pub agent: Pubkey,
+    pub signer: Pubkey,
+    pub reason: PauseReason,
+    pub resume_at: Option<i64>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentResumed {
+    pub agent: Pubkey,
+    pub signer: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentClosed {
+    pub agent: Pubkey,
+    pub authority: Pubkey,
+    /// Rent returned to the authority
+    pub lamports: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentListed {
+    pub agent: Pubkey,
+    pub seller: Pubkey,
+    /// Price in lamports
+    pub price: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentSold {
+    pub agent: Pubkey,
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    /// Price in lamports
+    pub price: u64,
+}
+
+impl Event for AgentInitialized {
+    const NAME: &'static str = "AgentInitialized";
+}
+
+impl Event for AgentUpdated {
+    const NAME: &'static str = "AgentUpdated";
+}
+
+impl Event for AgentExecuted {
+    const NAME: &'static str = "AgentExecuted";
+}
+
+impl Event for AgentPaused {
+    const NAME: &'static str = "AgentPaused";
+}
+
+impl Event for AgentResumed {
+    const NAME: &'static str = "AgentResumed";
+}
+
+impl Event for AgentClosed {
+    const NAME: &'static str = "AgentClosed";
+}
+
+impl Event for AgentListed {
+    const NAME: &'static str = "AgentListed";
+}
+
+impl Event for AgentSold {
+    const NAME: &'static str = "AgentSold";
+}
+
+/// Any event emitted by the agent program
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    Initialized(AgentInitialized),
+    Updated(AgentUpdated),
+    Executed(AgentExecuted),
+    Paused(AgentPaused),
+    Resumed(AgentResumed),
+    Closed(AgentClosed),
+    Listed(AgentListed),
+    Sold(AgentSold),
+}
+
+impl AgentEvent {
+    /// Decode event data (discriminator and Borsh payload)
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 {
+            return None;
+        }
+        let (discriminator, mut payload) = data.split_at(8);
+
+        fn parse<E: Event + BorshDeserialize>(discriminator: &[u8], payload: &mut &[u8]) -> Option<E> {
+            if discriminator != E::discriminator() {
+                return None;
+            }
+            E::deserialize(payload).ok()
+        }
+
+        parse(discriminator, &mut payload)
+            .map(Self::Initialized)
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Updated))
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Executed))
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Paused))
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Resumed))
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Closed))
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Listed))
+            .or_else(|| parse(discriminator, &mut payload).map(Self::Sold))
+    }
+
+    /// Decode a `Program data: <base64>` transaction log line
+    pub fn from_log(line: &str) -> Option<Self> {
+        let encoded = line.strip_prefix(PROGRAM_DATA_PREFIX)?;
+        Self::decode(&STANDARD.decode(encoded.trim()).ok()?)
+    }
+
+    /// Decode every agent event in a transaction's logs
+    ///
+    /// Logs are not attributed to programs here, so callers should only
+    /// pass logs of transactions that invoked the agent program.
+    pub fn parse_logs<S: AsRef<str>>(logs: &[S]) -> Vec<Self> {
+        logs.iter().filter_map(|line| Self::from_log(line.as_ref())).collect()
+    }
+
+    /// Agent the event refers to
+    pub fn agent(&self) -> &Pubkey {
+        match self {
+            Self::Initialized(event) => &event.agent,
+            Self::Updated(event) => &event.agent,
+            Self::Executed(event) => &event.agent,
+            Self::Paused(event) => &event.agent,
+            Self::Resumed(event) => &event.agent,
+            Self::Closed(event) => &event.agent,
+            Self::Listed(event) => &event.agent,
+            Self::Sold(event) => &event.agent,
+        }
+    }
+
+    /// Name of the event, e.g. `AgentExecuted`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Initialized(_) => AgentInitialized::NAME,
+            Self::Updated(_) => AgentUpdated::NAME,
+            Self::Executed(_) => AgentExecuted::NAME,
+            Self::Paused(_) => AgentPaused::NAME,
+            Self::Resumed(_) => AgentResumed::NAME,
+            Self::Closed(_) => AgentClosed::NAME,
+            Self::Listed(_) => AgentListed::NAME,
+            Self::Sold(_) => AgentSold::NAME,
+        }
+    }
+
+    /// Discriminator followed by the Borsh-encoded event, as logged
+    pub fn data(&self) -> Vec<u8> {
+        match self {
+            Self::Initialized(event) => event.data(),
+            Self::Updated(event) => event.data(),
+            Self::Executed(event) => event.data(),
+            Self::Paused(event) => event.data(),
+            Self::Resumed(event) => event.data(),
+            Self::Closed(event) => event.data(),
+            Self::Listed(event) => event.data(),
+            Self::Sold(event) => event.data(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_anchor_discriminator() {
+        let expected = hash(b"event:AgentPaused").to_bytes();
+        assert_eq!(AgentPaused::discriminator(), expected[..8]);
+        assert_ne!(AgentPaused::discriminator(), AgentResumed::discriminator());
+    }
+
+    #[test]
+    fn test_event_round_trip_through_logs() {
+        let event = AgentExecuted {
+            agent: Pubkey::new_unique(),
+            signer: Pubkey::new_unique(),
+            execution_count: 7,
+            timestamp: 1_700_000_000,
+            action_hash: [3; 32],
+        };
+        let logs = vec![
+            "Program log: Instruction: Execute Agent Action".to_string(),
+            format!("{}{}", PROGRAM_DATA_PREFIX, STANDARD.encode(event.data())),
+            "Program data: bm90IGFuIGV2ZW50".to_string(),
+        ];
+
+        let events = AgentEvent::parse_logs(&logs);
+        assert_eq!(events, vec![AgentEvent::Executed(event.clone())]);
+        assert_eq!(events[0].agent(), &event.agent);
+    }
+}
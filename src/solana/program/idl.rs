@@ -0,0 +1,776 @@
+//! Anchor IDL describing the agent program
+//!
+//! Built with the `anchor-compat` feature, which also switches instruction
+//! data to Anchor discriminators (see `ANCHOR_DISCRIMINATORS`), so tools
+//! that expect an Anchor program can decode instructions, accounts and
+//! events. Program accounts carry no 8-byte discriminator; their data
+//! starts directly with the fields listed here.
+
+use num_traits::FromPrimitive;
+use serde_json::{json, Value};
+use crate::solana::program::error::AgentError;
+
+/// Program name used in the IDL
+pub const IDL_NAME: &str = "sonoma_labs_toolkit";
+
+/// The IDL as a JSON value
+pub fn idl() -> Value {
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "name": IDL_NAME,
+        "instructions": instructions(),
+        "accounts": accounts(),
+        "types": types(),
+        "events": events(),
+        "errors": errors(),
+    })
+}
+
+/// The IDL as pretty-printed JSON, e.g. to write to `target/idl`
+pub fn idl_json() -> String {
+    serde_json::to_string_pretty(&idl()).unwrap_or_default()
+}
+
+/// `snake_case` to Anchor's `camelCase`
+fn camel_case(name: &str) -> String {
+    let mut words = name.split('_');
+    let mut camel = words.next().unwrap_or_default().to_string();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+fn account(name: &str, is_mut: bool, is_signer: bool) -> Value {
+    json!({ "name": camel_case(name), "isMut": is_mut, "isSigner": is_signer })
+}
+
+fn field(name: &str, ty: Value) -> Value {
+    json!({ "name": camel_case(name), "type": ty })
+}
+
+fn defined(name: &str) -> Value {
+    json!({ "defined": name })
+}
+
+fn instruction(name: &str, accounts: Vec<Value>, args: Vec<Value>) -> Value {
+    json!({ "name": camel_case(name), "accounts": accounts, "args": args })
+}
+
+fn struct_type(name: &str, docs: &[&str], fields: Vec<Value>) -> Value {
+    json!({ "name": name, "docs": docs, "type": { "kind": "struct", "fields": fields } })
+}
+
+fn enum_type(name: &str, variants: Vec<Value>) -> Value {
+    json!({ "name": name, "type": { "kind": "enum", "variants": variants } })
+}
+
+/// Receipt, system program and program config of the execute instructions
+fn receipt_accounts() -> [Value; 3] {
+    [
+        account("receipt", true, false),
+        account("system_program", false, false),
+        account("program_config", false, false),
+    ]
+}
+
+fn execute_accounts(extra: &[Value]) -> Vec<Value> {
+    let mut accounts = vec![
+        account("agent", true, false),
+        account("authority", true, true),
+        account("result_account", true, false),
+    ];
+    accounts.extend_from_slice(extra);
+    accounts.extend(receipt_accounts());
+    accounts
+}
+
+/// Instructions in variant order, matching `ANCHOR_DISCRIMINATORS`
+fn instructions() -> Vec<Value> {
+    let agent_and_authority = || vec![account("agent", true, false), account("authority", false, true)];
+    let admin = || vec![account("program_config", true, false), account("admin", false, true)];
+
+    vec![
+        instruction(
+            "initialize",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("registry", true, false),
+                account("metadata", true, false),
+            ],
+            vec![field("name", json!("string")), field("config", defined("AgentConfig"))],
+        ),
+        instruction(
+            "update",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("metadata", true, false),
+                account("pending_update", true, false),
+            ],
+            vec![field("config", defined("AgentConfig"))],
+        ),
+        instruction(
+            "execute",
+            execute_accounts(&[]),
+            vec![field("action", defined("AgentAction")), field("memo", json!({ "option": "string" }))],
+        ),
+        instruction(
+            "pause",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+            ],
+            vec![
+                field("reason", defined("PauseReason")),
+                field("resume_at", json!({ "option": "i64" })),
+            ],
+        ),
+        instruction(
+            "resume",
+            [agent_and_authority(), vec![account("stake", false, false)]].concat(),
+            vec![],
+        ),
+        instruction(
+            "execute_after",
+            execute_accounts(&[]),
+            vec![
+                field("action", defined("AgentAction")),
+                field("earliest_slot", json!({ "option": "u64" })),
+                field("earliest_unix_time", json!({ "option": "i64" })),
+            ],
+        ),
+        instruction(
+            "execute_conditional",
+            execute_accounts(&[account("price_feed", false, false)]),
+            vec![
+                field("action", defined("AgentAction")),
+                field("condition", defined("PriceCondition")),
+            ],
+        ),
+        instruction(
+            "write_chunk",
+            vec![
+                account("agent", false, false),
+                account("authority", true, true),
+                account("staging", true, false),
+                account("system_program", false, false),
+            ],
+            vec![field("offset", json!("u32")), field("data", json!("bytes"))],
+        ),
+        instruction(
+            "finalize_execute",
+            execute_accounts(&[account("staging", true, false)]),
+            vec![
+                field("total_len", json!("u32")),
+                field("hash", json!({ "array": ["u8", 32] })),
+            ],
+        ),
+        instruction(
+            "close",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("registry", true, false),
+                account("pending_update", true, false),
+            ],
+            vec![],
+        ),
+        instruction(
+            "transfer_authority",
+            agent_and_authority(),
+            vec![field("new_authority", json!("publicKey"))],
+        ),
+        instruction(
+            "accept_authority",
+            vec![account("agent", true, false), account("pending_authority", false, true)],
+            vec![],
+        ),
+        instruction(
+            "migrate",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+            ],
+            vec![],
+        ),
+        instruction(
+            "delegate",
+            vec![
+                account("agent", false, false),
+                account("authority", true, true),
+                account("delegate_record", true, false),
+                account("system_program", false, false),
+            ],
+            vec![
+                field("delegate", json!("publicKey")),
+                field("expiry_slot", json!("u64")),
+                field("permissions", json!("u32")),
+            ],
+        ),
+        instruction(
+            "set_admin",
+            vec![
+                account("program_config", true, false),
+                account("admin", true, true),
+                account("system_program", false, false),
+                account("program_data", false, false),
+            ],
+            vec![field("new_admin", json!("publicKey"))],
+        ),
+        instruction("freeze_all", admin(), vec![]),
+        instruction("thaw_all", admin(), vec![]),
+        instruction(
+            "clone_agent",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("registry", true, false),
+                account("source_agent", false, false),
+                account("metadata", true, false),
+            ],
+            vec![field("name", json!("string"))],
+        ),
+        instruction(
+            "add_executor",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+            ],
+            vec![field("executor", json!("publicKey"))],
+        ),
+        instruction(
+            "remove_executor",
+            agent_and_authority(),
+            vec![field("executor", json!("publicKey"))],
+        ),
+        instruction(
+            "spawn_child",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("registry", true, false),
+                account("parent_agent", false, false),
+                account("metadata", true, false),
+            ],
+            vec![field("name", json!("string")), field("config", defined("AgentConfig"))],
+        ),
+        instruction(
+            "stake",
+            vec![
+                account("agent", false, false),
+                account("staker", true, true),
+                account("stake", true, false),
+                account("system_program", false, false),
+            ],
+            vec![field("lamports", json!("u64"))],
+        ),
+        instruction(
+            "unstake",
+            vec![
+                account("agent", false, false),
+                account("staker", true, true),
+                account("stake", true, false),
+            ],
+            vec![],
+        ),
+        instruction(
+            "commit_update",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("metadata", true, false),
+                account("pending_update", true, false),
+            ],
+            vec![],
+        ),
+        instruction(
+            "cancel_update",
+            vec![
+                account("agent", false, false),
+                account("authority", true, true),
+                account("pending_update", true, false),
+            ],
+            vec![],
+        ),
+        instruction(
+            "set_update_delay",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("pending_update", true, false),
+            ],
+            vec![field("slots", json!("u64"))],
+        ),
+        instruction(
+            "crank",
+            vec![account("agent", false, false), account("thread", false, true)],
+            vec![field("action", defined("AgentAction"))],
+        ),
+        instruction(
+            "crank_execute",
+            [
+                vec![
+                    account("agent", true, false),
+                    account("payer", true, true),
+                    account("result_account", true, false),
+                ],
+                receipt_accounts().to_vec(),
+                vec![account("executor", false, true)],
+            ]
+            .concat(),
+            vec![field("action", defined("AgentAction"))],
+        ),
+        instruction(
+            "list_for_sale",
+            vec![
+                account("agent", true, false),
+                account("authority", true, true),
+                account("system_program", false, false),
+                account("listing", true, false),
+            ],
+            vec![field("price", json!("u64"))],
+        ),
+        instruction(
+            "purchase",
+            vec![
+                account("agent", true, false),
+                account("buyer", true, true),
+                account("seller", true, false),
+                account("listing", true, false),
+                account("system_program", false, false),
+                account("pending_update", true, false),
+            ],
+            vec![field("price", json!("u64"))],
+        ),
+        instruction(
+            "cancel_listing",
+            vec![
+                account("agent", true, false),
+                account("seller", true, true),
+                account("listing", true, false),
+            ],
+            vec![],
+        ),
+        instruction(
+            "set_oracle_programs",
+            admin(),
+            vec![
+                field("pyth_program", json!("publicKey")),
+                field("switchboard_program", json!("publicKey")),
+            ],
+        ),
+        instruction(
+            "close_receipt",
+            vec![
+                account("agent", false, false),
+                account("authority", true, true),
+                account("receipt", true, false),
+            ],
+            vec![field("execution", json!("u64"))],
+        ),
+    ]
+}
+
+fn accounts() -> Vec<Value> {
+    vec![
+        struct_type(
+            "AgentAccount",
+            &["Layout version first; older versions are upgraded on write"],
+            vec![
+                field("version", json!("u8")),
+                field("authority", json!("publicKey")),
+                field("name", json!("string")),
+                field("config", defined("AgentConfig")),
+                field("state", defined("AgentState")),
+                field("last_execution", json!("i64")),
+                field("execution_count", json!("u64")),
+                field("pending_authority", json!({ "option": "publicKey" })),
+                field("creator", json!("publicKey")),
+                field("bump", json!("u8")),
+                field("metrics", defined("PerformanceMetrics")),
+                field("pause_reason", json!({ "option": { "defined": "PauseReason" } })),
+                field("resume_at", json!({ "option": "i64" })),
+                field("executors", json!({ "vec": "publicKey" })),
+                field("parent", json!({ "option": "publicKey" })),
+                field("update_delay", json!("u64")),
+                field("generation", json!("u64")),
+            ],
+        ),
+        struct_type(
+            "ExecutionReceipt",
+            &[],
+            vec![
+                field("agent", json!("publicKey")),
+                field("execution", json!("u64")),
+                field("signer", json!("publicKey")),
+                field("slot", json!("u64")),
+                field("timestamp", json!("i64")),
+                field("action_hash", json!({ "array": ["u8", 32] })),
+                field("status", defined("ExecutionStatus")),
+                field("bump", json!("u8")),
+                field("memo_hash", json!({ "option": { "array": ["u8", 32] } })),
+            ],
+        ),
+        struct_type(
+            "AgentMetadata",
+            &[],
+            vec![
+                field("agent", json!("publicKey")),
+                field("created_at", json!("i64")),
+                field("updated_at", json!("i64")),
+                field("version", json!("u32")),
+                field("bump", json!("u8")),
+            ],
+        ),
+        struct_type(
+            "ResultAccount",
+            &["Ring buffer of recent results; `head` is the next index written"],
+            vec![
+                field("agent", json!("publicKey")),
+                field("bump", json!("u8")),
+                field("head", json!("u32")),
+                field("results", json!({ "vec": { "defined": "ExecutionResult" } })),
+            ],
+        ),
+        struct_type(
+            "PendingUpdate",
+            &["Config update queued by an agent with an update delay"],
+            vec![
+                field("agent", json!("publicKey")),
+                field("config", json!({ "option": { "defined": "AgentConfig" } })),
+                field("update_delay", json!({ "option": "u64" })),
+                field("activation_slot", json!("u64")),
+                field("bump", json!("u8")),
+            ],
+        ),
+        struct_type(
+            "StakeAccount",
+            &["Escrow of the lamports staked for an agent; `amount` excludes rent"],
+            vec![
+                field("agent", json!("publicKey")),
+                field("staker", json!("publicKey")),
+                field("amount", json!("u64")),
+                field("bump", json!("u8")),
+            ],
+        ),
+        struct_type(
+            "Listing",
+            &["Agent offered for sale; the listing is the agent's authority until it is sold or withdrawn"],
+            vec![
+                field("agent", json!("publicKey")),
+                field("seller", json!("publicKey")),
+                field("price", json!("u64")),
+                field("bump", json!("u8")),
+            ],
+        ),
+        struct_type(
+            "DelegateRecord",
+            &[],
+            vec![
+                field("is_initialized", json!("bool")),
+                field("agent", json!("publicKey")),
+                field("authority", json!("publicKey")),
+                field("delegate", json!("publicKey")),
+                field("expiry_slot", json!("u64")),
+                field("permissions", json!("u32")),
+                field("bump", json!("u8")),
+                field("generation", json!("u64")),
+            ],
+        ),
+        struct_type(
+            "AgentRegistry",
+            &[],
+            vec![
+                field("authority", json!("publicKey")),
+                field("bump", json!("u8")),
+                field("created", json!("u64")),
+                field("agents", json!({ "vec": "publicKey" })),
+            ],
+        ),
+        struct_type(
+            "ProgramConfig",
+            &[],
+            vec![
+                field("is_initialized", json!("bool")),
+                field("admin", json!("publicKey")),
+                field("frozen", json!("bool")),
+                field("bump", json!("u8")),
+                field("pyth_program", json!("publicKey")),
+                field("switchboard_program", json!("publicKey")),
+            ],
+        ),
+    ]
+}
+
+fn types() -> Vec<Value> {
+    let unit_variants = |names: &[&str]| names.iter().map(|name| json!({ "name": name })).collect();
+
+    vec![
+        struct_type(
+            "AgentConfig",
+            &[],
+            vec![
+                field("autonomous_mode", json!("bool")),
+                field("execution_limit", json!("u64")),
+                field("memory_limit", json!("u64")),
+                field("capabilities", json!("u64")),
+                field("min_execution_interval", json!("u32")),
+                field("allowed_programs", json!({ "vec": "publicKey" })),
+            ],
+        ),
+        enum_type(
+            "AgentState",
+            unit_variants(&["Uninitialized", "Initialized", "Running", "Paused", "Error", "Terminated"]),
+        ),
+        enum_type(
+            "PauseReason",
+            unit_variants(&["Manual", "Maintenance", "RiskLimit", "Emergency", "Other"]),
+        ),
+        struct_type(
+            "PerformanceMetrics",
+            &[],
+            vec![
+                field("total_executions", json!("u64")),
+                field("successful_executions", json!("u64")),
+                field("failed_executions", json!("u64")),
+                field("average_execution_time", json!("u64")),
+                field("total_compute_units", json!("u64")),
+                field("average_compute_units", json!("u64")),
+            ],
+        ),
+        enum_type(
+            "ExecutionStatus",
+            vec![
+                json!({ "name": "Succeeded" }),
+                json!({ "name": "Failed", "fields": [field("error", json!("u32"))] }),
+            ],
+        ),
+        struct_type(
+            "ExecutionResult",
+            &[],
+            vec![
+                field("execution", json!("u64")),
+                field("status", defined("ExecutionStatus")),
+                field("output_hash", json!({ "array": ["u8", 32] })),
+                field("timestamp", json!("i64")),
+            ],
+        ),
+        enum_type(
+            "AgentAction",
+            vec![
+                json!({ "name": "Noop" }),
+                json!({
+                    "name": "Transfer",
+                    "fields": [field("destination", json!("publicKey")), field("lamports", json!("u64"))],
+                }),
+                json!({
+                    "name": "Swap",
+                    "fields": [
+                        field("cpi", defined("CpiAction")),
+                        field("amount_in", json!("u64")),
+                        field("minimum_amount_out", json!("u64")),
+                    ],
+                }),
+                json!({ "name": "Memo", "fields": [field("text", json!("string"))] }),
+                json!({ "name": "CustomCpi", "fields": [defined("CpiAction")] }),
+            ],
+        ),
+        struct_type(
+            "CpiAction",
+            &[],
+            vec![
+                field("program_id", json!("publicKey")),
+                field("accounts", json!({ "vec": defined("CpiAccount") })),
+                field("data", json!("bytes")),
+            ],
+        ),
+        struct_type(
+            "CpiAccount",
+            &[],
+            vec![
+                field("pubkey", json!("publicKey")),
+                field("is_signer", json!("bool")),
+                field("is_writable", json!("bool")),
+            ],
+        ),
+        struct_type(
+            "PriceCondition",
+            &[],
+            vec![
+                field("feed", json!("publicKey")),
+                field("comparator", defined("Comparator")),
+                field("threshold", json!("i64")),
+                field("expo", json!("i32")),
+                field("max_staleness_slots", json!("u64")),
+            ],
+        ),
+        enum_type(
+            "Comparator",
+            unit_variants(&["GreaterThan", "GreaterThanOrEqual", "LessThan", "LessThanOrEqual"]),
+        ),
+    ]
+}
+
+fn events() -> Vec<Value> {
+    let event = |name: &str, fields: Vec<Value>| {
+        let fields: Vec<Value> = fields
+            .into_iter()
+            .map(|mut field| {
+                field["index"] = json!(false);
+                field
+            })
+            .collect();
+        json!({ "name": name, "fields": fields })
+    };
+    let agent_and_signer = || vec![field("agent", json!("publicKey")), field("signer", json!("publicKey"))];
+
+    vec![
+        event(
+            "AgentInitialized",
+            vec![
+                field("agent", json!("publicKey")),
+                field("authority", json!("publicKey")),
+                field("name", json!("string")),
+            ],
+        ),
+        event(
+            "AgentUpdated",
+            [agent_and_signer(), vec![field("config", defined("AgentConfig"))]].concat(),
+        ),
+        event(
+            "AgentExecuted",
+            [
+                agent_and_signer(),
+                vec![
+                    field("execution_count", json!("u64")),
+                    field("timestamp", json!("i64")),
+                    field("action_hash", json!({ "array": ["u8", 32] })),
+                ],
+            ]
+            .concat(),
+        ),
+        event(
+            "AgentPaused",
+            [
+                agent_and_signer(),
+                vec![
+                    field("reason", defined("PauseReason")),
+                    field("resume_at", json!({ "option": "i64" })),
+                ],
+            ]
+            .concat(),
+        ),
+        event("AgentResumed", agent_and_signer()),
+        event(
+            "AgentClosed",
+            vec![
+                field("agent", json!("publicKey")),
+                field("authority", json!("publicKey")),
+                field("lamports", json!("u64")),
+            ],
+        ),
+        event(
+            "AgentListed",
+            vec![
+                field("agent", json!("publicKey")),
+                field("seller", json!("publicKey")),
+                field("price", json!("u64")),
+            ],
+        ),
+        event(
+            "AgentSold",
+            vec![
+                field("agent", json!("publicKey")),
+                field("seller", json!("publicKey")),
+                field("buyer", json!("publicKey")),
+                field("price", json!("u64")),
+            ],
+        ),
+    ]
+}
+
+/// Every `AgentError`, with its custom program error code
+fn errors() -> Vec<Value> {
+    (0..)
+        .map_while(AgentError::from_u32)
+        .map(|error| json!({ "code": error as u32, "name": format!("{:?}", error), "msg": error.to_string() }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solana::program::{
+        event::{
+            AgentClosed, AgentExecuted, AgentInitialized, AgentListed, AgentPaused, AgentResumed, AgentSold,
+            AgentUpdated, Event,
+        },
+        instruction::ANCHOR_DISCRIMINATORS,
+    };
+
+    #[test]
+    fn test_instructions_match_discriminators() {
+        let idl = idl();
+        let names: Vec<&str> = idl["instructions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|instruction| instruction["name"].as_str().unwrap())
+            .collect();
+        let expected: Vec<String> = ANCHOR_DISCRIMINATORS.iter().map(|(name, _)| camel_case(name)).collect();
+        assert_eq!(names, expected);
+        assert_eq!(idl["instructions"][5]["name"], "executeAfter");
+    }
+
+    #[test]
+    fn test_events_and_errors() {
+        let idl = idl();
+        let events: Vec<&str> = idl["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                AgentInitialized::NAME,
+                AgentUpdated::NAME,
+                AgentExecuted::NAME,
+                AgentPaused::NAME,
+                AgentResumed::NAME,
+                AgentClosed::NAME,
+                AgentListed::NAME,
+                AgentSold::NAME,
+            ]
+        );
+
+        let errors = idl["errors"].as_array().unwrap();
+        let frozen = errors.iter().find(|error| error["name"] == "ProgramFrozen").unwrap();
+        assert_eq!(frozen["code"], AgentError::ProgramFrozen as u32);
+        assert_eq!(errors[2]["name"], "InvalidAuthority");
+        assert_eq!(errors[2]["msg"], "Invalid authority for agent");
+    }
+
+    #[test]
+    fn test_idl_json() {
+        let parsed: Value = serde_json::from_str(&idl_json()).unwrap();
+        assert_eq!(parsed["name"], IDL_NAME);
+        assert_eq!(parsed["accounts"][0]["name"], "AgentAccount");
+    }
+}
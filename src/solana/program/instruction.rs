@@ -1,61 +1,598 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
+    hash::hash,
     instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
     pubkey::Pubkey,
     system_program,
 };
+use crate::solana::program::{
+    action::{AgentAction, CpiAction},
+    capability::CapabilityFlags,
+    oracle::PriceCondition,
+    pda,
+    state::PauseReason,
+};
+use crate::validation::{Validate, Violations};
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum AgentInstruction {
-    /// Initialize a new agent
+    /// Initialize a new agent at its PDA (see `pda::find_agent_address`)
+    /// with its `AgentMetadata`, and add it to the authority's registry
     /// Accounts expected:
-    /// 0. `[writable]` Agent account
-    /// 1. `[signer]` Authority
+    /// 0. `[writable]` Agent account, PDA of `[AGENT_SEED, authority, name]`
+    /// 1. `[writable, signer]` Authority, pays for the accounts
     /// 2. `[]` System program
+    /// 3. `[writable]` Registry, PDA of `[REGISTRY_SEED, authority]`
+    /// 4. `[writable]` Metadata, PDA of `[METADATA_SEED, agent]`
     Initialize {
         name: String,
         config: AgentConfig,
     },
 
-    /// Update agent configuration
+    /// Update agent configuration, reallocating the agent account if the
+    /// new config is larger. Child agents also pass their parent (see
+    /// `AgentInstruction::with_parent`), whose capabilities bound theirs.
+    ///
+    /// Agents with an `update_delay` queue the config in a `PendingUpdate`
+    /// instead, for `CommitUpdate` to apply once the delay has passed.
     /// Accounts expected:
     /// 0. `[writable]` Agent account
-    /// 1. `[signer]` Authority
+    /// 1. `[writable, signer]` Authority, pays for any extra rent
+    /// 2. `[]` System program
+    /// 3. `[writable]` Metadata, PDA of `[METADATA_SEED, agent]`, whose
+    ///    `updated_at` is bumped; created if the agent predates metadata
+    /// 4. `[writable]` Pending update, PDA of `[PENDING_UPDATE_SEED, agent]`;
+    ///    only used by agents with an `update_delay`
     Update {
         config: AgentConfig,
     },
 
-    /// Execute agent action
+    /// Execute agent action, recording it in an `ExecutionReceipt` and the
+    /// agent's `ResultAccount`, which the first execution creates. The
+    /// agent needs the action's capabilities on top of the instruction's
+    /// (see `AgentAction::required_capabilities`).
+    ///
+    /// Child agents also pass their parent (see
+    /// `AgentInstruction::with_parent`) and can't execute while it is
+    /// paused; this applies to every execute instruction.
+    ///
+    /// A `memo` is also written with the SPL Memo program, signed by the
+    /// agent PDA, and its hash kept in the receipt.
     /// Accounts expected:
     /// 0. `[writable]` Agent account
-    /// 1. `[signer]` Authority
-    /// 2. `[writable]` Data account
+    /// 1. `[writable, signer]` Authority or executor, pays for the receipt
+    ///    and result account
+    /// 2. `[writable]` Result account, PDA of `[RESULT_SEED, agent]`
+    /// 3. `[writable]` Receipt, PDA of `[RECEIPT_SEED, agent, execution_count + 1]`
+    /// 4. `[]` System program
+    /// 5. `[]` Program config, PDA of `[CONFIG_SEED]`
+    /// 6. `[]` Accounts of the action (see `AgentInstruction::action_accounts`)
+    /// 7. `[]` SPL Memo program, with a `memo`
     Execute {
-        action_data: Vec<u8>,
+        action: AgentAction,
+        memo: Option<String>,
     },
 
-    /// Pause agent operations
+    /// Pause agent operations. With `resume_at`, the first execution from
+    /// that unix time on resumes the agent.
     /// Accounts expected:
     /// 0. `[writable]` Agent account
-    /// 1. `[signer]` Authority
-    Pause,
+    /// 1. `[signer]` Authority; writable to grow accounts of older layouts
+    /// 2. `[]` (optional) System program, to grow accounts of older layouts
+    Pause {
+        reason: PauseReason,
+        resume_at: Option<i64>,
+    },
 
-    /// Resume agent operations
+    /// Resume agent operations. The first resume, which starts an agent
+    /// that has never run, needs `MIN_AGENT_STAKE` staked (see `Stake`).
     /// Accounts expected:
     /// 0. `[writable]` Agent account
     /// 1. `[signer]` Authority
+    /// 2. `[]` Stake escrow, PDA of `[STAKE_SEED, agent]`; only read on
+    ///    the first resume
     Resume,
+
+    /// Execute agent action, rejected until the given slot and/or
+    /// unix time has been reached
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority or executor, pays for the receipt
+    ///    and result account
+    /// 2. `[writable]` Result account, PDA of `[RESULT_SEED, agent]`
+    /// 3. `[writable]` Receipt, PDA of `[RECEIPT_SEED, agent, execution_count + 1]`
+    /// 4. `[]` System program
+    /// 5. `[]` Program config, PDA of `[CONFIG_SEED]`
+    /// 6. `[]` Accounts of the action (see `AgentInstruction::action_accounts`)
+    ExecuteAfter {
+        action: AgentAction,
+        earliest_slot: Option<u64>,
+        earliest_unix_time: Option<i64>,
+    },
+
+    /// Execute agent action only if an oracle price condition still holds
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority or executor, pays for the receipt
+    ///    and result account
+    /// 2. `[writable]` Result account, PDA of `[RESULT_SEED, agent]`
+    /// 3. `[]` Price feed account, Pyth or Switchboard (see `oracle::PRICE_FEEDS`),
+    ///    owned by the oracle program the program config names
+    /// 4. `[writable]` Receipt, PDA of `[RECEIPT_SEED, agent, execution_count + 1]`
+    /// 5. `[]` System program
+    /// 6. `[]` Program config, PDA of `[CONFIG_SEED]`
+    /// 7. `[]` Accounts of the action (see `AgentInstruction::action_accounts`)
+    ExecuteConditional {
+        action: AgentAction,
+        condition: PriceCondition,
+    },
+
+    /// Write a chunk of action data into a staging account. Chunks must not
+    /// leave gaps, so an interrupted upload resumes at the staged `written`
+    /// offset; rewriting an earlier range is allowed. The staging account
+    /// is created by the first chunk and grows to fit later ones, up to the
+    /// agent's `memory_limit`.
+    /// Accounts expected:
+    /// 0. `[]` Agent account
+    /// 1. `[writable, signer]` Authority or executor, pays the staging rent
+    /// 2. `[writable]` Staging account, PDA of `[STAGING_SEED, agent, authority]`
+    /// 3. `[]` System program
+    WriteChunk {
+        offset: u32,
+        data: Vec<u8>,
+    },
+
+    /// Execute the action data assembled in a staging account, verifying its
+    /// length and SHA-256 hash, then close the staging account
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority or executor, receives the staging
+    ///    rent and pays for the receipt and result account
+    /// 2. `[writable]` Result account, PDA of `[RESULT_SEED, agent]`
+    /// 3. `[writable]` Staging account, PDA of `[STAGING_SEED, agent, authority]`
+    /// 4. `[writable]` Receipt, PDA of `[RECEIPT_SEED, agent, execution_count + 1]`
+    /// 5. `[]` System program
+    /// 6. `[]` Program config, PDA of `[CONFIG_SEED]`
+    /// 7. `[]` (optional) Accounts of the CPI action, then its program
+    FinalizeExecute {
+        total_len: u32,
+        hash: [u8; 32],
+    },
+
+    /// Close the agent, returning its rent to the authority, and remove it
+    /// from the authority's registry. A queued update is discarded, so it
+    /// can't land on an agent recreated at the same address.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority, receives the rents of the agent
+    ///    and any pending update
+    /// 2. `[writable]` Registry, PDA of `[REGISTRY_SEED, authority]`
+    /// 3. `[writable]` Pending update, PDA of `[PENDING_UPDATE_SEED, agent]`
+    Close,
+
+    /// Hand control of the agent to another wallet. Takes effect immediately
+    /// if the new authority also signs, otherwise it must `AcceptAuthority`.
+    /// Passing the current authority cancels a pending transfer. The agent
+    /// moves to the new authority's registry with the authority.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[signer]` Current authority
+    /// 2. `[writable, signer]` (optional) New authority, pays for its registry
+    /// 3. `[writable]` (with 2) Registry, PDA of `[REGISTRY_SEED, authority]`
+    /// 4. `[writable]` (with 2) New registry, PDA of `[REGISTRY_SEED, new_authority]`
+    /// 5. `[]` (with 2) System program
+    TransferAuthority {
+        new_authority: Pubkey,
+    },
+
+    /// Accept a pending authority transfer, moving the agent to the new
+    /// authority's registry
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Pending authority, pays for its registry
+    /// 2. `[writable]` Registry of the current authority, PDA of
+    ///    `[REGISTRY_SEED, authority]`
+    /// 3. `[writable]` Registry of the pending authority
+    /// 4. `[]` System program
+    AcceptAuthority,
+
+    /// Rewrite the agent account in the current layout version, growing
+    /// it if needed. Older layouts are otherwise upgraded lazily on write.
+    ///
+    /// Agents of the first release, stored at keypair addresses, are moved
+    /// into their PDA instead, and the keypair account is closed (see
+    /// `AgentInstruction::migrate_legacy`).
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority, pays for any extra rent
+    /// 2. `[]` System program
+    /// 3. `[writable]` Agent PDA, for an agent at a keypair address
+    Migrate,
+
+    /// Register, update or revoke a session key allowed to sign the
+    /// instructions in `permissions` (`DELEGATE_*` bits) until
+    /// `expiry_slot`. Zero permissions revoke the key and close its record.
+    ///
+    /// A delegate signs in place of the authority and appends its record
+    /// as the last account (see `AgentInstruction::signed_by_delegate`).
+    /// Accounts expected:
+    /// 0. `[]` Agent account
+    /// 1. `[writable, signer]` Authority, pays for the record
+    /// 2. `[writable]` Delegate record, PDA of
+    ///    `[DELEGATE_SEED, agent, authority, delegate]`
+    /// 3. `[]` System program
+    Delegate {
+        delegate: Pubkey,
+        expiry_slot: u64,
+        permissions: u32,
+    },
+
+    /// Appoint the program admin. The first call creates the program config
+    /// and must be signed by the program's upgrade authority; later calls
+    /// must be signed by the current admin.
+    /// Accounts expected:
+    /// 0. `[writable]` Program config, PDA of `[CONFIG_SEED]`
+    /// 1. `[writable, signer]` Current admin, or upgrade authority paying
+    ///    for the config
+    /// 2. `[]` System program
+    /// 3. `[]` ProgramData account of this program
+    SetAdmin {
+        new_admin: Pubkey,
+    },
+
+    /// Freeze the program, rejecting every execution until `ThawAll`
+    /// Accounts expected:
+    /// 0. `[writable]` Program config
+    /// 1. `[signer]` Admin
+    FreezeAll,
+
+    /// Lift a freeze set by `FreezeAll`
+    /// Accounts expected:
+    /// 0. `[writable]` Program config
+    /// 1. `[signer]` Admin
+    ThawAll,
+
+    /// Initialize a new agent with the config of an existing one, which
+    /// may belong to another authority
+    /// Accounts expected:
+    /// 0. `[writable]` New agent account, PDA of `[AGENT_SEED, authority, name]`
+    /// 1. `[writable, signer]` Authority, pays for the accounts
+    /// 2. `[]` System program
+    /// 3. `[writable]` Registry, PDA of `[REGISTRY_SEED, authority]`
+    /// 4. `[]` Source agent account
+    /// 5. `[writable]` Metadata of the new agent, PDA of `[METADATA_SEED, agent]`
+    CloneAgent {
+        name: String,
+    },
+
+    /// Allow `executor` to sign execute instructions for the agent, and
+    /// nothing else. Executors can't run actions moving the agent's funds
+    /// (see `AgentAction::moves_funds`). Adding a listed executor does
+    /// nothing.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority, pays for any extra rent
+    /// 2. `[]` System program
+    AddExecutor {
+        executor: Pubkey,
+    },
+
+    /// Remove `executor` from the agent's executors. Removing a key that
+    /// is not listed does nothing.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[signer]` Authority
+    RemoveExecutor {
+        executor: Pubkey,
+    },
+
+    /// Initialize a child agent of an existing agent, recording it as the
+    /// child's `parent`. The child may only hold capabilities its parent
+    /// has, and can't execute while the parent is paused.
+    /// Accounts expected:
+    /// 0. `[writable]` Child agent account, PDA of `[AGENT_SEED, authority, name]`
+    /// 1. `[writable, signer]` Authority of the parent, pays for the accounts
+    /// 2. `[]` System program
+    /// 3. `[writable]` Registry, PDA of `[REGISTRY_SEED, authority]`
+    /// 4. `[]` Parent agent account
+    /// 5. `[writable]` Metadata of the child, PDA of `[METADATA_SEED, agent]`
+    SpawnChild {
+        name: String,
+        config: AgentConfig,
+    },
+
+    /// Lock lamports in the agent's stake escrow, created on the first
+    /// stake, which must come from the agent's authority. Later stakes top
+    /// it up from any payer; the escrow is returned to its staker.
+    /// Accounts expected:
+    /// 0. `[]` Agent account
+    /// 1. `[writable, signer]` Staker, pays for the escrow
+    /// 2. `[writable]` Stake escrow, PDA of `[STAKE_SEED, agent]`
+    /// 3. `[]` System program
+    Stake {
+        lamports: u64,
+    },
+
+    /// Return the stake and the escrow's rent to the staker, once the agent
+    /// has been closed
+    /// Accounts expected:
+    /// 0. `[]` Agent account, closed (or terminated earlier in the transaction)
+    /// 1. `[writable, signer]` Staker
+    /// 2. `[writable]` Stake escrow, PDA of `[STAKE_SEED, agent]`
+    Unstake,
+
+    /// Apply the pending update once its `activation_slot` is reached,
+    /// closing the pending update account
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority, receives the pending update's rent
+    /// 2. `[]` System program
+    /// 3. `[writable]` Metadata, PDA of `[METADATA_SEED, agent]`
+    /// 4. `[writable]` Pending update, PDA of `[PENDING_UPDATE_SEED, agent]`
+    CommitUpdate,
+
+    /// Discard the pending update, closing its account
+    /// Accounts expected:
+    /// 0. `[]` Agent account
+    /// 1. `[writable, signer]` Authority, receives the pending update's rent
+    /// 2. `[writable]` Pending update, PDA of `[PENDING_UPDATE_SEED, agent]`
+    CancelUpdate,
+
+    /// Set the number of slots config updates wait before they can be
+    /// committed. Raising the delay applies at once; lowering it is queued
+    /// like an `Update` and waits out the current delay.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority, pays for the pending update and
+    ///    for growing accounts of older layouts
+    /// 2. `[]` System program
+    /// 3. `[writable]` Pending update, PDA of `[PENDING_UPDATE_SEED, agent]`
+    SetUpdateDelay {
+        slots: u64,
+    },
+
+    /// Answer an automation thread with the `CrankExecute` running
+    /// `action` as the agent's next execution, in the thread's return data
+    /// (see `automation`). Requires the `clockwork` feature.
+    /// Accounts expected:
+    /// 0. `[]` Agent account
+    /// 1. `[signer]` Thread, an executor of the agent
+    Crank {
+        action: AgentAction,
+    },
+
+    /// Execute agent action signed by an executor, with a separate payer
+    /// funding the receipt and result account
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Payer, recorded as the receipt's signer
+    /// 2. `[writable]` Result account, PDA of `[RESULT_SEED, agent]`
+    /// 3. `[writable]` Receipt, PDA of `[RECEIPT_SEED, agent, execution_count + 1]`
+    /// 4. `[]` System program
+    /// 5. `[]` Program config, PDA of `[CONFIG_SEED]`
+    /// 6. `[signer]` Executor
+    /// 7. `[]` Accounts of the action (see `AgentInstruction::action_accounts`)
+    CrankExecute {
+        action: AgentAction,
+    },
+
+    /// Offer an agent for `price` lamports, escrowing it in a listing that
+    /// becomes its authority. Paused and terminated agents can't be listed.
+    /// The agent's executors are cleared and its delegate records lapse, so
+    /// it can't be run while listed.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Authority, pays for the listing
+    /// 2. `[]` System program
+    /// 3. `[writable]` Listing, PDA of `[LISTING_SEED, agent]`
+    ListForSale {
+        price: u64,
+    },
+
+    /// Buy a listed agent: pay the seller and become the agent's authority.
+    /// `price` must match the listing, so a relisted agent isn't bought at
+    /// a price the buyer never saw. An update the seller queued is
+    /// cancelled, and the agent moves from the seller's registry to the
+    /// buyer's. The buyer also takes over the stake escrow, if any, paying
+    /// its staker the escrow's balance on top of the price.
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Buyer
+    /// 2. `[writable]` Seller, receives the price and the rents of the
+    ///    listing and any pending update
+    /// 3. `[writable]` Listing, PDA of `[LISTING_SEED, agent]`
+    /// 4. `[]` System program
+    /// 5. `[writable]` Pending update, PDA of `[PENDING_UPDATE_SEED, agent]`
+    /// 6. `[writable]` Seller's registry, PDA of `[REGISTRY_SEED, seller]`
+    /// 7. `[writable]` Buyer's registry, PDA of `[REGISTRY_SEED, buyer]`
+    /// 8. `[writable]` Stake escrow, PDA of `[STAKE_SEED, agent]`
+    /// 9. `[writable]` Staker recorded in the escrow, usually the seller
+    Purchase {
+        price: u64,
+    },
+
+    /// Withdraw a listing, returning the agent to the seller
+    /// Accounts expected:
+    /// 0. `[writable]` Agent account
+    /// 1. `[writable, signer]` Seller, receives the listing's rent
+    /// 2. `[writable]` Listing, PDA of `[LISTING_SEED, agent]`
+    CancelListing,
+
+    /// Set the oracle programs that must own the price feeds read by
+    /// conditional executions, e.g. on devnet
+    /// Accounts expected:
+    /// 0. `[writable]` Program config
+    /// 1. `[signer]` Admin
+    SetOraclePrograms {
+        pyth_program: Pubkey,
+        switchboard_program: Pubkey,
+    },
+
+    /// Close an execution receipt, returning its rent to the authority; the
+    /// receipts of a closed agent must be closed before an agent recreated
+    /// at the same address executes again
+    /// Accounts expected:
+    /// 0. `[]` Agent account
+    /// 1. `[writable, signer]` Authority
+    /// 2. `[writable]` Receipt, PDA of `[RECEIPT_SEED, agent, execution]`
+    CloseReceipt { execution: u64 },
 }
 
+/// Largest chunk that comfortably fits a transaction alongside its accounts
+pub const MAX_CHUNK_SIZE: usize = 900;
+
+/// Maximum number of programs an agent may invoke
+pub const MAX_ALLOWED_PROGRAMS: usize = 8;
+
+/// Anchor instruction discriminators, `sha256("global:<name>")[..8]`,
+/// indexed by variant
+///
+/// With the `anchor-compat` feature these replace the leading Borsh
+/// variant byte, so Anchor-based indexers can decode instructions with the
+/// IDL from `idl::idl`.
+pub const ANCHOR_DISCRIMINATORS: [(&str, [u8; 8]); 33] = [
+    ("initialize", [175, 175, 109, 31, 13, 152, 155, 237]),
+    ("update", [219, 200, 88, 176, 158, 63, 253, 127]),
+    ("execute", [130, 221, 242, 154, 13, 193, 189, 29]),
+    ("pause", [211, 22, 221, 251, 74, 121, 193, 47]),
+    ("resume", [1, 166, 51, 170, 127, 32, 141, 206]),
+    ("execute_after", [52, 115, 152, 121, 77, 24, 185, 216]),
+    ("execute_conditional", [245, 72, 152, 227, 125, 152, 224, 116]),
+    ("write_chunk", [93, 141, 167, 15, 209, 133, 137, 51]),
+    ("finalize_execute", [132, 210, 96, 226, 12, 142, 165, 156]),
+    ("close", [98, 165, 201, 177, 108, 65, 206, 96]),
+    ("transfer_authority", [48, 169, 76, 72, 229, 180, 55, 161]),
+    ("accept_authority", [107, 86, 198, 91, 33, 12, 107, 160]),
+    ("migrate", [155, 234, 231, 146, 236, 158, 162, 30]),
+    ("delegate", [90, 147, 75, 178, 85, 88, 4, 137]),
+    ("set_admin", [251, 163, 0, 52, 91, 194, 187, 92]),
+    ("freeze_all", [187, 113, 158, 131, 118, 61, 214, 69]),
+    ("thaw_all", [63, 234, 129, 97, 17, 238, 181, 171]),
+    ("clone_agent", [138, 201, 253, 75, 134, 10, 70, 75]),
+    ("add_executor", [195, 90, 42, 209, 244, 246, 76, 18]),
+    ("remove_executor", [220, 155, 16, 109, 21, 139, 129, 190]),
+    ("spawn_child", [57, 254, 127, 116, 244, 20, 212, 84]),
+    ("stake", [206, 176, 202, 18, 200, 209, 179, 108]),
+    ("unstake", [90, 95, 107, 42, 205, 124, 50, 225]),
+    ("commit_update", [51, 234, 145, 208, 211, 247, 251, 182]),
+    ("cancel_update", [76, 229, 99, 195, 126, 138, 68, 247]),
+    ("set_update_delay", [68, 164, 2, 211, 136, 86, 233, 26]),
+    ("crank", [0, 232, 3, 195, 124, 117, 105, 53]),
+    ("crank_execute", [180, 189, 246, 227, 111, 114, 40, 46]),
+    ("list_for_sale", [188, 214, 1, 112, 93, 215, 124, 207]),
+    ("purchase", [21, 93, 113, 154, 193, 160, 242, 168]),
+    ("cancel_listing", [41, 183, 50, 232, 230, 233, 157, 70]),
+    ("set_oracle_programs", [147, 61, 75, 11, 175, 230, 200, 118]),
+    ("close_receipt", [126, 254, 244, 203, 124, 164, 134, 89]),
+];
+
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct AgentConfig {
     pub autonomous_mode: bool,
     pub execution_limit: u64,
     pub memory_limit: u64,
-    pub capabilities: Vec<String>,
+    pub capabilities: CapabilityFlags,
+    /// Minimum number of seconds between two executions; 0 disables the limit
+    pub min_execution_interval: u32,
+    /// Programs the agent may invoke through CPI actions (see `action`)
+    pub allowed_programs: Vec<Pubkey>,
+}
+
+impl Validate for AgentConfig {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            self.execution_limit > 0,
+            "execution_limit",
+            "must be greater than 0",
+            "allow at least 1 execution, e.g. 1000",
+        );
+        violations.check(
+            self.memory_limit > 0,
+            "memory_limit",
+            "must be greater than 0",
+            "set memory_limit in bytes, e.g. 5000",
+        );
+        violations.check(
+            self.capabilities.unknown().is_empty(),
+            "capabilities",
+            "must only contain known capability flags",
+            "build capabilities from CapabilityFlags constants or names",
+        );
+        violations.check(
+            self.allowed_programs.len() <= MAX_ALLOWED_PROGRAMS,
+            "allowed_programs",
+            "must not contain more than MAX_ALLOWED_PROGRAMS programs",
+            "only allow the programs the agent actually calls",
+        );
+    }
 }
 
 impl AgentInstruction {
+    /// Encode the instruction: Borsh, or Anchor style with the
+    /// `anchor-compat` feature
+    pub fn pack(&self) -> Vec<u8> {
+        if cfg!(feature = "anchor-compat") {
+            self.pack_anchor()
+        } else {
+            borsh::to_vec(self).unwrap_or_default()
+        }
+    }
+
+    /// Decode instruction data in the encoding selected by `pack`
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if cfg!(feature = "anchor-compat") {
+            Self::unpack_anchor(data)
+        } else {
+            Self::try_from_slice(data).map_err(|_| ProgramError::InvalidInstructionData)
+        }
+    }
+
+    /// Anchor discriminator followed by the Borsh-encoded fields
+    pub fn pack_anchor(&self) -> Vec<u8> {
+        let data = borsh::to_vec(self).unwrap_or_default();
+        match data.split_first() {
+            Some((variant, fields)) => {
+                let mut packed = ANCHOR_DISCRIMINATORS[*variant as usize].1.to_vec();
+                packed.extend_from_slice(fields);
+                packed
+            }
+            None => data,
+        }
+    }
+
+    /// Decode data produced by `pack_anchor`
+    pub fn unpack_anchor(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < 8 {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        let (discriminator, fields) = data.split_at(8);
+        let variant = ANCHOR_DISCRIMINATORS
+            .iter()
+            .position(|(_, known)| known[..] == *discriminator)
+            .ok_or(ProgramError::InvalidInstructionData)?;
+
+        let mut borsh_data = vec![variant as u8];
+        borsh_data.extend_from_slice(fields);
+        Self::try_from_slice(&borsh_data).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+
+    /// Capabilities an agent must hold for this instruction to run
+    pub fn required_capabilities(&self) -> CapabilityFlags {
+        match self {
+            AgentInstruction::Execute { .. }
+            | AgentInstruction::ExecuteAfter { .. }
+            | AgentInstruction::CrankExecute { .. } => CapabilityFlags::COMPUTE,
+            AgentInstruction::ExecuteConditional { .. } => {
+                CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE
+            }
+            AgentInstruction::WriteChunk { .. } | AgentInstruction::FinalizeExecute { .. } => {
+                CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE
+            }
+            _ => CapabilityFlags::NONE,
+        }
+    }
+
     pub fn initialize(
         program_id: &Pubkey,
         agent_account: &Pubkey,
@@ -63,78 +600,1156 @@ impl AgentInstruction {
         name: String,
         config: AgentConfig,
     ) -> Instruction {
+        let (registry, _) = pda::find_registry_address(program_id, authority);
+        let (metadata, _) = pda::find_metadata_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(registry, false),
+            AccountMeta::new(metadata, false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::Initialize { name, config }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn pause(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        reason: PauseReason,
+        resume_at: Option<i64>,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::Pause { reason, resume_at }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn resume(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey) -> Instruction {
+        let (stake, _) = pda::find_stake_address(program_id, agent_account);
         let accounts = vec![
             AccountMeta::new(*agent_account, false),
             AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new_readonly(stake, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Resume.pack(), accounts)
+    }
+
+    /// `initialize` copying the config of `source_agent`
+    pub fn clone_agent(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        source_agent: &Pubkey,
+        name: String,
+    ) -> Instruction {
+        let (registry, _) = pda::find_registry_address(program_id, authority);
+        let (metadata, _) = pda::find_metadata_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
             AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(registry, false),
+            AccountMeta::new_readonly(*source_agent, false),
+            AccountMeta::new(metadata, false),
         ];
 
-        Instruction::new_with_borsh(
+        Instruction::new_with_bytes(
             *program_id,
-            &AgentInstruction::Initialize { name, config },
+            &AgentInstruction::CloneAgent { name }.pack(),
             accounts,
         )
     }
 
-    pub fn update(
+    /// `initialize` for a child of `parent_agent`
+    pub fn spawn_child(
         program_id: &Pubkey,
         agent_account: &Pubkey,
         authority: &Pubkey,
+        parent_agent: &Pubkey,
+        name: String,
         config: AgentConfig,
     ) -> Instruction {
+        let (registry, _) = pda::find_registry_address(program_id, authority);
+        let (metadata, _) = pda::find_metadata_address(program_id, agent_account);
         let accounts = vec![
             AccountMeta::new(*agent_account, false),
-            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(registry, false),
+            AccountMeta::new_readonly(*parent_agent, false),
+            AccountMeta::new(metadata, false),
         ];
 
-        Instruction::new_with_borsh(
+        Instruction::new_with_bytes(
             *program_id,
-            &AgentInstruction::Update { config },
+            &AgentInstruction::SpawnChild { name, config }.pack(),
             accounts,
         )
     }
 
-    pub fn execute(
+    pub fn add_executor(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        executor: &Pubkey,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::AddExecutor { executor: *executor }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn remove_executor(
         program_id: &Pubkey,
         agent_account: &Pubkey,
         authority: &Pubkey,
-        data_account: &Pubkey,
-        action_data: Vec<u8>,
+        executor: &Pubkey,
     ) -> Instruction {
         let accounts = vec![
             AccountMeta::new(*agent_account, false),
             AccountMeta::new_readonly(*authority, true),
-            AccountMeta::new(*data_account, false),
         ];
 
-        Instruction::new_with_borsh(
+        Instruction::new_with_bytes(
             *program_id,
-            &AgentInstruction::Execute { action_data },
+            &AgentInstruction::RemoveExecutor { executor: *executor }.pack(),
             accounts,
         )
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    pub fn stake(program_id: &Pubkey, agent_account: &Pubkey, staker: &Pubkey, lamports: u64) -> Instruction {
+        let (stake, _) = pda::find_stake_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new(*staker, true),
+            AccountMeta::new(stake, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
 
-    #[test]
-    fn test_instruction_serialization() {
-        let config = AgentConfig {
-            autonomous_mode: true,
-            execution_limit: 1000,
-            memory_limit: 5000,
-            capabilities: vec!["compute".to_string()],
-        };
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Stake { lamports }.pack(), accounts)
+    }
 
-        let instruction = AgentInstruction::Initialize {
-            name: "test_agent".to_string(),
-            config: config.clone(),
-        };
+    pub fn list_for_sale(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey, price: u64) -> Instruction {
+        let (listing, _) = pda::find_listing_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(listing, false),
+        ];
 
-        let serialized = borsh::to_vec(&instruction).unwrap();
-        let deserialized = AgentInstruction::try_from_slice(&serialized).unwrap();
-        assert_eq!(instruction, deserialized);
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::ListForSale { price }.pack(), accounts)
+    }
+
+    /// `staker` is the one recorded in the agent's stake escrow, refunded by
+    /// the buyer; any account will do if nothing is staked
+    pub fn purchase(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        buyer: &Pubkey,
+        seller: &Pubkey,
+        staker: &Pubkey,
+        price: u64,
+    ) -> Instruction {
+        let (listing, _) = pda::find_listing_address(program_id, agent_account);
+        let (pending, _) = pda::find_pending_update_address(program_id, agent_account);
+        let (seller_registry, _) = pda::find_registry_address(program_id, seller);
+        let (buyer_registry, _) = pda::find_registry_address(program_id, buyer);
+        let (stake, _) = pda::find_stake_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*buyer, true),
+            AccountMeta::new(*seller, false),
+            AccountMeta::new(listing, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(pending, false),
+            AccountMeta::new(seller_registry, false),
+            AccountMeta::new(buyer_registry, false),
+            AccountMeta::new(stake, false),
+            AccountMeta::new(*staker, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Purchase { price }.pack(), accounts)
+    }
+
+    pub fn cancel_listing(program_id: &Pubkey, agent_account: &Pubkey, seller: &Pubkey) -> Instruction {
+        let (listing, _) = pda::find_listing_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*seller, true),
+            AccountMeta::new(listing, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::CancelListing.pack(), accounts)
+    }
+
+    pub fn close_receipt(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        execution: u64,
+    ) -> Instruction {
+        let (receipt, _) = pda::find_receipt_address(program_id, agent_account, execution);
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(receipt, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::CloseReceipt { execution }.pack(), accounts)
+    }
+
+    pub fn unstake(program_id: &Pubkey, agent_account: &Pubkey, staker: &Pubkey) -> Instruction {
+        let (stake, _) = pda::find_stake_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new(*staker, true),
+            AccountMeta::new(stake, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Unstake.pack(), accounts)
+    }
+
+    pub fn update(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        config: AgentConfig,
+    ) -> Instruction {
+        let (metadata, _) = pda::find_metadata_address(program_id, agent_account);
+        let (pending_update, _) = pda::find_pending_update_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(metadata, false),
+            AccountMeta::new(pending_update, false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::Update { config }.pack(),
+            accounts,
+        )
     }
-}
\ No newline at end of file
+
+    pub fn commit_update(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey) -> Instruction {
+        let (metadata, _) = pda::find_metadata_address(program_id, agent_account);
+        let (pending_update, _) = pda::find_pending_update_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(metadata, false),
+            AccountMeta::new(pending_update, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::CommitUpdate.pack(), accounts)
+    }
+
+    pub fn cancel_update(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey) -> Instruction {
+        let (pending_update, _) = pda::find_pending_update_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(pending_update, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::CancelUpdate.pack(), accounts)
+    }
+
+    pub fn set_update_delay(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey, slots: u64) -> Instruction {
+        let (pending_update, _) = pda::find_pending_update_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(pending_update, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::SetUpdateDelay { slots }.pack(), accounts)
+    }
+
+    /// `execution` is the number the execution will get, the agent's
+    /// `execution_count + 1`; it locates the receipt account
+    pub fn execute(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        action: AgentAction,
+    ) -> Instruction {
+        Self::execute_with_memo(program_id, agent_account, authority, result_account, execution, action, None)
+    }
+
+    /// `execute` attaching `memo` to the transaction
+    pub fn execute_with_memo(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        action: AgentAction,
+        memo: Option<String>,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*result_account, false),
+        ];
+        accounts.extend(Self::receipt_accounts(program_id, agent_account, execution));
+        accounts.extend(Self::action_accounts(program_id, agent_account, &action));
+        if memo.is_some() {
+            accounts.push(AccountMeta::new_readonly(spl_memo::id(), false));
+        }
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::Execute { action, memo }.pack(),
+            accounts,
+        )
+    }
+
+    /// `execute` signed by `executor` and paid for by `payer`; `execution`
+    /// is the agent's `execution_count + 1`
+    pub fn crank_execute(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        payer: &Pubkey,
+        executor: &Pubkey,
+        execution: u64,
+        action: AgentAction,
+    ) -> Instruction {
+        let (result_account, _) = pda::find_result_address(program_id, agent_account);
+        let mut accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(result_account, false),
+        ];
+        accounts.extend(Self::receipt_accounts(program_id, agent_account, execution));
+        accounts.push(AccountMeta::new_readonly(*executor, true));
+        accounts.extend(Self::action_accounts(program_id, agent_account, &action));
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::CrankExecute { action }.pack(),
+            accounts,
+        )
+    }
+
+    /// `Crank` for an automation thread running `action` (see `automation`)
+    pub fn crank(program_id: &Pubkey, agent_account: &Pubkey, thread: &Pubkey, action: AgentAction) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new_readonly(*thread, true),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Crank { action }.pack(), accounts)
+    }
+
+    /// `execute` with a `CustomCpi` action invoking `cpi`. The agent PDA
+    /// signs the CPI, so `cpi` may list it as signer.
+    pub fn execute_cpi(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        cpi: &Instruction,
+    ) -> Instruction {
+        let action = AgentAction::CustomCpi(CpiAction::from_instruction(cpi));
+        Self::execute(program_id, agent_account, authority, result_account, execution, action)
+    }
+
+    /// Accounts to append to an execute instruction running `action`
+    pub fn action_accounts(program_id: &Pubkey, agent_account: &Pubkey, action: &AgentAction) -> Vec<AccountMeta> {
+        match action {
+            AgentAction::Noop => Vec::new(),
+            AgentAction::Transfer { destination, .. } => {
+                let (vault, _) = pda::find_vault_address(program_id, agent_account);
+                vec![AccountMeta::new(vault, false), AccountMeta::new(*destination, false)]
+            }
+            AgentAction::Memo { .. } => vec![AccountMeta::new_readonly(spl_memo::id(), false)],
+            AgentAction::Swap { cpi, .. } | AgentAction::CustomCpi(cpi) => {
+                Self::cpi_accounts(agent_account, &cpi.instruction())
+            }
+        }
+    }
+
+    /// Accounts to append to an execute instruction whose action invokes
+    /// `cpi`: its accounts other than the agent, then its program
+    pub fn cpi_accounts(agent_account: &Pubkey, cpi: &Instruction) -> Vec<AccountMeta> {
+        cpi.accounts
+            .iter()
+            .filter(|meta| meta.pubkey != *agent_account)
+            .cloned()
+            .chain(std::iter::once(AccountMeta::new_readonly(cpi.program_id, false)))
+            .collect()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn execute_after(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        action: AgentAction,
+        earliest_slot: Option<u64>,
+        earliest_unix_time: Option<i64>,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*result_account, false),
+        ];
+        accounts.extend(Self::receipt_accounts(program_id, agent_account, execution));
+        accounts.extend(Self::action_accounts(program_id, agent_account, &action));
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::ExecuteAfter {
+                action,
+                earliest_slot,
+                earliest_unix_time,
+            }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn execute_conditional(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        action: AgentAction,
+        condition: PriceCondition,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*result_account, false),
+            AccountMeta::new_readonly(condition.feed, false),
+        ];
+        accounts.extend(Self::receipt_accounts(program_id, agent_account, execution));
+        accounts.extend(Self::action_accounts(program_id, agent_account, &action));
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::ExecuteConditional { action, condition }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn write_chunk(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        offset: u32,
+        data: Vec<u8>,
+    ) -> Instruction {
+        let (staging_account, _) = pda::find_staging_address(program_id, agent_account, authority);
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(staging_account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::WriteChunk { offset, data }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn finalize_execute(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        total_len: u32,
+        hash: [u8; 32],
+    ) -> Instruction {
+        let (staging_account, _) = pda::find_staging_address(program_id, agent_account, authority);
+        let mut accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(*result_account, false),
+            AccountMeta::new(staging_account, false),
+        ];
+        accounts.extend(Self::receipt_accounts(program_id, agent_account, execution));
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::FinalizeExecute { total_len, hash }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn close(program_id: &Pubkey, agent_account: &Pubkey, authority: &Pubkey) -> Instruction {
+        let (registry, _) = pda::find_registry_address(program_id, authority);
+        let (pending, _) = pda::find_pending_update_address(program_id, agent_account);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(registry, false),
+            AccountMeta::new(pending, false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Close.pack(), accounts)
+    }
+
+    pub fn transfer_authority(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        new_authority: &Pubkey,
+        new_authority_signs: bool,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new_readonly(*authority, true),
+        ];
+        if new_authority_signs {
+            let (registry, _) = pda::find_registry_address(program_id, authority);
+            let (new_registry, _) = pda::find_registry_address(program_id, new_authority);
+            accounts.extend([
+                AccountMeta::new(*new_authority, true),
+                AccountMeta::new(registry, false),
+                AccountMeta::new(new_registry, false),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ]);
+        }
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::TransferAuthority { new_authority: *new_authority }.pack(),
+            accounts,
+        )
+    }
+
+    /// `authority` is the agent's current authority, whose registry the
+    /// agent leaves
+    pub fn accept_authority(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        new_authority: &Pubkey,
+    ) -> Instruction {
+        let (registry, _) = pda::find_registry_address(program_id, authority);
+        let (new_registry, _) = pda::find_registry_address(program_id, new_authority);
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*new_authority, true),
+            AccountMeta::new(registry, false),
+            AccountMeta::new(new_registry, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::AcceptAuthority.pack(), accounts)
+    }
+
+    pub fn migrate(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &AgentInstruction::Migrate.pack(), accounts)
+    }
+
+    /// `migrate` the agent named `name` from the keypair account
+    /// `legacy_account` into its PDA
+    pub fn migrate_legacy(
+        program_id: &Pubkey,
+        legacy_account: &Pubkey,
+        authority: &Pubkey,
+        name: &str,
+    ) -> Instruction {
+        let (agent_account, _) = pda::find_agent_address(program_id, authority, name);
+        let mut instruction = Self::migrate(program_id, legacy_account, authority);
+        instruction.accounts.push(AccountMeta::new(agent_account, false));
+        instruction
+    }
+
+    pub fn delegate(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        delegate: &Pubkey,
+        expiry_slot: u64,
+        permissions: u32,
+    ) -> Instruction {
+        let (record, _) = pda::find_delegate_address(program_id, agent_account, authority, delegate);
+        let accounts = vec![
+            AccountMeta::new_readonly(*agent_account, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(record, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::Delegate {
+                delegate: *delegate,
+                expiry_slot,
+                permissions,
+            }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn set_admin(program_id: &Pubkey, admin: &Pubkey, new_admin: &Pubkey) -> Instruction {
+        let (config, _) = pda::find_config_address(program_id);
+        let accounts = vec![
+            AccountMeta::new(config, false),
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(pda::find_program_data_address(program_id), false),
+        ];
+
+        Instruction::new_with_bytes(
+            *program_id,
+            &AgentInstruction::SetAdmin { new_admin: *new_admin }.pack(),
+            accounts,
+        )
+    }
+
+    pub fn freeze_all(program_id: &Pubkey, admin: &Pubkey) -> Instruction {
+        Self::admin_instruction(program_id, admin, AgentInstruction::FreezeAll)
+    }
+
+    pub fn thaw_all(program_id: &Pubkey, admin: &Pubkey) -> Instruction {
+        Self::admin_instruction(program_id, admin, AgentInstruction::ThawAll)
+    }
+
+    pub fn set_oracle_programs(
+        program_id: &Pubkey,
+        admin: &Pubkey,
+        pyth_program: &Pubkey,
+        switchboard_program: &Pubkey,
+    ) -> Instruction {
+        let instruction = AgentInstruction::SetOraclePrograms {
+            pyth_program: *pyth_program,
+            switchboard_program: *switchboard_program,
+        };
+        Self::admin_instruction(program_id, admin, instruction)
+    }
+
+    fn admin_instruction(program_id: &Pubkey, admin: &Pubkey, instruction: AgentInstruction) -> Instruction {
+        let (config, _) = pda::find_config_address(program_id);
+        let accounts = vec![
+            AccountMeta::new(config, false),
+            AccountMeta::new_readonly(*admin, true),
+        ];
+
+        Instruction::new_with_bytes(*program_id, &instruction.pack(), accounts)
+    }
+
+    pub fn revoke_delegate(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        delegate: &Pubkey,
+    ) -> Instruction {
+        Self::delegate(program_id, agent_account, authority, delegate, 0, 0)
+    }
+
+    /// Pass the parent of a child agent to an update or execute instruction
+    /// built for the child. Call before `signed_by_delegate`, whose record
+    /// must stay last.
+    pub fn with_parent(mut instruction: Instruction, parent_agent: &Pubkey) -> Instruction {
+        instruction.accounts.push(AccountMeta::new_readonly(*parent_agent, false));
+        instruction
+    }
+
+    /// Turn an instruction built with `delegate` as its authority into one
+    /// the program accepts from that session key, by appending the record
+    /// the agent's `authority` issued it
+    pub fn signed_by_delegate(
+        mut instruction: Instruction,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        delegate: &Pubkey,
+    ) -> Instruction {
+        let (record, _) = pda::find_delegate_address(&instruction.program_id, agent_account, authority, delegate);
+        instruction.accounts.push(AccountMeta::new_readonly(record, false));
+        instruction
+    }
+
+    /// Split the encoded `action` into `WriteChunk` instructions followed by
+    /// a `FinalizeExecute`, one instruction per transaction.
+    ///
+    /// Pass the staging account's `written` offset as `resume_from` to skip
+    /// chunks that already landed. The staging account is the PDA of the
+    /// agent and `authority` (see `pda::find_staging_address`); the first
+    /// chunk creates it.
+    pub fn chunked_execute(
+        program_id: &Pubkey,
+        agent_account: &Pubkey,
+        authority: &Pubkey,
+        result_account: &Pubkey,
+        execution: u64,
+        action: &AgentAction,
+        resume_from: usize,
+    ) -> Vec<Instruction> {
+        let action_data = borsh::to_vec(action).unwrap_or_default();
+        let mut instructions: Vec<Instruction> = action_data
+            .chunks(MAX_CHUNK_SIZE)
+            .enumerate()
+            .map(|(i, chunk)| (i * MAX_CHUNK_SIZE, chunk))
+            .filter(|(offset, chunk)| offset + chunk.len() > resume_from)
+            .map(|(offset, chunk)| {
+                Self::write_chunk(
+                    program_id,
+                    agent_account,
+                    authority,
+                    offset as u32,
+                    chunk.to_vec(),
+                )
+            })
+            .collect();
+
+        let mut finalize = Self::finalize_execute(
+            program_id,
+            agent_account,
+            authority,
+            result_account,
+            execution,
+            action_data.len() as u32,
+            hash(&action_data).to_bytes(),
+        );
+        finalize.accounts.extend(Self::action_accounts(program_id, agent_account, action));
+        instructions.push(finalize);
+        instructions
+    }
+
+    /// Receipt, system program and program config accounts of the execute
+    /// instructions
+    fn receipt_accounts(program_id: &Pubkey, agent_account: &Pubkey, execution: u64) -> [AccountMeta; 3] {
+        let (receipt, _) = pda::find_receipt_address(program_id, agent_account, execution);
+        let (config, _) = pda::find_config_address(program_id);
+        [
+            AccountMeta::new(receipt, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(config, false),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_serialization() {
+        let config = AgentConfig {
+            autonomous_mode: true,
+            execution_limit: 1000,
+            memory_limit: 5000,
+            capabilities: CapabilityFlags::COMPUTE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+
+        let instruction = AgentInstruction::Initialize {
+            name: "test_agent".to_string(),
+            config: config.clone(),
+        };
+
+        let serialized = instruction.pack();
+        let deserialized = AgentInstruction::unpack(&serialized).unwrap();
+        assert_eq!(instruction, deserialized);
+    }
+
+    #[test]
+    fn test_lifecycle_instructions() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority, new_authority) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        let pause = AgentInstruction::pause(&program_id, &agent, &authority, PauseReason::RiskLimit, Some(1_700_000_000));
+        assert_eq!(
+            pause.accounts,
+            vec![
+                AccountMeta::new(agent, false),
+                AccountMeta::new(authority, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ]
+        );
+        assert_eq!(
+            AgentInstruction::unpack(&pause.data).unwrap(),
+            AgentInstruction::Pause { reason: PauseReason::RiskLimit, resume_at: Some(1_700_000_000) }
+        );
+
+        let resume = AgentInstruction::resume(&program_id, &agent, &authority);
+        assert_eq!(resume.accounts[..2], [AccountMeta::new(agent, false), AccountMeta::new_readonly(authority, true)]);
+        assert_eq!(AgentInstruction::unpack(&resume.data).unwrap(), AgentInstruction::Resume);
+
+        // The registry to unlist the agent from is the authority's
+        let close = AgentInstruction::close(&program_id, &agent, &authority);
+        let (registry, _) = pda::find_registry_address(&program_id, &authority);
+        let (pending, _) = pda::find_pending_update_address(&program_id, &agent);
+        assert_eq!(
+            close.accounts,
+            vec![
+                AccountMeta::new(agent, false),
+                AccountMeta::new(authority, true),
+                AccountMeta::new(registry, false),
+                AccountMeta::new(pending, false),
+            ]
+        );
+        assert_eq!(AgentInstruction::unpack(&close.data).unwrap(), AgentInstruction::Close);
+
+        // Only transfers taking effect move the agent between registries
+        let (new_registry, _) = pda::find_registry_address(&program_id, &new_authority);
+        let transfer = AgentInstruction::transfer_authority(&program_id, &agent, &authority, &new_authority, false);
+        assert_eq!(transfer.accounts.len(), 2);
+        let transfer = AgentInstruction::transfer_authority(&program_id, &agent, &authority, &new_authority, true);
+        assert_eq!(transfer.accounts[2], AccountMeta::new(new_authority, true));
+        assert_eq!((transfer.accounts[3].pubkey, transfer.accounts[4].pubkey), (registry, new_registry));
+        let accept = AgentInstruction::accept_authority(&program_id, &agent, &authority, &new_authority);
+        assert_eq!(accept.accounts[1], AccountMeta::new(new_authority, true));
+        assert_eq!((accept.accounts[2].pubkey, accept.accounts[3].pubkey), (registry, new_registry));
+        assert!([&pause, &resume, &close].iter().all(|instruction| instruction.program_id == program_id));
+    }
+
+    #[test]
+    fn test_anchor_discriminators() {
+        for (name, discriminator) in ANCHOR_DISCRIMINATORS {
+            let expected = &hash(format!("global:{}", name).as_bytes()).to_bytes()[..8];
+            assert_eq!(&discriminator[..], expected, "{}", name);
+        }
+
+        // Discriminators are indexed by variant
+        assert_eq!(AgentInstruction::Resume.pack_anchor(), ANCHOR_DISCRIMINATORS[4].1);
+        assert_eq!(AgentInstruction::ThawAll.pack_anchor(), ANCHOR_DISCRIMINATORS[16].1);
+
+        let instruction = AgentInstruction::WriteChunk { offset: 7, data: vec![1, 2] };
+        let packed = instruction.pack_anchor();
+        assert_eq!(&packed[..8], &ANCHOR_DISCRIMINATORS[7].1);
+        assert_eq!(&packed[8..], &borsh::to_vec(&instruction).unwrap()[1..]);
+        assert_eq!(AgentInstruction::unpack_anchor(&packed).unwrap(), instruction);
+
+        assert!(AgentInstruction::unpack_anchor(&[0; 8]).is_err());
+        assert!(AgentInstruction::unpack_anchor(&packed[..7]).is_err());
+    }
+
+    #[test]
+    fn test_chunked_execute() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority, data) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let action = AgentAction::CustomCpi(CpiAction {
+            program_id: Pubkey::new_unique(),
+            accounts: Vec::new(),
+            data: vec![7u8; MAX_CHUNK_SIZE * 2],
+        });
+        let action_data = borsh::to_vec(&action).unwrap();
+
+        let instructions = AgentInstruction::chunked_execute(
+            &program_id, &agent, &authority, &data, 1, &action, 0,
+        );
+        assert_eq!(instructions.len(), 4);
+        let (staging, _) = pda::find_staging_address(&program_id, &agent, &authority);
+        assert!(instructions
+            .iter()
+            .all(|instruction| instruction.accounts.iter().any(|meta| meta.pubkey == staging)));
+        assert_eq!(
+            AgentInstruction::unpack(&instructions[3].data).unwrap(),
+            AgentInstruction::FinalizeExecute {
+                total_len: action_data.len() as u32,
+                hash: hash(&action_data).to_bytes(),
+            }
+        );
+
+        // Resuming after the first chunk only re-sends the remaining ones
+        let resumed = AgentInstruction::chunked_execute(
+            &program_id, &agent, &authority, &data, 1, &action, MAX_CHUNK_SIZE,
+        );
+        assert_eq!(resumed.len(), 3);
+        match AgentInstruction::unpack(&resumed[0].data).unwrap() {
+            AgentInstruction::WriteChunk { offset, .. } => assert_eq!(offset as usize, MAX_CHUNK_SIZE),
+            other => panic!("unexpected instruction: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config = AgentConfig {
+            autonomous_mode: false,
+            execution_limit: 0,
+            memory_limit: 5000,
+            capabilities: CapabilityFlags::from_bits(1 << 63),
+            min_execution_interval: 0,
+            allowed_programs: vec![Pubkey::new_unique(); MAX_ALLOWED_PROGRAMS + 1],
+        };
+
+        let fields: Vec<String> = config
+            .validate()
+            .unwrap_err()
+            .0
+            .into_iter()
+            .map(|v| v.field)
+            .collect();
+        assert_eq!(fields, vec!["execution_limit", "capabilities", "allowed_programs"]);
+    }
+
+    #[test]
+    fn test_clone_agent_instruction() {
+        let program_id = Pubkey::new_unique();
+        let (authority, source) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent, _) = pda::find_agent_address(&program_id, &authority, "clone_1");
+
+        let instruction =
+            AgentInstruction::clone_agent(&program_id, &agent, &authority, &source, "clone_1".to_string());
+        assert_eq!(instruction.accounts[0].pubkey, agent);
+        assert_eq!(instruction.accounts[3].pubkey, pda::find_registry_address(&program_id, &authority).0);
+        assert_eq!(instruction.accounts[4], AccountMeta::new_readonly(source, false));
+        assert_eq!(
+            AgentInstruction::unpack(&instruction.data).unwrap(),
+            AgentInstruction::CloneAgent { name: "clone_1".to_string() }
+        );
+    }
+
+    #[test]
+    fn test_child_instructions() {
+        let program_id = Pubkey::new_unique();
+        let (authority, parent, delegate) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (child, _) = pda::find_agent_address(&program_id, &authority, "child");
+        let config = AgentConfig {
+            autonomous_mode: false,
+            execution_limit: 10,
+            memory_limit: 1000,
+            capabilities: CapabilityFlags::COMPUTE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+
+        let instruction =
+            AgentInstruction::spawn_child(&program_id, &child, &authority, &parent, "child".to_string(), config.clone());
+        assert_eq!(instruction.accounts[4], AccountMeta::new_readonly(parent, false));
+        assert_eq!(
+            AgentInstruction::unpack(&instruction.data).unwrap(),
+            AgentInstruction::SpawnChild { name: "child".to_string(), config: config.clone() }
+        );
+
+        // The delegate record stays last after the parent
+        let update = AgentInstruction::update(&program_id, &child, &delegate, config);
+        let update = AgentInstruction::with_parent(update, &parent);
+        let update = AgentInstruction::signed_by_delegate(update, &child, &authority, &delegate);
+        let len = update.accounts.len();
+        assert_eq!(update.accounts[len - 2].pubkey, parent);
+        let (record, _) = pda::find_delegate_address(&program_id, &child, &authority, &delegate);
+        assert_eq!(update.accounts[len - 1].pubkey, record);
+    }
+
+    #[test]
+    fn test_timelocked_update_instructions() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (pending, _) = pda::find_pending_update_address(&program_id, &agent);
+
+        let commit = AgentInstruction::commit_update(&program_id, &agent, &authority);
+        assert_eq!(commit.accounts[4], AccountMeta::new(pending, false));
+        assert_eq!(AgentInstruction::unpack(&commit.data).unwrap(), AgentInstruction::CommitUpdate);
+        assert_eq!(AgentInstruction::cancel_update(&program_id, &agent, &authority).accounts[2].pubkey, pending);
+
+        let set_delay = AgentInstruction::set_update_delay(&program_id, &agent, &authority, 150);
+        assert_eq!(set_delay.accounts[3].pubkey, pending);
+        assert_eq!(
+            AgentInstruction::unpack(&set_delay.data).unwrap(),
+            AgentInstruction::SetUpdateDelay { slots: 150 }
+        );
+    }
+
+    #[test]
+    fn test_crank_execute_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (agent, payer, executor) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (result_account, _) = pda::find_result_address(&program_id, &agent);
+        let (receipt, _) = pda::find_receipt_address(&program_id, &agent, 4);
+        let memo = AgentAction::Memo { text: "tick".to_string() };
+
+        let ix = AgentInstruction::crank_execute(&program_id, &agent, &payer, &executor, 4, memo.clone());
+        assert_eq!(ix.accounts[1], AccountMeta::new(payer, true));
+        assert_eq!(ix.accounts[2].pubkey, result_account);
+        assert_eq!(ix.accounts[3].pubkey, receipt);
+        assert_eq!(ix.accounts[6], AccountMeta::new_readonly(executor, true));
+        assert_eq!(ix.accounts[7].pubkey, spl_memo::id());
+        assert_eq!(AgentInstruction::unpack(&ix.data).unwrap(), AgentInstruction::CrankExecute { action: memo });
+    }
+
+    #[test]
+    fn test_marketplace_instructions() {
+        let program_id = Pubkey::new_unique();
+        let (agent, seller, buyer) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (listing, _) = pda::find_listing_address(&program_id, &agent);
+
+        let list = AgentInstruction::list_for_sale(&program_id, &agent, &seller, 5_000);
+        assert_eq!(list.accounts[3], AccountMeta::new(listing, false));
+        assert_eq!(AgentInstruction::unpack(&list.data).unwrap(), AgentInstruction::ListForSale { price: 5_000 });
+
+        let purchase = AgentInstruction::purchase(&program_id, &agent, &buyer, &seller, &seller, 5_000);
+        assert_eq!(purchase.accounts[1], AccountMeta::new(buyer, true));
+        assert_eq!(purchase.accounts[2], AccountMeta::new(seller, false));
+        assert_eq!(purchase.accounts[3].pubkey, listing);
+        let (pending, _) = pda::find_pending_update_address(&program_id, &agent);
+        assert_eq!(purchase.accounts[5], AccountMeta::new(pending, false));
+        assert_eq!(purchase.accounts[6].pubkey, pda::find_registry_address(&program_id, &seller).0);
+        assert_eq!(purchase.accounts[7].pubkey, pda::find_registry_address(&program_id, &buyer).0);
+        assert_eq!(purchase.accounts[8].pubkey, pda::find_stake_address(&program_id, &agent).0);
+        assert_eq!(purchase.accounts[9], AccountMeta::new(seller, false));
+        assert_eq!(AgentInstruction::unpack(&purchase.data).unwrap(), AgentInstruction::Purchase { price: 5_000 });
+
+        assert_eq!(AgentInstruction::cancel_listing(&program_id, &agent, &seller).accounts[2].pubkey, listing);
+    }
+
+    #[test]
+    fn test_close_receipt_instruction() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (receipt, _) = pda::find_receipt_address(&program_id, &agent, 7);
+
+        let close = AgentInstruction::close_receipt(&program_id, &agent, &authority, 7);
+        assert_eq!(close.accounts[0], AccountMeta::new_readonly(agent, false));
+        assert_eq!(close.accounts[1], AccountMeta::new(authority, true));
+        assert_eq!(close.accounts[2], AccountMeta::new(receipt, false));
+        assert_eq!(AgentInstruction::unpack(&close.data).unwrap(), AgentInstruction::CloseReceipt { execution: 7 });
+    }
+
+    #[test]
+    fn test_stake_instructions() {
+        let program_id = Pubkey::new_unique();
+        let (agent, staker) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (escrow, _) = pda::find_stake_address(&program_id, &agent);
+
+        let stake = AgentInstruction::stake(&program_id, &agent, &staker, 5);
+        assert_eq!(stake.accounts[2], AccountMeta::new(escrow, false));
+        assert_eq!(AgentInstruction::unpack(&stake.data).unwrap(), AgentInstruction::Stake { lamports: 5 });
+
+        // Resume passes the escrow for agents that haven't run yet
+        let resume = AgentInstruction::resume(&program_id, &agent, &staker);
+        assert_eq!(resume.accounts[2], AccountMeta::new_readonly(escrow, false));
+        assert_eq!(AgentInstruction::unstake(&program_id, &agent, &staker).accounts[2].pubkey, escrow);
+    }
+
+    #[test]
+    fn test_execute_after_instruction() {
+        let program_id = Pubkey::new_unique();
+        let instruction = AgentInstruction::execute_after(
+            &program_id,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1,
+            AgentAction::Noop,
+            Some(1_000),
+            None,
+        );
+
+        assert_eq!(instruction.accounts.len(), 6);
+        assert_eq!(
+            AgentInstruction::unpack(&instruction.data).unwrap(),
+            AgentInstruction::ExecuteAfter {
+                action: AgentAction::Noop,
+                earliest_slot: Some(1_000),
+                earliest_unix_time: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_execute_cpi_instruction() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let target = Pubkey::new_unique();
+        let cpi = Instruction::new_with_bytes(
+            target,
+            &[9],
+            vec![AccountMeta::new_readonly(agent, true), AccountMeta::new(Pubkey::new_unique(), false)],
+        );
+
+        let instruction = AgentInstruction::execute_cpi(
+            &program_id,
+            &agent,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1,
+            &cpi,
+        );
+
+        // The agent is already passed; the target program comes last
+        assert_eq!(instruction.accounts.len(), 8);
+        assert_eq!(instruction.accounts[6], cpi.accounts[1]);
+        assert_eq!(instruction.accounts[7], AccountMeta::new_readonly(target, false));
+
+        let action = match AgentInstruction::unpack(&instruction.data).unwrap() {
+            AgentInstruction::Execute { action, memo: None } => action,
+            other => panic!("unexpected instruction {:?}", other),
+        };
+        assert_eq!(action.cpi().unwrap().instruction(), cpi);
+    }
+
+    #[test]
+    fn test_execute_with_memo() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority, result) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+
+        let plain = AgentInstruction::execute(&program_id, &agent, &authority, &result, 1, AgentAction::Noop);
+        let memo = Some("rebalance for Q3 report".to_string());
+        let ix = AgentInstruction::execute_with_memo(&program_id, &agent, &authority, &result, 1, AgentAction::Noop, memo.clone());
+        assert_eq!(ix.accounts.len(), plain.accounts.len() + 1);
+        assert_eq!(ix.accounts.last(), Some(&AccountMeta::new_readonly(spl_memo::id(), false)));
+        assert_eq!(AgentInstruction::unpack(&ix.data).unwrap(), AgentInstruction::Execute { action: AgentAction::Noop, memo });
+    }
+
+    #[test]
+    fn test_action_accounts() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let (vault, _) = pda::find_vault_address(&program_id, &agent);
+
+        let transfer = AgentAction::Transfer { destination, lamports: 1 };
+        assert_eq!(
+            AgentInstruction::action_accounts(&program_id, &agent, &transfer),
+            vec![AccountMeta::new(vault, false), AccountMeta::new(destination, false)]
+        );
+
+        let memo = AgentAction::Memo { text: "gm".to_string() };
+        assert_eq!(
+            AgentInstruction::action_accounts(&program_id, &agent, &memo),
+            vec![AccountMeta::new_readonly(spl_memo::id(), false)]
+        );
+        assert!(AgentInstruction::action_accounts(&program_id, &agent, &AgentAction::Noop).is_empty());
+    }
+}
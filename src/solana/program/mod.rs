@@ -1,9 +1,7 @@
 use solana_program::{
     account_info::AccountInfo,
-    entrypoint,
     entrypoint::ProgramResult,
     pubkey::Pubkey,
-    program_error::ProgramError,
     msg,
 };
 
@@ -11,9 +9,23 @@ pub mod state;
 pub mod instruction;
 pub mod processor;
 pub mod error;
+pub mod pda;
+pub mod cpi;
+pub mod oracle;
+pub mod event;
+pub mod capability;
+pub mod action;
+#[cfg(feature = "anchor-compat")]
+pub mod idl;
+#[cfg(feature = "zero-copy")]
+pub mod zero_copy;
+#[cfg(feature = "clockwork")]
+pub mod automation;
 
-// Declare the program's entrypoint
-entrypoint!(process_instruction);
+// Declare the program's entrypoint. Crates that depend on the toolkit as a
+// library enable `no-entrypoint` so the symbol is only emitted once.
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
 
 /// Program entrypoint implementation
 pub fn process_instruction(
@@ -0,0 +1,423 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    account_info::AccountInfo,
+    msg,
+    program_error::ProgramError,
+    pubkey,
+    pubkey::Pubkey,
+};
+
+use crate::solana::program::{error::AgentError, state::ProgramConfig};
+
+/// Pyth oracle program on mainnet, owning its price accounts; the program
+/// config may name another, e.g. on devnet
+pub const PYTH_PROGRAM_ID: Pubkey = pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+
+/// Switchboard v2 oracle program on mainnet, owning its aggregator accounts
+pub const SWITCHBOARD_PROGRAM_ID: Pubkey = pubkey!("SW1TCH7qEPTdLsDHRgPuMQjbQxKdH2aBStViMFnt64f");
+
+/// Magic number at the start of every Pyth v2 account
+pub const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+
+/// Pyth account layout version supported by this parser
+pub const PYTH_VERSION: u32 = 2;
+
+/// Pyth account type tag for price accounts
+pub const PYTH_PRICE_ACCOUNT_TYPE: u32 = 3;
+
+/// Pyth aggregate status meaning the price is currently valid
+pub const PYTH_STATUS_TRADING: u32 = 1;
+
+// Byte offsets into the Pyth v2 price account
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const EXPO_OFFSET: usize = 20;
+const AGG_PRICE_OFFSET: usize = 208;
+const AGG_CONF_OFFSET: usize = 216;
+const AGG_STATUS_OFFSET: usize = 224;
+const AGG_PUB_SLOT_OFFSET: usize = 232;
+const MIN_ACCOUNT_LEN: usize = 240;
+
+/// Anchor discriminator of Switchboard v2 aggregator accounts
+pub const SWITCHBOARD_AGGREGATOR_DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+
+// Byte offsets into the Switchboard v2 aggregator account, discriminator
+// included; the account is packed
+const SB_MIN_ORACLE_RESULTS_OFFSET: usize = 236;
+const SB_NUM_SUCCESS_OFFSET: usize = 341;
+const SB_ROUND_OPEN_SLOT_OFFSET: usize = 350;
+const SB_RESULT_OFFSET: usize = 366;
+const SB_STD_DEVIATION_OFFSET: usize = 386;
+const SB_MIN_ACCOUNT_LEN: usize = 406;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Comparator {
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+}
+
+/// Price condition that must still hold when a conditional action lands
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PriceCondition {
+    /// Price feed account the condition is evaluated against
+    pub feed: Pubkey,
+    pub comparator: Comparator,
+    /// Threshold expressed as `threshold * 10^expo`
+    pub threshold: i64,
+    pub expo: i32,
+    /// Maximum age of the feed's last publish, in slots
+    pub max_staleness_slots: u64,
+}
+
+/// Aggregate price read from an oracle account
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_slot: u64,
+}
+
+impl PriceCondition {
+    /// Check the condition against a price read from the feed at `current_slot`
+    pub fn evaluate(&self, price: &OraclePrice, current_slot: u64) -> Result<(), AgentError> {
+        if current_slot.saturating_sub(price.publish_slot) > self.max_staleness_slots {
+            return Err(AgentError::StaleOraclePrice);
+        }
+
+        let (lhs, rhs) = normalize(price.price, price.expo, self.threshold, self.expo)
+            .ok_or(AgentError::InvalidOracleAccount)?;
+
+        let holds = match self.comparator {
+            Comparator::GreaterThan => lhs > rhs,
+            Comparator::GreaterThanOrEqual => lhs >= rhs,
+            Comparator::LessThan => lhs < rhs,
+            Comparator::LessThanOrEqual => lhs <= rhs,
+        };
+
+        if holds {
+            Ok(())
+        } else {
+            Err(AgentError::ConditionNotMet)
+        }
+    }
+}
+
+/// Oracle account layout a price can be read from
+pub trait PriceFeed {
+    /// Whether `data` is an account of this layout
+    fn recognizes(&self, data: &[u8]) -> bool;
+
+    /// Program that must own accounts of this layout, as `config` names it
+    fn program_id(&self, config: &ProgramConfig) -> Pubkey;
+
+    /// Latest aggregate price held by `data`
+    fn parse_price(&self, data: &[u8]) -> Result<OraclePrice, AgentError>;
+}
+
+/// Pyth v2 price accounts
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PythFeed;
+
+impl PriceFeed for PythFeed {
+    fn recognizes(&self, data: &[u8]) -> bool {
+        data.len() >= 4 && read_u32(data, MAGIC_OFFSET) == PYTH_MAGIC
+    }
+
+    fn program_id(&self, config: &ProgramConfig) -> Pubkey {
+        config.pyth_program
+    }
+
+    fn parse_price(&self, data: &[u8]) -> Result<OraclePrice, AgentError> {
+        parse_pyth_price(data)
+    }
+}
+
+/// Switchboard v2 aggregator accounts
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SwitchboardFeed;
+
+impl PriceFeed for SwitchboardFeed {
+    fn recognizes(&self, data: &[u8]) -> bool {
+        data.starts_with(&SWITCHBOARD_AGGREGATOR_DISCRIMINATOR)
+    }
+
+    fn program_id(&self, config: &ProgramConfig) -> Pubkey {
+        config.switchboard_program
+    }
+
+    fn parse_price(&self, data: &[u8]) -> Result<OraclePrice, AgentError> {
+        parse_switchboard_price(data)
+    }
+}
+
+/// Feeds prices are read from, in the order they are tried
+pub const PRICE_FEEDS: [&dyn PriceFeed; 2] = [&PythFeed, &SwitchboardFeed];
+
+/// Read the aggregate price from a Pyth v2 price account owned by
+/// `pyth_program`
+pub fn read_pyth_price(account: &AccountInfo, pyth_program: &Pubkey) -> Result<OraclePrice, ProgramError> {
+    check_feed_owner(account, pyth_program)?;
+    parse_pyth_price(&account.data.borrow()).map_err(Into::into)
+}
+
+/// Reject feed accounts not owned by the oracle program: anyone can write
+/// the layout of a price account into an account of their own
+fn check_feed_owner(account: &AccountInfo, oracle_program: &Pubkey) -> Result<(), AgentError> {
+    if account.owner != oracle_program {
+        msg!("Price feed {} is owned by {}, not {}", account.key, account.owner, oracle_program);
+        return Err(AgentError::InvalidOracleAccount);
+    }
+    Ok(())
+}
+
+/// Read the aggregate price from an account of any of `PRICE_FEEDS`,
+/// owned by the feed's oracle program as `config` names it
+pub fn read_price(account: &AccountInfo, config: &ProgramConfig) -> Result<OraclePrice, ProgramError> {
+    let data = account.data.borrow();
+    let feed = find_feed(&data)?;
+    check_feed_owner(account, &feed.program_id(config))?;
+    feed.parse_price(&data).map_err(Into::into)
+}
+
+/// Parse the aggregate price from account data of any of `PRICE_FEEDS`,
+/// e.g. fetched over RPC; the account's owner is the caller's to check
+/// (see `PriceFeed::program_id`)
+pub fn parse_price(data: &[u8]) -> Result<OraclePrice, AgentError> {
+    find_feed(data)?.parse_price(data)
+}
+
+/// Feed of `PRICE_FEEDS` whose layout `data` has
+pub fn find_feed(data: &[u8]) -> Result<&'static dyn PriceFeed, AgentError> {
+    PRICE_FEEDS
+        .iter()
+        .copied()
+        .find(|feed| feed.recognizes(data))
+        .ok_or(AgentError::InvalidOracleAccount)
+}
+
+fn parse_pyth_price(data: &[u8]) -> Result<OraclePrice, AgentError> {
+    if data.len() < MIN_ACCOUNT_LEN
+        || read_u32(data, MAGIC_OFFSET) != PYTH_MAGIC
+        || read_u32(data, VERSION_OFFSET) != PYTH_VERSION
+        || read_u32(data, ACCOUNT_TYPE_OFFSET) != PYTH_PRICE_ACCOUNT_TYPE
+    {
+        return Err(AgentError::InvalidOracleAccount);
+    }
+
+    if read_u32(data, AGG_STATUS_OFFSET) != PYTH_STATUS_TRADING {
+        return Err(AgentError::StaleOraclePrice);
+    }
+
+    Ok(OraclePrice {
+        price: read_u64(data, AGG_PRICE_OFFSET) as i64,
+        conf: read_u64(data, AGG_CONF_OFFSET),
+        expo: read_u32(data, EXPO_OFFSET) as i32,
+        publish_slot: read_u64(data, AGG_PUB_SLOT_OFFSET),
+    })
+}
+
+/// Latest confirmed round of a Switchboard v2 aggregator
+///
+/// Results are decimals of a 128-bit mantissa and a scale; precision
+/// beyond what fits an `i64` is dropped.
+fn parse_switchboard_price(data: &[u8]) -> Result<OraclePrice, AgentError> {
+    if data.len() < SB_MIN_ACCOUNT_LEN || !data.starts_with(&SWITCHBOARD_AGGREGATOR_DISCRIMINATOR) {
+        return Err(AgentError::InvalidOracleAccount);
+    }
+
+    // Rounds confirmed by fewer oracles than required carry no valid result
+    let num_success = read_u32(data, SB_NUM_SUCCESS_OFFSET);
+    if num_success == 0 || num_success < read_u32(data, SB_MIN_ORACLE_RESULTS_OFFSET) {
+        return Err(AgentError::StaleOraclePrice);
+    }
+
+    let mut mantissa = read_i128(data, SB_RESULT_OFFSET);
+    let mut scale = read_u32(data, SB_RESULT_OFFSET + 16);
+    let mut std_deviation = rescale(
+        read_i128(data, SB_STD_DEVIATION_OFFSET),
+        read_u32(data, SB_STD_DEVIATION_OFFSET + 16),
+        scale,
+    );
+    while i64::try_from(mantissa).is_err() && scale > 0 {
+        mantissa /= 10;
+        std_deviation /= 10;
+        scale -= 1;
+    }
+
+    Ok(OraclePrice {
+        price: i64::try_from(mantissa).map_err(|_| AgentError::InvalidOracleAccount)?,
+        conf: u64::try_from(std_deviation.unsigned_abs()).unwrap_or(u64::MAX),
+        expo: -i32::try_from(scale).map_err(|_| AgentError::InvalidOracleAccount)?,
+        publish_slot: read_u64(data, SB_ROUND_OPEN_SLOT_OFFSET),
+    })
+}
+
+/// Decimal `value * 10^-from` expressed at scale `to`
+fn rescale(value: i128, from: u32, to: u32) -> i128 {
+    if from >= to {
+        10i128.checked_pow(from - to).map_or(0, |factor| value / factor)
+    } else {
+        10i128.checked_pow(to - from).map_or(i128::MAX, |factor| value.saturating_mul(factor))
+    }
+}
+
+/// Bring two fixed-point values to a common exponent
+fn normalize(a: i64, a_expo: i32, b: i64, b_expo: i32) -> Option<(i128, i128)> {
+    let expo = a_expo.min(b_expo);
+    let scale = |value: i64, from: i32| -> Option<i128> {
+        10i128
+            .checked_pow((from - expo) as u32)
+            .and_then(|factor| (value as i128).checked_mul(factor))
+    };
+    Some((scale(a, a_expo)?, scale(b, b_expo)?))
+}
+
+/// Pyth v2 price account data holding an aggregate price published at
+/// `pub_slot`, for tests
+#[cfg(any(test, feature = "test-utils"))]
+pub fn pyth_price_data(price: i64, expo: i32, status: u32, pub_slot: u64) -> Vec<u8> {
+    let mut data = vec![0u8; MIN_ACCOUNT_LEN];
+    data[MAGIC_OFFSET..MAGIC_OFFSET + 4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+    data[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&PYTH_VERSION.to_le_bytes());
+    data[ACCOUNT_TYPE_OFFSET..ACCOUNT_TYPE_OFFSET + 4].copy_from_slice(&PYTH_PRICE_ACCOUNT_TYPE.to_le_bytes());
+    data[EXPO_OFFSET..EXPO_OFFSET + 4].copy_from_slice(&expo.to_le_bytes());
+    data[AGG_PRICE_OFFSET..AGG_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+    data[AGG_STATUS_OFFSET..AGG_STATUS_OFFSET + 4].copy_from_slice(&status.to_le_bytes());
+    data[AGG_PUB_SLOT_OFFSET..AGG_PUB_SLOT_OFFSET + 8].copy_from_slice(&pub_slot.to_le_bytes());
+    data
+}
+
+/// Switchboard v2 aggregator account data whose latest confirmed round,
+/// opened at `round_open_slot`, resulted in `mantissa * 10^-scale` from
+/// `num_success` of the 1 oracle result required, for tests
+#[cfg(any(test, feature = "test-utils"))]
+pub fn switchboard_aggregator_data(mantissa: i128, scale: u32, num_success: u32, round_open_slot: u64) -> Vec<u8> {
+    let mut data = vec![0u8; SB_MIN_ACCOUNT_LEN];
+    data[..8].copy_from_slice(&SWITCHBOARD_AGGREGATOR_DISCRIMINATOR);
+    data[SB_MIN_ORACLE_RESULTS_OFFSET..SB_MIN_ORACLE_RESULTS_OFFSET + 4].copy_from_slice(&1u32.to_le_bytes());
+    data[SB_NUM_SUCCESS_OFFSET..SB_NUM_SUCCESS_OFFSET + 4].copy_from_slice(&num_success.to_le_bytes());
+    data[SB_ROUND_OPEN_SLOT_OFFSET..SB_ROUND_OPEN_SLOT_OFFSET + 8].copy_from_slice(&round_open_slot.to_le_bytes());
+    data[SB_RESULT_OFFSET..SB_RESULT_OFFSET + 16].copy_from_slice(&mantissa.to_le_bytes());
+    data[SB_RESULT_OFFSET + 16..SB_RESULT_OFFSET + 20].copy_from_slice(&scale.to_le_bytes());
+    data
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_i128(data: &[u8], offset: usize) -> i128 {
+    i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(comparator: Comparator, threshold: i64, expo: i32) -> PriceCondition {
+        PriceCondition {
+            feed: Pubkey::new_unique(),
+            comparator,
+            threshold,
+            expo,
+            max_staleness_slots: 25,
+        }
+    }
+
+    #[test]
+    fn test_parse_pyth_price() {
+        let data = pyth_price_data(2_050_000_000, -8, PYTH_STATUS_TRADING, 100);
+        let price = parse_pyth_price(&data).unwrap();
+        assert_eq!(price.price, 2_050_000_000);
+        assert_eq!(price.expo, -8);
+        assert_eq!(price.publish_slot, 100);
+
+        assert_eq!(parse_pyth_price(&data[..64]), Err(AgentError::InvalidOracleAccount));
+
+        let halted = pyth_price_data(2_050_000_000, -8, 0, 100);
+        assert_eq!(parse_pyth_price(&halted), Err(AgentError::StaleOraclePrice));
+    }
+
+    #[test]
+    fn test_parse_switchboard_price() {
+        // 20.5 at scale 9
+        let data = switchboard_aggregator_data(20_500_000_000, 9, 3, 100);
+        let price = parse_switchboard_price(&data).unwrap();
+        assert_eq!((price.price, price.expo, price.publish_slot), (20_500_000_000, -9, 100));
+
+        // Mantissas beyond an i64 lose precision
+        let wide = switchboard_aggregator_data(20_500_000_000 * 10i128.pow(18), 27, 3, 100);
+        let price = parse_switchboard_price(&wide).unwrap();
+        assert_eq!(price.price as i128 * 10i128.pow((27 + price.expo) as u32), 20_500_000_000 * 10i128.pow(18));
+
+        let unconfirmed = switchboard_aggregator_data(20_500_000_000, 9, 0, 100);
+        assert_eq!(parse_switchboard_price(&unconfirmed), Err(AgentError::StaleOraclePrice));
+        assert_eq!(parse_switchboard_price(&data[..200]), Err(AgentError::InvalidOracleAccount));
+    }
+
+    #[test]
+    fn test_feeds_mix_transparently() {
+        let pyth = pyth_price_data(2_050_000_000, -8, PYTH_STATUS_TRADING, 100);
+        let switchboard = switchboard_aggregator_data(20_500_000_000, 9, 3, 100);
+        let condition = condition(Comparator::GreaterThan, 20, 0);
+
+        for data in [pyth, switchboard] {
+            assert!(condition.evaluate(&parse_price(&data).unwrap(), 110).is_ok());
+        }
+        assert_eq!(parse_price(&[0u8; 512]), Err(AgentError::InvalidOracleAccount));
+    }
+
+    #[test]
+    fn test_read_price_checks_owner() {
+        let key = Pubkey::new_unique();
+        let mut data = pyth_price_data(2_050_000_000, -8, PYTH_STATUS_TRADING, 100);
+        let (mut lamports, spoofer) = (0, Pubkey::new_unique());
+        let config = ProgramConfig::default();
+
+        let mut account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &PYTH_PROGRAM_ID, false, 0);
+        assert_eq!(read_price(&account, &config).unwrap().price, 2_050_000_000);
+
+        account.owner = &spoofer;
+        assert_eq!(read_price(&account, &config), Err(AgentError::InvalidOracleAccount.into()));
+        assert_eq!(read_pyth_price(&account, &spoofer).unwrap().price, 2_050_000_000);
+
+        // Each layout is checked against its own oracle program
+        let mut data = switchboard_aggregator_data(20_500_000_000, 9, 3, 100);
+        let mut account = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &PYTH_PROGRAM_ID, false, 0);
+        assert_eq!(read_price(&account, &config), Err(AgentError::InvalidOracleAccount.into()));
+        account.owner = &SWITCHBOARD_PROGRAM_ID;
+        assert_eq!(read_price(&account, &config).unwrap().expo, -9);
+        assert_eq!(find_feed(&account.data.borrow()).unwrap().program_id(&config), SWITCHBOARD_PROGRAM_ID);
+    }
+
+    #[test]
+    fn test_condition_evaluation() {
+        // 20.50 at expo -8
+        let price = OraclePrice {
+            price: 2_050_000_000,
+            conf: 0,
+            expo: -8,
+            publish_slot: 100,
+        };
+
+        // Thresholds at a different exponent are rescaled before comparing
+        assert!(condition(Comparator::GreaterThan, 20, 0).evaluate(&price, 110).is_ok());
+        assert!(condition(Comparator::LessThanOrEqual, 2050, -2).evaluate(&price, 110).is_ok());
+        assert_eq!(
+            condition(Comparator::LessThan, 20, 0).evaluate(&price, 110),
+            Err(AgentError::ConditionNotMet)
+        );
+        assert_eq!(
+            condition(Comparator::GreaterThan, 20, 0).evaluate(&price, 200),
+            Err(AgentError::StaleOraclePrice)
+        );
+    }
+}
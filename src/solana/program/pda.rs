@@ -0,0 +1,510 @@
+use solana_program::{bpf_loader_upgradeable, pubkey::Pubkey};
+
+/// Seed prefix for agent accounts
+pub const AGENT_SEED: &[u8] = b"agent";
+
+/// Seed prefix for agent vaults
+pub const VAULT_SEED: &[u8] = b"vault";
+
+/// Seed prefix for delegate records
+pub const DELEGATE_SEED: &[u8] = b"delegate";
+
+/// Seed prefix for execution receipts
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+/// Seed prefix for agent metadata accounts
+pub const METADATA_SEED: &[u8] = b"metadata";
+
+/// Seed prefix for execution result accounts
+pub const RESULT_SEED: &[u8] = b"result";
+
+/// Seed prefix for agent stake escrows
+pub const STAKE_SEED: &[u8] = b"stake";
+
+/// Seed prefix for time-locked config updates
+pub const PENDING_UPDATE_SEED: &[u8] = b"pending_update";
+
+/// Seed prefix for marketplace listings
+pub const LISTING_SEED: &[u8] = b"listing";
+
+/// Seed prefix for per-authority agent registries
+pub const REGISTRY_SEED: &[u8] = b"registry";
+
+/// Seed of the program-wide config account
+pub const CONFIG_SEED: &[u8] = b"config";
+
+/// Seed prefix for chunked upload staging accounts
+pub const STAGING_SEED: &[u8] = b"staging";
+
+/// Derive the agent PDA for an authority and agent name
+///
+/// Names are used directly as a seed, so they must not exceed
+/// `solana_program::pubkey::MAX_SEED_LEN` bytes.
+pub fn find_agent_address(program_id: &Pubkey, authority: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[AGENT_SEED, authority.as_ref(), name.as_bytes()],
+        program_id,
+    )
+}
+
+/// Derive the vault PDA holding lamports and tokens on behalf of an agent
+pub fn find_vault_address(program_id: &Pubkey, agent: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, agent.as_ref()], program_id)
+}
+
+/// Derive the record PDA registering `delegate` as a session key of an agent
+/// on behalf of `authority`
+///
+/// The authority is part of the seeds so that records issued by a previous
+/// owner of the agent don't apply once its authority changes.
+pub fn find_delegate_address(
+    program_id: &Pubkey,
+    agent: &Pubkey,
+    authority: &Pubkey,
+    delegate: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[DELEGATE_SEED, agent.as_ref(), authority.as_ref(), delegate.as_ref()], program_id)
+}
+
+/// Derive the receipt PDA of an agent's `execution`-th execution (1-based)
+pub fn find_receipt_address(program_id: &Pubkey, agent: &Pubkey, execution: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[RECEIPT_SEED, agent.as_ref(), &execution.to_le_bytes()],
+        program_id,
+    )
+}
+
+/// Derive the PDA holding an agent's creation and update times
+pub fn find_metadata_address(program_id: &Pubkey, agent: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[METADATA_SEED, agent.as_ref()], program_id)
+}
+
+/// Derive the PDA holding an agent's recent execution results
+pub fn find_result_address(program_id: &Pubkey, agent: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RESULT_SEED, agent.as_ref()], program_id)
+}
+
+/// Derive the escrow PDA holding the lamports staked for an agent
+pub fn find_stake_address(program_id: &Pubkey, agent: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAKE_SEED, agent.as_ref()], program_id)
+}
+
+/// Derive the PDA holding an agent's config update while its delay runs
+pub fn find_pending_update_address(program_id: &Pubkey, agent: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PENDING_UPDATE_SEED, agent.as_ref()], program_id)
+}
+
+/// Derive the PDA listing an agent for sale
+pub fn find_listing_address(program_id: &Pubkey, agent: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[LISTING_SEED, agent.as_ref()], program_id)
+}
+
+/// Derive the registry PDA listing the agents created by `authority`
+pub fn find_registry_address(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REGISTRY_SEED, authority.as_ref()], program_id)
+}
+
+/// Derive the program-wide config PDA
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id)
+}
+
+/// Derive the staging PDA `authority` uploads chunked action data for
+/// `agent` into
+pub fn find_staging_address(program_id: &Pubkey, agent: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[STAGING_SEED, agent.as_ref(), authority.as_ref()], program_id)
+}
+
+/// Derive the ProgramData account of an upgradeable program, which
+/// records its upgrade authority
+pub fn find_program_data_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id()).0
+}
+
+/// Signer seeds for the agent PDA
+pub struct AgentSeeds<'a> {
+    authority: &'a Pubkey,
+    name: &'a str,
+    bump: [u8; 1],
+}
+
+impl<'a> AgentSeeds<'a> {
+    pub fn new(authority: &'a Pubkey, name: &'a str, bump: u8) -> Self {
+        Self {
+            authority,
+            name,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 4] {
+        [AGENT_SEED, self.authority.as_ref(), self.name.as_bytes(), &self.bump]
+    }
+}
+
+/// Signer seeds for an agent's vault PDA
+pub struct VaultSeeds<'a> {
+    agent: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> VaultSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [VAULT_SEED, self.agent.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for a delegate record PDA
+pub struct DelegateSeeds<'a> {
+    agent: &'a Pubkey,
+    authority: &'a Pubkey,
+    delegate: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> DelegateSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, authority: &'a Pubkey, delegate: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            authority,
+            delegate,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 5] {
+        [DELEGATE_SEED, self.agent.as_ref(), self.authority.as_ref(), self.delegate.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for an execution receipt PDA
+pub struct ReceiptSeeds<'a> {
+    agent: &'a Pubkey,
+    execution: [u8; 8],
+    bump: [u8; 1],
+}
+
+impl<'a> ReceiptSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, execution: u64, bump: u8) -> Self {
+        Self {
+            agent,
+            execution: execution.to_le_bytes(),
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 4] {
+        [RECEIPT_SEED, self.agent.as_ref(), &self.execution, &self.bump]
+    }
+}
+
+/// Signer seeds for an agent metadata PDA
+pub struct MetadataSeeds<'a> {
+    agent: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> MetadataSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [METADATA_SEED, self.agent.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for an execution result PDA
+pub struct ResultSeeds<'a> {
+    agent: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> ResultSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [RESULT_SEED, self.agent.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for a stake escrow PDA
+pub struct StakeSeeds<'a> {
+    agent: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> StakeSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [STAKE_SEED, self.agent.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for a pending update PDA
+pub struct PendingUpdateSeeds<'a> {
+    agent: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> PendingUpdateSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [PENDING_UPDATE_SEED, self.agent.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for a listing PDA
+pub struct ListingSeeds<'a> {
+    agent: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> ListingSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [LISTING_SEED, self.agent.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for an agent registry PDA
+pub struct RegistrySeeds<'a> {
+    authority: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> RegistrySeeds<'a> {
+    pub fn new(authority: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            authority,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 3] {
+        [REGISTRY_SEED, self.authority.as_ref(), &self.bump]
+    }
+}
+
+/// Signer seeds for the program config PDA
+pub struct ConfigSeeds {
+    bump: [u8; 1],
+}
+
+impl ConfigSeeds {
+    pub fn new(bump: u8) -> Self {
+        Self { bump: [bump] }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 2] {
+        [CONFIG_SEED, &self.bump]
+    }
+}
+
+/// Signer seeds for a staging PDA
+pub struct StagingSeeds<'a> {
+    agent: &'a Pubkey,
+    authority: &'a Pubkey,
+    bump: [u8; 1],
+}
+
+impl<'a> StagingSeeds<'a> {
+    pub fn new(agent: &'a Pubkey, authority: &'a Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            authority,
+            bump: [bump],
+        }
+    }
+
+    /// Seeds in the form expected by `invoke_signed`
+    pub fn as_seeds(&self) -> [&[u8]; 4] {
+        [STAGING_SEED, self.agent.as_ref(), self.authority.as_ref(), &self.bump]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (agent, bump) = find_agent_address(&program_id, &authority, "test_agent");
+
+        let seeds = AgentSeeds::new(&authority, "test_agent", bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, agent);
+    }
+
+    #[test]
+    fn test_vault_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (vault, bump) = find_vault_address(&program_id, &agent);
+
+        let seeds = VaultSeeds::new(&agent, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, vault);
+    }
+
+    #[test]
+    fn test_delegate_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority, delegate) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (record, bump) = find_delegate_address(&program_id, &agent, &authority, &delegate);
+
+        let seeds = DelegateSeeds::new(&agent, &authority, &delegate, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, record);
+        // A new authority doesn't inherit the records of the previous one
+        assert_ne!(find_delegate_address(&program_id, &agent, &Pubkey::new_unique(), &delegate).0, record);
+    }
+
+    #[test]
+    fn test_receipt_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (receipt, bump) = find_receipt_address(&program_id, &agent, 42);
+
+        let seeds = ReceiptSeeds::new(&agent, 42, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, receipt);
+        assert_ne!(find_receipt_address(&program_id, &agent, 43).0, receipt);
+    }
+
+    #[test]
+    fn test_metadata_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (metadata, bump) = find_metadata_address(&program_id, &agent);
+
+        let seeds = MetadataSeeds::new(&agent, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, metadata);
+        assert_ne!(find_result_address(&program_id, &agent).0, metadata);
+    }
+
+    #[test]
+    fn test_result_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (result, bump) = find_result_address(&program_id, &agent);
+
+        let seeds = ResultSeeds::new(&agent, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, result);
+    }
+
+    #[test]
+    fn test_stake_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (stake, bump) = find_stake_address(&program_id, &agent);
+
+        let seeds = StakeSeeds::new(&agent, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, stake);
+        assert_ne!(find_vault_address(&program_id, &agent).0, stake);
+    }
+
+    #[test]
+    fn test_pending_update_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (pending, bump) = find_pending_update_address(&program_id, &agent);
+
+        let seeds = PendingUpdateSeeds::new(&agent, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, pending);
+    }
+
+    #[test]
+    fn test_listing_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let agent = Pubkey::new_unique();
+        let (listing, bump) = find_listing_address(&program_id, &agent);
+
+        let seeds = ListingSeeds::new(&agent, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, listing);
+    }
+
+    #[test]
+    fn test_registry_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (registry, bump) = find_registry_address(&program_id, &authority);
+
+        let seeds = RegistrySeeds::new(&authority, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, registry);
+    }
+
+    #[test]
+    fn test_config_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let (config, bump) = find_config_address(&program_id);
+
+        let seeds = ConfigSeeds::new(bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, config);
+    }
+
+    #[test]
+    fn test_staging_seeds_match_derivation() {
+        let program_id = Pubkey::new_unique();
+        let (agent, authority) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (staging, bump) = find_staging_address(&program_id, &agent, &authority);
+
+        let seeds = StagingSeeds::new(&agent, &authority, bump);
+        let derived = Pubkey::create_program_address(&seeds.as_seeds(), &program_id).unwrap();
+        assert_eq!(derived, staging);
+        assert_ne!(find_staging_address(&program_id, &agent, &Pubkey::new_unique()).0, staging);
+    }
+}
@@ -1,18 +1,50 @@
-use borsh::BorshDeserialize;
+use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    compute_units::sol_remaining_compute_units,
     entrypoint::ProgramResult,
+    hash::hash,
     msg,
+    program::{get_return_data, invoke_signed},
     program_error::ProgramError,
     pubkey::Pubkey,
+    rent::Rent,
     system_program,
+    sysvar::Sysvar,
 };
 
 use crate::solana::program::{
+    action::{self, AgentAction, CpiAction},
+    capability::CapabilityFlags,
+    cpi,
     error::AgentError,
-    instruction::AgentInstruction,
-    state::{AgentAccount, AgentState},
+    event::{
+        AgentClosed, AgentExecuted, AgentInitialized, AgentListed, AgentPaused, AgentResumed, AgentSold, AgentUpdated,
+        Event,
+    },
+    instruction::{AgentConfig, AgentInstruction},
+    oracle::{self, PriceCondition},
+    pda::{
+        self, AgentSeeds, ConfigSeeds, DelegateSeeds, ListingSeeds, MetadataSeeds, ReceiptSeeds, RegistrySeeds,
+        ResultSeeds, PendingUpdateSeeds, StagingSeeds, StakeSeeds, VaultSeeds,
+    },
+    state::{
+        AgentAccount, AgentMetadata, AgentRegistry, AgentState, DelegateRecord, ExecutionReceipt, ExecutionResult,
+        ExecutionStatus, Listing, PauseReason, PendingUpdate, ProgramConfig, ResultAccount, StagingHeader, StakeAccount,
+        AGENT_ACCOUNT_VERSION,
+        DELEGATE_ALL, DELEGATE_EXECUTE, DELEGATE_PAUSE, DELEGATE_RESUME, DELEGATE_UPDATE,
+        MAX_NAME_LEN, MIN_AGENT_STAKE,
+    },
 };
+#[cfg(feature = "zero-copy")]
+use crate::solana::program::zero_copy::AgentAccountZc;
+#[cfg(feature = "clockwork")]
+use crate::solana::program::automation;
+#[cfg(feature = "clockwork")]
+use solana_program::program::set_return_data;
+use crate::validation::Validate;
 
 pub struct Processor;
 
@@ -22,8 +54,8 @@ impl Processor {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
-        let instruction = AgentInstruction::try_from_slice(instruction_data)
-            .map_err(|_| ProgramError::InvalidInstructionData)?;
+        let instruction = AgentInstruction::unpack(instruction_data)?;
+        let required = instruction.required_capabilities();
 
         match instruction {
             AgentInstruction::Initialize { name, config } => {
@@ -34,18 +66,137 @@ impl Processor {
                 msg!("Instruction: Update Agent");
                 Self::process_update(program_id, accounts, config)
             }
-            AgentInstruction::Execute { action_data } => {
+            AgentInstruction::Execute { action, memo } => {
                 msg!("Instruction: Execute Agent Action");
-                Self::process_execute(program_id, accounts, action_data)
+                Self::process_execute(program_id, accounts, action, memo, required)
             }
-            AgentInstruction::Pause => {
+            AgentInstruction::Pause { reason, resume_at } => {
                 msg!("Instruction: Pause Agent");
-                Self::process_pause(program_id, accounts)
+                Self::process_pause(program_id, accounts, reason, resume_at)
             }
             AgentInstruction::Resume => {
                 msg!("Instruction: Resume Agent");
                 Self::process_resume(program_id, accounts)
             }
+            AgentInstruction::ExecuteAfter { action, earliest_slot, earliest_unix_time } => {
+                msg!("Instruction: Execute Agent Action (time-locked)");
+                Self::process_execute_after(
+                    program_id,
+                    accounts,
+                    action,
+                    earliest_slot,
+                    earliest_unix_time,
+                    required,
+                )
+            }
+            AgentInstruction::ExecuteConditional { action, condition } => {
+                msg!("Instruction: Execute Agent Action (price-conditioned)");
+                Self::process_execute_conditional(program_id, accounts, action, condition, required)
+            }
+            AgentInstruction::WriteChunk { offset, data } => {
+                msg!("Instruction: Write Action Data Chunk");
+                Self::process_write_chunk(program_id, accounts, offset, data, required)
+            }
+            AgentInstruction::FinalizeExecute { total_len, hash } => {
+                msg!("Instruction: Finalize Chunked Execution");
+                Self::process_finalize_execute(program_id, accounts, total_len, hash, required)
+            }
+            AgentInstruction::Close => {
+                msg!("Instruction: Close Agent");
+                Self::process_close(program_id, accounts)
+            }
+            AgentInstruction::TransferAuthority { new_authority } => {
+                msg!("Instruction: Transfer Agent Authority");
+                Self::process_transfer_authority(program_id, accounts, new_authority)
+            }
+            AgentInstruction::AcceptAuthority => {
+                msg!("Instruction: Accept Agent Authority");
+                Self::process_accept_authority(program_id, accounts)
+            }
+            AgentInstruction::Migrate => {
+                msg!("Instruction: Migrate Agent Account");
+                Self::process_migrate(program_id, accounts)
+            }
+            AgentInstruction::Delegate { delegate, expiry_slot, permissions } => {
+                msg!("Instruction: Delegate Agent Session Key");
+                Self::process_delegate(program_id, accounts, delegate, expiry_slot, permissions)
+            }
+            AgentInstruction::SetAdmin { new_admin } => {
+                msg!("Instruction: Set Program Admin");
+                Self::process_set_admin(program_id, accounts, new_admin)
+            }
+            AgentInstruction::FreezeAll => {
+                msg!("Instruction: Freeze All Agents");
+                Self::process_set_frozen(program_id, accounts, true)
+            }
+            AgentInstruction::ThawAll => {
+                msg!("Instruction: Thaw All Agents");
+                Self::process_set_frozen(program_id, accounts, false)
+            }
+            AgentInstruction::CloneAgent { name } => {
+                msg!("Instruction: Clone Agent");
+                Self::process_clone_agent(program_id, accounts, name)
+            }
+            AgentInstruction::AddExecutor { executor } => {
+                msg!("Instruction: Add Agent Executor");
+                Self::process_add_executor(program_id, accounts, executor)
+            }
+            AgentInstruction::RemoveExecutor { executor } => {
+                msg!("Instruction: Remove Agent Executor");
+                Self::process_remove_executor(program_id, accounts, executor)
+            }
+            AgentInstruction::SpawnChild { name, config } => {
+                msg!("Instruction: Spawn Child Agent");
+                Self::process_spawn_child(program_id, accounts, name, config)
+            }
+            AgentInstruction::Stake { lamports } => {
+                msg!("Instruction: Stake");
+                Self::process_stake(program_id, accounts, lamports)
+            }
+            AgentInstruction::Unstake => {
+                msg!("Instruction: Unstake");
+                Self::process_unstake(program_id, accounts)
+            }
+            AgentInstruction::CommitUpdate => {
+                msg!("Instruction: Commit Agent Update");
+                Self::process_commit_update(program_id, accounts)
+            }
+            AgentInstruction::CancelUpdate => {
+                msg!("Instruction: Cancel Agent Update");
+                Self::process_cancel_update(program_id, accounts)
+            }
+            AgentInstruction::SetUpdateDelay { slots } => {
+                msg!("Instruction: Set Agent Update Delay");
+                Self::process_set_update_delay(program_id, accounts, slots)
+            }
+            AgentInstruction::Crank { action } => {
+                msg!("Instruction: Crank Agent");
+                Self::process_crank(program_id, accounts, action)
+            }
+            AgentInstruction::CrankExecute { action } => {
+                msg!("Instruction: Execute Agent Action (cranked)");
+                Self::process_crank_execute(program_id, accounts, action, required)
+            }
+            AgentInstruction::ListForSale { price } => {
+                msg!("Instruction: List Agent For Sale");
+                Self::process_list_for_sale(program_id, accounts, price)
+            }
+            AgentInstruction::Purchase { price } => {
+                msg!("Instruction: Purchase Agent");
+                Self::process_purchase(program_id, accounts, price)
+            }
+            AgentInstruction::CancelListing => {
+                msg!("Instruction: Cancel Agent Listing");
+                Self::process_cancel_listing(program_id, accounts)
+            }
+            AgentInstruction::SetOraclePrograms { pyth_program, switchboard_program } => {
+                msg!("Instruction: Set Oracle Programs");
+                Self::process_set_oracle_programs(program_id, accounts, pyth_program, switchboard_program)
+            }
+            AgentInstruction::CloseReceipt { execution } => {
+                msg!("Instruction: Close Receipt");
+                Self::process_close_receipt(program_id, accounts, execution)
+            }
         }
     }
 
@@ -53,88 +204,329 @@ impl Processor {
         program_id: &Pubkey,
         accounts: &[AccountInfo],
         name: String,
-        config: crate::solana::program::instruction::AgentConfig,
+        config: AgentConfig,
+    ) -> ProgramResult {
+        Self::create_agent(program_id, accounts, name, config, None, 4)?;
+        msg!("Agent initialized successfully");
+        Ok(())
+    }
+
+    fn process_clone_agent(program_id: &Pubkey, accounts: &[AccountInfo], name: String) -> ProgramResult {
+        let source_account = accounts.get(4).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let source = Self::load_agent(program_id, source_account)?;
+
+        Self::create_agent(program_id, accounts, name, source.config, None, 5)?;
+        msg!("Agent cloned from {}", source_account.key);
+        Ok(())
+    }
+
+    fn process_spawn_child(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        name: String,
+        config: AgentConfig,
+    ) -> ProgramResult {
+        let authority = accounts.get(1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let parent_account = accounts.get(4).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let parent = Self::load_agent(program_id, parent_account)?;
+        if parent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        Self::check_capabilities(&parent, config.capabilities)?;
+
+        Self::create_agent(program_id, accounts, name, config, Some(*parent_account.key), 5)?;
+        msg!("Child agent spawned by {}", parent_account.key);
+        Ok(())
+    }
+
+    /// Create an agent PDA with its metadata and register it; the accounts
+    /// are those of `Initialize`, with the metadata at `metadata_index`
+    fn create_agent(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        name: String,
+        config: AgentConfig,
+        parent: Option<Pubkey>,
+        metadata_index: usize,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let agent_account = next_account_info(account_info_iter)?;
         let authority = next_account_info(account_info_iter)?;
         let system_program = next_account_info(account_info_iter)?;
+        let registry_account = next_account_info(account_info_iter)?;
+        let metadata_account = accounts.get(metadata_index).ok_or(ProgramError::NotEnoughAccountKeys)?;
 
         if !authority.is_signer {
             return Err(ProgramError::MissingRequiredSignature);
         }
+        Self::check_writable(agent_account)?;
+        Self::check_writable(authority)?;
 
         if system_program.key != &system_program::id() {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        let agent = AgentAccount {
-            authority: *authority.key,
-            name,
-            config,
-            state: AgentState::Initialized,
-            last_execution: 0,
-            execution_count: 0,
-        };
+        Self::validate_config(&config)?;
 
-        agent.serialize(&mut *agent_account.data.borrow_mut())?;
-        msg!("Agent initialized successfully");
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            msg!("Agent name must be 1-{} bytes", MAX_NAME_LEN);
+            return Err(AgentError::InvalidConfiguration.into());
+        }
+
+        let (address, bump) = pda::find_agent_address(program_id, authority.key, &name);
+        if address != *agent_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        // The PDA is unique per (authority, name), so an existing account
+        // means the authority already owns an agent with this name
+        if !agent_account.data_is_empty() {
+            msg!("Agent name {} is already taken", name);
+            return Err(AgentError::NameAlreadyTaken.into());
+        }
+
+        let mut agent = AgentAccount::new(*authority.key, name, config);
+        agent.bump = bump;
+        agent.parent = parent;
+
+        let rent = Rent::get()?;
+        let space = agent.required_space();
+        let seeds = AgentSeeds::new(authority.key, &agent.name, bump);
+        cpi::create_pda_account(
+            authority,
+            agent_account,
+            system_program,
+            rent.minimum_balance(space),
+            space,
+            program_id,
+            &seeds.as_seeds(),
+        )?;
+        Self::check_account(program_id, agent_account, space, &rent)?;
+
+        agent.generation =
+            Self::register_agent(program_id, authority, registry_account, system_program, agent_account.key, true)?;
+        agent.save(agent_account)?;
+        Self::init_metadata(
+            program_id,
+            agent_account.key,
+            authority,
+            metadata_account,
+            system_program,
+            Clock::get()?.unix_timestamp,
+        )?;
+
+        AgentInitialized {
+            agent: *agent_account.key,
+            authority: agent.authority,
+            name: agent.name,
+        }
+        .emit();
         Ok(())
     }
 
     fn process_update(
         program_id: &Pubkey,
         accounts: &[AccountInfo],
-        config: crate::solana::program::instruction::AgentConfig,
+        config: AgentConfig,
     ) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let agent_account = next_account_info(account_info_iter)?;
         let authority = next_account_info(account_info_iter)?;
 
-        if !authority.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        let agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_signer(program_id, agent_account, &agent, authority, accounts, DELEGATE_UPDATE)?;
+        Self::check_config(program_id, accounts, &agent, &config)?;
+
+        if agent.update_delay > 0 {
+            let system_program = next_account_info(account_info_iter)?;
+            return Self::queue_update(program_id, accounts, &agent, system_program, Some(config), None);
         }
+        Self::apply_config(program_id, accounts, agent, config)
+    }
 
-        let mut agent = AgentAccount::try_from_slice(&agent_account.data.borrow())?;
-        if agent.authority != *authority.key {
-            return Err(AgentError::InvalidAuthority.into());
+    /// Reject configs that are invalid or grant a child agent capabilities
+    /// its parent lacks
+    fn check_config(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        agent: &AgentAccount,
+        config: &AgentConfig,
+    ) -> ProgramResult {
+        Self::validate_config(config)?;
+        if let Some(parent) = agent.parent {
+            if let Some(parent) = Self::load_parent(program_id, accounts, &parent)? {
+                Self::check_capabilities(&parent, config.capabilities)?;
+            }
         }
+        Ok(())
+    }
 
+    /// Replace an agent's config and record the update in its metadata
+    ///
+    /// The agent and its signer are the first two accounts, followed by the
+    /// system program, needed to grow the agent account, and the metadata.
+    fn apply_config(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        mut agent: AgentAccount,
+        config: AgentConfig,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
         agent.config = config;
-        agent.serialize(&mut *agent_account.data.borrow_mut())?;
+
+        // Grow the account if the new config no longer fits
+        let space = agent.required_space();
+        if space > AgentAccount::max_space() {
+            return Err(AgentError::InvalidConfiguration.into());
+        }
+        if agent_account.data_len() < space {
+            let system_program = next_account_info(account_info_iter)?;
+            Self::check_writable(authority)?;
+            cpi::resize_account(authority, agent_account, system_program, space)?;
+            msg!("Agent account resized to {} bytes", space);
+        }
+
+        agent.save(agent_account)?;
+
+        // Clients predating the metadata account don't pass it
+        let (metadata_address, _) = pda::find_metadata_address(program_id, agent_account.key);
+        if let Some(metadata_account) = accounts.get(3).filter(|account| account.key == &metadata_address) {
+            let system_program = &accounts[2];
+            Self::touch_metadata(program_id, agent_account.key, authority, metadata_account, system_program)?;
+        }
+
+        AgentUpdated {
+            agent: *agent_account.key,
+            signer: *authority.key,
+            config: agent.config,
+        }
+        .emit();
         msg!("Agent updated successfully");
         Ok(())
     }
 
-    fn process_execute(
+    /// Queue a config and/or update delay in the agent's pending update,
+    /// paid for by the signer, merging with an update already queued. The
+    /// agent and signer are the first two accounts; the pending update may
+    /// be any of them.
+    fn queue_update<'a>(
         program_id: &Pubkey,
-        accounts: &[AccountInfo],
-        action_data: Vec<u8>,
+        accounts: &[AccountInfo<'a>],
+        agent: &AgentAccount,
+        system_program: &AccountInfo<'a>,
+        config: Option<AgentConfig>,
+        update_delay: Option<u64>,
     ) -> ProgramResult {
+        let agent_account = &accounts[0];
+        let payer = &accounts[1];
+        let (address, bump) = pda::find_pending_update_address(program_id, agent_account.key);
+        let pending_account = Self::account_by_key(accounts, &address)?;
+        Self::check_writable(pending_account)?;
+
+        let queued = if pending_account.data_is_empty() {
+            Self::check_writable(payer)?;
+            let seeds = PendingUpdateSeeds::new(agent_account.key, bump);
+            cpi::create_pda_account(
+                payer,
+                pending_account,
+                system_program,
+                Rent::get()?.minimum_balance(PendingUpdate::LEN),
+                PendingUpdate::LEN,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+            None
+        } else {
+            Self::check_owner(program_id, pending_account)?;
+            Some(PendingUpdate::unpack(&pending_account.data.borrow())?)
+        };
+
+        let activation_slot = Clock::get()?.slot.saturating_add(agent.update_delay);
+        let pending = PendingUpdate {
+            agent: *agent_account.key,
+            config: config.or_else(|| queued.as_ref().and_then(|queued| queued.config.clone())),
+            update_delay: update_delay.or_else(|| queued.and_then(|queued| queued.update_delay)),
+            activation_slot,
+            bump,
+        };
+        pending.serialize(&mut &mut pending_account.data.borrow_mut()[..])?;
+
+        msg!("Update queued until slot {}", activation_slot);
+        Ok(())
+    }
+
+    fn process_commit_update(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let agent_account = next_account_info(account_info_iter)?;
         let authority = next_account_info(account_info_iter)?;
-        let data_account = next_account_info(account_info_iter)?;
 
-        if !authority.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_signer(program_id, agent_account, &agent, authority, accounts, DELEGATE_UPDATE)?;
+
+        let (address, _) = pda::find_pending_update_address(program_id, agent_account.key);
+        let pending_account = Self::account_by_key(accounts, &address)?;
+        Self::check_owner(program_id, pending_account)?;
+        let pending = PendingUpdate::unpack(&pending_account.data.borrow())?;
+        if pending.agent != *agent_account.key {
+            return Err(AgentError::InvalidAccountData.into());
         }
 
-        let mut agent = AgentAccount::try_from_slice(&agent_account.data.borrow())?;
-        if agent.state != AgentState::Running {
-            return Err(AgentError::InvalidAgentState.into());
+        let slot = Clock::get()?.slot;
+        if slot < pending.activation_slot {
+            msg!("Update locked until slot {}, now {}", pending.activation_slot, slot);
+            return Err(AgentError::UpdateLocked.into());
         }
 
-        // Process action data and update agent state
-        agent.execution_count += 1;
-        agent.last_execution = solana_program::clock::Clock::get()?.unix_timestamp;
-        agent.serialize(&mut *agent_account.data.borrow_mut())?;
+        // Rules may have changed since the update was queued
+        let config = pending.config.unwrap_or_else(|| agent.config.clone());
+        Self::check_config(program_id, accounts, &agent, &config)?;
+        if let Some(update_delay) = pending.update_delay {
+            agent.update_delay = update_delay;
+            msg!("Update delay set to {} slots", update_delay);
+        }
 
-        msg!("Agent execution completed successfully");
+        Self::close_account(pending_account, authority)?;
+        Self::apply_config(program_id, accounts, agent, config)
+    }
+
+    fn process_cancel_update(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let pending_account = next_account_info(account_info_iter)?;
+
+        let agent = Self::load_agent(program_id, agent_account)?;
+        Self::check_signer(program_id, agent_account, &agent, authority, accounts, DELEGATE_UPDATE)?;
+
+        let (address, _) = pda::find_pending_update_address(program_id, agent_account.key);
+        if address != *pending_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        Self::check_owner(program_id, pending_account)?;
+
+        Self::close_account(pending_account, authority)?;
+        msg!("Pending update cancelled");
+        Ok(())
+    }
+
+    /// Close a program account, returning its rent to `recipient`
+    fn close_account(account: &AccountInfo, recipient: &AccountInfo) -> ProgramResult {
+        Self::check_writable(account)?;
+        Self::check_writable(recipient)?;
+
+        let lamports = account.lamports();
+        **account.lamports.borrow_mut() = 0;
+        **recipient.lamports.borrow_mut() = recipient
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        account.data.borrow_mut().fill(0);
         Ok(())
     }
 
-    fn process_pause(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    fn process_set_update_delay(program_id: &Pubkey, accounts: &[AccountInfo], slots: u64) -> ProgramResult {
         let account_info_iter = &mut accounts.iter();
         let agent_account = next_account_info(account_info_iter)?;
         let authority = next_account_info(account_info_iter)?;
@@ -143,55 +535,3002 @@ impl Processor {
             return Err(ProgramError::MissingRequiredSignature);
         }
 
-        let mut agent = AgentAccount::try_from_slice(&agent_account.data.borrow())?;
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
         if agent.authority != *authority.key {
             return Err(AgentError::InvalidAuthority.into());
         }
 
-        agent.state = AgentState::Paused;
-        agent.serialize(&mut *agent_account.data.borrow_mut())?;
-        msg!("Agent paused successfully");
+        // Lowering the delay would let the next update skip it
+        if slots < agent.update_delay {
+            let system_program = next_account_info(account_info_iter)?;
+            return Self::queue_update(program_id, accounts, &agent, system_program, None, Some(slots));
+        }
+
+        agent.update_delay = slots;
+
+        // Accounts of older layouts grow to fit the delay
+        let space = agent.required_space();
+        if agent_account.data_len() < space {
+            let system_program = next_account_info(account_info_iter)?;
+            Self::check_writable(authority)?;
+            cpi::resize_account(authority, agent_account, system_program, space)?;
+        }
+
+        agent.save(agent_account)?;
+        msg!("Update delay set to {} slots", slots);
         Ok(())
     }
 
-    fn process_resume(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    fn process_execute(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        action: AgentAction,
+        memo: Option<String>,
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        Self::execute_checked(program_id, accounts, 3, &action, memo.as_deref(), required)
+    }
+
+    /// Check the signer and run an action (see `execute_action`)
+    fn execute_checked(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        receipt_index: usize,
+        action: &AgentAction,
+        memo: Option<&str>,
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        let start_units = sol_remaining_compute_units();
         let account_info_iter = &mut accounts.iter();
         let agent_account = next_account_info(account_info_iter)?;
         let authority = next_account_info(account_info_iter)?;
+        let _data_account = next_account_info(account_info_iter)?;
 
-        if !authority.is_signer {
-            return Err(ProgramError::MissingRequiredSignature);
+        let agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_executor(program_id, agent_account, &agent, authority, accounts, Some(action))?;
+        Self::check_capabilities(&agent, required)?;
+
+        Self::execute_action(program_id, accounts, receipt_index, agent, action, memo, start_units)
+    }
+
+    /// Hand an automation thread the instruction running `action` as the
+    /// agent's next execution
+    #[cfg(feature = "clockwork")]
+    fn process_crank(program_id: &Pubkey, accounts: &[AccountInfo], action: AgentAction) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let thread = next_account_info(account_info_iter)?;
+
+        let agent = Self::load_agent(program_id, agent_account)?;
+        Self::check_executor(program_id, agent_account, &agent, thread, accounts, Some(&action))?;
+
+        let response = automation::crank_response(
+            program_id,
+            agent_account.key,
+            thread.key,
+            agent.execution_count + 1,
+            action,
+        );
+        set_return_data(&borsh::to_vec(&response)?);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "clockwork"))]
+    fn process_crank(_program_id: &Pubkey, _accounts: &[AccountInfo], _action: AgentAction) -> ProgramResult {
+        msg!("Program built without the clockwork feature");
+        Err(AgentError::InvalidInstructionData.into())
+    }
+
+    fn process_crank_execute(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        action: AgentAction,
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        let start_units = sol_remaining_compute_units();
+        let agent_account = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let executor = accounts.get(6).ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        let agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_executor(program_id, agent_account, &agent, executor, accounts, Some(&action))?;
+        Self::check_capabilities(&agent, required)?;
+
+        Self::execute_action(program_id, accounts, 3, agent, &action, None, start_units)
+    }
+
+    /// Run an action for an agent whose signer has already been checked,
+    /// recording it in an execution receipt and the agent's result account,
+    /// both paid for by the signer
+    ///
+    /// The agent, signer and result account are the first three accounts;
+    /// the receipt account,
+    /// the system program and the program config are expected from
+    /// `receipt_index` on. The action may use any of the accounts.
+    ///
+    /// A `memo` is written, signed by the agent PDA, with the SPL Memo
+    /// program found among the accounts. The compute units consumed since
+    /// `start_units` were remaining, up to the end of the action, are
+    /// recorded in the agent's metrics.
+    fn execute_action<'a>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo<'a>],
+        receipt_index: usize,
+        mut agent: AgentAccount,
+        action: &AgentAction,
+        memo: Option<&str>,
+        start_units: u64,
+    ) -> ProgramResult {
+        Self::check_capabilities(&agent, action.required_capabilities())?;
+        action.validate()?;
+        if let Some(memo) = memo {
+            action::validate_memo(memo)?;
         }
 
-        let mut agent = AgentAccount::try_from_slice(&agent_account.data.borrow())?;
-        if agent.authority != *authority.key {
-            return Err(AgentError::InvalidAuthority.into());
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let signer = next_account_info(account_info_iter)?;
+        let result_account = next_account_info(account_info_iter)?;
+        let receipt_account = accounts.get(receipt_index).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let system_program = accounts.get(receipt_index + 1).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let config_account = accounts.get(receipt_index + 2).ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+        Self::check_writable(signer)?;
+        Self::check_writable(receipt_account)?;
+        Self::check_writable(result_account)?;
+
+        let clock = Clock::get()?;
+        if agent.auto_resume_due(clock.unix_timestamp) {
+            msg!("Pause ended at {}, resuming agent", agent.resume_at.unwrap_or_default());
+            agent.resume();
+            AgentResumed {
+                agent: *agent_account.key,
+                signer: *signer.key,
+            }
+            .emit();
+        }
+        if agent.state != AgentState::Running {
+            return Err(AgentError::InvalidAgentState.into());
+        }
+        if let Some(parent) = agent.parent {
+            let parent_paused = Self::load_parent(program_id, accounts, &parent)?
+                .map_or(false, |parent| parent.is_paused_at(clock.unix_timestamp));
+            if parent_paused {
+                msg!("Parent {} is paused", parent);
+                return Err(AgentError::ParentPaused.into());
+            }
         }
+        Self::check_not_frozen(program_id, config_account)?;
 
-        agent.state = AgentState::Running;
-        agent.serialize(&mut *agent_account.data.borrow_mut())?;
-        msg!("Agent resumed successfully");
+        if let Err(error) = agent.check_rate_limit(clock.unix_timestamp) {
+            msg!(
+                "Last execution at {}, minimum interval {}s",
+                agent.last_execution,
+                agent.config.min_execution_interval
+            );
+            return Err(error.into());
+        }
+        if agent.execution_count >= agent.config.execution_limit {
+            msg!("Execution limit of {} reached", agent.config.execution_limit);
+            return Err(AgentError::ExecutionLimitExceeded.into());
+        }
+
+        // Actions the program refuses are recorded as failed executions;
+        // other errors, like a failed CPI, abort the transaction
+        let (status, output_hash) =
+            match Self::run_action(program_id, accounts, agent_account, &agent, system_program, action) {
+                Ok(output_hash) => (ExecutionStatus::Succeeded, output_hash),
+                Err(ProgramError::Custom(error)) => {
+                    msg!("Action failed with error {}", error);
+                    (ExecutionStatus::Failed { error }, [0; 32])
+                }
+                Err(error) => return Err(error),
+            };
+
+        let memo_hash = match memo.filter(|_| status == ExecutionStatus::Succeeded) {
+            Some(memo) => {
+                let seeds = AgentSeeds::new(&agent.creator, &agent.name, agent.bump);
+                let memo_program = Self::account_by_key(accounts, &spl_memo::id())?;
+                cpi::memo_signed(memo_program, agent_account, memo, &seeds.as_seeds())?;
+                Some(hash(memo.as_bytes()).to_bytes())
+            }
+            None => None,
+        };
+
+        // Update agent state and metrics
+        agent.execution_count += 1;
+        agent.last_execution = clock.unix_timestamp;
+        agent.metrics.record(
+            status == ExecutionStatus::Succeeded,
+            start_units.saturating_sub(sol_remaining_compute_units()),
+        );
+
+        // Accounts created by older layouts grow to fit the metrics
+        let space = agent.required_space();
+        if agent_account.data_len() < space {
+            cpi::resize_account(signer, agent_account, system_program, space)?;
+        }
+        Self::save_execution(&agent, agent_account)?;
+
+        let (address, bump) = pda::find_receipt_address(program_id, agent_account.key, agent.execution_count);
+        if address != *receipt_account.key {
+            msg!("Expected receipt for execution {}", agent.execution_count);
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        // Receipts are never overwritten: the receipts of a closed agent
+        // must be closed with `CloseReceipt` before an agent recreated at the
+        // same address executes again
+        if !receipt_account.data_is_empty() {
+            msg!("Receipt for execution {} already exists", agent.execution_count);
+            return Err(AgentError::AlreadyInitialized.into());
+        }
+        let seeds = ReceiptSeeds::new(agent_account.key, agent.execution_count, bump);
+        cpi::create_pda_account(
+            signer,
+            receipt_account,
+            system_program,
+            Rent::get()?.minimum_balance(ExecutionReceipt::LEN),
+            ExecutionReceipt::LEN,
+            program_id,
+            &seeds.as_seeds(),
+        )?;
+
+        let receipt = ExecutionReceipt {
+            agent: *agent_account.key,
+            execution: agent.execution_count,
+            signer: *signer.key,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+            action_hash: hash(&borsh::to_vec(action)?).to_bytes(),
+            status,
+            bump,
+            memo_hash,
+        };
+        receipt.serialize(&mut &mut receipt_account.data.borrow_mut()[..])?;
+
+        Self::record_result(
+            program_id,
+            agent_account.key,
+            signer,
+            result_account,
+            system_program,
+            ExecutionResult {
+                execution: receipt.execution,
+                status: receipt.status.clone(),
+                output_hash,
+                timestamp: receipt.timestamp,
+            },
+        )?;
+
+        AgentExecuted {
+            agent: *agent_account.key,
+            signer: *signer.key,
+            execution_count: agent.execution_count,
+            timestamp: agent.last_execution,
+            action_hash: receipt.action_hash,
+        }
+        .emit();
+        msg!("Agent execution completed successfully");
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use solana_program::clock::Epoch;
+    /// Run `action` for `agent`, returning the hash of its output
+    ///
+    /// The program's own refusals, such as a program outside the agent's
+    /// allowlist, are `AgentError`s returned before anything changed. A
+    /// failed CPI aborts the transaction instead of returning.
+    fn run_action<'a>(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo<'a>],
+        agent_account: &AccountInfo<'a>,
+        agent: &AgentAccount,
+        system_program: &AccountInfo<'a>,
+        action: &AgentAction,
+    ) -> Result<[u8; 32], ProgramError> {
+        let output_hash = match action {
+            AgentAction::Noop => [0; 32],
+            AgentAction::Transfer { destination, lamports } => {
+                let (vault, bump) = pda::find_vault_address(program_id, agent_account.key);
+                let seeds = VaultSeeds::new(agent_account.key, bump);
+                cpi::transfer_lamports_signed(
+                    Self::account_by_key(accounts, &vault)?,
+                    Self::account_by_key(accounts, destination)?,
+                    system_program,
+                    *lamports,
+                    &seeds.as_seeds(),
+                )?;
+                [0; 32]
+            }
+            AgentAction::Memo { text } => {
+                let seeds = AgentSeeds::new(&agent.creator, &agent.name, agent.bump);
+                let memo_program = Self::account_by_key(accounts, &spl_memo::id())?;
+                cpi::memo_signed(memo_program, agent_account, text, &seeds.as_seeds())?;
+                [0; 32]
+            }
+            AgentAction::Swap { cpi, amount_in, minimum_amount_out } => {
+                msg!("Swapping {} for at least {}", amount_in, minimum_amount_out);
+                Self::invoke_cpi(program_id, agent, accounts, cpi)?;
+                Self::output_hash(&cpi.program_id)
+            }
+            AgentAction::CustomCpi(cpi) => {
+                Self::invoke_cpi(program_id, agent, accounts, cpi)?;
+                Self::output_hash(&cpi.program_id)
+            }
+        };
+        Ok(output_hash)
+    }
 
-    #[test]
-    fn test_initialize() {
-        // Test implementation
+    fn process_execute_after(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        action: AgentAction,
+        earliest_slot: Option<u64>,
+        earliest_unix_time: Option<i64>,
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        let clock = Clock::get()?;
+        if !Self::time_lock_elapsed(&clock, earliest_slot, earliest_unix_time) {
+            msg!(
+                "Action locked until slot {:?} / unix time {:?}",
+                earliest_slot,
+                earliest_unix_time
+            );
+            return Err(AgentError::ExecutionLocked.into());
+        }
+
+        Self::process_execute(program_id, accounts, action, None, required)
     }
 
-    #[test]
-    fn test_update() {
-        // Test implementation
+    fn process_execute_conditional(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        action: AgentAction,
+        condition: PriceCondition,
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        let price_feed = accounts.get(3).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        if price_feed.key != &condition.feed {
+            return Err(AgentError::InvalidOracleAccount.into());
+        }
+
+        // The program config follows the receipt and the system program
+        let config_account = accounts.get(6).ok_or(ProgramError::NotEnoughAccountKeys)?;
+        let config = Self::load_config_or_default(program_id, config_account)?;
+        let price = oracle::read_price(price_feed, &config)?;
+        let clock = Clock::get()?;
+        if let Err(error) = condition.evaluate(&price, clock.slot) {
+            msg!("Price condition rejected: {} (price {} expo {})", error, price.price, price.expo);
+            return Err(error.into());
+        }
+
+        Self::execute_checked(program_id, accounts, 4, &action, None, required)
     }
 
-    #[test]
-    fn test_execute() {
-        // Test implementation
+    fn process_write_chunk(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        offset: u32,
+        data: Vec<u8>,
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let staging_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        Self::check_writable(staging_account)?;
+
+        // The staged action is checked against the signer once finalized
+        let agent = Self::load_agent(program_id, agent_account)?;
+        Self::check_executor(program_id, agent_account, &agent, authority, accounts, None)?;
+        Self::check_capabilities(&agent, required)?;
+        let bump = Self::check_staging_address(program_id, agent_account, authority, staging_account)?;
+
+        let start = offset as usize;
+        let end = start
+            .checked_add(data.len())
+            .ok_or(AgentError::InvalidChunk)?;
+        if end as u64 > agent.config.memory_limit {
+            return Err(AgentError::MemoryLimitExceeded.into());
+        }
+
+        // The staging account is created on the first chunk and grows with
+        // the upload, paid for by the uploader
+        let space = StagingHeader::space(end);
+        if staging_account.data_is_empty() {
+            Self::check_writable(authority)?;
+            let seeds = StagingSeeds::new(agent_account.key, authority.key, bump);
+            cpi::create_pda_account(
+                authority,
+                staging_account,
+                system_program,
+                Rent::get()?.minimum_balance(space),
+                space,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+        } else {
+            Self::check_owner(program_id, staging_account)?;
+            if staging_account.data_len() < space {
+                Self::check_writable(authority)?;
+                cpi::resize_account(authority, staging_account, system_program, space)?;
+            }
+        }
+
+        let mut staging = staging_account.data.borrow_mut();
+        let mut header = Self::staging_header(&staging)?;
+        if !header.is_initialized {
+            header = StagingHeader {
+                is_initialized: true,
+                agent: *agent_account.key,
+                authority: *authority.key,
+                written: 0,
+            };
+        } else if header.agent != *agent_account.key || header.authority != *authority.key {
+            return Err(AgentError::InvalidAccountData.into());
+        }
+
+        // Chunks may overwrite staged bytes but never leave a gap
+        if start > header.written as usize || end > StagingHeader::capacity(staging.len()) {
+            msg!("Chunk {}..{} rejected, {} bytes staged", start, end, header.written);
+            return Err(AgentError::InvalidChunk.into());
+        }
+
+        staging[StagingHeader::LEN + start..StagingHeader::LEN + end].copy_from_slice(&data);
+        header.written = header.written.max(end as u32);
+        header.serialize(&mut &mut staging[..StagingHeader::LEN])?;
+
+        msg!("Staged {} bytes at offset {} ({} total)", data.len(), start, header.written);
+        Ok(())
+    }
+
+    fn process_finalize_execute(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        total_len: u32,
+        expected_hash: [u8; 32],
+        required: CapabilityFlags,
+    ) -> ProgramResult {
+        let start_units = sol_remaining_compute_units();
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let _data_account = next_account_info(account_info_iter)?;
+        let staging_account = next_account_info(account_info_iter)?;
+        Self::check_owner(program_id, staging_account)?;
+        Self::check_writable(staging_account)?;
+
+        let agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_capabilities(&agent, required)?;
+        Self::check_staging_address(program_id, agent_account, authority, staging_account)?;
+
+        let action = {
+            let staging = staging_account.data.borrow();
+            let header = Self::staging_header(&staging)?;
+            if !header.is_initialized
+                || header.agent != *agent_account.key
+                || header.authority != *authority.key
+            {
+                return Err(AgentError::InvalidAccountData.into());
+            }
+
+            if header.written != total_len {
+                msg!("Staged {} of {} bytes", header.written, total_len);
+                return Err(AgentError::UploadIncomplete.into());
+            }
+
+            if total_len as u64 > agent.config.memory_limit {
+                return Err(AgentError::MemoryLimitExceeded.into());
+            }
+
+            let data = &staging[StagingHeader::LEN..StagingHeader::LEN + total_len as usize];
+            if hash(data).to_bytes() != expected_hash {
+                return Err(AgentError::HashMismatch.into());
+            }
+            AgentAction::try_from_slice(data).map_err(|_| AgentError::InvalidAction)?
+        };
+
+        Self::check_executor(program_id, agent_account, &agent, authority, accounts, Some(&action))?;
+        Self::execute_action(program_id, accounts, 4, agent, &action, None, start_units)?;
+
+        // Close the staging account, returning its rent to the authority
+        let lamports = staging_account.lamports();
+        **staging_account.lamports.borrow_mut() = 0;
+        **authority.lamports.borrow_mut() = authority
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        staging_account.data.borrow_mut().fill(0);
+
+        msg!("Chunked execution finalized ({} bytes)", total_len);
+        Ok(())
+    }
+
+    fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let registry_account = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(authority)?;
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        // An update queued for this agent must not land on one recreated at
+        // its address
+        let pending_account = next_account_info(account_info_iter)?;
+        let (address, _) = pda::find_pending_update_address(program_id, agent_account.key);
+        if address != *pending_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        if pending_account.owner == program_id && !pending_account.data_is_empty() {
+            Self::close_account(pending_account, authority)?;
+        }
+
+        // Mark the account terminated so it can't be revived within the same
+        // transaction by refunding its rent
+        agent.update_state(AgentState::Terminated)?;
+        agent.save(agent_account)?;
+        Self::unregister_agent(program_id, registry_account, &agent.authority, agent_account.key)?;
+
+        let lamports = agent_account.lamports();
+        **agent_account.lamports.borrow_mut() = 0;
+        **authority.lamports.borrow_mut() = authority
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+
+        AgentClosed {
+            agent: *agent_account.key,
+            authority: *authority.key,
+            lamports,
+        }
+        .emit();
+        msg!("Agent closed, {} lamports returned to authority", lamports);
+        Ok(())
+    }
+
+    fn process_transfer_authority(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        new_authority: Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let new_authority_info = next_account_info(account_info_iter).ok();
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        let new_authority_signer = new_authority_info.filter(|info| info.key == &new_authority && info.is_signer);
+
+        if new_authority == agent.authority {
+            agent.pending_authority = None;
+            msg!("Pending authority transfer cancelled");
+        } else if let Some(new_signer) = new_authority_signer {
+            let registry_account = next_account_info(account_info_iter)?;
+            let new_registry_account = next_account_info(account_info_iter)?;
+            let system_program = next_account_info(account_info_iter)?;
+            Self::unregister_agent(program_id, registry_account, &agent.authority, agent_account.key)?;
+            Self::register_agent(program_id, new_signer, new_registry_account, system_program, agent_account.key, false)?;
+
+            agent.authority = new_authority;
+            agent.pending_authority = None;
+            msg!("Authority transferred to {}", new_authority);
+        } else {
+            agent.pending_authority = Some(new_authority);
+            msg!("Authority transfer to {} pending acceptance", new_authority);
+        }
+
+        agent.save(agent_account)?;
+        Ok(())
+    }
+
+    fn process_accept_authority(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let new_authority = next_account_info(account_info_iter)?;
+        let registry_account = next_account_info(account_info_iter)?;
+        let new_registry_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        if !new_authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.pending_authority != Some(*new_authority.key) {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        Self::unregister_agent(program_id, registry_account, &agent.authority, agent_account.key)?;
+        Self::register_agent(program_id, new_authority, new_registry_account, system_program, agent_account.key, false)?;
+
+        agent.authority = *new_authority.key;
+        agent.pending_authority = None;
+        agent.save(agent_account)?;
+
+        msg!("Authority transferred to {}", new_authority.key);
+        Ok(())
+    }
+
+    fn process_migrate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        // The first release stored agents at keypair addresses
+        Self::check_owner(program_id, agent_account)?;
+        let legacy = AgentAccount::unpack_v1(&agent_account.data.borrow()).ok();
+        if let Some(agent) = legacy {
+            if agent.name.len() > MAX_NAME_LEN {
+                msg!("Agent names must be at most {} bytes to have a PDA", MAX_NAME_LEN);
+                return Err(AgentError::InvalidConfiguration.into());
+            }
+            if pda::find_agent_address(program_id, &agent.creator, &agent.name).0 != *agent_account.key {
+                let pda_account = next_account_info(account_info_iter)?;
+                return Self::move_legacy_agent(program_id, agent_account, authority, system_program, pda_account, agent);
+            }
+        }
+
+        let stored_version = agent_account.data.borrow().first().copied();
+        let agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        let space = agent.required_space();
+        if agent_account.data_len() < space {
+            Self::check_writable(authority)?;
+            cpi::resize_account(authority, agent_account, system_program, space)?;
+        }
+        agent.save(agent_account)?;
+
+        if stored_version == Some(AGENT_ACCOUNT_VERSION) {
+            msg!("Agent account already at v{}", AGENT_ACCOUNT_VERSION);
+        } else {
+            msg!("Agent account migrated to v{}", AGENT_ACCOUNT_VERSION);
+        }
+        Ok(())
+    }
+
+    /// Move a v1 agent from the keypair account the first release stored it
+    /// in into its PDA, closing the keypair account
+    fn move_legacy_agent<'a>(
+        program_id: &Pubkey,
+        legacy_account: &AccountInfo<'a>,
+        authority: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        agent_account: &AccountInfo<'a>,
+        mut agent: AgentAccount,
+    ) -> ProgramResult {
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        Self::check_writable(legacy_account)?;
+        Self::check_writable(agent_account)?;
+
+        let (address, bump) = pda::find_agent_address(program_id, authority.key, &agent.name);
+        if address != *agent_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        if !agent_account.data_is_empty() {
+            msg!("Agent name {} is already taken", agent.name);
+            return Err(AgentError::NameAlreadyTaken.into());
+        }
+
+        agent.bump = bump;
+        let rent = Rent::get()?;
+        let space = agent.required_space();
+        let seeds = AgentSeeds::new(authority.key, &agent.name, bump);
+        cpi::create_pda_account(
+            authority,
+            agent_account,
+            system_program,
+            rent.minimum_balance(space),
+            space,
+            program_id,
+            &seeds.as_seeds(),
+        )?;
+        agent.save(agent_account)?;
+        Self::close_account(legacy_account, authority)?;
+
+        msg!("Agent moved from {} to {} in layout v{}", legacy_account.key, agent_account.key, AGENT_ACCOUNT_VERSION);
+        Ok(())
+    }
+
+    fn process_add_executor(program_id: &Pubkey, accounts: &[AccountInfo], executor: Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        if !agent.add_executor(executor)? {
+            msg!("Executor {} already listed", executor);
+            return Ok(());
+        }
+
+        let space = agent.required_space();
+        if agent_account.data_len() < space {
+            let system_program = next_account_info(account_info_iter)?;
+            Self::check_writable(authority)?;
+            cpi::resize_account(authority, agent_account, system_program, space)?;
+        }
+
+        agent.save(agent_account)?;
+        msg!("Executor {} added", executor);
+        Ok(())
+    }
+
+    fn process_remove_executor(program_id: &Pubkey, accounts: &[AccountInfo], executor: Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        if !agent.remove_executor(&executor) {
+            msg!("Executor {} not listed", executor);
+            return Ok(());
+        }
+
+        agent.save(agent_account)?;
+        msg!("Executor {} removed", executor);
+        Ok(())
+    }
+
+    fn process_stake(program_id: &Pubkey, accounts: &[AccountInfo], lamports: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let staker = next_account_info(account_info_iter)?;
+        let stake_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        if !staker.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(staker)?;
+        Self::check_writable(stake_account)?;
+        if lamports == 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let agent = Self::load_agent(program_id, agent_account)?;
+        if agent.state == AgentState::Terminated {
+            return Err(AgentError::InvalidAgentState.into());
+        }
+
+        let (address, bump) = pda::find_stake_address(program_id, agent_account.key);
+        if address != *stake_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        // The escrow belongs to the authority that opened it; anyone may top
+        // it up, but only the authority can open it and get it back
+        let mut stake = if stake_account.data_is_empty() {
+            if agent.authority != *staker.key {
+                msg!("Only the agent's authority can open its stake escrow");
+                return Err(AgentError::InvalidAuthority.into());
+            }
+            let seeds = StakeSeeds::new(agent_account.key, bump);
+            cpi::create_pda_account(
+                staker,
+                stake_account,
+                system_program,
+                Rent::get()?.minimum_balance(StakeAccount::LEN),
+                StakeAccount::LEN,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+            StakeAccount::new(*agent_account.key, *staker.key, bump)
+        } else {
+            Self::check_owner(program_id, stake_account)?;
+            StakeAccount::unpack(&stake_account.data.borrow())?
+        };
+
+        cpi::transfer_lamports(staker, stake_account, system_program, lamports)?;
+        stake.amount = stake.amount.checked_add(lamports).ok_or(ProgramError::ArithmeticOverflow)?;
+        stake.serialize(&mut &mut stake_account.data.borrow_mut()[..])?;
+
+        msg!("Staked {} lamports, {} in total", lamports, stake.amount);
+        Ok(())
+    }
+
+    fn process_list_for_sale(program_id: &Pubkey, accounts: &[AccountInfo], price: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let listing_account = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(authority)?;
+        Self::check_writable(listing_account)?;
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        if matches!(agent.state, AgentState::Paused | AgentState::Terminated) {
+            msg!("{:?} agents can't be listed", agent.state);
+            return Err(AgentError::InvalidAgentState.into());
+        }
+
+        let (address, bump) = pda::find_listing_address(program_id, agent_account.key);
+        if address != *listing_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        let seeds = ListingSeeds::new(agent_account.key, bump);
+        cpi::create_pda_account(
+            authority,
+            listing_account,
+            system_program,
+            Rent::get()?.minimum_balance(Listing::LEN),
+            Listing::LEN,
+            program_id,
+            &seeds.as_seeds(),
+        )?;
+        let listing = Listing {
+            agent: *agent_account.key,
+            seller: *authority.key,
+            price,
+            bump,
+        };
+        listing.serialize(&mut &mut listing_account.data.borrow_mut()[..])?;
+
+        // The listing holds the agent until it is bought or withdrawn, and
+        // nothing runs it meanwhile
+        agent.authority = address;
+        agent.pending_authority = None;
+        agent.executors.clear();
+        agent.save(agent_account)?;
+
+        AgentListed {
+            agent: *agent_account.key,
+            seller: *authority.key,
+            price,
+        }
+        .emit();
+        msg!("Agent listed for {} lamports", price);
+        Ok(())
+    }
+
+    fn process_purchase(program_id: &Pubkey, accounts: &[AccountInfo], price: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let buyer = next_account_info(account_info_iter)?;
+        let seller = next_account_info(account_info_iter)?;
+        let listing_account = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+        let pending_account = next_account_info(account_info_iter)?;
+        let seller_registry = next_account_info(account_info_iter)?;
+        let buyer_registry = next_account_info(account_info_iter)?;
+        let stake_account = next_account_info(account_info_iter)?;
+        let staker = next_account_info(account_info_iter)?;
+
+        if !buyer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(buyer)?;
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        let listing = Self::load_listing(program_id, agent_account, &agent, listing_account)?;
+        if listing.seller != *seller.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        if listing.price != price {
+            msg!("Listed for {} lamports, {} offered", listing.price, price);
+            return Err(AgentError::PriceMismatch.into());
+        }
+
+        let (address, _) = pda::find_pending_update_address(program_id, agent_account.key);
+        if address != *pending_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        let (address, _) = pda::find_stake_address(program_id, agent_account.key);
+        if address != *stake_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        // The buyer takes the stake escrow over, refunding its staker what
+        // unstaking would have returned
+        if stake_account.owner == program_id && !stake_account.data_is_empty() {
+            Self::check_writable(stake_account)?;
+            let mut stake = StakeAccount::unpack(&stake_account.data.borrow())?;
+            if stake.staker != *staker.key {
+                msg!("Stake escrow belongs to {}", stake.staker);
+                return Err(AgentError::InvalidAuthority.into());
+            }
+            cpi::transfer_lamports(buyer, staker, system_program, stake_account.lamports())?;
+            stake.staker = *buyer.key;
+            stake.serialize(&mut &mut stake_account.data.borrow_mut()[..])?;
+        }
+
+        cpi::transfer_lamports(buyer, seller, system_program, price)?;
+        Self::close_account(listing_account, seller)?;
+        // An update the seller queued must not land on the buyer's agent
+        if pending_account.owner == program_id && !pending_account.data_is_empty() {
+            Self::close_account(pending_account, seller)?;
+        }
+        // Listed agents stay in the seller's registry until sold
+        Self::unregister_agent(program_id, seller_registry, seller.key, agent_account.key)?;
+        Self::register_agent(program_id, buyer, buyer_registry, system_program, agent_account.key, false)?;
+
+        // Executors were chosen by the seller
+        agent.authority = *buyer.key;
+        agent.executors.clear();
+        agent.save(agent_account)?;
+
+        AgentSold {
+            agent: *agent_account.key,
+            seller: *seller.key,
+            buyer: *buyer.key,
+            price,
+        }
+        .emit();
+        msg!("Agent sold to {} for {} lamports", buyer.key, price);
+        Ok(())
+    }
+
+    fn process_cancel_listing(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let seller = next_account_info(account_info_iter)?;
+        let listing_account = next_account_info(account_info_iter)?;
+
+        if !seller.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        let listing = Self::load_listing(program_id, agent_account, &agent, listing_account)?;
+        if listing.seller != *seller.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        Self::close_account(listing_account, seller)?;
+        agent.authority = listing.seller;
+        agent.save(agent_account)?;
+
+        msg!("Listing cancelled");
+        Ok(())
+    }
+
+    /// Load the listing currently holding an agent
+    fn load_listing(
+        program_id: &Pubkey,
+        agent_account: &AccountInfo,
+        agent: &AgentAccount,
+        listing_account: &AccountInfo,
+    ) -> Result<Listing, ProgramError> {
+        Self::check_owner(program_id, listing_account)?;
+        // Listings only become the authority of the agent they were created for
+        if agent.authority != *listing_account.key {
+            msg!("Agent is not listed");
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        let listing = Listing::unpack(&listing_account.data.borrow())?;
+        if listing.agent != *agent_account.key {
+            return Err(AgentError::InvalidAccountData.into());
+        }
+        Ok(listing)
+    }
+
+    fn process_unstake(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let staker = next_account_info(account_info_iter)?;
+        let stake_account = next_account_info(account_info_iter)?;
+
+        if !staker.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(staker)?;
+        Self::check_owner(program_id, stake_account)?;
+        Self::check_writable(stake_account)?;
+
+        let stake = StakeAccount::unpack(&stake_account.data.borrow())?;
+        if stake.staker != *staker.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        if stake.agent != *agent_account.key {
+            return Err(AgentError::InvalidAccountData.into());
+        }
+
+        // A closed agent's account is gone once the closing transaction
+        // ends; within it, the agent is left terminated
+        if !agent_account.data_is_empty() {
+            let agent = Self::load_agent(program_id, agent_account)?;
+            if agent.state != AgentState::Terminated {
+                msg!("Close the agent before unstaking");
+                return Err(AgentError::StakeLocked.into());
+            }
+        }
+
+        let lamports = stake_account.lamports();
+        **stake_account.lamports.borrow_mut() = 0;
+        **staker.lamports.borrow_mut() = staker
+            .lamports()
+            .checked_add(lamports)
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        stake_account.data.borrow_mut().fill(0);
+
+        msg!("Unstaked {} lamports", stake.amount);
+        Ok(())
+    }
+
+    fn process_delegate(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        delegate: Pubkey,
+        expiry_slot: u64,
+        permissions: u32,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let record_account = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(authority)?;
+        Self::check_writable(record_account)?;
+
+        let agent = Self::load_agent(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+
+        if permissions & !DELEGATE_ALL != 0 {
+            msg!("Unknown delegate permissions {:#x}", permissions & !DELEGATE_ALL);
+            return Err(AgentError::InvalidConfiguration.into());
+        }
+
+        let (address, bump) = pda::find_delegate_address(program_id, agent_account.key, authority.key, &delegate);
+        if address != *record_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        if permissions == 0 {
+            // Close the record, returning its rent to the authority
+            if record_account.owner == program_id {
+                let lamports = record_account.lamports();
+                **record_account.lamports.borrow_mut() = 0;
+                **authority.lamports.borrow_mut() = authority
+                    .lamports()
+                    .checked_add(lamports)
+                    .ok_or(ProgramError::ArithmeticOverflow)?;
+                record_account.data.borrow_mut().fill(0);
+            }
+            msg!("Delegate {} revoked", delegate);
+            return Ok(());
+        }
+
+        if record_account.data_is_empty() {
+            let system_program = next_account_info(account_info_iter)?;
+            let seeds = DelegateSeeds::new(agent_account.key, authority.key, &delegate, bump);
+            cpi::create_pda_account(
+                authority,
+                record_account,
+                system_program,
+                Rent::get()?.minimum_balance(DelegateRecord::LEN),
+                DelegateRecord::LEN,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+        } else {
+            Self::check_owner(program_id, record_account)?;
+        }
+
+        let record = DelegateRecord {
+            is_initialized: true,
+            agent: *agent_account.key,
+            authority: *authority.key,
+            delegate,
+            expiry_slot,
+            permissions,
+            bump,
+            generation: agent.generation,
+        };
+        record.serialize(&mut &mut record_account.data.borrow_mut()[..])?;
+
+        msg!("Delegate {} allowed {:#x} until slot {}", delegate, permissions, expiry_slot);
+        Ok(())
+    }
+
+    fn process_set_admin(program_id: &Pubkey, accounts: &[AccountInfo], new_admin: Pubkey) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let config_account = next_account_info(account_info_iter)?;
+        let admin = next_account_info(account_info_iter)?;
+        let system_program = next_account_info(account_info_iter)?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(config_account)?;
+
+        let config = if config_account.data_is_empty() {
+            let program_data = next_account_info(account_info_iter)?;
+            Self::check_upgrade_authority(program_id, program_data, admin)?;
+            Self::check_writable(admin)?;
+
+            let (address, bump) = pda::find_config_address(program_id);
+            if address != *config_account.key {
+                return Err(AgentError::InvalidProgramAddress.into());
+            }
+            cpi::create_pda_account(
+                admin,
+                config_account,
+                system_program,
+                Rent::get()?.minimum_balance(ProgramConfig::LEN),
+                ProgramConfig::LEN,
+                program_id,
+                &ConfigSeeds::new(bump).as_seeds(),
+            )?;
+            ProgramConfig {
+                is_initialized: true,
+                admin: new_admin,
+                bump,
+                ..ProgramConfig::default()
+            }
+        } else {
+            let mut config = Self::load_config(program_id, config_account)?;
+            if config.admin != *admin.key {
+                return Err(AgentError::InvalidAuthority.into());
+            }
+            config.admin = new_admin;
+            config
+        };
+        config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+        msg!("Program admin set to {}", new_admin);
+        Ok(())
+    }
+
+    fn process_set_frozen(program_id: &Pubkey, accounts: &[AccountInfo], frozen: bool) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let config_account = next_account_info(account_info_iter)?;
+        let admin = next_account_info(account_info_iter)?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(config_account)?;
+
+        let mut config = Self::load_config(program_id, config_account)?;
+        if config.admin != *admin.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        config.frozen = frozen;
+        config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+        msg!("Program {}", if frozen { "frozen" } else { "thawed" });
+        Ok(())
+    }
+
+    fn process_set_oracle_programs(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        pyth_program: Pubkey,
+        switchboard_program: Pubkey,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let config_account = next_account_info(account_info_iter)?;
+        let admin = next_account_info(account_info_iter)?;
+
+        if !admin.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        Self::check_writable(config_account)?;
+
+        let mut config = Self::load_config(program_id, config_account)?;
+        if config.admin != *admin.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        config.pyth_program = pyth_program;
+        config.switchboard_program = switchboard_program;
+        config.serialize(&mut &mut config_account.data.borrow_mut()[..])?;
+
+        msg!("Oracle programs set to {} (Pyth) and {} (Switchboard)", pyth_program, switchboard_program);
+        Ok(())
+    }
+
+    fn process_close_receipt(program_id: &Pubkey, accounts: &[AccountInfo], execution: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+        let receipt_account = next_account_info(account_info_iter)?;
+
+        if !authority.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        let agent = Self::load_agent(program_id, agent_account)?;
+        if agent.authority != *authority.key {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        let (address, _) = pda::find_receipt_address(program_id, agent_account.key, execution);
+        if address != *receipt_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        Self::check_owner(program_id, receipt_account)?;
+
+        Self::close_account(receipt_account, authority)?;
+
+        msg!("Receipt for execution {} closed", execution);
+        Ok(())
+    }
+
+    /// Check that `signer` is this program's upgrade authority, as recorded
+    /// in its ProgramData account
+    fn check_upgrade_authority(program_id: &Pubkey, program_data: &AccountInfo, signer: &AccountInfo) -> ProgramResult {
+        if *program_data.key != pda::find_program_data_address(program_id)
+            || *program_data.owner != bpf_loader_upgradeable::id()
+        {
+            return Err(AgentError::InvalidAccountData.into());
+        }
+
+        // ProgramData metadata: u32 variant (3), u64 slot, Option<Pubkey>
+        let data = program_data.data.borrow();
+        let upgrade_authority = match data.get(..UpgradeableLoaderState::size_of_programdata_metadata()) {
+            Some([3, 0, 0, 0, _, _, _, _, _, _, _, _, 1, authority @ ..]) => Pubkey::try_from(authority).ok(),
+            _ => None,
+        };
+        if upgrade_authority != Some(*signer.key) {
+            msg!("Program config must be created by the upgrade authority");
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        Ok(())
+    }
+
+    /// Reject executions while the program is frozen. Before the first
+    /// `SetAdmin` the config doesn't exist and nothing is frozen.
+    fn check_not_frozen(program_id: &Pubkey, config_account: &AccountInfo) -> ProgramResult {
+        if Self::load_config_or_default(program_id, config_account)?.frozen {
+            msg!("Program is frozen, executions are disabled");
+            return Err(AgentError::ProgramFrozen.into());
+        }
+        Ok(())
+    }
+
+    /// Load the program config, or the default settings before the first
+    /// `SetAdmin` created it
+    fn load_config_or_default(
+        program_id: &Pubkey,
+        config_account: &AccountInfo,
+    ) -> Result<ProgramConfig, ProgramError> {
+        if config_account.data_is_empty() {
+            let (address, _) = pda::find_config_address(program_id);
+            if address != *config_account.key {
+                return Err(AgentError::InvalidProgramAddress.into());
+            }
+            return Ok(ProgramConfig::default());
+        }
+        Self::load_config(program_id, config_account)
+    }
+
+    /// Load the program config, checking its owner and PDA derivation
+    fn load_config(program_id: &Pubkey, config_account: &AccountInfo) -> Result<ProgramConfig, ProgramError> {
+        Self::check_owner(program_id, config_account)?;
+
+        let config = ProgramConfig::unpack(&config_account.data.borrow())?;
+        if !config.is_initialized {
+            return Err(AgentError::NotInitialized.into());
+        }
+        config.verify_address(program_id, config_account.key)?;
+        Ok(config)
+    }
+
+    /// Invoke a CPI action signed by the agent PDA, provided the agent
+    /// allows its target program
+    fn invoke_cpi<'a>(
+        program_id: &Pubkey,
+        agent: &AgentAccount,
+        accounts: &[AccountInfo<'a>],
+        action: &CpiAction,
+    ) -> ProgramResult {
+        // Re-entering the agent program would bypass its own checks
+        if action.program_id == *program_id || !agent.config.allowed_programs.contains(&action.program_id) {
+            msg!("Program {} is not allowed for this agent", action.program_id);
+            return Err(AgentError::ProgramNotAllowed.into());
+        }
+
+        let seeds = AgentSeeds::new(&agent.creator, &agent.name, agent.bump);
+        invoke_signed(&action.instruction(), accounts, &[&seeds.as_seeds()])
+    }
+
+    /// Write fresh metadata for an agent created at `created_at`, creating
+    /// the account unless a closed agent at the same address left one
+    fn init_metadata<'a>(
+        program_id: &Pubkey,
+        agent: &Pubkey,
+        payer: &AccountInfo<'a>,
+        metadata_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        created_at: i64,
+    ) -> ProgramResult {
+        let (address, bump) = pda::find_metadata_address(program_id, agent);
+        if address != *metadata_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        Self::check_writable(metadata_account)?;
+
+        if metadata_account.data_is_empty() {
+            let seeds = MetadataSeeds::new(agent, bump);
+            cpi::create_pda_account(
+                payer,
+                metadata_account,
+                system_program,
+                Rent::get()?.minimum_balance(AgentMetadata::LEN),
+                AgentMetadata::LEN,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+        } else {
+            Self::check_owner(program_id, metadata_account)?;
+        }
+
+        AgentMetadata::new(*agent, created_at, bump).serialize(&mut &mut metadata_account.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// Record an update in an agent's metadata, creating it for agents
+    /// created before metadata existed
+    fn touch_metadata<'a>(
+        program_id: &Pubkey,
+        agent: &Pubkey,
+        payer: &AccountInfo<'a>,
+        metadata_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+    ) -> ProgramResult {
+        if metadata_account.data_is_empty() {
+            Self::check_writable(payer)?;
+            Self::init_metadata(program_id, agent, payer, metadata_account, system_program, 0)?;
+        }
+        Self::check_owner(program_id, metadata_account)?;
+        Self::check_writable(metadata_account)?;
+
+        let mut metadata = AgentMetadata::unpack(&metadata_account.data.borrow())?;
+        metadata.record_update(Clock::get()?.unix_timestamp);
+        metadata.serialize(&mut &mut metadata_account.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// SHA-256 of the data `program` returned from a CPI, zeroed if it
+    /// returned none
+    fn output_hash(program: &Pubkey) -> [u8; 32] {
+        match get_return_data() {
+            Some((returned_by, data)) if returned_by == *program => hash(&data).to_bytes(),
+            _ => [0; 32],
+        }
+    }
+
+    /// Add a result to an agent's result account, creating the account on
+    /// the first execution
+    fn record_result<'a>(
+        program_id: &Pubkey,
+        agent: &Pubkey,
+        payer: &AccountInfo<'a>,
+        result_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        result: ExecutionResult,
+    ) -> ProgramResult {
+        let (address, bump) = pda::find_result_address(program_id, agent);
+        if address != *result_account.key {
+            msg!("Expected result account {}", address);
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        let mut results = if result_account.data_is_empty() {
+            let seeds = ResultSeeds::new(agent, bump);
+            cpi::create_pda_account(
+                payer,
+                result_account,
+                system_program,
+                Rent::get()?.minimum_balance(ResultAccount::LEN),
+                ResultAccount::LEN,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+            ResultAccount::new(*agent, bump)
+        } else {
+            Self::check_owner(program_id, result_account)?;
+            ResultAccount::unpack(&result_account.data.borrow())?
+        };
+
+        results.push(result);
+        results.serialize(&mut &mut result_account.data.borrow_mut()[..])?;
+        Ok(())
+    }
+
+    /// Add an agent to its authority's registry, creating or growing the
+    /// registry as needed, and count it as created by the authority if
+    /// `created`. Returns the number of agents the authority has created,
+    /// the generation of a newly created agent.
+    fn register_agent<'a>(
+        program_id: &Pubkey,
+        authority: &AccountInfo<'a>,
+        registry_account: &AccountInfo<'a>,
+        system_program: &AccountInfo<'a>,
+        agent: &Pubkey,
+        created: bool,
+    ) -> Result<u64, ProgramError> {
+        let (address, bump) = pda::find_registry_address(program_id, authority.key);
+        if address != *registry_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        Self::check_writable(registry_account)?;
+
+        let mut registry = if registry_account.data_is_empty() {
+            let space = AgentRegistry::space(1);
+            let seeds = RegistrySeeds::new(authority.key, bump);
+            cpi::create_pda_account(
+                authority,
+                registry_account,
+                system_program,
+                Rent::get()?.minimum_balance(space),
+                space,
+                program_id,
+                &seeds.as_seeds(),
+            )?;
+            AgentRegistry {
+                authority: *authority.key,
+                bump,
+                created: 0,
+                agents: vec![],
+            }
+        } else {
+            Self::check_owner(program_id, registry_account)?;
+            AgentRegistry::unpack(&registry_account.data.borrow())?
+        };
+
+        registry.insert(*agent);
+        if created {
+            registry.created = registry.created.checked_add(1).ok_or(ProgramError::ArithmeticOverflow)?;
+        }
+        let space = AgentRegistry::space(registry.agents.len());
+        if registry_account.data_len() < space {
+            cpi::resize_account(authority, registry_account, system_program, space)?;
+        }
+        registry.serialize(&mut &mut registry_account.data.borrow_mut()[..])?;
+        Ok(registry.created)
+    }
+
+    /// Remove an agent from its authority's registry. Agents created before
+    /// registries existed may have none.
+    fn unregister_agent(
+        program_id: &Pubkey,
+        registry_account: &AccountInfo,
+        authority: &Pubkey,
+        agent: &Pubkey,
+    ) -> ProgramResult {
+        let (address, _) = pda::find_registry_address(program_id, authority);
+        if address != *registry_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+
+        if registry_account.data_is_empty() {
+            return Ok(());
+        }
+        Self::check_owner(program_id, registry_account)?;
+        Self::check_writable(registry_account)?;
+
+        let mut registry = AgentRegistry::unpack(&registry_account.data.borrow())?;
+        if registry.remove(agent) {
+            registry.serialize(&mut &mut registry_account.data.borrow_mut()[..])?;
+        }
+        Ok(())
+    }
+
+    /// Check that `signer` may act for the agent: either as its authority,
+    /// or as a delegate holding `permission` whose record is passed as the
+    /// last account
+    fn check_signer(
+        program_id: &Pubkey,
+        agent_account: &AccountInfo,
+        agent: &AgentAccount,
+        signer: &AccountInfo,
+        accounts: &[AccountInfo],
+        permission: u32,
+    ) -> ProgramResult {
+        if !signer.is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        if agent.authority == *signer.key {
+            return Ok(());
+        }
+
+        let record_account = accounts
+            .last()
+            .filter(|account| account.owner == program_id && account.key != agent_account.key)
+            .ok_or(AgentError::InvalidAuthority)?;
+        let record = DelegateRecord::unpack(&record_account.data.borrow())
+            .map_err(|_| AgentError::InvalidAuthority)?;
+        // Records issued by a previous authority lapse with the transfer,
+        // and those of a closed agent when it is recreated
+        if !record.is_initialized
+            || record.agent != *agent_account.key
+            || record.authority != agent.authority
+            || record.generation != agent.generation
+            || record.delegate != *signer.key
+        {
+            return Err(AgentError::InvalidAuthority.into());
+        }
+        record.verify_address(program_id, record_account.key)?;
+        record.check_permission(permission)?;
+        record.check_expiry(Clock::get()?.slot)?;
+        Ok(())
+    }
+
+    /// Check that `signer` may execute `action` for the agent: as anyone
+    /// `check_signer` accepts with `DELEGATE_EXECUTE`, or as one of its
+    /// executors unless the action moves the agent's funds. `None` stands
+    /// for an action not known yet, e.g. while it is staged.
+    fn check_executor(
+        program_id: &Pubkey,
+        agent_account: &AccountInfo,
+        agent: &AgentAccount,
+        signer: &AccountInfo,
+        accounts: &[AccountInfo],
+        action: Option<&AgentAction>,
+    ) -> ProgramResult {
+        if signer.is_signer && agent.executors.contains(signer.key) {
+            if !action.map_or(false, AgentAction::moves_funds) {
+                return Ok(());
+            }
+            msg!("Executors can't run actions moving the agent's funds");
+        }
+        Self::check_signer(program_id, agent_account, agent, signer, accounts, DELEGATE_EXECUTE)
+    }
+
+    /// Check that an account is program owned, rent exempt and can hold
+    /// `space` bytes
+    fn check_account(
+        program_id: &Pubkey,
+        account: &AccountInfo,
+        space: usize,
+        rent: &Rent,
+    ) -> ProgramResult {
+        Self::check_owner(program_id, account)?;
+
+        if account.data_len() < space {
+            msg!("Account holds {} bytes, {} required", account.data_len(), space);
+            return Err(AgentError::AccountTooSmall.into());
+        }
+
+        if !rent.is_exempt(account.lamports(), account.data_len()) {
+            return Err(AgentError::NotRentExempt.into());
+        }
+
+        Ok(())
+    }
+
+    /// Check that an account is owned by this program
+    fn check_owner(program_id: &Pubkey, account: &AccountInfo) -> ProgramResult {
+        if account.owner != program_id {
+            msg!("Account {} is owned by {}", account.key, account.owner);
+            return Err(AgentError::InvalidOwner.into());
+        }
+        Ok(())
+    }
+
+    /// Check that an account the instruction modifies was passed writable
+    fn check_writable(account: &AccountInfo) -> ProgramResult {
+        if !account.is_writable {
+            msg!("Account {} must be writable", account.key);
+            return Err(AgentError::AccountNotWritable.into());
+        }
+        Ok(())
+    }
+
+    /// Load an agent account, checking its owner and PDA derivation. Agents
+    /// in the zero-copy layout are cast in place rather than decoded.
+    fn load_agent(program_id: &Pubkey, agent_account: &AccountInfo) -> Result<AgentAccount, ProgramError> {
+        Self::check_owner(program_id, agent_account)?;
+
+        #[cfg(feature = "zero-copy")]
+        if let Ok(agent) = AgentAccountZc::load(&agent_account.data.borrow()).and_then(AgentAccountZc::to_account) {
+            agent.verify_address(program_id, agent_account.key)?;
+            return Ok(agent);
+        }
+
+        let agent = AgentAccount::unpack_at(&agent_account.data.borrow(), program_id, agent_account.key)?;
+        agent.verify_address(program_id, agent_account.key)?;
+        Ok(agent)
+    }
+
+    /// Load the parent of a child agent, which must be passed among
+    /// `accounts`; `None` once the parent has been closed
+    fn load_parent(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        parent: &Pubkey,
+    ) -> Result<Option<AgentAccount>, ProgramError> {
+        let parent_account = Self::account_by_key(accounts, parent)?;
+        if parent_account.data_is_empty() {
+            return Ok(None);
+        }
+        Self::load_agent(program_id, parent_account).map(Some)
+    }
+
+    /// Find an account the instruction passes anywhere among `accounts`
+    fn account_by_key<'a, 'b>(
+        accounts: &'b [AccountInfo<'a>],
+        key: &Pubkey,
+    ) -> Result<&'b AccountInfo<'a>, ProgramError> {
+        accounts
+            .iter()
+            .find(|account| account.key == key)
+            .ok_or(ProgramError::NotEnoughAccountKeys)
+    }
+
+    /// Load an agent account the instruction modifies
+    fn load_agent_mut(program_id: &Pubkey, agent_account: &AccountInfo) -> Result<AgentAccount, ProgramError> {
+        Self::check_writable(agent_account)?;
+        Self::load_agent(program_id, agent_account)
+    }
+
+    /// Save an agent after an execution. Agents in the zero-copy layout
+    /// only have their execution counters and metrics updated in place.
+    fn save_execution(agent: &AgentAccount, agent_account: &AccountInfo) -> ProgramResult {
+        #[cfg(feature = "zero-copy")]
+        if let Ok(stored) = AgentAccountZc::load_mut(&mut agent_account.data.borrow_mut()) {
+            stored.record_execution(agent);
+            return Ok(());
+        }
+
+        agent.save(agent_account)
+    }
+
+    /// Reject starting an agent without `MIN_AGENT_STAKE` in its escrow,
+    /// which must be passed among `accounts`
+    fn check_stake(program_id: &Pubkey, agent: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let (address, _) = pda::find_stake_address(program_id, agent);
+        let staked = match Self::account_by_key(accounts, &address) {
+            Ok(stake_account) if !stake_account.data_is_empty() => {
+                Self::check_owner(program_id, stake_account)?;
+                StakeAccount::unpack(&stake_account.data.borrow())?.amount
+            }
+            _ => 0,
+        };
+
+        if staked < MIN_AGENT_STAKE {
+            msg!("Agent has {} of {} lamports staked", staked, MIN_AGENT_STAKE);
+            return Err(AgentError::InsufficientStake.into());
+        }
+        Ok(())
+    }
+
+    /// Reject instructions needing capabilities the agent wasn't granted
+    fn check_capabilities(agent: &AgentAccount, required: CapabilityFlags) -> ProgramResult {
+        let missing = agent.config.capabilities.missing(required);
+        if !missing.is_empty() {
+            msg!("Agent lacks capabilities: {}", missing);
+            return Err(AgentError::MissingCapability.into());
+        }
+        Ok(())
+    }
+
+    /// Check that `staging_account` is the staging PDA of the agent and
+    /// `authority`, returning its bump
+    ///
+    /// Only the PDA is accepted: any other program-owned account, such as a
+    /// stake escrow, would otherwise be taken for a fresh staging account
+    /// and closed to the uploader on finalization.
+    fn check_staging_address(
+        program_id: &Pubkey,
+        agent_account: &AccountInfo,
+        authority: &AccountInfo,
+        staging_account: &AccountInfo,
+    ) -> Result<u8, ProgramError> {
+        let (address, bump) = pda::find_staging_address(program_id, agent_account.key, authority.key);
+        if address != *staging_account.key {
+            return Err(AgentError::InvalidProgramAddress.into());
+        }
+        Ok(bump)
+    }
+
+    fn staging_header(data: &[u8]) -> Result<StagingHeader, ProgramError> {
+        if data.len() < StagingHeader::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok(StagingHeader::deserialize(&mut &data[..StagingHeader::LEN])?)
+    }
+
+    /// Reject invalid configs, logging every violation
+    fn validate_config(config: &AgentConfig) -> ProgramResult {
+        if let Err(errors) = config.validate() {
+            for violation in &errors.0 {
+                msg!("Invalid config: {}", violation);
+            }
+            return Err(AgentError::InvalidConfiguration.into());
+        }
+        Ok(())
+    }
+
+    fn time_lock_elapsed(
+        clock: &Clock,
+        earliest_slot: Option<u64>,
+        earliest_unix_time: Option<i64>,
+    ) -> bool {
+        earliest_slot.map_or(true, |slot| clock.slot >= slot)
+            && earliest_unix_time.map_or(true, |time| clock.unix_timestamp >= time)
+    }
+
+    fn process_pause(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        reason: PauseReason,
+        resume_at: Option<i64>,
+    ) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_signer(program_id, agent_account, &agent, authority, accounts, DELEGATE_PAUSE)?;
+
+        // Agents that never ran can't be paused, so that the first resume
+        // is always the one checking the stake, and closed agents stay closed
+        if matches!(agent.state, AgentState::Initialized | AgentState::Terminated) {
+            return Err(AgentError::InvalidAgentState.into());
+        }
+
+        agent.pause(reason, resume_at);
+
+        // Accounts of older layouts grow to fit the pause details
+        let space = agent.required_space();
+        if agent_account.data_len() < space {
+            let system_program = next_account_info(account_info_iter)?;
+            Self::check_writable(authority)?;
+            cpi::resize_account(authority, agent_account, system_program, space)?;
+        }
+
+        agent.save(agent_account)?;
+        AgentPaused {
+            agent: *agent_account.key,
+            signer: *authority.key,
+            reason,
+            resume_at,
+        }
+        .emit();
+        msg!("Agent paused successfully");
+        Ok(())
+    }
+
+    fn process_resume(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let agent_account = next_account_info(account_info_iter)?;
+        let authority = next_account_info(account_info_iter)?;
+
+        let mut agent = Self::load_agent_mut(program_id, agent_account)?;
+        Self::check_signer(program_id, agent_account, &agent, authority, accounts, DELEGATE_RESUME)?;
+
+        // A closed agent may already have been unstaked within the closing
+        // transaction, so it can't be brought back by refunding its rent
+        match agent.state {
+            AgentState::Terminated => return Err(AgentError::InvalidAgentState.into()),
+            AgentState::Initialized => Self::check_stake(program_id, agent_account.key, accounts)?,
+            _ => {}
+        }
+
+        agent.resume();
+        agent.save(agent_account)?;
+        AgentResumed {
+            agent: *agent_account.key,
+            signer: *authority.key,
+        }
+        .emit();
+        msg!("Agent resumed successfully");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::clock::Epoch;
+    use crate::solana::program::action::CpiAccount;
+    use crate::test_utils::v1_agent_data;
+
+    /// Agent PDA and account data sized for the agent's largest layout
+    fn agent_fixture(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, Vec<u8>) {
+        let config = AgentConfig {
+            autonomous_mode: false,
+            execution_limit: 10,
+            memory_limit: 5_000,
+            capabilities: CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+        let (address, bump) = pda::find_agent_address(program_id, authority, "agent");
+
+        let mut agent = AgentAccount::new(*authority, "agent".to_string(), config.clone());
+        agent.bump = bump;
+        let mut data = borsh::to_vec(&agent).unwrap();
+        data.resize(AgentAccount::space("agent", &config), 0);
+        (address, data)
+    }
+
+    #[test]
+    fn test_write_chunk() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (staging_key, _) = pda::find_staging_address(&program_id, &agent_key, &authority_key);
+        let mut staging_data = vec![0u8; StagingHeader::space(8)];
+        let (mut agent_lamports, mut authority_lamports, mut staging_lamports) = (0, 0, 0);
+        let (mut authority_data, mut system_data) = (vec![], vec![]);
+        let (system, mut system_lamports) = (system_program::id(), 0);
+
+        let mut accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&staging_key, false, true, &mut staging_lamports, &mut staging_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+        ];
+
+        let required = AgentInstruction::WriteChunk { offset: 0, data: vec![] }.required_capabilities();
+        Processor::process_write_chunk(&program_id, &accounts, 0, vec![1, 2, 3, 4], required).unwrap();
+        // Gaps and uploads past the memory limit are rejected, rewrites are not
+        assert_eq!(
+            Processor::process_write_chunk(&program_id, &accounts, 5, vec![6], required),
+            Err(AgentError::InvalidChunk.into())
+        );
+        assert_eq!(
+            Processor::process_write_chunk(&program_id, &accounts, 4, vec![0; 5_000], required),
+            Err(AgentError::MemoryLimitExceeded.into())
+        );
+        Processor::process_write_chunk(&program_id, &accounts, 2, vec![3, 4, 5, 6], required).unwrap();
+
+        // Agents can't use instructions they lack the capabilities for
+        assert_eq!(
+            Processor::process_write_chunk(&program_id, &accounts, 6, vec![7], CapabilityFlags::TRADING),
+            Err(AgentError::MissingCapability.into())
+        );
+
+        {
+            let staging = accounts[2].data.borrow();
+            let header = Processor::staging_header(&staging).unwrap();
+            assert_eq!(header.written, 6);
+            assert_eq!(header.agent, agent_key);
+            assert_eq!(&staging[StagingHeader::LEN..StagingHeader::LEN + 6], &[1, 2, 3, 4, 5, 6]);
+        }
+
+        // Only the staging PDA is written to, so no other program-owned
+        // account, e.g. a stake escrow, can be taken for a staging account
+        let escrow_key = pda::find_stake_address(&program_id, &agent_key).0;
+        let mut escrow_data = vec![0u8; StakeAccount::LEN];
+        let mut escrow_lamports = 1_000_000;
+        accounts[2] = AccountInfo::new(&escrow_key, false, true, &mut escrow_lamports, &mut escrow_data, &program_id, false, Epoch::default());
+        assert_eq!(
+            Processor::process_write_chunk(&program_id, &accounts, 0, vec![1], required),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+        let finalize_accounts = [
+            accounts[0].clone(),
+            accounts[1].clone(),
+            accounts[3].clone(),
+            accounts[2].clone(),
+        ];
+        assert_eq!(
+            Processor::process_finalize_execute(&program_id, &finalize_accounts, 1, [0; 32], required),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+    }
+
+    #[test]
+    fn test_close() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (registry_key, bump) = pda::find_registry_address(&program_id, &authority_key);
+        let other_agent = Pubkey::new_unique();
+        let mut registry_data = borsh::to_vec(&AgentRegistry {
+            authority: authority_key,
+            bump,
+            created: 2,
+            agents: vec![agent_key, other_agent],
+        })
+        .unwrap();
+        let (pending_key, pending_bump) = pda::find_pending_update_address(&program_id, &agent_key);
+        let mut pending_data = borsh::to_vec(&PendingUpdate {
+            agent: agent_key,
+            config: None,
+            update_delay: Some(0),
+            activation_slot: 1_000,
+            bump: pending_bump,
+        })
+        .unwrap();
+        let (mut agent_lamports, mut authority_lamports, mut registry_lamports, mut pending_lamports) = (1_000, 5, 1, 100);
+        let mut authority_data = vec![];
+        let system = system_program::id();
+
+        let accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&registry_key, false, true, &mut registry_lamports, &mut registry_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&pending_key, false, true, &mut pending_lamports, &mut pending_data, &program_id, false, Epoch::default()),
+        ];
+
+        // The queued update is discarded with the agent
+        Processor::process_close(&program_id, &accounts).unwrap();
+        assert_eq!(accounts[0].lamports(), 0);
+        assert_eq!(accounts[1].lamports(), 1_105);
+        assert_eq!(accounts[3].lamports(), 0);
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(agent.state, AgentState::Terminated);
+        let registry = AgentRegistry::unpack(&accounts[2].data.borrow()).unwrap();
+        assert_eq!(registry.agents, vec![other_agent]);
+    }
+
+    #[test]
+    fn test_close_receipt() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (receipt_key, _) = pda::find_receipt_address(&program_id, &agent_key, 3);
+        let mut receipt_data = vec![1u8; ExecutionReceipt::LEN];
+        let (mut agent_lamports, mut authority_lamports, mut receipt_lamports, mut other_lamports) = (0, 5, 1_000, 0);
+        let (mut authority_data, mut other_data) = (vec![], vec![]);
+        let system = system_program::id();
+
+        let mut accounts = vec![
+            AccountInfo::new(&agent_key, false, false, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&other_key, true, true, &mut other_lamports, &mut other_data, &system, false, Epoch::default()),
+            AccountInfo::new(&receipt_key, false, true, &mut receipt_lamports, &mut receipt_data, &program_id, false, Epoch::default()),
+        ];
+        assert_eq!(
+            Processor::process_close_receipt(&program_id, &accounts, 3),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        accounts[1] = AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default());
+        assert_eq!(
+            Processor::process_close_receipt(&program_id, &accounts, 4),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+        Processor::process_close_receipt(&program_id, &accounts, 3).unwrap();
+        assert_eq!(accounts[1].lamports(), 1_005);
+        assert_eq!(accounts[2].lamports(), 0);
+        assert!(accounts[2].data.borrow().iter().all(|&byte| byte == 0));
+    }
+
+    /// Registry of `authority` holding `agents`, with room for one more
+    fn registry_fixture(program_id: &Pubkey, authority: &Pubkey, agents: Vec<Pubkey>) -> (Pubkey, Vec<u8>) {
+        let (address, bump) = pda::find_registry_address(program_id, authority);
+        let space = AgentRegistry::space(agents.len() + 1);
+        let mut data = borsh::to_vec(&AgentRegistry { authority: *authority, bump, created: 0, agents }).unwrap();
+        data.resize(space, 0);
+        (address, data)
+    }
+
+    #[test]
+    fn test_transfer_authority() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, new_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (registry_key, mut registry_data) = registry_fixture(&program_id, &authority_key, vec![agent_key]);
+        let (new_registry_key, mut new_registry_data) = registry_fixture(&program_id, &new_key, vec![]);
+        let (mut agent_lamports, mut authority_lamports, mut new_lamports) = (0, 0, 0);
+        let (mut registry_lamports, mut new_registry_lamports, mut system_lamports) = (1, 1, 0);
+        let (mut authority_data, mut new_data, mut system_data) = (vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let agent = AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default());
+        let authority = AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default());
+        let new_authority = AccountInfo::new(&new_key, true, true, &mut new_lamports, &mut new_data, &system, false, Epoch::default());
+        let registry = AccountInfo::new(&registry_key, false, true, &mut registry_lamports, &mut registry_data, &program_id, false, Epoch::default());
+        let new_registry = AccountInfo::new(&new_registry_key, false, true, &mut new_registry_lamports, &mut new_registry_data, &program_id, false, Epoch::default());
+        let system_account = AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default());
+        let registered = |registry: &AccountInfo| AgentRegistry::unpack(&registry.data.borrow()).unwrap().agents;
+
+        // Without the new authority's signature the transfer waits for acceptance
+        Processor::process_transfer_authority(&program_id, &[agent.clone(), authority.clone()], new_key).unwrap();
+        let state = AgentAccount::unpack(&agent.data.borrow()).unwrap();
+        assert_eq!(state.authority, authority_key);
+        assert_eq!(state.pending_authority, Some(new_key));
+        assert_eq!(registered(&registry), vec![agent_key]);
+
+        let accept = |signer, from, to| {
+            let accounts = [agent.clone(), signer, from, to, system_account.clone()];
+            Processor::process_accept_authority(&program_id, &accounts)
+        };
+        assert_eq!(accept(authority.clone(), registry.clone(), new_registry.clone()), Err(AgentError::InvalidAuthority.into()));
+        // The agent leaves the registry of its current authority only
+        assert_eq!(accept(new_authority.clone(), new_registry.clone(), registry.clone()), Err(AgentError::InvalidProgramAddress.into()));
+        accept(new_authority.clone(), registry.clone(), new_registry.clone()).unwrap();
+        let state = AgentAccount::unpack(&agent.data.borrow()).unwrap();
+        assert_eq!(state.authority, new_key);
+        assert_eq!(state.pending_authority, None);
+        assert!(registered(&registry).is_empty());
+        assert_eq!(registered(&new_registry), vec![agent_key]);
+
+        // The old authority no longer controls the agent
+        assert_eq!(
+            Processor::process_transfer_authority(&program_id, &[agent.clone(), authority.clone()], authority_key),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        // With both signatures the transfer is immediate
+        let accounts = [agent.clone(), new_authority, authority, new_registry.clone(), registry.clone(), system_account];
+        Processor::process_transfer_authority(&program_id, &accounts, authority_key).unwrap();
+        let state = AgentAccount::unpack(&agent.data.borrow()).unwrap();
+        assert_eq!(state.authority, authority_key);
+        assert!(registered(&new_registry).is_empty());
+        assert_eq!(registered(&registry), vec![agent_key]);
+    }
+
+    #[test]
+    fn test_load_agent_rejects_spoofed_accounts() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (mut spoof_data, spoof_key) = (agent_data.clone(), Pubkey::new_unique());
+        let (mut lamports, mut spoof_lamports) = (0, 0);
+
+        let agent = AccountInfo::new(&agent_key, false, true, &mut lamports, &mut agent_data, &program_id, false, Epoch::default());
+        assert!(Processor::load_agent(&program_id, &agent).is_ok());
+
+        let spoofed = AccountInfo::new(&spoof_key, false, true, &mut spoof_lamports, &mut spoof_data, &program_id, false, Epoch::default());
+        assert_eq!(
+            Processor::load_agent(&program_id, &spoofed).unwrap_err(),
+            AgentError::InvalidProgramAddress.into()
+        );
+    }
+
+    #[test]
+    fn test_handlers_reject_spoofed_owner() {
+        let program_id = Pubkey::new_unique();
+        let attacker = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let other_key = Pubkey::new_unique();
+
+        // A byte-for-byte copy of a valid agent at the right address, but
+        // owned by another program
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (staging_key, _) = pda::find_staging_address(&program_id, &agent_key, &authority_key);
+        let mut staging_data = vec![0u8; StagingHeader::space(8)];
+        let (mut agent_lamports, mut authority_lamports, mut staging_lamports, mut other_lamports) = (0, 0, 0, 0);
+        let (mut authority_data, mut other_data, mut system_data) = (vec![], vec![], vec![]);
+        let (system, mut system_lamports) = (system_program::id(), 0);
+
+        let accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &attacker, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&staging_key, false, true, &mut staging_lamports, &mut staging_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&other_key, false, true, &mut other_lamports, &mut other_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+        ];
+        let config = AgentConfig {
+            autonomous_mode: false,
+            execution_limit: 10,
+            memory_limit: 5_000,
+            capabilities: CapabilityFlags::COMPUTE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+        let spoofed: ProgramResult = Err(AgentError::InvalidOwner.into());
+        let required = CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE;
+
+        assert_eq!(Processor::process_update(&program_id, &accounts, config.clone()), spoofed);
+        assert_eq!(Processor::process_execute(&program_id, &accounts, AgentAction::Noop, None, required), spoofed);
+        assert_eq!(Processor::process_write_chunk(&program_id, &accounts, 0, vec![1], required), spoofed);
+        assert_eq!(Processor::process_pause(&program_id, &accounts, PauseReason::Manual, None), spoofed);
+        assert_eq!(Processor::process_resume(&program_id, &accounts), spoofed);
+        assert_eq!(Processor::process_close(&program_id, &accounts), spoofed);
+        assert_eq!(Processor::process_transfer_authority(&program_id, &accounts, other_key), spoofed);
+        assert_eq!(Processor::process_accept_authority(&program_id, &accounts), spoofed);
+        assert_eq!(Processor::process_migrate(&program_id, &accounts), spoofed);
+        assert_eq!(Processor::process_delegate(&program_id, &accounts, other_key, 10, DELEGATE_ALL), spoofed);
+        assert_eq!(Processor::process_add_executor(&program_id, &accounts, other_key), spoofed);
+        assert_eq!(Processor::process_remove_executor(&program_id, &accounts, other_key), spoofed);
+        // The clone source is the fifth account, here not owned by the program
+        assert_eq!(Processor::process_clone_agent(&program_id, &accounts, "clone".to_string()), spoofed);
+        // So is the parent of a spawned child
+        assert_eq!(Processor::process_spawn_child(&program_id, &accounts, "child".to_string(), config), spoofed);
+
+        // Staging accounts must belong to the program too
+        let mut agent_data = agent_fixture(&program_id, &authority_key).1;
+        let mut agent_lamports = 0;
+        let mut accounts = accounts;
+        accounts[0] = AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default());
+        accounts[2].owner = &attacker;
+        assert_eq!(Processor::process_write_chunk(&program_id, &accounts, 0, vec![1], required), spoofed);
+        let finalize_accounts = [
+            accounts[0].clone(),
+            accounts[1].clone(),
+            accounts[3].clone(),
+            accounts[2].clone(),
+            accounts[3].clone(),
+            accounts[4].clone(),
+        ];
+        assert_eq!(Processor::process_finalize_execute(&program_id, &finalize_accounts, 1, [0; 32], required), spoofed);
+    }
+
+    #[test]
+    fn test_handlers_require_writable_accounts() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (registry_key, staging_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let mut staging_data = vec![0u8; StagingHeader::space(8)];
+        let (mut agent_lamports, mut authority_lamports, mut registry_lamports, mut staging_lamports) = (0, 0, 0, 0);
+        let (mut authority_data, mut registry_data, mut system_data) = (vec![], vec![], vec![]);
+        let (system, mut system_lamports) = (system_program::id(), 0);
+        let not_writable: ProgramResult = Err(AgentError::AccountNotWritable.into());
+        let config = AgentAccount::unpack(&agent_data).unwrap().config;
+
+        // Read-only agent
+        let mut accounts = vec![
+            AccountInfo::new(&agent_key, false, false, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, false, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&registry_key, false, false, &mut registry_lamports, &mut registry_data, &program_id, false, Epoch::default()),
+        ];
+        assert_eq!(Processor::process_pause(&program_id, &accounts, PauseReason::Manual, None), not_writable);
+        assert_eq!(Processor::process_update(&program_id, &accounts, config), not_writable);
+
+        // Read-only authority receiving the rent of a closed agent
+        accounts[0].is_writable = true;
+        assert_eq!(Processor::process_close(&program_id, &accounts), not_writable);
+        assert_eq!(
+            AgentAccount::unpack(&accounts[0].data.borrow()).unwrap().state,
+            AgentState::Initialized
+        );
+
+        // Read-only staging account
+        accounts[2] = AccountInfo::new(&staging_key, false, false, &mut staging_lamports, &mut staging_data, &program_id, false, Epoch::default());
+        accounts.push(AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()));
+        let required = CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE;
+        assert_eq!(Processor::process_write_chunk(&program_id, &accounts, 0, vec![1], required), not_writable);
+    }
+
+    #[test]
+    fn test_update_needs_system_program_to_grow() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (mut agent_lamports, mut authority_lamports) = (0, 0);
+        let mut authority_data = vec![];
+        let system = system_program::id();
+
+        // Accounts created by older versions have no room reserved for a
+        // pending authority
+        let stored = AgentAccount::unpack(&agent_data).unwrap();
+        agent_data.truncate(borsh::to_vec(&stored).unwrap().len());
+        let mut full_data = agent_data.clone();
+        full_data.resize(AgentAccount::space(&stored.name, &stored.config), 0);
+        let mut full_lamports = 0;
+
+        let config = AgentConfig {
+            autonomous_mode: true,
+            execution_limit: 20,
+            memory_limit: 5_000,
+            capabilities: CapabilityFlags::COMPUTE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+
+        // Fits in an account sized for the current layout
+        let accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut full_lamports, &mut full_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+        ];
+        Processor::process_update(&program_id, &accounts, config.clone()).unwrap();
+        assert_eq!(AgentAccount::unpack(&accounts[0].data.borrow()).unwrap().config.execution_limit, 20);
+        drop(accounts);
+
+        // Growing requires the system program to fund the realloc
+        let accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+        ];
+        assert_eq!(
+            Processor::process_update(&program_id, &accounts, config),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+    }
+
+    #[test]
+    fn test_delegate_permissions() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, session_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (record_key, bump) = pda::find_delegate_address(&program_id, &agent_key, &authority_key, &session_key);
+        let mut record_data = borsh::to_vec(&DelegateRecord {
+            is_initialized: true,
+            agent: agent_key,
+            authority: authority_key,
+            delegate: session_key,
+            expiry_slot: u64::MAX,
+            permissions: DELEGATE_EXECUTE,
+            bump,
+            generation: 0,
+        })
+        .unwrap();
+        let (mut agent_lamports, mut session_lamports, mut record_lamports) = (0, 0, 0);
+        let mut session_data = vec![];
+        let system = system_program::id();
+
+        let agent = AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default());
+        let session = AccountInfo::new(&session_key, true, true, &mut session_lamports, &mut session_data, &system, false, Epoch::default());
+        let record = AccountInfo::new(&record_key, false, false, &mut record_lamports, &mut record_data, &program_id, false, Epoch::default());
+
+        // Without its record a session key is just another wallet
+        assert_eq!(
+            Processor::process_pause(&program_id, &[agent.clone(), session.clone()], PauseReason::Manual, None),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        // An execute-only key can't pause, and never closes the agent
+        assert_eq!(
+            Processor::process_pause(&program_id, &[agent.clone(), session.clone(), record.clone()], PauseReason::Manual, None),
+            Err(AgentError::Unauthorized.into())
+        );
+        assert_eq!(
+            Processor::process_close(&program_id, &[agent.clone(), session.clone(), record.clone()]),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        // A record issued for another key isn't honored
+        let (other_key, mut other_lamports, mut other_data) = (Pubkey::new_unique(), 0, vec![]);
+        let other = AccountInfo::new(&other_key, true, true, &mut other_lamports, &mut other_data, &system, false, Epoch::default());
+        assert_eq!(
+            Processor::process_pause(&program_id, &[agent.clone(), other, record], PauseReason::Manual, None),
+            Err(AgentError::InvalidAuthority.into())
+        );
+    }
+
+    #[test]
+    fn test_pause_records_reason() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let mut agent = AgentAccount::unpack(&agent_data).unwrap();
+        agent.state = AgentState::Running;
+        agent.serialize(&mut &mut agent_data[..]).unwrap();
+        let (mut agent_lamports, mut authority_lamports, mut authority_data) = (0, 0, vec![]);
+        let system = system_program::id();
+
+        let accounts = [
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, false, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+        ];
+
+        Processor::process_pause(&program_id, &accounts, PauseReason::RiskLimit, Some(1_700_000_000)).unwrap();
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(agent.state, AgentState::Paused);
+        assert_eq!(agent.pause_reason, Some(PauseReason::RiskLimit));
+        assert_eq!(agent.resume_at, Some(1_700_000_000));
+
+        Processor::process_resume(&program_id, &accounts).unwrap();
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(agent.state, AgentState::Running);
+        assert_eq!(agent.pause_reason, None);
+    }
+
+    #[test]
+    fn test_v1_account_at_keypair_address() {
+        let program_id = Pubkey::new_unique();
+        let (legacy_key, authority_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, _) = pda::find_agent_address(&program_id, &authority_key, "legacy");
+        let wrong_key = pda::find_agent_address(&program_id, &authority_key, "other").0;
+
+        let mut legacy_data = v1_agent_data(&authority_key, "legacy");
+        let (mut legacy_lamports, mut authority_lamports, mut other_lamports) = (1_000, 0, 0);
+        let (mut agent_lamports, mut wrong_lamports, mut system_lamports) = (0, 0, 0);
+        let (mut authority_data, mut other_data, mut agent_data, mut wrong_data, mut system_data) =
+            (vec![], vec![], vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let legacy = AccountInfo::new(&legacy_key, false, true, &mut legacy_lamports, &mut legacy_data, &program_id, false, Epoch::default());
+        let authority = AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default());
+        let other = AccountInfo::new(&other_key, true, true, &mut other_lamports, &mut other_data, &system, false, Epoch::default());
+        let system_program = AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default());
+        let agent = AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &system, false, Epoch::default());
+        let wrong = AccountInfo::new(&wrong_key, false, true, &mut wrong_lamports, &mut wrong_data, &system, false, Epoch::default());
+
+        // Other instructions refuse the agent until it is moved into its PDA
+        assert_eq!(
+            Processor::process_pause(&program_id, &[legacy.clone(), authority.clone()], PauseReason::Manual, None),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+
+        // Only its authority moves it, and only into its own PDA
+        let migrate = |signer, target| {
+            Processor::process_migrate(&program_id, &[legacy.clone(), signer, system_program.clone(), target])
+        };
+        assert_eq!(migrate(other.clone(), agent.clone()), Err(AgentError::InvalidAuthority.into()));
+        assert_eq!(migrate(authority.clone(), wrong.clone()), Err(AgentError::InvalidProgramAddress.into()));
+        assert_eq!(
+            Processor::process_migrate(&program_id, &[legacy.clone(), authority.clone(), system_program.clone()]),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        assert_eq!(AgentAccount::unpack_v1(&legacy.data.borrow()).unwrap().execution_count, 4);
+    }
+
+    #[test]
+    fn test_stake_gates_activation() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, staker_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (stake_key, bump) = pda::find_stake_address(&program_id, &agent_key);
+        let mut stake = StakeAccount::new(agent_key, staker_key, bump);
+        stake.amount = MIN_AGENT_STAKE - 1;
+        let mut stake_data = borsh::to_vec(&stake).unwrap();
+        let (mut agent_lamports, mut authority_lamports, mut staker_lamports) = (0, 0, 0);
+        let mut stake_lamports = MIN_AGENT_STAKE + 1_000;
+        let (mut authority_data, mut staker_data) = (vec![], vec![]);
+        let system = system_program::id();
+
+        let mut accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, false, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&stake_key, false, true, &mut stake_lamports, &mut stake_data, &program_id, false, Epoch::default()),
+        ];
+        let insufficient: ProgramResult = Err(AgentError::InsufficientStake.into());
+
+        // A new agent can be neither paused nor started without its stake
+        assert_eq!(
+            Processor::process_pause(&program_id, &accounts, PauseReason::Manual, None),
+            Err(AgentError::InvalidAgentState.into())
+        );
+        assert_eq!(Processor::process_resume(&program_id, &accounts[..2]), insufficient);
+        assert_eq!(Processor::process_resume(&program_id, &accounts), insufficient);
+
+        stake.amount = MIN_AGENT_STAKE;
+        stake.serialize(&mut &mut accounts[2].data.borrow_mut()[..]).unwrap();
+        Processor::process_resume(&program_id, &accounts).unwrap();
+        assert_eq!(AgentAccount::unpack(&accounts[0].data.borrow()).unwrap().state, AgentState::Running);
+
+        // The stake stays locked while the agent lives, and only the
+        // staker gets it back
+        let staker = AccountInfo::new(&staker_key, true, true, &mut staker_lamports, &mut staker_data, &system, false, Epoch::default());
+        accounts[1] = staker;
+        assert_eq!(Processor::process_unstake(&program_id, &accounts), Err(AgentError::StakeLocked.into()));
+        let mut impostor = accounts.clone();
+        impostor[1].key = &authority_key;
+        assert_eq!(Processor::process_unstake(&program_id, &impostor), Err(AgentError::InvalidAuthority.into()));
+
+        let mut agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        agent.update_state(AgentState::Terminated).unwrap();
+        agent.serialize(&mut &mut accounts[0].data.borrow_mut()[..]).unwrap();
+        Processor::process_unstake(&program_id, &accounts).unwrap();
+        assert_eq!(accounts[1].lamports(), MIN_AGENT_STAKE + 1_000);
+        assert_eq!(accounts[2].lamports(), 0);
+    }
+
+    #[test]
+    fn test_closed_agent_stays_closed() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let mut agent = AgentAccount::unpack(&agent_data).unwrap();
+        agent.state = AgentState::Running;
+        agent.serialize(&mut &mut agent_data[..]).unwrap();
+        let (registry_key, _) = pda::find_registry_address(&program_id, &authority_key);
+        let (stake_key, bump) = pda::find_stake_address(&program_id, &agent_key);
+        let mut stake = StakeAccount::new(agent_key, authority_key, bump);
+        stake.amount = MIN_AGENT_STAKE;
+        let mut stake_data = borsh::to_vec(&stake).unwrap();
+        let (pending_key, _) = pda::find_pending_update_address(&program_id, &agent_key);
+        let (mut agent_lamports, mut authority_lamports, mut registry_lamports, mut pending_lamports) = (1_000, 0, 0, 0);
+        let mut stake_lamports = MIN_AGENT_STAKE + 1_000;
+        let (mut authority_data, mut registry_data, mut pending_data) = (vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let agent = AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default());
+        let authority = AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default());
+        let registry = AccountInfo::new(&registry_key, false, true, &mut registry_lamports, &mut registry_data, &system, false, Epoch::default());
+        let stake_account = AccountInfo::new(&stake_key, false, true, &mut stake_lamports, &mut stake_data, &program_id, false, Epoch::default());
+        let pending = AccountInfo::new(&pending_key, false, true, &mut pending_lamports, &mut pending_data, &system, false, Epoch::default());
+
+        // Close, unstake and refund the rent within one transaction
+        Processor::process_close(&program_id, &[agent.clone(), authority.clone(), registry, pending]).unwrap();
+        Processor::process_unstake(&program_id, &[agent.clone(), authority.clone(), stake_account.clone()]).unwrap();
+        **agent.lamports.borrow_mut() = 1_000;
+
+        let accounts = [agent.clone(), authority, stake_account];
+        let invalid_state: ProgramResult = Err(AgentError::InvalidAgentState.into());
+        assert_eq!(Processor::process_resume(&program_id, &accounts), invalid_state);
+        assert_eq!(Processor::process_pause(&program_id, &accounts, PauseReason::Manual, None), invalid_state);
+        assert_eq!(AgentAccount::unpack(&agent.data.borrow()).unwrap().state, AgentState::Terminated);
+    }
+
+    #[test]
+    fn test_stake_escrow_belongs_to_authority() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, payer_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (stake_key, bump) = pda::find_stake_address(&program_id, &agent_key);
+        let (mut agent_lamports, mut payer_lamports, mut stake_lamports, mut system_lamports) = (0, 10_000, 0, 0);
+        let (mut payer_data, mut stake_data, mut system_data) = (vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let accounts = [
+            AccountInfo::new(&agent_key, false, false, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&payer_key, true, true, &mut payer_lamports, &mut payer_data, &system, false, Epoch::default()),
+            AccountInfo::new(&stake_key, false, true, &mut stake_lamports, &mut stake_data, &system, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+        ];
+
+        // Nobody but the authority opens the escrow, so nobody else owns it
+        assert_eq!(Processor::process_stake(&program_id, &accounts, 1), Err(AgentError::InvalidAuthority.into()));
+
+        // Anyone tops up the authority's escrow
+        let mut accounts = accounts;
+        let mut opened_data = borsh::to_vec(&StakeAccount::new(agent_key, authority_key, bump)).unwrap();
+        let mut opened_lamports = 1_000;
+        accounts[2] = AccountInfo::new(&stake_key, false, true, &mut opened_lamports, &mut opened_data, &program_id, false, Epoch::default());
+        Processor::process_stake(&program_id, &accounts, 500).unwrap();
+        let stake = StakeAccount::unpack(&accounts[2].data.borrow()).unwrap();
+        assert_eq!((stake.staker, stake.amount), (authority_key, 500));
+    }
+
+    #[test]
+    fn test_marketplace() {
+        let program_id = Pubkey::new_unique();
+        let (seller_key, buyer_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &seller_key);
+        let (listing_key, bump) = pda::find_listing_address(&program_id, &agent_key);
+        let listing = Listing { agent: agent_key, seller: seller_key, price: 5_000, bump };
+        let mut listing_data = borsh::to_vec(&listing).unwrap();
+        let (pending_key, pending_bump) = pda::find_pending_update_address(&program_id, &agent_key);
+        let mut pending_data = borsh::to_vec(&PendingUpdate {
+            agent: agent_key,
+            config: None,
+            update_delay: Some(0),
+            activation_slot: 1_000,
+            bump: pending_bump,
+        })
+        .unwrap();
+        let (seller_registry_key, mut seller_registry_data) = registry_fixture(&program_id, &seller_key, vec![agent_key]);
+        let (buyer_registry_key, mut buyer_registry_data) = registry_fixture(&program_id, &buyer_key, vec![]);
+        let (stake_key, stake_bump) = pda::find_stake_address(&program_id, &agent_key);
+        let mut stake = StakeAccount::new(agent_key, seller_key, stake_bump);
+        stake.amount = MIN_AGENT_STAKE;
+        let mut stake_data = borsh::to_vec(&stake).unwrap();
+        let (mut agent_lamports, mut seller_lamports, mut buyer_lamports, mut system_lamports) = (0, 0, 10_000, 0);
+        let (mut listing_lamports, mut pending_lamports) = (1_000, 2_000);
+        let (mut seller_registry_lamports, mut buyer_registry_lamports, mut stake_lamports) = (1, 1, MIN_AGENT_STAKE);
+        let (mut seller_data, mut buyer_data, mut system_data) = (vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let mut accounts = vec![
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&buyer_key, true, true, &mut buyer_lamports, &mut buyer_data, &system, false, Epoch::default()),
+            AccountInfo::new(&seller_key, true, true, &mut seller_lamports, &mut seller_data, &system, false, Epoch::default()),
+            AccountInfo::new(&listing_key, false, true, &mut listing_lamports, &mut listing_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+            AccountInfo::new(&pending_key, false, true, &mut pending_lamports, &mut pending_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&seller_registry_key, false, true, &mut seller_registry_lamports, &mut seller_registry_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&buyer_registry_key, false, true, &mut buyer_registry_lamports, &mut buyer_registry_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&stake_key, false, true, &mut stake_lamports, &mut stake_data, &program_id, false, Epoch::default()),
+        ];
+        // The seller opened the stake escrow
+        accounts.push(accounts[2].clone());
+        let list_accounts = [accounts[0].clone(), accounts[2].clone(), accounts[4].clone(), accounts[3].clone()];
+        let set_agent = |update: &dyn Fn(&mut AgentAccount)| {
+            let mut agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+            update(&mut agent);
+            agent.serialize(&mut &mut accounts[0].data.borrow_mut()[..]).unwrap();
+        };
+
+        set_agent(&|agent| agent.state = AgentState::Paused);
+        assert_eq!(
+            Processor::process_list_for_sale(&program_id, &list_accounts, 5_000),
+            Err(AgentError::InvalidAgentState.into())
+        );
+
+        // As left by a listing: held by the listing, with the seller's executor
+        set_agent(&|agent| {
+            agent.state = AgentState::Running;
+            agent.authority = listing_key;
+            agent.executors = vec![seller_key];
+        });
+        assert_eq!(Processor::process_purchase(&program_id, &accounts, 4_000), Err(AgentError::PriceMismatch.into()));
+        let mut wrong_pending = accounts.clone();
+        wrong_pending[5] = accounts[3].clone();
+        assert_eq!(
+            Processor::process_purchase(&program_id, &wrong_pending, 5_000),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+        // Only the staker recorded in the escrow is refunded
+        let mut wrong_staker = accounts.clone();
+        wrong_staker[9] = accounts[1].clone();
+        assert_eq!(
+            Processor::process_purchase(&program_id, &wrong_staker, 5_000),
+            Err(AgentError::InvalidAuthority.into())
+        );
+        let cancel_accounts = [accounts[0].clone(), accounts[1].clone(), accounts[3].clone()];
+        assert_eq!(
+            Processor::process_cancel_listing(&program_id, &cancel_accounts),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        Processor::process_purchase(&program_id, &accounts, 5_000).unwrap();
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(agent.authority, buyer_key);
+        assert!(agent.executors.is_empty());
+        assert_eq!((accounts[2].lamports(), accounts[3].lamports()), (1_000 + 2_000, 0));
+        // The seller's queued update was cancelled with the sale
+        assert_eq!(accounts[5].lamports(), 0);
+        assert!(accounts[5].data.borrow().iter().all(|byte| *byte == 0));
+        // The agent moved to the buyer's registry
+        assert!(AgentRegistry::unpack(&accounts[6].data.borrow()).unwrap().agents.is_empty());
+        assert_eq!(AgentRegistry::unpack(&accounts[7].data.borrow()).unwrap().agents, vec![agent_key]);
+        // The buyer now holds the stake, so the seller can't withdraw it
+        let stake = StakeAccount::unpack(&accounts[8].data.borrow()).unwrap();
+        assert_eq!((stake.staker, stake.amount), (buyer_key, MIN_AGENT_STAKE));
+
+        // The listing is gone with the sale
+        assert_eq!(
+            Processor::process_purchase(&program_id, &accounts, 5_000),
+            Err(AgentError::InvalidAuthority.into())
+        );
+    }
+
+    #[test]
+    fn test_update_delay() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let (pending_key, bump) = pda::find_pending_update_address(&program_id, &agent_key);
+        let config = AgentAccount::unpack(&agent_data).unwrap().config;
+        let mut pending_data = borsh::to_vec(&PendingUpdate {
+            agent: agent_key,
+            config: Some(config.clone()),
+            update_delay: None,
+            activation_slot: 1_000,
+            bump,
+        })
+        .unwrap();
+        let (mut agent_lamports, mut authority_lamports, mut other_lamports, mut system_lamports) = (0, 0, 0, 0);
+        let mut pending_lamports = 5_000;
+        let (mut authority_data, mut other_data, mut system_data) = (vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let accounts = [
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+            AccountInfo::new(&pending_key, false, true, &mut pending_lamports, &mut pending_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&other_key, true, true, &mut other_lamports, &mut other_data, &system, false, Epoch::default()),
+        ];
+
+        // Raising the delay applies at once, after which updates are queued
+        Processor::process_set_update_delay(&program_id, &accounts, 100).unwrap();
+        assert_eq!(AgentAccount::unpack(&accounts[0].data.borrow()).unwrap().update_delay, 100);
+        assert_eq!(
+            Processor::process_update(&program_id, &accounts[..3], config),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+
+        // Only the authority cancels, recovering the rent
+        let cancel_by = |signer: usize| [accounts[0].clone(), accounts[signer].clone(), accounts[3].clone()];
+        assert_eq!(
+            Processor::process_cancel_update(&program_id, &cancel_by(4)),
+            Err(AgentError::InvalidAuthority.into())
+        );
+        Processor::process_cancel_update(&program_id, &cancel_by(1)).unwrap();
+        assert_eq!((accounts[1].lamports(), accounts[3].lamports()), (5_000, 0));
+    }
+
+    #[test]
+    fn test_executors() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, executor_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        agent_data.resize(agent_data.len() + 32, 0);
+        let (mut agent_lamports, mut authority_lamports, mut authority_data) = (0, 0, vec![]);
+        let (mut executor_lamports, mut executor_data) = (0, vec![]);
+        let system = system_program::id();
+
+        let accounts = [
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, false, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&executor_key, true, false, &mut executor_lamports, &mut executor_data, &system, false, Epoch::default()),
+        ];
+        let invalid_authority: ProgramResult = Err(AgentError::InvalidAuthority.into());
+
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(Processor::check_executor(&program_id, &accounts[0], &agent, &accounts[2], &accounts, None), invalid_authority);
+
+        Processor::process_add_executor(&program_id, &accounts, executor_key).unwrap();
+        Processor::process_add_executor(&program_id, &accounts, executor_key).unwrap();
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert_eq!(agent.executors, vec![executor_key]);
+        assert_eq!(Processor::check_executor(&program_id, &accounts[0], &agent, &accounts[2], &accounts, None), Ok(()));
+        assert_eq!(Processor::check_executor(&program_id, &accounts[0], &agent, &accounts[1], &accounts, None), Ok(()));
+
+        // Executors can run actions, but not ones moving the agent's funds
+        let memo = AgentAction::Memo { text: "tick".to_string() };
+        let transfer = AgentAction::Transfer { destination: executor_key, lamports: 1 };
+        let check = |signer: usize, action: &AgentAction| {
+            Processor::check_executor(&program_id, &accounts[0], &agent, &accounts[signer], &accounts, Some(action))
+        };
+        assert_eq!(check(2, &memo), Ok(()));
+        assert_eq!(check(2, &transfer), invalid_authority);
+        assert_eq!(check(1, &transfer), Ok(()));
+
+        // Executors can execute but not manage the agent
+        let executor_accounts = [accounts[0].clone(), accounts[2].clone()];
+        assert_eq!(Processor::process_pause(&program_id, &executor_accounts, PauseReason::Manual, None), invalid_authority);
+        assert_eq!(Processor::process_add_executor(&program_id, &executor_accounts, Pubkey::new_unique()), invalid_authority);
+        assert_eq!(Processor::process_remove_executor(&program_id, &executor_accounts, executor_key), invalid_authority);
+
+        Processor::process_remove_executor(&program_id, &accounts, executor_key).unwrap();
+        let agent = AgentAccount::unpack(&accounts[0].data.borrow()).unwrap();
+        assert!(agent.executors.is_empty());
+        assert_eq!(Processor::check_executor(&program_id, &accounts[0], &agent, &accounts[2], &accounts, None), invalid_authority);
+    }
+
+    #[test]
+    fn test_child_bounded_by_parent() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (parent_key, mut parent_data) = agent_fixture(&program_id, &authority_key);
+        let (child_key, child_bump) = pda::find_agent_address(&program_id, &authority_key, "child");
+        let registry_key = pda::find_registry_address(&program_id, &authority_key).0;
+
+        // A child of the fixture agent, which holds COMPUTE and STORAGE
+        let mut child = AgentAccount::unpack(&parent_data).unwrap();
+        (child.name, child.bump) = ("child".to_string(), child_bump);
+        child.parent = Some(parent_key);
+        let mut child_data = borsh::to_vec(&child).unwrap();
+        child_data.resize(child.required_space(), 0);
+
+        let (mut parent_lamports, mut child_lamports, mut authority_lamports, mut other_lamports) = (0, 0, 0, 0);
+        let (mut new_lamports, mut registry_lamports, mut system_lamports) = (0, 0, 0);
+        let (mut authority_data, mut other_data, mut new_data, mut registry_data, mut system_data) =
+            (vec![], vec![], vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let spawn_accounts = [
+            AccountInfo::new(&child_key, false, true, &mut new_lamports, &mut new_data, &system, false, Epoch::default()),
+            AccountInfo::new(&other_key, true, true, &mut other_lamports, &mut other_data, &system, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+            AccountInfo::new(&registry_key, false, true, &mut registry_lamports, &mut registry_data, &system, false, Epoch::default()),
+            AccountInfo::new(&parent_key, false, false, &mut parent_lamports, &mut parent_data, &program_id, false, Epoch::default()),
+        ];
+        let config = |capabilities| AgentConfig {
+            autonomous_mode: false,
+            execution_limit: 10,
+            memory_limit: 5_000,
+            capabilities,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+
+        // Only the parent's authority may spawn children
+        assert_eq!(
+            Processor::process_spawn_child(&program_id, &spawn_accounts, "child".to_string(), config(CapabilityFlags::COMPUTE)),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        let parent_account = spawn_accounts[4].clone();
+        let accounts = [
+            AccountInfo::new(&child_key, false, true, &mut child_lamports, &mut child_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, false, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+        ];
+        let with_parent = [accounts[0].clone(), accounts[1].clone(), parent_account];
+
+        // Children can't gain capabilities their parent lacks
+        let oracle = CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE;
+        assert_eq!(
+            Processor::process_update(&program_id, &with_parent, config(oracle)),
+            Err(AgentError::MissingCapability.into())
+        );
+        assert_eq!(
+            Processor::process_update(&program_id, &accounts, config(CapabilityFlags::COMPUTE)),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        Processor::process_update(&program_id, &with_parent, config(CapabilityFlags::COMPUTE)).unwrap();
+    }
+
+    #[test]
+    fn test_initialize_rejects_taken_name() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (agent_key, mut agent_data) = agent_fixture(&program_id, &authority_key);
+        let config = AgentAccount::unpack(&agent_data).unwrap().config;
+        let (registry_key, metadata_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (mut agent_lamports, mut authority_lamports, mut system_lamports) = (0, 0, 0);
+        let (mut registry_lamports, mut metadata_lamports) = (0, 0);
+        let (mut authority_data, mut system_data, mut registry_data, mut metadata_data) =
+            (vec![], vec![], vec![], vec![]);
+        let system = system_program::id();
+
+        let accounts = [
+            AccountInfo::new(&agent_key, false, true, &mut agent_lamports, &mut agent_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default()),
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default()),
+            AccountInfo::new(&registry_key, false, true, &mut registry_lamports, &mut registry_data, &program_id, false, Epoch::default()),
+            AccountInfo::new(&metadata_key, false, true, &mut metadata_lamports, &mut metadata_data, &program_id, false, Epoch::default()),
+        ];
+
+        // The fixture agent already holds the PDA for its name
+        assert_eq!(
+            Processor::process_initialize(&program_id, &accounts, "agent".to_string(), config.clone()),
+            Err(AgentError::NameAlreadyTaken.into())
+        );
+        // Another name derives another address
+        assert_eq!(
+            Processor::process_initialize(&program_id, &accounts, "other".to_string(), config),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+    }
+
+    #[test]
+    fn test_init_metadata() {
+        let program_id = Pubkey::new_unique();
+        let (agent_key, payer_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (metadata_key, _) = pda::find_metadata_address(&program_id, &agent_key);
+        let system = system_program::id();
+
+        // Metadata left by a closed agent at the same address
+        let mut stale = AgentMetadata::new(agent_key, 1_600_000_000, 255);
+        stale.record_update(1_650_000_000);
+        let mut metadata_data = borsh::to_vec(&stale).unwrap();
+        let (mut metadata_lamports, mut payer_lamports, mut system_lamports) = (0, 0, 0);
+        let (mut payer_data, mut system_data) = (vec![], vec![]);
+
+        let metadata_account =
+            AccountInfo::new(&metadata_key, false, true, &mut metadata_lamports, &mut metadata_data, &program_id, false, Epoch::default());
+        let payer = AccountInfo::new(&payer_key, true, true, &mut payer_lamports, &mut payer_data, &system, false, Epoch::default());
+        let system_program =
+            AccountInfo::new(&system, false, false, &mut system_lamports, &mut system_data, &system, true, Epoch::default());
+
+        Processor::init_metadata(&program_id, &agent_key, &payer, &metadata_account, &system_program, 1_700_000_000)
+            .unwrap();
+        let metadata = AgentMetadata::unpack(&metadata_account.data.borrow()).unwrap();
+        assert_eq!((metadata.created_at, metadata.updated_at, metadata.version), (1_700_000_000, 1_700_000_000, 1));
+
+        // Another agent's metadata address is rejected
+        assert_eq!(
+            Processor::init_metadata(&program_id, &payer_key, &payer, &metadata_account, &system_program, 0),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+    }
+
+    #[test]
+    fn test_invoke_cpi_requires_allowed_program() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (allowed, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (agent_key, agent_data) = agent_fixture(&program_id, &authority_key);
+        let mut agent = AgentAccount::unpack(&agent_data).unwrap();
+        agent.config.allowed_programs = vec![allowed];
+
+        let action = |target: Pubkey| CpiAction {
+            program_id: target,
+            accounts: vec![CpiAccount {
+                pubkey: agent_key,
+                is_signer: true,
+                is_writable: false,
+            }],
+            data: vec![],
+        };
+        let not_allowed: ProgramResult = Err(AgentError::ProgramNotAllowed.into());
+
+        assert_eq!(Processor::invoke_cpi(&program_id, &agent, &[], &action(other)), not_allowed);
+        // The agent program itself can never be allowed
+        agent.config.allowed_programs.push(program_id);
+        assert_eq!(Processor::invoke_cpi(&program_id, &agent, &[], &action(program_id)), not_allowed);
+    }
+
+    #[test]
+    fn test_execute_action_checks_action() {
+        let program_id = Pubkey::new_unique();
+        let authority_key = Pubkey::new_unique();
+        let (_, agent_data) = agent_fixture(&program_id, &authority_key);
+        let agent = || AgentAccount::unpack(&agent_data).unwrap();
+
+        // Transfers need the trading capability the fixture lacks
+        let transfer = AgentAction::Transfer { destination: Pubkey::new_unique(), lamports: 1 };
+        assert_eq!(
+            Processor::execute_action(&program_id, &[], 3, agent(), &transfer, None, 0),
+            Err(AgentError::MissingCapability.into())
+        );
+
+        let empty_memo = AgentAction::Memo { text: String::new() };
+        assert_eq!(
+            Processor::execute_action(&program_id, &[], 3, agent(), &empty_memo, None, 0),
+            Err(AgentError::InvalidAction.into())
+        );
+        assert_eq!(
+            Processor::execute_action(&program_id, &[], 3, agent(), &AgentAction::Noop, Some(""), 0),
+            Err(AgentError::InvalidAction.into())
+        );
+    }
+
+    #[test]
+    fn test_freeze_and_admin() {
+        let program_id = Pubkey::new_unique();
+        let (admin_key, new_admin_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (config_key, bump) = pda::find_config_address(&program_id);
+        let config = ProgramConfig {
+            is_initialized: true,
+            admin: admin_key,
+            bump,
+            ..ProgramConfig::default()
+        };
+        let mut config_data = borsh::to_vec(&config).unwrap();
+        let (mut config_lamports, mut admin_lamports, mut new_admin_lamports) = (0, 0, 0);
+        let (mut admin_data, mut new_admin_data) = (vec![], vec![]);
+        let system = system_program::id();
+
+        let config_account = AccountInfo::new(&config_key, false, true, &mut config_lamports, &mut config_data, &program_id, false, Epoch::default());
+        let admin = AccountInfo::new(&admin_key, true, false, &mut admin_lamports, &mut admin_data, &system, false, Epoch::default());
+        let new_admin = AccountInfo::new(&new_admin_key, true, false, &mut new_admin_lamports, &mut new_admin_data, &system, false, Epoch::default());
+
+        // Only the admin can freeze, and nothing executes while frozen
+        assert_eq!(
+            Processor::process_set_frozen(&program_id, &[config_account.clone(), new_admin.clone()], true),
+            Err(AgentError::InvalidAuthority.into())
+        );
+        Processor::process_set_frozen(&program_id, &[config_account.clone(), admin.clone()], true).unwrap();
+        assert_eq!(
+            Processor::check_not_frozen(&program_id, &config_account),
+            Err(AgentError::ProgramFrozen.into())
+        );
+
+        // Handing over the admin role
+        let set_admin = [config_account.clone(), admin.clone(), new_admin.clone()];
+        Processor::process_set_admin(&program_id, &set_admin, new_admin_key).unwrap();
+        assert_eq!(
+            Processor::process_set_frozen(&program_id, &[config_account.clone(), admin.clone()], false),
+            Err(AgentError::InvalidAuthority.into())
+        );
+        Processor::process_set_frozen(&program_id, &[config_account.clone(), new_admin.clone()], false).unwrap();
+        assert!(Processor::check_not_frozen(&program_id, &config_account).is_ok());
+
+        // Only the admin names the oracle programs
+        let (pyth_program, switchboard_program) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let set_oracles = |admin| {
+            let accounts = [config_account.clone(), admin];
+            Processor::process_set_oracle_programs(&program_id, &accounts, pyth_program, switchboard_program)
+        };
+        assert_eq!(set_oracles(admin.clone()), Err(AgentError::InvalidAuthority.into()));
+        set_oracles(new_admin.clone()).unwrap();
+        let config = ProgramConfig::unpack(&config_account.data.borrow()).unwrap();
+        assert_eq!((config.pyth_program, config.switchboard_program), (pyth_program, switchboard_program));
+    }
+
+    #[test]
+    fn test_freeze_check_without_config() {
+        let program_id = Pubkey::new_unique();
+        let (config_key, _) = pda::find_config_address(&program_id);
+        let other_key = Pubkey::new_unique();
+        let (mut lamports, mut other_lamports) = (0, 0);
+        let (mut data, mut other_data) = (vec![], vec![]);
+        let system = system_program::id();
+
+        let config = AccountInfo::new(&config_key, false, false, &mut lamports, &mut data, &system, false, Epoch::default());
+        assert!(Processor::check_not_frozen(&program_id, &config).is_ok());
+
+        // An empty account can't stand in for the config
+        let other = AccountInfo::new(&other_key, false, false, &mut other_lamports, &mut other_data, &system, false, Epoch::default());
+        assert_eq!(
+            Processor::check_not_frozen(&program_id, &other),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+    }
+
+    #[test]
+    fn test_check_upgrade_authority() {
+        let program_id = Pubkey::new_unique();
+        let (authority_key, other_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let program_data_key = pda::find_program_data_address(&program_id);
+        let loader = bpf_loader_upgradeable::id();
+
+        let mut program_data = vec![3, 0, 0, 0];
+        program_data.extend(42u64.to_le_bytes());
+        program_data.push(1);
+        program_data.extend(authority_key.to_bytes());
+        program_data.extend([0; 16]);
+        let (mut lamports, mut authority_lamports, mut other_lamports) = (0, 0, 0);
+        let (mut authority_data, mut other_data) = (vec![], vec![]);
+        let system = system_program::id();
+
+        let program_data = AccountInfo::new(&program_data_key, false, false, &mut lamports, &mut program_data, &loader, false, Epoch::default());
+        let authority = AccountInfo::new(&authority_key, true, true, &mut authority_lamports, &mut authority_data, &system, false, Epoch::default());
+        let other = AccountInfo::new(&other_key, true, true, &mut other_lamports, &mut other_data, &system, false, Epoch::default());
+
+        assert!(Processor::check_upgrade_authority(&program_id, &program_data, &authority).is_ok());
+        assert_eq!(
+            Processor::check_upgrade_authority(&program_id, &program_data, &other),
+            Err(AgentError::InvalidAuthority.into())
+        );
+
+        // Immutable programs have no upgrade authority
+        program_data.data.borrow_mut()[12] = 0;
+        assert!(Processor::check_upgrade_authority(&program_id, &program_data, &authority).is_err());
+    }
+
+    #[test]
+    fn test_check_account() {
+        let program_id = Pubkey::new_unique();
+        let key = Pubkey::new_unique();
+        let rent = Rent::default();
+        let mut data = vec![0u8; 100];
+        let mut lamports = rent.minimum_balance(100);
+        let mut poor_lamports = lamports - 1;
+        let mut poor_data = data.clone();
+        let other_owner = Pubkey::new_unique();
+
+        let account = AccountInfo::new(&key, false, true, &mut lamports, &mut data, &program_id, false, Epoch::default());
+        assert!(Processor::check_account(&program_id, &account, 100, &rent).is_ok());
+        assert_eq!(
+            Processor::check_account(&program_id, &account, 101, &rent),
+            Err(AgentError::AccountTooSmall.into())
+        );
+        assert_eq!(
+            Processor::check_account(&other_owner, &account, 100, &rent),
+            Err(AgentError::InvalidOwner.into())
+        );
+
+        let poor = AccountInfo::new(&key, false, true, &mut poor_lamports, &mut poor_data, &program_id, false, Epoch::default());
+        assert_eq!(
+            Processor::check_account(&program_id, &poor, 100, &rent),
+            Err(AgentError::NotRentExempt.into())
+        );
+    }
+
+    #[test]
+    fn test_time_lock() {
+        let clock = Clock {
+            slot: 100,
+            unix_timestamp: 1_000,
+            ..Clock::default()
+        };
+
+        assert!(Processor::time_lock_elapsed(&clock, None, None));
+        assert!(Processor::time_lock_elapsed(&clock, Some(100), Some(1_000)));
+        assert!(!Processor::time_lock_elapsed(&clock, Some(101), None));
+        assert!(!Processor::time_lock_elapsed(&clock, None, Some(1_001)));
     }
 }
\ No newline at end of file
@@ -1,9 +1,19 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
+    account_info::AccountInfo,
+    msg,
     program_error::ProgramError,
-    pubkey::Pubkey,
+    pubkey::{Pubkey, MAX_SEED_LEN},
 };
-use crate::solana::program::instruction::AgentConfig;
+use crate::solana::program::{
+    capability::CapabilityFlags,
+    error::AgentError,
+    instruction::{AgentConfig, MAX_ALLOWED_PROGRAMS},
+    oracle::{PYTH_PROGRAM_ID, SWITCHBOARD_PROGRAM_ID},
+    pda::{self, AgentSeeds, ConfigSeeds, DelegateSeeds, RegistrySeeds},
+};
+#[cfg(feature = "zero-copy")]
+use crate::solana::program::zero_copy::AgentAccountZc;
 
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum AgentState {
@@ -15,41 +25,648 @@ pub enum AgentState {
     Terminated,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+/// Why an agent was paused
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq)]
+pub enum PauseReason {
+    /// Paused by its authority without a specific reason
+    Manual,
+    Maintenance,
+    /// A risk or loss limit was hit
+    RiskLimit,
+    /// Paused in response to an incident
+    Emergency,
+    Other,
+}
+
+/// Current layout version of `AgentAccount`
+pub const AGENT_ACCOUNT_VERSION: u8 = 2;
+
+/// Maximum number of executors an agent may list
+pub const MAX_EXECUTORS: usize = 8;
+
+/// Maximum agent name length; names are used as a PDA seed
+pub const MAX_NAME_LEN: usize = MAX_SEED_LEN;
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct AgentAccount {
+    /// Layout version, always serialized first
+    pub version: u8,
     pub authority: Pubkey,
     pub name: String,
     pub config: AgentConfig,
     pub state: AgentState,
     pub last_execution: i64,
     pub execution_count: u64,
+    /// Authority proposed by `TransferAuthority`, awaiting acceptance
+    pub pending_authority: Option<Pubkey>,
+    /// Authority the agent PDA was derived from; unchanged by transfers
+    pub creator: Pubkey,
+    /// Bump seed of the agent PDA
+    pub bump: u8,
+    /// Execution statistics, updated by every execution
+    pub metrics: PerformanceMetrics,
+    /// Reason given by the last `Pause`, cleared on resume
+    pub pause_reason: Option<PauseReason>,
+    /// Unix time from which a paused agent resumes on its next execution
+    pub resume_at: Option<i64>,
+    /// Keys besides the authority allowed to sign execute instructions for
+    /// actions that don't move the agent's funds
+    pub executors: Vec<Pubkey>,
+    /// Agent that spawned this one with `SpawnChild`; the child can't
+    /// execute while its parent is paused
+    pub parent: Option<Pubkey>,
+    /// Slots an `Update` waits in a `PendingUpdate` before `CommitUpdate`
+    /// can apply it; 0 applies updates at once
+    pub update_delay: u64,
+    /// Number of this agent among those its creator has created, so an
+    /// agent recreated at a closed agent's address is told apart from it
+    pub generation: u64,
+}
+
+/// Flags for capabilities stored by name in layout v1
+///
+/// Names were never enforced, so unknown names are dropped and upgraded
+/// agents keep the instructions they could already run: plain, chunked
+/// and price-conditioned executions need `COMPUTE`, `STORAGE` and `ORACLE`.
+fn legacy_capabilities(names: &[String]) -> CapabilityFlags {
+    names
+        .iter()
+        .filter_map(|name| CapabilityFlags::from_name(name))
+        .fold(LEGACY_CAPABILITIES, |flags, flag| flags | flag)
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+/// Capabilities every agent upgraded from layout v1 holds
+const LEGACY_CAPABILITIES: CapabilityFlags = CapabilityFlags::from_bits(
+    CapabilityFlags::COMPUTE.bits() | CapabilityFlags::STORAGE.bits() | CapabilityFlags::ORACLE.bits(),
+);
+
+/// `AgentConfig` as stored by layout v1
+#[derive(BorshDeserialize)]
+struct AgentConfigV1 {
+    autonomous_mode: bool,
+    execution_limit: u64,
+    memory_limit: u64,
+    capabilities: Vec<String>,
+}
+
+impl From<AgentConfigV1> for AgentConfig {
+    fn from(v1: AgentConfigV1) -> Self {
+        Self {
+            autonomous_mode: v1.autonomous_mode,
+            execution_limit: v1.execution_limit,
+            memory_limit: v1.memory_limit,
+            capabilities: legacy_capabilities(&v1.capabilities),
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        }
+    }
+}
+
+/// Unversioned layout of the first release, before `AgentAccount::version`
+/// existed
+///
+/// It stores neither creator nor bump. Authorities couldn't be transferred
+/// then, so the authority is the creator the agent PDA was derived from,
+/// and `AgentAccount::unpack_at` recovers the bump from the address.
+#[derive(BorshDeserialize)]
+struct AgentAccountV1 {
+    authority: Pubkey,
+    name: String,
+    config: AgentConfigV1,
+    state: AgentState,
+    last_execution: i64,
+    execution_count: u64,
+}
+
+impl From<AgentAccountV1> for AgentAccount {
+    fn from(v1: AgentAccountV1) -> Self {
+        Self {
+            version: AGENT_ACCOUNT_VERSION,
+            authority: v1.authority,
+            name: v1.name,
+            config: v1.config.into(),
+            state: v1.state,
+            last_execution: v1.last_execution,
+            execution_count: v1.execution_count,
+            pending_authority: None,
+            creator: v1.authority,
+            bump: 0,
+            metrics: PerformanceMetrics::default(),
+            pause_reason: None,
+            resume_at: None,
+            executors: vec![],
+            parent: None,
+            update_delay: 0,
+            generation: 0,
+        }
+    }
+}
+
+/// Creation and update times of an agent, PDA of `[METADATA_SEED, agent]`
+///
+/// Created with the agent; `Update` bumps `updated_at` and `version`.
+/// Execution metrics live on the agent account (`AgentAccount::metrics`).
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub struct AgentMetadata {
+    pub agent: Pubkey,
+    /// Unix time of creation, 0 for agents created before metadata existed
     pub created_at: i64,
     pub updated_at: i64,
+    /// Config version, 1 at creation and incremented by every `Update`
     pub version: u32,
-    pub performance_metrics: PerformanceMetrics,
+    /// Bump seed of the metadata PDA
+    pub bump: u8,
+}
+
+impl AgentMetadata {
+    pub const LEN: usize = 32 + 8 + 8 + 4 + 1;
+
+    pub fn new(agent: Pubkey, created_at: i64, bump: u8) -> Self {
+        Self {
+            agent,
+            created_at,
+            updated_at: created_at,
+            version: 1,
+            bump,
+        }
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Record a config update at unix time `now`
+    pub fn record_update(&mut self, now: i64) {
+        self.updated_at = now;
+        self.version = self.version.saturating_add(1);
+    }
+}
+
+/// Config update waiting out an agent's `update_delay`, PDA of
+/// `[PENDING_UPDATE_SEED, agent]`
+///
+/// Queued by `Update` (and by `SetUpdateDelay` when lowering the delay),
+/// applied by `CommitUpdate` from `activation_slot` on and discarded by
+/// `CancelUpdate`. Queuing again merges into it and restarts the delay.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct PendingUpdate {
+    pub agent: Pubkey,
+    /// New config, `None` to keep the current one
+    pub config: Option<AgentConfig>,
+    /// New update delay, `None` to keep the current one
+    pub update_delay: Option<u64>,
+    /// First slot at which the update can be committed
+    pub activation_slot: u64,
+    /// Bump seed of the pending update PDA
+    pub bump: u8,
+}
+
+impl PendingUpdate {
+    /// Size with the largest config, which every account is allocated for
+    pub const LEN: usize = 32 + 1 + (1 + 8 + 8 + 8 + 4 + 4 + 32 * MAX_ALLOWED_PROGRAMS) + 1 + 8 + 8 + 1;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
+
+/// Lamports an agent must have staked before it first runs
+pub const MIN_AGENT_STAKE: u64 = 100_000_000;
+
+/// Escrow of the lamports staked for an agent, PDA of `[STAKE_SEED, agent]`
+///
+/// The agent can't be resumed into `Running` for the first time with less
+/// than `MIN_AGENT_STAKE` staked. The stake is returned to the staker,
+/// together with the escrow's rent, only once the agent has been closed.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct StakeAccount {
+    pub agent: Pubkey,
+    /// Authority of the agent when the escrow was opened, or the buyer who
+    /// took it over with the agent; the only account that can unstake,
+    /// while anyone can top the escrow up
+    pub staker: Pubkey,
+    /// Staked lamports, excluding the escrow's rent
+    pub amount: u64,
+    /// Bump seed of the escrow PDA
+    pub bump: u8,
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
+impl StakeAccount {
+    pub const LEN: usize = 32 + 32 + 8 + 1;
+
+    pub fn new(agent: Pubkey, staker: Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            staker,
+            amount: 0,
+            bump,
+        }
+    }
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Whether enough is staked for the agent to run
+    pub fn is_sufficient(&self) -> bool {
+        self.amount >= MIN_AGENT_STAKE
+    }
+}
+
+/// Agent offered for sale, PDA of `[LISTING_SEED, agent]`
+///
+/// While listed, the listing is the agent's authority, so the seller can
+/// neither change nor close it. `Purchase` hands the agent to the buyer and
+/// `CancelListing` back to the seller; either closes the listing.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub agent: Pubkey,
+    /// Authority of the agent when it was listed, paid on purchase
+    pub seller: Pubkey,
+    /// Price in lamports
+    pub price: u64,
+    /// Bump seed of the listing PDA
+    pub bump: u8,
+}
+
+impl Listing {
+    pub const LEN: usize = 32 + 32 + 8 + 1;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Default, PartialEq)]
 pub struct PerformanceMetrics {
     pub total_executions: u64,
     pub successful_executions: u64,
     pub failed_executions: u64,
+    /// Not measured: programs have no clock finer than the slot, so this
+    /// stays 0. Execution cost is tracked in compute units instead.
     pub average_execution_time: u64,
     pub total_compute_units: u64,
+    /// Average compute units per execution, 0 before the first one
+    pub average_compute_units: u64,
+}
+
+impl PerformanceMetrics {
+    /// Account for one execution that consumed `compute_units`
+    pub fn record(&mut self, succeeded: bool, compute_units: u64) {
+        self.total_executions = self.total_executions.saturating_add(1);
+        if succeeded {
+            self.successful_executions = self.successful_executions.saturating_add(1);
+        } else {
+            self.failed_executions = self.failed_executions.saturating_add(1);
+        }
+        self.total_compute_units = self.total_compute_units.saturating_add(compute_units);
+        self.update_average();
+    }
+
+    /// Recompute `average_compute_units` from the totals
+    pub(crate) fn update_average(&mut self) {
+        self.average_compute_units = self.total_compute_units.checked_div(self.total_executions).unwrap_or(0);
+    }
+}
+
+/// Header of a staging account used to upload action data too large for
+/// a single transaction. The assembled bytes follow the header.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Default)]
+pub struct StagingHeader {
+    pub is_initialized: bool,
+    pub agent: Pubkey,
+    pub authority: Pubkey,
+    /// Length of the contiguous prefix written so far; uploads resume here
+    pub written: u32,
 }
 
-impl Default for PerformanceMetrics {
+impl StagingHeader {
+    pub const LEN: usize = 1 + 32 + 32 + 4;
+
+    /// Account size needed to stage `data_len` bytes
+    pub fn space(data_len: usize) -> usize {
+        Self::LEN + data_len
+    }
+
+    /// Capacity of the data region of a staging account of `account_len` bytes
+    pub fn capacity(account_len: usize) -> usize {
+        account_len.saturating_sub(Self::LEN)
+    }
+}
+
+/// Delegate may call `Execute`, `ExecuteAfter`, `ExecuteConditional` and
+/// the chunked upload instructions
+pub const DELEGATE_EXECUTE: u32 = 1 << 0;
+
+/// Delegate may call `Pause`
+pub const DELEGATE_PAUSE: u32 = 1 << 1;
+
+/// Delegate may call `Resume`
+pub const DELEGATE_RESUME: u32 = 1 << 2;
+
+/// Delegate may call `Update`
+pub const DELEGATE_UPDATE: u32 = 1 << 3;
+
+/// Every permission a delegate can hold. `Close`, `TransferAuthority`,
+/// `Migrate` and `Delegate` always require the authority.
+pub const DELEGATE_ALL: u32 = DELEGATE_EXECUTE | DELEGATE_PAUSE | DELEGATE_RESUME | DELEGATE_UPDATE;
+
+/// Session key allowed to sign a subset of instructions on behalf of an
+/// agent's authority until an expiry slot
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq, Default)]
+pub struct DelegateRecord {
+    pub is_initialized: bool,
+    pub agent: Pubkey,
+    /// Authority of the agent that issued the record; the record lapses
+    /// once the agent changes hands
+    pub authority: Pubkey,
+    pub delegate: Pubkey,
+    /// Last slot at which the delegate is honored
+    pub expiry_slot: u64,
+    /// Bitmask of `DELEGATE_*` permissions
+    pub permissions: u32,
+    /// Bump seed of the record PDA
+    pub bump: u8,
+    /// `AgentAccount::generation` of the agent that issued the record;
+    /// records outlive `Close` and lapse once the agent is recreated
+    pub generation: u64,
+}
+
+impl DelegateRecord {
+    pub const LEN: usize = 1 + 32 + 32 + 32 + 8 + 4 + 1 + 8;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Self::deserialize(&mut &data[..Self::LEN]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Check that `address` is the PDA of this record
+    pub fn verify_address(&self, program_id: &Pubkey, address: &Pubkey) -> Result<(), ProgramError> {
+        let seeds = DelegateSeeds::new(&self.agent, &self.authority, &self.delegate, self.bump);
+        match Pubkey::create_program_address(&seeds.as_seeds(), program_id) {
+            Ok(derived) if derived == *address => Ok(()),
+            _ => Err(AgentError::InvalidProgramAddress.into()),
+        }
+    }
+
+    /// Check that the delegate holds `permission`
+    pub fn check_permission(&self, permission: u32) -> Result<(), AgentError> {
+        if !self.is_initialized || self.permissions & permission != permission {
+            return Err(AgentError::Unauthorized);
+        }
+        Ok(())
+    }
+
+    /// Check that the session has not expired at `slot`
+    pub fn check_expiry(&self, slot: u64) -> Result<(), AgentError> {
+        if slot > self.expiry_slot {
+            return Err(AgentError::DelegateExpired);
+        }
+        Ok(())
+    }
+}
+
+/// Outcome recorded in an execution receipt
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum ExecutionStatus {
+    Succeeded,
+    /// The program refused the action, e.g. a CPI to a program the agent
+    /// isn't allowed to call, with the `AgentError` code; the execution
+    /// still counts against the agent's limits
+    Failed { error: u32 },
+}
+
+/// Audit record of a single execution, PDA of
+/// `[RECEIPT_SEED, agent, execution]`
+///
+/// Executions that abort their transaction leave no receipt.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ExecutionReceipt {
+    pub agent: Pubkey,
+    /// 1-based execution number, the agent's `execution_count` right after
+    /// this execution
+    pub execution: u64,
+    /// Authority or delegate that triggered the execution
+    pub signer: Pubkey,
+    pub slot: u64,
+    pub timestamp: i64,
+    /// SHA-256 of the action data
+    pub action_hash: [u8; 32],
+    pub status: ExecutionStatus,
+    /// Bump seed of the receipt PDA
+    pub bump: u8,
+    /// SHA-256 of the memo attached to the execution, if any
+    pub memo_hash: Option<[u8; 32]>,
+}
+
+impl ExecutionReceipt {
+    /// Size of a receipt with its largest status and a memo hash
+    pub const LEN: usize = 32 + 8 + 32 + 8 + 8 + 32 + (1 + 4) + 1 + (1 + 32);
+
+    /// Size of the receipts allocated before memo hashes
+    pub const LEGACY_LEN: usize = Self::LEN - (1 + 32);
+
+    /// Deserialize from account data; receipts allocated before memo hashes
+    /// read as having none
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        // A legacy `Failed` receipt fills its account, leaving no byte for
+        // the `memo_hash` tag, so a missing tag stands for `None`
+        let padded;
+        let data = if data.len() == Self::LEGACY_LEN {
+            padded = [data, &[0]].concat();
+            &padded[..]
+        } else {
+            data
+        };
+        Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
+
+/// Number of recent results kept in a `ResultAccount`
+pub const RESULT_HISTORY_LEN: usize = 16;
+
+/// Outcome of one execution as kept in a `ResultAccount`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ExecutionResult {
+    /// 1-based execution number, as in `ExecutionReceipt::execution`
+    pub execution: u64,
+    pub status: ExecutionStatus,
+    /// SHA-256 of the data returned by the action's CPI, zeroed if it
+    /// returned none
+    pub output_hash: [u8; 32],
+    pub timestamp: i64,
+}
+
+impl ExecutionResult {
+    /// Size of a result with its largest status
+    pub const LEN: usize = 8 + (1 + 4) + 32 + 8;
+}
+
+/// Recent execution results of an agent, PDA of `[RESULT_SEED, agent]`
+///
+/// Passed as the data account of the execute instructions and created by
+/// the first execution. Results form a ring buffer of the last
+/// `RESULT_HISTORY_LEN` executions.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ResultAccount {
+    pub agent: Pubkey,
+    /// Bump seed of the result PDA
+    pub bump: u8,
+    /// Index in `results` the next result is written to
+    pub head: u32,
+    pub results: Vec<ExecutionResult>,
+}
+
+impl ResultAccount {
+    /// Size of an account holding `RESULT_HISTORY_LEN` results
+    pub const LEN: usize = 32 + 1 + 4 + 4 + ExecutionResult::LEN * RESULT_HISTORY_LEN;
+
+    pub fn new(agent: Pubkey, bump: u8) -> Self {
+        Self {
+            agent,
+            bump,
+            head: 0,
+            results: Vec::with_capacity(RESULT_HISTORY_LEN),
+        }
+    }
+
+    /// Deserialize from account data, ignoring the space reserved for
+    /// results not written yet
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let account = Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)?;
+        if account.results.len() > RESULT_HISTORY_LEN || account.head as usize > account.results.len() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(account)
+    }
+
+    /// Record a result, overwriting the oldest one once the buffer is full
+    pub fn push(&mut self, result: ExecutionResult) {
+        let head = self.head as usize;
+        if head < self.results.len() {
+            self.results[head] = result;
+        } else {
+            self.results.push(result);
+        }
+        self.head = ((head + 1) % RESULT_HISTORY_LEN) as u32;
+    }
+
+    /// Results oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &ExecutionResult> {
+        let (newer, older) = self.results.split_at(self.head as usize);
+        older.iter().chain(newer)
+    }
+
+    /// Result of the latest execution
+    pub fn latest(&self) -> Option<&ExecutionResult> {
+        self.recent().last()
+    }
+}
+
+/// Agents held by an authority, PDA of `[REGISTRY_SEED, authority]`
+///
+/// Agents move to the new authority's registry when their authority is
+/// transferred or they are sold, and are removed on `Close`. A listed agent
+/// stays in the seller's registry until it is sold.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct AgentRegistry {
+    pub authority: Pubkey,
+    /// Bump seed of the registry PDA
+    pub bump: u8,
+    /// Agents the authority has created, numbering their generations
+    pub created: u64,
+    pub agents: Vec<Pubkey>,
+}
+
+impl AgentRegistry {
+    /// Account size needed to list `agents` agents
+    pub fn space(agents: usize) -> usize {
+        32 + 1 + 8 + 4 + 32 * agents
+    }
+
+    /// Deserialize from account data, ignoring unused trailing space left
+    /// by removed agents
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::deserialize(&mut &data[..]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Check that `address` is the PDA of this registry
+    pub fn verify_address(&self, program_id: &Pubkey, address: &Pubkey) -> Result<(), ProgramError> {
+        let seeds = RegistrySeeds::new(&self.authority, self.bump);
+        match Pubkey::create_program_address(&seeds.as_seeds(), program_id) {
+            Ok(derived) if derived == *address => Ok(()),
+            _ => Err(AgentError::InvalidProgramAddress.into()),
+        }
+    }
+
+    /// Add an agent, returning false if it is already listed
+    pub fn insert(&mut self, agent: Pubkey) -> bool {
+        if self.agents.contains(&agent) {
+            return false;
+        }
+        self.agents.push(agent);
+        true
+    }
+
+    /// Remove an agent, returning false if it was not listed
+    pub fn remove(&mut self, agent: &Pubkey) -> bool {
+        let len = self.agents.len();
+        self.agents.retain(|listed| listed != agent);
+        self.agents.len() != len
+    }
+}
+
+/// Program-wide settings, PDA of `[CONFIG_SEED]`
+///
+/// Created by the program's upgrade authority with the first `SetAdmin`.
+/// Until then no admin exists, the program can't be frozen and the default
+/// settings apply.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct ProgramConfig {
+    pub is_initialized: bool,
+    /// Key allowed to freeze the program and appoint a new admin
+    pub admin: Pubkey,
+    /// While set, no agent can execute
+    pub frozen: bool,
+    /// Bump seed of the config PDA
+    pub bump: u8,
+    /// Program that must own the Pyth price accounts conditions read
+    pub pyth_program: Pubkey,
+    /// Program that must own the Switchboard aggregators conditions read
+    pub switchboard_program: Pubkey,
+}
+
+impl Default for ProgramConfig {
     fn default() -> Self {
         Self {
-            total_executions: 0,
-            successful_executions: 0,
-            failed_executions: 0,
-            average_execution_time: 0,
-            total_compute_units: 0,
+            is_initialized: false,
+            admin: Pubkey::default(),
+            frozen: false,
+            bump: 0,
+            pyth_program: PYTH_PROGRAM_ID,
+            switchboard_program: SWITCHBOARD_PROGRAM_ID,
+        }
+    }
+}
+
+impl ProgramConfig {
+    pub const LEN: usize = 1 + 32 + 1 + 1 + 32 + 32;
+
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Self::deserialize(&mut &data[..Self::LEN]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Check that `address` is the config PDA
+    pub fn verify_address(&self, program_id: &Pubkey, address: &Pubkey) -> Result<(), ProgramError> {
+        let seeds = ConfigSeeds::new(self.bump);
+        match Pubkey::create_program_address(&seeds.as_seeds(), program_id) {
+            Ok(derived) if derived == *address => Ok(()),
+            _ => Err(AgentError::InvalidProgramAddress.into()),
         }
     }
 }
@@ -57,17 +674,158 @@ impl Default for PerformanceMetrics {
 impl AgentAccount {
     pub fn new(authority: Pubkey, name: String, config: AgentConfig) -> Self {
         Self {
+            version: AGENT_ACCOUNT_VERSION,
             authority,
             name,
             config,
             state: AgentState::Initialized,
             last_execution: 0,
             execution_count: 0,
+            pending_authority: None,
+            creator: authority,
+            bump: 0,
+            metrics: PerformanceMetrics::default(),
+            pause_reason: None,
+            resume_at: None,
+            executors: vec![],
+            parent: None,
+            update_delay: 0,
+            generation: 0,
         }
     }
 
+    /// Account size for a new agent, leaving room for a pending authority,
+    /// pause details and a parent
+    #[cfg(not(feature = "zero-copy"))]
+    pub fn space(name: &str, config: &AgentConfig) -> usize {
+        let mut account = Self::new(Pubkey::default(), name.to_string(), config.clone());
+        account.pending_authority = Some(Pubkey::default());
+        account.pause_reason = Some(PauseReason::Manual);
+        account.resume_at = Some(0);
+        account.parent = Some(Pubkey::default());
+        account.try_to_vec().map_or(0, |data| data.len())
+    }
+
+    /// Account size for an agent, the same for every agent in the
+    /// fixed-size layout
+    #[cfg(feature = "zero-copy")]
+    pub fn space(_name: &str, _config: &AgentConfig) -> usize {
+        AgentAccountZc::LEN
+    }
+
+    /// Account size for this agent, including its executors
+    pub fn required_space(&self) -> usize {
+        let space = Self::space(&self.name, &self.config);
+        if cfg!(feature = "zero-copy") {
+            space
+        } else {
+            space + 32 * self.executors.len()
+        }
+    }
+
+    /// Account size for the longest name, program allowlist and executor
+    /// list an agent can have
+    pub fn max_space() -> usize {
+        let config = AgentConfig {
+            autonomous_mode: false,
+            execution_limit: 0,
+            memory_limit: 0,
+            capabilities: CapabilityFlags::NONE,
+            min_execution_interval: 0,
+            allowed_programs: vec![Pubkey::default(); MAX_ALLOWED_PROGRAMS],
+        };
+        let mut account = Self::new(Pubkey::default(), "x".repeat(MAX_NAME_LEN), config);
+        account.executors = vec![Pubkey::default(); MAX_EXECUTORS];
+        account.required_space()
+    }
+
+    /// Check that `address` is the PDA this agent was created at
+    pub fn verify_address(&self, program_id: &Pubkey, address: &Pubkey) -> Result<(), ProgramError> {
+        let seeds = AgentSeeds::new(&self.creator, &self.name, self.bump);
+        match Pubkey::create_program_address(&seeds.as_seeds(), program_id) {
+            Ok(derived) if derived == *address => Ok(()),
+            _ => Err(AgentError::InvalidProgramAddress.into()),
+        }
+    }
+
+    /// Deserialize from account data, ignoring unused trailing space
+    ///
+    /// Accounts are allocated for their largest layout (e.g. with a pending
+    /// authority set), so the serialized state is usually shorter than the
+    /// account. Accounts in the v1 layout are upgraded in memory, with a
+    /// bump of 0 (see `unpack_at`). The first release stored them at
+    /// keypair addresses, from which `Migrate` moves them into their PDA.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::decode(data).map(|(account, _)| account)
+    }
+
+    /// `unpack` the agent stored at `address`, recovering the bump of a v1
+    /// account from its address
+    pub fn unpack_at(data: &[u8], program_id: &Pubkey, address: &Pubkey) -> Result<Self, ProgramError> {
+        let (mut account, legacy) = Self::decode(data)?;
+        if legacy {
+            // Names longer than a seed never had a PDA
+            if account.name.len() > MAX_NAME_LEN {
+                return Err(AgentError::InvalidProgramAddress.into());
+            }
+            let (derived, bump) = pda::find_agent_address(program_id, &account.creator, &account.name);
+            if derived != *address {
+                return Err(AgentError::InvalidProgramAddress.into());
+            }
+            account.bump = bump;
+        }
+        Ok(account)
+    }
+
+    /// `unpack` an account which must be in the v1 layout, upgraded in
+    /// memory with a bump of 0, wherever it is stored
+    pub fn unpack_v1(data: &[u8]) -> Result<Self, ProgramError> {
+        match Self::decode(data)? {
+            (account, true) => Ok(account),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    /// Deserialize account data, and whether it was in the v1 layout
+    fn decode(data: &[u8]) -> Result<(Self, bool), ProgramError> {
+        match data.first() {
+            #[cfg(feature = "zero-copy")]
+            Some(_) if AgentAccountZc::is_zero_copy(data) => {
+                if let Ok(account) = AgentAccountZc::read(data).and_then(|stored| stored.to_account()) {
+                    return Ok((account, false));
+                }
+            }
+            Some(&AGENT_ACCOUNT_VERSION) => {
+                if let Ok(account) = Self::deserialize(&mut &data[..]) {
+                    return Ok((account, false));
+                }
+            }
+            _ => {}
+        }
+
+        // v1 accounts start with the authority key, so a first byte equal to
+        // either version is only trusted if the whole account parses
+        AgentAccountV1::deserialize(&mut &data[..])
+            .map(|v1| (Self::from(v1), true))
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Serialize into account data in the current layout, the fixed-size
+    /// `AgentAccountZc` with the `zero-copy` feature
+    pub fn save(&self, account: &AccountInfo) -> Result<(), ProgramError> {
+        #[cfg(feature = "zero-copy")]
+        let result = AgentAccountZc::store(self, &mut account.data.borrow_mut());
+        #[cfg(not(feature = "zero-copy"))]
+        let result = self.serialize(&mut &mut account.data.borrow_mut()[..]);
+
+        result.map_err(|_| {
+            msg!("Agent account too small for layout v{}, run Migrate", AGENT_ACCOUNT_VERSION);
+            AgentError::AccountTooSmall.into()
+        })
+    }
+
     pub fn update_state(&mut self, new_state: AgentState) -> Result<(), ProgramError> {
-        match (self.state.clone(), new_state) {
+        match (self.state.clone(), new_state.clone()) {
             (AgentState::Uninitialized, AgentState::Initialized) => Ok(()),
             (AgentState::Initialized, AgentState::Running) => Ok(()),
             (AgentState::Running, AgentState::Paused) => Ok(()),
@@ -89,6 +847,63 @@ impl AgentAccount {
         self.is_active() && self.config.execution_limit > self.execution_count
     }
 
+    /// Check that `min_execution_interval` has passed since the last
+    /// execution at unix time `now`
+    pub fn check_rate_limit(&self, now: i64) -> Result<(), AgentError> {
+        if self.execution_count == 0 {
+            return Ok(());
+        }
+
+        let elapsed = now.saturating_sub(self.last_execution);
+        if elapsed < self.config.min_execution_interval as i64 {
+            return Err(AgentError::RateLimited);
+        }
+        Ok(())
+    }
+
+    pub fn pause(&mut self, reason: PauseReason, resume_at: Option<i64>) {
+        self.state = AgentState::Paused;
+        self.pause_reason = Some(reason);
+        self.resume_at = resume_at;
+    }
+
+    pub fn resume(&mut self) {
+        self.state = AgentState::Running;
+        self.pause_reason = None;
+        self.resume_at = None;
+    }
+
+    /// Whether the agent is paused with an auto-resume time reached at
+    /// unix time `now`
+    pub fn auto_resume_due(&self, now: i64) -> bool {
+        self.state == AgentState::Paused && self.resume_at.map_or(false, |resume_at| now >= resume_at)
+    }
+
+    /// Whether the agent is paused at unix time `now`, which also holds
+    /// back its children
+    pub fn is_paused_at(&self, now: i64) -> bool {
+        self.state == AgentState::Paused && !self.auto_resume_due(now)
+    }
+
+    /// Add an executor, returning false if it is already listed
+    pub fn add_executor(&mut self, executor: Pubkey) -> Result<bool, AgentError> {
+        if self.executors.contains(&executor) {
+            return Ok(false);
+        }
+        if self.executors.len() >= MAX_EXECUTORS {
+            return Err(AgentError::TooManyExecutors);
+        }
+        self.executors.push(executor);
+        Ok(true)
+    }
+
+    /// Remove an executor, returning false if it was not listed
+    pub fn remove_executor(&mut self, executor: &Pubkey) -> bool {
+        let len = self.executors.len();
+        self.executors.retain(|listed| listed != executor);
+        self.executors.len() != len
+    }
+
     pub fn record_execution(&mut self, timestamp: i64) {
         self.last_execution = timestamp;
         self.execution_count += 1;
@@ -98,6 +913,11 @@ impl AgentAccount {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::solana::program::{
+        action::AgentAction,
+        instruction::AgentInstruction,
+        oracle::{Comparator, PriceCondition},
+    };
 
     #[test]
     fn test_agent_state_transitions() {
@@ -108,7 +928,9 @@ mod tests {
                 autonomous_mode: true,
                 execution_limit: 1000,
                 memory_limit: 5000,
-                capabilities: vec!["compute".to_string()],
+                capabilities: CapabilityFlags::COMPUTE,
+                min_execution_interval: 0,
+                allowed_programs: vec![],
             },
         );
 
@@ -126,7 +948,9 @@ mod tests {
                 autonomous_mode: true,
                 execution_limit: 2,
                 memory_limit: 5000,
-                capabilities: vec!["compute".to_string()],
+                capabilities: CapabilityFlags::COMPUTE,
+                min_execution_interval: 0,
+                allowed_programs: vec![],
             },
         );
 
@@ -140,11 +964,441 @@ mod tests {
         assert!(!agent.can_execute());
     }
 
+    #[test]
+    fn test_rate_limit() {
+        let mut agent = AgentAccount::new(
+            Pubkey::new_unique(),
+            "test_agent".to_string(),
+            AgentConfig {
+                autonomous_mode: true,
+                execution_limit: 10,
+                memory_limit: 5000,
+                capabilities: CapabilityFlags::NONE,
+                min_execution_interval: 60,
+                allowed_programs: vec![],
+            },
+        );
+
+        // The first execution is never limited
+        assert!(agent.check_rate_limit(0).is_ok());
+
+        agent.record_execution(1_000);
+        assert_eq!(agent.check_rate_limit(1_059), Err(AgentError::RateLimited));
+        assert!(agent.check_rate_limit(1_060).is_ok());
+
+        agent.config.min_execution_interval = 0;
+        assert!(agent.check_rate_limit(1_000).is_ok());
+    }
+
+    #[test]
+    fn test_verify_address() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (address, bump) = crate::solana::program::pda::find_agent_address(&program_id, &authority, "agent");
+
+        let mut agent = AgentAccount::new(
+            authority,
+            "agent".to_string(),
+            AgentConfig {
+                autonomous_mode: false,
+                execution_limit: 1,
+                memory_limit: 1,
+                capabilities: CapabilityFlags::NONE,
+                min_execution_interval: 0,
+                allowed_programs: vec![],
+            },
+        );
+        agent.bump = bump;
+
+        assert!(agent.verify_address(&program_id, &address).is_ok());
+        assert!(agent.verify_address(&program_id, &Pubkey::new_unique()).is_err());
+
+        // The address stays valid after the authority changes
+        agent.authority = Pubkey::new_unique();
+        assert!(agent.verify_address(&program_id, &address).is_ok());
+    }
+
+    #[derive(BorshSerialize)]
+    struct LegacyConfig {
+        autonomous_mode: bool,
+        execution_limit: u64,
+        memory_limit: u64,
+        capabilities: Vec<String>,
+    }
+
+    /// `AgentAccount` as written by the first release of the program
+    #[derive(BorshSerialize)]
+    struct LegacyAccount {
+        authority: Pubkey,
+        name: String,
+        config: LegacyConfig,
+        state: AgentState,
+        last_execution: i64,
+        execution_count: u64,
+    }
+
+    fn legacy_account(authority: Pubkey) -> Vec<u8> {
+        let mut data = borsh::to_vec(&LegacyAccount {
+            authority,
+            name: "legacy".to_string(),
+            config: LegacyConfig {
+                autonomous_mode: false,
+                execution_limit: 5,
+                memory_limit: 100,
+                capabilities: vec!["compute".to_string()],
+            },
+            state: AgentState::Running,
+            last_execution: 42,
+            execution_count: 3,
+        })
+        .unwrap();
+        data.resize(data.len() + 32, 0);
+        data
+    }
+
+    #[test]
+    fn test_unpack_upgrades_v1() {
+        // Authority key starting with the current version byte
+        let mut key = [7u8; 32];
+        key[0] = AGENT_ACCOUNT_VERSION;
+        let authority = Pubkey::new_from_array(key);
+        let data = legacy_account(authority);
+
+        let agent = AgentAccount::unpack(&data).unwrap();
+        assert_eq!(agent.version, AGENT_ACCOUNT_VERSION);
+        assert_eq!((agent.authority, agent.creator), (authority, authority));
+        assert_eq!(agent.name, "legacy");
+        assert_eq!((&agent.state, agent.execution_count), (&AgentState::Running, 3));
+        assert_eq!(agent.pending_authority, None);
+
+        // The bump is recovered from the agent's address
+        let program_id = Pubkey::new_unique();
+        let (address, bump) = crate::solana::program::pda::find_agent_address(&program_id, &authority, "legacy");
+        let agent = AgentAccount::unpack_at(&data, &program_id, &address).unwrap();
+        assert_eq!(agent.bump, bump);
+        assert!(agent.verify_address(&program_id, &address).is_ok());
+        assert_eq!(
+            AgentAccount::unpack_at(&data, &program_id, &Pubkey::new_unique()),
+            Err(AgentError::InvalidProgramAddress.into())
+        );
+
+        // Chunked and price-conditioned executions keep working
+        let chunked = AgentInstruction::WriteChunk { offset: 0, data: vec![] };
+        let conditional = AgentInstruction::ExecuteConditional {
+            action: AgentAction::Noop,
+            condition: PriceCondition {
+                feed: Pubkey::new_unique(),
+                comparator: Comparator::GreaterThan,
+                threshold: 0,
+                expo: 0,
+                max_staleness_slots: 0,
+            },
+        };
+        for instruction in [chunked, conditional] {
+            assert!(agent.config.capabilities.contains(instruction.required_capabilities()));
+        }
+
+        // Once rewritten the account reads back in the current layout
+        let current = borsh::to_vec(&agent).unwrap();
+        assert_eq!(current[0], AGENT_ACCOUNT_VERSION);
+        assert_eq!(AgentAccount::unpack(&current).unwrap().last_execution, 42);
+    }
+
+    #[cfg(feature = "zero-copy")]
+    #[test]
+    fn test_unpack_v1_with_zero_copy_version_byte() {
+        use crate::solana::program::zero_copy::ZERO_COPY_VERSION;
+
+        // Authority key starting with the zero-copy version byte
+        let mut key = [7u8; 32];
+        key[0] = ZERO_COPY_VERSION;
+        let authority = Pubkey::new_from_array(key);
+        let data = legacy_account(authority);
+
+        let program_id = Pubkey::new_unique();
+        let (address, bump) = crate::solana::program::pda::find_agent_address(&program_id, &authority, "legacy");
+        let agent = AgentAccount::unpack_at(&data, &program_id, &address).unwrap();
+        assert_eq!(agent.authority, authority);
+        assert_eq!(agent.name, "legacy");
+        assert_eq!((agent.bump, agent.execution_count), (bump, 3));
+    }
+
+    #[test]
+    fn test_pending_update_len() {
+        let pending = PendingUpdate {
+            agent: Pubkey::new_unique(),
+            config: Some(AgentConfig {
+                autonomous_mode: true,
+                execution_limit: 5,
+                memory_limit: 100,
+                capabilities: CapabilityFlags::ALL,
+                min_execution_interval: 10,
+                allowed_programs: vec![Pubkey::new_unique(); MAX_ALLOWED_PROGRAMS],
+            }),
+            update_delay: Some(100),
+            activation_slot: 1_000,
+            bump: 255,
+        };
+        let data = borsh::to_vec(&pending).unwrap();
+        assert_eq!(data.len(), PendingUpdate::LEN);
+        assert_eq!(PendingUpdate::unpack(&data).unwrap(), pending);
+    }
+
+    #[test]
+    fn test_executors() {
+        let config = AgentConfig {
+            autonomous_mode: true,
+            execution_limit: 5,
+            memory_limit: 100,
+            capabilities: CapabilityFlags::COMPUTE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+        let mut agent = AgentAccount::new(Pubkey::new_unique(), "agent".to_string(), config);
+        let bot = Pubkey::new_unique();
+
+        assert_eq!(agent.add_executor(bot), Ok(true));
+        assert_eq!(agent.add_executor(bot), Ok(false));
+        // Zero-copy accounts already reserve room for every executor
+        let grown = if cfg!(feature = "zero-copy") { 0 } else { 32 };
+        assert_eq!(agent.required_space(), AgentAccount::space(&agent.name, &agent.config) + grown);
+
+        for _ in 1..MAX_EXECUTORS {
+            agent.add_executor(Pubkey::new_unique()).unwrap();
+        }
+        assert_eq!(agent.add_executor(Pubkey::new_unique()), Err(AgentError::TooManyExecutors));
+        assert!(agent.required_space() <= AgentAccount::max_space());
+
+        assert!(agent.remove_executor(&bot));
+        assert!(!agent.remove_executor(&bot));
+    }
+
+    #[test]
+    fn test_pause_and_auto_resume() {
+        let config = AgentConfig {
+            autonomous_mode: true,
+            execution_limit: 5,
+            memory_limit: 100,
+            capabilities: CapabilityFlags::COMPUTE,
+            min_execution_interval: 0,
+            allowed_programs: vec![],
+        };
+        let mut agent = AgentAccount::new(Pubkey::new_unique(), "agent".to_string(), config);
+        agent.state = AgentState::Running;
+
+        agent.pause(PauseReason::Maintenance, Some(1_000));
+        assert_eq!(agent.state, AgentState::Paused);
+        assert!(!agent.auto_resume_due(999));
+        assert!(agent.auto_resume_due(1_000));
+        assert!(agent.is_paused_at(999));
+        assert!(!agent.is_paused_at(1_000));
+
+        agent.resume();
+        assert_eq!(agent.state, AgentState::Running);
+        assert_eq!((agent.pause_reason, agent.resume_at), (None, None));
+        assert!(!agent.auto_resume_due(2_000));
+
+        // Without a resume time the agent stays paused
+        agent.pause(PauseReason::RiskLimit, None);
+        assert!(!agent.auto_resume_due(i64::MAX));
+        assert!(agent.is_paused_at(i64::MAX));
+    }
+
+    #[test]
+    fn test_max_space() {
+        let config = AgentConfig {
+            autonomous_mode: true,
+            execution_limit: 1000,
+            memory_limit: 5000,
+            capabilities: CapabilityFlags::ALL,
+            min_execution_interval: 0,
+            allowed_programs: vec![Pubkey::new_unique(); MAX_ALLOWED_PROGRAMS],
+        };
+        assert!(AgentAccount::space("test_agent", &config) <= AgentAccount::max_space());
+        assert!(AgentAccount::max_space() < 1_024);
+    }
+
+    #[test]
+    fn test_staging_header_len() {
+        let header = StagingHeader {
+            is_initialized: true,
+            agent: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            written: 42,
+        };
+        assert_eq!(borsh::to_vec(&header).unwrap().len(), StagingHeader::LEN);
+        assert_eq!(StagingHeader::capacity(StagingHeader::space(1_000)), 1_000);
+    }
+
+    #[test]
+    fn test_delegate_record() {
+        let record = DelegateRecord {
+            is_initialized: true,
+            agent: Pubkey::new_unique(),
+            authority: Pubkey::new_unique(),
+            delegate: Pubkey::new_unique(),
+            expiry_slot: 100,
+            permissions: DELEGATE_EXECUTE,
+            bump: 255,
+            generation: 1,
+        };
+        assert_eq!(borsh::to_vec(&record).unwrap().len(), DelegateRecord::LEN);
+
+        assert!(record.check_permission(DELEGATE_EXECUTE).is_ok());
+        assert_eq!(record.check_permission(DELEGATE_UPDATE), Err(AgentError::Unauthorized));
+        assert!(record.check_expiry(100).is_ok());
+        assert_eq!(record.check_expiry(101), Err(AgentError::DelegateExpired));
+
+        // Revoked records grant nothing
+        assert_eq!(
+            DelegateRecord::default().check_permission(0),
+            Err(AgentError::Unauthorized)
+        );
+    }
+
+    #[test]
+    fn test_program_config_len() {
+        let config = ProgramConfig {
+            is_initialized: true,
+            admin: Pubkey::new_unique(),
+            frozen: true,
+            bump: 254,
+            pyth_program: Pubkey::new_unique(),
+            switchboard_program: Pubkey::new_unique(),
+        };
+        let data = borsh::to_vec(&config).unwrap();
+        assert_eq!(data.len(), ProgramConfig::LEN);
+        assert_eq!(ProgramConfig::unpack(&data).unwrap(), config);
+    }
+
+    #[test]
+    fn test_agent_metadata() {
+        let mut metadata = AgentMetadata::new(Pubkey::new_unique(), 1_700_000_000, 254);
+        assert_eq!((metadata.updated_at, metadata.version), (1_700_000_000, 1));
+
+        metadata.record_update(1_700_000_100);
+        assert_eq!((metadata.created_at, metadata.updated_at, metadata.version), (1_700_000_000, 1_700_000_100, 2));
+
+        let data = borsh::to_vec(&metadata).unwrap();
+        assert_eq!(data.len(), AgentMetadata::LEN);
+        assert_eq!(AgentMetadata::unpack(&data).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_stake_account() {
+        let mut stake = StakeAccount::new(Pubkey::new_unique(), Pubkey::new_unique(), 253);
+        assert!(!stake.is_sufficient());
+        stake.amount = MIN_AGENT_STAKE;
+        assert!(stake.is_sufficient());
+
+        let data = borsh::to_vec(&stake).unwrap();
+        assert_eq!(data.len(), StakeAccount::LEN);
+        assert_eq!(StakeAccount::unpack(&data).unwrap(), stake);
+    }
+
+    #[test]
+    fn test_execution_receipt_len() {
+        let receipt = ExecutionReceipt {
+            agent: Pubkey::new_unique(),
+            execution: 1,
+            signer: Pubkey::new_unique(),
+            slot: 100,
+            timestamp: 1_700_000_000,
+            action_hash: [1; 32],
+            status: ExecutionStatus::Failed { error: 6 },
+            bump: 255,
+            memo_hash: Some([2; 32]),
+        };
+        let data = borsh::to_vec(&receipt).unwrap();
+        assert_eq!(data.len(), ExecutionReceipt::LEN);
+        assert_eq!(ExecutionReceipt::unpack(&data).unwrap(), receipt);
+
+        // Receipts sized before memo hashes read as having none, including
+        // failed ones with no room left for the tag
+        let failed = ExecutionReceipt { memo_hash: None, ..receipt };
+        let legacy = borsh::to_vec(&failed).unwrap();
+        assert_eq!(ExecutionReceipt::unpack(&legacy[..ExecutionReceipt::LEGACY_LEN]).unwrap(), failed);
+        assert!(ExecutionReceipt::unpack(&legacy[..ExecutionReceipt::LEGACY_LEN - 1]).is_err());
+
+        let succeeded = ExecutionReceipt { status: ExecutionStatus::Succeeded, ..failed };
+        let mut legacy = borsh::to_vec(&succeeded).unwrap();
+        legacy.resize(ExecutionReceipt::LEGACY_LEN, 0);
+        assert_eq!(ExecutionReceipt::unpack(&legacy).unwrap(), succeeded);
+    }
+
+    #[test]
+    fn test_result_account_ring_buffer() {
+        let mut account = ResultAccount::new(Pubkey::new_unique(), 255);
+        let result = |execution: u64| ExecutionResult {
+            execution,
+            status: ExecutionStatus::Failed { error: 6 },
+            output_hash: [1; 32],
+            timestamp: 1_700_000_000 + execution as i64,
+        };
+        assert_eq!(account.latest(), None);
+
+        for execution in 1..=RESULT_HISTORY_LEN as u64 + 3 {
+            account.push(result(execution));
+        }
+        let executions: Vec<u64> = account.recent().map(|result| result.execution).collect();
+        let expected: Vec<u64> = (4..=RESULT_HISTORY_LEN as u64 + 3).collect();
+        assert_eq!(executions, expected);
+        assert_eq!(account.latest(), Some(&result(RESULT_HISTORY_LEN as u64 + 3)));
+
+        // A full buffer fills the whole account
+        let data = borsh::to_vec(&account).unwrap();
+        assert_eq!(data.len(), ResultAccount::LEN);
+        assert_eq!(ResultAccount::unpack(&data).unwrap(), account);
+
+        let mut partial = ResultAccount::new(account.agent, 255);
+        partial.push(result(1));
+        let mut data = borsh::to_vec(&partial).unwrap();
+        data.resize(ResultAccount::LEN, 0);
+        assert_eq!(ResultAccount::unpack(&data).unwrap(), partial);
+        assert_eq!(partial.recent().count(), 1);
+    }
+
+    #[test]
+    fn test_agent_registry() {
+        let mut registry = AgentRegistry {
+            authority: Pubkey::new_unique(),
+            bump: 255,
+            created: 0,
+            agents: vec![],
+        };
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        assert!(registry.insert(first));
+        assert!(registry.insert(second));
+        assert!(!registry.insert(first));
+        assert_eq!(borsh::to_vec(&registry).unwrap().len(), AgentRegistry::space(2));
+
+        assert!(registry.remove(&first));
+        assert!(!registry.remove(&first));
+        assert_eq!(registry.agents, vec![second]);
+
+        // A registry that shrank still reads back from its larger account
+        let mut data = borsh::to_vec(&registry).unwrap();
+        data.resize(AgentRegistry::space(2), 0);
+        assert_eq!(AgentRegistry::unpack(&data).unwrap(), registry);
+    }
+
     #[test]
     fn test_performance_metrics() {
-        let metrics = PerformanceMetrics::default();
+        let mut metrics = PerformanceMetrics::default();
         assert_eq!(metrics.total_executions, 0);
         assert_eq!(metrics.successful_executions, 0);
         assert_eq!(metrics.failed_executions, 0);
+        assert_eq!(metrics.average_compute_units, 0);
+
+        metrics.record(true, 1_000);
+        metrics.record(true, 3_000);
+        metrics.record(false, 2_600);
+        assert_eq!(metrics.total_executions, 3);
+        assert_eq!(metrics.successful_executions, 2);
+        assert_eq!(metrics.failed_executions, 1);
+        assert_eq!(metrics.total_compute_units, 6_600);
+        assert_eq!(metrics.average_execution_time, 0);
+        assert_eq!(metrics.average_compute_units, 2_200);
     }
 }
\ No newline at end of file
@@ -0,0 +1,352 @@
+//! Fixed-size agent account layout read in place
+//!
+//! With the `zero-copy` feature, agents are stored as an `AgentAccountZc`
+//! instead of the Borsh layout: the name, program allowlist and executors
+//! are padded to their maximum size, so every field sits at a fixed
+//! offset and the account is cast with `bytemuck` rather than decoded.
+//! The processor loads agents through `AgentAccountZc::load`, and
+//! executions update their counters in place (see
+//! `AgentAccountZc::record_execution`).
+//!
+//! The layout is identified by `ZERO_COPY_VERSION` in its first byte, so
+//! `AgentAccount::unpack` reads both layouts. Borsh accounts are rewritten
+//! in this layout on their next save, growing to `AgentAccountZc::LEN`.
+//! Clients reading such accounts need the feature as well.
+
+use bytemuck::{Pod, Zeroable};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use crate::solana::program::{
+    capability::CapabilityFlags,
+    instruction::{AgentConfig, MAX_ALLOWED_PROGRAMS},
+    state::{AgentAccount, AgentState, PauseReason, PerformanceMetrics, AGENT_ACCOUNT_VERSION, MAX_EXECUTORS, MAX_NAME_LEN},
+};
+
+/// Version byte of the zero-copy layout, distinct from every Borsh version
+pub const ZERO_COPY_VERSION: u8 = 0x80 | AGENT_ACCOUNT_VERSION;
+
+/// Offset of `AgentAccountZc::state`, for RPC filters
+pub const STATE_OFFSET: usize = 1;
+
+/// Offset of `AgentAccountZc::authority`, for RPC filters
+pub const AUTHORITY_OFFSET: usize = 96;
+
+/// `AgentAccount` with fixed-size fields. Fields are ordered so the struct
+/// has no padding; flags are stored as `u8`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct AgentAccountZc {
+    /// Always `ZERO_COPY_VERSION`
+    pub version: u8,
+    /// `AgentState` discriminant
+    pub state: u8,
+    pub autonomous_mode: u8,
+    pub bump: u8,
+    pub name_len: u8,
+    pub has_pending_authority: u8,
+    pub allowed_programs_len: u8,
+    /// `PauseReason` discriminant plus one, 0 without a reason
+    pub pause_reason: u8,
+    pub min_execution_interval: u32,
+    pub has_resume_at: u8,
+    pub executors_len: u8,
+    pub has_parent: u8,
+    pub _padding: u8,
+    pub execution_limit: u64,
+    pub memory_limit: u64,
+    pub capabilities: u64,
+    pub last_execution: i64,
+    pub execution_count: u64,
+    pub total_executions: u64,
+    pub successful_executions: u64,
+    pub failed_executions: u64,
+    pub average_execution_time: u64,
+    pub total_compute_units: u64,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub creator: Pubkey,
+    pub name: [u8; MAX_NAME_LEN],
+    pub allowed_programs: [Pubkey; MAX_ALLOWED_PROGRAMS],
+    pub resume_at: i64,
+    pub executors: [Pubkey; MAX_EXECUTORS],
+    pub parent: Pubkey,
+    pub update_delay: u64,
+    pub average_compute_units: u64,
+    pub generation: u64,
+}
+
+impl AgentAccountZc {
+    pub const LEN: usize = std::mem::size_of::<Self>();
+
+    /// Whether `data` holds an agent in this layout
+    pub fn is_zero_copy(data: &[u8]) -> bool {
+        data.first() == Some(&ZERO_COPY_VERSION)
+    }
+
+    /// Cast account data, which must start with the current layout
+    pub fn load(data: &[u8]) -> Result<&Self, ProgramError> {
+        if data.first() != Some(&ZERO_COPY_VERSION) || data.len() < Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        bytemuck::try_from_bytes(&data[..Self::LEN]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Copy account data in this layout, however it is aligned
+    pub fn read(data: &[u8]) -> Result<Self, ProgramError> {
+        if !Self::is_zero_copy(data) || data.len() < Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(bytemuck::pod_read_unaligned(&data[..Self::LEN]))
+    }
+
+    /// Cast account data mutably, which must start with the current layout
+    pub fn load_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        if data.first() != Some(&ZERO_COPY_VERSION) || data.len() < Self::LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        bytemuck::try_from_bytes_mut(&mut data[..Self::LEN]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    /// Write `agent` to account data in this layout
+    pub fn store(agent: &AgentAccount, data: &mut [u8]) -> Result<(), ProgramError> {
+        let stored = Self::from_account(agent)?;
+        if data.len() < Self::LEN {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        data[..Self::LEN].copy_from_slice(bytemuck::bytes_of(&stored));
+        Ok(())
+    }
+
+    pub fn from_account(agent: &AgentAccount) -> Result<Self, ProgramError> {
+        let name = agent.name.as_bytes();
+        let programs = &agent.config.allowed_programs;
+        let executors = &agent.executors;
+        if name.len() > MAX_NAME_LEN || programs.len() > MAX_ALLOWED_PROGRAMS || executors.len() > MAX_EXECUTORS {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut stored = Self::zeroed();
+        stored.version = ZERO_COPY_VERSION;
+        stored.state = agent.state.clone() as u8;
+        stored.autonomous_mode = agent.config.autonomous_mode as u8;
+        stored.bump = agent.bump;
+        stored.name_len = name.len() as u8;
+        stored.has_pending_authority = agent.pending_authority.is_some() as u8;
+        stored.allowed_programs_len = programs.len() as u8;
+        stored.pause_reason = agent.pause_reason.map_or(0, |reason| reason as u8 + 1);
+        stored.has_resume_at = agent.resume_at.is_some() as u8;
+        stored.resume_at = agent.resume_at.unwrap_or_default();
+        stored.min_execution_interval = agent.config.min_execution_interval;
+        stored.execution_limit = agent.config.execution_limit;
+        stored.memory_limit = agent.config.memory_limit;
+        stored.capabilities = agent.config.capabilities.bits();
+        stored.last_execution = agent.last_execution;
+        stored.execution_count = agent.execution_count;
+        stored.set_metrics(&agent.metrics);
+        stored.authority = agent.authority;
+        stored.pending_authority = agent.pending_authority.unwrap_or_default();
+        stored.creator = agent.creator;
+        stored.name[..name.len()].copy_from_slice(name);
+        stored.allowed_programs[..programs.len()].copy_from_slice(programs);
+        stored.executors_len = executors.len() as u8;
+        stored.executors[..executors.len()].copy_from_slice(executors);
+        stored.has_parent = agent.parent.is_some() as u8;
+        stored.parent = agent.parent.unwrap_or_default();
+        stored.update_delay = agent.update_delay;
+        stored.generation = agent.generation;
+        Ok(stored)
+    }
+
+    pub fn to_account(&self) -> Result<AgentAccount, ProgramError> {
+        let name = self
+            .name
+            .get(..self.name_len as usize)
+            .and_then(|name| std::str::from_utf8(name).ok())
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let allowed_programs = self
+            .allowed_programs
+            .get(..self.allowed_programs_len as usize)
+            .ok_or(ProgramError::InvalidAccountData)?;
+        let executors = self
+            .executors
+            .get(..self.executors_len as usize)
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        Ok(AgentAccount {
+            version: AGENT_ACCOUNT_VERSION,
+            authority: self.authority,
+            name: name.to_string(),
+            config: AgentConfig {
+                autonomous_mode: self.autonomous_mode != 0,
+                execution_limit: self.execution_limit,
+                memory_limit: self.memory_limit,
+                capabilities: CapabilityFlags::from_bits(self.capabilities),
+                min_execution_interval: self.min_execution_interval,
+                allowed_programs: allowed_programs.to_vec(),
+            },
+            state: self.state()?,
+            last_execution: self.last_execution,
+            execution_count: self.execution_count,
+            pending_authority: (self.has_pending_authority != 0).then_some(self.pending_authority),
+            creator: self.creator,
+            bump: self.bump,
+            metrics: self.metrics(),
+            pause_reason: self.pause_reason()?,
+            resume_at: (self.has_resume_at != 0).then_some(self.resume_at),
+            executors: executors.to_vec(),
+            parent: (self.has_parent != 0).then_some(self.parent),
+            update_delay: self.update_delay,
+            generation: self.generation,
+        })
+    }
+
+    pub fn pause_reason(&self) -> Result<Option<PauseReason>, ProgramError> {
+        match self.pause_reason {
+            0 => Ok(None),
+            1 => Ok(Some(PauseReason::Manual)),
+            2 => Ok(Some(PauseReason::Maintenance)),
+            3 => Ok(Some(PauseReason::RiskLimit)),
+            4 => Ok(Some(PauseReason::Emergency)),
+            5 => Ok(Some(PauseReason::Other)),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    pub fn state(&self) -> Result<AgentState, ProgramError> {
+        match self.state {
+            0 => Ok(AgentState::Uninitialized),
+            1 => Ok(AgentState::Initialized),
+            2 => Ok(AgentState::Running),
+            3 => Ok(AgentState::Paused),
+            4 => Ok(AgentState::Error),
+            5 => Ok(AgentState::Terminated),
+            _ => Err(ProgramError::InvalidAccountData),
+        }
+    }
+
+    pub fn metrics(&self) -> PerformanceMetrics {
+        PerformanceMetrics {
+            total_executions: self.total_executions,
+            successful_executions: self.successful_executions,
+            failed_executions: self.failed_executions,
+            average_execution_time: self.average_execution_time,
+            total_compute_units: self.total_compute_units,
+            average_compute_units: self.average_compute_units,
+        }
+    }
+
+    pub fn set_metrics(&mut self, metrics: &PerformanceMetrics) {
+        self.total_executions = metrics.total_executions;
+        self.successful_executions = metrics.successful_executions;
+        self.failed_executions = metrics.failed_executions;
+        self.average_execution_time = metrics.average_execution_time;
+        self.total_compute_units = metrics.total_compute_units;
+        self.average_compute_units = metrics.average_compute_units;
+    }
+
+    /// Write back the fields an execution changes, leaving the rest of the
+    /// account untouched
+    pub fn record_execution(&mut self, agent: &AgentAccount) {
+        self.last_execution = agent.last_execution;
+        self.execution_count = agent.execution_count;
+        self.set_metrics(&agent.metrics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent() -> AgentAccount {
+        let mut agent = AgentAccount::new(
+            Pubkey::new_unique(),
+            "zero_copy_agent".to_string(),
+            AgentConfig {
+                autonomous_mode: true,
+                execution_limit: 100,
+                memory_limit: 5000,
+                capabilities: CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE,
+                min_execution_interval: 30,
+                allowed_programs: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            },
+        );
+        agent.state = AgentState::Running;
+        agent.pending_authority = Some(Pubkey::new_unique());
+        agent.bump = 254;
+        agent.metrics.record(true, 1_200);
+        agent.pause(PauseReason::Emergency, Some(1_800_000_000));
+        agent.executors = vec![Pubkey::new_unique()];
+        agent.parent = Some(Pubkey::new_unique());
+        agent.update_delay = 150;
+        agent
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let agent = agent();
+        // u64 buffer so the cast is aligned, as account data is on-chain
+        let mut buffer = vec![0u64; AgentAccountZc::LEN / 8];
+        let data: &mut [u8] = bytemuck::cast_slice_mut(&mut buffer);
+        AgentAccountZc::store(&agent, data).unwrap();
+
+        let stored = AgentAccountZc::load(data).unwrap();
+        assert_eq!(stored.version, ZERO_COPY_VERSION);
+        let unpacked = stored.to_account().unwrap();
+        assert_eq!(unpacked.name, agent.name);
+        assert_eq!(unpacked.config, agent.config);
+        assert_eq!(unpacked.state, agent.state);
+        assert_eq!(unpacked.pending_authority, agent.pending_authority);
+        assert_eq!(unpacked.metrics, agent.metrics);
+        assert_eq!(unpacked.bump, agent.bump);
+        assert_eq!(unpacked.pause_reason, agent.pause_reason);
+        assert_eq!(unpacked.resume_at, agent.resume_at);
+        assert_eq!(unpacked.executors, agent.executors);
+        assert_eq!(unpacked.parent, agent.parent);
+        assert_eq!(unpacked.update_delay, agent.update_delay);
+
+        let unpacked = AgentAccount::unpack(data).unwrap();
+        assert_eq!(unpacked.creator, agent.creator);
+    }
+
+    #[test]
+    fn test_record_execution_in_place() {
+        let mut agent = agent();
+        let mut buffer = vec![0u64; AgentAccountZc::LEN / 8];
+        let data: &mut [u8] = bytemuck::cast_slice_mut(&mut buffer);
+        AgentAccountZc::store(&agent, data).unwrap();
+
+        agent.record_execution(1_700_000_000);
+        agent.metrics.record(false, 800);
+        AgentAccountZc::load_mut(data).unwrap().record_execution(&agent);
+
+        let unpacked = AgentAccount::unpack(data).unwrap();
+        assert_eq!(unpacked.execution_count, 1);
+        assert_eq!(unpacked.last_execution, 1_700_000_000);
+        assert_eq!(unpacked.metrics.failed_executions, 1);
+    }
+
+    #[test]
+    fn test_field_offsets() {
+        let mut stored = AgentAccountZc::zeroed();
+        stored.state = AgentState::Paused as u8;
+        stored.authority = Pubkey::new_unique();
+
+        let data = bytemuck::bytes_of(&stored);
+        assert_eq!(data[STATE_OFFSET], AgentState::Paused as u8);
+        assert_eq!(&data[AUTHORITY_OFFSET..AUTHORITY_OFFSET + 32], stored.authority.as_ref());
+    }
+
+    #[test]
+    fn test_rejects_other_layouts() {
+        let agent = agent();
+        let data = borsh::to_vec(&agent).unwrap();
+        assert!(AgentAccountZc::load(&data).is_err());
+
+        let mut too_long = agent;
+        too_long.config.allowed_programs = vec![Pubkey::new_unique(); MAX_ALLOWED_PROGRAMS + 1];
+        assert!(AgentAccountZc::from_account(&too_long).is_err());
+
+        too_long.config.allowed_programs.clear();
+        too_long.executors = vec![Pubkey::new_unique(); MAX_EXECUTORS + 1];
+        assert!(AgentAccountZc::from_account(&too_long).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Sending and confirming client transactions
+//!
+//! This module provides:
+//! - A configurable send strategy shared by client operations
+//! - Confirmation through a signature subscription or by polling
+//! - Re-signing with a fresh blockhash once the previous one expired
+//!
+//! A transaction is only re-sent after its blockhash expired without it
+//! landing, so a retry can never execute twice. When the confirmation
+//! timeout runs out first, the signature is returned in the error, as the
+//! transaction may still land.
+
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use solana_client::{
+    client_error::{ClientError as RpcError, ClientErrorKind},
+    pubsub_client::{PubsubClient, SignatureSubscription},
+    rpc_client::RpcClient,
+    rpc_config::RpcSignatureSubscribeConfig,
+    rpc_response::RpcSignatureResult,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    hash::Hash,
+    signature::Signature,
+    transaction::{TransactionError, VersionedTransaction},
+};
+use crate::error::{SonomaError, SonomaResult};
+use crate::solana::client;
+
+/// How often the signature status and blockhash are checked while waiting
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How transactions are sent and confirmed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SendStrategy {
+    /// Attempts after the first, each re-signed with a fresh blockhash
+    pub max_retries: u32,
+    /// How long to wait for each attempt to be confirmed
+    pub confirm_timeout: Duration,
+    /// Wait on a PubSub signature subscription instead of only polling
+    pub use_subscription: bool,
+}
+
+impl Default for SendStrategy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            confirm_timeout: Duration::from_secs(60),
+            use_subscription: true,
+        }
+    }
+}
+
+/// Outcome of waiting for one attempt
+enum Confirmation {
+    Landed(Result<(), TransactionError>),
+    Expired,
+}
+
+/// Send the transaction `sign` returns for a blockhash, re-signing it with
+/// a fresh blockhash each time the previous one expired
+pub fn send_with_strategy<F>(rpc: &RpcClient, strategy: &SendStrategy, mut sign: F) -> SonomaResult<Signature>
+where
+    F: FnMut(Hash) -> SonomaResult<VersionedTransaction>,
+{
+    for _ in 0..=strategy.max_retries {
+        let blockhash = rpc.get_latest_blockhash()?;
+        let transaction = sign(blockhash)?;
+        let signature = transaction.signatures[0];
+
+        // Subscribe before sending so the notification can't be missed
+        let subscription = if strategy.use_subscription {
+            Some(subscribe(rpc, &signature)?)
+        } else {
+            None
+        };
+        rpc.send_transaction(&transaction)?;
+        let confirmation = confirm(rpc, strategy, &signature, &blockhash, subscription.as_ref());
+        if let Some((mut client, _)) = subscription {
+            let _ = client.shutdown();
+        }
+
+        match confirmation? {
+            Confirmation::Landed(Ok(())) => return Ok(signature),
+            Confirmation::Landed(Err(error)) => {
+                return Err(RpcError::from(ClientErrorKind::TransactionError(error)).into())
+            }
+            Confirmation::Expired => continue,
+        }
+    }
+
+    Err(SonomaError::Expired(strategy.max_retries + 1))
+}
+
+fn subscribe(rpc: &RpcClient, signature: &Signature) -> SonomaResult<SignatureSubscription> {
+    let config = RpcSignatureSubscribeConfig {
+        commitment: Some(rpc.commitment()),
+        enable_received_notification: Some(false),
+    };
+    Ok(PubsubClient::signature_subscribe(&client::websocket_url(&rpc.url()), signature, Some(config))?)
+}
+
+/// Wait until `signature` landed, its blockhash expired or the timeout ran
+/// out, whichever comes first
+fn confirm(
+    rpc: &RpcClient,
+    strategy: &SendStrategy,
+    signature: &Signature,
+    blockhash: &Hash,
+    subscription: Option<&SignatureSubscription>,
+) -> SonomaResult<Confirmation> {
+    let deadline = Instant::now() + strategy.confirm_timeout;
+
+    loop {
+        let wait = POLL_INTERVAL.min(deadline.saturating_duration_since(Instant::now()));
+        match subscription {
+            Some((_, receiver)) => {
+                if let Ok(response) = receiver.recv_timeout(wait) {
+                    if let RpcSignatureResult::ProcessedSignature(result) = response.value {
+                        return Ok(Confirmation::Landed(result.err.map_or(Ok(()), Err)));
+                    }
+                }
+            }
+            None => thread::sleep(wait),
+        }
+
+        if let Some(result) = rpc.get_signature_status_with_commitment(signature, rpc.commitment())? {
+            return Ok(Confirmation::Landed(result));
+        }
+        // A processed transaction may still be confirmed, so only treat
+        // the attempt as expired when it wasn't processed either
+        if !rpc.is_blockhash_valid(blockhash, CommitmentConfig::processed())?
+            && rpc.get_signature_status_with_commitment(signature, CommitmentConfig::processed())?.is_none()
+        {
+            return Ok(Confirmation::Expired);
+        }
+        if Instant::now() >= deadline {
+            return Err(SonomaError::Timeout(*signature));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::{
+        instruction::Instruction,
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        transaction::Transaction,
+    };
+
+    fn strategy() -> SendStrategy {
+        SendStrategy {
+            max_retries: 0,
+            confirm_timeout: Duration::ZERO,
+            use_subscription: false,
+        }
+    }
+
+    fn sign(payer: &Keypair) -> impl FnMut(Hash) -> SonomaResult<VersionedTransaction> + '_ {
+        move |blockhash| {
+            let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+            let transaction =
+                Transaction::new_signed_with_payer(&[instruction], Some(&payer.pubkey()), &[payer], blockhash);
+            Ok(transaction.into())
+        }
+    }
+
+    #[test]
+    fn test_send_confirmed() {
+        let payer = Keypair::new();
+        let rpc = RpcClient::new_mock("succeeds".to_string());
+        assert!(send_with_strategy(&rpc, &strategy(), sign(&payer)).is_ok());
+
+        let rpc = RpcClient::new_mock("instruction_error".to_string());
+        assert!(matches!(
+            send_with_strategy(&rpc, &strategy(), sign(&payer)),
+            Err(SonomaError::Client(_))
+        ));
+    }
+
+    #[test]
+    fn test_send_expired() {
+        let payer = Keypair::new();
+        let expired = json!({"context": {"slot": 1}, "value": false});
+        let rpc = RpcClient::new_mock_with_mocks(
+            "sig_not_found".to_string(),
+            HashMap::from([(RpcRequest::IsBlockhashValid, expired)]),
+        );
+        assert!(matches!(
+            send_with_strategy(&rpc, &strategy(), sign(&payer)),
+            Err(SonomaError::Expired(1))
+        ));
+    }
+}
@@ -0,0 +1,482 @@
+//! Signers for client transactions
+//!
+//! This module provides:
+//! - The `SonomaSigner` trait, a thread-safe transaction signer
+//! - Keypairs loaded from a file or an environment variable
+//! - A remote signer delegating signatures to an HTTP signing service
+//! - Loading a signer from its configured source, and saving that source
+//!   to a configuration file
+//! - Generating new keypair files, e.g. to rotate an authority
+//!
+//! With a remote signer the secret key never enters the process: only the
+//! message is sent, and the returned signature is checked against the
+//! signer's public key before use. Its HTTP requests run on a dedicated
+//! thread, so it can sign from within a tokio runtime.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    bs58,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, write_keypair, Keypair, Signature},
+    signer::{Signer, SignerError},
+};
+
+/// Timeout of a remote signing request
+pub const REMOTE_SIGN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Transaction signer that can be shared between threads
+pub trait SonomaSigner: Signer + AsSigner + Send + Sync {
+    /// Where the key is held, for logs; never includes key material
+    fn source(&self) -> String;
+}
+
+/// `&dyn Signer` view of a signer, to pass a `dyn SonomaSigner` where
+/// `Signers` are expected
+pub trait AsSigner {
+    fn as_signer(&self) -> &dyn Signer;
+}
+
+impl<T: Signer> AsSigner for T {
+    fn as_signer(&self) -> &dyn Signer {
+        self
+    }
+}
+
+impl SonomaSigner for Keypair {
+    fn source(&self) -> String {
+        "in-memory".to_string()
+    }
+}
+
+/// Where to load a signer from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerSource {
+    /// JSON keypair file, as written by `solana-keygen`
+    File(PathBuf),
+    /// Environment variable holding a keypair
+    Env(String),
+    /// HTTP signing service holding the key of `pubkey`
+    Remote {
+        url: String,
+        pubkey: Pubkey,
+        /// Environment variable holding the bearer token sent to the
+        /// service, if it requires one
+        #[serde(default)]
+        auth_token_env: Option<String>,
+    },
+}
+
+impl SignerSource {
+    pub fn load(&self) -> Result<Arc<dyn SonomaSigner>, SignerError> {
+        Ok(match self {
+            Self::File(path) => Arc::new(FileSigner::load(path)?),
+            Self::Env(var) => Arc::new(EnvSigner::from_env(var)?),
+            Self::Remote { url, pubkey, auth_token_env } => {
+                let mut signer = RemoteSigner::new(url, *pubkey)?;
+                if let Some(var) = auth_token_env {
+                    let token =
+                        std::env::var(var).map_err(|error| SignerError::InvalidInput(format!("{}: {}", var, error)))?;
+                    signer = signer.with_auth_token(token);
+                }
+                Arc::new(signer)
+            }
+        })
+    }
+
+    /// Source saved at `path` by `save`
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SignerError> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|error| SignerError::InvalidInput(format!("{}: {}", path.display(), error)))?;
+        serde_json::from_str(&json).map_err(|error| SignerError::InvalidInput(format!("{}: {}", path.display(), error)))
+    }
+
+    /// Save the source to `path` as JSON, replacing the file atomically:
+    /// readers see either the previous source or this one
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SignerError> {
+        let path = path.as_ref();
+        let failed = |error: std::io::Error| SignerError::Custom(format!("{}: {}", path.display(), error));
+        let json = serde_json::to_vec_pretty(self).map_err(|error| SignerError::Custom(error.to_string()))?;
+        let staged = path.with_extension("tmp");
+        fs::write(&staged, json).map_err(failed)?;
+        fs::rename(&staged, path).map_err(failed)
+    }
+}
+
+/// Parse a keypair encoded as a base58 string or a JSON byte array
+pub fn parse_keypair(encoded: &str) -> Result<Keypair, SignerError> {
+    let encoded = encoded.trim();
+    let bytes = if encoded.starts_with('[') {
+        serde_json::from_str::<Vec<u8>>(encoded)
+            .map_err(|error| SignerError::InvalidInput(format!("invalid keypair bytes: {}", error)))?
+    } else {
+        bs58::decode(encoded)
+            .into_vec()
+            .map_err(|error| SignerError::InvalidInput(format!("invalid base58 keypair: {}", error)))?
+    };
+    Keypair::from_bytes(&bytes).map_err(|error| SignerError::InvalidInput(error.to_string()))
+}
+
+/// Keypair read from a JSON keypair file
+pub struct FileSigner {
+    path: PathBuf,
+    keypair: Keypair,
+}
+
+impl FileSigner {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SignerError> {
+        let path = path.as_ref();
+        let keypair = read_keypair_file(path)
+            .map_err(|error| SignerError::InvalidInput(format!("{}: {}", path.display(), error)))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            keypair,
+        })
+    }
+
+    /// Generate a keypair and write it to `path`, readable by the owner
+    /// only; fails rather than overwrite an existing file
+    pub fn generate(path: impl AsRef<Path>) -> Result<Self, SignerError> {
+        let path = path.as_ref();
+        let failed = |error: &dyn fmt::Display| SignerError::Custom(format!("{}: {}", path.display(), error));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let keypair = Keypair::new();
+        let mut file = options.open(path).map_err(|error| failed(&error))?;
+        write_keypair(&keypair, &mut file).map_err(|error| failed(&error))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            keypair,
+        })
+    }
+}
+
+/// Keypair read from an environment variable, base58 or as a JSON byte
+/// array
+pub struct EnvSigner {
+    var: String,
+    keypair: Keypair,
+}
+
+impl EnvSigner {
+    pub fn from_env(var: &str) -> Result<Self, SignerError> {
+        let encoded = std::env::var(var).map_err(|error| SignerError::InvalidInput(format!("{}: {}", var, error)))?;
+        Ok(Self {
+            var: var.to_string(),
+            keypair: parse_keypair(&encoded)?,
+        })
+    }
+}
+
+impl Signer for FileSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        self.keypair.try_pubkey()
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.keypair.try_sign_message(message)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+impl SonomaSigner for FileSigner {
+    fn source(&self) -> String {
+        format!("file:{}", self.path.display())
+    }
+}
+
+impl fmt::Debug for FileSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileSigner")
+            .field("path", &self.path)
+            .field("pubkey", &self.keypair.pubkey())
+            .finish()
+    }
+}
+
+impl Signer for EnvSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        self.keypair.try_pubkey()
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.keypair.try_sign_message(message)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+impl SonomaSigner for EnvSigner {
+    fn source(&self) -> String {
+        format!("env:{}", self.var)
+    }
+}
+
+impl fmt::Debug for EnvSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvSigner")
+            .field("var", &self.var)
+            .field("pubkey", &self.keypair.pubkey())
+            .finish()
+    }
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    pubkey: String,
+    /// Base64-encoded message
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    /// Base58-encoded signature
+    signature: String,
+}
+
+/// Request run by the thread owning a remote signer's HTTP client
+type HttpJob = Box<dyn FnOnce(&reqwest::blocking::Client) + Send>;
+
+/// Signer whose key is held by an HTTP signing service
+///
+/// Messages are POSTed to `{url}/sign` as `{"pubkey", "message"}`, the
+/// message base64-encoded, and the service answers `{"signature"}` in
+/// base58.
+///
+/// The blocking HTTP client can't be created, used or dropped on a tokio
+/// worker, so it lives on a thread of its own, which stops once the signer
+/// is dropped. Signing waits for that thread's answer.
+pub struct RemoteSigner {
+    url: String,
+    pubkey: Pubkey,
+    auth_token: Option<String>,
+    jobs: mpsc::Sender<HttpJob>,
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, pubkey: Pubkey) -> Result<Self, SignerError> {
+        let (jobs, pending) = mpsc::channel::<HttpJob>();
+        let (started, start) = mpsc::channel();
+        thread::Builder::new()
+            .name("remote-signer".to_string())
+            .spawn(move || {
+                let http = match reqwest::blocking::Client::builder().timeout(REMOTE_SIGN_TIMEOUT).build() {
+                    Ok(http) => http,
+                    Err(error) => {
+                        let _ = started.send(Err(SignerError::Connection(error.to_string())));
+                        return;
+                    }
+                };
+                let _ = started.send(Ok(()));
+                for job in pending {
+                    job(&http);
+                }
+            })
+            .map_err(|error| SignerError::Custom(error.to_string()))?;
+        start
+            .recv()
+            .map_err(|_| SignerError::Custom("remote signer thread stopped".to_string()))??;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            pubkey,
+            auth_token: None,
+            jobs,
+        })
+    }
+
+    /// Authenticate signing requests with a bearer token
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    fn request_signature(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let request = SignRequest {
+            pubkey: self.pubkey.to_string(),
+            message: BASE64.encode(message),
+        };
+        let url = format!("{}/sign", self.url);
+        let auth_token = self.auth_token.clone();
+        let (reply, response) = mpsc::channel();
+        let job: HttpJob = Box::new(move |http| {
+            let mut builder = http.post(url).json(&request);
+            if let Some(token) = &auth_token {
+                builder = builder.bearer_auth(token);
+            }
+            let response = builder
+                .send()
+                .and_then(|response| response.error_for_status())
+                .map_err(|error| SignerError::Connection(error.to_string()))
+                .and_then(|response| {
+                    response
+                        .json::<SignResponse>()
+                        .map_err(|error| SignerError::Protocol(error.to_string()))
+                });
+            let _ = reply.send(response);
+        });
+
+        let stopped = || SignerError::Connection("remote signer thread stopped".to_string());
+        self.jobs.send(job).map_err(|_| stopped())?;
+        let response = response.recv().map_err(|_| stopped())??;
+        verify_signature(&self.pubkey, message, &response.signature)
+    }
+}
+
+/// Parse a signature returned for `message` and check it was made by
+/// `pubkey`
+fn verify_signature(pubkey: &Pubkey, message: &[u8], signature: &str) -> Result<Signature, SignerError> {
+    let signature: Signature = signature
+        .parse()
+        .map_err(|error| SignerError::Protocol(format!("invalid signature: {}", error)))?;
+    if !signature.verify(pubkey.as_ref(), message) {
+        return Err(SignerError::Protocol(format!("signature was not made by {}", pubkey)));
+    }
+    Ok(signature)
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.request_signature(message)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+impl SonomaSigner for RemoteSigner {
+    fn source(&self) -> String {
+        format!("remote:{}", self.url)
+    }
+}
+
+impl fmt::Debug for RemoteSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteSigner")
+            .field("url", &self.url)
+            .field("pubkey", &self.pubkey)
+            .field("authenticated", &self.auth_token.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::write_keypair_file;
+
+    #[test]
+    fn test_keypair_sources() {
+        let keypair = Keypair::new();
+        assert_eq!(parse_keypair(&keypair.to_base58_string()).unwrap(), keypair);
+        assert_eq!(parse_keypair(&serde_json::to_string(&keypair.to_bytes().to_vec()).unwrap()).unwrap(), keypair);
+        assert!(matches!(parse_keypair("not a key"), Err(SignerError::InvalidInput(_))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("payer.json");
+        write_keypair_file(&keypair, &path).unwrap();
+        let signer = SignerSource::File(path.clone()).load().unwrap();
+        assert_eq!(signer.pubkey(), keypair.pubkey());
+        assert_eq!(signer.source(), format!("file:{}", path.display()));
+        assert!(FileSigner::load(dir.path().join("missing.json")).is_err());
+
+        let generated = FileSigner::generate(dir.path().join("next.json")).unwrap();
+        assert_eq!(FileSigner::load(dir.path().join("next.json")).unwrap().pubkey(), generated.pubkey());
+        assert!(FileSigner::generate(&path).is_err());
+
+        let config = dir.path().join("signer.json");
+        let source = SignerSource::File(dir.path().join("next.json"));
+        source.save(&config).unwrap();
+        assert_eq!(SignerSource::read(&config).unwrap(), source);
+        assert_eq!(SignerSource::read(&config).unwrap().load().unwrap().pubkey(), generated.pubkey());
+    }
+
+    #[test]
+    fn test_verify_remote_signature() {
+        let keypair = Keypair::new();
+        let signature = keypair.sign_message(b"message");
+        assert_eq!(verify_signature(&keypair.pubkey(), b"message", &signature.to_string()).unwrap(), signature);
+        assert!(verify_signature(&keypair.pubkey(), b"other", &signature.to_string()).is_err());
+        assert!(verify_signature(&Pubkey::new_unique(), b"message", &signature.to_string()).is_err());
+    }
+
+    /// Serve one signing request with `keypair`, returning the request's
+    /// headers and body as received
+    fn serve_signature(keypair: Keypair) -> (String, thread::JoinHandle<String>) {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 4096];
+            let body = loop {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((_, body)) = text.split_once("\r\n\r\n") {
+                    if serde_json::from_str::<serde_json::Value>(body).is_ok() {
+                        break body.to_string();
+                    }
+                }
+            };
+
+            let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+            let message = BASE64.decode(json["message"].as_str().unwrap()).unwrap();
+            let response = serde_json::json!({ "signature": keypair.sign_message(&message).to_string() }).to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+            String::from_utf8_lossy(&request).to_string()
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_remote_signer_in_runtime() {
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let (url, server) = serve_signature(keypair);
+
+        std::env::set_var("SONOMA_TEST_SIGNER_TOKEN", "secret");
+        let source = SignerSource::Remote {
+            url,
+            pubkey,
+            auth_token_env: Some("SONOMA_TEST_SIGNER_TOKEN".to_string()),
+        };
+        let signer = source.load().unwrap();
+        let signature = signer.try_sign_message(b"message").unwrap();
+        assert!(signature.verify(pubkey.as_ref(), b"message"));
+
+        let request = server.join().unwrap().to_lowercase();
+        assert!(request.contains("authorization: bearer secret"));
+        // The HTTP client is dropped on its own thread, not this runtime's
+        drop(signer);
+    }
+}
@@ -0,0 +1,189 @@
+//! Pre-flight simulation of client transactions
+//!
+//! This module provides:
+//! - Simulating a signed transaction without sending it
+//! - Parsing program logs into per-invocation results
+//! - Simulated compute units, errors and agent events
+
+use solana_client::{
+    rpc_client::{RpcClient, SerializableTransaction},
+    rpc_config::RpcSimulateTransactionConfig,
+};
+use solana_sdk::{pubkey::Pubkey, transaction::TransactionError};
+use crate::error::{agent_error, SonomaResult};
+use crate::solana::program::{error::AgentError, event::{AgentEvent, PROGRAM_DATA_PREFIX}};
+
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+/// One program invocation, as reported by the runtime's logs
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProgramInvocation {
+    pub program_id: Pubkey,
+    /// 1 for instructions of the transaction, deeper for CPIs
+    pub depth: u32,
+    /// Messages logged by the program itself
+    pub logs: Vec<String>,
+    /// Base64 data logged by the program with `sol_log_data`, e.g. events
+    pub data: Vec<String>,
+    /// Compute units consumed, including the CPIs it made
+    pub compute_units: Option<u64>,
+    /// Why the invocation failed
+    pub error: Option<String>,
+}
+
+/// Outcome of a simulated transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationResult {
+    pub error: Option<TransactionError>,
+    pub units_consumed: Option<u64>,
+    /// Invocations in the order they started
+    pub invocations: Vec<ProgramInvocation>,
+    pub events: Vec<AgentEvent>,
+    pub logs: Vec<String>,
+}
+
+impl SimulationResult {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Agent program error the simulation failed with
+    pub fn program_error(&self) -> Option<AgentError> {
+        self.error.as_ref().and_then(agent_error)
+    }
+
+    /// Compute units consumed by the top-level invocations of `program_id`
+    pub fn program_units(&self, program_id: &Pubkey) -> u64 {
+        self.invocations
+            .iter()
+            .filter(|invocation| invocation.depth == 1 && invocation.program_id == *program_id)
+            .filter_map(|invocation| invocation.compute_units)
+            .sum()
+    }
+}
+
+/// Simulate a signed transaction against the latest blockhash, without
+/// checking its signatures
+pub fn simulate(rpc: &RpcClient, transaction: &impl SerializableTransaction) -> SonomaResult<SimulationResult> {
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        commitment: Some(rpc.commitment()),
+        ..Default::default()
+    };
+    let result = rpc.simulate_transaction_with_config(transaction, config)?.value;
+    let logs = result.logs.unwrap_or_default();
+
+    Ok(SimulationResult {
+        error: result.err,
+        units_consumed: result.units_consumed,
+        invocations: parse_invocations(&logs),
+        events: AgentEvent::parse_logs(&logs),
+        logs,
+    })
+}
+
+/// Group program logs by invocation
+///
+/// Truncated logs leave the invocations still open at the cut without a
+/// result.
+pub fn parse_invocations<S: AsRef<str>>(logs: &[S]) -> Vec<ProgramInvocation> {
+    let mut invocations: Vec<ProgramInvocation> = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+
+    for line in logs.iter().map(AsRef::as_ref) {
+        if let Some(message) = line.strip_prefix(PROGRAM_LOG_PREFIX) {
+            if let Some(&current) = stack.last() {
+                invocations[current].logs.push(message.to_string());
+            }
+            continue;
+        }
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA_PREFIX) {
+            if let Some(&current) = stack.last() {
+                invocations[current].data.push(data.to_string());
+            }
+            continue;
+        }
+
+        let Some(rest) = line.strip_prefix("Program ") else {
+            continue;
+        };
+        let Some((program, status)) = rest.split_once(' ') else {
+            continue;
+        };
+        let Ok(program_id) = program.parse::<Pubkey>() else {
+            continue;
+        };
+
+        if let Some(depth) = status.strip_prefix("invoke [").and_then(|depth| depth.strip_suffix(']')) {
+            stack.push(invocations.len());
+            invocations.push(ProgramInvocation {
+                program_id,
+                depth: depth.parse().unwrap_or(0),
+                logs: Vec::new(),
+                data: Vec::new(),
+                compute_units: None,
+                error: None,
+            });
+        } else if let Some(&current) = stack.last() {
+            if let Some(consumed) = status.strip_prefix("consumed ") {
+                invocations[current].compute_units = consumed.split(' ').next().and_then(|units| units.parse().ok());
+            } else if status == "success" {
+                stack.pop();
+            } else if let Some(error) = status.strip_prefix("failed: ") {
+                invocations[current].error = Some(error.to_string());
+                stack.pop();
+            }
+        }
+    }
+
+    invocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_invocations() {
+        let (program_id, memo) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs = vec![
+            "Program ComputeBudget111111111111111111111111111111 invoke [1]".to_string(),
+            "Program ComputeBudget111111111111111111111111111111 success".to_string(),
+            format!("Program {} invoke [1]", program_id),
+            "Program log: Instruction: Execute".to_string(),
+            format!("Program {} invoke [2]", memo),
+            "Program log: Memo (len 4): \"gm\"".to_string(),
+            format!("Program {} consumed 7000 of 180000 compute units", memo),
+            format!("Program {} success", memo),
+            "Program data: bm90IGFuIGV2ZW50".to_string(),
+            format!("Program {} consumed 25000 of 199850 compute units", program_id),
+            format!("Program {} failed: custom program error: 0x20", program_id),
+        ];
+
+        let invocations = parse_invocations(&logs);
+        assert_eq!(invocations.len(), 3);
+        assert_eq!(invocations[1].program_id, program_id);
+        assert_eq!(invocations[1].logs, vec!["Instruction: Execute"]);
+        assert_eq!(invocations[1].data, vec!["bm90IGFuIGV2ZW50"]);
+        assert_eq!(invocations[1].compute_units, Some(25_000));
+        assert_eq!(invocations[1].error.as_deref(), Some("custom program error: 0x20"));
+        assert_eq!((invocations[2].program_id, invocations[2].depth), (memo, 2));
+        assert_eq!(invocations[2].compute_units, Some(7_000));
+        assert!(invocations[2].error.is_none());
+
+        let result = SimulationResult {
+            error: Some(TransactionError::InstructionError(
+                1,
+                solana_sdk::instruction::InstructionError::Custom(AgentError::InvalidAction as u32),
+            )),
+            units_consumed: Some(25_150),
+            invocations,
+            events: Vec::new(),
+            logs,
+        };
+        assert!(!result.succeeded());
+        assert_eq!(result.program_error(), Some(AgentError::InvalidAction));
+        assert_eq!(result.program_units(&program_id), 25_000);
+    }
+}
@@ -0,0 +1,338 @@
+//! Account update streams, independent of their transport
+//!
+//! This module provides:
+//! - The `AccountStream` trait: updates of one account, or of a program's
+//!   accounts matching RPC filters, over a broadcast channel
+//! - `WebSocketStream`, reading the validator's PubSub subscriptions
+//! - `GeyserStream`, reading a Yellowstone Geyser gRPC endpoint (requires
+//!   the `geyser` feature)
+//!
+//! Watchers of agent state take any `AccountStream`, so the same code runs
+//! on WebSocket in development and on Geyser in production. Updates are
+//! read on a background thread per subscription, which stops once every
+//! receiver is dropped.
+
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    pubsub_client::PubsubClient,
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::RpcFilterType,
+};
+use solana_sdk::{account::Account, clock::Slot, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use tokio::sync::broadcast;
+use crate::error::SonomaResult;
+use crate::solana::client;
+
+/// Number of account updates a lagging subscriber may fall behind by
+pub const ACCOUNT_CHANNEL_CAPACITY: usize = 256;
+
+/// New contents of an account, as of `slot`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountUpdate {
+    pub address: Pubkey,
+    pub slot: Slot,
+    pub account: Account,
+}
+
+/// Source of account updates
+pub trait AccountStream: Send + Sync {
+    /// Broadcast each update of the account at `address`
+    fn subscribe_account(&self, address: &Pubkey) -> SonomaResult<broadcast::Receiver<AccountUpdate>>;
+
+    /// Broadcast each update of the accounts `program_id` owns that match
+    /// every filter in `filters`
+    fn subscribe_program(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> SonomaResult<broadcast::Receiver<AccountUpdate>>;
+}
+
+/// Account updates from a validator's PubSub WebSocket
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSocketStream {
+    url: String,
+    commitment: CommitmentConfig,
+}
+
+impl WebSocketStream {
+    /// Stream from the PubSub endpoint at `url`, e.g. `wss://...`
+    pub fn new(url: &str, commitment: CommitmentConfig) -> Self {
+        Self {
+            url: url.to_string(),
+            commitment,
+        }
+    }
+
+    /// Stream from the PubSub endpoint next to `rpc`'s URL, at its
+    /// commitment
+    pub fn from_rpc(rpc: &RpcClient) -> Self {
+        Self::new(&client::websocket_url(&rpc.url()), rpc.commitment())
+    }
+
+    fn account_config(&self) -> RpcAccountInfoConfig {
+        RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment),
+            ..Default::default()
+        }
+    }
+}
+
+impl AccountStream for WebSocketStream {
+    fn subscribe_account(&self, address: &Pubkey) -> SonomaResult<broadcast::Receiver<AccountUpdate>> {
+        let (mut subscription, notifications) =
+            PubsubClient::account_subscribe(&self.url, address, Some(self.account_config()))?;
+        let (sender, receiver) = broadcast::channel(ACCOUNT_CHANNEL_CAPACITY);
+        let address = *address;
+
+        std::thread::spawn(move || {
+            for notification in notifications {
+                let Some(account) = notification.value.decode::<Account>() else {
+                    continue;
+                };
+                let update = AccountUpdate {
+                    address,
+                    slot: notification.context.slot,
+                    account,
+                };
+                if sender.send(update).is_err() {
+                    break;
+                }
+            }
+            let _ = subscription.shutdown();
+        });
+        Ok(receiver)
+    }
+
+    fn subscribe_program(
+        &self,
+        program_id: &Pubkey,
+        filters: Vec<RpcFilterType>,
+    ) -> SonomaResult<broadcast::Receiver<AccountUpdate>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(filters),
+            account_config: self.account_config(),
+            ..Default::default()
+        };
+        let (mut subscription, notifications) = PubsubClient::program_subscribe(&self.url, program_id, Some(config))?;
+        let (sender, receiver) = broadcast::channel(ACCOUNT_CHANNEL_CAPACITY);
+
+        std::thread::spawn(move || {
+            for notification in notifications {
+                let keyed = notification.value;
+                let Ok(address) = keyed.pubkey.parse::<Pubkey>() else {
+                    continue;
+                };
+                let Some(account) = keyed.account.decode::<Account>() else {
+                    continue;
+                };
+                let update = AccountUpdate {
+                    address,
+                    slot: notification.context.slot,
+                    account,
+                };
+                if sender.send(update).is_err() {
+                    break;
+                }
+            }
+            let _ = subscription.shutdown();
+        });
+        Ok(receiver)
+    }
+}
+
+#[cfg(feature = "geyser")]
+pub use geyser::GeyserStream;
+
+#[cfg(feature = "geyser")]
+mod geyser {
+    use std::collections::HashMap;
+    use std::sync::mpsc;
+    use futures::StreamExt;
+    use solana_client::rpc_filter::RpcFilterType;
+    use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+    use tokio::sync::broadcast;
+    use yellowstone_grpc_client::GeyserGrpcClient;
+    use yellowstone_grpc_proto::geyser::{
+        subscribe_request_filter_accounts_filter::Filter,
+        subscribe_request_filter_accounts_filter_memcmp::Data,
+        subscribe_update::UpdateOneof,
+        CommitmentLevel,
+        SubscribeRequest,
+        SubscribeRequestFilterAccounts,
+        SubscribeRequestFilterAccountsFilter,
+        SubscribeRequestFilterAccountsFilterMemcmp,
+        SubscribeUpdateAccountInfo,
+    };
+    use crate::error::{SonomaError, SonomaResult};
+    use crate::solana::client::ClientError;
+    use super::{AccountStream, AccountUpdate, ACCOUNT_CHANNEL_CAPACITY};
+
+    /// Account updates from a Yellowstone Geyser gRPC endpoint
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct GeyserStream {
+        endpoint: String,
+        x_token: Option<String>,
+        commitment: CommitmentConfig,
+    }
+
+    impl GeyserStream {
+        /// Stream from the gRPC endpoint at `endpoint`, authenticated with
+        /// `x_token` if the provider requires one
+        pub fn new(endpoint: &str, x_token: Option<String>, commitment: CommitmentConfig) -> Self {
+            Self {
+                endpoint: endpoint.to_string(),
+                x_token,
+                commitment,
+            }
+        }
+
+        fn commitment_level(&self) -> CommitmentLevel {
+            if self.commitment.is_finalized() {
+                CommitmentLevel::Finalized
+            } else if self.commitment.is_confirmed() {
+                CommitmentLevel::Confirmed
+            } else {
+                CommitmentLevel::Processed
+            }
+        }
+
+        /// Run `filter` on a background thread with its own runtime,
+        /// returning once the subscription is established
+        fn subscribe(&self, filter: SubscribeRequestFilterAccounts) -> SonomaResult<broadcast::Receiver<AccountUpdate>> {
+            let request = SubscribeRequest {
+                accounts: HashMap::from([("sonoma".to_string(), filter)]),
+                commitment: Some(self.commitment_level() as i32),
+                ..Default::default()
+            };
+            let (endpoint, x_token) = (self.endpoint.clone(), self.x_token.clone());
+            let (sender, receiver) = broadcast::channel(ACCOUNT_CHANNEL_CAPACITY);
+            let (ready, established) = mpsc::channel::<Result<(), String>>();
+
+            std::thread::spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(error) => {
+                        let _ = ready.send(Err(error.to_string()));
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    let mut client = match GeyserGrpcClient::connect(endpoint, x_token, None) {
+                        Ok(client) => client,
+                        Err(error) => {
+                            let _ = ready.send(Err(error.to_string()));
+                            return;
+                        }
+                    };
+                    let mut updates = match client.subscribe_once2(request).await {
+                        Ok(updates) => {
+                            let _ = ready.send(Ok(()));
+                            updates
+                        }
+                        Err(error) => {
+                            let _ = ready.send(Err(error.to_string()));
+                            return;
+                        }
+                    };
+
+                    while let Some(Ok(update)) = updates.next().await {
+                        let Some(UpdateOneof::Account(account)) = update.update_oneof else {
+                            continue;
+                        };
+                        let Some(update) = account.account.and_then(|info| account_update(info, account.slot)) else {
+                            continue;
+                        };
+                        if sender.send(update).is_err() {
+                            break;
+                        }
+                    }
+                });
+            });
+
+            match established.recv() {
+                Ok(Ok(())) => Ok(receiver),
+                Ok(Err(error)) => Err(SonomaError::Client(ClientError::Stream(error))),
+                Err(_) => Err(SonomaError::Client(ClientError::Stream("subscription thread exited".to_string()))),
+            }
+        }
+    }
+
+    impl AccountStream for GeyserStream {
+        fn subscribe_account(&self, address: &Pubkey) -> SonomaResult<broadcast::Receiver<AccountUpdate>> {
+            self.subscribe(SubscribeRequestFilterAccounts {
+                account: vec![address.to_string()],
+                ..Default::default()
+            })
+        }
+
+        fn subscribe_program(
+            &self,
+            program_id: &Pubkey,
+            filters: Vec<RpcFilterType>,
+        ) -> SonomaResult<broadcast::Receiver<AccountUpdate>> {
+            let filters = filters
+                .iter()
+                .map(geyser_filter)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| SonomaError::Client(ClientError::Stream("unsupported account filter".to_string())))?;
+            self.subscribe(SubscribeRequestFilterAccounts {
+                owner: vec![program_id.to_string()],
+                filters,
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Geyser counterpart of an RPC account filter, if it has one
+    fn geyser_filter(filter: &RpcFilterType) -> Option<SubscribeRequestFilterAccountsFilter> {
+        let filter = match filter {
+            RpcFilterType::DataSize(size) => Filter::Datasize(*size),
+            RpcFilterType::Memcmp(memcmp) => Filter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                offset: memcmp.offset as u64,
+                data: Some(Data::Bytes(memcmp.bytes()?.into_owned())),
+            }),
+            _ => return None,
+        };
+        Some(SubscribeRequestFilterAccountsFilter { filter: Some(filter) })
+    }
+
+    fn account_update(info: SubscribeUpdateAccountInfo, slot: u64) -> Option<AccountUpdate> {
+        Some(AccountUpdate {
+            address: Pubkey::try_from(info.pubkey.as_slice()).ok()?,
+            slot,
+            account: Account {
+                lamports: info.lamports,
+                data: info.data,
+                owner: Pubkey::try_from(info.owner.as_slice()).ok()?,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+            },
+        })
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use solana_client::rpc_filter::Memcmp;
+
+        #[test]
+        fn test_geyser_filter() {
+            let memcmp = RpcFilterType::Memcmp(Memcmp::new_raw_bytes(1, vec![7, 8]));
+            assert_eq!(
+                geyser_filter(&memcmp).and_then(|filter| filter.filter),
+                Some(Filter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                    offset: 1,
+                    data: Some(Data::Bytes(vec![7, 8])),
+                }))
+            );
+            assert_eq!(
+                geyser_filter(&RpcFilterType::DataSize(64)).and_then(|filter| filter.filter),
+                Some(Filter::Datasize(64))
+            );
+            assert!(geyser_filter(&RpcFilterType::TokenAccountState).is_none());
+        }
+    }
+}
@@ -0,0 +1,249 @@
+//! Building client transactions
+//!
+//! This module provides:
+//! - Compute unit limit and priority fee instructions
+//! - A memo attached to the transaction, free-form or attributing it to an
+//!   agent and strategy
+//! - Composing both with agent instructions into one transaction
+//! - v0 transactions compressed by address lookup tables
+//!
+//! Compute budget instructions are placed first and the memo last, so the
+//! instructions in between keep their relative order.
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    address_lookup_table::AddressLookupTableAccount,
+    compute_budget::ComputeBudgetInstruction,
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, CompileError, VersionedMessage},
+    pubkey::Pubkey,
+    signer::SignerError,
+    signers::Signers,
+    transaction::{Transaction, VersionedTransaction},
+};
+use thiserror::Error;
+use crate::solana::memo::TransactionMemo;
+
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error("Failed to compile message: {0}")]
+    Compile(#[from] CompileError),
+
+    #[error("Failed to sign transaction: {0}")]
+    Signer(#[from] SignerError),
+}
+
+/// Compute unit limit of the JS SDK's default `computeBudget`
+pub const DEFAULT_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+
+/// Compute budget requested by a transaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComputeBudget {
+    /// Compute units the transaction may use; `None` keeps the runtime's
+    /// per-instruction default
+    pub unit_limit: Option<u32>,
+    /// Priority fee in micro-lamports per compute unit (see
+    /// `fees::PriorityFeeOracle` for an estimate)
+    pub unit_price: Option<u64>,
+}
+
+impl ComputeBudget {
+    /// Instructions requesting this budget
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = Vec::new();
+        if let Some(units) = self.unit_limit {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_limit(units));
+        }
+        if let Some(micro_lamports) = self.unit_price {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(micro_lamports));
+        }
+        instructions
+    }
+}
+
+/// Transaction composed of agent instructions, a compute budget and a memo
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionBuilder {
+    compute_budget: ComputeBudget,
+    memo: Option<String>,
+    instructions: Vec<Instruction>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_compute_budget(mut self, compute_budget: ComputeBudget) -> Self {
+        self.compute_budget = compute_budget;
+        self
+    }
+
+    pub fn with_compute_unit_limit(mut self, units: u32) -> Self {
+        self.compute_budget.unit_limit = Some(units);
+        self
+    }
+
+    /// Priority fee in micro-lamports per compute unit
+    pub fn with_compute_unit_price(mut self, micro_lamports: u64) -> Self {
+        self.compute_budget.unit_price = Some(micro_lamports);
+        self
+    }
+
+    /// Log `memo` through the SPL memo program after the instructions
+    pub fn with_memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    /// Log `memo` as the transaction's memo, attributing it to an agent
+    pub fn with_attribution(self, memo: &TransactionMemo) -> Self {
+        self.with_memo(memo.to_string())
+    }
+
+    pub fn add_instruction(mut self, instruction: Instruction) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    pub fn add_instructions(mut self, instructions: impl IntoIterator<Item = Instruction>) -> Self {
+        self.instructions.extend(instructions);
+        self
+    }
+
+    /// Look up accounts in `table` when building versioned transactions
+    pub fn with_lookup_table(mut self, table: AddressLookupTableAccount) -> Self {
+        self.lookup_tables.push(table);
+        self
+    }
+
+    pub fn with_lookup_tables(mut self, tables: impl IntoIterator<Item = AddressLookupTableAccount>) -> Self {
+        self.lookup_tables.extend(tables);
+        self
+    }
+
+    pub fn compute_budget(&self) -> ComputeBudget {
+        self.compute_budget
+    }
+
+    pub fn lookup_tables(&self) -> &[AddressLookupTableAccount] {
+        &self.lookup_tables
+    }
+
+    /// All instructions of the transaction, in order
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = self.compute_budget.instructions();
+        instructions.extend(self.instructions.iter().cloned());
+        if let Some(memo) = &self.memo {
+            instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
+        }
+        instructions
+    }
+
+    /// Unsigned transaction paid for by `payer`
+    pub fn build(&self, payer: &Pubkey) -> Transaction {
+        Transaction::new_with_payer(&self.instructions(), Some(payer))
+    }
+
+    /// Transaction paid for by `payer`, signed by `signers`
+    pub fn build_signed<T: Signers + ?Sized>(&self, payer: &Pubkey, signers: &T, blockhash: Hash) -> Transaction {
+        Transaction::new_signed_with_payer(&self.instructions(), Some(payer), signers, blockhash)
+    }
+
+    /// Transaction paid for by `payer`, signed by `signers`: a v0
+    /// transaction using the lookup tables if any are configured, a legacy
+    /// one otherwise
+    pub fn build_versioned<T: Signers + ?Sized>(
+        &self,
+        payer: &Pubkey,
+        signers: &T,
+        blockhash: Hash,
+    ) -> Result<VersionedTransaction, BuildError> {
+        if self.lookup_tables.is_empty() {
+            return Ok(self.build_signed(payer, signers, blockhash).into());
+        }
+
+        let message = v0::Message::try_compile(payer, &self.instructions(), &self.lookup_tables, blockhash)?;
+        Ok(VersionedTransaction::try_new(VersionedMessage::V0(message), signers)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{compute_budget, signature::Keypair, signer::Signer};
+
+    #[test]
+    fn test_instruction_order() {
+        let payer = Keypair::new();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let builder = TransactionBuilder::new()
+            .with_memo("rebalance")
+            .add_instruction(instruction.clone())
+            .with_compute_unit_limit(DEFAULT_COMPUTE_UNIT_LIMIT)
+            .with_compute_unit_price(5_000);
+
+        let instructions = builder.instructions();
+        assert_eq!(instructions.len(), 4);
+        assert_eq!(instructions[0], ComputeBudgetInstruction::set_compute_unit_limit(DEFAULT_COMPUTE_UNIT_LIMIT));
+        assert_eq!(instructions[1], ComputeBudgetInstruction::set_compute_unit_price(5_000));
+        assert_eq!(instructions[2], instruction);
+        assert_eq!(instructions[3].program_id, spl_memo::id());
+        assert_eq!(instructions[3].data, b"rebalance");
+
+        let transaction = builder.build_signed(&payer.pubkey(), &[&payer], Hash::default());
+        assert!(transaction.is_signed());
+        assert_eq!(transaction.message.account_keys[0], payer.pubkey());
+        assert!(transaction.message.account_keys.contains(&compute_budget::id()));
+    }
+
+    #[test]
+    fn test_attribution_memo() {
+        let memo = TransactionMemo::default().with_strategy("dca");
+        let instructions = TransactionBuilder::new().with_attribution(&memo).instructions();
+        assert_eq!(instructions[0].program_id, spl_memo::id());
+        assert_eq!(instructions[0].data, memo.to_string().into_bytes());
+    }
+
+    #[test]
+    fn test_lookup_tables() {
+        let payer = Keypair::new();
+        let accounts: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        let instruction = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            accounts.iter().map(|account| solana_sdk::instruction::AccountMeta::new(*account, false)).collect(),
+        );
+        let table = AddressLookupTableAccount { key: Pubkey::new_unique(), addresses: accounts };
+
+        let builder = TransactionBuilder::new().add_instruction(instruction);
+        let legacy = builder.build_versioned(&payer.pubkey(), &[&payer], Hash::default()).unwrap();
+        assert!(matches!(legacy.message, VersionedMessage::Legacy(_)));
+
+        let transaction = builder
+            .with_lookup_table(table.clone())
+            .build_versioned(&payer.pubkey(), &[&payer], Hash::default())
+            .unwrap();
+        match transaction.message {
+            VersionedMessage::V0(message) => {
+                assert_eq!(message.address_table_lookups[0].account_key, table.key);
+                assert_eq!(message.address_table_lookups[0].writable_indexes, vec![0, 1, 2, 3]);
+                assert_eq!(message.account_keys.len(), 2);
+            }
+            VersionedMessage::Legacy(_) => panic!("expected a v0 message"),
+        }
+    }
+
+    #[test]
+    fn test_default_budget() {
+        let builder = TransactionBuilder::new().add_instruction(Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[],
+            vec![],
+        ));
+        assert_eq!(builder.compute_budget(), ComputeBudget::default());
+        assert_eq!(builder.instructions().len(), 1);
+    }
+}
@@ -0,0 +1,164 @@
+//! In-memory cache in front of the database
+//!
+//! This module provides:
+//! - Values of any type, kept bincode-encoded
+//! - Per-entry time-to-live
+//! - Least recently used eviction once the cache is full
+//! - Cleanup of expired entries
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use super::StorageResult;
+
+/// Default time-to-live of cached items
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+/// Cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Maximum number of cached items
+    pub max_entries: usize,
+    /// Time after which an item is no longer served
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 10_000,
+            ttl: DEFAULT_CACHE_TTL,
+        }
+    }
+}
+
+struct CacheEntry {
+    data: Vec<u8>,
+    stored_at: Instant,
+    /// Value of `Cache::uses` when the entry was last read or written
+    last_used: u64,
+}
+
+/// Cache of recently stored and retrieved items
+pub struct Cache {
+    config: CacheConfig,
+    entries: HashMap<String, CacheEntry>,
+    uses: u64,
+}
+
+impl Cache {
+    /// Create an empty cache
+    pub async fn new(config: CacheConfig) -> StorageResult<Self> {
+        Ok(Self {
+            config,
+            entries: HashMap::new(),
+            uses: 0,
+        })
+    }
+
+    /// Cache `value` under `key`, evicting the least recently used item if
+    /// the cache is full
+    pub async fn set<T: Serialize>(&mut self, key: &str, value: &T) -> StorageResult<()> {
+        if self.config.max_entries == 0 {
+            return Ok(());
+        }
+        let data = bincode::serialize(value)?;
+        if !self.entries.contains_key(key) && self.entries.len() >= self.config.max_entries {
+            self.evict();
+        }
+
+        self.uses += 1;
+        self.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                data,
+                stored_at: Instant::now(),
+                last_used: self.uses,
+            },
+        );
+        Ok(())
+    }
+
+    /// Cached item under `key`, `None` if it isn't cached or has expired
+    pub async fn get<T: for<'de> Deserialize<'de>>(&mut self, key: &str) -> StorageResult<Option<T>> {
+        let ttl = self.config.ttl;
+        match self.entries.get_mut(key) {
+            Some(entry) if entry.stored_at.elapsed() < ttl => {
+                self.uses += 1;
+                entry.last_used = self.uses;
+                Ok(Some(bincode::deserialize(&entry.data)?))
+            }
+            Some(_) => {
+                self.entries.remove(key);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Drop the item cached under `key`, if any
+    pub async fn delete(&mut self, key: &str) -> StorageResult<()> {
+        self.entries.remove(key);
+        Ok(())
+    }
+
+    /// Drop every cached item
+    pub async fn clear(&mut self) -> StorageResult<()> {
+        self.entries.clear();
+        Ok(())
+    }
+
+    /// Drop the expired items
+    pub async fn cleanup(&mut self) -> StorageResult<()> {
+        let ttl = self.config.ttl;
+        self.entries.retain(|_, entry| entry.stored_at.elapsed() < ttl);
+        Ok(())
+    }
+
+    /// Number of cached items, including expired ones not yet cleaned up
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no item is cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drop the least recently used item
+    fn evict(&mut self) {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cache_eviction_and_expiry() {
+        let mut cache = Cache::new(CacheConfig { max_entries: 2, ..Default::default() }).await.unwrap();
+        cache.set("a", &1u32).await.unwrap();
+        cache.set("b", &2u32).await.unwrap();
+
+        // Reading "a" makes "b" the least recently used
+        assert_eq!(cache.get::<u32>("a").await.unwrap(), Some(1));
+        cache.set("c", &3u32).await.unwrap();
+        assert_eq!(cache.get::<u32>("b").await.unwrap(), None);
+        assert_eq!(cache.len(), 2);
+
+        let mut cache = Cache::new(CacheConfig { ttl: Duration::ZERO, ..Default::default() }).await.unwrap();
+        cache.set("a", &1u32).await.unwrap();
+        assert_eq!(cache.get::<u32>("a").await.unwrap(), None);
+        cache.set("b", &2u32).await.unwrap();
+        cache.cleanup().await.unwrap();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+//! File-backed key-value database
+//!
+//! This module provides:
+//! - One file per item, bincode-encoded and named after its hex-encoded key
+//! - Atomic writes through a temporary file
+//! - An in-memory index of the keys, in ascending order
+//! - Prefix scans resuming after a cursor, reading only the requested items
+
+use std::collections::BTreeSet;
+use std::ops::Bound;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+use super::{StorageError, StorageResult};
+
+/// Name of the database directory under the storage base directory
+pub const DATABASE_DIR: &str = "db";
+
+/// Maximum key length in bytes, keeping hex-encoded file names within the
+/// 255-byte limit of common file systems
+pub const MAX_KEY_LEN: usize = 120;
+
+/// Extension of the files holding items
+const ITEM_EXTENSION: &str = "item";
+
+/// Extension of items being written
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Database configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DatabaseConfig {
+    /// Directory holding the items; `None` uses `DATABASE_DIR` under the
+    /// storage base directory
+    pub path: Option<PathBuf>,
+    /// Flush each item to disk before `store` returns
+    pub sync_writes: bool,
+}
+
+/// Key-value database storing each item in its own file
+///
+/// Keys are indexed in memory when the database is opened, so scans find
+/// the keys of a page without listing the directory, and only that page's
+/// values are read from disk.
+pub struct Database {
+    config: DatabaseConfig,
+    dir: PathBuf,
+    keys: BTreeSet<String>,
+}
+
+impl Database {
+    /// Open the database at `config.path`, indexing the items already
+    /// stored there
+    pub async fn new(config: DatabaseConfig) -> StorageResult<Self> {
+        let dir = config
+            .path
+            .clone()
+            .ok_or_else(|| StorageError::InvalidPath("database path not set".to_string()))?;
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let mut keys = BTreeSet::new();
+        let mut entries = tokio::fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some(ITEM_EXTENSION) => {}
+                // Left over by a write that never completed
+                Some(TEMP_EXTENSION) => {
+                    tokio::fs::remove_file(&path).await?;
+                    continue;
+                }
+                _ => continue,
+            }
+            match path.file_stem().and_then(|stem| stem.to_str()).and_then(decode_key) {
+                Some(key) => {
+                    keys.insert(key);
+                }
+                None => println!("Skipping unrecognized database file {}", path.display()),
+            }
+        }
+
+        Ok(Self { config, dir, keys })
+    }
+
+    /// Store `value` under `key`, replacing any item stored there
+    pub async fn store<T: Serialize>(&mut self, key: &str, value: &T) -> StorageResult<()> {
+        let path = self.path(key)?;
+        let data = bincode::serialize(value)?;
+
+        // Readers never see a partly written item
+        let temp = path.with_extension(TEMP_EXTENSION);
+        let mut file = tokio::fs::File::create(&temp).await?;
+        file.write_all(&data).await?;
+        if self.config.sync_writes {
+            file.sync_all().await?;
+        }
+        drop(file);
+        tokio::fs::rename(&temp, &path).await?;
+
+        self.keys.insert(key.to_string());
+        Ok(())
+    }
+
+    /// Retrieve the item stored under `key`
+    pub async fn retrieve<T: for<'de> Deserialize<'de>>(&self, key: &str) -> StorageResult<T> {
+        if !self.keys.contains(key) {
+            return Err(StorageError::NotFound(key.to_string()));
+        }
+        let data = tokio::fs::read(self.path(key)?).await?;
+        Ok(bincode::deserialize(&data)?)
+    }
+
+    /// Delete the item stored under `key`, if any
+    pub async fn delete(&mut self, key: &str) -> StorageResult<()> {
+        if self.keys.remove(key) {
+            tokio::fs::remove_file(self.path(key)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` items whose key starts with `prefix`, in ascending key
+    /// order, starting strictly after `after`
+    pub async fn scan<T: for<'de> Deserialize<'de>>(
+        &self,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> StorageResult<Vec<(String, T)>> {
+        let start = match after {
+            Some(cursor) if cursor >= prefix => Bound::Excluded(cursor),
+            _ => Bound::Included(prefix),
+        };
+        let keys: Vec<String> = self
+            .keys
+            .range::<str, _>((start, Bound::Unbounded))
+            .take_while(|key| key.starts_with(prefix))
+            .take(limit)
+            .cloned()
+            .collect();
+
+        let mut items = Vec::with_capacity(keys.len());
+        for key in keys {
+            let value = self.retrieve(&key).await?;
+            items.push((key, value));
+        }
+        Ok(items)
+    }
+
+    /// Delete every item
+    pub async fn clear(&mut self) -> StorageResult<()> {
+        while let Some(key) = self.keys.pop_first() {
+            tokio::fs::remove_file(self.path(&key)?).await?;
+        }
+        Ok(())
+    }
+
+    /// Number of stored items
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Whether no item is stored
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Path of the file holding the item stored under `key`
+    fn path(&self, key: &str) -> StorageResult<PathBuf> {
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Err(StorageError::InvalidPath(format!("Invalid key: {:?}", key)));
+        }
+        Ok(self.dir.join(format!("{}.{}", encode_key(key), ITEM_EXTENSION)))
+    }
+}
+
+/// Hex-encode a key into a file name valid on every platform
+fn encode_key(key: &str) -> String {
+    key.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode a file name produced by `encode_key`
+fn decode_key(name: &str) -> Option<String> {
+    if name.len() % 2 != 0 {
+        return None;
+    }
+    let bytes = (0..name.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(name.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_database_reopens_and_scans() {
+        let temp_dir = tempdir().unwrap();
+        let config = DatabaseConfig {
+            path: Some(temp_dir.path().join(DATABASE_DIR)),
+            ..Default::default()
+        };
+
+        let mut database = Database::new(config.clone()).await.unwrap();
+        for key in ["tenants/b/1", "tenants/a/2", "tenants/a/1", "other"] {
+            database.store(key, &key.len()).await.unwrap();
+        }
+        database.delete("other").await.unwrap();
+        assert!(matches!(database.retrieve::<usize>("other").await, Err(StorageError::NotFound(_))));
+        assert!(database.store(&"x".repeat(MAX_KEY_LEN + 1), &0usize).await.is_err());
+
+        // Keys survive reopening, and scans follow key order within the prefix
+        let database = Database::new(config).await.unwrap();
+        assert_eq!(database.len(), 3);
+        let keys = |items: Vec<(String, usize)>| items.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+        let first = database.scan::<usize>("tenants/a/", None, 10).await.unwrap();
+        assert_eq!(keys(first), ["tenants/a/1", "tenants/a/2"]);
+        let rest = database.scan::<usize>("tenants/", Some("tenants/a/2"), 10).await.unwrap();
+        assert_eq!(keys(rest), ["tenants/b/1"]);
+    }
+
+    #[test]
+    fn test_key_encoding() {
+        let key = "tenants/acme/agent:1";
+        assert_eq!(decode_key(&encode_key(key)).as_deref(), Some(key));
+        assert_eq!(decode_key("abc"), None);
+        assert_eq!(decode_key("zz"), None);
+    }
+}
@@ -7,21 +7,35 @@
 //! - Storage optimization
 //! - Backup/restore functionality
 
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
 use tokio::sync::RwLock;
 use std::sync::Arc;
+use crate::validation::{ConfigErrors, Validate, Violations};
 
 mod database;
 mod cache;
+mod tenant;
+mod quote_cache;
 
 pub use database::{Database, DatabaseConfig};
 pub use cache::{Cache, CacheConfig};
+pub use tenant::{TenantId, TenantQuota, TenantStorage};
+pub use quote_cache::{QuoteCache, QuoteCacheConfig, QuoteCacheStats, QuoteKey};
+use tenant::{TenantRegistry, TenantState};
 
 /// Default storage directory name
 pub const DEFAULT_STORAGE_DIR: &str = ".sonoma/storage";
 
+/// Default number of items returned by a paginated scan
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Maximum number of items a single page may contain
+pub const MAX_PAGE_SIZE: usize = 1000;
+
 /// Storage configuration options
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageConfig {
@@ -51,6 +65,29 @@ impl Default for StorageConfig {
     }
 }
 
+impl Validate for StorageConfig {
+    fn collect_violations(&self, violations: &mut Violations) {
+        violations.check(
+            !self.base_dir.as_os_str().is_empty(),
+            "base_dir",
+            "must not be empty",
+            "set base_dir to a writable directory, e.g. ~/.sonoma/storage",
+        );
+        violations.check(
+            self.max_size > 0,
+            "max_size",
+            "must be greater than 0",
+            "set max_size in bytes, e.g. 1073741824 for 1GB",
+        );
+        violations.check(
+            self.cleanup_threshold > 0.0 && self.cleanup_threshold <= 1.0,
+            "cleanup_threshold",
+            "must be in (0, 1]",
+            "use a fraction of max_size such as 0.9",
+        );
+    }
+}
+
 /// Storage errors that can occur during operations
 #[derive(Error, Debug)]
 pub enum StorageError {
@@ -84,11 +121,91 @@ pub enum StorageError {
     /// Data not found
     #[error("Data not found: {0}")]
     NotFound(String),
+
+    /// Invalid configuration
+    #[error("Invalid storage configuration: {0}")]
+    InvalidConfig(#[from] ConfigErrors),
+
+    /// Tenant not registered
+    #[error("Unknown tenant: {0}")]
+    UnknownTenant(String),
+
+    /// Tenant quota exceeded
+    #[error("Quota exceeded for tenant: {0}")]
+    QuotaExceeded(String),
+
+    /// Tenant rate limit exceeded
+    #[error("Rate limit exceeded for tenant {tenant}. Try again in {retry_after:?}")]
+    RateLimited {
+        tenant: String,
+        retry_after: Duration,
+    },
 }
 
 /// Result type for storage operations
 pub type StorageResult<T> = Result<T, StorageError>;
 
+/// Cursor-based page request for scan/query APIs
+///
+/// Items are always returned in ascending key order, so a cursor taken from
+/// one page remains valid even if items are inserted or removed before the
+/// next page is requested.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRequest {
+    /// Cursor returned by the previous page, `None` to start from the beginning
+    pub cursor: Option<String>,
+    /// Maximum number of items to return (clamped to `MAX_PAGE_SIZE`)
+    pub limit: usize,
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            limit: DEFAULT_PAGE_SIZE,
+        }
+    }
+}
+
+impl PageRequest {
+    /// Create a page request starting at the beginning of the collection
+    pub fn first(limit: usize) -> Self {
+        Self {
+            cursor: None,
+            limit,
+        }
+    }
+
+    /// Create a page request continuing after the given cursor
+    pub fn after(cursor: impl Into<String>, limit: usize) -> Self {
+        Self {
+            cursor: Some(cursor.into()),
+            limit,
+        }
+    }
+
+    /// Effective limit after clamping to the allowed range
+    pub fn effective_limit(&self) -> usize {
+        self.limit.clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// A single page of results from a paginated scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    /// Items in this page, in ascending key order
+    pub items: Vec<T>,
+    /// Cursor for the next page, `None` when this is the last page
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Whether more pages are available after this one
+    pub fn has_more(&self) -> bool {
+        self.next_cursor.is_some()
+    }
+}
+
 /// Storage metrics for monitoring
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct StorageMetrics {
@@ -112,16 +229,24 @@ pub struct StorageManager {
     cache: Arc<RwLock<Cache>>,
     /// Storage metrics
     metrics: Arc<RwLock<StorageMetrics>>,
+    /// Size of each item stored through this manager, by key
+    sizes: Arc<RwLock<HashMap<String, u64>>>,
+    /// Registered tenants
+    tenants: Arc<RwLock<TenantRegistry>>,
 }
 
 impl StorageManager {
     /// Create a new storage manager
     pub async fn new(config: StorageConfig) -> StorageResult<Self> {
+        config.validate()?;
+
         // Ensure storage directory exists
         tokio::fs::create_dir_all(&config.base_dir).await?;
 
         // Initialize database and cache
-        let database = Database::new(config.database.clone()).await?;
+        let mut database_config = config.database.clone();
+        database_config.path.get_or_insert_with(|| config.base_dir.join(database::DATABASE_DIR));
+        let database = Database::new(database_config).await?;
         let cache = Cache::new(config.cache.clone()).await?;
 
         Ok(Self {
@@ -129,14 +254,74 @@ impl StorageManager {
             database: Arc::new(RwLock::new(database)),
             cache: Arc::new(RwLock::new(cache)),
             metrics: Arc::new(RwLock::new(StorageMetrics::default())),
+            sizes: Arc::new(RwLock::new(HashMap::new())),
+            tenants: Arc::new(RwLock::new(TenantRegistry::new())),
         })
     }
 
+    /// Register a tenant with the given quota, replacing any existing quota
+    pub async fn register_tenant(&self, tenant: TenantId, quota: TenantQuota) {
+        let mut tenants = self.tenants.write().await;
+        match tenants.get_mut(&tenant) {
+            Some(state) => state.set_quota(quota),
+            None => {
+                tenants.insert(tenant, TenantState::new(quota));
+            }
+        }
+    }
+
+    /// Get a storage view scoped to a registered tenant
+    pub async fn tenant(&self, tenant: &TenantId) -> StorageResult<TenantStorage<'_>> {
+        if !self.tenants.read().await.contains_key(tenant) {
+            return Err(StorageError::UnknownTenant(tenant.to_string()));
+        }
+        Ok(TenantStorage::new(self, tenant.clone()))
+    }
+
     /// Store data with given key
+    ///
+    /// Overwriting an item replaces its size in the metrics rather than
+    /// adding to it.
     pub async fn store<T: Serialize>(&self, key: &str, value: &T) -> StorageResult<()> {
-        // Check storage capacity
+        let mut sizes = self.sizes.write().await;
+        self.store_locked(&mut sizes, key, value).await
+    }
+
+    /// Store data with given key unless an item is already stored there,
+    /// returning whether it was stored
+    ///
+    /// Checking and storing happen under one lock, so of two concurrent
+    /// calls for the same key only one stores.
+    pub async fn store_if_absent<T>(&self, key: &str, value: &T) -> StorageResult<bool>
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        let mut sizes = self.sizes.write().await;
+        if sizes.contains_key(key) {
+            return Ok(false);
+        }
+        // Items stored before this manager was created aren't sized yet
+        match self.database.read().await.retrieve::<T>(key).await {
+            Ok(_) => return Ok(false),
+            Err(StorageError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+
+        self.store_locked(&mut sizes, key, value).await?;
+        Ok(true)
+    }
+
+    /// `store`, with the sizes already locked
+    async fn store_locked<T: Serialize>(
+        &self,
+        sizes: &mut HashMap<String, u64>,
+        key: &str,
+        value: &T,
+    ) -> StorageResult<()> {
+        // Check storage capacity, net of the item being replaced
         let size = bincode::serialized_size(value)? as u64;
-        self.ensure_capacity(size).await?;
+        let old_size = sizes.get(key).copied();
+        self.ensure_capacity(size.saturating_sub(old_size.unwrap_or(0))).await?;
 
         // Try cache first
         let mut cache = self.cache.write().await;
@@ -148,14 +333,17 @@ impl StorageManager {
 
         // Update metrics
         let mut metrics = self.metrics.write().await;
-        metrics.used_size += size;
-        metrics.total_items += 1;
+        metrics.used_size = metrics.used_size.saturating_sub(old_size.unwrap_or(0)) + size;
+        if old_size.is_none() {
+            metrics.total_items += 1;
+        }
+        sizes.insert(key.to_string(), size);
 
         Ok(())
     }
 
     /// Retrieve data for given key
-    pub async fn retrieve<T: for<'de> Deserialize<'de>>(&self, key: &str) -> StorageResult<T> {
+    pub async fn retrieve<T: Serialize + for<'de> Deserialize<'de>>(&self, key: &str) -> StorageResult<T> {
         // Try cache first
         let mut cache = self.cache.write().await;
         if let Some(value) = cache.get::<T>(key).await? {
@@ -173,13 +361,15 @@ impl StorageManager {
 
         // Update metrics
         let mut metrics = self.metrics.write().await;
-        metrics.cache_hit_rate = metrics.cache_hit_rate * 0.9;
+        metrics.cache_hit_rate *= 0.9;
 
         Ok(value)
     }
 
     /// Delete data for given key
     pub async fn delete(&self, key: &str) -> StorageResult<()> {
+        let mut sizes = self.sizes.write().await;
+
         // Remove from cache
         let mut cache = self.cache.write().await;
         cache.delete(key).await?;
@@ -188,11 +378,47 @@ impl StorageManager {
         let mut database = self.database.write().await;
         database.delete(key).await?;
 
+        // Update metrics
+        if let Some(size) = sizes.remove(key) {
+            let mut metrics = self.metrics.write().await;
+            metrics.used_size = metrics.used_size.saturating_sub(size);
+            metrics.total_items = metrics.total_items.saturating_sub(1);
+        }
+
         Ok(())
     }
 
+    /// Scan items whose key starts with `prefix`, one page at a time
+    ///
+    /// The cursor is the last key of the previous page; the scan resumes
+    /// strictly after it. Only one page of values is ever held in memory.
+    pub async fn scan<T: for<'de> Deserialize<'de>>(
+        &self,
+        prefix: &str,
+        page: &PageRequest,
+    ) -> StorageResult<Page<(String, T)>> {
+        let limit = page.effective_limit();
+
+        // Fetch one extra item to learn whether another page follows
+        let database = self.database.read().await;
+        let mut items = database
+            .scan::<T>(prefix, page.cursor.as_deref(), limit + 1)
+            .await?;
+
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(|(key, _)| key.clone())
+        } else {
+            None
+        };
+
+        Ok(Page { items, next_cursor })
+    }
+
     /// Clear all storage
     pub async fn clear(&self) -> StorageResult<()> {
+        let mut sizes = self.sizes.write().await;
+
         // Clear cache
         let mut cache = self.cache.write().await;
         cache.clear().await?;
@@ -204,6 +430,7 @@ impl StorageManager {
         // Reset metrics
         let mut metrics = self.metrics.write().await;
         *metrics = StorageMetrics::default();
+        sizes.clear();
 
         Ok(())
     }
@@ -265,4 +492,113 @@ mod tests {
         manager.delete("test-key").await.unwrap();
         assert!(manager.retrieve::<String>("test-key").await.is_err());
     }
+
+    #[tokio::test]
+    async fn test_overwrite_accounting() {
+        let temp_dir = tempdir().unwrap();
+        let value = vec![0u8; 100];
+        let size = bincode::serialized_size(&value).unwrap();
+        let config = StorageConfig {
+            base_dir: temp_dir.path().to_path_buf(),
+            max_size: size * 2,
+            ..Default::default()
+        };
+
+        // Rewriting a key, as checkpoints do, never fills the storage
+        let manager = StorageManager::new(config).await.unwrap();
+        for _ in 0..5 {
+            manager.store("checkpoint", &value).await.unwrap();
+        }
+        let metrics = manager.get_metrics().await;
+        assert_eq!((metrics.used_size, metrics.total_items), (size, 1));
+
+        manager.store("other", &value).await.unwrap();
+        assert!(matches!(manager.store("third", &value).await, Err(StorageError::StorageFull { .. })));
+
+        manager.delete("other").await.unwrap();
+        let metrics = manager.get_metrics().await;
+        assert_eq!((metrics.used_size, metrics.total_items), (size, 1));
+    }
+
+    #[test]
+    fn test_config_validation() {
+        assert!(StorageConfig::default().validate().is_ok());
+
+        let config = StorageConfig {
+            max_size: 0,
+            cleanup_threshold: 1.5,
+            ..Default::default()
+        };
+        let errors = config.validate().unwrap_err();
+        assert_eq!(errors.0.len(), 2);
+        assert_eq!(errors.0[1].field, "cleanup_threshold");
+    }
+
+    #[tokio::test]
+    async fn test_tenant_isolation() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            base_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let manager = StorageManager::new(config).await.unwrap();
+        let acme = TenantId::new("acme").unwrap();
+        let globex = TenantId::new("globex").unwrap();
+        manager.register_tenant(acme.clone(), TenantQuota::default()).await;
+        manager.register_tenant(globex.clone(), TenantQuota {
+            max_items: 1,
+            ..Default::default()
+        }).await;
+
+        let acme_storage = manager.tenant(&acme).await.unwrap();
+        let globex_storage = manager.tenant(&globex).await.unwrap();
+
+        acme_storage.store("agent", &"acme-agent").await.unwrap();
+        assert!(globex_storage.retrieve::<String>("agent").await.is_err());
+
+        globex_storage.store("agent", &"globex-agent").await.unwrap();
+        assert!(matches!(
+            globex_storage.store("other", &"value").await,
+            Err(StorageError::QuotaExceeded(_))
+        ));
+
+        let value: String = acme_storage.retrieve("agent").await.unwrap();
+        assert_eq!(value, "acme-agent");
+        assert_eq!(acme_storage.get_metrics().await.unwrap().total_items, 1);
+
+        let unknown = TenantId::new("initech").unwrap();
+        assert!(manager.tenant(&unknown).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_paginated_scan() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            base_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        };
+
+        let manager = StorageManager::new(config).await.unwrap();
+        for i in 0..5u32 {
+            manager.store(&format!("item-{}", i), &i).await.unwrap();
+        }
+        manager.store("other-key", &99u32).await.unwrap();
+
+        let first: Page<(String, u32)> = manager.scan("item-", &PageRequest::first(2)).await.unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.items[0].0, "item-0");
+        assert!(first.has_more());
+
+        let cursor = first.next_cursor.unwrap();
+        let second: Page<(String, u32)> = manager.scan("item-", &PageRequest::after(cursor, 2)).await.unwrap();
+        assert_eq!(second.items[0].0, "item-2");
+
+        let last: Page<(String, u32)> = manager
+            .scan("item-", &PageRequest::after(second.next_cursor.unwrap(), 2))
+            .await
+            .unwrap();
+        assert_eq!(last.items.len(), 1);
+        assert!(!last.has_more());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,257 @@
+//! Short-lived cache for swap and price quotes
+//!
+//! This module provides:
+//! - Quote keys by token pair and trade size bucket
+//! - Millisecond-level TTLs
+//! - Explicit staleness checks
+//! - Hit/miss accounting
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+
+/// Default time-to-live for cached quotes
+pub const DEFAULT_QUOTE_TTL: Duration = Duration::from_millis(500);
+
+/// Quote cache configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteCacheConfig {
+    /// Time after which a quote is no longer served
+    pub ttl: Duration,
+    /// Number of size buckets per doubling of trade size
+    pub buckets_per_doubling: u32,
+    /// Maximum number of cached quotes
+    pub max_entries: usize,
+}
+
+impl Default for QuoteCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: DEFAULT_QUOTE_TTL,
+            buckets_per_doubling: 4,
+            max_entries: 10_000,
+        }
+    }
+}
+
+/// Cache key for a quote
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QuoteKey {
+    /// Input token (mint address or symbol)
+    pub input: String,
+    /// Output token (mint address or symbol)
+    pub output: String,
+    /// Trade size bucket
+    pub size_bucket: u32,
+}
+
+/// Cache hit/miss statistics
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct QuoteCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+struct CachedQuote<Q> {
+    quote: Q,
+    fetched_at: Instant,
+}
+
+/// Cache for swap/price quotes with per-entry staleness tracking
+pub struct QuoteCache<Q> {
+    config: QuoteCacheConfig,
+    entries: HashMap<QuoteKey, CachedQuote<Q>>,
+    stats: QuoteCacheStats,
+}
+
+impl<Q: Clone> QuoteCache<Q> {
+    /// Create a new quote cache
+    pub fn new(config: QuoteCacheConfig) -> Self {
+        Self {
+            config,
+            entries: HashMap::new(),
+            stats: QuoteCacheStats::default(),
+        }
+    }
+
+    /// Build the cache key for a pair and trade size
+    ///
+    /// Sizes are bucketed logarithmically, so nearby sizes share a quote
+    /// while sizes with materially different price impact do not.
+    pub fn key(&self, input: &str, output: &str, amount: u64) -> QuoteKey {
+        QuoteKey {
+            input: input.to_string(),
+            output: output.to_string(),
+            size_bucket: self.size_bucket(amount),
+        }
+    }
+
+    /// Get a quote if it is younger than the configured TTL
+    pub fn get(&mut self, key: &QuoteKey) -> Option<Q> {
+        match self.entries.get(key) {
+            Some(entry) if entry.fetched_at.elapsed() <= self.config.ttl => {
+                self.stats.hits += 1;
+                Some(entry.quote.clone())
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Get a quote regardless of TTL along with its age
+    pub fn get_with_age(&self, key: &QuoteKey) -> Option<(Q, Duration)> {
+        self.entries
+            .get(key)
+            .map(|entry| (entry.quote.clone(), entry.fetched_at.elapsed()))
+    }
+
+    /// Whether the quote for `key` is missing or older than `max_age`
+    pub fn is_stale(&self, key: &QuoteKey, max_age: Duration) -> bool {
+        self.entries
+            .get(key)
+            .map_or(true, |entry| entry.fetched_at.elapsed() > max_age)
+    }
+
+    /// Insert a freshly fetched quote
+    pub fn insert(&mut self, key: QuoteKey, quote: Q) {
+        if self.entries.len() >= self.config.max_entries && !self.entries.contains_key(&key) {
+            self.purge_expired();
+            if self.entries.len() >= self.config.max_entries {
+                self.evict_oldest();
+            }
+        }
+
+        self.entries.insert(key, CachedQuote {
+            quote,
+            fetched_at: Instant::now(),
+        });
+    }
+
+    /// Return the cached quote or fetch and cache a new one
+    pub async fn get_or_fetch<F, Fut, E>(&mut self, key: QuoteKey, fetch: F) -> Result<Q, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Q, E>>,
+    {
+        if let Some(quote) = self.get(&key) {
+            return Ok(quote);
+        }
+
+        let quote = fetch().await?;
+        self.insert(key, quote.clone());
+        Ok(quote)
+    }
+
+    /// Remove a quote, e.g. after a trade moved the market
+    pub fn invalidate(&mut self, key: &QuoteKey) {
+        self.entries.remove(key);
+    }
+
+    /// Drop all quotes older than the TTL
+    pub fn purge_expired(&mut self) {
+        let ttl = self.config.ttl;
+        self.entries.retain(|_, entry| entry.fetched_at.elapsed() <= ttl);
+    }
+
+    /// Get cache statistics
+    pub fn stats(&self) -> QuoteCacheStats {
+        QuoteCacheStats {
+            entries: self.entries.len(),
+            ..self.stats.clone()
+        }
+    }
+
+    fn size_bucket(&self, amount: u64) -> u32 {
+        if amount == 0 {
+            return 0;
+        }
+        ((amount as f64).log2() * self.config.buckets_per_doubling as f64).floor() as u32 + 1
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(oldest) = self
+            .entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.fetched_at)
+            .map(|(key, _)| key.clone())
+        {
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_buckets() {
+        let cache: QuoteCache<u64> = QuoteCache::new(QuoteCacheConfig::default());
+        assert_eq!(
+            cache.key("SOL", "USDC", 1_100_000),
+            cache.key("SOL", "USDC", 1_150_000)
+        );
+        assert_ne!(
+            cache.key("SOL", "USDC", 1_000_000),
+            cache.key("SOL", "USDC", 2_000_000)
+        );
+        assert_ne!(
+            cache.key("SOL", "USDC", 1_000_000),
+            cache.key("USDC", "SOL", 1_000_000)
+        );
+    }
+
+    #[test]
+    fn test_ttl_and_staleness() {
+        let mut cache = QuoteCache::new(QuoteCacheConfig {
+            ttl: Duration::from_millis(20),
+            ..Default::default()
+        });
+        let key = cache.key("SOL", "USDC", 1_000_000);
+
+        assert!(cache.is_stale(&key, Duration::from_secs(1)));
+        cache.insert(key.clone(), 42u64);
+        assert_eq!(cache.get(&key), Some(42));
+        assert!(!cache.is_stale(&key, Duration::from_millis(20)));
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get(&key), None);
+        assert!(cache.is_stale(&key, Duration::from_millis(20)));
+        assert!(cache.get_with_age(&key).is_some());
+
+        let stats = cache.stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_fetch() {
+        let mut cache = QuoteCache::new(QuoteCacheConfig::default());
+        let key = cache.key("SOL", "USDC", 1_000_000);
+
+        let first: Result<u64, ()> = cache.get_or_fetch(key.clone(), || async { Ok(1) }).await;
+        let second: Result<u64, ()> = cache.get_or_fetch(key, || async { Ok(2) }).await;
+
+        assert_eq!(first, Ok(1));
+        assert_eq!(second, Ok(1));
+    }
+
+    #[test]
+    fn test_capacity_eviction() {
+        let mut cache = QuoteCache::new(QuoteCacheConfig {
+            max_entries: 2,
+            ..Default::default()
+        });
+
+        for amount in [1_000u64, 100_000, 10_000_000] {
+            let key = cache.key("SOL", "USDC", amount);
+            cache.insert(key, amount);
+        }
+
+        assert_eq!(cache.stats().entries, 2);
+    }
+}
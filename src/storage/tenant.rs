@@ -0,0 +1,310 @@
+//! Tenant isolation for shared storage
+//!
+//! This module provides:
+//! - Tenant identifiers
+//! - Key namespacing per tenant
+//! - Per-tenant quotas and rate limits
+//! - Per-tenant metrics
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use super::{Page, PageRequest, StorageError, StorageManager, StorageMetrics, StorageResult};
+
+/// Key prefix under which all tenant data is stored
+pub const TENANT_KEY_PREFIX: &str = "tenants";
+
+/// Identifier of a tenant sharing a storage manager
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TenantId(String);
+
+impl TenantId {
+    /// Create a new tenant id, rejecting ids that could escape their namespace
+    pub fn new(id: impl Into<String>) -> StorageResult<Self> {
+        let id = id.into();
+        if id.is_empty() || id.contains('/') {
+            return Err(StorageError::InvalidPath(format!("Invalid tenant id: {:?}", id)));
+        }
+        Ok(Self(id))
+    }
+
+    /// Get the tenant id as a string slice
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Key prefix for all data belonging to this tenant
+    fn key_prefix(&self) -> String {
+        format!("{}/{}/", TENANT_KEY_PREFIX, self.0)
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Resource quota for a single tenant
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantQuota {
+    /// Maximum storage size (in bytes)
+    pub max_size: u64,
+    /// Maximum number of stored items
+    pub max_items: u64,
+    /// Maximum storage operations per second
+    pub max_ops_per_second: u32,
+}
+
+impl Default for TenantQuota {
+    fn default() -> Self {
+        Self {
+            max_size: 100 * 1024 * 1024, // 100MB
+            max_items: 100_000,
+            max_ops_per_second: 1_000,
+        }
+    }
+}
+
+/// Book-keeping for a registered tenant
+#[derive(Debug)]
+pub(crate) struct TenantState {
+    quota: TenantQuota,
+    metrics: StorageMetrics,
+    /// Size of each item stored through the tenant's view, by unscoped key
+    sizes: HashMap<String, u64>,
+    window_start: Instant,
+    ops_in_window: u32,
+}
+
+impl TenantState {
+    pub(crate) fn new(quota: TenantQuota) -> Self {
+        Self {
+            quota,
+            metrics: StorageMetrics::default(),
+            sizes: HashMap::new(),
+            window_start: Instant::now(),
+            ops_in_window: 0,
+        }
+    }
+
+    pub(crate) fn set_quota(&mut self, quota: TenantQuota) {
+        self.quota = quota;
+    }
+
+    /// Count an operation against the tenant's per-second rate limit
+    fn record_op(&mut self, tenant: &TenantId) -> StorageResult<()> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.ops_in_window = 0;
+        }
+
+        if self.ops_in_window >= self.quota.max_ops_per_second {
+            return Err(StorageError::RateLimited {
+                tenant: tenant.to_string(),
+                retry_after: Duration::from_secs(1).saturating_sub(elapsed),
+            });
+        }
+
+        self.ops_in_window += 1;
+        Ok(())
+    }
+}
+
+/// Registry of tenants known to a storage manager
+pub(crate) type TenantRegistry = HashMap<TenantId, TenantState>;
+
+/// Storage view scoped to a single tenant
+///
+/// Every key is transparently namespaced under the tenant's prefix, so one
+/// tenant can never read, overwrite, or enumerate another tenant's data.
+pub struct TenantStorage<'a> {
+    /// Underlying shared storage manager
+    manager: &'a StorageManager,
+    /// Tenant this view is scoped to
+    tenant: TenantId,
+}
+
+impl<'a> TenantStorage<'a> {
+    pub(crate) fn new(manager: &'a StorageManager, tenant: TenantId) -> Self {
+        Self { manager, tenant }
+    }
+
+    /// Tenant this view is scoped to
+    pub fn tenant(&self) -> &TenantId {
+        &self.tenant
+    }
+
+    /// Store data with given key
+    ///
+    /// Overwriting an item replaces its size in the quota rather than
+    /// adding to it.
+    pub async fn store<T: Serialize>(&self, key: &str, value: &T) -> StorageResult<()> {
+        let size = bincode::serialized_size(value)? as u64;
+
+        // Held through the write, so concurrent stores can't all pass the
+        // quota check
+        let mut tenants = self.manager.tenants.write().await;
+        let state = self.state_mut(&mut tenants)?;
+        state.record_op(&self.tenant)?;
+
+        let old_size = state.sizes.get(key).copied();
+        let used_size = state.metrics.used_size.saturating_sub(old_size.unwrap_or(0)) + size;
+        let total_items = state.metrics.total_items + u64::from(old_size.is_none());
+        if used_size > state.quota.max_size || total_items > state.quota.max_items {
+            return Err(StorageError::QuotaExceeded(self.tenant.to_string()));
+        }
+
+        self.manager.store(&self.scoped_key(key), value).await?;
+
+        state.metrics.used_size = used_size;
+        state.metrics.total_items = total_items;
+        state.sizes.insert(key.to_string(), size);
+        Ok(())
+    }
+
+    /// Retrieve data for given key
+    pub async fn retrieve<T: Serialize + for<'de> Deserialize<'de>>(&self, key: &str) -> StorageResult<T> {
+        self.record_op().await?;
+        self.manager.retrieve(&self.scoped_key(key)).await
+    }
+
+    /// Delete data for given key, releasing its share of the quota
+    pub async fn delete(&self, key: &str) -> StorageResult<()> {
+        let mut tenants = self.manager.tenants.write().await;
+        let state = self.state_mut(&mut tenants)?;
+        state.record_op(&self.tenant)?;
+
+        self.manager.delete(&self.scoped_key(key)).await?;
+
+        if let Some(size) = state.sizes.remove(key) {
+            state.metrics.used_size = state.metrics.used_size.saturating_sub(size);
+            state.metrics.total_items = state.metrics.total_items.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    /// Scan this tenant's items whose key starts with `prefix`
+    ///
+    /// Returned keys and cursors are relative to the tenant namespace.
+    pub async fn scan<T: for<'de> Deserialize<'de>>(
+        &self,
+        prefix: &str,
+        page: &PageRequest,
+    ) -> StorageResult<Page<(String, T)>> {
+        self.record_op().await?;
+
+        let scoped = PageRequest {
+            cursor: page.cursor.as_deref().map(|cursor| self.scoped_key(cursor)),
+            limit: page.limit,
+        };
+        let page = self.manager.scan::<T>(&self.scoped_key(prefix), &scoped).await?;
+
+        Ok(Page {
+            items: page
+                .items
+                .into_iter()
+                .map(|(key, value)| (self.unscoped_key(&key), value))
+                .collect(),
+            next_cursor: page.next_cursor.map(|cursor| self.unscoped_key(&cursor)),
+        })
+    }
+
+    /// Get current metrics for this tenant
+    pub async fn get_metrics(&self) -> StorageResult<StorageMetrics> {
+        let tenants = self.manager.tenants.read().await;
+        tenants
+            .get(&self.tenant)
+            .map(|state| state.metrics.clone())
+            .ok_or_else(|| StorageError::UnknownTenant(self.tenant.to_string()))
+    }
+
+    fn scoped_key(&self, key: &str) -> String {
+        format!("{}{}", self.tenant.key_prefix(), key)
+    }
+
+    fn unscoped_key(&self, key: &str) -> String {
+        key.strip_prefix(&self.tenant.key_prefix())
+            .unwrap_or(key)
+            .to_string()
+    }
+
+    async fn record_op(&self) -> StorageResult<()> {
+        let mut tenants = self.manager.tenants.write().await;
+        self.state_mut(&mut tenants)?.record_op(&self.tenant)
+    }
+
+    fn state_mut<'r>(&self, tenants: &'r mut TenantRegistry) -> StorageResult<&'r mut TenantState> {
+        tenants
+            .get_mut(&self.tenant)
+            .ok_or_else(|| StorageError::UnknownTenant(self.tenant.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tenant_id_validation() {
+        assert!(TenantId::new("acme").is_ok());
+        assert!(TenantId::new("").is_err());
+        assert!(TenantId::new("acme/../other").is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let tenant = TenantId::new("acme").unwrap();
+        let mut state = TenantState::new(TenantQuota {
+            max_ops_per_second: 2,
+            ..Default::default()
+        });
+
+        assert!(state.record_op(&tenant).is_ok());
+        assert!(state.record_op(&tenant).is_ok());
+        assert!(matches!(
+            state.record_op(&tenant),
+            Err(StorageError::RateLimited { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_quota_accounting() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = StorageManager::new(crate::storage::StorageConfig {
+            base_dir: temp_dir.path().to_path_buf(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let tenant = TenantId::new("acme").unwrap();
+        let value = vec![0u8; 100];
+        let size = bincode::serialized_size(&value).unwrap();
+        manager
+            .register_tenant(tenant.clone(), TenantQuota {
+                max_size: size * 2,
+                max_items: 2,
+                ..Default::default()
+            })
+            .await;
+        let storage = manager.tenant(&tenant).await.unwrap();
+
+        // Overwrites replace the item's size instead of adding to it
+        for _ in 0..3 {
+            storage.store("a", &value).await.unwrap();
+        }
+        let metrics = storage.get_metrics().await.unwrap();
+        assert_eq!((metrics.used_size, metrics.total_items), (size, 1));
+
+        storage.store("b", &value).await.unwrap();
+        assert!(matches!(storage.store("c", &value).await, Err(StorageError::QuotaExceeded(_))));
+
+        // Deleting releases the item's share of the quota
+        storage.delete("a").await.unwrap();
+        let metrics = storage.get_metrics().await.unwrap();
+        assert_eq!((metrics.used_size, metrics.total_items), (size, 1));
+        storage.store("c", &value).await.unwrap();
+    }
+}
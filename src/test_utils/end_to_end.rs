@@ -0,0 +1,739 @@
+//! Every agent program instruction sent through `ProgramTest`
+
+use super::*;
+use crate::solana::program::{
+    action::CpiAction,
+    oracle::{Comparator, PriceCondition},
+    state::{
+        AgentMetadata, AgentRegistry, AgentState, ExecutionReceipt, ExecutionStatus, PauseReason, ProgramConfig,
+        ResultAccount, StakeAccount, DELEGATE_PAUSE,
+    },
+};
+
+fn expect_error(result: Result<(), BanksClientError>, expected: AgentError) {
+    let error = result.expect_err("transaction should fail");
+    assert_eq!(agent_error(&error), Some(expected), "{error:?}");
+}
+
+impl TestContext {
+    async fn registry(&mut self, authority: &Pubkey) -> AgentRegistry {
+        let (registry, _) = pda::find_registry_address(&self.program_id, authority);
+        AgentRegistry::unpack(&self.account(&registry).await.unwrap().data).unwrap()
+    }
+
+    async fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        self.process(&[instruction], signers).await
+    }
+
+    /// Start a program test whose program data records `upgrade_authority`
+    async fn start_upgradeable(upgrade_authority: &Pubkey) -> Self {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = program_test(&program_id);
+        program_test.add_account(pda::find_program_data_address(&program_id), program_data_account(upgrade_authority));
+        Self::start_with(program_test, program_id).await
+    }
+
+    /// Have `upgrade_authority` appoint a new admin, paying for the config
+    async fn appoint_admin(&mut self, upgrade_authority: &Keypair) -> Keypair {
+        let admin = Keypair::new();
+        let payer = self.context.payer.pubkey();
+        let transfer = system_instruction::transfer(&payer, &upgrade_authority.pubkey(), PAYER_LAMPORTS);
+        self.send(transfer, &[]).await.unwrap();
+        let set_admin = AgentInstruction::set_admin(&self.program_id, &upgrade_authority.pubkey(), &admin.pubkey());
+        self.send(set_admin, &[upgrade_authority]).await.unwrap();
+        admin
+    }
+
+    async fn program_config(&mut self) -> ProgramConfig {
+        let (config, _) = pda::find_config_address(&self.program_id);
+        ProgramConfig::unpack(&self.account(&config).await.unwrap().data).unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.create_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let account = ctx.agent(&agent).await;
+    assert_eq!(account.state, AgentState::Initialized);
+    assert_eq!((account.authority, account.creator), (authority.pubkey(), authority.pubkey()));
+    assert_eq!(ctx.registry(&authority.pubkey()).await.agents, vec![agent]);
+
+    let (metadata, _) = pda::find_metadata_address(&ctx.program_id, &agent);
+    let metadata = AgentMetadata::unpack(&ctx.account(&metadata).await.unwrap().data).unwrap();
+    assert_eq!((metadata.agent, metadata.version), (agent, 1));
+
+    let initialize = AgentInstruction::initialize(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        "agent".to_string(),
+        agent_config(CapabilityFlags::COMPUTE),
+    );
+    expect_error(ctx.send(initialize, &[&authority]).await, AgentError::NameAlreadyTaken);
+}
+
+#[tokio::test]
+async fn test_stake_and_resume() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.create_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let resume = AgentInstruction::resume(&ctx.program_id, &agent, &authority.pubkey());
+    expect_error(ctx.send(resume.clone(), &[&authority]).await, AgentError::InsufficientStake);
+
+    let stake = AgentInstruction::stake(&ctx.program_id, &agent, &authority.pubkey(), MIN_AGENT_STAKE);
+    ctx.send(stake, &[&authority]).await.unwrap();
+    ctx.send(resume, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.state, AgentState::Running);
+
+    let (stake, _) = pda::find_stake_address(&ctx.program_id, &agent);
+    assert!(ctx.lamports(&stake).await >= MIN_AGENT_STAKE);
+}
+
+#[tokio::test]
+async fn test_execute() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    ctx.execute(&authority, &agent, AgentAction::Noop).await.unwrap();
+    ctx.execute(&authority, &agent, AgentAction::Memo { text: "hello".to_string() }).await.unwrap();
+
+    let (result, _) = pda::find_result_address(&ctx.program_id, &agent);
+    let with_memo = AgentInstruction::execute_with_memo(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        &result,
+        3,
+        AgentAction::Noop,
+        Some("audit".to_string()),
+    );
+    ctx.send(with_memo, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.execution_count, 3);
+
+    let (receipt, _) = pda::find_receipt_address(&ctx.program_id, &agent, 3);
+    let receipt = ExecutionReceipt::unpack(&ctx.account(&receipt).await.unwrap().data).unwrap();
+    assert_eq!((receipt.execution, receipt.signer), (3, authority.pubkey()));
+    assert!(receipt.memo_hash.is_some());
+
+    let results = ResultAccount::unpack(&ctx.account(&result).await.unwrap().data).unwrap();
+    assert_eq!(results.latest().map(|result| result.execution), Some(3));
+
+    // Executing a stale execution number misses the receipt PDA
+    let stale = AgentInstruction::execute(&ctx.program_id, &agent, &authority.pubkey(), &result, 3, AgentAction::Noop);
+    expect_error(ctx.send(stale, &[&authority]).await, AgentError::InvalidProgramAddress);
+}
+
+#[tokio::test]
+async fn test_execution_limit() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let config = AgentConfig { execution_limit: 2, ..agent_config(CapabilityFlags::COMPUTE) };
+    let agent = ctx.start_agent(&authority, "agent", config).await;
+
+    ctx.execute(&authority, &agent, AgentAction::Noop).await.unwrap();
+    ctx.execute(&authority, &agent, AgentAction::Noop).await.unwrap();
+    expect_error(ctx.execute(&authority, &agent, AgentAction::Noop).await, AgentError::ExecutionLimitExceeded);
+    assert_eq!(ctx.agent(&agent).await.execution_count, 2);
+}
+
+#[tokio::test]
+async fn test_refused_action_recorded_as_failed() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    // The agent's allowlist is empty, so the CPI is refused
+    let cpi = CpiAction { program_id: Pubkey::new_unique(), accounts: vec![], data: vec![] };
+    ctx.execute(&authority, &agent, AgentAction::CustomCpi(cpi)).await.unwrap();
+
+    let (receipt, _) = pda::find_receipt_address(&ctx.program_id, &agent, 1);
+    let receipt = ExecutionReceipt::unpack(&ctx.account(&receipt).await.unwrap().data).unwrap();
+    assert_eq!(receipt.status, ExecutionStatus::Failed { error: AgentError::ProgramNotAllowed as u32 });
+
+    let account = ctx.agent(&agent).await;
+    assert_eq!(account.execution_count, 1);
+    assert_eq!((account.metrics.successful_executions, account.metrics.failed_executions), (0, 1));
+}
+
+#[tokio::test]
+async fn test_pause_and_resume() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let pause = AgentInstruction::pause(&ctx.program_id, &agent, &authority.pubkey(), PauseReason::Maintenance, None);
+    ctx.send(pause, &[&authority]).await.unwrap();
+    let account = ctx.agent(&agent).await;
+    assert_eq!((account.state, account.pause_reason), (AgentState::Paused, Some(PauseReason::Maintenance)));
+    expect_error(ctx.execute(&authority, &agent, AgentAction::Noop).await, AgentError::InvalidAgentState);
+
+    let resume = AgentInstruction::resume(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.send(resume, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.pause_reason, None);
+    ctx.execute(&authority, &agent, AgentAction::Noop).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_update() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let mut config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE);
+    config.allowed_programs = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+    let update = AgentInstruction::update(&ctx.program_id, &agent, &authority.pubkey(), config.clone());
+    ctx.send(update, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.config, config);
+
+    let other = ctx.funded_payer().await;
+    let update = AgentInstruction::update(&ctx.program_id, &agent, &other.pubkey(), config);
+    expect_error(ctx.send(update, &[&other]).await, AgentError::InvalidAuthority);
+}
+
+#[tokio::test]
+async fn test_delayed_update() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+    let (pending, _) = pda::find_pending_update_address(&ctx.program_id, &agent);
+
+    let set_delay = AgentInstruction::set_update_delay(&ctx.program_id, &agent, &authority.pubkey(), 100);
+    ctx.send(set_delay, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.update_delay, 100);
+
+    // Updates are queued until the delay has passed
+    let config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE);
+    let update = AgentInstruction::update(&ctx.program_id, &agent, &authority.pubkey(), config.clone());
+    ctx.send(update.clone(), &[&authority]).await.unwrap();
+    assert!(ctx.account(&pending).await.is_some());
+    assert_eq!(ctx.agent(&agent).await.config.capabilities, CapabilityFlags::COMPUTE);
+
+    let commit = AgentInstruction::commit_update(&ctx.program_id, &agent, &authority.pubkey());
+    expect_error(ctx.send(commit.clone(), &[&authority]).await, AgentError::UpdateLocked);
+
+    let slot = ctx.clock().await.slot;
+    ctx.warp_to_slot(slot + 200);
+    ctx.send(commit, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.config, config);
+    assert!(ctx.account(&pending).await.is_none());
+
+    // A queued update can be discarded instead
+    ctx.send(update.clone(), &[&authority]).await.unwrap();
+    let cancel = AgentInstruction::cancel_update(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.send(cancel, &[&authority]).await.unwrap();
+    assert!(ctx.account(&pending).await.is_none());
+
+    // Or goes with the agent, rather than waiting for one recreated at its
+    // address
+    ctx.send(update, &[&authority]).await.unwrap();
+    let close = AgentInstruction::close(&ctx.program_id, &agent, &authority.pubkey());
+    let unstake = AgentInstruction::unstake(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.process(&[close, unstake], &[&authority]).await.unwrap();
+    assert!(ctx.account(&pending).await.is_none());
+}
+
+#[tokio::test]
+async fn test_execute_after() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+    let (result, _) = pda::find_result_address(&ctx.program_id, &agent);
+
+    let earliest_slot = ctx.clock().await.slot + 50;
+    let execute_after = AgentInstruction::execute_after(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        &result,
+        1,
+        AgentAction::Noop,
+        Some(earliest_slot),
+        None,
+    );
+    expect_error(ctx.send(execute_after.clone(), &[&authority]).await, AgentError::ExecutionLocked);
+
+    ctx.warp_to_slot(earliest_slot);
+    ctx.send(execute_after, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.execution_count, 1);
+}
+
+#[tokio::test]
+async fn test_execute_conditional() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE);
+    let agent = ctx.start_agent(&authority, "agent", config).await;
+    let (result, _) = pda::find_result_address(&ctx.program_id, &agent);
+
+    let feed = Pubkey::new_unique();
+    let slot = ctx.clock().await.slot;
+    ctx.set_account(&feed, pyth_price_account(150, 0, slot));
+
+    let condition = |comparator| PriceCondition {
+        feed,
+        comparator,
+        threshold: 100,
+        expo: 0,
+        max_staleness_slots: 25,
+    };
+    let execute_if = |comparator| {
+        AgentInstruction::execute_conditional(
+            &ctx.program_id,
+            &agent,
+            &authority.pubkey(),
+            &result,
+            1,
+            AgentAction::Noop,
+            condition(comparator),
+        )
+    };
+    let (below, above) = (execute_if(Comparator::LessThan), execute_if(Comparator::GreaterThan));
+
+    expect_error(ctx.send(below, &[&authority]).await, AgentError::ConditionNotMet);
+
+    // A copy of a price account held by another program isn't a feed
+    let mut spoofed = pyth_price_account(150, 0, slot);
+    spoofed.owner = Pubkey::new_unique();
+    ctx.set_account(&feed, spoofed);
+    expect_error(ctx.send(above.clone(), &[&authority]).await, AgentError::InvalidOracleAccount);
+
+    ctx.set_account(&feed, pyth_price_account(150, 0, slot));
+    ctx.send(above, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.execution_count, 1);
+
+    // Switchboard aggregators gate actions the same way
+    ctx.set_account(&feed, switchboard_aggregator_account(150_000_000_000, 9, slot));
+    let above = AgentInstruction::execute_conditional(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        &result,
+        2,
+        AgentAction::Noop,
+        condition(Comparator::GreaterThan),
+    );
+    ctx.send(above, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.execution_count, 2);
+}
+
+#[tokio::test]
+async fn test_chunked_execute() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE);
+    let agent = ctx.start_agent(&authority, "agent", config).await;
+    let (result, _) = pda::find_result_address(&ctx.program_id, &agent);
+
+    let action = AgentAction::Memo { text: "x".repeat(200) };
+    let (staging, _) = pda::find_staging_address(&ctx.program_id, &agent, &authority.pubkey());
+    let instructions =
+        AgentInstruction::chunked_execute(&ctx.program_id, &agent, &authority.pubkey(), &result, 1, &action, 0);
+    assert_eq!(instructions.len(), 2);
+
+    for instruction in instructions {
+        ctx.send(instruction, &[&authority]).await.unwrap();
+    }
+    assert_eq!(ctx.agent(&agent).await.execution_count, 1);
+    assert!(ctx.account(&staging).await.is_none());
+}
+
+#[tokio::test]
+async fn test_close_and_unstake() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+    let (stake, _) = pda::find_stake_address(&ctx.program_id, &agent);
+
+    let unstake = AgentInstruction::unstake(&ctx.program_id, &agent, &authority.pubkey());
+    expect_error(ctx.send(unstake.clone(), &[&authority]).await, AgentError::StakeLocked);
+
+    let balance = ctx.lamports(&authority.pubkey()).await;
+    let refund = ctx.lamports(&agent).await + ctx.lamports(&stake).await;
+    let close = AgentInstruction::close(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.process(&[close, unstake], &[&authority]).await.unwrap();
+
+    assert!(ctx.account(&agent).await.is_none());
+    assert!(ctx.account(&stake).await.is_none());
+    assert_eq!(ctx.lamports(&authority.pubkey()).await, balance + refund);
+    assert!(ctx.registry(&authority.pubkey()).await.agents.is_empty());
+}
+
+#[tokio::test]
+async fn test_recreated_agent_closes_receipts() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+    ctx.execute(&authority, &agent, AgentAction::Noop).await.unwrap();
+
+    let close = AgentInstruction::close(&ctx.program_id, &agent, &authority.pubkey());
+    let unstake = AgentInstruction::unstake(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.process(&[close, unstake], &[&authority]).await.unwrap();
+    assert_eq!(ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await, agent);
+
+    // The old agent's first receipt is kept until its authority closes it
+    expect_error(ctx.execute(&authority, &agent, AgentAction::Noop).await, AgentError::AlreadyInitialized);
+    let (receipt, _) = pda::find_receipt_address(&ctx.program_id, &agent, 1);
+    let close_receipt = AgentInstruction::close_receipt(&ctx.program_id, &agent, &authority.pubkey(), 1);
+    ctx.send(close_receipt, &[&authority]).await.unwrap();
+    assert!(ctx.account(&receipt).await.is_none());
+    let (result, _) = pda::find_result_address(&ctx.program_id, &agent);
+    let execute = AgentInstruction::execute_with_memo(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        &result,
+        1,
+        AgentAction::Noop,
+        Some("again".to_string()),
+    );
+    ctx.send(execute, &[&authority]).await.unwrap();
+
+    let receipt = ExecutionReceipt::unpack(&ctx.account(&receipt).await.unwrap().data).unwrap();
+    assert_eq!(receipt.status, ExecutionStatus::Succeeded);
+    assert!(receipt.memo_hash.is_some());
+}
+
+#[tokio::test]
+async fn test_transfer_authority() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let new_authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let transfer = AgentInstruction::transfer_authority(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        &new_authority.pubkey(),
+        false,
+    );
+    ctx.send(transfer, &[&authority]).await.unwrap();
+    let account = ctx.agent(&agent).await;
+    assert_eq!((account.authority, account.pending_authority), (authority.pubkey(), Some(new_authority.pubkey())));
+
+    let accept =
+        AgentInstruction::accept_authority(&ctx.program_id, &agent, &authority.pubkey(), &new_authority.pubkey());
+    ctx.send(accept, &[&new_authority]).await.unwrap();
+    let account = ctx.agent(&agent).await;
+    assert_eq!((account.authority, account.pending_authority), (new_authority.pubkey(), None));
+    assert!(ctx.registry(&authority.pubkey()).await.agents.is_empty());
+    assert_eq!(ctx.registry(&new_authority.pubkey()).await.agents, vec![agent]);
+    expect_error(ctx.execute(&authority, &agent, AgentAction::Noop).await, AgentError::InvalidAuthority);
+}
+
+#[tokio::test]
+async fn test_migrate() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.create_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+    let before = ctx.account(&agent).await.unwrap();
+
+    // Agents in the current layout are left as they are
+    let migrate = AgentInstruction::migrate(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.send(migrate, &[&authority]).await.unwrap();
+    assert_eq!(ctx.account(&agent).await.unwrap().data, before.data);
+
+    // Agents of the first release move from their keypair account into
+    // their PDA, which other instructions require
+    let legacy = Pubkey::new_unique();
+    let data = v1_agent_data(&authority.pubkey(), "legacy");
+    let account = Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: ctx.program_id,
+        executable: false,
+        rent_epoch: 0,
+    };
+    ctx.set_account(&legacy, account);
+    let program_id = ctx.program_id;
+    let pause = |agent: &Pubkey| {
+        AgentInstruction::pause(&program_id, agent, &authority.pubkey(), PauseReason::Maintenance, None)
+    };
+    expect_error(ctx.send(pause(&legacy), &[&authority]).await, AgentError::InvalidProgramAddress);
+
+    let migrate = AgentInstruction::migrate_legacy(&ctx.program_id, &legacy, &authority.pubkey(), "legacy");
+    ctx.send(migrate, &[&authority]).await.unwrap();
+    assert!(ctx.account(&legacy).await.is_none());
+    let (agent, bump) = pda::find_agent_address(&ctx.program_id, &authority.pubkey(), "legacy");
+    let moved = ctx.agent(&agent).await;
+    assert_eq!((moved.creator, moved.bump, moved.execution_count), (authority.pubkey(), bump, 4));
+
+    ctx.send(pause(&agent), &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.state, AgentState::Paused);
+}
+
+#[tokio::test]
+async fn test_delegate() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let session = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let expiry_slot = ctx.clock().await.slot + 1_000;
+    let delegate =
+        AgentInstruction::delegate(&ctx.program_id, &agent, &authority.pubkey(), &session.pubkey(), expiry_slot, DELEGATE_PAUSE);
+    ctx.send(delegate, &[&authority]).await.unwrap();
+
+    // The session key may pause, but not execute
+    let execute = ctx.execute_instruction(&session.pubkey(), &agent, AgentAction::Noop).await;
+    let execute = AgentInstruction::signed_by_delegate(execute, &agent, &authority.pubkey(), &session.pubkey());
+    expect_error(ctx.send(execute, &[&session]).await, AgentError::Unauthorized);
+
+    let pause = AgentInstruction::pause(&ctx.program_id, &agent, &session.pubkey(), PauseReason::Emergency, None);
+    let pause = AgentInstruction::signed_by_delegate(pause, &agent, &authority.pubkey(), &session.pubkey());
+    ctx.send(pause, &[&session]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.state, AgentState::Paused);
+
+    let revoke = AgentInstruction::revoke_delegate(&ctx.program_id, &agent, &authority.pubkey(), &session.pubkey());
+    ctx.send(revoke, &[&authority]).await.unwrap();
+    let (record, _) = pda::find_delegate_address(&ctx.program_id, &agent, &authority.pubkey(), &session.pubkey());
+    assert!(ctx.account(&record).await.is_none());
+}
+
+#[tokio::test]
+async fn test_recreated_agent_drops_delegates() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let session = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let expiry_slot = ctx.clock().await.slot + 1_000;
+    let delegate = |ctx: &TestContext| {
+        AgentInstruction::delegate(&ctx.program_id, &agent, &authority.pubkey(), &session.pubkey(), expiry_slot, DELEGATE_PAUSE)
+    };
+    ctx.send(delegate(&ctx), &[&authority]).await.unwrap();
+
+    let close = AgentInstruction::close(&ctx.program_id, &agent, &authority.pubkey());
+    let unstake = AgentInstruction::unstake(&ctx.program_id, &agent, &authority.pubkey());
+    ctx.process(&[close, unstake], &[&authority]).await.unwrap();
+    assert_eq!(ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await, agent);
+
+    // The closed agent's record outlives it, but doesn't carry over
+    let pause = AgentInstruction::pause(&ctx.program_id, &agent, &session.pubkey(), PauseReason::Emergency, None);
+    let pause = AgentInstruction::signed_by_delegate(pause, &agent, &authority.pubkey(), &session.pubkey());
+    expect_error(ctx.send(pause.clone(), &[&session]).await, AgentError::InvalidAuthority);
+
+    ctx.send(delegate(&ctx), &[&authority]).await.unwrap();
+    ctx.send(pause, &[&session]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.state, AgentState::Paused);
+}
+
+#[tokio::test]
+async fn test_admin_freeze() {
+    let upgrade_authority = Keypair::new();
+    let mut ctx = TestContext::start_upgradeable(&upgrade_authority.pubkey()).await;
+    let program_id = ctx.program_id;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    // Only the upgrade authority appoints the first admin
+    let impostor = ctx.funded_payer().await;
+    let set_admin = AgentInstruction::set_admin(&program_id, &impostor.pubkey(), &impostor.pubkey());
+    expect_error(ctx.send(set_admin, &[&impostor]).await, AgentError::InvalidAuthority);
+    let admin = ctx.appoint_admin(&upgrade_authority).await;
+    assert_eq!(ctx.program_config().await.admin, admin.pubkey());
+
+    ctx.send(AgentInstruction::freeze_all(&program_id, &admin.pubkey()), &[&admin]).await.unwrap();
+    expect_error(ctx.execute(&authority, &agent, AgentAction::Noop).await, AgentError::ProgramFrozen);
+
+    ctx.send(AgentInstruction::thaw_all(&program_id, &admin.pubkey()), &[&admin]).await.unwrap();
+    ctx.execute(&authority, &agent, AgentAction::Noop).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_set_oracle_programs() {
+    let upgrade_authority = Keypair::new();
+    let mut ctx = TestContext::start_upgradeable(&upgrade_authority.pubkey()).await;
+    let admin = ctx.appoint_admin(&upgrade_authority).await;
+    let authority = ctx.funded_payer().await;
+    let config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE);
+    let agent = ctx.start_agent(&authority, "agent", config).await;
+
+    // Only the admin points the program at other oracle deployments
+    let (pyth, switchboard) = (Pubkey::new_unique(), Pubkey::new_unique());
+    let set_programs = |signer: &Pubkey| {
+        AgentInstruction::set_oracle_programs(&ctx.program_id, signer, &pyth, &switchboard)
+    };
+    let (by_authority, by_admin) = (set_programs(&authority.pubkey()), set_programs(&admin.pubkey()));
+    expect_error(ctx.send(by_authority, &[&authority]).await, AgentError::InvalidAuthority);
+    ctx.send(by_admin, &[&admin]).await.unwrap();
+    let config = ctx.program_config().await;
+    assert_eq!((config.pyth_program, config.switchboard_program), (pyth, switchboard));
+
+    // Feeds are now only trusted when owned by the configured program
+    let feed = Pubkey::new_unique();
+    let slot = ctx.clock().await.slot;
+    ctx.set_account(&feed, pyth_price_account(150, 0, slot));
+    let (result, _) = pda::find_result_address(&ctx.program_id, &agent);
+    let condition = PriceCondition {
+        feed,
+        comparator: Comparator::GreaterThan,
+        threshold: 100,
+        expo: 0,
+        max_staleness_slots: 25,
+    };
+    let execute_if = AgentInstruction::execute_conditional(
+        &ctx.program_id,
+        &agent,
+        &authority.pubkey(),
+        &result,
+        1,
+        AgentAction::Noop,
+        condition,
+    );
+    expect_error(ctx.send(execute_if.clone(), &[&authority]).await, AgentError::InvalidOracleAccount);
+
+    ctx.set_account(&feed, Account { owner: pyth, ..pyth_price_account(150, 0, slot) });
+    ctx.send(execute_if, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.execution_count, 1);
+}
+
+#[tokio::test]
+async fn test_clone_agent() {
+    let mut ctx = TestContext::start().await;
+    let owner = ctx.funded_payer().await;
+    let authority = ctx.funded_payer().await;
+    let config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::ORACLE);
+    let source = ctx.create_agent(&owner, "source", config.clone()).await;
+
+    let (clone, _) = pda::find_agent_address(&ctx.program_id, &authority.pubkey(), "clone");
+    let clone_agent =
+        AgentInstruction::clone_agent(&ctx.program_id, &clone, &authority.pubkey(), &source, "clone".to_string());
+    ctx.send(clone_agent, &[&authority]).await.unwrap();
+
+    let account = ctx.agent(&clone).await;
+    assert_eq!((account.authority, account.config), (authority.pubkey(), config));
+    assert_eq!(ctx.registry(&authority.pubkey()).await.agents, vec![clone]);
+}
+
+#[tokio::test]
+async fn test_executors() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let executor = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let add = AgentInstruction::add_executor(&ctx.program_id, &agent, &authority.pubkey(), &executor.pubkey());
+    ctx.send(add, &[&authority]).await.unwrap();
+    ctx.execute(&executor, &agent, AgentAction::Noop).await.unwrap();
+
+    // Executors execute, but can't move the agent's funds
+    let transfer = AgentAction::Transfer { destination: executor.pubkey(), lamports: 1 };
+    expect_error(ctx.execute(&executor, &agent, transfer).await, AgentError::InvalidAuthority);
+
+    // Executors execute but can't manage the agent
+    let pause = AgentInstruction::pause(&ctx.program_id, &agent, &executor.pubkey(), PauseReason::Manual, None);
+    expect_error(ctx.send(pause, &[&executor]).await, AgentError::InvalidAuthority);
+
+    let remove = AgentInstruction::remove_executor(&ctx.program_id, &agent, &authority.pubkey(), &executor.pubkey());
+    ctx.send(remove, &[&authority]).await.unwrap();
+    expect_error(ctx.execute(&executor, &agent, AgentAction::Noop).await, AgentError::InvalidAuthority);
+}
+
+#[tokio::test]
+async fn test_crank_execute() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let executor = Keypair::new();
+    let payer = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let add = AgentInstruction::add_executor(&ctx.program_id, &agent, &authority.pubkey(), &executor.pubkey());
+    ctx.send(add, &[&authority]).await.unwrap();
+
+    let crank_execute =
+        AgentInstruction::crank_execute(&ctx.program_id, &agent, &payer.pubkey(), &executor.pubkey(), 1, AgentAction::Noop);
+    ctx.send(crank_execute, &[&payer, &executor]).await.unwrap();
+
+    let (receipt, _) = pda::find_receipt_address(&ctx.program_id, &agent, 1);
+    let receipt = ExecutionReceipt::unpack(&ctx.account(&receipt).await.unwrap().data).unwrap();
+    assert_eq!(receipt.signer, payer.pubkey());
+}
+
+#[cfg(not(feature = "clockwork"))]
+#[tokio::test]
+async fn test_crank_needs_clockwork() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&authority, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+
+    let crank = AgentInstruction::crank(&ctx.program_id, &agent, &authority.pubkey(), AgentAction::Noop);
+    expect_error(ctx.send(crank, &[&authority]).await, AgentError::InvalidInstructionData);
+}
+
+#[tokio::test]
+async fn test_spawn_child() {
+    let mut ctx = TestContext::start().await;
+    let authority = ctx.funded_payer().await;
+    let config = agent_config(CapabilityFlags::COMPUTE | CapabilityFlags::STORAGE);
+    let parent = ctx.start_agent(&authority, "parent", config).await;
+
+    let (child, _) = pda::find_agent_address(&ctx.program_id, &authority.pubkey(), "child");
+    let spawn = |name: &str, capabilities| {
+        AgentInstruction::spawn_child(
+            &ctx.program_id,
+            &pda::find_agent_address(&ctx.program_id, &authority.pubkey(), name).0,
+            &authority.pubkey(),
+            &parent,
+            name.to_string(),
+            agent_config(capabilities),
+        )
+    };
+    let (too_capable, spawn_child) = (spawn("oracle", CapabilityFlags::ORACLE), spawn("child", CapabilityFlags::COMPUTE));
+    expect_error(ctx.send(too_capable, &[&authority]).await, AgentError::MissingCapability);
+    ctx.send(spawn_child, &[&authority]).await.unwrap();
+    assert_eq!(ctx.agent(&child).await.parent, Some(parent));
+
+    let start = [
+        AgentInstruction::stake(&ctx.program_id, &child, &authority.pubkey(), MIN_AGENT_STAKE),
+        AgentInstruction::resume(&ctx.program_id, &child, &authority.pubkey()),
+    ];
+    ctx.process(&start, &[&authority]).await.unwrap();
+
+    let execute = ctx.execute_instruction(&authority.pubkey(), &child, AgentAction::Noop).await;
+    ctx.send(AgentInstruction::with_parent(execute.clone(), &parent), &[&authority]).await.unwrap();
+
+    // Pausing the parent holds the child
+    let pause = AgentInstruction::pause(&ctx.program_id, &parent, &authority.pubkey(), PauseReason::RiskLimit, None);
+    ctx.send(pause, &[&authority]).await.unwrap();
+    let execute = ctx.execute_instruction(&authority.pubkey(), &child, AgentAction::Noop).await;
+    expect_error(ctx.send(AgentInstruction::with_parent(execute, &parent), &[&authority]).await, AgentError::ParentPaused);
+}
+
+#[tokio::test]
+async fn test_marketplace() {
+    let mut ctx = TestContext::start().await;
+    let seller = ctx.funded_payer().await;
+    let buyer = ctx.funded_payer().await;
+    let agent = ctx.start_agent(&seller, "agent", agent_config(CapabilityFlags::COMPUTE)).await;
+    let price = 1_000_000;
+
+    let list = AgentInstruction::list_for_sale(&ctx.program_id, &agent, &seller.pubkey(), price);
+    ctx.send(list.clone(), &[&seller]).await.unwrap();
+    let cancel = AgentInstruction::cancel_listing(&ctx.program_id, &agent, &seller.pubkey());
+    ctx.send(cancel, &[&seller]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.authority, seller.pubkey());
+
+    ctx.send(list, &[&seller]).await.unwrap();
+    let (buyer_key, seller_key) = (buyer.pubkey(), seller.pubkey());
+    let underpay = AgentInstruction::purchase(&ctx.program_id, &agent, &buyer_key, &seller_key, &seller_key, price - 1);
+    expect_error(ctx.send(underpay, &[&buyer]).await, AgentError::PriceMismatch);
+
+    let balance = ctx.lamports(&seller_key).await;
+    let (stake, _) = pda::find_stake_address(&ctx.program_id, &agent);
+    let escrow = ctx.lamports(&stake).await;
+    let purchase = AgentInstruction::purchase(&ctx.program_id, &agent, &buyer_key, &seller_key, &seller_key, price);
+    ctx.send(purchase, &[&buyer]).await.unwrap();
+    assert_eq!(ctx.agent(&agent).await.authority, buyer.pubkey());
+    // The seller is refunded the stake the buyer took over
+    assert!(ctx.lamports(&seller_key).await >= balance + price + escrow);
+    assert_eq!(StakeAccount::unpack(&ctx.account(&stake).await.unwrap().data).unwrap().staker, buyer_key);
+    assert!(ctx.registry(&seller.pubkey()).await.agents.is_empty());
+    assert_eq!(ctx.registry(&buyer.pubkey()).await.agents, vec![agent]);
+    ctx.execute(&buyer, &agent, AgentAction::Noop).await.unwrap();
+}
@@ -0,0 +1,292 @@
+//! Fixtures for end-to-end tests of the agent program
+//!
+//! This module provides:
+//! - A `ProgramTest` running the agent program natively
+//! - Funded payers, and agents created, staked and started through the
+//!   program's own instructions
+//! - Sending instructions and reading program accounts back
+//! - Decoding the `AgentError` a transaction failed with
+//! - Agent configs and accounts, encoded as the program stores them
+//!
+//! It is compiled for the crate's own tests and, with the `test-utils`
+//! feature, for dependents testing programs that call the agent program.
+
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::Clock,
+    instruction::Instruction,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+use crate::solana::program::{
+    action::AgentAction,
+    capability::CapabilityFlags,
+    error::AgentError,
+    instruction::{AgentConfig, AgentInstruction},
+    oracle::{self, PYTH_STATUS_TRADING},
+    pda,
+    process_instruction,
+    state::{AgentAccount, MIN_AGENT_STAKE},
+};
+#[cfg(feature = "zero-copy")]
+use crate::solana::program::zero_copy::AgentAccountZc;
+
+#[cfg(test)]
+mod end_to_end;
+
+/// Name the agent program is registered under
+pub const PROGRAM_NAME: &str = "sonoma_labs_toolkit";
+
+/// Lamports given to each payer from `TestContext::funded_payer`
+pub const PAYER_LAMPORTS: u64 = 10 * LAMPORTS_PER_SOL;
+
+/// `ProgramTest` with the agent program registered at `program_id`
+pub fn program_test(program_id: &Pubkey) -> ProgramTest {
+    let mut program_test = ProgramTest::new(PROGRAM_NAME, *program_id, processor!(process_instruction));
+    program_test.prefer_bpf(false);
+    program_test
+}
+
+/// ProgramData account recording `upgrade_authority` as the upgrade
+/// authority, to add at `pda::find_program_data_address` for `SetAdmin`
+pub fn program_data_account(upgrade_authority: &Pubkey) -> Account {
+    let mut data = programdata_metadata(upgrade_authority);
+    data.resize(UpgradeableLoaderState::size_of_programdata_metadata(), 0);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: bpf_loader_upgradeable::id(),
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// `UpgradeableLoaderState::ProgramData` metadata as the loader stores it:
+/// u32 variant, u64 slot, then `Option<Pubkey>`
+fn programdata_metadata(upgrade_authority: &Pubkey) -> Vec<u8> {
+    let mut data = 3u32.to_le_bytes().to_vec();
+    data.extend(0u64.to_le_bytes());
+    data.push(1);
+    data.extend(upgrade_authority.to_bytes());
+    data
+}
+
+/// Pyth price account publishing `price * 10^expo` at `slot`, owned by
+/// the mainnet Pyth program
+pub fn pyth_price_account(price: i64, expo: i32, slot: u64) -> Account {
+    let data = oracle::pyth_price_data(price, expo, PYTH_STATUS_TRADING, slot);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: oracle::PYTH_PROGRAM_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// Switchboard aggregator account whose latest round resulted in
+/// `mantissa * 10^-scale`, opened at `slot`, owned by the mainnet
+/// Switchboard program
+pub fn switchboard_aggregator_account(mantissa: i128, scale: u32, slot: u64) -> Account {
+    let data = oracle::switchboard_aggregator_data(mantissa, scale, 1, slot);
+    Account {
+        lamports: Rent::default().minimum_balance(data.len()),
+        data,
+        owner: oracle::SWITCHBOARD_PROGRAM_ID,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+/// Config of a test agent holding `capabilities`
+pub fn agent_config(capabilities: CapabilityFlags) -> AgentConfig {
+    AgentConfig {
+        autonomous_mode: false,
+        execution_limit: 1_000,
+        memory_limit: 10_000,
+        capabilities,
+        min_execution_interval: 0,
+        allowed_programs: vec![],
+    }
+}
+
+/// Client-side config of a test agent, as passed to `Agent::new`
+pub fn client_config() -> crate::agent::AgentConfig {
+    crate::agent::AgentConfig {
+        autonomous_mode: true,
+        execution_limit: 1_000,
+        memory_limit: 5_000,
+        capabilities: crate::agent::Capabilities::default(),
+        metadata: None,
+    }
+}
+
+/// Agent account named `name` of a new authority, holding no capabilities
+pub fn agent_account(name: &str) -> AgentAccount {
+    AgentAccount::new(Pubkey::new_unique(), name.to_string(), agent_config(CapabilityFlags::default()))
+}
+
+/// Running agent named `name` of `authority` with 4 executions, in the
+/// unversioned v1 layout the first release stored at keypair addresses
+pub fn v1_agent_data(authority: &Pubkey, name: &str) -> Vec<u8> {
+    [
+        &authority.to_bytes()[..],
+        &(name.len() as u32).to_le_bytes(),
+        name.as_bytes(),
+        &[0],
+        &10u64.to_le_bytes(),
+        &5_000u64.to_le_bytes(),
+        &[1, 0, 0, 0, 7, 0, 0, 0],
+        b"compute",
+        &[2],
+        &1_700_000_000i64.to_le_bytes(),
+        &4u64.to_le_bytes(),
+    ]
+    .concat()
+}
+
+/// `agent` encoded in the layout the program stores agents in
+pub fn encode_agent(agent: &AgentAccount) -> Vec<u8> {
+    #[cfg(not(feature = "zero-copy"))]
+    let data = borsh::to_vec(agent).unwrap();
+    #[cfg(feature = "zero-copy")]
+    let data = {
+        let mut data = vec![0; AgentAccountZc::LEN];
+        AgentAccountZc::store(agent, &mut data).unwrap();
+        data
+    };
+    data
+}
+
+/// Agent program error a transaction failed with, if any
+pub fn agent_error(error: &BanksClientError) -> Option<AgentError> {
+    match error {
+        BanksClientError::TransactionError(error) | BanksClientError::SimulationError { err: error, .. } => {
+            crate::error::agent_error(error)
+        }
+        _ => None,
+    }
+}
+
+/// Transaction error of a failed transaction, if it got that far
+pub fn transaction_error(error: &BanksClientError) -> Option<&TransactionError> {
+    match error {
+        BanksClientError::TransactionError(error) | BanksClientError::SimulationError { err: error, .. } => Some(error),
+        _ => None,
+    }
+}
+
+/// Started program test with the agent program
+pub struct TestContext {
+    pub context: ProgramTestContext,
+    pub program_id: Pubkey,
+}
+
+impl TestContext {
+    /// Start a program test with only the agent program
+    pub async fn start() -> Self {
+        let program_id = Pubkey::new_unique();
+        Self::start_with(program_test(&program_id), program_id).await
+    }
+
+    /// Start `program_test`, which registers the agent program at
+    /// `program_id` (see `program_test`)
+    pub async fn start_with(program_test: ProgramTest, program_id: Pubkey) -> Self {
+        Self {
+            context: program_test.start_with_context().await,
+            program_id,
+        }
+    }
+
+    /// Send `instructions` in one transaction paid for by the context's
+    /// payer and also signed by `signers`
+    pub async fn process(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        // A fresh blockhash keeps repeated identical transactions apart
+        let blockhash = self.context.get_new_latest_blockhash().await?;
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&self.context.payer.pubkey()), &all_signers, blockhash);
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    /// New keypair holding `PAYER_LAMPORTS`
+    pub async fn funded_payer(&mut self) -> Keypair {
+        let payer = Keypair::new();
+        let transfer = system_instruction::transfer(&self.context.payer.pubkey(), &payer.pubkey(), PAYER_LAMPORTS);
+        self.process(&[transfer], &[]).await.unwrap();
+        payer
+    }
+
+    pub async fn account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(*address).await.unwrap()
+    }
+
+    pub async fn lamports(&mut self, address: &Pubkey) -> u64 {
+        self.account(address).await.map_or(0, |account| account.lamports)
+    }
+
+    /// Fetch and decode an agent account, which must exist
+    pub async fn agent(&mut self, address: &Pubkey) -> AgentAccount {
+        let account = self.account(address).await.expect("agent account exists");
+        AgentAccount::unpack(&account.data).unwrap()
+    }
+
+    pub async fn clock(&mut self) -> Clock {
+        self.context.banks_client.get_sysvar::<Clock>().await.unwrap()
+    }
+
+    /// Advance the bank to `slot`
+    pub fn warp_to_slot(&mut self, slot: u64) {
+        self.context.warp_to_slot(slot).unwrap();
+    }
+
+    /// Store `account` at `address`
+    pub fn set_account(&mut self, address: &Pubkey, account: Account) {
+        self.context.set_account(address, &account.into());
+    }
+
+    /// Initialize an agent named `name` for `authority`, without starting it
+    pub async fn create_agent(&mut self, authority: &Keypair, name: &str, config: AgentConfig) -> Pubkey {
+        let (agent, _) = pda::find_agent_address(&self.program_id, &authority.pubkey(), name);
+        let initialize =
+            AgentInstruction::initialize(&self.program_id, &agent, &authority.pubkey(), name.to_string(), config);
+        self.process(&[initialize], &[authority]).await.unwrap();
+        agent
+    }
+
+    /// Initialize an agent, stake `MIN_AGENT_STAKE` for it and start it,
+    /// as `Agent::new` does
+    pub async fn start_agent(&mut self, authority: &Keypair, name: &str, config: AgentConfig) -> Pubkey {
+        let (agent, _) = pda::find_agent_address(&self.program_id, &authority.pubkey(), name);
+        let instructions = [
+            AgentInstruction::initialize(&self.program_id, &agent, &authority.pubkey(), name.to_string(), config),
+            AgentInstruction::stake(&self.program_id, &agent, &authority.pubkey(), MIN_AGENT_STAKE),
+            AgentInstruction::resume(&self.program_id, &agent, &authority.pubkey()),
+        ];
+        self.process(&instructions, &[authority]).await.unwrap();
+        agent
+    }
+
+    /// `Execute` instruction running `action` as the agent's next execution,
+    /// signed by `signer`
+    pub async fn execute_instruction(&mut self, signer: &Pubkey, agent: &Pubkey, action: AgentAction) -> Instruction {
+        let execution = self.agent(agent).await.execution_count + 1;
+        let (result, _) = pda::find_result_address(&self.program_id, agent);
+        AgentInstruction::execute(&self.program_id, agent, signer, &result, execution, action)
+    }
+
+    /// Run `action` as the agent's next execution, signed by `signer`
+    pub async fn execute(&mut self, signer: &Keypair, agent: &Pubkey, action: AgentAction) -> Result<(), BanksClientError> {
+        let instruction = self.execute_instruction(&signer.pubkey(), agent, action).await;
+        self.process(&[instruction], &[signer]).await
+    }
+
+}
@@ -0,0 +1,143 @@
+//! Configuration validation with actionable diagnostics
+//!
+//! Validation collects every violated rule instead of stopping at the
+//! first one, so a misconfigured deployment can be fixed in one pass.
+
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+/// A single violated configuration rule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigViolation {
+    /// Dotted path of the offending field, e.g. `storage.cleanup_threshold`
+    pub field: String,
+    /// What is wrong with the value
+    pub message: String,
+    /// How to fix it
+    pub suggestion: String,
+}
+
+impl fmt::Display for ConfigViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.field, self.message, self.suggestion)
+    }
+}
+
+/// All violations found while validating a configuration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigErrors(pub Vec<ConfigViolation>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} configuration error(s):", self.0.len())?;
+        for violation in &self.0 {
+            writeln!(f, "  - {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Collector handed to `Validate` implementations
+pub struct Violations<'a> {
+    prefix: String,
+    found: &'a mut Vec<ConfigViolation>,
+}
+
+impl<'a> Violations<'a> {
+    /// Record a violation for `field` unless `ok` holds
+    pub fn check(&mut self, ok: bool, field: &str, message: &str, suggestion: &str) {
+        if !ok {
+            self.found.push(ConfigViolation {
+                field: format!("{}{}", self.prefix, field),
+                message: message.to_string(),
+                suggestion: suggestion.to_string(),
+            });
+        }
+    }
+
+    /// Validate a nested configuration under `field`
+    pub fn nested<V: Validate + ?Sized>(&mut self, field: &str, value: &V) {
+        let mut nested = Violations {
+            prefix: format!("{}{}.", self.prefix, field),
+            found: &mut *self.found,
+        };
+        value.collect_violations(&mut nested);
+    }
+}
+
+/// Configuration types that can validate themselves
+pub trait Validate {
+    /// Record every violated rule
+    fn collect_violations(&self, violations: &mut Violations);
+
+    /// Validate, returning all violations at once
+    fn validate(&self) -> Result<(), ConfigErrors> {
+        let mut found = Vec::new();
+        self.collect_violations(&mut Violations {
+            prefix: String::new(),
+            found: &mut found,
+        });
+
+        if found.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigErrors(found))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Inner {
+        ratio: f32,
+    }
+
+    struct Outer {
+        count: u32,
+        inner: Inner,
+    }
+
+    impl Validate for Inner {
+        fn collect_violations(&self, violations: &mut Violations) {
+            violations.check(
+                self.ratio > 0.0 && self.ratio <= 1.0,
+                "ratio",
+                "must be in (0, 1]",
+                "use a value such as 0.5",
+            );
+        }
+    }
+
+    impl Validate for Outer {
+        fn collect_violations(&self, violations: &mut Violations) {
+            violations.check(self.count > 0, "count", "must be positive", "set count to at least 1");
+            violations.nested("inner", &self.inner);
+        }
+    }
+
+    #[test]
+    fn test_collects_all_violations_with_paths() {
+        let config = Outer {
+            count: 0,
+            inner: Inner { ratio: 1.5 },
+        };
+
+        let errors = config.validate().unwrap_err();
+        let fields: Vec<&str> = errors.0.iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(fields, vec!["count", "inner.ratio"]);
+        assert!(errors.to_string().contains("inner.ratio: must be in (0, 1]"));
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let config = Outer {
+            count: 1,
+            inner: Inner { ratio: 0.5 },
+        };
+        assert!(config.validate().is_ok());
+    }
+}